@@ -0,0 +1,212 @@
+//! Component Benchmarks
+//!
+//! Criterion benchmarks of the parts of the engine every message passes
+//! through, on their own: a publish and receive on each channel type, a rule
+//! processor applying a few rules, condition evaluation, field access by
+//! dotted path and the vectorised column math of batches.
+//!
+//! ```text
+//! cargo bench --bench components
+//! cargo bench --bench components -- --save-baseline main   # record a baseline
+//! cargo bench --bench components -- --baseline main        # compare against it
+//! ```
+//!
+//! Criterion reports the time per iteration with a confidence interval and,
+//! once a previous run or a named baseline exists, whether it regressed.
+//! `liminal bench` measures a whole pipeline instead.
+
+use liminal::Message;
+use liminal::config::StageConfig;
+use liminal::config::types::{ChannelConfig, ChannelType};
+use liminal::core::channel::{Channel, PubSubChannel};
+use liminal::processors::common::column;
+use liminal::processors::common::condition_utils::{ConditionEvaluator, ConditionOperation};
+use liminal::processors::common::field_utils::FieldUtils;
+use liminal::processors::factory::create_processor;
+use liminal::testing::TestContext;
+
+use criterion::{BatchSize, Criterion, Throughput, criterion_group, criterion_main};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::hint::black_box;
+use std::time::Instant;
+use tokio::runtime::Runtime;
+
+const CAPACITY: usize = 1024;
+/// Messages per iteration of the channel and rule benchmarks: half the
+/// capacity, so that no channel type blocks or drops
+const BATCH: usize = CAPACITY / 2;
+/// Readings per column in the column benchmarks
+const COLUMN: usize = 1024;
+
+/// A column kernel over the values and the keep mask of a batch
+type Kernel = dyn Fn(&mut [f64], &mut [bool]);
+
+fn runtime() -> Runtime {
+    tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(4)
+        .enable_all()
+        .build()
+        .unwrap()
+}
+
+fn bench_channels(c: &mut Criterion) {
+    let runtime = runtime();
+    let mut group = c.benchmark_group("channel");
+    group.throughput(Throughput::Elements(BATCH as u64));
+
+    for channel_type in [
+        ChannelType::Broadcast,
+        ChannelType::Direct,
+        ChannelType::Shared,
+        ChannelType::Fanout,
+        ChannelType::Persistent,
+        ChannelType::Priority,
+    ] {
+        let directory = std::env::temp_dir().join(format!("liminal-bench-channel-{}", std::process::id()));
+        let config = ChannelConfig {
+            r#type: channel_type.clone(),
+            capacity: CAPACITY,
+            path: (channel_type == ChannelType::Persistent).then(|| directory.to_string_lossy().to_string()),
+            ..Default::default()
+        };
+        let channel = runtime.block_on(async { Channel::open("bench", &config) }).unwrap();
+        let mut subscriber = channel.subscribe();
+        let message = Message::new("bench", "bench", json!({ "value": 21.5, "device": "esp32-7" }));
+
+        group.bench_function(format!("{:?}", channel_type).to_lowercase(), |b| {
+            b.iter_custom(|iterations| {
+                runtime.block_on(async {
+                    let started = Instant::now();
+                    for _ in 0..iterations {
+                        for _ in 0..BATCH {
+                            let _ = channel.publish(message.clone()).await;
+                        }
+                        for _ in 0..BATCH {
+                            black_box(subscriber.recv().await);
+                        }
+                    }
+                    started.elapsed()
+                })
+            })
+        });
+
+        drop(subscriber);
+        drop(channel);
+        let _ = std::fs::remove_dir_all(&directory);
+    }
+    group.finish();
+}
+
+fn bench_rules(c: &mut Criterion) {
+    let rules = json!([
+        {
+            "condition": { "field_path": "temperature", "operation": ">", "value": 80.0 },
+            "actions": [{ "type": "set_field", "field_path": "status", "value": "hot" }]
+        },
+        {
+            "condition": { "all": [
+                { "field_path": "device", "operation": "matches", "value": "^esp32-\\d+$" },
+                { "field_path": "status", "operation": "in", "value": ["ok", "hot"] }
+            ] },
+            "actions": [{ "type": "compute_field", "field_path": "fahrenheit", "expression": "temperature * 1.8 + 32" }]
+        }
+    ]);
+    let config = StageConfig {
+        r#type: "rule".to_string(),
+        parameters: Some(HashMap::from([("rules".to_string(), rules)])),
+        ..Default::default()
+    };
+    let runtime = runtime();
+    let mut processor = create_processor("rule", config).unwrap();
+    runtime.block_on(processor.init()).unwrap();
+    let mut test = TestContext::new("rule").input("raw").output("rules");
+
+    let mut group = c.benchmark_group("rule");
+    group.throughput(Throughput::Elements(BATCH as u64));
+    group.bench_function("two_rules", |b| {
+        b.iter_custom(|iterations| {
+            runtime.block_on(async {
+                let started = Instant::now();
+                for index in 0..iterations {
+                    for _ in 0..BATCH {
+                        let payload = json!({ "device": "esp32-7", "status": "ok", "temperature": (index % 120) as f64 });
+                        test.send("raw", Message::new("bench", "raw", payload)).await.unwrap();
+                    }
+                    test.process_pending(processor.as_mut()).await.unwrap();
+                    while let Some(output) = test.try_output().await {
+                        black_box(output);
+                    }
+                }
+                started.elapsed()
+            })
+        })
+    });
+    group.finish();
+}
+
+fn bench_conditions(c: &mut Criterion) {
+    let value = json!(72.5);
+    let threshold = json!(80.0);
+    let device = json!("esp32-7");
+    let devices = json!(["esp32-1", "esp32-4", "esp32-7"]);
+
+    let mut group = c.benchmark_group("condition");
+    group.bench_function("greater_than", |b| {
+        b.iter(|| ConditionEvaluator::evaluate_condition(black_box(&value), &ConditionOperation::GreaterThan, &threshold))
+    });
+    group.bench_function("in", |b| {
+        b.iter(|| ConditionEvaluator::evaluate_condition(black_box(&device), &ConditionOperation::In, &devices))
+    });
+    group.finish();
+}
+
+fn bench_fields(c: &mut Criterion) {
+    let mut payload: Value = json!({ "sensor": { "readings": { "temperature": 21.5 } }, "device": "esp32-7" });
+
+    let mut group = c.benchmark_group("field");
+    group.bench_function("extract_field_value", |b| {
+        b.iter(|| FieldUtils::extract_field_value(black_box(&payload), "sensor.readings.temperature").cloned())
+    });
+    group.bench_function("set_field_value", |b| {
+        b.iter(|| FieldUtils::set_field_value(&mut payload, "sensor.readings.humidity", json!(black_box(42))).unwrap())
+    });
+    group.finish();
+}
+
+/// Column math over batches of `COLUMN` readings, against scaling one
+/// reading in a JSON payload, both per reading.
+fn bench_columns(c: &mut Criterion) {
+    let mut group = c.benchmark_group("column");
+
+    let mut payload = json!({ "raw": 1000.0 });
+    group.throughput(Throughput::Elements(1));
+    group.bench_function("scale_json_field", |b| {
+        b.iter(|| {
+            let raw = FieldUtils::extract_f64(black_box(&payload), "raw").unwrap_or_default();
+            FieldUtils::set_field_value(&mut payload, "celsius", json!(raw * 0.0625 - 40.0)).unwrap();
+        })
+    });
+
+    let values: Vec<f64> = (0..COLUMN).map(|i| i as f64).collect();
+    let kernels: [(&str, &Kernel); 4] = [
+        ("scale", &|values, _| column::scale(values, black_box(0.0625), black_box(-40.0))),
+        ("round", &|values, _| column::round(values, 2)),
+        ("clamp", &|values, _| column::clamp(values, black_box(-40.0), black_box(-39.5))),
+        ("threshold", &|values, keep| column::keep_within(values, black_box(-40.0), black_box(-39.5), keep)),
+    ];
+    group.throughput(Throughput::Elements(COLUMN as u64));
+    for (label, kernel) in kernels {
+        group.bench_function(label, |b| {
+            b.iter_batched_ref(
+                || (values.clone(), vec![true; COLUMN]),
+                |(values, keep)| kernel(black_box(values), keep),
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_channels, bench_rules, bench_conditions, bench_fields, bench_columns);
+criterion_main!(benches);
@@ -0,0 +1,144 @@
+//! Stage Concurrency Benchmark
+//!
+//! Runs a chain of stages, each spending a little CPU time per message, under
+//! `thread` concurrency and under `pipeline` concurrency with pools of
+//! different sizes, and reports the throughput of a burst of messages and the
+//! latency of messages sent one at a time:
+//!
+//! ```text
+//! cargo bench --bench concurrency
+//! ```
+
+use liminal::Message;
+use liminal::config::types::OverflowPolicy;
+use liminal::core::channel::{MpscChannel, PubSubChannel, RecvResult, Subscriber};
+use liminal::core::context::ProcessingContext;
+use liminal::core::pool::{DEFAULT_BATCH, DEFAULT_MAX_IN_FLIGHT, WorkerPool};
+use liminal::core::stage::Stage;
+use liminal::processors::Processor;
+
+use serde_json::json;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const STAGES: usize = 8;
+const CAPACITY: usize = 1024;
+const BURST: usize = 20_000;
+const SAMPLES: usize = 500;
+/// CPU time spent by each stage on each message
+const WORK: Duration = Duration::from_micros(20);
+
+/// Forwards every message after spinning for `WORK`.
+struct Busy;
+
+#[async_trait::async_trait]
+impl Processor for Busy {
+    async fn init(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    async fn process(&mut self, context: &mut ProcessingContext) -> anyhow::Result<()> {
+        if let Some((_, message)) = context.recv(Duration::from_millis(10)).await {
+            let started = Instant::now();
+            while started.elapsed() < WORK {
+                std::hint::spin_loop();
+            }
+            if let Some(output) = &context.output {
+                let _ = output.channel.publish(message).await;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Mode {
+    Thread,
+    Pipeline { workers: usize },
+}
+
+/// Start a chain of stages, returning its head channel and tail subscriber.
+async fn start_chain(mode: Mode) -> (Arc<dyn PubSubChannel<Message>>, Subscriber<Message>, Vec<tokio::task::JoinHandle<()>>) {
+    let channel = || -> Arc<dyn PubSubChannel<Message>> { Arc::new(MpscChannel::new(CAPACITY, OverflowPolicy::Block)) };
+    let pool = match mode {
+        Mode::Thread => None,
+        Mode::Pipeline { workers } => Some(Arc::new(WorkerPool::new(workers))),
+    };
+
+    let head = channel();
+    let mut upstream = Arc::clone(&head);
+    let mut tasks = Vec::new();
+    for index in 0..STAGES {
+        let downstream = channel();
+        let mut stage = Stage::new(format!("busy{}", index), Box::new(Busy), None);
+        stage.add_input("in", upstream.subscribe()).await;
+        stage.add_output("out", Arc::clone(&downstream)).await;
+        if let Some(pool) = &pool {
+            stage.attach_pool(Arc::clone(pool), DEFAULT_BATCH, DEFAULT_MAX_IN_FLIGHT);
+        }
+        tasks.push(tokio::spawn(async move {
+            let _ = stage.run().await;
+        }));
+        upstream = downstream;
+    }
+    (head, upstream.subscribe(), tasks)
+}
+
+async fn receive(tail: &mut Subscriber<Message>) {
+    while !matches!(tail.recv().await, RecvResult::Message(_)) {}
+}
+
+async fn bench(label: &str, mode: Mode) {
+    let (head, mut tail, tasks) = start_chain(mode).await;
+
+    let started = Instant::now();
+    let producer = {
+        let head = Arc::clone(&head);
+        tokio::spawn(async move {
+            for sequence in 0..BURST {
+                let _ = head.publish(Message::new("bench", "in", json!({ "sequence": sequence }))).await;
+            }
+        })
+    };
+    for _ in 0..BURST {
+        receive(&mut tail).await;
+    }
+    let elapsed = started.elapsed();
+    producer.await.unwrap();
+
+    let mut latencies = Vec::with_capacity(SAMPLES);
+    for sequence in 0..SAMPLES {
+        let sent = Instant::now();
+        let _ = head.publish(Message::new("bench", "in", json!({ "sequence": sequence }))).await;
+        receive(&mut tail).await;
+        latencies.push(sent.elapsed());
+    }
+    latencies.sort();
+
+    println!(
+        "{:<22} {:>10.0} msg/s   p50 {:>8.2?}   p99 {:>8.2?}",
+        label,
+        BURST as f64 / elapsed.as_secs_f64(),
+        latencies[SAMPLES / 2],
+        latencies[SAMPLES * 99 / 100],
+    );
+    for task in tasks {
+        task.abort();
+    }
+}
+
+fn main() {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(4)
+        .enable_all()
+        .build()
+        .unwrap();
+
+    println!("{} stages, {:?} of work per message per stage, 4 runtime threads", STAGES, WORK);
+    runtime.block_on(async {
+        bench("thread", Mode::Thread).await;
+        bench("pipeline (1 worker)", Mode::Pipeline { workers: 1 }).await;
+        bench("pipeline (2 workers)", Mode::Pipeline { workers: 2 }).await;
+        bench("pipeline (4 workers)", Mode::Pipeline { workers: 4 }).await;
+    });
+}
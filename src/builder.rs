@@ -0,0 +1,255 @@
+//! Pipeline Builder
+//!
+//! Assembles a configuration in code, for applications embedding Liminal
+//! without a TOML file. Stages are chained in the order they are added: each
+//! transform and output reads the stream built so far, a transform replaces
+//! that stream with its own output, and an input joins it.
+//!
+//! ```rust,ignore
+//! use liminal::PipelineBuilder;
+//! use serde_json::json;
+//!
+//! PipelineBuilder::new()
+//!     .input("sensor", "simulated", json!({ "field_out": "value", "interval_ms": 100 }))
+//!     .transform("delta", "delta", json!({ "field_in": "value", "field_out": "value_delta" }))
+//!     .output("console", "console", json!(null))
+//!     .run()
+//!     .await?;
+//! ```
+//!
+//! Settings without a dedicated method, such as channels, timing or replicas,
+//! are set on the most recently added stage with `configure`.
+
+use crate::config::{Config, StageConfig, parameter_errors, validate_config};
+use crate::config::types::PipelineConfig;
+use crate::core::pipeline::PipelineManager;
+use crate::processors::factory::processor_metadata;
+
+use anyhow::{Result, anyhow};
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Which part of the configuration a stage belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Section {
+    Input,
+    Transform,
+    Output,
+}
+
+/// Builds a `Config`, or a `PipelineManager` ready to connect and start.
+pub struct PipelineBuilder {
+    config: Config,
+    /// Pipeline receiving the transforms added next
+    pipeline: String,
+    /// Channels read by the next transform or output
+    stream: Vec<String>,
+    /// Most recently added stage, for `configure`
+    last: Option<(Section, String)>,
+    /// First error found while adding stages, reported by `build_config`
+    error: Option<anyhow::Error>,
+}
+
+impl Default for PipelineBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PipelineBuilder {
+    /// An empty builder. Transforms go to a pipeline named `main` unless
+    /// `pipeline` is called first, and parameters are checked strictly.
+    pub fn new() -> Self {
+        Self {
+            config: Config { strict: true, ..Default::default() },
+            pipeline: "main".to_string(),
+            stream: Vec::new(),
+            last: None,
+            error: None,
+        }
+    }
+
+    /// Add an input stage publishing to a channel named after it.
+    pub fn input(mut self, name: &str, processor: &str, parameters: Value) -> Self {
+        let stage = self.stage(name, processor, parameters, None, Some(name));
+        self.insert(Section::Input, name, stage);
+        self.stream.push(name.to_string());
+        self
+    }
+
+    /// Add a transform reading the current stream. Its output, a channel
+    /// named after it, becomes the stream.
+    pub fn transform(mut self, name: &str, processor: &str, parameters: Value) -> Self {
+        let stream = std::mem::replace(&mut self.stream, vec![name.to_string()]);
+        let stage = self.stage(name, processor, parameters, Some(stream), Some(name));
+        self.insert(Section::Transform, name, stage);
+        self
+    }
+
+    /// Add an output stage reading the current stream.
+    pub fn output(mut self, name: &str, processor: &str, parameters: Value) -> Self {
+        let stage = self.stage(name, processor, parameters, Some(self.stream.clone()), None);
+        self.insert(Section::Output, name, stage);
+        self
+    }
+
+    /// Send the transforms added next to the named pipeline.
+    pub fn pipeline(mut self, name: &str, description: &str) -> Self {
+        self.pipeline = name.to_string();
+        self.config
+            .pipelines
+            .entry(name.to_string())
+            .or_insert_with(|| PipelineConfig { description: String::new(), stages: HashMap::new(), runtime: None, enabled: None })
+            .description = description.to_string();
+        self
+    }
+
+    /// Change the most recently added stage, e.g. to set its channel or timing.
+    pub fn configure(mut self, change: impl FnOnce(&mut StageConfig)) -> Self {
+        let stage = match &self.last {
+            Some((Section::Input, name)) => self.config.inputs.get_mut(name),
+            Some((Section::Output, name)) => self.config.outputs.get_mut(name),
+            Some((Section::Transform, name)) => self
+                .config
+                .pipelines
+                .get_mut(&self.pipeline)
+                .and_then(|pipeline| pipeline.stages.get_mut(name)),
+            None => None,
+        };
+        match stage {
+            Some(stage) => change(stage),
+            None => self.fail(anyhow!("configure() called before any stage was added")),
+        }
+        self
+    }
+
+    /// Whether unknown or mistyped parameters are errors (the default) or are
+    /// only logged as warnings.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.config.strict = strict;
+        self
+    }
+
+    /// The configuration built so far, checked as a configuration file would be.
+    pub fn build_config(self) -> Result<Config> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        validate_config(&self.config)?;
+
+        let problems = parameter_errors(&self.config, processor_metadata);
+        if let Some((_, error)) = problems.into_iter().next() {
+            if self.config.strict {
+                return Err(error);
+            }
+            tracing::warn!("Configuration warning: {error}");
+        }
+        Ok(self.config)
+    }
+
+    /// A pipeline manager with every stage created, ready for
+    /// `connect_stages` and `start_all`.
+    pub fn build(self) -> Result<PipelineManager> {
+        PipelineManager::new(self.build_config()?).build_all()
+    }
+
+    /// Build, connect and start the stages, then wait until they finish.
+    pub async fn run(self) -> Result<()> {
+        self.build()?
+            .connect_stages()
+            .await?
+            .start_all()
+            .await?
+            .wait_for_all()
+            .await
+    }
+
+    fn stage(
+        &mut self,
+        name: &str,
+        processor: &str,
+        parameters: Value,
+        inputs: Option<Vec<String>>,
+        output: Option<&str>,
+    ) -> StageConfig {
+        let parameters = match parameters {
+            Value::Null => None,
+            Value::Object(map) => Some(map.into_iter().collect()),
+            other => {
+                self.fail(anyhow!("Stage '{}' parameters must be an object, found {}", name, other));
+                None
+            }
+        };
+        StageConfig {
+            r#type: processor.to_string(),
+            inputs,
+            output: output.map(str::to_string),
+            parameters,
+            ..Default::default()
+        }
+    }
+
+    fn insert(&mut self, section: Section, name: &str, stage: StageConfig) {
+        let taken = self.config.inputs.contains_key(name)
+            || self.config.outputs.contains_key(name)
+            || self.config.pipelines.values().any(|pipeline| pipeline.stages.contains_key(name));
+        if taken {
+            self.fail(anyhow!("Stage '{}' is added more than once", name));
+            return;
+        }
+
+        match section {
+            Section::Input => {
+                self.config.inputs.insert(name.to_string(), stage);
+            }
+            Section::Output => {
+                self.config.outputs.insert(name.to_string(), stage);
+            }
+            Section::Transform => {
+                self.config
+                    .pipelines
+                    .entry(self.pipeline.clone())
+                    .or_insert_with(|| PipelineConfig { description: String::new(), stages: HashMap::new(), runtime: None, enabled: None })
+                    .stages
+                    .insert(name.to_string(), stage);
+            }
+        }
+        self.last = Some((section, name.to_string()));
+    }
+
+    fn fail(&mut self, error: anyhow::Error) {
+        self.error.get_or_insert(error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_builder_chains_stages_through_channels() {
+        let config = PipelineBuilder::new()
+            .input("north", "simulated", json!({ "field_out": "value" }))
+            .input("south", "simulated", json!({ "field_out": "value" }))
+            .transform("delta", "delta", json!({ "field_in": "value" }))
+            .configure(|stage| stage.replicas = Some(2))
+            .output("console", "console", json!(null))
+            .output("file", "file", json!({ "file_path": "out.jsonl" }))
+            .build_config()
+            .unwrap();
+
+        assert_eq!(config.inputs["north"].output.as_deref(), Some("north"));
+        let delta = &config.pipelines["main"].stages["delta"];
+        assert_eq!(delta.inputs, Some(vec!["north".to_string(), "south".to_string()]));
+        assert_eq!(delta.replicas, Some(2));
+        assert_eq!(config.outputs["file"].inputs, Some(vec!["delta".to_string()]));
+
+        let error = PipelineBuilder::new()
+            .input("sensor", "simulated", json!({ "intervl_ms": 100 }))
+            .output("console", "console", json!(null))
+            .build_config()
+            .unwrap_err();
+        assert!(error.to_string().contains("unknown parameter 'intervl_ms'"), "{}", error);
+    }
+}
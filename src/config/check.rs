@@ -0,0 +1,399 @@
+//! Configuration Checks
+//!
+//! Deeper checks behind `liminal validate`, run on top of the structural
+//! rules of `validate_config`. Every problem is collected rather than stopping
+//! at the first, and each is located in the TOML source where possible:
+//!
+//! - stage inputs that no stage outputs (a warning when a disabled stage
+//!   outputs them)
+//! - unknown processor types
+//! - stage names defined more than once across inputs, pipelines and outputs
+//! - cycles between stages
+//! - unknown or mistyped processor parameters (warnings when `strict` is off)
+//! - outputs that no stage reads (a warning)
+//! - recorded channels that no stage outputs (a warning)
+//!
+//! Stages and pipelines turned off with `enabled = false` are not checked.
+
+use super::params::ProcessorMetadata;
+use super::types::{Config, StageConfig};
+use super::validation::{parameter_errors, validation_errors};
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A line of the configuration file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    /// Line number, counting from 1
+    pub line: usize,
+    pub text: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Problem {
+    pub severity: Severity,
+    pub message: String,
+    /// TOML table the problem was found in, e.g. `pipelines.main.stages.scale`
+    pub table: Option<String>,
+    pub location: Option<Location>,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(f, "{}: {}", severity, self.message)?;
+        if let Some(location) = &self.location {
+            let width = location.line.to_string().len();
+            write!(f, "\n{:>width$}--> line {}", "", location.line, width = width)?;
+            write!(f, "\n{:>width$} |", "", width = width)?;
+            write!(f, "\n{} | {}", location.line, location.text)?;
+        } else if let Some(table) = &self.table {
+            write!(f, "\n  in [{}]", table)?;
+        }
+        Ok(())
+    }
+}
+
+/// A configured stage and the table that defines it.
+struct StageEntry<'a> {
+    table: String,
+    name: &'a str,
+    config: &'a StageConfig,
+}
+
+fn stage_entries(config: &Config) -> Vec<StageEntry<'_>> {
+    fn section<'a>(prefix: String, stages: &'a HashMap<String, StageConfig>) -> Vec<StageEntry<'a>> {
+        let mut entries: Vec<StageEntry> = stages
+            .iter()
+            .map(|(name, stage)| StageEntry { table: format!("{}.{}", prefix, name), name, config: stage })
+            .collect();
+        entries.sort_by(|a, b| a.name.cmp(b.name));
+        entries
+    }
+
+    let mut pipelines: Vec<_> = config.pipelines.iter().collect();
+    pipelines.sort_by(|a, b| a.0.cmp(b.0));
+
+    let mut entries = section("inputs".to_string(), &config.inputs);
+    for (name, pipeline_config) in pipelines {
+        entries.extend(section(format!("pipelines.{}.stages", name), &pipeline_config.stages));
+    }
+    entries.extend(section("outputs".to_string(), &config.outputs));
+    entries
+}
+
+/// Split a TOML table header such as `[pipelines."main".stages.scale]` into keys.
+fn header_keys(line: &str) -> Option<Vec<String>> {
+    let line = line.trim();
+    if line.starts_with("[[") {
+        return None;
+    }
+    let header = line.strip_prefix('[')?.split(']').next()?;
+    Some(
+        header
+            .split('.')
+            .map(|key| key.trim().trim_matches('"').trim_matches('\'').to_string())
+            .collect(),
+    )
+}
+
+/// Find the line defining `table`: its header, or failing that a `key = ...`
+/// line inside its parent table (for inline tables).
+fn locate(source: &str, table: &str) -> Option<Location> {
+    let keys: Vec<&str> = table.split('.').collect();
+    let lines: Vec<&str> = source.lines().collect();
+    let location = |index: usize| Location { line: index + 1, text: lines[index].trim_end().to_string() };
+
+    if let Some(index) = lines.iter().position(|line| header_keys(line).is_some_and(|header| header == keys)) {
+        return Some(location(index));
+    }
+
+    // Inline table: `scale = { ... }` under `[pipelines.main.stages]`
+    let (last, parent) = keys.split_last()?;
+    let start = lines.iter().position(|line| header_keys(line).is_some_and(|header| header == parent))?;
+    lines
+        .iter()
+        .enumerate()
+        .skip(start + 1)
+        .take_while(|(_, line)| header_keys(line).is_none())
+        .find(|(_, line)| {
+            line.split_once('=')
+                .is_some_and(|(key, _)| key.trim().trim_matches('"').trim_matches('\'') == *last)
+        })
+        .map(|(index, _)| location(index))
+}
+
+/// Find cycles in the stage graph (stage -> stages reading its outputs).
+/// Each cycle is reported once, starting from its smallest stage name.
+fn find_cycles(edges: &BTreeMap<&str, BTreeSet<&str>>) -> Vec<Vec<String>> {
+    fn visit<'a>(
+        stage: &'a str,
+        edges: &BTreeMap<&'a str, BTreeSet<&'a str>>,
+        path: &mut Vec<&'a str>,
+        done: &mut BTreeSet<&'a str>,
+        cycles: &mut BTreeSet<Vec<String>>,
+    ) {
+        if let Some(start) = path.iter().position(|s| *s == stage) {
+            let mut cycle: Vec<String> = path[start..].iter().map(|s| s.to_string()).collect();
+            let smallest = (0..cycle.len()).min_by_key(|i| &cycle[*i]).unwrap_or(0);
+            cycle.rotate_left(smallest);
+            cycles.insert(cycle);
+            return;
+        }
+        if done.contains(stage) {
+            return;
+        }
+        path.push(stage);
+        for next in edges.get(stage).into_iter().flatten() {
+            visit(next, edges, path, done, cycles);
+        }
+        path.pop();
+        done.insert(stage);
+    }
+
+    let mut cycles = BTreeSet::new();
+    let mut done = BTreeSet::new();
+    for stage in edges.keys() {
+        visit(stage, edges, &mut Vec::new(), &mut done, &mut cycles);
+    }
+    cycles.into_iter().collect()
+}
+
+/// Find the line defining `table`, or failing that its nearest enclosing
+/// table (e.g. an inline `parameters = { ... }` for one of its keys).
+fn locate_nearest(source: &str, table: &str) -> Option<Location> {
+    let mut table = table;
+    loop {
+        if let Some(location) = locate(source, table) {
+            return Some(location);
+        }
+        table = table.rsplit_once('.')?.0;
+    }
+}
+
+/// Run every check on `config`, locating problems in its TOML `source`.
+/// `processor_exists` tells whether a processor type is registered, and
+/// `processor_metadata` describes the parameters of those that have metadata.
+pub fn check_config(
+    config: &Config,
+    source: &str,
+    processor_exists: impl Fn(&str) -> bool,
+    processor_metadata: impl Fn(&str) -> Option<&'static ProcessorMetadata>,
+) -> Vec<Problem> {
+    let mut enabled = config.clone();
+    let disabled = enabled.remove_disabled();
+    let config = &enabled;
+
+    let mut problems = Vec::new();
+    let mut problem = |severity, message: String, table: Option<String>| {
+        let location = table.as_deref().and_then(|table| locate_nearest(source, table));
+        problems.push(Problem { severity, message, table, location });
+    };
+
+    for (table, error) in validation_errors(config) {
+        problem(Severity::Error, error.to_string(), Some(table));
+    }
+
+    let stages = stage_entries(config);
+
+    // Stage names must be unique across sections, as stages are run by name
+    let mut definitions: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for stage in &stages {
+        definitions.entry(stage.name).or_default().push(&stage.table);
+    }
+    for (name, tables) in &definitions {
+        if tables.len() > 1 {
+            let others: Vec<String> = tables[1..].iter().map(|table| format!("[{}]", table)).collect();
+            problem(
+                Severity::Error,
+                format!("Stage '{}' is defined more than once (also in {})", name, others.join(", ")),
+                Some(tables[0].to_string()),
+            );
+        }
+    }
+
+    for stage in &stages {
+        if !processor_exists(&stage.config.r#type) {
+            problem(
+                Severity::Error,
+                format!(
+                    "Stage '{}' has unknown processor type '{}' (see `liminal --list-processors`)",
+                    stage.name, stage.config.r#type
+                ),
+                Some(stage.table.clone()),
+            );
+        }
+    }
+
+    let severity = if config.strict { Severity::Error } else { Severity::Warning };
+    for (table, error) in parameter_errors(config, processor_metadata) {
+        problem(severity, error.to_string(), Some(table));
+    }
+
+    // Channels and the stages either side of them
+    let mut producers: HashMap<&str, Vec<&str>> = HashMap::new();
+    let mut consumers: HashMap<&str, Vec<&str>> = HashMap::new();
+    for stage in &stages {
+        for output in stage.config.output.iter().chain(stage.config.side_outputs.iter().flatten()) {
+            producers.entry(output).or_default().push(stage.name);
+        }
+        for input in stage.config.inputs.iter().flatten() {
+            consumers.entry(input).or_default().push(stage.name);
+        }
+    }
+
+    for stage in &stages {
+        for input in stage.config.inputs.iter().flatten() {
+            if producers.contains_key(input.as_str()) {
+                continue;
+            }
+            // A channel whose producers are all disabled is expected to be quiet
+            let disabled_producer = disabled.iter().any(|(_, disabled)| {
+                disabled.output.iter().chain(disabled.side_outputs.iter().flatten()).any(|output| output == input)
+            });
+            if disabled_producer {
+                problem(
+                    Severity::Warning,
+                    format!("Stage '{}' reads from channel '{}', which only disabled stages output", stage.name, input),
+                    Some(stage.table.clone()),
+                );
+            } else {
+                problem(
+                    Severity::Error,
+                    format!("Stage '{}' reads from channel '{}', which no stage outputs", stage.name, input),
+                    Some(stage.table.clone()),
+                );
+            }
+        }
+        for output in stage.config.output.iter().chain(stage.config.side_outputs.iter().flatten()) {
+            if !consumers.contains_key(output.as_str()) {
+                problem(
+                    Severity::Warning,
+                    format!("Stage '{}' outputs to channel '{}', which no stage reads", stage.name, output),
+                    Some(stage.table.clone()),
+                );
+            }
+        }
+    }
+
+    for channel in config.record.iter().flat_map(|record| &record.channels) {
+        if !producers.contains_key(channel.as_str()) {
+            problem(
+                Severity::Warning,
+                format!("Recorded channel '{}' is not output by any stage, so nothing will be recorded", channel),
+                Some("record".to_string()),
+            );
+        }
+    }
+
+    let mut edges: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+    for (channel, from) in &producers {
+        for producer in from {
+            let downstream = edges.entry(producer).or_default();
+            downstream.extend(consumers.get(channel).into_iter().flatten());
+        }
+    }
+    for cycle in find_cycles(&edges) {
+        let table = stages.iter().find(|stage| stage.name == cycle[0]).map(|stage| stage.table.clone());
+        problem(
+            Severity::Error,
+            format!("Stages form a cycle: {} -> {}", cycle.join(" -> "), cycle[0]),
+            table,
+        );
+    }
+
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::loader::load_config_from_string;
+
+    #[test]
+    fn test_check_reports_every_problem_with_its_line() {
+        let source = r#"
+[inputs.sensor]
+type = "simulated"
+output = "raw"
+
+[pipelines.main]
+description = "Main"
+
+[pipelines.main.stages.a]
+type = "scale"
+inputs = ["raw", "b_out"]
+output = "a_out"
+
+[pipelines.main.stages.b]
+type = "warp_drive"
+inputs = ["a_out"]
+output = "b_out"
+
+[outputs.sensor]
+type = "console"
+inputs = ["missing"]
+"#;
+        let config = load_config_from_string(source).unwrap();
+        let problems = check_config(&config, source, |kind| kind != "warp_drive", |_| None);
+        let messages: Vec<(Severity, &str, Option<usize>)> = problems
+            .iter()
+            .map(|p| (p.severity, p.message.as_str(), p.location.as_ref().map(|l| l.line)))
+            .collect();
+
+        assert_eq!(
+            messages,
+            [
+                (Severity::Error, "Stage 'sensor' is defined more than once (also in [outputs.sensor])", Some(2)),
+                (Severity::Error, "Stage 'b' has unknown processor type 'warp_drive' (see `liminal --list-processors`)", Some(14)),
+                (Severity::Error, "Stage 'sensor' reads from channel 'missing', which no stage outputs", Some(19)),
+                (Severity::Error, "Stages form a cycle: a -> b -> a", Some(9)),
+            ]
+        );
+
+        // Disabled stages are skipped, and channels only they output are expected to be quiet
+        let source = r#"
+[inputs.sensor]
+type = "simulated"
+output = "raw"
+enabled = false
+
+[pipelines.main]
+description = "Main"
+
+[pipelines.main.stages.a]
+type = "scale"
+inputs = ["raw"]
+output = "scaled"
+
+[pipelines.spare]
+description = "Not yet wired up"
+enabled = false
+
+[pipelines.spare.stages.b]
+type = "warp_drive"
+
+[outputs.console]
+type = "console"
+inputs = ["scaled"]
+"#;
+        let config = load_config_from_string(source).unwrap();
+        let problems = check_config(&config, source, |kind| kind != "warp_drive", |_| None);
+        let messages: Vec<(Severity, &str)> = problems.iter().map(|p| (p.severity, p.message.as_str())).collect();
+        assert_eq!(messages, [(Severity::Warning, "Stage 'a' reads from channel 'raw', which only disabled stages output")]);
+
+        // Inline tables are located by their key
+        let source = "[pipelines.main.stages]\nscale = { type = \"scale\" }\n";
+        assert_eq!(locate(source, "pipelines.main.stages.scale").map(|l| l.line), Some(2));
+    }
+}
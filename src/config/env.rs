@@ -0,0 +1,119 @@
+//! Configuration from the Environment
+//!
+//! Lets a container be configured without mounting a file into it:
+//!
+//! - `LIMINAL_CONFIG` holds a whole configuration, as TOML or as
+//!   base64-encoded TOML, and is loaded instead of the configuration file
+//! - `LIMINAL__`-prefixed variables set single values, with `__` separating
+//!   the keys of the path, as `--set` does with dots:
+//!
+//! ```text
+//! LIMINAL__inputs__plant__type=mqtt_sub
+//! LIMINAL__inputs__plant__output=readings
+//! LIMINAL__inputs__plant__parameters__qos=1
+//! ```
+//!
+//! Keys are case-sensitive, and values are read as TOML and otherwise as plain
+//! strings, like `--set` values. The variables apply in order of name, so a
+//! whole table set by one is overridden key by key by longer ones, and before
+//! `--set`, which wins over them. They apply on top of `LIMINAL_CONFIG` or the
+//! configuration file; when neither exists, the configuration is assembled
+//! from them alone.
+
+use anyhow::{Context, Result, anyhow};
+use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
+use std::ffi::OsString;
+
+/// Variable holding a whole configuration.
+pub const CONFIG_VAR: &str = "LIMINAL_CONFIG";
+
+/// Prefix of variables setting single values.
+pub const VAR_PREFIX: &str = "LIMINAL__";
+
+/// The configuration in `LIMINAL_CONFIG`, if it is set.
+pub fn inline_config() -> Result<Option<String>> {
+    match std::env::var(CONFIG_VAR) {
+        Ok(value) => decode_config(&value).map(Some),
+        Err(std::env::VarError::NotPresent) => Ok(None),
+        Err(e) => Err(anyhow!("{}: {}", CONFIG_VAR, e)),
+    }
+}
+
+/// Read a configuration given as TOML, or else as base64-encoded TOML.
+fn decode_config(value: &str) -> Result<String> {
+    if value.parse::<toml::Table>().is_ok() {
+        return Ok(value.to_string());
+    }
+    let compact: String = value.split_whitespace().collect();
+    let decoded = BASE64
+        .decode(compact)
+        .ok()
+        .and_then(|bytes| String::from_utf8(bytes).ok())
+        .ok_or_else(|| anyhow!("{} is neither TOML nor base64-encoded TOML", CONFIG_VAR))?;
+    decoded
+        .parse::<toml::Table>()
+        .with_context(|| format!("{} is base64-encoded, but not TOML", CONFIG_VAR))?;
+    Ok(decoded)
+}
+
+/// `KEY=VALUE` overrides from the `LIMINAL__` variables of the environment.
+pub fn env_overrides() -> Vec<String> {
+    env_overrides_from(std::env::vars_os())
+}
+
+fn env_overrides_from(vars: impl IntoIterator<Item = (OsString, OsString)>) -> Vec<String> {
+    let mut overrides: Vec<(String, String)> = vars
+        .into_iter()
+        .filter_map(|(name, value)| {
+            let path = name.to_str()?.strip_prefix(VAR_PREFIX)?.replace("__", ".");
+            match value.into_string() {
+                Ok(value) => Some((path, value)),
+                Err(_) => {
+                    tracing::warn!("Ignoring {}{}: its value is not UTF-8", VAR_PREFIX, path.replace('.', "__"));
+                    None
+                }
+            }
+        })
+        .collect();
+    overrides.sort();
+    overrides.into_iter().map(|(path, value)| format!("{}={}", path, value)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::config::overrides::apply_overrides;
+
+    #[test]
+    fn test_configuration_assembled_from_variables() {
+        let vars = [
+            ("LIMINAL__inputs__plant__type", "simulated"),
+            ("LIMINAL__inputs__plant__parameters__interval_ms", "250"),
+            ("LIMINAL__inputs__plant__parameters", "{ interval_ms = 100, field_out = \"temperature\" }"),
+            ("LIMINAL__inputs__plant__output", "readings"),
+            ("LIMINAL__outputs__console__type", "console"),
+            ("LIMINAL__outputs__console__inputs", "[\"readings\"]"),
+            ("LIMINAL_CONFIG", "ignored"),
+            ("PATH", "/usr/bin"),
+        ]
+        .map(|(name, value)| (OsString::from(name), OsString::from(value)));
+
+        let mut table = toml::Table::new();
+        apply_overrides(&mut table, &env_overrides_from(vars)).unwrap();
+        let config: Config = toml::Value::Table(table).try_into().unwrap();
+        let plant = &config.inputs["plant"];
+        assert_eq!(plant.output.as_deref(), Some("readings"));
+        let parameters = plant.parameters.as_ref().unwrap();
+        // The longer name applies after the whole table
+        assert_eq!(parameters["interval_ms"].as_i64(), Some(250));
+        assert_eq!(parameters["field_out"].as_str(), Some("temperature"));
+        assert_eq!(config.outputs["console"].inputs.as_deref(), Some(&["readings".to_string()][..]));
+
+        let toml = "[inputs.plant]\ntype = \"simulated\"\noutput = \"readings\"\n";
+        assert_eq!(decode_config(toml).unwrap(), toml);
+        assert_eq!(decode_config(&BASE64.encode(toml)).unwrap(), toml);
+        assert!(decode_config("not a configuration").is_err());
+        assert!(decode_config(&BASE64.encode("not toml")).is_err());
+    }
+}
@@ -0,0 +1,279 @@
+//! Topology Graph Export
+//!
+//! Renders the stages and channels of a configuration as a Graphviz DOT or
+//! Mermaid flowchart, for `liminal graph`. Stages are boxes, grouped into
+//! inputs, one cluster per pipeline and outputs; channels are ellipses labelled
+//! with their type and capacity, taken from the stage that publishes to them.
+//! Side outputs are drawn as dashed edges.
+//!
+//! ```text
+//! liminal graph -c config.toml | dot -Tsvg > pipeline.svg
+//! liminal graph -c config.toml --format mermaid
+//! ```
+
+use super::types::{ChannelConfig, ChannelType, Config, StageConfig};
+
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum GraphFormat {
+    /// Graphviz DOT
+    Dot,
+    /// Mermaid flowchart
+    Mermaid,
+}
+
+/// A group of stages drawn together.
+struct Group<'a> {
+    id: String,
+    label: String,
+    stages: Vec<(&'a String, &'a StageConfig)>,
+}
+
+/// A channel and the stages either side of it.
+#[derive(Default)]
+struct ChannelNode<'a> {
+    /// Settings of the stage that publishes to the channel
+    config: Option<&'a ChannelConfig>,
+    /// Publishing stages, and whether they publish as a side output
+    producers: Vec<(&'a str, bool)>,
+    consumers: Vec<&'a str>,
+}
+
+struct Topology<'a> {
+    groups: Vec<Group<'a>>,
+    channels: BTreeMap<&'a str, ChannelNode<'a>>,
+}
+
+fn sorted(stages: &std::collections::HashMap<String, StageConfig>) -> Vec<(&String, &StageConfig)> {
+    let mut stages: Vec<_> = stages.iter().collect();
+    stages.sort_by(|a, b| a.0.cmp(b.0));
+    stages
+}
+
+impl<'a> Topology<'a> {
+    fn new(config: &'a Config) -> Self {
+        let mut groups = vec![Group {
+            id: "inputs".to_string(),
+            label: "inputs".to_string(),
+            stages: sorted(&config.inputs),
+        }];
+        let mut pipelines: Vec<_> = config.pipelines.iter().collect();
+        pipelines.sort_by(|a, b| a.0.cmp(b.0));
+        for (name, pipeline) in pipelines {
+            let label = if pipeline.description.is_empty() {
+                name.clone()
+            } else {
+                format!("{}: {}", name, pipeline.description)
+            };
+            groups.push(Group { id: format!("pipeline_{}", name), label, stages: sorted(&pipeline.stages) });
+        }
+        groups.push(Group {
+            id: "outputs".to_string(),
+            label: "outputs".to_string(),
+            stages: sorted(&config.outputs),
+        });
+
+        let mut channels: BTreeMap<&str, ChannelNode> = BTreeMap::new();
+        for (stage_name, stage_config) in groups.iter().flat_map(|group| group.stages.iter()) {
+            let outputs = stage_config
+                .output
+                .iter()
+                .map(|output| (output, false))
+                .chain(stage_config.side_outputs.iter().flatten().map(|output| (output, true)));
+            for (output, side) in outputs {
+                let channel = channels.entry(output.as_str()).or_default();
+                channel.producers.push((stage_name.as_str(), side));
+                if channel.config.is_none() {
+                    channel.config = stage_config.channel.as_ref();
+                }
+            }
+            for input in stage_config.inputs.iter().flatten() {
+                channels.entry(input.as_str()).or_default().consumers.push(stage_name.as_str());
+            }
+        }
+
+        Self { groups, channels }
+    }
+}
+
+fn stage_label(name: &str, config: &StageConfig) -> String {
+    match config.replicas {
+        Some(replicas) if replicas > 1 => format!("{}\n{} x{}", name, config.r#type, replicas),
+        _ => format!("{}\n{}", name, config.r#type),
+    }
+}
+
+fn channel_label(name: &str, channel: &ChannelNode) -> String {
+    if channel.producers.is_empty() {
+        return format!("{}\n(no producer)", name);
+    }
+    let default = ChannelConfig::default();
+    let config = channel.config.unwrap_or(&default);
+    let kind = match config.r#type {
+        ChannelType::Broadcast => "broadcast",
+        ChannelType::Direct => "direct",
+        ChannelType::Shared => "shared",
+        ChannelType::Fanout => "fanout",
+        ChannelType::Persistent => "persistent",
+        ChannelType::Priority => "priority",
+    };
+    match config.r#type {
+        ChannelType::Persistent => format!("{}\n{}", name, kind),
+        _ => format!("{}\n{}, {}", name, kind, config.capacity),
+    }
+}
+
+/// Render the topology of `config` in the given format.
+pub fn render(config: &Config, format: GraphFormat) -> String {
+    let topology = Topology::new(config);
+    match format {
+        GraphFormat::Dot => render_dot(&topology),
+        GraphFormat::Mermaid => render_mermaid(&topology),
+    }
+}
+
+fn dot_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+fn render_dot(topology: &Topology) -> String {
+    let mut out = String::from("digraph liminal {\n    rankdir=LR;\n    node [fontname=\"Helvetica\"];\n");
+
+    for group in &topology.groups {
+        if group.stages.is_empty() {
+            continue;
+        }
+        out.push_str(&format!(
+            "    subgraph \"cluster_{}\" {{\n        label=\"{}\";\n",
+            dot_escape(&group.id),
+            dot_escape(&group.label)
+        ));
+        for (name, config) in &group.stages {
+            out.push_str(&format!(
+                "        \"stage:{}\" [shape=box, label=\"{}\"];\n",
+                dot_escape(name),
+                dot_escape(&stage_label(name, config))
+            ));
+        }
+        out.push_str("    }\n");
+    }
+
+    for (name, channel) in &topology.channels {
+        let style = if channel.producers.is_empty() { ", style=dashed, color=red" } else { "" };
+        out.push_str(&format!(
+            "    \"channel:{}\" [shape=ellipse, label=\"{}\"{}];\n",
+            dot_escape(name),
+            dot_escape(&channel_label(name, channel)),
+            style
+        ));
+        for (producer, side) in &channel.producers {
+            let style = if *side { " [style=dashed, label=\"side\"]" } else { "" };
+            out.push_str(&format!(
+                "    \"stage:{}\" -> \"channel:{}\"{};\n",
+                dot_escape(producer),
+                dot_escape(name),
+                style
+            ));
+        }
+        for consumer in &channel.consumers {
+            out.push_str(&format!("    \"channel:{}\" -> \"stage:{}\";\n", dot_escape(name), dot_escape(consumer)));
+        }
+    }
+
+    out.push_str("}\n");
+    out
+}
+
+/// Mermaid node ids allow only a restricted character set.
+fn mermaid_id(prefix: &str, name: &str) -> String {
+    let name: String = name.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect();
+    format!("{}_{}", prefix, name)
+}
+
+fn mermaid_label(text: &str) -> String {
+    text.replace('"', "#quot;").replace('\n', "<br/>")
+}
+
+fn render_mermaid(topology: &Topology) -> String {
+    let mut out = String::from("flowchart LR\n");
+
+    for group in &topology.groups {
+        if group.stages.is_empty() {
+            continue;
+        }
+        out.push_str(&format!(
+            "    subgraph {}[\"{}\"]\n",
+            mermaid_id("group", &group.id),
+            mermaid_label(&group.label)
+        ));
+        for (name, config) in &group.stages {
+            out.push_str(&format!(
+                "        {}[\"{}\"]\n",
+                mermaid_id("stage", name),
+                mermaid_label(&stage_label(name, config))
+            ));
+        }
+        out.push_str("    end\n");
+    }
+
+    for (name, channel) in &topology.channels {
+        let id = mermaid_id("channel", name);
+        out.push_str(&format!("    {}([\"{}\"])\n", id, mermaid_label(&channel_label(name, channel))));
+        for (producer, side) in &channel.producers {
+            let arrow = if *side { "-.->|side|" } else { "-->" };
+            out.push_str(&format!("    {} {} {}\n", mermaid_id("stage", producer), arrow, id));
+        }
+        for consumer in &channel.consumers {
+            out.push_str(&format!("    {} --> {}\n", id, mermaid_id("stage", consumer)));
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::loader::load_config_from_string;
+
+    #[test]
+    fn test_graph_renders_stages_and_channels() {
+        let config = load_config_from_string(
+            r#"
+            [inputs.sensor]
+            type = "simulated"
+            output = "raw"
+            channel = { type = "shared", capacity = 64 }
+
+            [pipelines.main]
+            description = "Main"
+
+            [pipelines.main.stages.scale]
+            type = "scale"
+            inputs = ["raw"]
+            output = "scaled"
+            side_outputs = ["rejected"]
+            replicas = 2
+
+            [outputs.console]
+            type = "console"
+            inputs = ["scaled"]
+            "#,
+        )
+        .unwrap();
+
+        let dot = render(&config, GraphFormat::Dot);
+        assert!(dot.contains("subgraph \"cluster_pipeline_main\""));
+        assert!(dot.contains("\"stage:scale\" [shape=box, label=\"scale\\nscale x2\"];"));
+        assert!(dot.contains("\"channel:raw\" [shape=ellipse, label=\"raw\\nshared, 64\"];"));
+        assert!(dot.contains("\"stage:scale\" -> \"channel:rejected\" [style=dashed, label=\"side\"];"));
+        assert!(dot.contains("\"channel:scaled\" -> \"stage:console\";"));
+
+        let mermaid = render(&config, GraphFormat::Mermaid);
+        assert!(mermaid.starts_with("flowchart LR\n"));
+        assert!(mermaid.contains("stage_sensor --> channel_raw"));
+        assert!(mermaid.contains("stage_scale -.->|side| channel_rejected"));
+        assert!(mermaid.contains("channel_scaled --> stage_console"));
+    }
+}
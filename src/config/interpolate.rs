@@ -0,0 +1,147 @@
+//! Configuration Interpolation
+//!
+//! Substitutes environment variables and secret files into string values of a
+//! configuration as it is loaded, so that site-specific settings and
+//! credentials can stay out of the TOML:
+//!
+//! ```toml
+//! [inputs.plant.parameters]
+//! broker_url = "mqtt://${MQTT_HOST:-localhost}:1883"
+//! username = "${MQTT_USER}"
+//! password = "${file:/run/secrets/mqtt_password}"
+//! ```
+//!
+//! - `${VAR}` is replaced by the environment variable `VAR`; loading fails if
+//!   it is unset
+//! - `${VAR:-fallback}` uses `fallback` when `VAR` is unset or empty
+//! - `${file:PATH}` is replaced by the contents of the file at `PATH`, without
+//!   trailing newlines; relative paths are resolved from the working directory
+//! - `$${` is a literal `${`
+//!
+//! Only string values are interpolated, never keys, and substituted text is
+//! not interpolated again.
+
+use anyhow::{Context, Result, anyhow};
+
+/// Interpolate every string value in `table`, reading the environment.
+pub fn interpolate_table(table: &mut toml::Table) -> Result<()> {
+    interpolate_table_with(table, &|name| std::env::var(name).ok(), "")
+}
+
+fn interpolate_table_with(table: &mut toml::Table, env: &dyn Fn(&str) -> Option<String>, path: &str) -> Result<()> {
+    for (key, value) in table.iter_mut() {
+        let path = if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) };
+        interpolate_value(value, env, &path)?;
+    }
+    Ok(())
+}
+
+fn interpolate_value(value: &mut toml::Value, env: &dyn Fn(&str) -> Option<String>, path: &str) -> Result<()> {
+    match value {
+        toml::Value::String(text) => {
+            // Spelt out in full, as the loader reports only the outermost error
+            *text = interpolate_str(text, env).map_err(|e| anyhow!("Failed to interpolate '{}': {:#}", path, e))?;
+        }
+        toml::Value::Array(items) => {
+            for (index, item) in items.iter_mut().enumerate() {
+                interpolate_value(item, env, &format!("{}[{}]", path, index))?;
+            }
+        }
+        toml::Value::Table(table) => interpolate_table_with(table, env, path)?,
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Expand the `${...}` references in one string.
+fn interpolate_str(text: &str, env: &dyn Fn(&str) -> Option<String>) -> Result<String> {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('$') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        if let Some(after) = rest.strip_prefix("$${") {
+            out.push_str("${");
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("${") {
+            let end = after.find('}').ok_or_else(|| anyhow!("Unterminated '${{' in \"{}\"", text))?;
+            out.push_str(&resolve(&after[..end], env)?);
+            rest = &after[end + 1..];
+        } else {
+            out.push('$');
+            rest = &rest[1..];
+        }
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// Resolve the inside of one `${...}` reference.
+fn resolve(reference: &str, env: &dyn Fn(&str) -> Option<String>) -> Result<String> {
+    if let Some(path) = reference.strip_prefix("file:") {
+        let contents = std::fs::read_to_string(path).with_context(|| format!("Failed to read secret file '{}'", path))?;
+        return Ok(contents.trim_end_matches(['\r', '\n']).to_string());
+    }
+
+    let (name, fallback) = match reference.split_once(":-") {
+        Some((name, fallback)) => (name, Some(fallback)),
+        None => (reference, None),
+    };
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(anyhow!("Invalid variable name '{}'", name));
+    }
+
+    match (env(name), fallback) {
+        (Some(value), Some(fallback)) if value.is_empty() => Ok(fallback.to_string()),
+        (Some(value), _) => Ok(value),
+        (None, Some(fallback)) => Ok(fallback.to_string()),
+        (None, None) => Err(anyhow!("Environment variable '{}' is not set", name)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_interpolation_of_variables_defaults_and_files() {
+        let secret = std::env::temp_dir().join(format!("liminal_secret_{}", std::process::id()));
+        std::fs::write(&secret, "s3cret\n").unwrap();
+
+        let vars = HashMap::from([("MQTT_HOST", "broker.local"), ("EMPTY", "")]);
+        let env = |name: &str| vars.get(name).map(|value| value.to_string());
+
+        let mut table: toml::Table = toml::from_str(&format!(
+            r#"
+            [inputs.plant.parameters]
+            broker_url = "mqtt://${{MQTT_HOST}}:${{MQTT_PORT:-1883}}"
+            client_id = "${{EMPTY:-liminal}}"
+            password = "${{file:{}}}"
+            topics = ["site/$${{literal}}", "cost: $5"]
+            qos = 1
+            "#,
+            secret.display()
+        ))
+        .unwrap();
+        interpolate_table_with(&mut table, &env, "").unwrap();
+        std::fs::remove_file(&secret).unwrap();
+
+        let parameters = &table["inputs"]["plant"]["parameters"];
+        assert_eq!(parameters["broker_url"].as_str(), Some("mqtt://broker.local:1883"));
+        assert_eq!(parameters["client_id"].as_str(), Some("liminal"));
+        assert_eq!(parameters["password"].as_str(), Some("s3cret"));
+        assert_eq!(parameters["topics"][0].as_str(), Some("site/${literal}"));
+        assert_eq!(parameters["topics"][1].as_str(), Some("cost: $5"));
+        assert_eq!(parameters["qos"].as_integer(), Some(1));
+
+        let mut table: toml::Table = toml::from_str("[inputs.plant]\ntype = \"${MISSING}\"").unwrap();
+        let error = interpolate_table_with(&mut table, &env, "").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Failed to interpolate 'inputs.plant.type': Environment variable 'MISSING' is not set"
+        );
+    }
+}
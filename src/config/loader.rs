@@ -15,6 +15,25 @@
 //! - **String content**: Load from TOML content in memory
 //! - **Default config**: Generate sensible default configurations
 //! 
+//! A configuration may layer other files beneath itself with a top-level
+//! `include = ["common.toml", "site.toml"]`. Paths are relative to the including
+//! file; later files override earlier ones and the including file overrides
+//! them all. Tables such as stage parameters are merged key by key, while other
+//! values, arrays included, are replaced whole.
+//! 
+//! Stage definitions with a `foreach` key are expanded into one stage per
+//! entry; see the `template` module.
+//! 
+//! String values may reference environment variables and secret files, such as
+//! `"${MQTT_HOST:-localhost}"` or `"${file:/run/secrets/mqtt_password}"`; see
+//! the `interpolate` module.
+//! 
+//! Single values can be overridden after loading with `KEY=VALUE` entries, as
+//! given to `--set` on the command line; see the `overrides` module.
+//! 
+//! The command line also reads configuration from `LIMINAL_CONFIG` and
+//! `LIMINAL__` environment variables; see the `env` module.
+//! 
 //! # Error Handling
 //! 
 //! All loading functions return detailed errors that help diagnose configuration problems:
@@ -39,9 +58,13 @@
 //! let config = load_config_from_string(toml_content)?;
 //! ```
 
+use crate::config::env;
+use crate::config::interpolate::interpolate_table;
+use crate::config::overrides::apply_overrides;
+use crate::config::template::expand_templates;
 use crate::config::types::Config;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use toml;
 
 /// Loads configuration from a TOML file.
@@ -63,6 +86,14 @@ use toml;
 /// 
 /// This function can fail in several ways:
 /// 
+/// ## Include Errors
+/// - **Missing includes**: A file listed in `include` doesn't exist or can't be parsed
+/// - **Include cycles**: A file includes itself, directly or through other files
+/// 
+/// ## Interpolation Errors
+/// - **Unset variables**: A `${VAR}` reference without a default names an unset variable
+/// - **Unreadable secrets**: A `${file:PATH}` reference names a file that can't be read
+/// 
 /// ## File I/O Errors
 /// - **File not found**: The specified path doesn't exist
 /// - **Permission denied**: Insufficient permissions to read the file
@@ -119,9 +150,43 @@ use toml;
 /// parameters = { format = "json" }
 /// ```
 pub fn load_config<P: AsRef<Path>>(path: P) -> Result<Config, Box<dyn std::error::Error>> {
+    load_config_with_overrides(path, &[])
+}
+
+/// Loads configuration from a TOML file, then overrides single values with
+/// `KEY=VALUE` entries such as `inputs.mqtt_in.parameters.qos=2`.
+/// 
+/// Overrides apply after includes, templates and interpolation, and before the
+/// configuration is deserialised, so an override of the wrong type fails like
+/// the same value written in the file.
+pub fn load_config_with_overrides<P: AsRef<Path>>(
+    path: P,
+    overrides: &[String],
+) -> Result<Config, Box<dyn std::error::Error>> {
+    let path = path.as_ref();
     let content = fs::read_to_string(path)?;
-    let config: Config = toml::from_str(&content)?;
-    Ok(config)
+    let base = path.parent().unwrap_or(Path::new("."));
+    parse_config(&content, base, vec![path.canonicalize()?], overrides)
+}
+
+/// Loads configuration as the command line does: from `LIMINAL_CONFIG` if it
+/// is set, and otherwise from the TOML file at `path`, then overrides single
+/// values with the `LIMINAL__` environment variables followed by
+/// `overrides`. When the file does not exist and `LIMINAL__` variables are
+/// set, the configuration is assembled from the variables alone.
+pub fn load_config_with_env<P: AsRef<Path>>(
+    path: P,
+    overrides: &[String],
+) -> Result<Config, Box<dyn std::error::Error>> {
+    let path = path.as_ref();
+    let from_env = env::env_overrides();
+    let assembled = !from_env.is_empty() && !path.exists();
+    let overrides: Vec<String> = from_env.into_iter().chain(overrides.iter().cloned()).collect();
+    match env::inline_config()? {
+        Some(content) => parse_config(&content, Path::new("."), Vec::new(), &overrides),
+        None if assembled => parse_config("", Path::new("."), Vec::new(), &overrides),
+        None => load_config_with_overrides(path, &overrides),
+    }
 }
 
 /// Loads configuration from a TOML string.
@@ -193,8 +258,84 @@ pub fn load_config<P: AsRef<Path>>(path: P) -> Result<Config, Box<dyn std::error
 /// }
 /// ```
 pub fn load_config_from_string(content: &str) -> Result<Config, Box<dyn std::error::Error>> {
-    let config: Config = toml::from_str(content)?;
-    Ok(config)
+    parse_config(content, Path::new("."), Vec::new(), &[])
+}
+
+/// Parses configuration content, merging in the files it includes (relative to
+/// `base`), expanding stage templates, interpolating its strings and applying
+/// `overrides`. `stack` holds the files being loaded, to catch include cycles.
+fn parse_config(
+    content: &str,
+    base: &Path,
+    mut stack: Vec<PathBuf>,
+    overrides: &[String],
+) -> Result<Config, Box<dyn std::error::Error>> {
+    let mut table: toml::Table = toml::from_str(content)?;
+    let layered = table.contains_key("include");
+    if layered {
+        table = resolve_includes(table, base, &mut stack)?;
+    }
+    let templated = expand_templates(&mut table)?;
+    if !layered && !templated {
+        // Deserialise the source as written first, so that errors point at their line
+        let config: Config = toml::from_str(content)?;
+        if !content.contains('$') && overrides.is_empty() {
+            return Ok(config);
+        }
+    }
+
+    interpolate_table(&mut table)?;
+    apply_overrides(&mut table, overrides)?;
+    Ok(toml::Value::Table(table).try_into()?)
+}
+
+/// Merges the files listed in a table's `include` key beneath it: each file
+/// overrides the ones before it, and the table itself overrides them all.
+/// Included files may include further files.
+fn resolve_includes(mut table: toml::Table, base: &Path, stack: &mut Vec<PathBuf>) -> anyhow::Result<toml::Table> {
+    let Some(include) = table.remove("include") else {
+        return Ok(table);
+    };
+    let includes: Vec<String> = include
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("include must be an array of file paths"))?;
+
+    let mut merged = toml::Table::new();
+    for include in includes {
+        let path = base.join(&include);
+        let canonical = path
+            .canonicalize()
+            .map_err(|e| anyhow::anyhow!("Failed to include '{}': {}", path.display(), e))?;
+        if stack.contains(&canonical) {
+            return Err(anyhow::anyhow!("'{}' includes itself", canonical.display()));
+        }
+
+        let content = fs::read_to_string(&canonical)
+            .map_err(|e| anyhow::anyhow!("Failed to include '{}': {}", path.display(), e))?;
+        let included: toml::Table = toml::from_str(&content)
+            .map_err(|e| anyhow::anyhow!("Failed to parse included '{}': {}", path.display(), e))?;
+
+        stack.push(canonical.clone());
+        let included = resolve_includes(included, canonical.parent().unwrap_or(Path::new(".")), stack)?;
+        stack.pop();
+
+        merge_tables(&mut merged, included);
+    }
+    merge_tables(&mut merged, table);
+    Ok(merged)
+}
+
+/// Deep-merges `overlay` into `base`: tables are merged key by key, anything
+/// else (including arrays) is replaced.
+fn merge_tables(base: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(overlay)) => merge_tables(base, overlay),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
 }
 
 /// Creates a minimal default configuration for testing and examples.
@@ -211,7 +352,7 @@ pub fn load_config_from_string(content: &str) -> Result<Config, Box<dyn std::err
 /// 
 /// The default configuration includes:
 /// - **Input**: Simulated data source generating test data
-/// - **Pipeline**: Simple scale transformation
+/// - **Pipeline**: Differences between consecutive values
 /// - **Output**: Console logging for verification
 /// 
 /// # Example
@@ -239,67 +380,91 @@ pub fn load_config_from_string(content: &str) -> Result<Config, Box<dyn std::err
 /// [pipelines.default_pipeline]
 /// description = "Default processing pipeline"
 /// 
-/// [pipelines.default_pipeline.stages.scale]
-/// type = "scale"
+/// [pipelines.default_pipeline.stages.delta]
+/// type = "delta"
 /// inputs = ["raw_data"]
 /// output = "processed_data"
-/// parameters = { field_in = "value", field_out = "scaled_value", scale_factor = 1.0 }
+/// parameters = { field_in = "value", field_out = "value_delta" }
 /// 
 /// [outputs.default_console]
-/// type = "log"
+/// type = "console"
 /// inputs = ["processed_data"]
-/// parameters = { format = "pretty" }
 /// ```
 pub fn default_config() -> Config {
     use std::collections::HashMap;
-    use super::types::{StageConfig, PipelineConfig};
+    use super::types::{StageConfig, PipelineConfig, ShutdownConfig};
     
     // Create default input stage
     let default_input = StageConfig {
         r#type: "simulated".to_string(),
         inputs: None,
         output: Some("raw_data".to_string()),
+        side_outputs: None,
         concurrency: None,
         channel: None,
         timing: None,
+        state: None,
+        replicas: None,
+        partition_by: None,
+        restart: None,
+        sla: None,
+        ttl: None,
+        limits: None,
+        pause_when: None,
         parameters: Some({
             let mut params = HashMap::new();
             params.insert("field_out".to_string(), serde_json::json!("value"));
             params.insert("interval_ms".to_string(), serde_json::json!(1000));
             params
         }),
+        enabled: None,
     };
     
     // Create default pipeline stage
     let default_stage = StageConfig {
-        r#type: "scale".to_string(),
+        r#type: "delta".to_string(),
         inputs: Some(vec!["raw_data".to_string()]),
         output: Some("processed_data".to_string()),
+        side_outputs: None,
         concurrency: None,
         channel: None,
         timing: None,
+        state: None,
+        replicas: None,
+        partition_by: None,
+        restart: None,
+        sla: None,
+        ttl: None,
+        limits: None,
+        pause_when: None,
         parameters: Some({
             let mut params = HashMap::new();
             params.insert("field_in".to_string(), serde_json::json!("value"));
-            params.insert("field_out".to_string(), serde_json::json!("scaled_value"));
-            params.insert("scale_factor".to_string(), serde_json::json!(1.0));
+            params.insert("field_out".to_string(), serde_json::json!("value_delta"));
             params
         }),
+        enabled: None,
     };
     
     // Create default output stage
     let default_output = StageConfig {
-        r#type: "log".to_string(),
+        r#type: "console".to_string(),
         inputs: Some(vec!["processed_data".to_string()]),
         output: None,
+        side_outputs: None,
         concurrency: None,
         channel: None,
         timing: None,
-        parameters: Some({
-            let mut params = HashMap::new();
-            params.insert("format".to_string(), serde_json::json!("pretty"));
-            params
-        }),
+        state: None,
+        replicas: None,
+        partition_by: None,
+        restart: None,
+        sla: None,
+        ttl: None,
+        limits: None,
+        pause_when: None,
+        parameters: None,
+        enabled: None,
     };
     
     // Assemble the complete configuration
@@ -314,8 +479,10 @@ pub fn default_config() -> Config {
             let mut pipeline = PipelineConfig {
                 description: "Default processing pipeline".to_string(),
                 stages: HashMap::new(),
+                runtime: None,
+                enabled: None,
             };
-            pipeline.stages.insert("scale".to_string(), default_stage);
+            pipeline.stages.insert("delta".to_string(), default_stage);
             pipelines.insert("default_pipeline".to_string(), pipeline);
             pipelines
         },
@@ -324,5 +491,74 @@ pub fn default_config() -> Config {
             outputs.insert("default_console".to_string(), default_output);
             outputs
         },
+        checkpoint: None,
+        record: None,
+        channel_report: None,
+        memory_report: None,
+        runtimes: HashMap::new(),
+        conditions: HashMap::new(),
+        credentials: HashMap::new(),
+        shutdown: ShutdownConfig::default(),
+        control: None,
+        admin: None,
+        ha: None,
+        security: None,
+        strict: true,
+        plugins_dir: None,
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_includes_merge_with_later_files_overriding() {
+        let directory = std::env::temp_dir().join(format!("liminal-include-{}", std::process::id()));
+        fs::create_dir_all(directory.join("sites")).unwrap();
+        fs::write(
+            directory.join("common.toml"),
+            r#"
+            [inputs.plant]
+            type = "mqtt_sub"
+            output = "raw"
+            parameters = { broker_url = "mqtt://localhost:1883", qos = 1, topics = ["plant/#"] }
+
+            [outputs.console]
+            type = "console"
+            inputs = ["raw"]
+            "#,
+        )
+        .unwrap();
+        fs::write(
+            directory.join("sites/north.toml"),
+            r#"
+            include = ["../common.toml"]
+
+            [inputs.plant.parameters]
+            broker_url = "mqtt://north:1883"
+            topics = ["north/#"]
+            "#,
+        )
+        .unwrap();
+        fs::write(
+            directory.join("north-debug.toml"),
+            "include = [\"sites/north.toml\"]\n[inputs.plant.parameters]\nqos = 0\n",
+        )
+        .unwrap();
+        fs::write(directory.join("loop.toml"), "include = [\"loop.toml\"]\n").unwrap();
+
+        let config = load_config(directory.join("north-debug.toml")).unwrap();
+        let loop_error = load_config(directory.join("loop.toml")).unwrap_err();
+        fs::remove_dir_all(&directory).unwrap();
+
+        let plant = &config.inputs["plant"];
+        assert_eq!(plant.r#type, "mqtt_sub");
+        assert_eq!(plant.output.as_deref(), Some("raw"));
+        let parameters = plant.parameters.as_ref().unwrap();
+        assert_eq!(parameters["broker_url"], "mqtt://north:1883");
+        assert_eq!(parameters["topics"], serde_json::json!(["north/#"]));
+        assert_eq!(parameters["qos"], 0);
+        assert!(config.outputs.contains_key("console"));
+        assert!(loop_error.to_string().ends_with("loop.toml' includes itself"));
     }
-}
\ No newline at end of file
+}
@@ -4,13 +4,22 @@ pub mod loader;
 pub mod types;
 pub mod validation;
 pub mod field;
+pub mod graph;
+pub mod env;
+pub mod interpolate;
+pub mod overrides;
+pub mod template;
+pub mod check;
 pub mod params;
+pub mod schema;
+pub mod select;
+pub mod starter;
 pub mod traits;
 
 pub use field::FieldConfig;
 pub use traits::ProcessorConfig;
 
-pub use loader::{load_config};
-pub use params::{extract_param, extract_field_params};
-pub use types::{ Config, StageConfig, TimingConfig };
-pub use validation::validate_config;
+pub use loader::{load_config, load_config_with_env, load_config_with_overrides};
+pub use params::{FIELD_PARAMS, ParamSpec, ParamType, ProcessorMetadata, extract_param, extract_field_params, require_param};
+pub use types::{ Config, StageConfig, StateConfig, TimingConfig };
+pub use validation::{parameter_errors, validate_config};
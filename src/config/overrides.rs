@@ -0,0 +1,134 @@
+//! Configuration Overrides
+//!
+//! Replaces single values of a configuration after it is loaded, from
+//! `--set KEY=VALUE` on the command line, so that a quick experiment or one
+//! container of a deployment can differ from the file without editing it:
+//!
+//! ```text
+//! liminal --set inputs.mqtt_in.parameters.broker_url=tcp://test:1883 \
+//!         --set pipelines.main.stages.scale.parameters.scale_factor=2.5
+//! ```
+//!
+//! - `KEY` is a dotted path of table keys; array entries are addressed by
+//!   index, as in `outputs.db.inputs.0`. Tables missing along the path are
+//!   created, so an override can add a parameter the file leaves out
+//! - `VALUE` is read as a TOML value, such as `2.5`, `true`, `["a", "b"]` or
+//!   `{ type = "direct" }`, and as a plain string when it is not one
+//!
+//! Overrides apply after includes, templates and interpolation, in the order
+//! given, so a later override of the same key wins. Override values are not
+//! interpolated.
+
+use anyhow::{Result, anyhow};
+
+/// Apply `KEY=VALUE` overrides to a configuration table.
+pub fn apply_overrides(table: &mut toml::Table, overrides: &[String]) -> Result<()> {
+    for entry in overrides {
+        let (key, value) = entry
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Override '{}' is not of the form KEY=VALUE", entry))?;
+        set_path(table, key.trim(), parse_value(value.trim()))
+            .map_err(|e| anyhow!("Failed to override '{}': {}", key.trim(), e))?;
+    }
+    Ok(())
+}
+
+/// Read a value as TOML, or else as a plain string.
+fn parse_value(text: &str) -> toml::Value {
+    format!("value = {}", text)
+        .parse::<toml::Table>()
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| toml::Value::String(text.to_string()))
+}
+
+fn set_path(table: &mut toml::Table, path: &str, value: toml::Value) -> Result<()> {
+    let keys: Vec<&str> = path.split('.').collect();
+    if keys.iter().any(|key| key.is_empty()) {
+        return Err(anyhow!("empty key in path"));
+    }
+    let mut root = toml::Value::Table(std::mem::take(table));
+    let set = set_in(&mut root, &keys, value);
+    if let toml::Value::Table(root) = root {
+        *table = root;
+    }
+    set
+}
+
+fn set_in(mut current: &mut toml::Value, keys: &[&str], value: toml::Value) -> Result<()> {
+    let (last, parents) = keys.split_last().ok_or_else(|| anyhow!("empty path"))?;
+    for key in parents {
+        current = child(current, key)?;
+    }
+    match current {
+        toml::Value::Table(table) => {
+            table.insert(last.to_string(), value);
+        }
+        toml::Value::Array(items) => *index(items, last)? = value,
+        other => return Err(anyhow!("cannot set a key inside a {} value", other.type_str())),
+    }
+    Ok(())
+}
+
+/// Step into the entry `key` of a table or array, creating a table if a
+/// table has none.
+fn child<'a>(value: &'a mut toml::Value, key: &str) -> Result<&'a mut toml::Value> {
+    match value {
+        toml::Value::Table(table) => Ok(table
+            .entry(key)
+            .or_insert_with(|| toml::Value::Table(toml::Table::new()))),
+        toml::Value::Array(items) => index(items, key),
+        _ => Err(anyhow!("cannot set a key inside a {} value", value.type_str())),
+    }
+}
+
+fn index<'a>(items: &'a mut [toml::Value], key: &str) -> Result<&'a mut toml::Value> {
+    let len = items.len();
+    key.parse::<usize>()
+        .ok()
+        .and_then(|index| items.get_mut(index))
+        .ok_or_else(|| anyhow!("'{}' is not an index of an array of {} entries", key, len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overrides_set_values_by_path() {
+        let mut table: toml::Table = toml::from_str(
+            r#"
+            [inputs.mqtt_in]
+            type = "mqtt"
+            output = "raw"
+            parameters = { broker_url = "tcp://prod:1883", qos = 1 }
+
+            [outputs.db]
+            type = "log"
+            inputs = ["raw", "alerts"]
+            "#,
+        )
+        .unwrap();
+
+        let overrides = [
+            "inputs.mqtt_in.parameters.broker_url=tcp://test:1883",
+            "inputs.mqtt_in.parameters.qos = 2",
+            "inputs.mqtt_in.parameters.topics=[\"site/#\"]",
+            "outputs.db.inputs.1=warnings",
+            "outputs.db.channel={ type = \"direct\" }",
+        ]
+        .map(String::from);
+        apply_overrides(&mut table, &overrides).unwrap();
+
+        let parameters = &table["inputs"]["mqtt_in"]["parameters"];
+        assert_eq!(parameters["broker_url"].as_str(), Some("tcp://test:1883"));
+        assert_eq!(parameters["qos"].as_integer(), Some(2));
+        assert_eq!(parameters["topics"][0].as_str(), Some("site/#"));
+        assert_eq!(table["outputs"]["db"]["inputs"][1].as_str(), Some("warnings"));
+        assert_eq!(table["outputs"]["db"]["channel"]["type"].as_str(), Some("direct"));
+
+        for bad in ["no_equals", "outputs.db.type.inner=1", "outputs.db.inputs.5=x", "inputs..x=1"] {
+            assert!(apply_overrides(&mut table, &[bad.to_string()]).is_err(), "{}", bad);
+        }
+    }
+}
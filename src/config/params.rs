@@ -7,7 +7,9 @@
 //! # Parameter Extraction
 //! 
 //! The `extract_param` function provides type-safe extraction of parameters with
-//! fallback to default values when parameters are missing or invalid.
+//! fallback to default values when parameters are missing or invalid, and
+//! `require_param` reads parameters without a default, failing with a
+//! `LiminalError` that names the parameter.
 //! 
 //! # Field Configuration Extraction
 //! 
@@ -35,8 +37,17 @@
 //! // Extract field configuration
 //! let field_config = extract_field_params(&config.parameters);
 //! ```
+//!
+//! # Parameter Metadata
+//!
+//! Since `extract_param` falls back to defaults, a misspelt key or a value of the
+//! wrong type would otherwise go unnoticed. Each built-in processor describes the
+//! parameters it reads with a `ProcessorMetadata`, which `check_parameters` uses
+//! to report unknown keys and type mismatches.
 
 use crate::config::field::FieldConfig;
+use crate::error::LiminalError;
+use serde_json::Value;
 use std::collections::HashMap;
 
 /// Extracts a typed parameter from the stage configuration parameters.
@@ -115,6 +126,31 @@ where
         .unwrap_or(default)
 }
 
+/// Extracts a required typed parameter from the stage configuration parameters.
+///
+/// Unlike `extract_param`, a missing parameter is a
+/// `LiminalError::MissingParameter` and a value that cannot be deserialised to
+/// `T` a `LiminalError::InvalidParameter`, so that the pipeline builder can
+/// report which stage is misconfigured and how.
+///
+/// ```rust
+/// let field_in: String = require_param(&config.parameters, "field_in")?;
+/// ```
+pub fn require_param<T>(
+    params: &Option<HashMap<String, serde_json::Value>>,
+    key: &str,
+) -> Result<T, LiminalError>
+where
+    T: serde::de::DeserializeOwned,
+{
+    let value = params
+        .as_ref()
+        .and_then(|p| p.get(key))
+        .filter(|v| !v.is_null())
+        .ok_or_else(|| LiminalError::missing_parameter(key))?;
+    serde_json::from_value(value.clone()).map_err(|e| LiminalError::invalid_parameter(key, e))
+}
+
 /// Extracts field mapping configuration from stage parameters.
 /// 
 /// This function analyzes the parameter map to determine the appropriate field
@@ -259,4 +295,234 @@ pub fn extract_field_params(params: &Option<HashMap<String, serde_json::Value>>)
     }
 
     FieldConfig::None
-}
\ No newline at end of file
+}
+
+/// The type a processor parameter must have.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParamType {
+    String,
+    /// A whole number, zero or greater
+    Integer,
+    /// Any number, whole or not
+    Number,
+    Boolean,
+    Array,
+    /// A TOML table
+    Object,
+    /// One of a fixed set of strings
+    Choice(&'static [&'static str]),
+    /// Any value (e.g. a value written into payloads)
+    Any,
+}
+
+impl ParamType {
+    pub fn accepts(&self, value: &Value) -> bool {
+        match self {
+            ParamType::String => value.is_string(),
+            ParamType::Integer => value.is_u64(),
+            ParamType::Number => value.is_number(),
+            ParamType::Boolean => value.is_boolean(),
+            ParamType::Array => value.is_array(),
+            ParamType::Object => value.is_object(),
+            ParamType::Choice(choices) => value.as_str().is_some_and(|value| choices.contains(&value)),
+            ParamType::Any => true,
+        }
+    }
+
+    /// What the parameter should be, e.g. "a number" or "one of 'keep', 'drop'".
+    pub fn describe(&self) -> String {
+        match self {
+            ParamType::String => "a string".to_string(),
+            ParamType::Integer => "a non-negative integer".to_string(),
+            ParamType::Number => "a number".to_string(),
+            ParamType::Boolean => "a boolean".to_string(),
+            ParamType::Array => "an array".to_string(),
+            ParamType::Object => "a table".to_string(),
+            ParamType::Choice(choices) => {
+                let choices: Vec<String> = choices.iter().map(|choice| format!("'{}'", choice)).collect();
+                format!("one of {}", choices.join(", "))
+            }
+            ParamType::Any => "any value".to_string(),
+        }
+    }
+}
+
+/// Describes a value as found in a configuration, for error messages.
+fn describe_value(value: &Value) -> String {
+    match value {
+        Value::Null => "nothing".to_string(),
+        Value::Bool(_) => "a boolean".to_string(),
+        Value::Number(number) if number.is_u64() => "an integer".to_string(),
+        Value::Number(number) if number.is_i64() => "a negative integer".to_string(),
+        Value::Number(_) => "a number".to_string(),
+        Value::String(string) => format!("the string '{}'", string),
+        Value::Array(_) => "an array".to_string(),
+        Value::Object(_) => "a table".to_string(),
+    }
+}
+
+/// A parameter read by a processor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParamSpec {
+    pub name: &'static str,
+    pub kind: ParamType,
+    pub description: &'static str,
+}
+
+impl ParamSpec {
+    pub const fn new(name: &'static str, kind: ParamType, description: &'static str) -> Self {
+        Self { name, kind, description }
+    }
+}
+
+/// Describes a processor type and the parameters it accepts.
+#[derive(Debug)]
+pub struct ProcessorMetadata {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub parameters: &'static [ParamSpec],
+    /// Parameter groups shared with other processors, such as the MQTT
+    /// connection settings
+    pub shared: &'static [&'static [ParamSpec]],
+}
+
+impl ProcessorMetadata {
+    /// Every parameter the processor accepts, its own first.
+    pub fn all_parameters(&self) -> impl Iterator<Item = &ParamSpec> {
+        self.parameters.iter().chain(self.shared.iter().flat_map(|group| group.iter()))
+    }
+
+    pub fn parameter(&self, name: &str) -> Option<&ParamSpec> {
+        self.all_parameters().find(|param| param.name == name)
+    }
+
+    /// Checks the parameters of stage `stage` against this metadata, returning
+    /// each offending key (sorted) with the problem found.
+    pub fn check_parameters(
+        &self,
+        stage: &str,
+        params: &Option<HashMap<String, Value>>,
+    ) -> Vec<(String, anyhow::Error)> {
+        let Some(params) = params else {
+            return Vec::new();
+        };
+        let mut keys: Vec<&String> = params.keys().collect();
+        keys.sort();
+
+        let mut errors = Vec::new();
+        for key in keys {
+            match self.parameter(key) {
+                None => {
+                    let hint = closest(key, self.all_parameters().map(|param| param.name))
+                        .map(|name| format!(" (did you mean '{}'?)", name))
+                        .unwrap_or_default();
+                    errors.push((
+                        key.clone(),
+                        anyhow::anyhow!(
+                            "Stage '{}' has unknown parameter '{}' for processor '{}'{}",
+                            stage, key, self.name, hint
+                        ),
+                    ));
+                }
+                Some(param) if !param.kind.accepts(&params[key]) => {
+                    errors.push((
+                        key.clone(),
+                        anyhow::anyhow!(
+                            "Stage '{}' parameter '{}' should be {}, found {}",
+                            stage, key, param.kind.describe(), describe_value(&params[key])
+                        ),
+                    ));
+                }
+                Some(_) => {}
+            }
+        }
+        errors
+    }
+}
+
+/// The candidate closest to `name`, if it is close enough to be a likely typo.
+pub(crate) fn closest<'a>(name: &str, candidates: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    fn distance(a: &str, b: &str) -> usize {
+        let b: Vec<char> = b.chars().collect();
+        let mut row: Vec<usize> = (0..=b.len()).collect();
+        for (i, ca) in a.chars().enumerate() {
+            let mut previous = row[0];
+            row[0] = i + 1;
+            for (j, cb) in b.iter().enumerate() {
+                let current = row[j + 1];
+                row[j + 1] = if ca == *cb { previous } else { 1 + previous.min(row[j]).min(row[j + 1]) };
+                previous = current;
+            }
+        }
+        row[b.len()]
+    }
+
+    candidates
+        .map(|candidate| (distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= 2.max(name.len() / 4))
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Field mapping parameters read by `extract_field_params`.
+pub const FIELD_PARAMS: &[ParamSpec] = &[
+    ParamSpec::new("field_in", ParamType::String, "Input field"),
+    ParamSpec::new("field_out", ParamType::String, "Output field"),
+    ParamSpec::new("fields_in", ParamType::Array, "Input fields, paired with fields_out"),
+    ParamSpec::new("fields_out", ParamType::Array, "Output fields, paired with fields_in"),
+    ParamSpec::new("field_mapping", ParamType::Object, "Input field to output field mapping"),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const METADATA: ProcessorMetadata = ProcessorMetadata {
+        name: "scale",
+        description: "Scales a numeric field",
+        parameters: &[
+            ParamSpec::new("scale_factor", ParamType::Number, "Multiplier"),
+            ParamSpec::new("rounding", ParamType::Choice(&["none", "nearest"]), "Rounding of the result"),
+        ],
+        shared: &[FIELD_PARAMS],
+    };
+
+    #[test]
+    fn test_check_parameters_reports_unknown_and_mistyped_keys() {
+        let params = Some(HashMap::from([
+            ("scale_facter".to_string(), json!(2.0)),
+            ("field_in".to_string(), json!("raw")),
+            ("field_out".to_string(), json!(5)),
+            ("rounding".to_string(), json!("up")),
+            ("scale_factor".to_string(), json!(3)),
+        ]));
+
+        let errors: Vec<(String, String)> = METADATA
+            .check_parameters("scaler", &params)
+            .into_iter()
+            .map(|(key, error)| (key, error.to_string()))
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                (
+                    "field_out".to_string(),
+                    "Stage 'scaler' parameter 'field_out' should be a string, found an integer".to_string()
+                ),
+                (
+                    "rounding".to_string(),
+                    "Stage 'scaler' parameter 'rounding' should be one of 'none', 'nearest', found the string 'up'"
+                        .to_string()
+                ),
+                (
+                    "scale_facter".to_string(),
+                    "Stage 'scaler' has unknown parameter 'scale_facter' for processor 'scale' (did you mean 'scale_factor'?)"
+                        .to_string()
+                ),
+            ]
+        );
+
+        assert!(METADATA.check_parameters("scaler", &None).is_empty());
+    }
+}
@@ -0,0 +1,120 @@
+//! Configuration Schema
+//!
+//! Builds a JSON Schema for configuration files, for `liminal schema`. The
+//! structure comes from the configuration types, and stage `parameters` are
+//! described per processor type from the processor metadata, so editors can
+//! complete and check them:
+//!
+//! ```text
+//! liminal schema > liminal.schema.json
+//! ```
+//!
+//! With the Even Better TOML extension for VS Code, a configuration opts in
+//! with a `#:schema ./liminal.schema.json` comment on its first line.
+
+use super::params::{ParamSpec, ParamType, ProcessorMetadata};
+use super::types::Config;
+
+use serde_json::{Map, Value, json};
+
+fn parameter_schema(param: &ParamSpec) -> Value {
+    let mut schema = match param.kind {
+        ParamType::String => json!({ "type": "string" }),
+        ParamType::Integer => json!({ "type": "integer", "minimum": 0 }),
+        ParamType::Number => json!({ "type": "number" }),
+        ParamType::Boolean => json!({ "type": "boolean" }),
+        ParamType::Array => json!({ "type": "array" }),
+        ParamType::Object => json!({ "type": "object" }),
+        ParamType::Choice(choices) => json!({ "type": "string", "enum": choices }),
+        ParamType::Any => json!({}),
+    };
+    schema["description"] = json!(param.description);
+    schema
+}
+
+/// Schema of the `parameters` table of one processor type. Unknown keys are
+/// rejected, as in strict mode.
+fn parameters_schema(metadata: &ProcessorMetadata) -> Value {
+    let properties: Map<String, Value> = metadata
+        .all_parameters()
+        .map(|param| (param.name.to_string(), parameter_schema(param)))
+        .collect();
+    json!({
+        "type": "object",
+        "description": format!("Parameters of the {} processor: {}", metadata.name, metadata.description),
+        "properties": properties,
+        "additionalProperties": false,
+    })
+}
+
+/// JSON Schema of a configuration file. `types` are the registered processor
+/// types, and `metadata` describes the parameters of those that have metadata.
+pub fn config_schema(types: &[String], metadata: impl Fn(&str) -> Option<&'static ProcessorMetadata>) -> Value {
+    let mut schema = serde_json::to_value(schemars::schema_for!(Config)).unwrap_or_default();
+    schema["title"] = json!("Liminal configuration");
+
+    // Keys handled by the loader rather than the configuration types
+    schema["properties"]["include"] = json!({
+        "type": "array",
+        "items": { "type": "string" },
+        "description": "Files merged beneath this one, relative to it; later files override earlier ones",
+    });
+
+    let mut types = types.to_vec();
+    types.sort();
+    let conditions: Vec<Value> = types
+        .iter()
+        .filter_map(|kind| {
+            let metadata = metadata(kind)?;
+            Some(json!({
+                "if": { "properties": { "type": { "const": kind } }, "required": ["type"] },
+                "then": { "properties": { "parameters": parameters_schema(metadata) } },
+            }))
+        })
+        .collect();
+
+    let stage = &mut schema["$defs"]["StageConfig"];
+    stage["properties"]["type"]["enum"] = json!(types);
+    stage["properties"]["foreach"] = json!({
+        "type": "array",
+        "description": "Expand this stage once per entry, substituting {{key}} placeholders (or {{item}} for plain values)",
+    });
+    stage["allOf"] = json!(conditions);
+
+    schema
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const METADATA: ProcessorMetadata = ProcessorMetadata {
+        name: "delta",
+        description: "Emits differences between consecutive values",
+        parameters: &[
+            ParamSpec::new("field_in", ParamType::String, "Numeric field to difference"),
+            ParamSpec::new("on_negative", ParamType::Choice(&["keep", "reset", "drop"]), "How a decrease is treated"),
+        ],
+        shared: &[],
+    };
+
+    #[test]
+    fn test_schema_describes_structure_and_processor_parameters() {
+        let types = vec!["delta".to_string(), "console".to_string()];
+        let schema = config_schema(&types, |kind| (kind == "delta").then_some(&METADATA));
+
+        assert!(schema["properties"]["pipelines"].is_object());
+        assert!(schema["properties"]["include"].is_object());
+
+        let stage = &schema["$defs"]["StageConfig"];
+        assert_eq!(stage["properties"]["type"]["enum"], json!(["console", "delta"]));
+        assert!(stage["properties"]["channel"].is_object());
+
+        let conditions = stage["allOf"].as_array().unwrap();
+        assert_eq!(conditions.len(), 1);
+        assert_eq!(conditions[0]["if"]["properties"]["type"]["const"], "delta");
+        let parameters = &conditions[0]["then"]["properties"]["parameters"];
+        assert_eq!(parameters["additionalProperties"], false);
+        assert_eq!(parameters["properties"]["on_negative"]["enum"], json!(["keep", "reset", "drop"]));
+    }
+}
@@ -0,0 +1,127 @@
+//! Pipeline Selection
+//!
+//! Narrows a configuration to part of it, from `--pipeline` and
+//! `--exclude-stage` on the command line, so that one configuration shared by
+//! a whole site can run just its diagnostics pipeline, or everything but a
+//! sink that is down for maintenance.
+//!
+//! Selecting pipelines drops every other pipeline, along with the sources
+//! that only feed stages outside the selection and the sinks that only read
+//! from them: a source is kept if a selected stage reads one of its channels,
+//! a sink if it reads a channel a selected stage publishes. Excluding a stage
+//! removes it wherever it is, before pipelines are selected.
+
+use crate::config::types::{Config, StageConfig};
+
+use anyhow::{Result, anyhow};
+use std::collections::BTreeSet;
+
+/// Keep only the named pipelines (all of them when none are named) and the
+/// sources and sinks connected to them, without the excluded stages.
+pub fn select(config: &mut Config, pipelines: &[String], excluded: &[String]) -> Result<()> {
+    for name in pipelines {
+        if !config.pipelines.contains_key(name) {
+            let known: BTreeSet<&String> = config.pipelines.keys().collect();
+            return Err(anyhow!("No pipeline named '{}' (pipelines: {:?})", name, known));
+        }
+    }
+    for name in excluded {
+        let mut found = config.inputs.remove(name).is_some();
+        found |= config.outputs.remove(name).is_some();
+        for pipeline in config.pipelines.values_mut() {
+            found |= pipeline.stages.remove(name).is_some();
+        }
+        if !found {
+            return Err(anyhow!("No stage named '{}' to exclude", name));
+        }
+    }
+    if pipelines.is_empty() {
+        return Ok(());
+    }
+
+    config.pipelines.retain(|name, _| pipelines.contains(name));
+    let selected: Vec<&StageConfig> = config.pipelines.values().flat_map(|pipeline| pipeline.stages.values()).collect();
+    let read: BTreeSet<&str> = selected.iter().flat_map(|stage| stage.inputs.iter().flatten()).map(String::as_str).collect();
+    let published: BTreeSet<&str> = selected.iter().flat_map(|stage| publishes(stage)).collect();
+
+    config.inputs.retain(|_, stage| publishes(stage).any(|channel| read.contains(channel)));
+    config.outputs.retain(|_, stage| stage.inputs.iter().flatten().any(|channel| published.contains(channel.as_str())));
+
+    // Stages left reading a channel nothing selected publishes never receive anything
+    let sourced: BTreeSet<&str> = config.inputs.values().flat_map(publishes).collect();
+    for channel in read.iter().filter(|channel| !published.contains(*channel) && !sourced.contains(*channel)) {
+        tracing::warn!("Channel '{}' is read by a selected pipeline but published by no selected stage", channel);
+    }
+    Ok(())
+}
+
+/// Channels a stage publishes to.
+fn publishes(stage: &StageConfig) -> impl Iterator<Item = &str> {
+    stage.output.iter().chain(stage.side_outputs.iter().flatten()).map(String::as_str)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::loader::load_config_from_string;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_selection_keeps_connected_sources_and_sinks() {
+        let site = r#"
+            [inputs.plc]
+            type = "simulated"
+            output = "raw"
+
+            [inputs.heartbeat]
+            type = "simulated"
+            output = "beats"
+
+            [pipelines.production]
+            description = "Scaled readings for the historian"
+
+            [pipelines.production.stages.scale]
+            type = "scale"
+            inputs = ["raw"]
+            output = "scaled"
+
+            [pipelines.diagnostics]
+            description = "Gateway health"
+
+            [pipelines.diagnostics.stages.health]
+            type = "heartbeat"
+            inputs = ["beats", "raw"]
+            output = "health"
+
+            [outputs.historian]
+            type = "log"
+            inputs = ["scaled"]
+
+            [outputs.console]
+            type = "console"
+            inputs = ["health"]
+
+            [outputs.pager]
+            type = "log"
+            inputs = ["health"]
+        "#;
+        let names = |stages: &HashMap<String, StageConfig>| stages.keys().cloned().collect::<BTreeSet<_>>();
+        let set = |names: &[&str]| names.iter().map(|name| name.to_string()).collect::<BTreeSet<_>>();
+
+        let mut config = load_config_from_string(site).unwrap();
+        select(&mut config, &["diagnostics".to_string()], &["pager".to_string()]).unwrap();
+        assert_eq!(config.pipelines.keys().collect::<Vec<_>>(), ["diagnostics"]);
+        assert_eq!(names(&config.inputs), set(&["heartbeat", "plc"]));
+        assert_eq!(names(&config.outputs), set(&["console"]));
+
+        let mut config = load_config_from_string(site).unwrap();
+        select(&mut config, &[], &["heartbeat".to_string(), "scale".to_string()]).unwrap();
+        assert_eq!(names(&config.inputs), set(&["plc"]));
+        assert!(config.pipelines["production"].stages.is_empty());
+        assert_eq!(names(&config.outputs), set(&["console", "historian", "pager"]));
+
+        let mut config = load_config_from_string(site).unwrap();
+        assert!(select(&mut config, &["nightly".to_string()], &[]).is_err());
+        assert!(select(&mut config, &[], &["nothing".to_string()]).is_err());
+    }
+}
@@ -0,0 +1,135 @@
+//! Starter Configurations
+//!
+//! Writes a runnable configuration to start from, for `liminal init`. Each
+//! template is a variant of `default_config()`, with its inputs or outputs
+//! swapped for the named transport:
+//!
+//! ```text
+//! liminal init                          # simulated-demo, to ./liminal.toml
+//! liminal init mqtt-to-file -o plant.toml
+//! liminal -c plant.toml
+//! ```
+
+use super::loader::default_config;
+use super::types::{Config, StageConfig};
+
+use anyhow::Result;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum StarterTemplate {
+    /// Simulated sensor, differenced and printed to the console
+    SimulatedDemo,
+    /// MQTT subscription, differenced and written to a JSON lines file
+    MqttToFile,
+    /// TCP server, differenced and forwarded to a TCP peer
+    TcpBridge,
+}
+
+fn stage(kind: &str, inputs: Option<&str>, output: Option<&str>, parameters: Value) -> StageConfig {
+    StageConfig {
+        r#type: kind.to_string(),
+        inputs: inputs.map(|input| vec![input.to_string()]),
+        output: output.map(str::to_string),
+        parameters: serde_json::from_value(parameters).ok(),
+        ..Default::default()
+    }
+}
+
+/// The configuration of a starter template.
+pub fn starter_config(template: StarterTemplate) -> Config {
+    let mut config = default_config();
+    match template {
+        StarterTemplate::SimulatedDemo => {}
+        StarterTemplate::MqttToFile => {
+            config.inputs = HashMap::from([(
+                "mqtt_source".to_string(),
+                stage("mqtt_sub", None, Some("raw_data"), json!({
+                    "broker_url": "mqtt://${MQTT_HOST:-localhost}:1883",
+                    "client_id": "liminal",
+                    "topics": ["sensors/#"],
+                    "qos": 1,
+                })),
+            )]);
+            config.outputs = HashMap::from([(
+                "file_sink".to_string(),
+                stage("file", Some("processed_data"), None, json!({
+                    "file_path": "data/readings.jsonl",
+                    "format": "json",
+                    "append": true,
+                    "create_dirs": true,
+                })),
+            )]);
+        }
+        StarterTemplate::TcpBridge => {
+            config.inputs = HashMap::from([(
+                "tcp_source".to_string(),
+                stage("tcp_input", None, Some("raw_data"), json!({
+                    "mode": "server",
+                    "host": "0.0.0.0",
+                    "port": 9000,
+                })),
+            )]);
+            config.outputs = HashMap::from([(
+                "tcp_sink".to_string(),
+                stage("tcp_output", Some("processed_data"), None, json!({
+                    "mode": "client",
+                    "host": "${TCP_PEER:-localhost}",
+                    "port": 9001,
+                    "reconnect": true,
+                })),
+            )]);
+        }
+    }
+    config
+}
+
+/// Remove nulls, which TOML can't represent, so unset options are left out.
+fn strip_nulls(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            map.retain(|_, value| !value.is_null());
+            map.values_mut().for_each(strip_nulls);
+        }
+        Value::Array(items) => items.iter_mut().for_each(strip_nulls),
+        _ => {}
+    }
+}
+
+/// Render a starter template as TOML, with keys sorted so that the output is
+/// the same on every run.
+pub fn render(template: StarterTemplate) -> Result<String> {
+    let mut value = serde_json::to_value(starter_config(template))?;
+    strip_nulls(&mut value);
+    let name = clap::ValueEnum::to_possible_value(&template).map(|value| value.get_name().to_string()).unwrap_or_default();
+    Ok(format!(
+        "# Liminal starter configuration ({})\n# Run with `liminal -c <this file>`; check changes with `liminal validate -c <this file>`\n\n{}",
+        name,
+        toml::to_string(&value)?
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::check::check_config;
+    use crate::config::loader::load_config_from_string;
+    use crate::processors::factory::{processor_exists, processor_metadata};
+
+    #[test]
+    fn test_every_template_renders_a_valid_configuration() {
+        for template in [StarterTemplate::SimulatedDemo, StarterTemplate::MqttToFile, StarterTemplate::TcpBridge] {
+            let source = render(template).unwrap();
+            let config = load_config_from_string(&source).unwrap();
+            let problems = check_config(&config, &source, processor_exists, processor_metadata);
+            assert!(problems.is_empty(), "{:?}: {:?}", template, problems);
+            assert!(config.strict);
+        }
+
+        let config = load_config_from_string(&render(StarterTemplate::SimulatedDemo).unwrap()).unwrap();
+        assert_eq!(serde_json::to_value(config).unwrap(), serde_json::to_value(default_config()).unwrap());
+        let config = load_config_from_string(&render(StarterTemplate::MqttToFile).unwrap()).unwrap();
+        assert_eq!(config.inputs["mqtt_source"].parameters.as_ref().unwrap()["broker_url"], "mqtt://localhost:1883");
+    }
+}
@@ -0,0 +1,191 @@
+//! Stage Templates
+//!
+//! Expands stage definitions carrying a `foreach` key into one stage per entry,
+//! so a fleet of near-identical devices needs a single definition:
+//!
+//! ```toml
+//! [inputs."plant_{{building}}"]
+//! foreach = [
+//!     { building = "north", qos = 1 },
+//!     { building = "south", qos = 0 },
+//! ]
+//! type = "mqtt_sub"
+//! output = "raw_{{building}}"
+//! parameters = { topics = ["{{building}}/sensors/#"], qos = "{{qos}}" }
+//! ```
+//!
+//! Each entry's keys are substituted for `{{key}}` placeholders in the stage
+//! name, and in the keys and string values of the definition. A value that is
+//! only a placeholder takes the entry's value as-is, so `"{{qos}}"` above is an
+//! integer. Entries may also be plain values, available as `{{item}}`:
+//!
+//! ```toml
+//! [outputs."log_{{item}}"]
+//! foreach = ["north", "south"]
+//! type = "file"
+//! inputs = ["raw_{{item}}"]
+//! parameters = { file_path = "logs/{{item}}.jsonl" }
+//! ```
+//!
+//! Templates work in `inputs`, `outputs` and the stages of every pipeline.
+
+use anyhow::{Result, anyhow};
+use toml::{Table, Value};
+
+/// Expand every stage template in `table`. Returns whether there were any.
+pub fn expand_templates(table: &mut Table) -> Result<bool> {
+    let mut expanded = false;
+    for section in ["inputs", "outputs"] {
+        if let Some(Value::Table(stages)) = table.get_mut(section) {
+            expanded |= expand_stages(stages, section)?;
+        }
+    }
+    if let Some(Value::Table(pipelines)) = table.get_mut("pipelines") {
+        for (name, pipeline) in pipelines.iter_mut() {
+            if let Some(Value::Table(stages)) = pipeline.get_mut("stages") {
+                expanded |= expand_stages(stages, &format!("pipelines.{}.stages", name))?;
+            }
+        }
+    }
+    Ok(expanded)
+}
+
+fn expand_stages(stages: &mut Table, section: &str) -> Result<bool> {
+    let templates: Vec<String> = stages
+        .iter()
+        .filter(|(_, stage)| stage.get("foreach").is_some())
+        .map(|(name, _)| name.clone())
+        .collect();
+
+    for name in &templates {
+        let table = format!("{}.{}", section, name);
+        let Some(Value::Table(mut stage)) = stages.remove(name) else {
+            continue;
+        };
+        let Some(Value::Array(entries)) = stage.remove("foreach") else {
+            return Err(anyhow!("{}.foreach must be an array", table));
+        };
+
+        for entry in entries {
+            let vars = match entry {
+                Value::Table(vars) => vars,
+                item => Table::from_iter([("item".to_string(), item)]),
+            };
+            let instance = substitute_str(name, &vars)
+                .map_err(|e| anyhow!("In template [{}]: {}", table, e))?;
+            let instance = match instance {
+                Value::String(instance) => instance,
+                other => other.to_string(),
+            };
+            if stages.contains_key(&instance) {
+                return Err(anyhow!(
+                    "Template [{}] defines stage '{}' more than once; its name needs a placeholder that differs per entry",
+                    table, instance
+                ));
+            }
+            let definition = substitute(&Value::Table(stage.clone()), &vars)
+                .map_err(|e| anyhow!("In template [{}]: {}", table, e))?;
+            stages.insert(instance, definition);
+        }
+    }
+    Ok(!templates.is_empty())
+}
+
+/// Substitute placeholders in the keys and strings of a value.
+fn substitute(value: &Value, vars: &Table) -> Result<Value> {
+    Ok(match value {
+        Value::String(text) => substitute_str(text, vars)?,
+        Value::Array(items) => Value::Array(items.iter().map(|item| substitute(item, vars)).collect::<Result<_>>()?),
+        Value::Table(table) => {
+            let mut substituted = Table::new();
+            for (key, value) in table {
+                let key = match substitute_str(key, vars)? {
+                    Value::String(key) => key,
+                    other => other.to_string(),
+                };
+                substituted.insert(key, substitute(value, vars)?);
+            }
+            Value::Table(substituted)
+        }
+        other => other.clone(),
+    })
+}
+
+/// Substitute placeholders in one string. A string that is a single
+/// placeholder becomes the variable's value, whatever its type.
+fn substitute_str(text: &str, vars: &Table) -> Result<Value> {
+    let lookup = |name: &str| {
+        vars.get(name.trim())
+            .ok_or_else(|| anyhow!("Unknown template variable '{}'", name.trim()))
+    };
+
+    if let Some(name) = text.strip_prefix("{{").and_then(|rest| rest.strip_suffix("}}"))
+        && !name.contains("{{")
+    {
+        return lookup(name).cloned();
+    }
+
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let end = after.find("}}").ok_or_else(|| anyhow!("Unterminated '{{{{' in \"{}\"", text))?;
+        match lookup(&after[..end])? {
+            Value::String(value) => out.push_str(value),
+            Value::Array(_) | Value::Table(_) => {
+                return Err(anyhow!("Template variable '{}' can't be placed inside \"{}\"", after[..end].trim(), text));
+            }
+            value => out.push_str(&value.to_string()),
+        }
+        rest = &after[end + 2..];
+    }
+    out.push_str(rest);
+    Ok(Value::String(out))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::types::Config;
+
+    #[test]
+    fn test_foreach_expands_stage_per_entry() {
+        let mut table: Table = toml::from_str(
+            r#"
+            [inputs."plant_{{building}}"]
+            foreach = [{ building = "north", qos = 1 }, { building = "south", qos = 0 }]
+            type = "mqtt_sub"
+            output = "raw_{{building}}"
+            parameters = { topics = ["{{building}}/#"], qos = "{{qos}}", topic_map = { "{{building}}" = "x" } }
+
+            [outputs."log_{{item}}"]
+            foreach = ["north", "south"]
+            type = "file"
+            inputs = ["raw_{{item}}"]
+            "#,
+        )
+        .unwrap();
+        assert!(expand_templates(&mut table).unwrap());
+        let config: Config = Value::Table(table).try_into().unwrap();
+
+        let south = &config.inputs["plant_south"];
+        assert_eq!(south.output.as_deref(), Some("raw_south"));
+        let parameters = south.parameters.as_ref().unwrap();
+        assert_eq!(parameters["topics"], serde_json::json!(["south/#"]));
+        assert_eq!(parameters["qos"], 0);
+        assert!(parameters["topic_map"].get("south").is_some());
+        assert_eq!(config.inputs.len(), 2);
+        assert_eq!(config.outputs["log_north"].inputs, Some(vec!["raw_north".to_string()]));
+
+        let mut table: Table = toml::from_str(
+            r#"
+            [inputs.plant]
+            foreach = [{ building = "north" }, { building = "south" }]
+            type = "mqtt_sub"
+            "#,
+        )
+        .unwrap();
+        assert!(expand_templates(&mut table).unwrap_err().to_string().contains("defines stage 'plant' more than once"));
+    }
+}
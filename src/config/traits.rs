@@ -28,17 +28,15 @@
 //!     fn from_stage_config(config: &StageConfig) -> anyhow::Result<Self> {
 //!         // Extract and validate processor-specific parameters
 //!         let scale_factor = extract_param(&config.parameters, "scale_factor", 1.0)?;
-//!         let field_in = extract_param(&config.parameters, "field_in", None::<String>)?
-//!             .ok_or_else(|| anyhow::anyhow!("field_in parameter is required"))?;
-//!         let field_out = extract_param(&config.parameters, "field_out", None::<String>)?
-//!             .ok_or_else(|| anyhow::anyhow!("field_out parameter is required"))?;
+//!         let field_in = require_param::<String>(&config.parameters, "field_in")?;
+//!         let field_out = require_param::<String>(&config.parameters, "field_out")?;
 //!         
 //!         Ok(Self { scale_factor, field_in, field_out })
 //!     }
 //!     
 //!     fn validate(&self) -> anyhow::Result<()> {
 //!         if self.scale_factor <= 0.0 {
-//!             return Err(anyhow::anyhow!("scale_factor must be positive"));
+//!             return Err(LiminalError::invalid_parameter("scale_factor", "must be positive").into());
 //!         }
 //!         Ok(())
 //!     }
@@ -63,10 +61,10 @@ use crate::config::types::StageConfig;
 /// # Implementation Guidelines
 /// 
 /// ## Required Parameters
-/// Use `extract_param` with `None` default and `ok_or_else` for required params:
+/// Use `require_param`, which fails with `LiminalError::MissingParameter`
+/// (or `InvalidParameter` for a value of the wrong type):
 /// ```rust
-/// let scale_factor = extract_param(&config.parameters, "scale_factor", None::<f64>)?
-///     .ok_or_else(|| anyhow::anyhow!("scale_factor parameter is required"))?;
+/// let scale_factor = require_param::<f64>(&config.parameters, "scale_factor")?;
 /// ```
 /// 
 /// ## Optional Parameters
@@ -171,10 +169,12 @@ pub trait ProcessorConfig: Sized {
     /// 
     /// # Error Guidelines
     /// 
-    /// Return errors that help users fix their configuration:
-    /// - "Parameter 'scale_factor' is required for scale processor"
-    /// - "Parameter 'threshold' must be positive (got -5.0)"
-    /// - "Parameter 'field_in' cannot be empty"
+    /// Return errors that help users fix their configuration. Problems with a
+    /// single parameter are best raised as a `LiminalError`, which the pipeline
+    /// builder attributes to the stage:
+    /// - `LiminalError::missing_parameter("scale_factor")`
+    /// - `LiminalError::invalid_parameter("threshold", "must be positive (got -5.0)")`
+    /// - `LiminalError::invalid_parameter("field_in", "cannot be empty")`
     /// 
     /// # Implementation Note
     /// 
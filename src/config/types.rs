@@ -3,43 +3,60 @@
 //! Core configuration structures for liminal. These types are deserialised 
 //! from TOML configuration files and used to construct processing pipelines.
 
-use serde::Deserialize;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::time::Duration;
 
 /// Concurrency execution model for stages.
 /// 
-/// Currently all variants execute as single-threaded stages.
-/// Different types are reserved for future concurrency implementations.
+/// `Thread` stages each run as their own task and process input as soon as
+/// it arrives. `Pipeline` stages of a `[pipelines]` section instead share a
+/// pool of workers: a stage with queued input waits for a worker, processes
+/// up to `batch` messages and hands the worker back, so a long chain of
+/// stages is scheduled cooperatively on a few workers rather than competing
+/// for every thread, at some cost in latency.
 
-#[derive(Clone, Debug, Deserialize, Default, PartialEq, Eq)]
+#[derive(Clone, Debug, Deserialize, Serialize, Default, PartialEq, Eq, JsonSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum ConcurrencyType {
-    /// Single dedicated thread per stage (default and current implementation)
+    /// Dedicated task per stage (default)
     #[default]
     Thread,
     
-    /// Pipeline-style concurrent execution (future enhancement)
+    /// Cooperatively scheduled on a worker pool shared by the pipeline's stages
     Pipeline,
     
-    /// User-managed threading (future enhancement)
+    /// User-managed threading (future enhancement; runs as `Thread`)
     Owner,
 }
 
 /// Configuration for stage concurrency behaviour.
 /// 
-/// Currently all concurrency types execute as single-threaded stages.
-/// The configuration is preserved for future compatibility when enhanced
-/// concurrency models are implemented.
-#[derive(Clone, Debug, Deserialize, Default, PartialEq, Eq)]
+/// `workers`, `batch` and `max_in_flight` apply to `pipeline` stages only.
+/// A pipeline's pool has as many workers as the most any of its stages ask
+/// for. A stage skips its turn while its output channel holds
+/// `max_in_flight` messages or more, bounding the messages in flight between
+/// stages without shedding any.
+#[derive(Clone, Debug, Deserialize, Serialize, Default, PartialEq, Eq, JsonSchema)]
 pub struct ConcurrencyConfig {
     /// The concurrency model to use for this stage
     #[serde(rename = "type", default)]
     pub r#type: ConcurrencyType,
+    
+    /// Workers in the pipeline's shared pool (default 1)
+    pub workers: Option<usize>,
+    
+    /// Messages processed per turn on a worker (default 32)
+    pub batch: Option<usize>,
+    
+    /// Messages queued on the output channel above which the stage waits
+    /// (default 256)
+    pub max_in_flight: Option<usize>,
 }
 
 /// Timing configuration for stages
-#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, JsonSchema)]
 pub struct TimingConfig {
     /// Field in payload to use for event time (optional)
     pub event_time_field: Option<String>,
@@ -60,6 +77,11 @@ pub struct TimingConfig {
     /// Enable timing metrics collection
     #[serde(default = "default_metrics_enabled")]
     pub metrics_enabled: bool,
+    
+    /// Advance periodic watermarks with the event times of the data rather
+    /// than the wall clock (set on every stage by back-fill runs)
+    #[serde(default)]
+    pub event_clock: bool,
 }
 
 impl Default for TimingConfig {
@@ -71,6 +93,7 @@ impl Default for TimingConfig {
             processing_timeout_ms: None,
             jitter_bounds_ms: None,
             metrics_enabled: default_metrics_enabled(),
+            event_clock: false,
         }
     }
 }
@@ -84,14 +107,18 @@ impl TimingConfig {
                 .unwrap_or(crate::core::timing::WatermarkStrategy::None),
             max_lateness: Duration::from_millis(self.max_lateness_ms),
             jitter_bounds: self.jitter_bounds_ms.map(Duration::from_millis),
-            clock_source: crate::core::timing::ClockSource::System,
+            clock_source: if self.event_clock {
+                crate::core::timing::ClockSource::Event
+            } else {
+                crate::core::timing::ClockSource::System
+            },
             metrics_enabled: self.metrics_enabled,
         }
     }
 }
 
 /// Watermark generation strategy configuration
-#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, JsonSchema)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum WatermarkStrategy {
     /// Generate watermarks periodically
@@ -147,7 +174,7 @@ const fn default_metrics_enabled() -> bool {
 /// 
 /// Different channel types offer different trade-offs between performance,
 /// reliability, and backpressure handling.
-#[derive(Clone, Debug, Deserialize, Default, PartialEq, Eq)]
+#[derive(Clone, Debug, Deserialize, Serialize, Default, PartialEq, Eq, JsonSchema, clap::ValueEnum)]
 #[serde(rename_all = "lowercase")]
 pub enum ChannelType {
     /// Broadcast channel with no backpressure (default)
@@ -174,21 +201,89 @@ pub enum ChannelType {
     /// Each consumer gets a copy of every message with reliable delivery.
     /// Producer will wait if any consumer falls behind.
     Fanout,
+    
+    /// Durable queue backed by an on-disk segment log
+    /// 
+    /// Messages survive process crashes and are buffered on disk while
+    /// consumers fall behind. Consumers share the queue, as with `Shared`,
+    /// and delivery is at-least-once across restarts. Requires `path`.
+    Persistent,
+    
+    /// Bounded queue delivering higher-priority messages first
+    /// 
+    /// Messages carrying a higher `priority` (in their metadata or in
+    /// `priority_field`) overtake a backlog of lower-priority ones, so alarms
+    /// are not held up behind bulk telemetry. Consumers share the queue, as
+    /// with `Shared`.
+    Priority,
+}
+
+impl ChannelType {
+    /// Overflow behaviour of this channel type when none is configured.
+    pub fn default_overflow(&self) -> OverflowPolicy {
+        match self {
+            ChannelType::Broadcast => OverflowPolicy::DropOldest,
+            _ => OverflowPolicy::Block,
+        }
+    }
+}
+
+/// What a channel does when a message is published while it is full.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OverflowPolicy {
+    /// Wait until there is room (backpressure on the producer)
+    Block,
+    
+    /// Discard the oldest buffered message to make room
+    DropOldest,
+    
+    /// Discard the message being published
+    DropNewest,
+    
+    /// Fail the publish with an overflow error
+    Error,
 }
 
 /// Configuration for inter-stage communication channels.
 /// 
 /// Defines how messages flow between processing stages, including
 /// the communication pattern and buffer capacity.
-#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq, JsonSchema)]
 pub struct ChannelConfig {
     /// The type of channel to create
     #[serde(rename = "type", default)]
     pub r#type: ChannelType,
     
     /// Maximum number of messages the channel can buffer
+    /// (persistent channels are bounded by disk space instead)
     #[serde(default = "default_capacity")]
     pub capacity: usize,
+    
+    /// Directory for persistent channel queues; each channel gets a subdirectory
+    pub path: Option<String>,
+    
+    /// Behaviour when the channel is full (defaults to `drop_oldest` for
+    /// broadcast channels and `block` for the others). Priority channels drop
+    /// the oldest message of the lowest priority.
+    pub overflow: Option<OverflowPolicy>,
+    
+    /// Payload field holding the priority of messages without a `priority`
+    /// metadata entry (priority channels)
+    pub priority_field: Option<String>,
+    
+    /// Most higher-priority messages delivered in a row while lower-priority
+    /// ones wait, before the oldest waiting message is delivered (priority
+    /// channels)
+    #[serde(default = "default_max_burst")]
+    pub max_burst: usize,
+}
+
+impl ChannelConfig {
+    /// The configured overflow policy, or the channel type's default.
+    pub fn overflow_policy(&self) -> OverflowPolicy {
+        self.overflow.unwrap_or_else(|| self.r#type.default_overflow())
+    }
 }
 
 impl Default for ChannelConfig {
@@ -196,15 +291,585 @@ impl Default for ChannelConfig {
         Self {
             r#type: ChannelType::default(),
             capacity: default_capacity(),
+            path: None,
+            overflow: None,
+            priority_field: None,
+            max_burst: default_max_burst(),
         }
     }
 }
 
+const fn default_max_burst() -> usize {
+    16
+}
+
 /// Provides the default capacity for channels.
 const fn default_capacity() -> usize {
     128
 }
 
+/// Storage backend for a stage's keyed state.
+#[derive(Clone, Debug, Deserialize, Serialize, Default, PartialEq, Eq, JsonSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StateBackendConfig {
+    /// Keep state in memory only (default)
+    #[default]
+    Memory,
+    
+    /// Mirror state to an embedded sled database (requires the `sled` feature)
+    Sled {
+        /// Database directory, shared by all stages that name it
+        path: String,
+    },
+}
+
+/// Configuration for the keyed state kept by stateful stages.
+/// 
+/// Bounds how much per-key state a stage may hold and where it is stored.
+/// Without limits, high-cardinality keys grow state without bound.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq, JsonSchema)]
+pub struct StateConfig {
+    /// Evict keys not updated for this long (in milliseconds)
+    pub ttl_ms: Option<u64>,
+    
+    /// Maximum number of keys; the least recently updated are evicted first
+    pub max_keys: Option<usize>,
+    
+    /// Maximum estimated size of the stored entries in bytes; the least
+    /// recently updated are evicted first
+    pub max_bytes: Option<usize>,
+    
+    /// Where state is stored
+    #[serde(default)]
+    pub backend: StateBackendConfig,
+    
+    /// How often changes are written to a persistent backend (in milliseconds)
+    #[serde(default = "default_flush_interval_ms")]
+    pub flush_interval_ms: u64,
+}
+
+impl Default for StateConfig {
+    fn default() -> Self {
+        Self {
+            ttl_ms: None,
+            max_keys: None,
+            max_bytes: None,
+            backend: StateBackendConfig::default(),
+            flush_interval_ms: default_flush_interval_ms(),
+        }
+    }
+}
+
+const fn default_flush_interval_ms() -> u64 {
+    1_000
+}
+
+/// When a failed stage is restarted.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RestartPolicy {
+    /// Leave the stage stopped
+    #[default]
+    Never,
+    
+    /// Restart after `delay_ms`, however often it fails
+    Always,
+    
+    /// Restart after a delay that doubles with each failure, up to
+    /// `max_delay_ms` (and at most `max_retries` times, if set)
+    Backoff,
+    
+    /// Restart after `delay_ms`, at most `max_retries` times
+    MaxRetries,
+}
+
+/// What a stage does with a message received past its processing deadline.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SlaAction {
+    /// Process it anyway (the violation is only counted)
+    #[default]
+    Pass,
+    
+    /// Discard it
+    Drop,
+    
+    /// Send it to `violation_output` instead of processing it
+    Route,
+}
+
+/// Enforcement of message processing deadlines on a stage.
+/// 
+/// Messages carry the deadline set by their source's
+/// `timing.processing_timeout_ms`. A stage with an `sla` table checks each
+/// message it receives against that deadline, or against `max_latency_ms`
+/// from ingestion for messages without one, and counts those past it as
+/// violations before acting on them according to `on_violation`.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq, JsonSchema)]
+pub struct SlaConfig {
+    /// Latency allowed from ingestion, for messages without a deadline (in milliseconds)
+    pub max_latency_ms: Option<u64>,
+    
+    /// What to do with a late message
+    #[serde(default)]
+    pub on_violation: SlaAction,
+    
+    /// Side output receiving late messages under the `route` action
+    pub violation_output: Option<String>,
+}
+
+/// What a stage does with a message older than its TTL.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ExpiryAction {
+    /// Discard it
+    #[default]
+    Drop,
+    
+    /// Send it to `expired_output` (a dead-letter side output) instead
+    Route,
+}
+
+/// Expiry of stale messages on a stage.
+/// 
+/// A message whose event time is more than `ttl_ms` before the time a stage
+/// receives it is counted as expired and, instead of being processed,
+/// dropped or routed according to `on_expiry`. Readings flushed after a long
+/// outage then no longer reach stages that only care about current values.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq, JsonSchema)]
+pub struct TtlConfig {
+    /// Age by event time beyond which messages expire (in milliseconds)
+    pub ttl_ms: u64,
+    
+    /// What to do with an expired message
+    #[serde(default)]
+    pub on_expiry: ExpiryAction,
+    
+    /// Side output receiving expired messages under the `route` action
+    pub expired_output: Option<String>,
+}
+
+/// What a stage does with the oldest messages waiting on its inputs while
+/// more than `max_in_flight` are queued.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum LimitAction {
+    /// Discard them
+    Shed,
+    
+    /// Send them to `shed_output` (a dead-letter side output) instead
+    Route,
+}
+
+/// Resource guards on a stage.
+/// 
+/// `max_in_flight` bounds the messages queued on the stage's inputs: while
+/// more are waiting, the oldest are shed or routed away according to
+/// `on_limit` rather than processed, so a stage that cannot keep up works on
+/// recent data instead of an ever older backlog. `on_limit` must be given
+/// with `max_in_flight`, as either action loses messages for this stage.
+/// A stage whose processing takes longer than `slow_processing_ms` is logged
+/// and flagged as slow in its health until it catches up again.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq, JsonSchema)]
+pub struct LimitsConfig {
+    /// Most messages waiting on the stage's inputs before the oldest are
+    /// shed or routed
+    pub max_in_flight: Option<usize>,
+    
+    /// What to do with messages over `max_in_flight`
+    pub on_limit: Option<LimitAction>,
+    
+    /// Side output receiving messages over `max_in_flight` under the `route`
+    /// action
+    pub shed_output: Option<String>,
+    
+    /// Processing time of a batch of input above which the stage is flagged
+    /// as slow (in milliseconds)
+    pub slow_processing_ms: Option<u64>,
+}
+
+/// Supervision of a stage whose processor panics or keeps failing.
+/// 
+/// A stage fails when its processor panics, or when `process()` returns
+/// `max_errors` errors in a row. A failed stage is restarted according to
+/// `policy`: it is restored from its last checkpoint (if checkpointing is
+/// enabled) and initialised again, keeping its channels.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq, JsonSchema)]
+pub struct RestartConfig {
+    /// When to restart the stage
+    #[serde(default)]
+    pub policy: RestartPolicy,
+    
+    /// Restarts allowed before the stage is left failed
+    pub max_retries: Option<u32>,
+    
+    /// Delay before a restart (the first delay for `backoff`, in milliseconds)
+    #[serde(default = "default_restart_delay_ms")]
+    pub delay_ms: u64,
+    
+    /// Longest delay between `backoff` restarts (in milliseconds)
+    #[serde(default = "default_max_restart_delay_ms")]
+    pub max_delay_ms: u64,
+    
+    /// Consecutive `process()` errors tolerated before the stage fails
+    #[serde(default = "default_max_errors")]
+    pub max_errors: u32,
+    
+    /// Run time after which a restarted stage counts as recovered, so its
+    /// retries and backoff start over (in milliseconds)
+    #[serde(default = "default_restart_stable_ms")]
+    pub stable_ms: u64,
+}
+
+impl Default for RestartConfig {
+    fn default() -> Self {
+        Self {
+            policy: RestartPolicy::default(),
+            max_retries: None,
+            delay_ms: default_restart_delay_ms(),
+            max_delay_ms: default_max_restart_delay_ms(),
+            max_errors: default_max_errors(),
+            stable_ms: default_restart_stable_ms(),
+        }
+    }
+}
+
+const fn default_restart_stable_ms() -> u64 {
+    300_000
+}
+
+const fn default_restart_delay_ms() -> u64 {
+    500
+}
+
+const fn default_max_restart_delay_ms() -> u64 {
+    60_000
+}
+
+const fn default_max_errors() -> u32 {
+    1
+}
+
+/// Configuration for pipeline checkpointing.
+/// 
+/// Stages whose processors support snapshots write their state to
+/// `directory` every `interval_ms`, and on shutdown. On startup, each stage
+/// is restored from its last checkpoint before it is initialised.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq, JsonSchema)]
+pub struct CheckpointConfig {
+    /// Directory holding one checkpoint file per stage
+    pub directory: String,
+    
+    /// How often stage state is checkpointed (in milliseconds)
+    #[serde(default = "default_checkpoint_interval_ms")]
+    pub interval_ms: u64,
+}
+
+const fn default_checkpoint_interval_ms() -> u64 {
+    10_000
+}
+
+/// Configuration for recording channels to a capture file.
+/// 
+/// Every message published to one of `channels` is appended to
+/// `<directory>/capture-<timestamp>.jsonl`, along with the channel and when it
+/// was published. The `replay` input feeds a capture back in.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq, JsonSchema)]
+pub struct RecordConfig {
+    /// Channels whose messages are recorded
+    pub channels: Vec<String>,
+    
+    /// Directory capture files are written to
+    #[serde(default = "default_record_directory")]
+    pub directory: String,
+}
+
+fn default_record_directory() -> String {
+    "./captures".to_string()
+}
+
+/// Configuration for a named runtime.
+/// 
+/// Pipelines naming a runtime run their stages on its own worker threads, so
+/// a busy pipeline cannot starve the others. Pipelines may share a runtime.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq, JsonSchema)]
+pub struct RuntimeConfig {
+    /// Number of worker threads
+    #[serde(default = "default_runtime_worker_threads")]
+    pub worker_threads: usize,
+}
+
+const fn default_runtime_worker_threads() -> usize {
+    2
+}
+
+/// How a condition is checked.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, JsonSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ConditionProbe {
+    /// Holds while the filesystem containing `path` is fuller than `max_usage`
+    DiskUsage {
+        /// A file or directory on the filesystem to check
+        path: String,
+        /// Fraction of the filesystem in use above which the condition holds
+        max_usage: f64,
+    },
+    
+    /// Holds while no TCP connection to `address` can be made (e.g. the
+    /// uplink to a broker or collector is down)
+    TcpUnreachable {
+        /// `host:port` to connect to
+        address: String,
+        /// How long a connection attempt may take (in milliseconds)
+        #[serde(default = "default_condition_timeout_ms")]
+        timeout_ms: u64,
+    },
+}
+
+/// A system condition that pauses stages while it holds.
+/// 
+/// The condition is probed every `interval_ms`. Stages listing it in their
+/// `pause_when` are paused when it starts to hold and resumed once none of
+/// their conditions hold, while their input queues up on their channels.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, JsonSchema)]
+pub struct ConditionConfig {
+    #[serde(flatten)]
+    pub probe: ConditionProbe,
+    
+    /// How often the condition is probed (in milliseconds)
+    #[serde(default = "default_condition_interval_ms")]
+    pub interval_ms: u64,
+}
+
+const fn default_condition_timeout_ms() -> u64 {
+    2_000
+}
+
+const fn default_condition_interval_ms() -> u64 {
+    5_000
+}
+
+/// Configuration for the periodic channel depth report.
+/// 
+/// Every `interval_ms`, the depth of each non-empty channel is logged, with a
+/// warning for bounded channels filled to at least `warn_fill` of their
+/// capacity, as their overflow policy is about to apply.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, JsonSchema)]
+pub struct ChannelReportConfig {
+    /// How often channel depths are reported (in milliseconds)
+    #[serde(default = "default_channel_report_interval_ms")]
+    pub interval_ms: u64,
+    
+    /// Fraction of its capacity a channel is filled to before a warning is logged
+    #[serde(default = "default_channel_report_warn_fill")]
+    pub warn_fill: f64,
+}
+
+impl Default for ChannelReportConfig {
+    fn default() -> Self {
+        Self {
+            interval_ms: default_channel_report_interval_ms(),
+            warn_fill: default_channel_report_warn_fill(),
+        }
+    }
+}
+
+const fn default_channel_report_interval_ms() -> u64 {
+    10_000
+}
+
+const fn default_channel_report_warn_fill() -> f64 {
+    0.8
+}
+
+/// Configuration for the periodic memory report.
+/// 
+/// Every `interval_ms`, the resident size of the process, the heap in use
+/// (when built with the `tracking-allocator` feature) and the size of every
+/// keyed state store are logged and exported as metrics.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq, JsonSchema)]
+pub struct MemoryReportConfig {
+    /// How often memory use is reported (in milliseconds)
+    #[serde(default = "default_memory_report_interval_ms")]
+    pub interval_ms: u64,
+}
+
+impl Default for MemoryReportConfig {
+    fn default() -> Self {
+        Self {
+            interval_ms: default_memory_report_interval_ms(),
+        }
+    }
+}
+
+const fn default_memory_report_interval_ms() -> u64 {
+    60_000
+}
+
+/// Configuration for graceful shutdown.
+/// 
+/// On shutdown, sources stop first and every downstream stage drains its
+/// inputs and flushes before stopping. Stages still running once
+/// `drain_timeout_ms` has passed are aborted.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq, JsonSchema)]
+pub struct ShutdownConfig {
+    /// How long to wait for stages to drain before aborting them (in milliseconds)
+    #[serde(default = "default_drain_timeout_ms")]
+    pub drain_timeout_ms: u64,
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> Self {
+        Self {
+            drain_timeout_ms: default_drain_timeout_ms(),
+        }
+    }
+}
+
+const fn default_drain_timeout_ms() -> u64 {
+    5_000
+}
+
+/// Configuration for the runtime control API.
+/// 
+/// When present, Liminal listens on the Unix socket at `socket` for commands
+/// (one JSON object per line) to pause, resume and stop stages or pipelines,
+/// inject messages into a channel and query stage status.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq, JsonSchema)]
+pub struct ControlConfig {
+    /// Path of the Unix socket to listen on
+    pub socket: String,
+}
+
+/// Configuration for active/standby operation of several instances.
+/// 
+/// Instances running the same configuration share a lease file. Only the
+/// instance holding the lease runs its stages; the others stand by, and take
+/// over once the lease has not been renewed for `lease_ms`. Each takeover
+/// increases the lease's fencing token, and an instance that finds a newer
+/// token than its own stops immediately, without flushing or checkpointing.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq, JsonSchema)]
+pub struct HaConfig {
+    /// Lease file, on storage shared by the instances
+    pub lease_file: String,
+    
+    /// How long the lease lasts without renewal (in milliseconds)
+    #[serde(default = "default_lease_ms")]
+    pub lease_ms: u64,
+    
+    /// Name of this instance in the lease (host name and process id by default)
+    pub instance: Option<String>,
+}
+
+const fn default_lease_ms() -> u64 {
+    10_000
+}
+
+/// Configuration for the admin HTTP server.
+/// 
+/// When present, Liminal serves `/healthz`, `/readyz`, `/pipelines` and
+/// `/stages/{name}/stats` on `host:port`, for liveness and readiness probes
+/// and for inspecting a running deployment.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq, JsonSchema)]
+pub struct AdminConfig {
+    /// Address to listen on
+    #[serde(default = "default_admin_host")]
+    pub host: String,
+    
+    /// Port to listen on
+    #[serde(default = "default_admin_port")]
+    pub port: u16,
+}
+
+fn default_admin_host() -> String {
+    "0.0.0.0".to_string()
+}
+
+const fn default_admin_port() -> u16 {
+    9090
+}
+
+/// Security of the HTTP endpoints Liminal listens on (the admin server).
+/// 
+/// With `tls`, endpoints are served over HTTPS, optionally verifying client
+/// certificates. With `tokens`, every request must carry one of them as an
+/// `Authorization: Bearer` header, unless its client presented a verified
+/// certificate.
+#[derive(Clone, Debug, Deserialize, Serialize, Default, PartialEq, Eq, JsonSchema)]
+pub struct SecurityConfig {
+    /// HTTPS settings (plain HTTP when absent)
+    #[serde(default)]
+    pub tls: Option<ServerTlsConfig>,
+    
+    /// Bearer tokens accepted from clients (no token required when empty)
+    #[serde(default)]
+    pub tokens: Vec<String>,
+}
+
+/// TLS settings of a server.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq, JsonSchema)]
+pub struct ServerTlsConfig {
+    /// Certificate chain (PEM) presented to clients
+    pub cert_file: String,
+    
+    /// Private key (PEM) of `cert_file`
+    pub key_file: String,
+    
+    /// CA certificates (PEM) client certificates are verified against
+    #[serde(default)]
+    pub client_ca_file: Option<String>,
+    
+    /// Whether clients must present a certificate
+    #[serde(default)]
+    pub client_auth: ClientAuth,
+}
+
+/// Client certificate authentication of a TLS server.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Default, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ClientAuth {
+    /// Client certificates are not requested
+    #[default]
+    None,
+    /// Client certificates are verified if presented
+    Optional,
+    /// Clients without a verified certificate are turned away
+    Required,
+}
+
+/// Named credentials, defined once and referenced by stages with a
+/// `credentials = "<name>"` parameter.
+/// 
+/// Stages use whichever fields their processor understands: MQTT stages the
+/// username and password, TCP stages the certificate and key for TLS, Avro
+/// stages the username and password or token for the schema registry,
+/// `crypto` stages the keys.
+/// Parameters set on a stage take precedence over its credentials.
+#[derive(Clone, Debug, Deserialize, Serialize, Default, PartialEq, Eq, JsonSchema)]
+pub struct CredentialsConfig {
+    #[serde(default)]
+    pub username: Option<String>,
+    
+    #[serde(default)]
+    pub password: Option<String>,
+    
+    /// Bearer token
+    #[serde(default)]
+    pub token: Option<String>,
+    
+    /// Client certificate chain (PEM)
+    #[serde(default)]
+    pub cert_file: Option<String>,
+    
+    /// Private key (PEM) of `cert_file`, or a signing or encryption key
+    #[serde(default)]
+    pub key_file: Option<String>,
+    
+    /// Public key (PEM) signatures are verified with
+    #[serde(default)]
+    pub public_key_file: Option<String>,
+}
+
 /// Root configuration for the entire liminal system.
 /// 
 /// Contains all configuration needed to set up data processing pipelines,
@@ -213,6 +878,8 @@ const fn default_capacity() -> usize {
 /// # Example Structure
 /// 
 /// ```toml
+/// plugins_dir = "./plugins"
+/// 
 /// [inputs.sensor_data]
 /// type = "simulated"
 /// output = "raw_data"
@@ -230,8 +897,50 @@ const fn default_capacity() -> usize {
 /// [outputs.console]
 /// type = "log"
 /// inputs = ["filtered_data"]
+/// 
+/// [checkpoint]
+/// directory = "./checkpoints"
+/// interval_ms = 10000
+/// 
+/// [record]
+/// channels = ["raw_data"]
+/// directory = "./captures"
+/// 
+/// [channel_report]
+/// interval_ms = 10000
+/// warn_fill = 0.8
+/// 
+/// [memory_report]
+/// interval_ms = 60000
+/// 
+/// [runtimes.analytics]
+/// worker_threads = 4
+/// 
+/// [conditions.disk_full]
+/// type = "disk_usage"
+/// path = "./captures"
+/// max_usage = 0.9
+/// 
+/// [credentials.plant_broker]
+/// username = "edge"
+/// password = "${file:/run/secrets/mqtt_password}"
+/// 
+/// [shutdown]
+/// drain_timeout_ms = 5000
+/// 
+/// [control]
+/// socket = "/tmp/liminal.sock"
+/// 
+/// [admin]
+/// port = 9090
+/// 
+/// [ha]
+/// lease_file = "/shared/liminal.lease"
+/// 
+/// [security]
+/// tokens = ["${ADMIN_TOKEN}"]
 /// ```
-#[derive(Clone, Debug, Deserialize, Default)]
+#[derive(Clone, Debug, Deserialize, Serialize, Default, JsonSchema)]
 pub struct Config {
     /// Input stage configurations - data sources that generate messages
     #[serde(default)]
@@ -244,6 +953,98 @@ pub struct Config {
     /// Output stage configurations - data sinks that consume messages
     #[serde(default)]
     pub outputs: HashMap<String, StageConfig>,
+    
+    /// Periodic checkpointing of stage state (disabled when absent)
+    #[serde(default)]
+    pub checkpoint: Option<CheckpointConfig>,
+    
+    /// Recording of channels to a capture file (disabled when absent)
+    #[serde(default)]
+    pub record: Option<RecordConfig>,
+    
+    /// Periodic logging of channel depths (disabled when absent)
+    #[serde(default)]
+    pub channel_report: Option<ChannelReportConfig>,
+    
+    /// Periodic logging of memory use (disabled when absent)
+    #[serde(default)]
+    pub memory_report: Option<MemoryReportConfig>,
+    
+    /// Dedicated runtimes that pipelines can run on, by name
+    #[serde(default)]
+    pub runtimes: HashMap<String, RuntimeConfig>,
+    
+    /// System conditions that stages can be paused on, by name
+    #[serde(default)]
+    pub conditions: HashMap<String, ConditionConfig>,
+    
+    /// Credentials shared by stages, by name
+    #[serde(default)]
+    pub credentials: HashMap<String, CredentialsConfig>,
+    
+    /// Graceful shutdown settings
+    #[serde(default)]
+    pub shutdown: ShutdownConfig,
+    
+    /// Runtime control API (disabled when absent)
+    #[serde(default)]
+    pub control: Option<ControlConfig>,
+    
+    /// Admin HTTP server (disabled when absent)
+    #[serde(default)]
+    pub admin: Option<AdminConfig>,
+    
+    /// Active/standby coordination with other instances (disabled when absent)
+    #[serde(default)]
+    pub ha: Option<HaConfig>,
+    
+    /// Security of the HTTP endpoints (unsecured when absent)
+    #[serde(default)]
+    pub security: Option<SecurityConfig>,
+    
+    /// Reject unknown processor parameters and parameters of the wrong type.
+    /// When off, they are only logged as warnings.
+    #[serde(default = "default_strict")]
+    pub strict: bool,
+
+    /// Directory of processor plugins loaded at startup (requires the
+    /// `plugins` feature)
+    #[serde(default)]
+    pub plugins_dir: Option<String>,
+}
+
+const fn default_strict() -> bool {
+    true
+}
+
+impl Config {
+    /// Remove the stages turned off with `enabled = false`, and the pipelines
+    /// turned off along with all their stages, returning the removed stages
+    /// by name.
+    pub fn remove_disabled(&mut self) -> Vec<(String, StageConfig)> {
+        let mut removed = Vec::new();
+        let mut take = |stages: &mut HashMap<String, StageConfig>, all: bool| {
+            let names: Vec<String> = stages
+                .iter()
+                .filter(|(_, stage)| all || !stage.is_enabled())
+                .map(|(name, _)| name.clone())
+                .collect();
+            for name in names {
+                if let Some(stage) = stages.remove(&name) {
+                    removed.push((name, stage));
+                }
+            }
+        };
+        take(&mut self.inputs, false);
+        take(&mut self.outputs, false);
+        for pipeline in self.pipelines.values_mut() {
+            let all = !pipeline.is_enabled();
+            take(&mut pipeline.stages, all);
+        }
+        self.pipelines.retain(|_, pipeline| pipeline.is_enabled());
+        removed.sort_by(|a, b| a.0.cmp(&b.0));
+        removed
+    }
 }
 
 /// Configuration for an individual processing stage.
@@ -257,7 +1058,7 @@ pub struct Config {
 /// - **Input stages**: Generate data, have `output` but no `inputs`
 /// - **Transform stages**: Process data, have both `inputs` and `output`
 /// - **Output stages**: Consume data, have `inputs` but no `output`
-#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, JsonSchema)]
 pub struct StageConfig {
     /// The processor type to instantiate (e.g., "simulated", "scale", "log")
     #[serde(rename = "type")]
@@ -269,7 +1070,11 @@ pub struct StageConfig {
     /// Output data stream name this stage produces to
     pub output: Option<String>,
     
-    /// Concurrency configuration (currently unused, reserved for future)
+    /// Additional named data streams this stage may emit to (e.g. alerts).
+    /// Processors decide what, if anything, is sent to each side output.
+    pub side_outputs: Option<Vec<String>>,
+    
+    /// Concurrency model: a task of its own, or a share of the pipeline's workers
     pub concurrency: Option<ConcurrencyConfig>,
     
     /// Channel configuration for this stage's output communication
@@ -278,19 +1083,90 @@ pub struct StageConfig {
     /// Timing configuration for this stage
     pub timing: Option<TimingConfig>,
     
+    /// Keyed state limits and storage for stateful processors
+    pub state: Option<StateConfig>,
+    
+    /// Number of copies of this stage to run (pipeline stages only). Replicas
+    /// share the stage's inputs, each message going to exactly one replica.
+    pub replicas: Option<usize>,
+    
+    /// Payload field whose value picks the replica for each message, so that
+    /// messages with the same key are processed in order by one replica
+    pub partition_by: Option<String>,
+    
+    /// Restart policy for when the stage fails (never restarted when absent)
+    pub restart: Option<RestartConfig>,
+    
+    /// Processing deadline enforcement (deadlines are ignored when absent)
+    pub sla: Option<SlaConfig>,
+    
+    /// Expiry of messages older than a TTL (messages never expire when absent)
+    pub ttl: Option<TtlConfig>,
+    
+    /// In-flight message limit and slow processing watchdog
+    pub limits: Option<LimitsConfig>,
+    
+    /// Conditions (from `[conditions]`) that pause the stage while any holds
+    pub pause_when: Option<Vec<String>>,
+    
     /// Processor-specific configuration parameters
     pub parameters: Option<HashMap<String, serde_json::Value>>,
+    
+    /// `false` turns the stage off while keeping its configuration (enabled
+    /// when absent)
+    pub enabled: Option<bool>,
+}
+
+impl StageConfig {
+    /// Whether the stage runs, i.e. is not turned off with `enabled = false`.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled != Some(false)
+    }
+
+    /// Name of the credentials (from `[credentials]`) the stage references.
+    pub fn credentials(&self) -> Option<&str> {
+        self.parameters.as_ref()?.get("credentials")?.as_str()
+    }
+
+    /// The stage with its `credentials` parameter replaced by the credentials
+    /// it names, as processors read them.
+    pub fn with_credentials(&self, credentials: &HashMap<String, CredentialsConfig>) -> StageConfig {
+        let mut stage = self.clone();
+        if let Some(resolved) = self.credentials().and_then(|name| credentials.get(name))
+            && let Some(parameters) = stage.parameters.as_mut()
+            && let Ok(resolved) = serde_json::to_value(resolved)
+        {
+            parameters.insert("credentials".to_string(), resolved);
+        }
+        stage
+    }
 }
 
 /// Configuration for a multi-stage processing pipeline.
 /// 
 /// Pipelines contain multiple stages that process data in sequence or parallel,
 /// depending on their input/output data stream connections.
-#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, JsonSchema)]
 pub struct PipelineConfig {
     /// Human-readable description of the pipeline's purpose
     pub description: String,
     
     /// Map of stage name to stage configuration
     pub stages: HashMap<String, StageConfig>,
+    
+    /// Named runtime (from `[runtimes]`) the pipeline's stages run on, instead
+    /// of the runtime shared with sources, sinks and other pipelines
+    #[serde(default)]
+    pub runtime: Option<String>,
+    
+    /// `false` turns every stage of the pipeline off (enabled when absent)
+    #[serde(default)]
+    pub enabled: Option<bool>,
+}
+
+impl PipelineConfig {
+    /// Whether the pipeline runs, i.e. is not turned off with `enabled = false`.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled != Some(false)
+    }
 }
\ No newline at end of file
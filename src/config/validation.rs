@@ -19,8 +19,16 @@
 //! ## Output Stages
 //! - Must have at least one input data stream
 //! - Must not have an output data stream
+//! - May have side outputs, receiving messages they could not deliver
 //! - Field configuration is processor-specific
 //! 
+//! ## Parameters
+//! - Must be known to the stage's processor, as described by its metadata
+//! - Must have the type the processor expects
+//! 
+//! Parameter checks need the processor registry, so they are run separately
+//! through `parameter_errors`.
+//! 
 //! # Example Usage
 //! 
 //! ```rust
@@ -32,9 +40,11 @@
 //! ```
 
 use crate::config::types::*;
-use crate::config::params::extract_field_params;
+use crate::config::params::{ProcessorMetadata, extract_field_params};
 use crate::config::field::FieldConfig;
 
+use std::collections::{BTreeSet, HashMap};
+
 /// Validates the entire Liminal configuration for structural correctness.
 /// 
 /// This function performs comprehensive validation of all configuration sections,
@@ -73,6 +83,7 @@ use crate::config::field::FieldConfig;
 ///     inputs: HashMap::from([("sensor".to_string(), StageConfig { /* ... */ })]),
 ///     pipelines: HashMap::new(),
 ///     outputs: HashMap::from([("console".to_string(), StageConfig { /* ... */ })]),
+///     checkpoint: None,
 /// };
 /// 
 /// match validate_config(&config) {
@@ -81,21 +92,435 @@ use crate::config::field::FieldConfig;
 /// }
 /// ```
 pub fn validate_config(config: &Config) -> anyhow::Result<()> {
+    match validation_errors(config).into_iter().next() {
+        Some((_, error)) => Err(error),
+        None => Ok(()),
+    }
+}
+
+/// Collects every structural problem with a configuration, rather than
+/// stopping at the first.
+/// 
+/// Each error is paired with the TOML table it was found in (e.g.
+/// `pipelines.main.stages.scale`), and errors are listed in the order
+/// `validate_config` checks them, with stages sorted by name.
+pub fn validation_errors(config: &Config) -> Vec<(String, anyhow::Error)> {
+    let mut errors = Vec::new();
+
     // Validate all input stages - these generate data into the system
-    for (name, stage_config) in &config.inputs {
-        validate_input_stage(name, stage_config)?;
+    for (name, stage_config) in sorted(&config.inputs) {
+        if let Err(e) = validate_input_stage(name, stage_config) {
+            errors.push((format!("inputs.{}", name), e));
+        }
     }
 
-     // Validate all pipeline configurations - these transform data
-    for (name, pipeline_config) in &config.pipelines {
-        validate_pipeline(name, pipeline_config)?;
+    // Validate all pipeline configurations - these transform data
+    let mut pipelines: Vec<_> = config.pipelines.iter().collect();
+    pipelines.sort_by(|a, b| a.0.cmp(b.0));
+    for (name, pipeline_config) in &pipelines {
+        errors.extend(validate_pipeline(name, pipeline_config));
     }
     
     // Validate all output stages - these consume data from the system
-    for (name, stage_config) in &config.outputs {
-        validate_output_stage(name, stage_config)?;
+    for (name, stage_config) in sorted(&config.outputs) {
+        if let Err(e) = validate_output_stage(name, stage_config) {
+            errors.push((format!("outputs.{}", name), e));
+        }
+    }
+
+    // Validate channel settings of every stage
+    for (table, name, stage_config) in stage_tables(config) {
+        if let Err(e) = validate_channel(name, stage_config) {
+            errors.push((table.clone(), e));
+        }
+        if let Err(e) = validate_restart(name, stage_config) {
+            errors.push((table.clone(), e));
+        }
+        if let Err(e) = validate_sla(name, stage_config) {
+            errors.push((table.clone(), e));
+        }
+        if let Err(e) = validate_ttl(name, stage_config) {
+            errors.push((table.clone(), e));
+        }
+        if let Err(e) = validate_limits(name, stage_config) {
+            errors.push((table.clone(), e));
+        }
+        if let Err(e) = validate_concurrency(name, stage_config) {
+            errors.push((table, e));
+        }
+    }
+
+    errors.extend(validate_shared_channels(config));
+
+    if let Some(checkpoint) = &config.checkpoint {
+        if checkpoint.directory.is_empty() {
+            errors.push(("checkpoint".to_string(), anyhow::anyhow!("checkpoint.directory cannot be empty")));
+        }
+        if checkpoint.interval_ms == 0 {
+            errors.push(("checkpoint".to_string(), anyhow::anyhow!("checkpoint.interval_ms must be greater than 0")));
+        }
+    }
+
+    for (name, runtime) in &config.runtimes {
+        if runtime.worker_threads == 0 {
+            errors.push((format!("runtimes.{}", name), anyhow::anyhow!("Runtime '{}' has no worker threads", name)));
+        }
+    }
+    for (name, pipeline) in &config.pipelines {
+        if let Some(runtime) = &pipeline.runtime
+            && !config.runtimes.contains_key(runtime)
+        {
+            errors.push((
+                format!("pipelines.{}", name),
+                anyhow::anyhow!("Pipeline '{}' runs on runtime '{}', which is not defined in [runtimes]", name, runtime),
+            ));
+        }
+    }
+
+    for (name, condition) in &config.conditions {
+        if let Err(e) = validate_condition(condition) {
+            errors.push((format!("conditions.{}", name), e));
+        }
+    }
+    for (table, name, stage_config) in stage_tables(config) {
+        if let Some(credentials) = stage_config.credentials()
+            && !config.credentials.contains_key(credentials)
+        {
+            errors.push((
+                table.clone(),
+                anyhow::anyhow!("Stage '{}' uses credentials '{}', which are not defined in [credentials]", name, credentials),
+            ));
+        }
+        for condition in stage_config.pause_when.iter().flatten() {
+            if !config.conditions.contains_key(condition) {
+                errors.push((
+                    table.clone(),
+                    anyhow::anyhow!("Stage '{}' pauses on condition '{}', which is not defined in [conditions]", name, condition),
+                ));
+            }
+        }
+    }
+
+    if let Some(report) = &config.channel_report {
+        if report.interval_ms == 0 {
+            errors.push(("channel_report".to_string(), anyhow::anyhow!("channel_report.interval_ms must be greater than 0")));
+        }
+        if !(report.warn_fill > 0.0 && report.warn_fill <= 1.0) {
+            errors.push(("channel_report".to_string(), anyhow::anyhow!("channel_report.warn_fill must be in (0, 1]")));
+        }
+    }
+
+    if let Some(report) = &config.memory_report
+        && report.interval_ms == 0
+    {
+        errors.push(("memory_report".to_string(), anyhow::anyhow!("memory_report.interval_ms must be greater than 0")));
+    }
+
+    if let Some(ha) = &config.ha {
+        if ha.lease_file.is_empty() {
+            errors.push(("ha".to_string(), anyhow::anyhow!("ha.lease_file cannot be empty")));
+        }
+        if ha.lease_ms == 0 {
+            errors.push(("ha".to_string(), anyhow::anyhow!("ha.lease_ms must be greater than 0")));
+        }
     }
 
+    if let Some(security) = &config.security {
+        if let Some(tls) = &security.tls {
+            if tls.cert_file.is_empty() || tls.key_file.is_empty() {
+                errors.push(("security".to_string(), anyhow::anyhow!("security.tls.cert_file and key_file cannot be empty")));
+            }
+            if tls.client_auth != ClientAuth::None && tls.client_ca_file.is_none() {
+                errors.push(("security".to_string(), anyhow::anyhow!("security.tls.client_auth needs a client_ca_file")));
+            }
+        }
+        if security.tokens.iter().any(|token| token.is_empty()) {
+            errors.push(("security".to_string(), anyhow::anyhow!("security.tokens cannot contain empty tokens")));
+        }
+    }
+
+    if let Some(control) = &config.control
+        && control.socket.is_empty()
+    {
+        errors.push(("control".to_string(), anyhow::anyhow!("control.socket cannot be empty")));
+    }
+
+    if let Some(admin) = &config.admin
+        && admin.host.is_empty()
+    {
+        errors.push(("admin".to_string(), anyhow::anyhow!("admin.host cannot be empty")));
+    }
+
+    errors
+}
+
+/// Collects unknown and mistyped parameters of every stage whose processor
+/// has metadata, looked up through `metadata`.
+/// 
+/// Each error names the stage and parameter, and is paired with the TOML table
+/// of the parameter (e.g. `pipelines.main.stages.scale.parameters.factor`).
+pub fn parameter_errors(
+    config: &Config,
+    metadata: impl Fn(&str) -> Option<&'static ProcessorMetadata>,
+) -> Vec<(String, anyhow::Error)> {
+    let mut errors = Vec::new();
+    for (table, name, stage_config) in stage_tables(config) {
+        let Some(metadata) = metadata(&stage_config.r#type) else {
+            continue;
+        };
+        for (key, error) in metadata.check_parameters(name, &stage_config.parameters) {
+            errors.push((format!("{}.parameters.{}", table, key), error));
+        }
+    }
+    errors
+}
+
+/// Every stage with the TOML table defining it: inputs, then pipeline stages,
+/// then outputs, each sorted by name.
+fn stage_tables(config: &Config) -> Vec<(String, &String, &StageConfig)> {
+    let mut pipelines: Vec<_> = config.pipelines.iter().collect();
+    pipelines.sort_by(|a, b| a.0.cmp(b.0));
+
+    sorted(&config.inputs)
+        .into_iter()
+        .map(|(name, stage_config)| (format!("inputs.{}", name), name, stage_config))
+        .chain(pipelines.into_iter().flat_map(|(pipeline_name, pipeline_config)| {
+            sorted(&pipeline_config.stages)
+                .into_iter()
+                .map(move |(name, stage_config)| (format!("pipelines.{}.stages.{}", pipeline_name, name), name, stage_config))
+        }))
+        .chain(
+            sorted(&config.outputs)
+                .into_iter()
+                .map(|(name, stage_config)| (format!("outputs.{}", name), name, stage_config)),
+        )
+        .collect()
+}
+
+/// Stages of one section, sorted by name.
+fn sorted(stages: &HashMap<String, StageConfig>) -> Vec<(&String, &StageConfig)> {
+    let mut stages: Vec<_> = stages.iter().collect();
+    stages.sort_by(|a, b| a.0.cmp(b.0));
+    stages
+}
+
+/// Validates the channels published to by more than one stage.
+/// 
+/// A shared channel merges the messages of its producers and is created with
+/// the settings of the first of them that sets any, so every producer with
+/// a `channel` table must give the same settings.
+fn validate_shared_channels(config: &Config) -> Vec<(String, anyhow::Error)> {
+    let mut errors = Vec::new();
+    let mut settings: HashMap<&String, (&String, &ChannelConfig)> = HashMap::new();
+    for (table, name, stage_config) in stage_tables(config) {
+        let Some(channel_config) = &stage_config.channel else {
+            continue;
+        };
+        let outputs: BTreeSet<&String> = stage_config
+            .output
+            .iter()
+            .chain(stage_config.side_outputs.iter().flatten())
+            .collect();
+        for output in outputs {
+            match settings.get(output) {
+                Some((producer, first)) if *first != channel_config => errors.push((
+                    table.clone(),
+                    anyhow::anyhow!(
+                        "Stages '{}' and '{}' both publish to channel '{}' with different channel settings",
+                        producer,
+                        name,
+                        output
+                    ),
+                )),
+                Some(_) => {}
+                None => {
+                    settings.insert(output, (name, channel_config));
+                }
+            }
+        }
+    }
+    errors
+}
+
+/// Validates the output channel settings of a stage.
+/// 
+/// Channels must buffer at least one message. Persistent channels keep their queues on disk and must name a directory;
+/// they never overflow, so they take no overflow policy.
+fn validate_channel(name: &str, config: &StageConfig) -> anyhow::Result<()> {
+    if let Some(channel) = &config.channel
+        && channel.capacity == 0
+    {
+        return Err(anyhow::anyhow!("Stage '{}' has a channel capacity of 0", name));
+    }
+    if let Some(channel) = &config.channel
+        && channel.r#type == ChannelType::Persistent
+        && channel.path.is_none()
+    {
+        return Err(anyhow::anyhow!("Stage '{}' uses a persistent channel but has no channel path", name));
+    }
+    if let Some(channel) = &config.channel
+        && channel.r#type == ChannelType::Persistent
+        && channel.overflow.is_some()
+    {
+        return Err(anyhow::anyhow!("Stage '{}' sets an overflow policy on a persistent channel, which buffers on disk", name));
+    }
+    if let Some(channel) = &config.channel
+        && channel.r#type == ChannelType::Priority
+        && channel.max_burst == 0
+    {
+        return Err(anyhow::anyhow!("Stage '{}' has a priority channel with a max_burst of 0", name));
+    }
+    Ok(())
+}
+
+/// Validates the restart policy of a stage.
+/// 
+/// A stage must be allowed at least one error before failing, `max_retries` needs a retry limit, and backoff delays
+/// must not start above their cap.
+fn validate_restart(name: &str, config: &StageConfig) -> anyhow::Result<()> {
+    let Some(restart) = &config.restart else {
+        return Ok(());
+    };
+    if restart.max_errors == 0 {
+        return Err(anyhow::anyhow!("Stage '{}' has restart.max_errors of 0", name));
+    }
+    if restart.stable_ms == 0 {
+        return Err(anyhow::anyhow!("Stage '{}' has restart.stable_ms of 0", name));
+    }
+    if restart.policy == RestartPolicy::MaxRetries && restart.max_retries.is_none() {
+        return Err(anyhow::anyhow!("Stage '{}' uses the max_retries restart policy but sets no restart.max_retries", name));
+    }
+    if restart.delay_ms > restart.max_delay_ms {
+        return Err(anyhow::anyhow!("Stage '{}' has restart.delay_ms greater than restart.max_delay_ms", name));
+    }
+    Ok(())
+}
+
+/// Validates the SLA settings of a stage.
+/// 
+/// The `route` action needs a `violation_output` that is one of the stage's side outputs.
+fn validate_sla(name: &str, config: &StageConfig) -> anyhow::Result<()> {
+    let Some(sla) = &config.sla else {
+        return Ok(());
+    };
+    if sla.on_violation == SlaAction::Route {
+        let Some(output) = &sla.violation_output else {
+            return Err(anyhow::anyhow!("Stage '{}' routes SLA violations but sets no sla.violation_output", name));
+        };
+        if !config.side_outputs.as_ref().is_some_and(|outputs| outputs.contains(output)) {
+            return Err(anyhow::anyhow!(
+                "Stage '{}' has sla.violation_output '{}', which is not one of its side_outputs",
+                name,
+                output
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Validates the message expiry settings of a stage.
+/// 
+/// The `route` action needs an `expired_output` that is one of the stage's side outputs.
+fn validate_ttl(name: &str, config: &StageConfig) -> anyhow::Result<()> {
+    let Some(ttl) = &config.ttl else {
+        return Ok(());
+    };
+    if ttl.ttl_ms == 0 {
+        return Err(anyhow::anyhow!("Stage '{}' has ttl.ttl_ms, which must be greater than 0", name));
+    }
+    if ttl.on_expiry == ExpiryAction::Route {
+        let Some(output) = &ttl.expired_output else {
+            return Err(anyhow::anyhow!("Stage '{}' routes expired messages but sets no ttl.expired_output", name));
+        };
+        if !config.side_outputs.as_ref().is_some_and(|outputs| outputs.contains(output)) {
+            return Err(anyhow::anyhow!(
+                "Stage '{}' has ttl.expired_output '{}', which is not one of its side_outputs",
+                name,
+                output
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Validates the resource limits of a stage.
+fn validate_limits(name: &str, config: &StageConfig) -> anyhow::Result<()> {
+    let Some(limits) = &config.limits else {
+        return Ok(());
+    };
+    if limits.max_in_flight == Some(0) {
+        return Err(anyhow::anyhow!("Stage '{}' has limits.max_in_flight, which must be greater than 0", name));
+    }
+    match (limits.max_in_flight, limits.on_limit) {
+        (Some(_), None) => {
+            return Err(anyhow::anyhow!(
+                "Stage '{}' has limits.max_in_flight but no limits.on_limit (\"shed\" or \"route\")",
+                name
+            ));
+        }
+        (None, Some(_)) => {
+            return Err(anyhow::anyhow!("Stage '{}' has limits.on_limit but no limits.max_in_flight", name));
+        }
+        _ => {}
+    }
+    if limits.on_limit == Some(LimitAction::Route) {
+        let Some(output) = &limits.shed_output else {
+            return Err(anyhow::anyhow!("Stage '{}' routes messages over its limit but sets no limits.shed_output", name));
+        };
+        if !config.side_outputs.as_ref().is_some_and(|outputs| outputs.contains(output)) {
+            return Err(anyhow::anyhow!(
+                "Stage '{}' has limits.shed_output '{}', which is not one of its side_outputs",
+                name,
+                output
+            ));
+        }
+    }
+    if limits.slow_processing_ms == Some(0) {
+        return Err(anyhow::anyhow!("Stage '{}' has limits.slow_processing_ms, which must be greater than 0", name));
+    }
+    Ok(())
+}
+
+/// Validates the concurrency settings of a stage.
+fn validate_concurrency(name: &str, config: &StageConfig) -> anyhow::Result<()> {
+    let Some(concurrency) = &config.concurrency else {
+        return Ok(());
+    };
+    for (setting, value) in [
+        ("workers", concurrency.workers),
+        ("batch", concurrency.batch),
+        ("max_in_flight", concurrency.max_in_flight),
+    ] {
+        if value == Some(0) {
+            return Err(anyhow::anyhow!("Stage '{}' has concurrency.{}, which must be greater than 0", name, setting));
+        }
+    }
+    Ok(())
+}
+
+/// Validates the probe settings of a condition.
+fn validate_condition(condition: &ConditionConfig) -> anyhow::Result<()> {
+    if condition.interval_ms == 0 {
+        return Err(anyhow::anyhow!("interval_ms must be greater than 0"));
+    }
+    match &condition.probe {
+        ConditionProbe::DiskUsage { path, max_usage } => {
+            if path.is_empty() {
+                return Err(anyhow::anyhow!("path cannot be empty"));
+            }
+            if !(*max_usage > 0.0 && *max_usage <= 1.0) {
+                return Err(anyhow::anyhow!("max_usage must be in (0, 1]"));
+            }
+        }
+        ConditionProbe::TcpUnreachable { address, timeout_ms } => {
+            if address.is_empty() {
+                return Err(anyhow::anyhow!("address cannot be empty"));
+            }
+            if *timeout_ms == 0 {
+                return Err(anyhow::anyhow!("timeout_ms must be greater than 0"));
+            }
+        }
+    }
     Ok(())
 }
 
@@ -139,6 +564,11 @@ fn validate_input_stage(name: &str, config: &StageConfig) -> anyhow::Result<()>
         return Err(anyhow::anyhow!("Input stage '{}' must have an output", name));
     }
 
+    // Only transform stages can share their inputs between replicas
+    if config.replicas.is_some_and(|replicas| replicas > 1) {
+        return Err(anyhow::anyhow!("Input stage '{}' cannot have replicas (only pipeline stages can)", name));
+    }
+
      // Validate that field configuration is appropriate for input stages
     let field_config = extract_field_params(&config.parameters);
     match field_config {
@@ -166,8 +596,8 @@ fn validate_input_stage(name: &str, config: &StageConfig) -> anyhow::Result<()>
 /// 
 /// # Returns
 /// 
-/// * `Ok(())` - Pipeline and all its stages are valid
-/// * `Err(anyhow::Error)` - Pipeline or one of its stages is invalid
+/// The errors of every invalid stage, each with the stage's TOML table
+/// (empty if the pipeline is valid)
 /// 
 /// # Validation Process
 /// 
@@ -187,13 +617,18 @@ fn validate_input_stage(name: &str, config: &StageConfig) -> anyhow::Result<()>
 /// inputs = ["raw_data"]
 /// output = "scaled_data"
 /// ```
-fn validate_pipeline(name: &str, config: &PipelineConfig) -> anyhow::Result<()> {
+fn validate_pipeline(name: &str, config: &PipelineConfig) -> Vec<(String, anyhow::Error)> {
     // Validate each stage within the pipeline
-    for (stage_name, stage_config) in &config.stages {
-        validate_pipeline_stage(name, stage_name, stage_config)
-            .map_err(|e| anyhow::anyhow!("Stage '{}' in pipeline '{}': {}", stage_name, name, e))?;
-    }
-    Ok(())
+    sorted(&config.stages)
+        .into_iter()
+        .filter_map(|(stage_name, stage_config)| {
+            let error = validate_pipeline_stage(name, stage_name, stage_config).err()?;
+            Some((
+                format!("pipelines.{}.stages.{}", name, stage_name),
+                anyhow::anyhow!("Stage '{}' in pipeline '{}': {}", stage_name, name, error),
+            ))
+        })
+        .collect()
 }
 
 /// Validates an individual stage within a pipeline.
@@ -218,6 +653,7 @@ fn validate_pipeline(name: &str, config: &PipelineConfig) -> anyhow::Result<()>
 /// - **Inputs required**: Transform stages must consume data from somewhere
 /// - **At least one input**: Transform stages need data to process
 /// - **Output required**: Transform stages must produce data somewhere
+/// - **Replicas**: If set, at least one
 /// - **Field config**: Can be any valid field configuration type
 /// 
 /// # Example Valid Pipeline Stage
@@ -248,6 +684,14 @@ fn validate_pipeline_stage(pipeline_name: &str, stage_name: &str, config: &Stage
         ));
     }
     
+    if config.replicas == Some(0) {
+        return Err(anyhow::anyhow!(
+            "Pipeline stage '{}.{}' must have at least one replica", 
+            pipeline_name, 
+            stage_name
+        ));
+    }
+    
     // Note: Field configuration validation is processor-specific and handled
     // during processor creation, not here at the structural level
     
@@ -275,6 +719,7 @@ fn validate_pipeline_stage(pipeline_name: &str, stage_name: &str, config: &Stage
 /// - **Inputs required**: Output stages must consume data from somewhere
 /// - **At least one input**: Output stages need data to process
 /// - **No output allowed**: Output stages are terminal, they don't produce data streams
+/// - **No side outputs allowed**: For the same reason
 /// - **Field config**: Can be any valid field configuration type
 /// 
 /// # Example Valid Output Stage
@@ -302,6 +747,15 @@ fn validate_output_stage(name: &str, config: &StageConfig) -> anyhow::Result<()>
         ));
     }
     
+    // Side outputs are allowed: sinks dead-letter undeliverable messages to them
+
+    if config.replicas.is_some_and(|replicas| replicas > 1) {
+        return Err(anyhow::anyhow!(
+            "Output stage '{}' cannot have replicas (only pipeline stages can)", 
+            name
+        ));
+    }
+    
     // Note: Field configuration validation is processor-specific and handled
     // during processor creation, not here at the structural level
 
@@ -0,0 +1,375 @@
+//! Admin HTTP Server
+//!
+//! An optional HTTP server for probes and inspection:
+//!
+//! - `GET /healthz` - liveness: answers as long as the process is serving
+//! - `GET /readyz` - readiness: `503` while any stage is failed or restarting
+//! - `GET /pipelines` - the configured topology, as JSON
+//! - `GET /stages/{name}/stats` - status, delivery results (for sinks) and
+//!   output channel counters of a stage (all of its replicas) or of a single
+//!   replica such as `enrich[1]`
+//! - `GET /metrics` - Prometheus metrics (see [`super::metrics`])
+//!
+//! The server reads shared state only, so it keeps answering while the
+//! pipelines are busy or shutting down.
+//!
+//! The `[security]` section secures the server: with `tls` it serves HTTPS,
+//! optionally verifying client certificates, and with `tokens` every request
+//! needs `Authorization: Bearer <token>` unless its client presented a
+//! verified certificate.
+
+use super::context::DeliveryReport;
+use super::memory::MemoryReport;
+use super::message::Message;
+use super::metrics::metrics;
+use super::registry::ChannelRegistry;
+use super::supervisor::{Health, StageStatus};
+use crate::config::types::{AdminConfig, SecurityConfig};
+use crate::processors::common::tls;
+
+use anyhow::Result;
+use axum::Router;
+use axum::extract::connect_info::{ConnectInfo, Connected};
+use axum::extract::{Path, Request, State};
+use axum::http::{StatusCode, header};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::serve::{IncomingStream, Listener};
+use axum::Json;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio_rustls::server::TlsStream;
+
+/// A configured stage, as the admin server reports it.
+pub struct StageInfo {
+    /// Names the stage runs under, one per replica
+    pub replicas: Vec<String>,
+    /// Output and side output channels
+    pub outputs: Vec<String>,
+}
+
+/// State shared with the admin server.
+pub struct AdminState {
+    pub health: Arc<Health>,
+    pub topology: Value,
+    pub stages: HashMap<String, StageInfo>,
+    pub channels: ChannelRegistry<Message>,
+}
+
+impl AdminState {
+    /// Find a stage by its configured name or by the name of one replica.
+    fn find(&self, name: &str) -> Option<(&StageInfo, Vec<String>)> {
+        if let Some(info) = self.stages.get(name) {
+            return Some((info, info.replicas.clone()));
+        }
+        self.stages
+            .values()
+            .find(|info| info.replicas.iter().any(|replica| replica == name))
+            .map(|info| (info, vec![name.to_string()]))
+    }
+}
+
+/// Start the admin server on the configured address, secured as `security`
+/// asks.
+pub async fn serve(
+    config: &AdminConfig,
+    security: Option<&SecurityConfig>,
+    state: AdminState,
+) -> Result<tokio::task::JoinHandle<()>> {
+    let security = security.cloned().unwrap_or_default();
+    let acceptor = match &security.tls {
+        Some(config) => Some(tls::acceptor(
+            &config.cert_file,
+            &config.key_file,
+            config.client_ca_file.as_deref(),
+            config.client_auth,
+        )?),
+        None => None,
+    };
+    let listener = tokio::net::TcpListener::bind((config.host.as_str(), config.port)).await?;
+    tracing::info!(
+        "Admin server listening on {}:{}{}",
+        config.host,
+        config.port,
+        if acceptor.is_some() { " (HTTPS)" } else { "" }
+    );
+
+    let app = Router::new()
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .route("/pipelines", get(pipelines))
+        .route("/stages/{name}/stats", get(stage_stats))
+        .route("/metrics", get(prometheus_metrics))
+        .with_state(Arc::new(state))
+        .layer(middleware::from_fn_with_state(Arc::new(security.tokens), authorise));
+
+    Ok(tokio::spawn(async move {
+        let served = match acceptor {
+            Some(acceptor) => {
+                let listener = TlsListener::new(listener, acceptor);
+                axum::serve(listener, app.into_make_service_with_connect_info::<Peer>()).await
+            }
+            None => axum::serve(listener, app).await,
+        };
+        if let Err(e) = served {
+            tracing::error!("Admin server failed: {}", e);
+        }
+    }))
+}
+
+/// A client connected over TLS.
+#[derive(Debug, Clone)]
+struct Peer {
+    /// Whether the client presented a verified certificate
+    certified: bool,
+}
+
+impl Connected<IncomingStream<'_, TlsListener>> for Peer {
+    fn connect_info(stream: IncomingStream<'_, TlsListener>) -> Self {
+        Peer { certified: stream.io().get_ref().1.peer_certificates().is_some() }
+    }
+}
+
+/// Accepts TLS connections, completing each handshake on its own task so a
+/// slow or failing client does not hold up the others.
+struct TlsListener {
+    connections: mpsc::Receiver<(TlsStream<TcpStream>, SocketAddr)>,
+    local_addr: std::io::Result<SocketAddr>,
+}
+
+impl TlsListener {
+    fn new(listener: tokio::net::TcpListener, acceptor: tokio_rustls::TlsAcceptor) -> Self {
+        let local_addr = listener.local_addr();
+        let (sender, connections) = mpsc::channel(16);
+        tokio::spawn(async move {
+            loop {
+                let (stream, addr) = match listener.accept().await {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        tracing::warn!("Admin server failed to accept a connection: {}", e);
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                        continue;
+                    }
+                };
+                if sender.is_closed() {
+                    return;
+                }
+                let acceptor = acceptor.clone();
+                let accepted = sender.clone();
+                tokio::spawn(async move {
+                    match tokio::time::timeout(Duration::from_secs(10), acceptor.accept(stream)).await {
+                        Ok(Ok(stream)) => {
+                            let _ = accepted.send((stream, addr)).await;
+                        }
+                        Ok(Err(e)) => tracing::debug!("Admin server: TLS handshake with {} failed: {}", addr, e),
+                        Err(_) => tracing::debug!("Admin server: TLS handshake with {} timed out", addr),
+                    }
+                });
+            }
+        });
+        Self { connections, local_addr }
+    }
+}
+
+impl Listener for TlsListener {
+    type Io = TlsStream<TcpStream>;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        match self.connections.recv().await {
+            Some(connection) => connection,
+            // The accepting task only stops once the server is gone
+            None => std::future::pending().await,
+        }
+    }
+
+    fn local_addr(&self) -> std::io::Result<Self::Addr> {
+        match &self.local_addr {
+            Ok(addr) => Ok(*addr),
+            Err(e) => Err(std::io::Error::new(e.kind(), e.to_string())),
+        }
+    }
+}
+
+/// Let a request through if no tokens are configured, its client presented a
+/// verified certificate, or it carries one of the tokens.
+async fn authorise(State(tokens): State<Arc<Vec<String>>>, request: Request, next: Next) -> Response {
+    let certified = request
+        .extensions()
+        .get::<ConnectInfo<Peer>>()
+        .is_some_and(|ConnectInfo(peer)| peer.certified);
+    let bearer = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    let authorised = tokens.is_empty()
+        || certified
+        || bearer.is_some_and(|bearer| tokens.iter().any(|token| constant_time_eq(token.as_bytes(), bearer.as_bytes())));
+
+    if authorised {
+        next.run(request).await
+    } else {
+        (StatusCode::UNAUTHORIZED, [(header::WWW_AUTHENTICATE, "Bearer")]).into_response()
+    }
+}
+
+/// Compare secrets in time independent of where they differ.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+async fn healthz() -> Json<Value> {
+    Json(json!({ "status": "ok" }))
+}
+
+async fn readyz(State(state): State<Arc<AdminState>>) -> (StatusCode, Json<Value>) {
+    let unready: Vec<String> = state
+        .health
+        .stages()
+        .into_iter()
+        .filter(|(_, health)| matches!(health.status, StageStatus::Failed | StageStatus::Restarting))
+        .map(|(name, _)| name)
+        .collect();
+
+    if unready.is_empty() {
+        (StatusCode::OK, Json(json!({ "ready": true })))
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, Json(json!({ "ready": false, "stages": unready })))
+    }
+}
+
+async fn pipelines(State(state): State<Arc<AdminState>>) -> Json<Value> {
+    Json(state.topology.clone())
+}
+
+async fn prometheus_metrics(State(state): State<Arc<AdminState>>) -> ([(header::HeaderName, &'static str); 1], String) {
+    metrics().record_channels(&state.channels.stats());
+    metrics().record_memory(&MemoryReport::collect());
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], metrics().render())
+}
+
+async fn stage_stats(State(state): State<Arc<AdminState>>, Path(name): Path<String>) -> (StatusCode, Json<Value>) {
+    let Some((info, replicas)) = state.find(&name) else {
+        return (StatusCode::NOT_FOUND, Json(json!({ "error": format!("Unknown stage: '{}'", name) })));
+    };
+
+    let replicas: Vec<Value> = replicas
+        .iter()
+        .map(|replica| match state.health.stage(replica) {
+            Some(health) => json!({
+                "name": replica,
+                "status": health.status,
+                "restarts": health.restarts,
+                "last_error": health.last_error,
+                "slow": health.slow,
+                "delivery": health.delivery.as_ref().map(DeliveryReport::to_json),
+            }),
+            None => json!({ "name": replica, "status": null }),
+        })
+        .collect();
+    let outputs: serde_json::Map<String, Value> = info
+        .outputs
+        .iter()
+        .filter_map(|output| {
+            let channel = state.channels.get(output)?;
+            Some((output.clone(), json!(channel.stats())))
+        })
+        .collect();
+
+    (StatusCode::OK, Json(json!({ "name": name, "replicas": replicas, "outputs": outputs })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+
+    #[test]
+    fn test_admin_stage_lookup() {
+        let state = Arc::new(AdminState {
+            health: Arc::new(Health::default()),
+            topology: json!({}),
+            stages: HashMap::from([(
+                "enrich".to_string(),
+                StageInfo {
+                    replicas: vec!["enrich[0]".to_string(), "enrich[1]".to_string()],
+                    outputs: vec!["enriched".to_string()],
+                },
+            )]),
+            channels: ChannelRegistry::new(),
+        });
+
+        let (status, Json(body)) = block_on(stage_stats(State(state.clone()), Path("enrich".to_string())));
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["replicas"].as_array().unwrap().len(), 2);
+
+        let (status, Json(body)) = block_on(stage_stats(State(state.clone()), Path("enrich[1]".to_string())));
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["replicas"][0]["name"], "enrich[1]");
+
+        let (status, _) = block_on(stage_stats(State(state.clone()), Path("missing".to_string())));
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let (status, _) = block_on(readyz(State(state)));
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_admin_security_accepts_certificates_or_tokens() {
+        use crate::config::types::{ClientAuth, ServerTlsConfig};
+        use crate::processors::common::TlsConfig;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let fixture = |name: &str| format!("{}/tests/fixtures/tls/{}", env!("CARGO_MANIFEST_DIR"), name);
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let config = AdminConfig { host: "127.0.0.1".to_string(), port };
+        let security = SecurityConfig {
+            tls: Some(ServerTlsConfig {
+                cert_file: fixture("server.pem"),
+                key_file: fixture("server.key"),
+                client_ca_file: Some(fixture("ca.pem")),
+                client_auth: ClientAuth::Optional,
+            }),
+            tokens: vec!["secret".to_string()],
+        };
+        let state = AdminState {
+            health: Arc::new(Health::default()),
+            topology: json!({}),
+            stages: HashMap::new(),
+            channels: ChannelRegistry::new(),
+        };
+        let server = serve(&config, Some(&security), state).await.unwrap();
+
+        let status = |certified: bool, authorization: &'static str| {
+            let tls = TlsConfig {
+                cert_file: certified.then(|| fixture("client.pem")),
+                key_file: certified.then(|| fixture("client.key")),
+                ca_file: Some(fixture("ca.pem")),
+                server_name: None,
+            };
+            async move {
+                let stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+                let connector = tls.connector().unwrap();
+                let mut stream = connector.connect(tls.server_name("localhost").unwrap(), stream).await.unwrap();
+                let request = format!("GET /healthz HTTP/1.1\r\nHost: localhost\r\n{}Connection: close\r\n\r\n", authorization);
+                stream.write_all(request.as_bytes()).await.unwrap();
+                let mut response = vec![0; 12];
+                stream.read_exact(&mut response).await.unwrap();
+                String::from_utf8(response).unwrap()
+            }
+        };
+
+        assert_eq!(status(false, "").await, "HTTP/1.1 401");
+        assert_eq!(status(false, "Authorization: Bearer wrong\r\n").await, "HTTP/1.1 401");
+        assert_eq!(status(false, "Authorization: Bearer secret\r\n").await, "HTTP/1.1 200");
+        assert_eq!(status(true, "").await, "HTTP/1.1 200");
+        server.abort();
+    }
+}
@@ -0,0 +1,78 @@
+//! Back-fill Runs
+//!
+//! A back-fill reprocesses history, for example to recompute aggregates after
+//! a pipeline bug is fixed. Every source reads the messages of a bounded range
+//! of event time, in event-time order and without pauses, and completes at the
+//! end of the range; the pipeline then drains and the run exits. Watermarks
+//! advance with the event times of the data instead of the wall clock.
+//!
+//! ```text
+//! liminal -c config.toml --backfill --from 2026-03-01T00:00:00Z --until 2026-03-02T00:00:00Z
+//! ```
+//!
+//! Only sources that can read history take part; a back-fill of a
+//! configuration with any other source (an MQTT subscription, say) fails to
+//! start.
+
+use anyhow::{Result, anyhow};
+use chrono::DateTime;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The event times a back-fill covers: from `from` (inclusive) until
+/// `until` (exclusive), unbounded where unset.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BackfillRange {
+    pub from: Option<SystemTime>,
+    pub until: Option<SystemTime>,
+}
+
+impl BackfillRange {
+    /// Parse the bounds of a range, each an RFC 3339 timestamp or
+    /// milliseconds since the epoch.
+    pub fn parse(from: Option<&str>, until: Option<&str>) -> Result<Self> {
+        let range = Self {
+            from: from.map(parse_time).transpose()?,
+            until: until.map(parse_time).transpose()?,
+        };
+        if let (Some(from), Some(until)) = (range.from, range.until)
+            && from >= until
+        {
+            return Err(anyhow!("back-fill range is empty: --from must be before --until"));
+        }
+        Ok(range)
+    }
+
+    /// Whether the range covers `event_time`.
+    pub fn contains(&self, event_time: SystemTime) -> bool {
+        self.from.is_none_or(|from| event_time >= from) && self.until.is_none_or(|until| event_time < until)
+    }
+}
+
+fn parse_time(text: &str) -> Result<SystemTime> {
+    if let Ok(millis) = text.parse::<u64>() {
+        return Ok(UNIX_EPOCH + Duration::from_millis(millis));
+    }
+    let time = DateTime::parse_from_rfc3339(text)
+        .map_err(|e| anyhow!("Invalid back-fill time '{}' (expected RFC 3339 or epoch milliseconds): {}", text, e))?;
+    Ok(time.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backfill_range_parses_and_bounds() {
+        let range = BackfillRange::parse(Some("2026-03-01T00:00:00Z"), Some("1772409600000")).unwrap();
+        let from = range.from.unwrap();
+        assert_eq!(from.duration_since(UNIX_EPOCH).unwrap().as_millis(), 1772323200000);
+        assert!(range.contains(from));
+        assert!(range.contains(from + Duration::from_secs(3600)));
+        assert!(!range.contains(range.until.unwrap()));
+        assert!(!range.contains(from - Duration::from_millis(1)));
+
+        assert!(BackfillRange::parse(None, None).unwrap().contains(UNIX_EPOCH));
+        assert!(BackfillRange::parse(Some("1000"), Some("1000")).is_err());
+        assert!(BackfillRange::parse(Some("yesterday"), None).is_err());
+    }
+}
@@ -0,0 +1,262 @@
+//! Columnar Batches
+//!
+//! High-rate numeric telemetry can travel as batches of rows rather than one
+//! JSON message per reading. A [`RecordBatch`] holds the event time of each
+//! row and one column of `f64` values per field, laid out column by column as
+//! in an Apache Arrow record batch, and is carried by a message in place of
+//! its payload (see [`Message::from_batch`]).
+//!
+//! Processors that work on whole columns say so with
+//! `Processor::accepts_batches`. Every other stage, including every sink,
+//! receives the rows of a batch as ordinary messages, split off by its
+//! processing context, so batches are converted to JSON only where a
+//! processor needs JSON.
+//!
+//! The batch is Liminal's own type rather than Arrow's: stages only ever
+//! need `f64` columns and a time per row, messages and the batches they
+//! carry serialize with serde (e.g. into a persistent channel), and the
+//! `arrow` crates are a sizeable dependency for edge builds. Builds with the
+//! `arrow` feature convert batches to and from Arrow record batches with
+//! [`RecordBatch::to_arrow`] and [`RecordBatch::from_arrow`], as an
+//! `event_time` column of millisecond timestamps followed by a `Float64`
+//! column per field.
+//!
+//! [`Message::from_batch`]: super::message::Message::from_batch
+
+use crate::processors::common::field_utils::FieldUtils;
+
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use serde_json::{Number, Value};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// One field of every row of a batch.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Column {
+    name: String,
+    values: Vec<f64>,
+}
+
+/// Rows of numeric readings, stored column by column.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RecordBatch {
+    /// Event time of each row, in milliseconds since the Unix epoch
+    event_times: Vec<u64>,
+    columns: Vec<Column>,
+}
+
+impl RecordBatch {
+    /// An empty batch with the named columns.
+    pub fn new(columns: &[&str]) -> Self {
+        Self {
+            event_times: Vec::new(),
+            columns: columns
+                .iter()
+                .map(|name| Column { name: name.to_string(), values: Vec::new() })
+                .collect(),
+        }
+    }
+
+    /// Append a row, with one value per column in the order they were named.
+    pub fn push_row(&mut self, event_time: SystemTime, values: &[f64]) -> Result<()> {
+        if values.len() != self.columns.len() {
+            return Err(anyhow!("Row has {} values but the batch has {} columns", values.len(), self.columns.len()));
+        }
+        self.event_times.push(event_time.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64);
+        for (column, value) in self.columns.iter_mut().zip(values) {
+            column.values.push(*value);
+        }
+        Ok(())
+    }
+
+    /// Number of rows.
+    pub fn len(&self) -> usize {
+        self.event_times.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.event_times.is_empty()
+    }
+
+    pub fn column_names(&self) -> impl Iterator<Item = &str> {
+        self.columns.iter().map(|column| column.name.as_str())
+    }
+
+    /// The values of a column, one per row.
+    pub fn column(&self, name: &str) -> Option<&[f64]> {
+        self.columns.iter().find(|column| column.name == name).map(|column| column.values.as_slice())
+    }
+
+    /// The values of a column, to change in place.
+    pub fn column_mut(&mut self, name: &str) -> Option<&mut [f64]> {
+        self.columns.iter_mut().find(|column| column.name == name).map(|column| column.values.as_mut_slice())
+    }
+
+    /// Replace a column, or add it if the batch has none of that name.
+    pub fn set_column(&mut self, name: &str, values: Vec<f64>) -> Result<()> {
+        if values.len() != self.len() {
+            return Err(anyhow!("Column '{}' has {} values but the batch has {} rows", name, values.len(), self.len()));
+        }
+        match self.columns.iter_mut().find(|column| column.name == name) {
+            Some(column) => column.values = values,
+            None => self.columns.push(Column { name: name.to_string(), values }),
+        }
+        Ok(())
+    }
+
+    /// Event time of each row.
+    pub fn event_times(&self) -> impl Iterator<Item = SystemTime> + '_ {
+        self.event_times.iter().map(|millis| UNIX_EPOCH + Duration::from_millis(*millis))
+    }
+
+    /// Keep the rows whose entry in `keep` is true.
+    pub fn retain(&mut self, keep: &[bool]) {
+        let mut kept = keep.iter();
+        self.event_times.retain(|_| *kept.next().unwrap_or(&true));
+        for column in &mut self.columns {
+            let mut kept = keep.iter();
+            column.values.retain(|_| *kept.next().unwrap_or(&true));
+        }
+    }
+
+    /// Each row as a JSON payload with a field per column (dotted names
+    /// nest), along with its event time. Values that are not finite become
+    /// `null`, as JSON has no numbers for them.
+    pub fn rows(&self) -> impl Iterator<Item = (SystemTime, Value)> + '_ {
+        self.event_times().enumerate().map(|(row, event_time)| {
+            let mut payload = Value::Object(Default::default());
+            for column in &self.columns {
+                let value = Number::from_f64(column.values[row]).map_or(Value::Null, Value::Number);
+                // Only fails on a path through a non-object, which columns never create
+                let _ = FieldUtils::set_field_value(&mut payload, &column.name, value);
+            }
+            (event_time, payload)
+        })
+    }
+}
+
+/// Name of the column holding the event time of each row in Arrow batches
+#[cfg(feature = "arrow")]
+const EVENT_TIME: &str = "event_time";
+
+#[cfg(feature = "arrow")]
+impl RecordBatch {
+    /// The batch as an Arrow record batch: an `event_time` column of
+    /// millisecond timestamps followed by a `Float64` column per field.
+    pub fn to_arrow(&self) -> Result<arrow_array::RecordBatch> {
+        use arrow_array::{ArrayRef, Float64Array, TimestampMillisecondArray};
+        use std::sync::Arc;
+
+        let event_times: Vec<i64> = self.event_times.iter().map(|millis| *millis as i64).collect();
+        let mut columns: Vec<(&str, ArrayRef)> = vec![(EVENT_TIME, Arc::new(TimestampMillisecondArray::from(event_times)))];
+        for column in &self.columns {
+            if column.name == EVENT_TIME {
+                return Err(anyhow!("Column '{}' clashes with the event time column of Arrow batches", EVENT_TIME));
+            }
+            columns.push((column.name.as_str(), Arc::new(Float64Array::from(column.values.clone()))));
+        }
+        arrow_array::RecordBatch::try_from_iter(columns).map_err(|e| anyhow!("Failed to build Arrow batch: {}", e))
+    }
+
+    /// A batch from an Arrow record batch laid out as by [`Self::to_arrow`].
+    /// Null values become `NaN`.
+    pub fn from_arrow(batch: &arrow_array::RecordBatch) -> Result<Self> {
+        use arrow_array::{Array, Float64Array, TimestampMillisecondArray};
+
+        let event_times = batch
+            .column_by_name(EVENT_TIME)
+            .and_then(|array| array.as_any().downcast_ref::<TimestampMillisecondArray>())
+            .ok_or_else(|| anyhow!("Arrow batch has no millisecond timestamp column '{}'", EVENT_TIME))?;
+        if event_times.null_count() > 0 {
+            return Err(anyhow!("Arrow batch has rows without an event time"));
+        }
+
+        let mut columns = Vec::new();
+        for (field, array) in batch.schema().fields().iter().zip(batch.columns()) {
+            if field.name() == EVENT_TIME {
+                continue;
+            }
+            let values = array
+                .as_any()
+                .downcast_ref::<Float64Array>()
+                .ok_or_else(|| anyhow!("Column '{}' is {}, not Float64", field.name(), array.data_type()))?;
+            columns.push(Column {
+                name: field.name().clone(),
+                values: values.iter().map(|value| value.unwrap_or(f64::NAN)).collect(),
+            });
+        }
+        Ok(Self {
+            event_times: event_times.values().iter().map(|millis| (*millis).max(0) as u64).collect(),
+            columns,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::message::Message;
+    use crate::testing::TestContext;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_batches_reach_json_processors_as_rows() {
+        let start = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let mut batch = RecordBatch::new(&["value", "sensor.rh"]);
+        for (i, value) in [1.0, 2.5, f64::NAN].into_iter().enumerate() {
+            batch.push_row(start + Duration::from_millis(i as u64 * 10), &[value, 40.0]).unwrap();
+        }
+        assert!(batch.push_row(start, &[1.0]).is_err());
+        batch.set_column("double", batch.column("value").unwrap().iter().map(|v| v * 2.0).collect()).unwrap();
+        batch.retain(&[true, false, true]);
+        assert_eq!(batch.len(), 2);
+        let double = batch.column("double").unwrap();
+        assert!(double[0] == 2.0 && double[1].is_nan());
+
+        let rows: Vec<_> = batch.rows().collect();
+        assert_eq!(rows[0], (start, json!({ "value": 1.0, "sensor": { "rh": 40.0 }, "double": 2.0 })));
+        assert_eq!(rows[1], (start + Duration::from_millis(20), json!({ "value": null, "sensor": { "rh": 40.0 }, "double": null })));
+
+        // A context whose processor does not accept batches hands out the rows
+        let mut test = TestContext::new("rows").input("in");
+        test.send("in", Message::from_batch("sensor", "in", batch.clone())).await.unwrap();
+        let first = test.context.try_recv().await.unwrap().1;
+        assert_eq!((first.payload, first.timing.event_time, first.batch), (rows[0].1.clone(), start, None));
+        assert!(test.context.has_pending_input());
+        assert_eq!(test.context.try_recv().await.unwrap().1.payload, rows[1].1);
+
+        test.context.accept_batches(true);
+        test.send("in", Message::from_batch("sensor", "in", batch.clone())).await.unwrap();
+        assert_eq!(test.context.try_recv().await.unwrap().1.batch.map(|batch| batch.len()), Some(2));
+    }
+
+    #[cfg(feature = "arrow")]
+    #[test]
+    fn test_batches_convert_to_and_from_arrow() {
+        use arrow_array::{ArrayRef, Float64Array, Int32Array, TimestampMillisecondArray};
+        use std::sync::Arc;
+
+        let start = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let mut batch = RecordBatch::new(&["value", "sensor.rh"]);
+        batch.push_row(start, &[1.0, 40.0]).unwrap();
+        batch.push_row(start + Duration::from_millis(10), &[f64::NAN, 41.0]).unwrap();
+
+        let arrow = batch.to_arrow().unwrap();
+        assert_eq!(arrow.num_rows(), 2);
+        let names: Vec<_> = arrow.schema().fields().iter().map(|field| field.name().clone()).collect();
+        assert_eq!(names, ["event_time", "value", "sensor.rh"]);
+        let back = RecordBatch::from_arrow(&arrow).unwrap();
+        assert_eq!(back.event_times().collect::<Vec<_>>(), batch.event_times().collect::<Vec<_>>());
+        assert_eq!(back.column("sensor.rh"), Some(&[40.0, 41.0][..]));
+        assert!(back.column("value").unwrap()[1].is_nan());
+
+        // Nulls become NaN, and columns must be Float64
+        let times: ArrayRef = Arc::new(TimestampMillisecondArray::from(vec![0, 10]));
+        let nulls: ArrayRef = Arc::new(Float64Array::from(vec![Some(2.0), None]));
+        let arrow = arrow_array::RecordBatch::try_from_iter([("event_time", times.clone()), ("value", nulls)]).unwrap();
+        assert!(RecordBatch::from_arrow(&arrow).unwrap().column("value").unwrap()[1].is_nan());
+        let integers: ArrayRef = Arc::new(Int32Array::from(vec![1, 2]));
+        let arrow = arrow_array::RecordBatch::try_from_iter([("event_time", times), ("count", integers)]).unwrap();
+        assert!(RecordBatch::from_arrow(&arrow).is_err());
+    }
+}
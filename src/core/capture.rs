@@ -0,0 +1,210 @@
+//! Capture Recording
+//!
+//! Records the messages published to selected channels, so that a field
+//! incident can be reproduced elsewhere with the `replay` input. Stages
+//! publishing to a recorded channel publish through a [`RecordingChannel`],
+//! which passes each message on to the channel and hands a copy to the
+//! recorder, so recording works with every channel type and takes nothing
+//! from the stages reading the channel.
+//!
+//! A capture is a JSON lines file, one [`CaptureEntry`] per message, in the
+//! order the messages were published. Entries are written by a background
+//! thread, so a slow disk doesn't hold up the pipeline.
+
+use super::channel::{PubSubChannel, PublishError, Subscriber};
+use super::message::Message;
+use crate::config::types::RecordConfig;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// One recorded message.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CaptureEntry {
+    /// Channel the message was published to
+    pub channel: String,
+    /// When it was published, relative to the start of the recording
+    pub offset_ms: u64,
+    pub message: Message,
+}
+
+enum Record {
+    Entry(Box<CaptureEntry>),
+    /// Flush everything written so far, then acknowledge
+    Flush(flume::Sender<()>),
+}
+
+/// Writes the messages of recorded channels to a capture file.
+pub struct Recorder {
+    channels: Vec<String>,
+    path: PathBuf,
+    started: Instant,
+    sender: flume::Sender<Record>,
+}
+
+impl Recorder {
+    /// Start a recording in a new timestamped file of the configured directory.
+    pub fn start(config: &RecordConfig) -> Result<Self> {
+        std::fs::create_dir_all(&config.directory)
+            .with_context(|| format!("Failed to create capture directory '{}'", config.directory))?;
+        let timestamp = chrono::Utc::now().format("%Y%m%dT%H%M%S%.3fZ");
+        let path = Path::new(&config.directory).join(format!("capture-{}.jsonl", timestamp));
+        let file = std::fs::File::create(&path)
+            .with_context(|| format!("Failed to create capture file '{}'", path.display()))?;
+
+        let (sender, receiver) = flume::unbounded();
+        let writer_path = path.clone();
+        std::thread::Builder::new()
+            .name("liminal-capture".to_string())
+            .spawn(move || write_entries(BufWriter::new(file), &writer_path, receiver))
+            .context("Failed to start the capture writer")?;
+
+        tracing::info!("Recording channels {:?} to '{}'", config.channels, path.display());
+        Ok(Self {
+            channels: config.channels.clone(),
+            path,
+            started: Instant::now(),
+            sender,
+        })
+    }
+
+    /// The capture file being written.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Wrap the channel a stage publishes to, recording what it publishes if
+    /// the channel is recorded.
+    pub fn tap(&self, name: &str, channel: Arc<dyn PubSubChannel<Message>>) -> Arc<dyn PubSubChannel<Message>> {
+        if !self.channels.iter().any(|recorded| recorded == name) {
+            return channel;
+        }
+        Arc::new(RecordingChannel {
+            name: name.to_string(),
+            inner: channel,
+            started: self.started,
+            sender: self.sender.clone(),
+        })
+    }
+
+    /// Wait until everything recorded so far is written, up to `timeout`.
+    pub async fn flush(&self, timeout: Duration) {
+        let (ack, acked) = flume::bounded(1);
+        if self.sender.send(Record::Flush(ack)).is_ok()
+            && tokio::time::timeout(timeout, acked.recv_async()).await.is_err()
+        {
+            tracing::warn!("Capture '{}' was not flushed within {:?}", self.path.display(), timeout);
+        }
+    }
+}
+
+fn write_entries(mut writer: BufWriter<std::fs::File>, path: &Path, receiver: flume::Receiver<Record>) {
+    let mut failed = false;
+    for record in receiver.iter() {
+        let result = match record {
+            Record::Entry(entry) => serde_json::to_writer(&mut writer, &*entry)
+                .map_err(std::io::Error::from)
+                .and_then(|_| writer.write_all(b"\n")),
+            Record::Flush(ack) => {
+                let result = writer.flush();
+                let _ = ack.send(());
+                result
+            }
+        };
+        // Flush whenever the queue runs dry, so little is lost on a crash
+        let result = result.and_then(|_| if receiver.is_empty() { writer.flush() } else { Ok(()) });
+        if let Err(e) = result
+            && !failed
+        {
+            tracing::error!("Failed to write capture '{}': {}", path.display(), e);
+            failed = true;
+        }
+    }
+}
+
+/// A channel whose published messages are also recorded.
+pub struct RecordingChannel {
+    name: String,
+    inner: Arc<dyn PubSubChannel<Message>>,
+    started: Instant,
+    sender: flume::Sender<Record>,
+}
+
+#[async_trait]
+impl PubSubChannel<Message> for RecordingChannel {
+    async fn publish(&self, msg: Message) -> Result<(), PublishError<Message>> {
+        let entry = CaptureEntry {
+            channel: self.name.clone(),
+            offset_ms: self.started.elapsed().as_millis() as u64,
+            message: msg.clone(),
+        };
+        self.inner.publish(msg).await?;
+        let _ = self.sender.send(Record::Entry(Box::new(entry)));
+        Ok(())
+    }
+
+    fn subscribe(&self) -> Subscriber<Message> {
+        self.inner.subscribe()
+    }
+
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    fn capacity(&self) -> Option<usize> {
+        self.inner.capacity()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::types::ChannelConfig;
+    use crate::core::channel::Channel;
+    use futures::executor::block_on;
+    use serde_json::json;
+
+    #[test]
+    fn test_recorder_writes_published_messages_of_recorded_channels() {
+        let directory = std::env::temp_dir().join(format!("liminal-capture-{}", std::process::id()));
+        let recorder = Recorder::start(&RecordConfig {
+            channels: vec!["raw".to_string()],
+            directory: directory.to_string_lossy().to_string(),
+        })
+        .unwrap();
+
+        let raw: Arc<dyn PubSubChannel<Message>> = Arc::new(Channel::open("raw", &ChannelConfig::default()).unwrap());
+        let other: Arc<dyn PubSubChannel<Message>> = Arc::new(Channel::open("other", &ChannelConfig::default()).unwrap());
+        let mut subscriber = raw.subscribe();
+        let _other_subscriber = other.subscribe();
+        let raw = recorder.tap("raw", raw);
+        let other = recorder.tap("other", other);
+
+        block_on(raw.publish(Message::new("sensor", "raw", json!({"value": 1})))).unwrap();
+        block_on(other.publish(Message::new("sensor", "other", json!({"value": 2})))).unwrap();
+        block_on(raw.publish(Message::new("sensor", "raw", json!({"value": 3})))).unwrap();
+        let (ack, acked) = flume::bounded(1);
+        recorder.sender.send(Record::Flush(ack)).unwrap();
+        acked.recv().unwrap();
+
+        // Readers of the channel still receive every message
+        assert_eq!(block_on(subscriber.try_recv()).into_message().unwrap().payload, json!({"value": 1}));
+
+        let entries: Vec<CaptureEntry> = std::fs::read_to_string(recorder.path())
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(entries.len(), 2);
+        assert!(entries.iter().all(|entry| entry.channel == "raw"));
+        assert_eq!(entries[1].message.payload, json!({"value": 3}));
+        assert!(entries[0].offset_ms <= entries[1].offset_ms);
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
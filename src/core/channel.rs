@@ -1,67 +1,235 @@
-use crate::config::types::ChannelType;
+use crate::config::types::{ChannelConfig, ChannelType, OverflowPolicy};
+use crate::core::queue::DiskQueue;
+use crate::error::LiminalError;
 use async_trait::async_trait;
 use flume;
-use std::sync::Mutex;
-use tokio::sync::{broadcast, mpsc};
+use serde::{Serialize, de::DeserializeOwned};
+use std::collections::{BTreeMap, VecDeque};
+use std::marker::PhantomData;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{Notify, broadcast};
 
 #[derive(Debug)]
 pub enum PublishError<M> {
     BroadcastError(broadcast::error::SendError<M>),
-    MpscError(mpsc::error::SendError<M>),
+    MpscError(flume::SendError<M>),
     FlumeError(flume::SendError<M>),
-    FanoutError(mpsc::error::SendError<M>),
+    FanoutError(flume::SendError<M>),
+    PersistentError(anyhow::Error),
+    /// The channel was full and its overflow policy is `error`
+    Overflow(M),
+}
+
+impl<M> PublishError<M> {
+    /// The error as a `LiminalError` naming `channel`, for reporting.
+    pub fn into_error(self, channel: &str) -> anyhow::Error {
+        let channel = channel.to_string();
+        match self {
+            PublishError::Overflow(_) => LiminalError::ChannelFull { channel }.into(),
+            PublishError::PersistentError(e) => e.context(format!("publishing to channel '{}'", channel)),
+            _ => LiminalError::ChannelClosed { channel }.into(),
+        }
+    }
+}
+
+/// Messages with a delivery priority, for priority channels.
+pub trait Prioritised {
+    /// Delivery priority, higher first. `field` names a payload field holding
+    /// it, for messages that do not carry one themselves.
+    fn priority(&self, field: Option<&str>) -> u8;
+}
+
+/// Message counters for a channel, shared by its publishers.
+#[derive(Debug, Default)]
+pub struct ChannelMetrics {
+    published: AtomicU64,
+    dropped: AtomicU64,
+    rejected: AtomicU64,
+}
+
+/// Point-in-time copy of a channel's counters and occupancy.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ChannelStats {
+    /// Messages accepted into the channel
+    pub published: u64,
+    /// Messages discarded by a `drop_oldest` or `drop_newest` policy
+    /// (counted per subscriber for fan-out channels)
+    pub dropped: u64,
+    /// Publishes refused by the `error` policy
+    pub rejected: u64,
+    /// Messages queued and not yet received (by the slowest subscriber of a
+    /// broadcast or fan-out channel)
+    pub depth: usize,
+    /// Messages the channel holds before its overflow policy applies
+    /// (`None` for persistent channels, which are unbounded)
+    pub capacity: Option<usize>,
+}
+
+impl ChannelMetrics {
+    fn record_published(&self) {
+        self.published.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn record_dropped(&self) {
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn record_rejected(&self) {
+        self.rejected.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn stats(&self) -> ChannelStats {
+        ChannelStats {
+            published: self.published.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            rejected: self.rejected.load(Ordering::Relaxed),
+            ..Default::default()
+        }
+    }
+}
+
+/// Publish to a bounded flume queue under an overflow policy. `evict` is a
+/// receiver on the same queue, used to discard the oldest message.
+async fn publish_bounded<M>(
+    sender: &flume::Sender<M>,
+    evict: &flume::Receiver<M>,
+    mut msg: M,
+    overflow: OverflowPolicy,
+    metrics: &ChannelMetrics,
+) -> Result<(), flume::SendError<M>>
+where
+    M: Send,
+{
+    match overflow {
+        OverflowPolicy::Block => sender.send_async(msg).await?,
+        OverflowPolicy::DropOldest => loop {
+            match sender.try_send(msg) {
+                Ok(()) => break,
+                Err(flume::TrySendError::Full(returned)) => {
+                    if evict.try_recv().is_ok() {
+                        metrics.record_dropped();
+                    }
+                    msg = returned;
+                }
+                Err(flume::TrySendError::Disconnected(returned)) => {
+                    return Err(flume::SendError(returned));
+                }
+            }
+        },
+        // Callers check for room first under the error policy
+        OverflowPolicy::DropNewest | OverflowPolicy::Error => match sender.try_send(msg) {
+            Ok(()) => {}
+            Err(flume::TrySendError::Full(_)) => {
+                metrics.record_dropped();
+                return Ok(());
+            }
+            Err(flume::TrySendError::Disconnected(returned)) => {
+                return Err(flume::SendError(returned));
+            }
+        },
+    }
+
+    metrics.record_published();
+    Ok(())
+}
+
+/// Outcome of receiving from a [`Subscriber`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum RecvResult<M> {
+    Message(M),
+    /// A broadcast subscriber fell behind and this many messages were
+    /// overwritten before it received them. Receiving again continues with
+    /// the oldest message still held.
+    Lagged(u64),
+    /// Nothing is waiting (only from `try_recv`)
+    Empty,
+    /// The channel is closed, or a persistent queue cannot be read
+    Closed,
+}
+
+impl<M> RecvResult<M> {
+    /// The received message, if there is one.
+    pub fn into_message(self) -> Option<M> {
+        match self {
+            RecvResult::Message(msg) => Some(msg),
+            _ => None,
+        }
+    }
 }
 
 pub enum Subscriber<M> {
-    Broadcast(broadcast::Receiver<M>),
-    Mpsc(mpsc::Receiver<M>),
+    Broadcast(BroadcastReceiver<M>),
+    Mpsc(flume::Receiver<M>),
     Flume(flume::Receiver<M>),
-    Fanout(mpsc::Receiver<M>),
+    Fanout(flume::Receiver<M>),
+    Persistent(PersistentReceiver<M>),
+    Priority(PriorityReceiver<M>),
 }
 
 impl<M> Subscriber<M>
 where
-    M: Clone,
+    M: Clone + DeserializeOwned,
 {
-    /// Receive the next message from the channel.
-    /// - mpsc: returns `None` if the channel is closed.
-    /// - broadcast: skips lagged, returns `None` if the channel is closed.
-    /// - flume: returns `None` if disconnected.
-    /// - fanout: returns `None` if the channel is closed.
-    pub async fn recv(&mut self) -> Option<M> {
+    /// Receive the next message from the channel, waiting until one arrives.
+    /// - broadcast: returns `Lagged` once when messages were skipped.
+    /// - mpsc, flume, fanout, broadcast: returns `Closed` once every sender is gone.
+    /// - persistent: returns `Closed` if the queue cannot be read.
+    pub async fn recv(&mut self) -> RecvResult<M> {
         match self {
-            Subscriber::Mpsc(rx) => rx.recv().await,
             Subscriber::Broadcast(rx) => match rx.recv().await {
-                Ok(msg) => Some(msg),
-                Err(broadcast::error::RecvError::Lagged(_)) => None,
-                Err(broadcast::error::RecvError::Closed) => None,
-            },
-            Subscriber::Flume(rx) => match rx.recv_async().await {
-                Ok(msg) => Some(msg),
-                Err(flume::RecvError::Disconnected) => None,
+                Ok(msg) => RecvResult::Message(msg),
+                Err(broadcast::error::RecvError::Lagged(skipped)) => RecvResult::Lagged(skipped),
+                Err(broadcast::error::RecvError::Closed) => RecvResult::Closed,
             },
-            Subscriber::Fanout(rx) => rx.recv().await,
+            Subscriber::Mpsc(rx) | Subscriber::Flume(rx) | Subscriber::Fanout(rx) => {
+                match rx.recv_async().await {
+                    Ok(msg) => RecvResult::Message(msg),
+                    Err(flume::RecvError::Disconnected) => RecvResult::Closed,
+                }
+            }
+            Subscriber::Persistent(rx) => rx.recv().await.map_or(RecvResult::Closed, RecvResult::Message),
+            Subscriber::Priority(rx) => RecvResult::Message(rx.recv().await),
         }
     }
 
-    pub async fn try_recv(&mut self) -> Option<M> {
+    /// Take the next message if one is waiting, returning `Empty` otherwise.
+    pub async fn try_recv(&mut self) -> RecvResult<M> {
         match self {
-            Subscriber::Mpsc(rx) => match rx.try_recv() {
-                Ok(msg) => Some(msg),
-                _ => None,
-            }
             Subscriber::Broadcast(rx) => match rx.try_recv() {
-                Ok(msg) => Some(msg),
-                _ => None,
+                Ok(msg) => RecvResult::Message(msg),
+                Err(broadcast::error::TryRecvError::Lagged(skipped)) => RecvResult::Lagged(skipped),
+                Err(broadcast::error::TryRecvError::Empty) => RecvResult::Empty,
+                Err(broadcast::error::TryRecvError::Closed) => RecvResult::Closed,
             },
-            Subscriber::Flume(rx) => match rx.try_recv() {
-                Ok(msg) => Some(msg),
-                _ => None,
-            }
-            Subscriber::Fanout(rx) => match rx.try_recv() {
-                Ok(msg) => Some(msg),
-                _ => None,
-            }
+            Subscriber::Mpsc(rx) | Subscriber::Flume(rx) | Subscriber::Fanout(rx) => match rx.try_recv() {
+                Ok(msg) => RecvResult::Message(msg),
+                Err(flume::TryRecvError::Empty) => RecvResult::Empty,
+                Err(flume::TryRecvError::Disconnected) => RecvResult::Closed,
+            },
+            Subscriber::Persistent(rx) => rx.try_recv().map_or(RecvResult::Empty, RecvResult::Message),
+            Subscriber::Priority(rx) => rx.try_recv().map_or(RecvResult::Empty, RecvResult::Message),
+        }
+    }
+
+    /// Whether no messages are waiting to be received.
+    pub fn is_empty(&self) -> bool {
+        match self {
+            Subscriber::Broadcast(rx) => rx.receiver.is_empty(),
+            Subscriber::Mpsc(rx) | Subscriber::Flume(rx) | Subscriber::Fanout(rx) => rx.is_empty(),
+            Subscriber::Persistent(rx) => rx.is_empty(),
+            Subscriber::Priority(rx) => rx.len() == 0,
+        }
+    }
+
+    /// Number of messages waiting to be received.
+    pub fn len(&self) -> usize {
+        match self {
+            Subscriber::Broadcast(rx) => rx.receiver.len(),
+            Subscriber::Mpsc(rx) | Subscriber::Flume(rx) | Subscriber::Fanout(rx) => rx.len(),
+            Subscriber::Persistent(rx) => rx.len(),
+            Subscriber::Priority(rx) => rx.len(),
         }
     }
 }
@@ -73,20 +241,41 @@ pub trait PubSubChannel<M>: Send + Sync {
 
     /// Subscribe to the channel to get a fresh receiver.
     fn subscribe(&self) -> Subscriber<M>;
+
+    /// Number of messages queued and not yet received. For channels where
+    /// every subscriber sees every message, this is the backlog of the
+    /// slowest subscriber.
+    fn len(&self) -> usize;
+
+    /// Number of messages the channel holds before its overflow policy
+    /// applies, or `None` if it is unbounded.
+    fn capacity(&self) -> Option<usize>;
+
+    /// Whether no messages are queued.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
 }
 
 /// MPSC / point-to-point channel
 pub struct MpscChannel<M> {
-    sender: mpsc::Sender<M>,
-    receiver: Mutex<Option<mpsc::Receiver<M>>>,
+    sender: flume::Sender<M>,
+    /// Kept by the channel to evict the oldest message under `drop_oldest`
+    receiver: flume::Receiver<M>,
+    subscribed: AtomicBool,
+    overflow: OverflowPolicy,
+    metrics: ChannelMetrics,
 }
 
 impl<M> MpscChannel<M> {
-    pub fn new(capacity: usize) -> Self {
-        let (sender, receiver) = mpsc::channel(capacity);
+    pub fn new(capacity: usize, overflow: OverflowPolicy) -> Self {
+        let (sender, receiver) = flume::bounded(capacity);
         Self {
             sender,
-            receiver: Mutex::new(Some(receiver)),
+            receiver,
+            subscribed: AtomicBool::new(false),
+            overflow,
+            metrics: ChannelMetrics::default(),
         }
     }
 }
@@ -97,35 +286,54 @@ where
     M: Send + 'static,
 {
     async fn publish(&self, msg: M) -> Result<(), PublishError<M>> {
-        self.sender.send(msg).await.map_err(PublishError::MpscError)
+        if self.overflow == OverflowPolicy::Error && self.sender.is_full() {
+            self.metrics.record_rejected();
+            return Err(PublishError::Overflow(msg));
+        }
+        publish_bounded(&self.sender, &self.receiver, msg, self.overflow, &self.metrics)
+            .await
+            .map_err(PublishError::MpscError)
     }
 
     fn subscribe(&self) -> Subscriber<M> {
-        let mut guard = self
-            .receiver
-            .lock()
-            .expect("mpsc: lock failed, poisoned receiver mutex!");
+        if self.subscribed.swap(true, Ordering::SeqCst) {
+            panic!("mpsc: subscribe() called more than once");
+        }
+        Subscriber::Mpsc(self.receiver.clone())
+    }
 
-        Subscriber::Mpsc(
-            guard
-                .take()
-                .expect("mpsc: subscribe() called more than once"),
-        )
+    fn len(&self) -> usize {
+        self.sender.len()
+    }
+
+    fn capacity(&self) -> Option<usize> {
+        self.sender.capacity()
     }
 }
 
 /// Broacast channel / fan-out channel (at-most-once)
 pub struct BroadcastChannel<M> {
     sender: broadcast::Sender<M>,
+    /// Signalled when a subscriber receives or leaves, and there may be room again
+    received: Arc<Notify>,
+    capacity: usize,
+    overflow: OverflowPolicy,
+    metrics: ChannelMetrics,
 }
 
 impl<M> BroadcastChannel<M>
 where
     M: Clone + Send + Sync + 'static,
 {
-    pub fn new(capacity: usize) -> Self {
+    pub fn new(capacity: usize, overflow: OverflowPolicy) -> Self {
         let (sender, _receiver) = broadcast::channel(capacity);
-        Self { sender }
+        Self {
+            sender,
+            received: Arc::new(Notify::new()),
+            capacity,
+            overflow,
+            metrics: ChannelMetrics::default(),
+        }
     }
 }
 
@@ -135,14 +343,85 @@ where
     M: Clone + Send + Sync + 'static,
 {
     async fn publish(&self, msg: M) -> Result<(), PublishError<M>> {
+        // `len` counts messages not yet seen by the slowest subscriber
+        match self.overflow {
+            OverflowPolicy::Block => loop {
+                let received = self.received.notified();
+                if self.sender.len() < self.capacity {
+                    break;
+                }
+                received.await;
+            },
+            OverflowPolicy::DropOldest => {
+                // Broadcast storage rounds up to a power of two before overwriting
+                if self.sender.len() >= self.capacity.next_power_of_two() {
+                    self.metrics.record_dropped();
+                }
+            }
+            OverflowPolicy::DropNewest if self.sender.len() >= self.capacity => {
+                self.metrics.record_dropped();
+                return Ok(());
+            }
+            OverflowPolicy::Error if self.sender.len() >= self.capacity => {
+                self.metrics.record_rejected();
+                return Err(PublishError::Overflow(msg));
+            }
+            OverflowPolicy::DropNewest | OverflowPolicy::Error => {}
+        }
+
         self.sender
             .send(msg)
-            .map(|_| ())
+            .map(|_| self.metrics.record_published())
             .map_err(PublishError::BroadcastError)
     }
 
     fn subscribe(&self) -> Subscriber<M> {
-        Subscriber::Broadcast(self.sender.subscribe())
+        Subscriber::Broadcast(BroadcastReceiver {
+            receiver: self.sender.subscribe(),
+            received: ReceivedSignal(Arc::clone(&self.received)),
+        })
+    }
+
+    fn len(&self) -> usize {
+        self.sender.len()
+    }
+
+    fn capacity(&self) -> Option<usize> {
+        Some(self.capacity)
+    }
+}
+
+/// Receiving end of a broadcast channel, waking publishers blocked on a full
+/// channel as it catches up.
+pub struct BroadcastReceiver<M> {
+    receiver: broadcast::Receiver<M>,
+    /// Declared after the receiver so that it is dropped after it, once the
+    /// messages this subscriber had not seen are released
+    received: ReceivedSignal,
+}
+
+impl<M: Clone> BroadcastReceiver<M> {
+    async fn recv(&mut self) -> Result<M, broadcast::error::RecvError> {
+        let result = self.receiver.recv().await;
+        self.received.0.notify_waiters();
+        result
+    }
+
+    fn try_recv(&mut self) -> Result<M, broadcast::error::TryRecvError> {
+        let result = self.receiver.try_recv();
+        if !matches!(result, Err(broadcast::error::TryRecvError::Empty)) {
+            self.received.0.notify_waiters();
+        }
+        result
+    }
+}
+
+/// Wakes publishers waiting for room when a broadcast subscriber leaves.
+struct ReceivedSignal(Arc<Notify>);
+
+impl Drop for ReceivedSignal {
+    fn drop(&mut self) {
+        self.0.notify_waiters();
     }
 }
 
@@ -150,12 +429,19 @@ where
 pub struct FlumeChannel<M> {
     sender: flume::Sender<M>,
     receiver: flume::Receiver<M>,
+    overflow: OverflowPolicy,
+    metrics: ChannelMetrics,
 }
 
 impl<M> FlumeChannel<M> {
-    pub fn new(capacity: usize) -> Self {
+    pub fn new(capacity: usize, overflow: OverflowPolicy) -> Self {
         let (sender, receiver) = flume::bounded(capacity);
-        Self { sender, receiver }
+        Self {
+            sender,
+            receiver,
+            overflow,
+            metrics: ChannelMetrics::default(),
+        }
     }
 }
 
@@ -165,8 +451,11 @@ where
     M: Send + 'static,
 {
     async fn publish(&self, msg: M) -> Result<(), PublishError<M>> {
-        self.sender
-            .send_async(msg)
+        if self.overflow == OverflowPolicy::Error && self.sender.is_full() {
+            self.metrics.record_rejected();
+            return Err(PublishError::Overflow(msg));
+        }
+        publish_bounded(&self.sender, &self.receiver, msg, self.overflow, &self.metrics)
             .await
             .map_err(PublishError::FlumeError)
     }
@@ -174,20 +463,33 @@ where
     fn subscribe(&self) -> Subscriber<M> {
         Subscriber::Flume(self.receiver.clone())
     }
+
+    fn len(&self) -> usize {
+        self.sender.len()
+    }
+
+    fn capacity(&self) -> Option<usize> {
+        self.sender.capacity()
+    }
 }
 
 /// Fanout channel / reliable fan-out channel (at-least-once)
 pub struct FanoutChannel<M> {
     capacity: usize,
-    senders: std::sync::Mutex<Vec<mpsc::Sender<M>>>//tokio::sync::Mutex<Vec<mpsc::Sender<M>>>,
+    overflow: OverflowPolicy,
+    metrics: ChannelMetrics,
+    /// One queue per subscriber; the channel keeps a receiver on each to
+    /// evict the oldest message under `drop_oldest`
+    queues: std::sync::Mutex<Vec<(flume::Sender<M>, flume::Receiver<M>)>>,
 }
 
 impl<M> FanoutChannel<M> {
-    pub fn new(capacity: usize) -> Self {
+    pub fn new(capacity: usize, overflow: OverflowPolicy) -> Self {
         Self {
             capacity,
-            senders: std::sync::Mutex::new(Vec::new()),
-            // senders: tokio::sync::Mutex::new(Vec::new()),
+            overflow,
+            metrics: ChannelMetrics::default(),
+            queues: std::sync::Mutex::new(Vec::new()),
         }
     }
 }
@@ -198,48 +500,354 @@ where
     M: Clone + Send + 'static,
 {
     async fn publish(&self, msg: M) -> Result<(), PublishError<M>> {
-        // Lock the senders vector to ensure thread safety
-        let senders = {
-            let guard = self.senders.lock().unwrap();
+        // Drop queues whose subscriber is gone (only our own receiver remains)
+        let queues = {
+            let mut guard = self.queues.lock().unwrap();
+            let total_count = guard.len();
+            guard.retain(|(sender, _)| sender.receiver_count() > 1);
+
+            if guard.is_empty() && total_count > 0 {
+                return Err(PublishError::FanoutError(flume::SendError(msg)));
+            }
             guard.clone()
         };
 
-        let mut failed_count = 0;
-        let total_count = senders.len();
-
-        for sender in &senders {
-            if sender.send(msg.clone()).await.is_err() {
-                failed_count += 1;
-            }
+        // Refuse the message outright rather than deliver it to some subscribers
+        if self.overflow == OverflowPolicy::Error && queues.iter().any(|(sender, _)| sender.is_full()) {
+            self.metrics.record_rejected();
+            return Err(PublishError::Overflow(msg));
         }
 
-        // Clean up dead senders if any failed
-        if failed_count > 0 {
-            let mut guard = self.senders.lock().unwrap();
-            guard.retain(|sender| !sender.is_closed());
-            
-            if failed_count == total_count && total_count > 0 {
-                return Err(PublishError::FanoutError(
-                    mpsc::error::SendError(msg) // Return the original message
-                ));
-            }
+        for (sender, evict) in &queues {
+            let _ = publish_bounded(sender, evict, msg.clone(), self.overflow, &self.metrics).await;
         }
 
         Ok(())
     }
 
     fn subscribe(&self) -> Subscriber<M> {
-        let (sender, receiver) = mpsc::channel(self.capacity);
+        let (sender, receiver) = flume::bounded(self.capacity);
 
         // Goddamn, using tokio Mutex in a sync function wasn't the greateast of ideas
         // Switched to std::sync::Mutex - should work well in a sync environment
         {
-            let mut guard = self.senders.lock().unwrap();
-            guard.push(sender);
+            let mut guard = self.queues.lock().unwrap();
+            guard.push((sender, receiver.clone()));
         }
-        
+
         Subscriber::Fanout(receiver)
     }
+
+    fn len(&self) -> usize {
+        let queues = self.queues.lock().unwrap();
+        queues.iter().map(|(sender, _)| sender.len()).max().unwrap_or(0)
+    }
+
+    fn capacity(&self) -> Option<usize> {
+        Some(self.capacity)
+    }
+}
+
+/// Persistent channel / durable shared queue (at-least-once, survives restarts)
+pub struct PersistentChannel<M> {
+    queue: Arc<Mutex<DiskQueue>>,
+    notify: Arc<Notify>,
+    metrics: ChannelMetrics,
+    _marker: PhantomData<fn(M) -> M>,
+}
+
+impl<M> PersistentChannel<M> {
+    pub fn open(directory: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let queue = DiskQueue::open(directory)?;
+        if !queue.is_empty() {
+            tracing::info!("Persistent channel resuming with {} queued messages", queue.len());
+        }
+        Ok(Self {
+            queue: Arc::new(Mutex::new(queue)),
+            notify: Arc::new(Notify::new()),
+            metrics: ChannelMetrics::default(),
+            _marker: PhantomData,
+        })
+    }
+}
+
+#[async_trait]
+impl<M> PubSubChannel<M> for PersistentChannel<M>
+where
+    M: Serialize + DeserializeOwned + Send + 'static,
+{
+    async fn publish(&self, msg: M) -> Result<(), PublishError<M>> {
+        let record = serde_json::to_vec(&msg).map_err(|e| PublishError::PersistentError(e.into()))?;
+        self.queue
+            .lock()
+            .expect("persistent: lock failed, poisoned queue mutex!")
+            .push(&record)
+            .map_err(PublishError::PersistentError)?;
+        self.metrics.record_published();
+        self.notify.notify_one();
+        Ok(())
+    }
+
+    fn subscribe(&self) -> Subscriber<M> {
+        Subscriber::Persistent(PersistentReceiver {
+            queue: Arc::clone(&self.queue),
+            notify: Arc::clone(&self.notify),
+            _marker: PhantomData,
+        })
+    }
+
+    fn len(&self) -> usize {
+        self.queue
+            .lock()
+            .expect("persistent: lock failed, poisoned queue mutex!")
+            .len()
+    }
+
+    fn capacity(&self) -> Option<usize> {
+        None
+    }
+}
+
+/// Receiving end of a persistent channel; all receivers share one queue.
+pub struct PersistentReceiver<M> {
+    queue: Arc<Mutex<DiskQueue>>,
+    notify: Arc<Notify>,
+    _marker: PhantomData<fn(M) -> M>,
+}
+
+impl<M> PersistentReceiver<M>
+where
+    M: DeserializeOwned,
+{
+    async fn recv(&mut self) -> Option<M> {
+        loop {
+            match self.pop() {
+                Ok(Some(msg)) => return Some(msg),
+                Ok(None) => self.notify.notified().await,
+                Err(e) => {
+                    tracing::error!("persistent: failed to read queue: {}", e);
+                    return None;
+                }
+            }
+        }
+    }
+
+    fn try_recv(&mut self) -> Option<M> {
+        self.pop().unwrap_or_else(|e| {
+            tracing::error!("persistent: failed to read queue: {}", e);
+            None
+        })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.queue
+            .lock()
+            .expect("persistent: lock failed, poisoned queue mutex!")
+            .is_empty()
+    }
+
+    fn len(&self) -> usize {
+        self.queue
+            .lock()
+            .expect("persistent: lock failed, poisoned queue mutex!")
+            .len()
+    }
+
+    /// Take the next decodable message, skipping corrupt records.
+    fn pop(&mut self) -> anyhow::Result<Option<M>> {
+        let mut queue = self
+            .queue
+            .lock()
+            .expect("persistent: lock failed, poisoned queue mutex!");
+
+        while let Some(record) = queue.pop()? {
+            match serde_json::from_slice(&record) {
+                Ok(msg) => return Ok(Some(msg)),
+                Err(e) => tracing::warn!("persistent: skipping undecodable record: {}", e),
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Messages of a priority channel, queued per priority level.
+struct PriorityQueue<M> {
+    /// Messages by priority, each level in publishing order with a sequence
+    /// number; empty levels are removed
+    levels: BTreeMap<u8, VecDeque<(u64, M)>>,
+    len: usize,
+    next_seq: u64,
+    /// Messages taken in a row from the top level while lower levels waited
+    burst: usize,
+    max_burst: usize,
+}
+
+impl<M> PriorityQueue<M> {
+    fn push(&mut self, priority: u8, msg: M) {
+        self.next_seq += 1;
+        self.levels.entry(priority).or_default().push_back((self.next_seq, msg));
+        self.len += 1;
+    }
+
+    /// Take the highest-priority message, or the oldest message once the top
+    /// level has been served `max_burst` times in a row.
+    fn pop(&mut self) -> Option<M> {
+        let top = *self.levels.keys().next_back()?;
+        let waiting = self.levels.len() > 1;
+        let level = if waiting && self.burst >= self.max_burst {
+            self.burst = 0;
+            self.levels
+                .iter()
+                .min_by_key(|(_, queue)| queue.front().map(|(seq, _)| *seq))
+                .map(|(level, _)| *level)?
+        } else {
+            self.burst = if waiting { self.burst + 1 } else { 0 };
+            top
+        };
+        self.take(level)
+    }
+
+    /// Discard the oldest message of the lowest priority.
+    fn evict(&mut self) -> Option<M> {
+        let lowest = *self.levels.keys().next()?;
+        self.take(lowest)
+    }
+
+    fn take(&mut self, level: u8) -> Option<M> {
+        let queue = self.levels.get_mut(&level)?;
+        let (_, msg) = queue.pop_front()?;
+        if queue.is_empty() {
+            self.levels.remove(&level);
+        }
+        self.len -= 1;
+        Some(msg)
+    }
+}
+
+/// Bounded queue delivering higher-priority messages first. Consumers share
+/// the queue, as with `Shared`.
+pub struct PriorityChannel<M> {
+    queue: Arc<Mutex<PriorityQueue<M>>>,
+    /// Signalled when a message is published
+    published: Arc<Notify>,
+    /// Signalled when a message is received and there is room again
+    received: Arc<Notify>,
+    capacity: usize,
+    overflow: OverflowPolicy,
+    priority_field: Option<String>,
+    metrics: ChannelMetrics,
+}
+
+impl<M> PriorityChannel<M> {
+    pub fn new(capacity: usize, overflow: OverflowPolicy, priority_field: Option<String>, max_burst: usize) -> Self {
+        Self {
+            queue: Arc::new(Mutex::new(PriorityQueue {
+                levels: BTreeMap::new(),
+                len: 0,
+                next_seq: 0,
+                burst: 0,
+                max_burst,
+            })),
+            published: Arc::new(Notify::new()),
+            received: Arc::new(Notify::new()),
+            capacity,
+            overflow,
+            priority_field,
+            metrics: ChannelMetrics::default(),
+        }
+    }
+
+    fn queue(&self) -> std::sync::MutexGuard<'_, PriorityQueue<M>> {
+        self.queue.lock().expect("priority: lock failed, poisoned queue mutex!")
+    }
+}
+
+#[async_trait]
+impl<M> PubSubChannel<M> for PriorityChannel<M>
+where
+    M: Prioritised + Send + 'static,
+{
+    async fn publish(&self, msg: M) -> Result<(), PublishError<M>> {
+        let priority = msg.priority(self.priority_field.as_deref());
+        loop {
+            let received = self.received.notified();
+            {
+                let mut queue = self.queue();
+                if queue.len >= self.capacity {
+                    match self.overflow {
+                        OverflowPolicy::Block => {}
+                        OverflowPolicy::DropOldest => {
+                            // Bulk data goes before anything more urgent
+                            queue.evict();
+                            self.metrics.record_dropped();
+                        }
+                        OverflowPolicy::DropNewest => {
+                            self.metrics.record_dropped();
+                            return Ok(());
+                        }
+                        OverflowPolicy::Error => {
+                            self.metrics.record_rejected();
+                            return Err(PublishError::Overflow(msg));
+                        }
+                    }
+                }
+                if queue.len < self.capacity {
+                    queue.push(priority, msg);
+                    break;
+                }
+            }
+            received.await;
+        }
+
+        self.metrics.record_published();
+        self.published.notify_one();
+        Ok(())
+    }
+
+    fn subscribe(&self) -> Subscriber<M> {
+        Subscriber::Priority(PriorityReceiver {
+            queue: Arc::clone(&self.queue),
+            published: Arc::clone(&self.published),
+            received: Arc::clone(&self.received),
+        })
+    }
+
+    fn len(&self) -> usize {
+        self.queue().len
+    }
+
+    fn capacity(&self) -> Option<usize> {
+        Some(self.capacity)
+    }
+}
+
+/// Receiving end of a priority channel; all receivers share one queue.
+pub struct PriorityReceiver<M> {
+    queue: Arc<Mutex<PriorityQueue<M>>>,
+    published: Arc<Notify>,
+    received: Arc<Notify>,
+}
+
+impl<M> PriorityReceiver<M> {
+    async fn recv(&mut self) -> M {
+        loop {
+            let published = self.published.notified();
+            if let Some(msg) = self.try_recv() {
+                return msg;
+            }
+            published.await;
+        }
+    }
+
+    fn try_recv(&self) -> Option<M> {
+        let msg = self.queue.lock().expect("priority: lock failed, poisoned queue mutex!").pop()?;
+        self.received.notify_waiters();
+        Some(msg)
+    }
+
+    fn len(&self) -> usize {
+        self.queue.lock().expect("priority: lock failed, poisoned queue mutex!").len
+    }
 }
 
 // Enum wrapper for different channel types
@@ -248,26 +856,65 @@ pub enum Channel<M> {
     Mpsc(MpscChannel<M>),
     Flume(FlumeChannel<M>),
     Fanout(FanoutChannel<M>),
+    Persistent(PersistentChannel<M>),
+    Priority(PriorityChannel<M>),
 }
 
 impl<M> Channel<M>
 where
-    M: Clone + Send + Sync + 'static,
+    M: Clone + Prioritised + Send + Sync + 'static,
 {
-    pub fn new(kind: ChannelType, capacity: usize) -> Self {
-        match kind {
-            ChannelType::Broadcast => Channel::Broadcast(BroadcastChannel::new(capacity)),
-            ChannelType::Direct => Channel::Mpsc(MpscChannel::new(capacity)),
-            ChannelType::Shared => Channel::Flume(FlumeChannel::new(capacity)),
-            ChannelType::Fanout => Channel::Fanout(FanoutChannel::new(capacity)),
-        }
+    /// Create the channel named `name` as described by its configuration.
+    /// Persistent channels keep their queue in `<path>/<name>`.
+    pub fn open(name: &str, config: &ChannelConfig) -> anyhow::Result<Self> {
+        let capacity = config.capacity;
+        let overflow = config.overflow_policy();
+        Ok(match config.r#type {
+            ChannelType::Broadcast => Channel::Broadcast(BroadcastChannel::new(capacity, overflow)),
+            ChannelType::Direct => Channel::Mpsc(MpscChannel::new(capacity, overflow)),
+            ChannelType::Shared => Channel::Flume(FlumeChannel::new(capacity, overflow)),
+            ChannelType::Fanout => Channel::Fanout(FanoutChannel::new(capacity, overflow)),
+            ChannelType::Persistent => {
+                let path = config.path.as_ref().ok_or_else(|| {
+                    anyhow::anyhow!("Persistent channel '{}' requires a path", name)
+                })?;
+                Channel::Persistent(PersistentChannel::open(Path::new(path).join(name))?)
+            }
+            ChannelType::Priority => Channel::Priority(PriorityChannel::new(
+                capacity,
+                overflow,
+                config.priority_field.clone(),
+                config.max_burst,
+            )),
+        })
+    }
+
+    /// Current message counters and occupancy of the channel.
+    pub fn stats(&self) -> ChannelStats {
+        let (stats, depth, capacity) = match self {
+            Channel::Broadcast(bc) => (bc.metrics.stats(), bc.sender.len(), Some(bc.capacity)),
+            Channel::Mpsc(mc) => (mc.metrics.stats(), mc.sender.len(), mc.sender.capacity()),
+            Channel::Flume(fc) => (fc.metrics.stats(), fc.sender.len(), fc.sender.capacity()),
+            Channel::Fanout(fc) => {
+                let queues = fc.queues.lock().unwrap();
+                let depth = queues.iter().map(|(sender, _)| sender.len()).max().unwrap_or(0);
+                (fc.metrics.stats(), depth, Some(fc.capacity))
+            }
+            Channel::Persistent(pc) => (
+                pc.metrics.stats(),
+                pc.queue.lock().expect("persistent: lock failed, poisoned queue mutex!").len(),
+                None,
+            ),
+            Channel::Priority(pc) => (pc.metrics.stats(), pc.queue().len, Some(pc.capacity)),
+        };
+        ChannelStats { depth, capacity, ..stats }
     }
 }
 
 #[async_trait]
 impl<M> PubSubChannel<M> for Channel<M>
 where
-    M: Clone + Send + Sync + 'static,
+    M: Clone + Prioritised + Serialize + DeserializeOwned + Send + Sync + 'static,
 {
     async fn publish(&self, msg: M) -> Result<(), PublishError<M>> {
         match self {
@@ -275,6 +922,8 @@ where
             Channel::Mpsc(mc) => mc.publish(msg).await,
             Channel::Flume(fc) => fc.publish(msg).await,
             Channel::Fanout(fc) => fc.publish(msg).await,
+            Channel::Persistent(pc) => pc.publish(msg).await,
+            Channel::Priority(pc) => pc.publish(msg).await,
         }
     }
 
@@ -284,6 +933,137 @@ where
             Channel::Mpsc(mc) => mc.subscribe(),
             Channel::Flume(fc) => fc.subscribe(),
             Channel::Fanout(fc) => fc.subscribe(),
+            Channel::Persistent(pc) => pc.subscribe(),
+            Channel::Priority(pc) => pc.subscribe(),
         }
     }
+
+    fn len(&self) -> usize {
+        match self {
+            Channel::Broadcast(bc) => bc.len(),
+            Channel::Mpsc(mc) => mc.len(),
+            Channel::Flume(fc) => fc.len(),
+            Channel::Fanout(fc) => fc.len(),
+            Channel::Persistent(pc) => pc.len(),
+            Channel::Priority(pc) => pc.len(),
+        }
+    }
+
+    fn capacity(&self) -> Option<usize> {
+        match self {
+            Channel::Broadcast(bc) => bc.capacity(),
+            Channel::Mpsc(mc) => mc.capacity(),
+            Channel::Flume(fc) => fc.capacity(),
+            Channel::Fanout(fc) => fc.capacity(),
+            Channel::Persistent(pc) => pc.capacity(),
+            Channel::Priority(pc) => pc.capacity(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+
+    /// Test messages are their own priority
+    impl Prioritised for i32 {
+        fn priority(&self, _field: Option<&str>) -> u8 {
+            *self as u8
+        }
+    }
+
+    fn config(r#type: ChannelType, overflow: OverflowPolicy) -> ChannelConfig {
+        ChannelConfig {
+            r#type,
+            capacity: 2,
+            overflow: Some(overflow),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_overflow_policies_are_consistent() {
+        for r#type in [ChannelType::Broadcast, ChannelType::Direct, ChannelType::Shared, ChannelType::Fanout] {
+            let channel = Channel::<i32>::open("test", &config(r#type.clone(), OverflowPolicy::DropOldest)).unwrap();
+            let mut subscriber = channel.subscribe();
+            for i in 0..3 {
+                block_on(channel.publish(i)).unwrap();
+            }
+            assert_eq!(channel.stats().dropped, 1, "{:?}", r#type);
+            // Broadcast subscribers are told how many messages they missed
+            if r#type == ChannelType::Broadcast {
+                assert_eq!(block_on(subscriber.try_recv()), RecvResult::Lagged(1));
+            }
+            assert_eq!(block_on(subscriber.try_recv()), RecvResult::Message(1), "{:?}", r#type);
+
+            let channel = Channel::<i32>::open("test", &config(r#type.clone(), OverflowPolicy::DropNewest)).unwrap();
+            let mut subscriber = channel.subscribe();
+            for i in 0..3 {
+                block_on(channel.publish(i)).unwrap();
+            }
+            assert_eq!(block_on(subscriber.try_recv()), RecvResult::Message(0), "{:?}", r#type);
+            assert_eq!(
+                channel.stats(),
+                ChannelStats { published: 2, dropped: 1, rejected: 0, depth: 1, capacity: Some(2) },
+                "{:?}",
+                r#type
+            );
+
+            let channel = Channel::<i32>::open("test", &config(r#type.clone(), OverflowPolicy::Error)).unwrap();
+            let _subscriber = channel.subscribe();
+            block_on(channel.publish(0)).unwrap();
+            block_on(channel.publish(1)).unwrap();
+            assert!(matches!(block_on(channel.publish(2)), Err(PublishError::Overflow(2))), "{:?}", r#type);
+            assert_eq!(channel.stats().rejected, 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_blocked_broadcast_publish_waits_for_subscribers() {
+        let channel = Arc::new(Channel::<i32>::open("test", &config(ChannelType::Broadcast, OverflowPolicy::Block)).unwrap());
+        let mut subscriber = channel.subscribe();
+        let mut lagging = channel.subscribe();
+        channel.publish(0).await.unwrap();
+        channel.publish(1).await.unwrap();
+
+        let publisher = Arc::clone(&channel);
+        let blocked = tokio::spawn(async move { publisher.publish(2).await.is_ok() });
+        tokio::task::yield_now().await;
+        assert!(!blocked.is_finished());
+
+        // One subscriber catching up leaves the other holding the channel full
+        assert_eq!(subscriber.recv().await, RecvResult::Message(0));
+        assert_eq!(lagging.recv().await, RecvResult::Message(0));
+        assert!(tokio::time::timeout(std::time::Duration::from_secs(5), blocked).await.unwrap().unwrap());
+
+        let publisher = Arc::clone(&channel);
+        let blocked = tokio::spawn(async move { publisher.publish(3).await.is_ok() });
+        tokio::task::yield_now().await;
+        assert_eq!(subscriber.recv().await, RecvResult::Message(1));
+        assert!(!blocked.is_finished());
+        drop(lagging);
+        assert!(tokio::time::timeout(std::time::Duration::from_secs(5), blocked).await.unwrap().unwrap());
+    }
+
+    #[test]
+    fn test_priority_channel_overtakes_without_starving() {
+        let config = ChannelConfig {
+            r#type: ChannelType::Priority,
+            capacity: 8,
+            max_burst: 2,
+            ..Default::default()
+        };
+        let channel = Channel::<i32>::open("test", &config).unwrap();
+        let mut subscriber = channel.subscribe();
+        for msg in [0, 0, 9, 9, 9, 5] {
+            block_on(channel.publish(msg)).unwrap();
+        }
+
+        // Two urgent messages, then the oldest waiting, then by priority again
+        let received: Vec<i32> = (0..6)
+            .map(|_| block_on(subscriber.try_recv()).into_message().unwrap())
+            .collect();
+        assert_eq!(received, [9, 9, 0, 9, 5, 0]);
+    }
 }
@@ -0,0 +1,122 @@
+//! Checkpointing
+//!
+//! Processors that keep state across messages (windows, counters, filter state,
+//! source offsets) implement [`Snapshot`] so that their state survives a
+//! restart. Each stage writes its processor's snapshot to
+//! `<directory>/<stage>.json` at the configured interval and on shutdown, and
+//! `PipelineManager` restores it before the stage is initialised.
+//!
+//! Checkpoint files are written to a temporary file and renamed into place, so
+//! a crash mid-write leaves the previous checkpoint intact.
+
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Recoverable processor state.
+pub trait Snapshot {
+    /// Capture the processor's current state.
+    fn snapshot(&self) -> Result<Value>;
+
+    /// Replace the processor's state with a previously captured snapshot.
+    fn restore(&mut self, state: Value) -> Result<()>;
+}
+
+/// On-disk layout of a stage checkpoint.
+#[derive(Debug, Serialize, Deserialize)]
+struct Checkpoint {
+    stage: String,
+    taken_at_ms: u64,
+    state: Value,
+}
+
+/// Directory of per-stage checkpoint files.
+#[derive(Debug)]
+pub struct CheckpointStore {
+    directory: PathBuf,
+}
+
+impl CheckpointStore {
+    /// Open the checkpoint directory, creating it if needed.
+    pub fn open(directory: impl AsRef<Path>) -> Result<Self> {
+        let directory = directory.as_ref().to_path_buf();
+        std::fs::create_dir_all(&directory).with_context(|| {
+            format!("Failed to create checkpoint directory '{}'", directory.display())
+        })?;
+        Ok(Self { directory })
+    }
+
+    fn path(&self, stage: &str) -> PathBuf {
+        // Stage names come from config keys; keep them filesystem-safe
+        let file_name: String = stage
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+            .collect();
+        self.directory.join(format!("{}.json", file_name))
+    }
+
+    /// Write the checkpoint for a stage, replacing any previous one.
+    pub fn save(&self, stage: &str, state: Value) -> Result<()> {
+        let checkpoint = Checkpoint {
+            stage: stage.to_string(),
+            taken_at_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or(0),
+            state,
+        };
+
+        let path = self.path(stage);
+        let temp_path = path.with_extension("json.tmp");
+        std::fs::write(&temp_path, serde_json::to_vec(&checkpoint)?)?;
+        std::fs::rename(&temp_path, &path)?;
+        Ok(())
+    }
+
+    /// Read the last checkpoint for a stage, if one exists.
+    pub fn load(&self, stage: &str) -> Result<Option<Value>> {
+        let path = self.path(stage);
+        let contents = match std::fs::read(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(anyhow!("Failed to read checkpoint '{}': {}", path.display(), e)),
+        };
+
+        let checkpoint: Checkpoint = serde_json::from_slice(&contents)
+            .with_context(|| format!("Failed to parse checkpoint '{}'", path.display()))?;
+        if checkpoint.stage != stage {
+            return Err(anyhow!(
+                "Checkpoint '{}' belongs to stage '{}', not '{}'",
+                path.display(),
+                checkpoint.stage,
+                stage
+            ));
+        }
+        Ok(Some(checkpoint.state))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_checkpoint_store_roundtrip() {
+        let directory = std::env::temp_dir().join(format!("liminal-checkpoint-{}", std::process::id()));
+        let store = CheckpointStore::open(&directory).unwrap();
+
+        assert!(store.load("counter").unwrap().is_none());
+        store.save("counter", json!({"count": 1})).unwrap();
+        store.save("counter", json!({"count": 2})).unwrap();
+        assert_eq!(store.load("counter").unwrap(), Some(json!({"count": 2})));
+
+        // Names that are not filesystem-safe must not collide with the original
+        store.save("a/b", json!(1)).unwrap();
+        assert!(store.load("a_b").is_err());
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
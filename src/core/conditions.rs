@@ -0,0 +1,115 @@
+//! Conditions
+//!
+//! System conditions that stages are paused on, for store-and-forward at the
+//! edge: a file sink paused while its disk is nearly full, a network sink
+//! paused while the uplink is down. Each condition of the `[conditions]`
+//! section is probed on its own task, and changes are reported to the
+//! pipeline manager, which pauses and resumes the stages listing the
+//! condition in their `pause_when`.
+//!
+//! A paused stage stops receiving, so its input queues up on its channels;
+//! a `persistent` input channel keeps that backlog on disk until the stage
+//! resumes and forwards it.
+
+use crate::config::types::{ConditionConfig, ConditionProbe};
+
+use anyhow::{Result, anyhow};
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// A condition that started or stopped holding.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConditionChange {
+    pub name: String,
+    pub holds: bool,
+}
+
+/// Start probing every condition, reporting each change (and the initial
+/// state of conditions that hold from the start) on the returned channel.
+pub fn watch(conditions: &HashMap<String, ConditionConfig>) -> mpsc::UnboundedReceiver<ConditionChange> {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    for (name, condition) in conditions {
+        let name = name.clone();
+        let condition = condition.clone();
+        let sender = sender.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_millis(condition.interval_ms));
+            let mut holding = false;
+            loop {
+                interval.tick().await;
+                let holds = match probe(&condition.probe).await {
+                    Ok(holds) => holds,
+                    Err(e) => {
+                        // Keep the last known state rather than flapping
+                        tracing::warn!("Failed to probe condition '{}': {}", name, e);
+                        continue;
+                    }
+                };
+                if holds == holding {
+                    continue;
+                }
+
+                holding = holds;
+                if sender.send(ConditionChange { name: name.clone(), holds }).is_err() {
+                    return;
+                }
+            }
+        });
+    }
+    receiver
+}
+
+/// Check whether a condition holds now.
+pub async fn probe(probe: &ConditionProbe) -> Result<bool> {
+    match probe {
+        ConditionProbe::DiskUsage { path, max_usage } => Ok(disk_usage(path)? > *max_usage),
+        ConditionProbe::TcpUnreachable { address, timeout_ms } => {
+            let connect = tokio::net::TcpStream::connect(address.as_str());
+            let connected = tokio::time::timeout(Duration::from_millis(*timeout_ms), connect).await;
+            Ok(!matches!(connected, Ok(Ok(_))))
+        }
+    }
+}
+
+/// Fraction of the filesystem containing `path` in use, counting the blocks
+/// reserved for root as unavailable (as `df` does).
+pub fn disk_usage(path: &str) -> Result<f64> {
+    let c_path = CString::new(path).map_err(|_| anyhow!("Invalid path '{}'", path))?;
+    let mut stats: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: `c_path` is a valid C string and `stats` is a valid statvfs to fill
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stats) } != 0 {
+        return Err(anyhow!("Failed to read filesystem usage of '{}': {}", path, std::io::Error::last_os_error()));
+    }
+
+    let used = stats.f_blocks.saturating_sub(stats.f_bfree) as f64;
+    let available = stats.f_bavail as f64;
+    if used + available == 0.0 {
+        return Ok(0.0);
+    }
+    Ok(used / (used + available))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_condition_probes() {
+        let usage = disk_usage(".").unwrap();
+        assert!((0.0..=1.0).contains(&usage));
+        let full = ConditionProbe::DiskUsage { path: ".".to_string(), max_usage: 1.0 };
+        assert!(!probe(&full).await.unwrap());
+        let missing = ConditionProbe::DiskUsage { path: "/no/such/path".to_string(), max_usage: 0.5 };
+        assert!(probe(&missing).await.is_err());
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let up = ConditionProbe::TcpUnreachable { address: address.clone(), timeout_ms: 1000 };
+        assert!(!probe(&up).await.unwrap());
+        drop(listener);
+        let down = ConditionProbe::TcpUnreachable { address, timeout_ms: 1000 };
+        assert!(probe(&down).await.unwrap());
+    }
+}
@@ -1,14 +1,103 @@
 use super::channel::{PubSubChannel, Subscriber};
-use super::message::Message;
+use super::fanin::FanIn;
+use super::message::{DELIVERY_ERROR, EXPIRED_AGE_MS, Message, SLA_EXCEEDED_MS};
+use crate::config::types::{ExpiryAction, LimitAction, LimitsConfig, SlaAction, SlaConfig, TtlConfig};
 
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
 
 pub struct ProcessingContext {
     pub stage_name: String,
     pub inputs: HashMap<String, Subscriber<Message>>,
     pub output: Option<OutputInfo>,
+    pub side_outputs: HashMap<String, OutputInfo>,
     pub metadata: HashMap<String, String>,
+    fan_in: FanIn,
+    complete: bool,
+    received: ReceivedInput,
+    sla: Option<SlaConfig>,
+    ttl: Option<TtlConfig>,
+    limits: Option<LimitsConfig>,
+    /// Whether the stage has shed a message yet, to warn only the first time
+    shedding: bool,
+    delivery: DeliveryReport,
+    /// Whether the processor takes batches as they are
+    accept_batches: bool,
+    /// Rows of a received batch not yet handed to the processor
+    rows: VecDeque<(String, Message)>,
+}
+
+/// Input received since the stage last collected it, for metrics.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ReceivedInput {
+    /// Messages received
+    pub count: u64,
+    /// When the first of them was received
+    pub first_at: Option<Instant>,
+    /// Latest watermark carried by any of them
+    pub watermark: Option<SystemTime>,
+    /// Messages missed by inputs that lagged behind a broadcast channel
+    pub lagged: u64,
+    /// Messages received past their processing deadline
+    pub sla_violations: u64,
+    /// Messages shed or routed because too many were waiting on the inputs
+    pub shed: u64,
+    /// Messages older than the stage's TTL
+    pub expired: u64,
+}
+
+/// Delivery results a sink reported since the stage last collected them.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DeliveryReport {
+    /// Messages delivered to the external system
+    pub delivered: u64,
+    /// Delivery attempts retried after a failure
+    pub retried: u64,
+    /// Messages that could not be delivered
+    pub failed: u64,
+    /// Undeliverable messages published to a dead-letter output
+    pub dead_lettered: u64,
+    /// Error of the latest failure
+    pub last_error: Option<String>,
+    /// When a message was last delivered
+    pub last_success: Option<SystemTime>,
+}
+
+impl DeliveryReport {
+    /// Whether the sink reported anything.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Add the results of a later report.
+    pub fn merge(&mut self, later: &DeliveryReport) {
+        self.delivered += later.delivered;
+        self.retried += later.retried;
+        self.failed += later.failed;
+        self.dead_lettered += later.dead_lettered;
+        if later.last_error.is_some() {
+            self.last_error.clone_from(&later.last_error);
+        }
+        self.last_success = self.last_success.max(later.last_success);
+    }
+
+    /// The report as JSON, with `last_success_ms` in milliseconds since the
+    /// epoch.
+    pub fn to_json(&self) -> serde_json::Value {
+        let last_success_ms = self
+            .last_success
+            .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|since| since.as_millis() as u64);
+        serde_json::json!({
+            "delivered": self.delivered,
+            "retried": self.retried,
+            "failed": self.failed,
+            "dead_lettered": self.dead_lettered,
+            "last_error": self.last_error,
+            "last_success_ms": last_success_ms,
+        })
+    }
 }
 
 pub struct OutputInfo {
@@ -22,15 +111,442 @@ impl ProcessingContext {
             stage_name,
             inputs: HashMap::new(),
             output: None,
+            side_outputs: HashMap::new(),
             metadata: HashMap::new(),
+            fan_in: FanIn::new(),
+            complete: false,
+            received: ReceivedInput::default(),
+            sla: None,
+            ttl: None,
+            limits: None,
+            shedding: false,
+            delivery: DeliveryReport::default(),
+            accept_batches: false,
+            rows: VecDeque::new(),
         }
     }
 
+    /// Enforce processing deadlines on the messages received from now on.
+    pub fn set_sla(&mut self, sla: SlaConfig) {
+        self.sla = Some(sla);
+    }
+
+    /// Expire the messages received from now on that are older than the TTL.
+    pub fn set_ttl(&mut self, ttl: TtlConfig) {
+        self.ttl = Some(ttl);
+    }
+
+    /// Shed or route the oldest input messages, according to `on_limit`,
+    /// while more than `max_in_flight` are waiting on the inputs.
+    pub fn set_limits(&mut self, limits: LimitsConfig) {
+        self.limits = Some(limits);
+    }
+
     pub fn attach_output(&mut self, name: String, channel: Arc<dyn PubSubChannel<Message>>) {
         self.output = Some(OutputInfo { channel, name });
     }
 
+    pub fn attach_side_output(&mut self, name: String, channel: Arc<dyn PubSubChannel<Message>>) {
+        self.side_outputs.insert(name.clone(), OutputInfo { channel, name });
+    }
+
     pub fn add_input(&mut self, name: String, subscriber: Subscriber<Message>) {
         self.inputs.insert(name, subscriber);
     }
-}
\ No newline at end of file
+
+    /// Receive the next message from any input, waiting up to `timeout` when
+    /// none is ready. Inputs are served round-robin so none can starve the
+    /// others. Returns the input channel name along with the message.
+    pub async fn recv(&mut self, timeout: Duration) -> Option<(String, Message)> {
+        if let Some(row) = self.rows.pop_front() {
+            return Some(row);
+        }
+        let received = self.fan_in.recv(&mut self.inputs, timeout).await;
+        let admitted = self.admit(received).await;
+        self.unbatch(admitted)
+    }
+
+    /// Take the next ready message from any input without waiting, serving
+    /// inputs round-robin.
+    pub async fn try_recv(&mut self) -> Option<(String, Message)> {
+        if let Some(row) = self.rows.pop_front() {
+            return Some(row);
+        }
+        let received = self.fan_in.try_recv(&mut self.inputs).await;
+        let admitted = self.admit(received).await;
+        self.unbatch(admitted)
+    }
+
+    /// Whether `recv` and `try_recv` return batches as they are, for
+    /// processors that accept them, rather than row by row.
+    pub fn accept_batches(&mut self, accept: bool) {
+        self.accept_batches = accept;
+    }
+
+    /// Split a received batch into rows, unless batches are accepted,
+    /// returning the first and keeping the rest for the next receives.
+    fn unbatch(&mut self, received: Option<(String, Message)>) -> Option<(String, Message)> {
+        let (input, message) = received?;
+        if self.accept_batches || message.batch.is_none() {
+            return Some((input, message));
+        }
+        self.rows.extend(message.into_rows().into_iter().map(|row| (input.clone(), row)));
+        self.rows.pop_front()
+    }
+
+    /// Account for a received message, shed it if the inputs are over their
+    /// in-flight limit, expire it if stale and enforce the SLA on it, moving
+    /// on to the next ready message when it is shed, dropped or routed away.
+    async fn admit(&mut self, mut received: Option<(String, Message)>) -> Option<(String, Message)> {
+        loop {
+            for (input, skipped) in self.fan_in.take_lagged() {
+                self.record_lag(&input, skipped);
+            }
+            let (input, message) = received?;
+            self.record_received(&message);
+            if self.over_in_flight() {
+                self.shed(message).await;
+                received = self.fan_in.try_recv(&mut self.inputs).await;
+                continue;
+            }
+            let Some(message) = self.expire(message).await else {
+                received = self.fan_in.try_recv(&mut self.inputs).await;
+                continue;
+            };
+            match self.enforce_sla(message).await {
+                Some(message) => return Some((input, message)),
+                None => received = self.fan_in.try_recv(&mut self.inputs).await,
+            }
+        }
+    }
+
+    /// Whether more messages are waiting on the inputs than `max_in_flight`.
+    fn over_in_flight(&self) -> bool {
+        self.limits
+            .as_ref()
+            .and_then(|limits| limits.max_in_flight)
+            .is_some_and(|max_in_flight| self.inputs.values().map(Subscriber::len).sum::<usize>() > max_in_flight)
+    }
+
+    /// Count a message received over the in-flight limit and discard or
+    /// route it, warning the first time the stage does.
+    async fn shed(&mut self, message: Message) {
+        let Some(limits) = &self.limits else {
+            return;
+        };
+        self.received.shed += 1;
+        if !self.shedding {
+            self.shedding = true;
+            tracing::warn!(
+                "Stage '{}' has more than {} messages waiting on its inputs and is {} the oldest",
+                self.stage_name,
+                limits.max_in_flight.unwrap_or_default(),
+                if limits.on_limit == Some(LimitAction::Route) { "routing away" } else { "shedding" }
+            );
+        }
+
+        if limits.on_limit == Some(LimitAction::Route) {
+            self.route(limits.shed_output.as_deref(), message).await;
+        }
+    }
+
+    /// How long ago a message's deadline passed, if it has. Messages without
+    /// a deadline get one `max_latency_ms` after ingestion, when that is set.
+    fn overdue(&self, message: &Message) -> Option<Duration> {
+        let sla = self.sla.as_ref()?;
+        let deadline = message.timing.processing_deadline.or_else(|| {
+            sla.max_latency_ms
+                .map(|max_latency_ms| message.timing.ingestion_time + Duration::from_millis(max_latency_ms))
+        })?;
+        SystemTime::now().duration_since(deadline).ok().filter(|overdue| !overdue.is_zero())
+    }
+
+    /// Count a message received past its deadline and act on it, returning
+    /// it if it should still be processed.
+    async fn enforce_sla(&mut self, mut message: Message) -> Option<Message> {
+        let Some(overdue) = self.overdue(&message) else {
+            return Some(message);
+        };
+        self.received.sla_violations += 1;
+        tracing::debug!("Stage '{}' received a message {:?} past its deadline", self.stage_name, overdue);
+
+        let sla = self.sla.as_ref()?;
+        match sla.on_violation {
+            SlaAction::Pass => Some(message),
+            SlaAction::Drop => None,
+            SlaAction::Route => {
+                message = message.with_metadata(SLA_EXCEEDED_MS, overdue.as_millis().to_string());
+                self.route(sla.violation_output.as_deref(), message).await;
+                None
+            }
+        }
+    }
+
+    /// Count a message older than the TTL by event time and drop or route
+    /// it, returning it if it has not expired.
+    async fn expire(&mut self, mut message: Message) -> Option<Message> {
+        let Some(ttl) = &self.ttl else {
+            return Some(message);
+        };
+        let age = SystemTime::now().duration_since(message.timing.event_time).unwrap_or_default();
+        if age <= Duration::from_millis(ttl.ttl_ms) {
+            return Some(message);
+        }
+        self.received.expired += 1;
+        tracing::debug!("Stage '{}' received a message {:?} old, past its TTL", self.stage_name, age);
+
+        if ttl.on_expiry == ExpiryAction::Route {
+            message = message.with_metadata(EXPIRED_AGE_MS, age.as_millis().to_string());
+            self.route(ttl.expired_output.as_deref(), message).await;
+        }
+        None
+    }
+
+    /// Send a message the stage will not process to one of its side outputs.
+    async fn route(&self, side_output: Option<&str>, mut message: Message) {
+        let Some(output) = side_output.and_then(|name| self.side_outputs.get(name)) else {
+            return;
+        };
+        message.topic = output.name.clone();
+        if let Err(e) = output.channel.publish(message).await {
+            tracing::warn!("Failed to route a message: {:#}", e.into_error(&output.name));
+        }
+    }
+
+    fn record_received(&mut self, message: &Message) {
+        self.received.count += 1;
+        self.received.first_at.get_or_insert_with(Instant::now);
+        if let Some(watermark) = message.timing.watermark {
+            self.received.watermark = self.received.watermark.max(Some(watermark));
+        }
+    }
+
+    /// Note that `input` lagged behind its broadcast channel and missed
+    /// `skipped` messages. `recv` and `try_recv` do this themselves; stages
+    /// receiving from `inputs` directly call it on [`RecvResult::Lagged`].
+    ///
+    /// [`RecvResult::Lagged`]: super::channel::RecvResult::Lagged
+    pub fn record_lag(&mut self, input: &str, skipped: u64) {
+        tracing::warn!(
+            "Stage '{}' lagged behind input '{}' and missed {} message(s)",
+            self.stage_name,
+            input,
+            skipped
+        );
+        self.received.lagged += skipped;
+    }
+
+    /// Input received since the last call.
+    pub fn take_received(&mut self) -> ReceivedInput {
+        std::mem::take(&mut self.received)
+    }
+
+    /// Report that a sink delivered `count` messages.
+    pub fn report_delivered(&mut self, count: u64) {
+        self.delivery.delivered += count;
+        self.delivery.last_success = Some(SystemTime::now());
+    }
+
+    /// Report that a sink is retrying a failed delivery.
+    pub fn report_retry(&mut self) {
+        self.delivery.retried += 1;
+    }
+
+    /// Report that a sink could not deliver a message.
+    pub fn report_failed(&mut self, error: impl std::fmt::Display) {
+        self.delivery.failed += 1;
+        self.delivery.last_error = Some(error.to_string());
+    }
+
+    /// Report that a sink published an undeliverable message to its
+    /// dead-letter output (after reporting it failed).
+    pub fn report_dead_lettered(&mut self) {
+        self.delivery.dead_lettered += 1;
+    }
+
+    /// Report a message a sink could not deliver as failed, and publish it to
+    /// the side output `dead_letter` (when set) tagged with the error.
+    pub async fn delivery_failed(&mut self, message: Message, dead_letter: Option<&str>, error: impl std::fmt::Display) {
+        let error = error.to_string();
+        self.report_failed(&error);
+        let Some(output) = dead_letter.and_then(|name| self.side_outputs.get(name)) else {
+            return;
+        };
+        let mut message = message.with_metadata(DELIVERY_ERROR, &error);
+        message.topic = output.name.clone();
+        match output.channel.publish(message).await {
+            Ok(_) => self.report_dead_lettered(),
+            Err(e) => tracing::warn!("Failed to dead-letter a message: {:#}", e.into_error(&output.name)),
+        }
+    }
+
+    /// Delivery results reported since the last call.
+    pub fn take_delivery(&mut self) -> DeliveryReport {
+        std::mem::take(&mut self.delivery)
+    }
+
+    /// Signal end of stream: a finite source calls this once it has produced
+    /// everything, and the stage stops after the current `process` call.
+    /// Downstream stages then drain and stop once all their inputs complete.
+    pub fn complete(&mut self) {
+        self.complete = true;
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    /// Whether any input has messages waiting to be received.
+    pub fn has_pending_input(&self) -> bool {
+        !self.rows.is_empty() || self.inputs.values().any(|input| !input.is_empty())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::types::ChannelConfig;
+    use crate::core::channel::{Channel, RecvResult};
+    use futures::executor::block_on;
+    use serde_json::json;
+
+    #[test]
+    fn test_sla_routes_messages_past_their_deadline() {
+        let input = Channel::open("readings", &ChannelConfig::default()).unwrap();
+        let late: Arc<Channel<Message>> = Arc::new(Channel::open("late", &ChannelConfig::default()).unwrap());
+        let mut late_subscriber = late.subscribe();
+
+        let mut context = ProcessingContext::new("stage".to_string());
+        context.add_input("readings".to_string(), input.subscribe());
+        context.attach_side_output("late".to_string(), late);
+        context.set_sla(SlaConfig {
+            max_latency_ms: None,
+            on_violation: SlaAction::Route,
+            violation_output: Some("late".to_string()),
+        });
+
+        let overdue = Message::new("src", "readings", json!(1))
+            .with_deadline(SystemTime::now() - Duration::from_millis(50));
+        block_on(input.publish(overdue)).unwrap();
+        block_on(input.publish(Message::new("src", "readings", json!(2)))).unwrap();
+
+        // The late message goes to the side output and the next one is processed
+        let (_, message) = block_on(context.try_recv()).unwrap();
+        assert_eq!(message.payload, json!(2));
+        assert_eq!(context.take_received().sla_violations, 1);
+
+        let RecvResult::Message(routed) = block_on(late_subscriber.try_recv()) else {
+            panic!("late message was not routed");
+        };
+        assert_eq!(routed.payload, json!(1));
+        assert!(routed.get_metadata(SLA_EXCEEDED_MS).unwrap().parse::<u64>().unwrap() >= 50);
+    }
+
+    #[test]
+    fn test_max_in_flight_sheds_oldest_input() {
+        let input = Channel::open("readings", &ChannelConfig::default()).unwrap();
+        let mut context = ProcessingContext::new("stage".to_string());
+        context.add_input("readings".to_string(), input.subscribe());
+        context.set_limits(LimitsConfig {
+            max_in_flight: Some(2),
+            on_limit: Some(LimitAction::Shed),
+            ..Default::default()
+        });
+
+        for i in 0..5 {
+            block_on(input.publish(Message::new("src", "readings", json!(i)))).unwrap();
+        }
+
+        // Messages 0 and 1 are shed, leaving two waiting behind message 2
+        let (_, message) = block_on(context.try_recv()).unwrap();
+        assert_eq!(message.payload, json!(2));
+        assert_eq!(context.take_received().shed, 2);
+        let (_, message) = block_on(context.try_recv()).unwrap();
+        assert_eq!(message.payload, json!(3));
+    }
+
+    #[test]
+    fn test_max_in_flight_routes_oldest_input() {
+        let input = Channel::open("readings", &ChannelConfig::default()).unwrap();
+        let backlog: Arc<Channel<Message>> = Arc::new(Channel::open("backlog", &ChannelConfig::default()).unwrap());
+        let mut backlog_subscriber = backlog.subscribe();
+
+        let mut context = ProcessingContext::new("stage".to_string());
+        context.add_input("readings".to_string(), input.subscribe());
+        context.attach_side_output("backlog".to_string(), backlog);
+        context.set_limits(LimitsConfig {
+            max_in_flight: Some(1),
+            on_limit: Some(LimitAction::Route),
+            shed_output: Some("backlog".to_string()),
+            ..Default::default()
+        });
+
+        for i in 0..3 {
+            block_on(input.publish(Message::new("src", "readings", json!(i)))).unwrap();
+        }
+
+        // Message 0 goes to the side output instead of being processed
+        let (_, message) = block_on(context.try_recv()).unwrap();
+        assert_eq!(message.payload, json!(1));
+        assert_eq!(context.take_received().shed, 1);
+        let RecvResult::Message(routed) = block_on(backlog_subscriber.try_recv()) else {
+            panic!("shed message was not routed");
+        };
+        assert_eq!((routed.payload, routed.topic), (json!(0), "backlog".to_string()));
+    }
+
+    #[test]
+    fn test_ttl_dead_letters_stale_messages() {
+        let input = Channel::open("readings", &ChannelConfig::default()).unwrap();
+        let stale: Arc<Channel<Message>> = Arc::new(Channel::open("stale", &ChannelConfig::default()).unwrap());
+        let mut stale_subscriber = stale.subscribe();
+
+        let mut context = ProcessingContext::new("stage".to_string());
+        context.add_input("readings".to_string(), input.subscribe());
+        context.attach_side_output("stale".to_string(), stale);
+        context.set_ttl(TtlConfig {
+            ttl_ms: 60_000,
+            on_expiry: ExpiryAction::Route,
+            expired_output: Some("stale".to_string()),
+        });
+
+        let mut old = Message::new("src", "readings", json!(1));
+        old.timing.event_time = SystemTime::now() - Duration::from_secs(3600);
+        block_on(input.publish(old)).unwrap();
+        block_on(input.publish(Message::new("src", "readings", json!(2)))).unwrap();
+
+        let (_, message) = block_on(context.try_recv()).unwrap();
+        assert_eq!(message.payload, json!(2));
+        assert_eq!(context.take_received().expired, 1);
+
+        let RecvResult::Message(routed) = block_on(stale_subscriber.try_recv()) else {
+            panic!("expired message was not routed");
+        };
+        assert!(routed.get_metadata(EXPIRED_AGE_MS).unwrap().parse::<u64>().unwrap() >= 3_600_000);
+    }
+
+    #[test]
+    fn test_delivery_results_accumulate_and_dead_letter() {
+        let undelivered: Arc<Channel<Message>> = Arc::new(Channel::open("undelivered", &ChannelConfig::default()).unwrap());
+        let mut undelivered_subscriber = undelivered.subscribe();
+        let mut context = ProcessingContext::new("sink".to_string());
+        context.attach_side_output("undelivered".to_string(), undelivered);
+
+        context.report_delivered(2);
+        context.report_retry();
+        block_on(context.delivery_failed(Message::new("src", "readings", json!(1)), Some("undelivered"), "broker gone"));
+        block_on(context.delivery_failed(Message::new("src", "readings", json!(2)), None, "still gone"));
+
+        let delivery = context.take_delivery();
+        assert_eq!((delivery.delivered, delivery.retried, delivery.failed, delivery.dead_lettered), (2, 1, 2, 1));
+        assert_eq!(delivery.last_error.as_deref(), Some("still gone"));
+        assert!(delivery.last_success.is_some());
+        assert!(context.take_delivery().is_empty());
+
+        let RecvResult::Message(dead) = block_on(undelivered_subscriber.try_recv()) else {
+            panic!("undelivered message was not dead-lettered");
+        };
+        assert_eq!(dead.topic, "undelivered");
+        assert_eq!(dead.get_metadata(DELIVERY_ERROR), Some("broker gone"));
+    }
+}
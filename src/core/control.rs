@@ -0,0 +1,244 @@
+//! Control API
+//!
+//! A runtime control surface on a Unix socket. Clients write one JSON command
+//! per line and read one JSON response per line:
+//!
+//! ```text
+//! {"command": "pause", "stage": "enrich"}
+//! {"command": "resume", "pipeline": "main"}
+//! {"command": "stop", "stage": "enrich[1]"}
+//! {"command": "inject", "channel": "raw", "payload": {"value": 1.5}}
+//! {"command": "status"}
+//! {"command": "tap", "channel": "raw", "sample": 10, "limit": 100}
+//! ```
+//!
+//! Responses are `{"ok": true, "result": ...}` or `{"ok": false, "error": ...}`.
+//! The listener only parses commands; each one is handed to the
+//! `PipelineManager`, which owns the running stages, and answered from there.
+//!
+//! `tap` is the exception: the listener attaches it to the channel itself
+//! (see [`super::tap`]), and after the response streams the tapped messages,
+//! one JSON message per line, until `limit` messages have been sent or the
+//! client writes another line or disconnects.
+
+use super::tap::Taps;
+
+use anyhow::Result;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader, Lines};
+use tokio::net::unix::OwnedReadHalf;
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::{mpsc, oneshot};
+
+/// The stages a command applies to: one stage (all of its replicas, or a
+/// single replica such as `enrich[1]`), or every stage of a pipeline.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct Target {
+    pub stage: Option<String>,
+    pub pipeline: Option<String>,
+}
+
+impl Target {
+    pub fn is_empty(&self) -> bool {
+        self.stage.is_none() && self.pipeline.is_none()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum ControlCommand {
+    /// Stop processing; input queues up on the stages' channels
+    Pause(Target),
+    /// Carry on processing after a pause
+    Resume(Target),
+    /// Drain and stop, as on shutdown
+    Stop(Target),
+    /// Publish a message to a channel
+    Inject { channel: String, payload: Value },
+    /// Status of the targeted stages, or of everything when untargeted
+    Status(Target),
+    /// Stream one in `sample` of the messages published to a channel, up to
+    /// `limit` messages
+    Tap {
+        channel: String,
+        sample: Option<u64>,
+        limit: Option<u64>,
+    },
+}
+
+/// A command awaiting its answer from the manager.
+pub struct ControlRequest {
+    pub command: ControlCommand,
+    pub reply: oneshot::Sender<Result<Value>>,
+}
+
+/// Listen for commands on the Unix socket at `path`, replacing a stale
+/// socket left behind by an earlier run, and attach taps to `taps`. Returns
+/// the requests to answer.
+pub fn serve(path: &str, taps: Arc<Taps>) -> Result<mpsc::Receiver<ControlRequest>> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+        _ => {}
+    }
+    let listener = UnixListener::bind(path)?;
+    tracing::info!("Control API listening on '{}'", path);
+
+    let (requests, receiver) = mpsc::channel(16);
+    tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    tokio::spawn(handle_connection(stream, requests.clone(), taps.clone()));
+                }
+                Err(e) => tracing::warn!("Control API failed to accept a connection: {}", e),
+            }
+        }
+    });
+
+    Ok(receiver)
+}
+
+/// Answer a command with the manager's reply.
+async fn answer(command: ControlCommand, requests: &mpsc::Sender<ControlRequest>) -> Result<Value> {
+    let (reply, response) = oneshot::channel();
+    requests
+        .send(ControlRequest { command, reply })
+        .await
+        .map_err(|_| anyhow::anyhow!("Pipelines are shutting down"))?;
+    response.await.map_err(|_| anyhow::anyhow!("Pipelines are shutting down"))?
+}
+
+/// Attach a tap and stream what it receives, until `limit` messages have
+/// been sent or the client writes or disconnects. Fails once the client
+/// can no longer be written to.
+async fn stream_tap(
+    taps: &Taps,
+    channel: &str,
+    sample: u64,
+    limit: Option<u64>,
+    lines: &mut Lines<BufReader<OwnedReadHalf>>,
+    writer: &mut (impl AsyncWrite + Unpin),
+) -> std::io::Result<()> {
+    let tap = match taps.attach(channel, sample) {
+        Ok(tap) => tap,
+        Err(e) => return write_line(writer, &json!({"ok": false, "error": e.to_string()})).await,
+    };
+    write_line(writer, &json!({"ok": true, "result": {"channel": channel, "sample": sample}})).await?;
+
+    let mut sent = 0;
+    while limit.is_none_or(|limit| sent < limit) {
+        tokio::select! {
+            message = tap.recv_async() => {
+                let Ok(message) = message else { break };
+                write_line(writer, &json!(message)).await?;
+                sent += 1;
+            }
+            _ = lines.next_line() => break,
+        }
+    }
+    tracing::info!("Tap on channel '{}' detached after {} message(s)", channel, sent);
+    Ok(())
+}
+
+async fn write_line(writer: &mut (impl AsyncWrite + Unpin), value: &Value) -> std::io::Result<()> {
+    writer.write_all(format!("{}\n", value).as_bytes()).await
+}
+
+async fn handle_connection(stream: UnixStream, requests: mpsc::Sender<ControlRequest>, taps: Arc<Taps>) {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+
+    while let Ok(Some(line)) = lines.next_line().await {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let result = match serde_json::from_str::<ControlCommand>(&line) {
+            Ok(ControlCommand::Tap { channel, sample, limit }) => {
+                let sample = sample.unwrap_or(1);
+                if stream_tap(&taps, &channel, sample, limit, &mut lines, &mut writer).await.is_err() {
+                    break;
+                }
+                continue;
+            }
+            Ok(command) => answer(command, &requests).await,
+            Err(e) => Err(e.into()),
+        };
+        let response = match result {
+            Ok(result) => json!({"ok": true, "result": result}),
+            Err(e) => json!({"ok": false, "error": e.to_string()}),
+        };
+        if write_line(&mut writer, &response).await.is_err() {
+            break;
+        }
+    }
+}
+
+/// Tap `channel` through the control API listening at `socket`, copying the
+/// tapped messages to `out`, one JSON message per line, until `limit`
+/// messages have been copied or the pipeline stops. Returns the number of
+/// messages copied.
+pub async fn tap(
+    socket: &str,
+    channel: &str,
+    sample: u64,
+    limit: Option<u64>,
+    out: &mut (impl AsyncWrite + Unpin),
+) -> Result<u64> {
+    let stream = UnixStream::connect(socket)
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to connect to the control API at '{}': {}", socket, e))?;
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+
+    let command = json!({"command": "tap", "channel": channel, "sample": sample, "limit": limit});
+    write_line(&mut writer, &command).await?;
+    let response: Value = match lines.next_line().await? {
+        Some(line) => serde_json::from_str(&line)?,
+        None => return Err(anyhow::anyhow!("The control API closed the connection")),
+    };
+    if response["ok"] != json!(true) {
+        return Err(anyhow::anyhow!("{}", response["error"].as_str().unwrap_or("tap refused")));
+    }
+
+    let mut copied = 0;
+    while limit.is_none_or(|limit| copied < limit)
+        && let Some(line) = lines.next_line().await?
+    {
+        out.write_all(line.as_bytes()).await?;
+        out.write_all(b"\n").await?;
+        out.flush().await?;
+        copied += 1;
+    }
+    Ok(copied)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_control_commands_parse() {
+        let parse = |line: &str| serde_json::from_str::<ControlCommand>(line).unwrap();
+
+        assert_eq!(
+            parse(r#"{"command": "pause", "stage": "enrich"}"#),
+            ControlCommand::Pause(Target { stage: Some("enrich".to_string()), pipeline: None })
+        );
+        assert_eq!(
+            parse(r#"{"command": "stop", "pipeline": "main"}"#),
+            ControlCommand::Stop(Target { stage: None, pipeline: Some("main".to_string()) })
+        );
+        assert_eq!(
+            parse(r#"{"command": "inject", "channel": "raw", "payload": {"value": 1}}"#),
+            ControlCommand::Inject { channel: "raw".to_string(), payload: json!({"value": 1}) }
+        );
+        assert_eq!(parse(r#"{"command": "status"}"#), ControlCommand::Status(Target::default()));
+        assert_eq!(
+            parse(r#"{"command": "tap", "channel": "raw", "sample": 10}"#),
+            ControlCommand::Tap { channel: "raw".to_string(), sample: Some(10), limit: None }
+        );
+        assert!(serde_json::from_str::<ControlCommand>(r#"{"command": "explode"}"#).is_err());
+    }
+}
@@ -0,0 +1,161 @@
+//! Fan-in
+//!
+//! Fair receiving across a stage's input channels. Inputs are polled
+//! round-robin, starting after the input that last delivered a message, so a
+//! busy input cannot starve the others whatever their `HashMap` order.
+//!
+//! Broadcast inputs that fell behind are noted as lag and received from
+//! again, so the caller can account for the messages they missed.
+
+use super::channel::{RecvResult, Subscriber};
+use super::message::Message;
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+#[derive(Debug, Default)]
+pub struct FanIn {
+    /// Input names in polling order
+    order: Vec<String>,
+    /// Index in `order` of the input to poll first
+    next: usize,
+    /// Messages skipped by lagging inputs since the last `take_lagged`
+    lagged: Vec<(String, u64)>,
+}
+
+impl FanIn {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn refresh(&mut self, inputs: &HashMap<String, Subscriber<Message>>) {
+        if self.order.len() == inputs.len() && self.order.iter().all(|name| inputs.contains_key(name)) {
+            return;
+        }
+        self.order = inputs.keys().cloned().collect();
+        self.order.sort();
+        self.next = 0;
+    }
+
+    fn served(&mut self, name: &str) {
+        self.next = self.order.iter().position(|n| n == name).map_or(0, |i| i + 1);
+    }
+
+    /// Inputs that lagged since the last call, with the messages they skipped.
+    pub fn take_lagged(&mut self) -> Vec<(String, u64)> {
+        std::mem::take(&mut self.lagged)
+    }
+
+    /// Take the next ready message from any input without waiting.
+    pub async fn try_recv(
+        &mut self,
+        inputs: &mut HashMap<String, Subscriber<Message>>,
+    ) -> Option<(String, Message)> {
+        self.refresh(inputs);
+
+        let count = self.order.len();
+        for offset in 0..count {
+            let index = (self.next + offset) % count;
+            let Some(input) = inputs.get_mut(&self.order[index]) else {
+                continue;
+            };
+            loop {
+                match input.try_recv().await {
+                    RecvResult::Message(message) => {
+                        self.next = index + 1;
+                        return Some((self.order[index].clone(), message));
+                    }
+                    RecvResult::Lagged(skipped) => self.lagged.push((self.order[index].clone(), skipped)),
+                    RecvResult::Empty | RecvResult::Closed => break,
+                }
+            }
+        }
+        None
+    }
+
+    /// Receive the next message from any input, waiting up to `timeout` when
+    /// none is ready.
+    pub async fn recv(
+        &mut self,
+        inputs: &mut HashMap<String, Subscriber<Message>>,
+        timeout: Duration,
+    ) -> Option<(String, Message)> {
+        if let Some(received) = self.try_recv(inputs).await {
+            return Some(received);
+        }
+        if inputs.is_empty() {
+            tokio::time::sleep(timeout).await;
+            return None;
+        }
+
+        // Nothing is queued, so whichever input delivers first is served
+        let waiting = inputs
+            .iter_mut()
+            .map(|(name, input)| Box::pin(async move { (name.clone(), input.recv().await) }));
+        let received = match tokio::time::timeout(timeout, futures::future::select_all(waiting)).await {
+            Ok(((name, result), _, _)) => (name, result),
+            Err(_) => return None,
+        };
+        match received {
+            (name, RecvResult::Message(message)) => {
+                self.served(&name);
+                Some((name, message))
+            }
+            (name, RecvResult::Lagged(skipped)) => {
+                // The lagging input has messages again
+                self.lagged.push((name, skipped));
+                self.try_recv(inputs).await
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::types::ChannelConfig;
+    use crate::core::channel::{Channel, PubSubChannel};
+    use futures::executor::block_on;
+    use serde_json::json;
+
+    #[test]
+    fn test_fan_in_is_round_robin() {
+        let config = ChannelConfig::default();
+        let busy = Channel::open("busy", &config).unwrap();
+        let quiet = Channel::open("quiet", &config).unwrap();
+        let mut inputs = HashMap::from([
+            ("busy".to_string(), busy.subscribe()),
+            ("quiet".to_string(), quiet.subscribe()),
+        ]);
+
+        for i in 0..3 {
+            block_on(busy.publish(Message::new("src", "busy", json!(i)))).unwrap();
+        }
+        block_on(quiet.publish(Message::new("src", "quiet", json!(0)))).unwrap();
+
+        let mut fan_in = FanIn::new();
+        let served: Vec<String> = (0..4)
+            .map(|_| block_on(fan_in.try_recv(&mut inputs)).unwrap().0)
+            .collect();
+        assert_eq!(served, ["busy", "quiet", "busy", "busy"]);
+        assert!(block_on(fan_in.try_recv(&mut inputs)).is_none());
+    }
+
+    #[test]
+    fn test_fan_in_reports_lag_and_carries_on() {
+        let config = ChannelConfig { capacity: 2, ..Default::default() };
+        let broadcast = Channel::open("readings", &config).unwrap();
+        let mut inputs = HashMap::from([("readings".to_string(), broadcast.subscribe())]);
+
+        for i in 0..5 {
+            block_on(broadcast.publish(Message::new("src", "readings", json!(i)))).unwrap();
+        }
+
+        let mut fan_in = FanIn::new();
+        let (_, message) = block_on(fan_in.try_recv(&mut inputs)).unwrap();
+        assert_eq!(message.payload, json!(3));
+        assert_eq!(fan_in.take_lagged(), [("readings".to_string(), 3)]);
+        assert!(fan_in.take_lagged().is_empty());
+    }
+}
@@ -0,0 +1,247 @@
+//! Lease
+//!
+//! Active/standby coordination between instances running the same
+//! configuration. The instances share a lease file recording which instance
+//! holds the lease, until when, and a fencing token increased on every
+//! takeover. Reads and updates of the file are serialised with an exclusive
+//! `flock`, so two standby instances never take over at once.
+//!
+//! The holder renews the lease three times per `lease_ms`. A standby takes
+//! over once the lease has expired. A holder that finds the lease taken (a
+//! newer token than its own), or that could not renew it before it expired,
+//! has been fenced and must stop at once.
+
+use crate::config::types::HaConfig;
+
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::os::fd::AsRawFd;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc;
+
+/// Contents of the lease file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct LeaseRecord {
+    holder: String,
+    token: u64,
+    /// Unix time the lease expires at, in milliseconds
+    expires_at_ms: u64,
+}
+
+/// This instance's claim on a lease file.
+#[derive(Debug, Clone)]
+pub struct Lease {
+    path: PathBuf,
+    holder: String,
+    duration: Duration,
+    /// Fencing token of the lease while this instance holds it
+    token: u64,
+}
+
+impl Lease {
+    pub fn new(config: &HaConfig) -> Self {
+        let holder = config.instance.clone().unwrap_or_else(|| {
+            let host = std::fs::read_to_string("/etc/hostname").unwrap_or_default();
+            let host = if host.trim().is_empty() { "liminal" } else { host.trim() };
+            format!("{}-{}", host, std::process::id())
+        });
+        Self {
+            path: PathBuf::from(&config.lease_file),
+            holder,
+            duration: Duration::from_millis(config.lease_ms),
+            token: 0,
+        }
+    }
+
+    pub fn holder(&self) -> &str {
+        &self.holder
+    }
+
+    /// Fencing token of the lease, once acquired.
+    pub fn token(&self) -> u64 {
+        self.token
+    }
+
+    fn renew_interval(&self) -> Duration {
+        (self.duration / 3).max(Duration::from_millis(1))
+    }
+
+    /// Stand by until the lease is free or has expired, then take it.
+    pub async fn acquire(config: &HaConfig) -> Result<Self> {
+        let mut lease = Self::new(config);
+        let mut standing_by = false;
+        loop {
+            match lease.try_take()? {
+                Ok(token) => {
+                    lease.token = token;
+                    tracing::info!(
+                        "Instance '{}' acquired lease '{}' (fencing token {})",
+                        lease.holder,
+                        lease.path.display(),
+                        token
+                    );
+                    return Ok(lease);
+                }
+                Err(holder) if !standing_by => {
+                    tracing::info!("Standing by: lease '{}' is held by '{}'", lease.path.display(), holder);
+                    standing_by = true;
+                }
+                Err(_) => {}
+            }
+            tokio::time::sleep(lease.renew_interval()).await;
+        }
+    }
+
+    /// Take the lease if nobody else holds it unexpired, returning the new
+    /// fencing token, or else the current holder.
+    pub fn try_take(&self) -> Result<Result<u64, String>> {
+        self.update(|record, now| match record {
+            Some(record) if record.holder != self.holder && record.expires_at_ms > now => {
+                (None, Err(record.holder))
+            }
+            record => {
+                let token = record.map_or(1, |record| record.token + 1);
+                let taken = LeaseRecord {
+                    holder: self.holder.clone(),
+                    token,
+                    expires_at_ms: now + self.duration.as_millis() as u64,
+                };
+                (Some(taken), Ok(token))
+            }
+        })
+    }
+
+    /// Extend the lease. Returns `false` if another instance has taken it.
+    pub fn renew(&self) -> Result<bool> {
+        self.update(|record, now| match record {
+            Some(record) if record.holder == self.holder && record.token == self.token => {
+                let renewed = LeaseRecord {
+                    expires_at_ms: now + self.duration.as_millis() as u64,
+                    ..record
+                };
+                (Some(renewed), true)
+            }
+            _ => (None, false),
+        })
+    }
+
+    /// Give the lease up, so a standby can take over without waiting for it
+    /// to expire.
+    pub fn release(&self) -> Result<()> {
+        self.update(|record, _| match record {
+            Some(record) if record.holder == self.holder && record.token == self.token => {
+                (Some(LeaseRecord { expires_at_ms: 0, ..record }), ())
+            }
+            _ => (None, ()),
+        })
+    }
+
+    /// Keep renewing the lease in the background. A message is sent on the
+    /// returned channel if the lease is lost, after which renewal stops.
+    pub fn keep(&self) -> (tokio::task::JoinHandle<()>, mpsc::UnboundedReceiver<String>) {
+        let (lost, receiver) = mpsc::unbounded_channel();
+        let lease = self.clone();
+        let task = tokio::spawn(async move {
+            let mut expires = Instant::now() + lease.duration;
+            loop {
+                tokio::time::sleep(lease.renew_interval()).await;
+                let reason = match lease.renew() {
+                    Ok(true) => {
+                        expires = Instant::now() + lease.duration;
+                        continue;
+                    }
+                    Ok(false) => "the lease was taken by another instance".to_string(),
+                    // A standby may take over once the lease has expired
+                    Err(e) if Instant::now() >= expires => format!("the lease expired: {}", e),
+                    Err(e) => {
+                        tracing::warn!("Failed to renew lease '{}': {}", lease.path.display(), e);
+                        continue;
+                    }
+                };
+                let _ = lost.send(reason);
+                return;
+            }
+        });
+        (task, receiver)
+    }
+
+    /// Read the lease record and write back its update (if any) under an
+    /// exclusive lock on the lease file.
+    fn update<T>(&self, update: impl FnOnce(Option<LeaseRecord>, u64) -> (Option<LeaseRecord>, T)) -> Result<T> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&self.path)
+            .map_err(|e| anyhow!("Failed to open lease file '{}': {}", self.path.display(), e))?;
+        lock(&file)?;
+
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)?;
+        let record = if contents.trim().is_empty() {
+            None
+        } else {
+            Some(
+                serde_json::from_str(&contents)
+                    .map_err(|e| anyhow!("Corrupt lease file '{}': {}", self.path.display(), e))?,
+            )
+        };
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64;
+        let (record, result) = update(record, now);
+        if let Some(record) = record {
+            file.set_len(0)?;
+            file.seek(SeekFrom::Start(0))?;
+            file.write_all(&serde_json::to_vec(&record)?)?;
+            file.sync_all()?;
+        }
+        // The lock is released when the file is closed
+        Ok(result)
+    }
+}
+
+/// Lock a file exclusively, waiting for other holders.
+fn lock(file: &File) -> Result<()> {
+    // SAFETY: the descriptor is open for as long as `file` is borrowed
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+        return Err(anyhow!("Failed to lock lease file: {}", std::io::Error::last_os_error()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lease_takeover_fences_previous_holder() {
+        let path = std::env::temp_dir().join(format!("liminal-lease-{}", std::process::id()));
+        let config = |instance: &str| HaConfig {
+            lease_file: path.to_string_lossy().to_string(),
+            lease_ms: 50,
+            instance: Some(instance.to_string()),
+        };
+        let mut active = Lease::new(&config("a"));
+        let mut standby = Lease::new(&config("b"));
+
+        active.token = active.try_take().unwrap().unwrap();
+        assert_eq!(active.token(), 1);
+        assert_eq!(standby.try_take().unwrap(), Err("a".to_string()));
+        assert!(active.renew().unwrap());
+
+        // Not renewed in time: the standby takes over and fences the holder
+        std::thread::sleep(Duration::from_millis(60));
+        standby.token = standby.try_take().unwrap().unwrap();
+        assert_eq!(standby.token(), 2);
+        assert!(!active.renew().unwrap());
+
+        // Released leases can be taken at once
+        standby.release().unwrap();
+        assert_eq!(active.try_take().unwrap(), Ok(3));
+        let _ = std::fs::remove_file(&path);
+    }
+}
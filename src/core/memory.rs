@@ -0,0 +1,228 @@
+//! Memory Report
+//!
+//! Shows where the memory of a long-running process goes: the resident size
+//! reported by the OS, the bytes allocated on the heap and the size of every
+//! keyed state store. A `[memory_report]` section logs these periodically,
+//! and the admin server exports them on `/metrics`.
+//!
+//! Heap bytes are only counted in builds with the `tracking-allocator`
+//! feature, whose binary installs [`TrackingAllocator`] as the global
+//! allocator. It wraps the system allocator, or mimalloc in builds that also
+//! have the `mimalloc` feature, so that the heap can be compared between the
+//! two. A resident size growing well ahead of the heap points at
+//! allocator fragmentation rather than a leak; a heap growing with a state
+//! store points at keys that are never evicted. Resident size is only read
+//! on Linux.
+
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, Weak};
+
+/// Entries and estimated size of one state store, updated as it changes.
+#[derive(Debug, Default)]
+pub struct StoreUsage {
+    entries: AtomicU64,
+    bytes: AtomicU64,
+    /// Whether the store estimates its size (only with `max_bytes`)
+    sized: AtomicBool,
+}
+
+impl StoreUsage {
+    pub fn update(&self, entries: usize, bytes: Option<usize>) {
+        self.entries.store(entries as u64, Ordering::Relaxed);
+        self.bytes.store(bytes.unwrap_or_default() as u64, Ordering::Relaxed);
+        self.sized.store(bytes.is_some(), Ordering::Relaxed);
+    }
+}
+
+/// The state stores open in the process, by namespace. Stores are forgotten
+/// once dropped.
+type Stores = Mutex<Vec<(String, Weak<StoreUsage>)>>;
+
+fn stores() -> &'static Stores {
+    static STORES: OnceLock<Stores> = OnceLock::new();
+    STORES.get_or_init(|| Mutex::new(Vec::new()))
+}
+
+/// Register a state store for the memory report, returning the usage it
+/// keeps up to date.
+pub fn register_store(namespace: &str) -> Arc<StoreUsage> {
+    let usage = Arc::new(StoreUsage::default());
+    let mut stores = stores().lock().unwrap();
+    stores.retain(|(_, usage)| usage.strong_count() > 0);
+    stores.push((namespace.to_string(), Arc::downgrade(&usage)));
+    usage
+}
+
+/// Memory used by one state store.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StoreMemory {
+    pub store: String,
+    pub entries: u64,
+    /// Estimated size, or `None` if the store has no `max_bytes` to estimate it for
+    pub bytes: Option<u64>,
+}
+
+/// Memory use of the process at one moment.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MemoryReport {
+    /// `None` where the OS does not report it
+    pub resident_bytes: Option<u64>,
+    /// `None` unless the tracking allocator is installed
+    pub heap_bytes: Option<u64>,
+    pub stores: Vec<StoreMemory>,
+}
+
+impl MemoryReport {
+    pub fn collect() -> Self {
+        let mut stores: Vec<StoreMemory> = stores()
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|(store, usage)| {
+                let usage = usage.upgrade()?;
+                Some(StoreMemory {
+                    store: store.clone(),
+                    entries: usage.entries.load(Ordering::Relaxed),
+                    bytes: usage.sized.load(Ordering::Relaxed).then(|| usage.bytes.load(Ordering::Relaxed)),
+                })
+            })
+            .collect();
+        stores.sort_by(|a, b| a.store.cmp(&b.store));
+        Self { resident_bytes: resident_bytes(), heap_bytes: heap_bytes(), stores }
+    }
+
+    /// Log the report, one line per state store.
+    pub fn log(&self) {
+        let describe = |bytes: Option<u64>| bytes.map_or_else(|| "unknown".to_string(), format_bytes);
+        tracing::info!(
+            "Memory: resident {}, heap {}, state stores {} ({} entries)",
+            describe(self.resident_bytes),
+            describe(self.heap_bytes),
+            self.stores.len(),
+            self.stores.iter().map(|store| store.entries).sum::<u64>()
+        );
+        for store in &self.stores {
+            match store.bytes {
+                Some(bytes) => tracing::info!("  state '{}': {} entries, {}", store.store, store.entries, format_bytes(bytes)),
+                None => tracing::info!("  state '{}': {} entries", store.store, store.entries),
+            }
+        }
+    }
+}
+
+fn format_bytes(bytes: u64) -> String {
+    match bytes {
+        0..1024 => format!("{} B", bytes),
+        1024..1_048_576 => format!("{:.1} KiB", bytes as f64 / 1024.0),
+        _ => format!("{:.1} MiB", bytes as f64 / 1_048_576.0),
+    }
+}
+
+/// Resident set size of the process, from `/proc/self/statm`.
+#[cfg(target_os = "linux")]
+fn resident_bytes() -> Option<u64> {
+    let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+    let pages: u64 = statm.split_whitespace().nth(1)?.parse().ok()?;
+    // SAFETY: sysconf only reads a system constant
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    (page_size > 0).then(|| pages * page_size as u64)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn resident_bytes() -> Option<u64> {
+    None
+}
+
+#[cfg(feature = "tracking-allocator")]
+static ALLOCATED: AtomicU64 = AtomicU64::new(0);
+
+/// An allocator counting the bytes allocated through it: the system
+/// allocator by default, or another global allocator such as mimalloc.
+/// Installed as the global allocator of the `liminal` binary when built with
+/// the `tracking-allocator` feature.
+#[cfg(feature = "tracking-allocator")]
+pub struct TrackingAllocator<A = std::alloc::System>(A);
+
+#[cfg(feature = "tracking-allocator")]
+impl<A> TrackingAllocator<A> {
+    pub const fn new(inner: A) -> Self {
+        Self(inner)
+    }
+}
+
+#[cfg(feature = "tracking-allocator")]
+unsafe impl<A: std::alloc::GlobalAlloc> std::alloc::GlobalAlloc for TrackingAllocator<A> {
+    unsafe fn alloc(&self, layout: std::alloc::Layout) -> *mut u8 {
+        let ptr = unsafe { self.0.alloc(layout) };
+        if !ptr.is_null() {
+            ALLOCATED.fetch_add(layout.size() as u64, Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: std::alloc::Layout) -> *mut u8 {
+        let ptr = unsafe { self.0.alloc_zeroed(layout) };
+        if !ptr.is_null() {
+            ALLOCATED.fetch_add(layout.size() as u64, Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: std::alloc::Layout) {
+        unsafe { self.0.dealloc(ptr, layout) };
+        ALLOCATED.fetch_sub(layout.size() as u64, Ordering::Relaxed);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: std::alloc::Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = unsafe { self.0.realloc(ptr, layout, new_size) };
+        if !new_ptr.is_null() {
+            ALLOCATED.fetch_sub(layout.size() as u64, Ordering::Relaxed);
+            ALLOCATED.fetch_add(new_size as u64, Ordering::Relaxed);
+        }
+        new_ptr
+    }
+}
+
+/// Bytes allocated and not yet freed, if the tracking allocator is installed.
+#[cfg(feature = "tracking-allocator")]
+fn heap_bytes() -> Option<u64> {
+    // Nothing counted means the allocator was built but not installed
+    Some(ALLOCATED.load(Ordering::Relaxed)).filter(|bytes| *bytes > 0)
+}
+
+#[cfg(not(feature = "tracking-allocator"))]
+fn heap_bytes() -> Option<u64> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::StateConfig;
+    use crate::core::state::StateStore;
+
+    #[test]
+    fn test_memory_report_follows_state_stores() {
+        let sized = StateConfig { max_bytes: Some(1024), ..Default::default() };
+        let mut counts: StateStore<String, u32> = StateStore::open("memory_counts", None).unwrap();
+        let mut sizes: StateStore<String, u32> = StateStore::open("memory_sizes", Some(&sized)).unwrap();
+        for key in ["a", "b", "c"] {
+            counts.insert(key.to_string(), 1);
+        }
+        sizes.insert("a".to_string(), 1);
+        counts.remove(&"b".to_string());
+
+        let report = MemoryReport::collect();
+        let store = |name: &str| report.stores.iter().find(|store| store.store == name).cloned();
+        assert_eq!(store("memory_counts"), Some(StoreMemory { store: "memory_counts".to_string(), entries: 2, bytes: None }));
+        // `"a"` plus `1`
+        assert_eq!(store("memory_sizes").and_then(|store| store.bytes), Some(4));
+        if cfg!(target_os = "linux") {
+            assert!(report.resident_bytes.is_some_and(|bytes| bytes > 0));
+        }
+
+        drop(counts);
+        assert_eq!(MemoryReport::collect().stores.iter().filter(|store| store.store == "memory_counts").count(), 0);
+    }
+}
@@ -1,8 +1,15 @@
+use super::batch::RecordBatch;
+use super::channel::Prioritised;
+use crate::processors::common::field_utils::FieldUtils;
+
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
 use std::time::{SystemTime, Duration};
 
 /// Timing metadata for messages in the processing pipeline
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TimingInfo {
     /// When the event actually occurred (event time)
     pub event_time: SystemTime,
@@ -95,7 +102,7 @@ impl TimingInfo {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Message {
     pub source: String,
     pub topic: String,
@@ -107,8 +114,50 @@ pub struct Message {
     
     /// Enhanced timing information
     pub timing: TimingInfo,
+
+    /// Transport metadata kept apart from the payload, such as the MQTT topic
+    /// a message arrived on (`MQTT_TOPIC`) or how a string payload encodes
+    /// binary data (`CONTENT_ENCODING`). Carried along as messages are
+    /// transformed, for processors to read and sinks to route by.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub metadata: HashMap<String, String>,
+
+    /// Rows of readings carried column by column in place of the payload,
+    /// which is then `null`. Only processors that accept batches see them;
+    /// other stages receive the rows as separate messages.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub batch: Option<Arc<RecordBatch>>,
 }
 
+/// Metadata key for the MQTT topic a message was received on
+pub const MQTT_TOPIC: &str = "mqtt_topic";
+
+/// Metadata key for how a string payload encodes binary data
+pub const CONTENT_ENCODING: &str = "content_encoding";
+
+/// `CONTENT_ENCODING` of binary payloads carried as base64 strings
+pub const ENCODING_BASE64: &str = "base64";
+
+/// Metadata key for how far past its processing deadline a message was
+/// received (in milliseconds), on messages routed by a stage's `sla`
+pub const SLA_EXCEEDED_MS: &str = "sla_exceeded_ms";
+
+/// Metadata key for the age by event time (in milliseconds) at which a
+/// message expired, on messages routed by a stage's `ttl`
+pub const EXPIRED_AGE_MS: &str = "expired_age_ms";
+
+/// Metadata key for the stage that published a message to a channel shared
+/// by several stages (the last such channel it passed through)
+pub const PRODUCER: &str = "producer";
+
+/// Metadata key for the error a sink failed to deliver a message with, on
+/// messages it published to its dead-letter output
+pub const DELIVERY_ERROR: &str = "delivery_error";
+
+/// Metadata key for a message's delivery priority on priority channels
+/// (0 to 255, higher first)
+pub const PRIORITY: &str = "priority";
+
 impl Message {
     /// Create a new message with current time as both event and ingestion time
     pub fn new(source: &str, topic: &str, payload: Value) -> Self {
@@ -124,6 +173,8 @@ impl Message {
             payload,
             timestamp,
             timing,
+            metadata: HashMap::new(),
+            batch: None,
         }
     }
     
@@ -141,9 +192,45 @@ impl Message {
             payload,
             timestamp,
             timing,
+            metadata: HashMap::new(),
+            batch: None,
         }
     }
     
+    /// Create a message carrying a batch of rows. Its event time is that of
+    /// the batch's first row.
+    pub fn from_batch(source: &str, topic: &str, batch: RecordBatch) -> Self {
+        let event_time = batch.event_times().next().unwrap_or_else(SystemTime::now);
+        let mut message = Self::new(source, topic, Value::Null);
+        message.timing.event_time = event_time;
+        message.batch = Some(Arc::new(batch));
+        message
+    }
+
+    /// The batch carried by this message, to change in place. The batch is
+    /// copied first if another message shares it.
+    pub fn batch_mut(&mut self) -> Option<&mut RecordBatch> {
+        self.batch.as_mut().map(Arc::make_mut)
+    }
+
+    /// Split a batch into one message per row, each with the row's event time
+    /// and this message's other timing and metadata. A message without a
+    /// batch is returned as it is.
+    pub fn into_rows(mut self) -> Vec<Message> {
+        let Some(batch) = self.batch.take() else {
+            return vec![self];
+        };
+        batch
+            .rows()
+            .map(|(event_time, payload)| {
+                let mut row = self.clone();
+                row.payload = payload;
+                row.timing.event_time = event_time;
+                row
+            })
+            .collect()
+    }
+    
     /// Set processing deadline for this message
     pub fn with_deadline(mut self, deadline: SystemTime) -> Self {
         self.timing.processing_deadline = Some(deadline);
@@ -162,6 +249,22 @@ impl Message {
         self
     }
     
+    /// Set a metadata entry
+    pub fn with_metadata(mut self, key: &str, value: impl Into<String>) -> Self {
+        self.metadata.insert(key.to_string(), value.into());
+        self
+    }
+
+    /// Get a metadata entry
+    pub fn get_metadata(&self, key: &str) -> Option<&str> {
+        self.metadata.get(key).map(String::as_str)
+    }
+
+    /// Set the delivery priority on priority channels (0 by default)
+    pub fn with_priority(self, priority: u8) -> Self {
+        self.with_metadata(PRIORITY, priority.to_string())
+    }
+    
     /// Set trace ID for debugging
     pub fn with_trace_id(mut self, trace_id: String) -> Self {
         self.timing.trace_id = Some(trace_id);
@@ -180,3 +283,19 @@ impl Message {
         !self.timing.is_deadline_exceeded()
     }
 }
+
+impl Prioritised for Message {
+    /// The `PRIORITY` metadata entry, or else the value of the payload
+    /// field, clamped to 0-255. Booleans count as 1 and 0.
+    fn priority(&self, field: Option<&str>) -> u8 {
+        if let Some(priority) = self.get_metadata(PRIORITY).and_then(|priority| priority.parse().ok()) {
+            return priority;
+        }
+        let value = field.and_then(|field| FieldUtils::extract_field_value(&self.payload, field));
+        match value {
+            Some(Value::Bool(flag)) => u8::from(*flag),
+            Some(value) => value.as_f64().map_or(0, |priority| priority.clamp(0.0, 255.0) as u8),
+            None => 0,
+        }
+    }
+}
@@ -0,0 +1,486 @@
+//! Metrics
+//!
+//! Prometheus metrics for stages and channels, served in the text exposition
+//! format on the admin server's `/metrics` endpoint:
+//!
+//! | Metric | Labels | Description |
+//! |--------|--------|-------------|
+//! | `liminal_stage_messages_in_total` | `stage` | Messages received from input channels |
+//! | `liminal_stage_messages_out_total` | `stage` | Messages published to output and side output channels |
+//! | `liminal_stage_errors_total` | `stage` | Errors returned by the processor |
+//! | `liminal_stage_restarts_total` | `stage` | Restarts by the supervisor |
+//! | `liminal_stage_lagged_total` | `stage` | Messages missed by falling behind broadcast inputs |
+//! | `liminal_stage_sla_violations_total` | `stage` | Messages received past their processing deadline (stages with an `sla` table) |
+//! | `liminal_stage_expired_total` | `stage` | Messages older than the stage's `ttl` |
+//! | `liminal_stage_shed_total` | `stage` | Messages shed or routed over the stage's `limits.max_in_flight` |
+//! | `liminal_stage_slow_total` | `stage` | Batches of input processed slower than the stage's `limits.slow_processing_ms` |
+//! | `liminal_stage_processing_seconds` | `stage` | Time from receiving input to `process` returning |
+//! | `liminal_stage_input_depth` | `stage`, `input` | Messages queued on an input |
+//! | `liminal_stage_watermark_lag_seconds` | `stage` | Wall-clock time minus the latest input watermark |
+//! | `liminal_sink_delivered_total` | `stage` | Messages a sink delivered |
+//! | `liminal_sink_retries_total` | `stage` | Deliveries a sink retried |
+//! | `liminal_sink_failed_total` | `stage` | Messages a sink could not deliver |
+//! | `liminal_sink_dead_lettered_total` | `stage` | Undeliverable messages published to a dead-letter output |
+//! | `liminal_sink_last_success_timestamp_seconds` | `stage` | When a sink last delivered a message (Unix time) |
+//! | `liminal_clock_skew_seconds` | `stage`, `key` | Ingestion time minus device event time, estimated by `clock_skew` stages |
+//! | `liminal_inference_skipped_total` | `stage`, `reason` | Messages an `onnx_infer` stage did not score, while its window filled (`warm_up`) or because inference failed (`error`) |
+//! | `liminal_channel_published_total` | `channel` | Messages accepted into a channel |
+//! | `liminal_channel_dropped_total` | `channel` | Messages discarded by the overflow policy |
+//! | `liminal_channel_rejected_total` | `channel` | Publishes refused by the overflow policy |
+//! | `liminal_channel_depth` | `channel` | Messages queued and not yet received |
+//! | `liminal_channel_capacity` | `channel` | Messages held before the overflow policy applies (bounded channels only) |
+//! | `liminal_process_resident_bytes` | | Resident set size of the process (Linux only) |
+//! | `liminal_heap_allocated_bytes` | | Heap in use (`tracking-allocator` builds only) |
+//! | `liminal_state_entries` | `store` | Entries in a keyed state store |
+//! | `liminal_state_bytes` | `store` | Estimated size of a keyed state store (stores with `max_bytes` only) |
+//!
+//! Stages record their own metrics unless their `timing.metrics_enabled` is
+//! off. Sink metrics appear once a sink reports a delivery result. Channel counters are kept by the channels themselves and copied into
+//! the registry when it is scraped.
+
+use super::channel::{ChannelStats, PubSubChannel, PublishError, Subscriber};
+use super::context::DeliveryReport;
+use super::memory::MemoryReport;
+use super::message::Message;
+use super::summary::{LatencySummary, StageSummary};
+
+use async_trait::async_trait;
+use prometheus::core::Metric as _;
+use prometheus::{
+    Encoder, Gauge, GaugeVec, Histogram, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge,
+    IntGaugeVec, Opts, Registry, TextEncoder,
+};
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, SystemTime};
+
+pub struct Metrics {
+    registry: Registry,
+    messages_in: IntCounterVec,
+    messages_out: IntCounterVec,
+    errors: IntCounterVec,
+    restarts: IntCounterVec,
+    lagged: IntCounterVec,
+    sla_violations: IntCounterVec,
+    expired: IntCounterVec,
+    shed: IntCounterVec,
+    slow: IntCounterVec,
+    processing: HistogramVec,
+    input_depth: IntGaugeVec,
+    watermark_lag: GaugeVec,
+    clock_skew: GaugeVec,
+    inference_skipped: IntCounterVec,
+    delivered: IntCounterVec,
+    retried: IntCounterVec,
+    delivery_failed: IntCounterVec,
+    dead_lettered: IntCounterVec,
+    last_success: GaugeVec,
+    published: IntCounterVec,
+    dropped: IntCounterVec,
+    rejected: IntCounterVec,
+    depth: IntGaugeVec,
+    capacity: IntGaugeVec,
+    resident: IntGaugeVec,
+    heap: IntGaugeVec,
+    state_entries: IntGaugeVec,
+    state_bytes: IntGaugeVec,
+}
+
+/// The process-wide metrics registry.
+pub fn metrics() -> &'static Metrics {
+    static METRICS: OnceLock<Metrics> = OnceLock::new();
+    METRICS.get_or_init(Metrics::new)
+}
+
+fn gauge(registry: &Registry, name: &str, help: &str, labels: &[&str]) -> IntGaugeVec {
+    let gauge = IntGaugeVec::new(Opts::new(name, help), labels).expect("metrics: invalid gauge");
+    registry.register(Box::new(gauge.clone())).expect("metrics: duplicate gauge");
+    gauge
+}
+
+fn counter(registry: &Registry, name: &str, help: &str, labels: &[&str]) -> IntCounterVec {
+    let counter = IntCounterVec::new(Opts::new(name, help), labels).expect("metrics: invalid counter");
+    registry.register(Box::new(counter.clone())).expect("metrics: duplicate counter");
+    counter
+}
+
+/// Bring a counter up to a total kept elsewhere.
+fn sync_counter(counter: &IntCounter, total: u64) {
+    let current = counter.get();
+    if total > current {
+        counter.inc_by(total - current);
+    }
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new();
+
+        let processing = HistogramVec::new(
+            HistogramOpts::new(
+                "liminal_stage_processing_seconds",
+                "Time from receiving input to process() returning",
+            )
+            .buckets(prometheus::exponential_buckets(0.0001, 4.0, 10).expect("metrics: invalid buckets")),
+            &["stage"],
+        )
+        .expect("metrics: invalid histogram");
+        registry.register(Box::new(processing.clone())).expect("metrics: duplicate histogram");
+
+        let input_depth = IntGaugeVec::new(
+            Opts::new("liminal_stage_input_depth", "Messages queued on a stage input"),
+            &["stage", "input"],
+        )
+        .expect("metrics: invalid gauge");
+        registry.register(Box::new(input_depth.clone())).expect("metrics: duplicate gauge");
+
+        let watermark_lag = GaugeVec::new(
+            Opts::new(
+                "liminal_stage_watermark_lag_seconds",
+                "Wall-clock time minus the latest input watermark",
+            ),
+            &["stage"],
+        )
+        .expect("metrics: invalid gauge");
+        registry.register(Box::new(watermark_lag.clone())).expect("metrics: duplicate gauge");
+
+        let clock_skew = GaugeVec::new(
+            Opts::new("liminal_clock_skew_seconds", "Ingestion time minus device event time"),
+            &["stage", "key"],
+        )
+        .expect("metrics: invalid gauge");
+        registry.register(Box::new(clock_skew.clone())).expect("metrics: duplicate gauge");
+
+        let last_success = GaugeVec::new(
+            Opts::new(
+                "liminal_sink_last_success_timestamp_seconds",
+                "When a sink last delivered a message, in seconds since the epoch",
+            ),
+            &["stage"],
+        )
+        .expect("metrics: invalid gauge");
+        registry.register(Box::new(last_success.clone())).expect("metrics: duplicate gauge");
+
+        Self {
+            messages_in: counter(&registry, "liminal_stage_messages_in_total", "Messages received by a stage", &["stage"]),
+            messages_out: counter(&registry, "liminal_stage_messages_out_total", "Messages published by a stage", &["stage"]),
+            errors: counter(&registry, "liminal_stage_errors_total", "Errors returned by a stage's processor", &["stage"]),
+            restarts: counter(&registry, "liminal_stage_restarts_total", "Stage restarts by the supervisor", &["stage"]),
+            lagged: counter(&registry, "liminal_stage_lagged_total", "Messages a stage missed by lagging behind broadcast inputs", &["stage"]),
+            sla_violations: counter(&registry, "liminal_stage_sla_violations_total", "Messages a stage received past their processing deadline", &["stage"]),
+            expired: counter(&registry, "liminal_stage_expired_total", "Messages a stage received older than its TTL", &["stage"]),
+            shed: counter(&registry, "liminal_stage_shed_total", "Messages a stage shed over its in-flight limit", &["stage"]),
+            slow: counter(&registry, "liminal_stage_slow_total", "Batches of input a stage processed slower than its limit", &["stage"]),
+            processing,
+            input_depth,
+            watermark_lag,
+            clock_skew,
+            inference_skipped: counter(&registry, "liminal_inference_skipped_total", "Messages an inference stage did not score", &["stage", "reason"]),
+            delivered: counter(&registry, "liminal_sink_delivered_total", "Messages a sink delivered", &["stage"]),
+            retried: counter(&registry, "liminal_sink_retries_total", "Deliveries a sink retried", &["stage"]),
+            delivery_failed: counter(&registry, "liminal_sink_failed_total", "Messages a sink could not deliver", &["stage"]),
+            dead_lettered: counter(&registry, "liminal_sink_dead_lettered_total", "Undeliverable messages a sink published to its dead-letter output", &["stage"]),
+            last_success,
+            published: counter(&registry, "liminal_channel_published_total", "Messages accepted into a channel", &["channel"]),
+            dropped: counter(&registry, "liminal_channel_dropped_total", "Messages discarded on overflow", &["channel"]),
+            rejected: counter(&registry, "liminal_channel_rejected_total", "Publishes refused on overflow", &["channel"]),
+            depth: gauge(&registry, "liminal_channel_depth", "Messages queued on a channel", &["channel"]),
+            capacity: gauge(&registry, "liminal_channel_capacity", "Capacity of a bounded channel", &["channel"]),
+            resident: gauge(&registry, "liminal_process_resident_bytes", "Resident set size of the process", &[]),
+            heap: gauge(&registry, "liminal_heap_allocated_bytes", "Heap allocated and not yet freed", &[]),
+            state_entries: gauge(&registry, "liminal_state_entries", "Entries in a keyed state store", &["store"]),
+            state_bytes: gauge(&registry, "liminal_state_bytes", "Estimated size of a keyed state store", &["store"]),
+            registry,
+        }
+    }
+
+    /// Metrics handles for one running stage.
+    pub fn stage(&self, stage: &str) -> StageMetrics {
+        StageMetrics {
+            stage: stage.to_string(),
+            messages_in: self.messages_in.with_label_values(&[stage]),
+            errors: self.errors.with_label_values(&[stage]),
+            lagged: self.lagged.with_label_values(&[stage]),
+            sla_violations: self.sla_violations.with_label_values(&[stage]),
+            expired: self.expired.with_label_values(&[stage]),
+            shed: self.shed.with_label_values(&[stage]),
+            slow: self.slow.with_label_values(&[stage]),
+            processing: self.processing.with_label_values(&[stage]),
+            watermark_lag: self.watermark_lag.with_label_values(&[stage]),
+            input_depth: HashMap::new(),
+        }
+    }
+
+    /// Wrap a channel `stage` publishes to, counting the messages it publishes.
+    pub fn count_published(&self, stage: &str, channel: Arc<dyn PubSubChannel<Message>>) -> Arc<dyn PubSubChannel<Message>> {
+        Arc::new(CountedChannel { inner: channel, published: self.messages_out.with_label_values(&[stage]) })
+    }
+
+    /// What `stage` recorded so far, for the run summary.
+    pub fn summarise_stage(&self, stage: &str) -> StageSummary {
+        let label = [stage];
+        let count = |counter: &IntCounterVec| counter.with_label_values(&label).get();
+        let messages_in = count(&self.messages_in);
+        let processing = self.processing.with_label_values(&label);
+        StageSummary {
+            stage: stage.to_string(),
+            messages_in,
+            messages_out: count(&self.messages_out),
+            dropped: count(&self.lagged) + count(&self.shed) + count(&self.expired),
+            errors: count(&self.errors),
+            restarts: count(&self.restarts),
+            latency: latency_summary(&processing),
+            // Sources receive no input, so they have no watermark to lag
+            watermark_lag_ms: (messages_in > 0).then(|| self.watermark_lag.with_label_values(&label).get() * 1000.0),
+        }
+    }
+
+    pub fn record_restart(&self, stage: &str) {
+        self.restarts.with_label_values(&[stage]).inc();
+    }
+
+    /// Record the clock skew estimated for a key (positive when its clock is behind).
+    pub fn record_clock_skew(&self, stage: &str, key: &str, skew_seconds: f64) {
+        self.clock_skew.with_label_values(&[stage, key]).set(skew_seconds);
+    }
+
+    /// Record a message an inference stage could not score.
+    pub fn record_inference_skipped(&self, stage: &str, reason: &str) {
+        self.inference_skipped.with_label_values(&[stage, reason]).inc();
+    }
+
+    /// Copy channel counters and occupancy into the registry.
+    pub fn record_channels(&self, stats: &[(String, ChannelStats)]) {
+        for (channel, stats) in stats {
+            sync_counter(&self.published.with_label_values(&[channel]), stats.published);
+            sync_counter(&self.dropped.with_label_values(&[channel]), stats.dropped);
+            sync_counter(&self.rejected.with_label_values(&[channel]), stats.rejected);
+            self.depth.with_label_values(&[channel]).set(stats.depth as i64);
+            if let Some(capacity) = stats.capacity {
+                self.capacity.with_label_values(&[channel]).set(capacity as i64);
+            }
+        }
+    }
+
+    /// Copy a memory report into the registry. Stores that were dropped since
+    /// the last report are removed.
+    pub fn record_memory(&self, report: &MemoryReport) {
+        if let Some(resident) = report.resident_bytes {
+            self.resident.with_label_values(&[] as &[&str]).set(resident as i64);
+        }
+        if let Some(heap) = report.heap_bytes {
+            self.heap.with_label_values(&[] as &[&str]).set(heap as i64);
+        }
+        self.state_entries.reset();
+        self.state_bytes.reset();
+        // Stores sharing a namespace, such as those of a restarted stage, add up
+        for store in &report.stores {
+            self.state_entries.with_label_values(&[store.store.as_str()]).add(store.entries as i64);
+            if let Some(bytes) = store.bytes {
+                self.state_bytes.with_label_values(&[store.store.as_str()]).add(bytes as i64);
+            }
+        }
+    }
+
+    /// Every metric in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut buffer = Vec::new();
+        if let Err(e) = TextEncoder::new().encode(&self.registry.gather(), &mut buffer) {
+            tracing::warn!("Failed to encode metrics: {}", e);
+        }
+        String::from_utf8(buffer).unwrap_or_default()
+    }
+}
+
+/// Mean and estimated percentiles of the observations of a histogram, or
+/// `None` if it has none. Percentiles are interpolated within buckets, so
+/// they are only as precise as the bucket boundaries.
+fn latency_summary(histogram: &Histogram) -> Option<LatencySummary> {
+    let count = histogram.get_sample_count();
+    if count == 0 {
+        return None;
+    }
+    let metric = histogram.metric();
+    let buckets = metric.get_histogram().get_bucket();
+    let percentile = |q: f64| {
+        let rank = q * count as f64;
+        let mut lower = (0.0, 0);
+        for bucket in buckets {
+            let (bound, cumulative) = (bucket.upper_bound(), bucket.cumulative_count());
+            if cumulative as f64 >= rank {
+                let within = (cumulative - lower.1) as f64;
+                let fraction = if within > 0.0 { (rank - lower.1 as f64) / within } else { 1.0 };
+                return (lower.0 + (bound - lower.0) * fraction) * 1000.0;
+            }
+            lower = (bound, cumulative);
+        }
+        // Beyond the last bucket: its bound is all that is known
+        lower.0 * 1000.0
+    };
+    Some(LatencySummary {
+        mean_ms: histogram.get_sample_sum() / count as f64 * 1000.0,
+        p50_ms: percentile(0.5),
+        p95_ms: percentile(0.95),
+        p99_ms: percentile(0.99),
+    })
+}
+
+/// A channel a stage publishes to, counting what the stage publishes.
+struct CountedChannel {
+    inner: Arc<dyn PubSubChannel<Message>>,
+    published: IntCounter,
+}
+
+#[async_trait]
+impl PubSubChannel<Message> for CountedChannel {
+    async fn publish(&self, msg: Message) -> Result<(), PublishError<Message>> {
+        self.inner.publish(msg).await?;
+        self.published.inc();
+        Ok(())
+    }
+
+    fn subscribe(&self) -> Subscriber<Message> {
+        self.inner.subscribe()
+    }
+
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    fn capacity(&self) -> Option<usize> {
+        self.inner.capacity()
+    }
+}
+
+/// Metrics of one running stage, resolved once so recording is cheap.
+pub struct StageMetrics {
+    stage: String,
+    messages_in: IntCounter,
+    errors: IntCounter,
+    lagged: IntCounter,
+    sla_violations: IntCounter,
+    expired: IntCounter,
+    shed: IntCounter,
+    slow: IntCounter,
+    processing: Histogram,
+    watermark_lag: Gauge,
+    input_depth: HashMap<String, IntGauge>,
+}
+
+impl StageMetrics {
+    pub fn record_received(&self, count: u64) {
+        self.messages_in.inc_by(count);
+    }
+
+    pub fn record_error(&self) {
+        self.errors.inc();
+    }
+
+    pub fn record_lagged(&self, skipped: u64) {
+        self.lagged.inc_by(skipped);
+    }
+
+    pub fn record_sla_violations(&self, count: u64) {
+        self.sla_violations.inc_by(count);
+    }
+
+    pub fn record_expired(&self, count: u64) {
+        self.expired.inc_by(count);
+    }
+
+    pub fn record_shed(&self, count: u64) {
+        self.shed.inc_by(count);
+    }
+
+    pub fn record_slow(&self) {
+        self.slow.inc();
+    }
+
+    pub fn record_processing(&self, elapsed: Duration) {
+        self.processing.observe(elapsed.as_secs_f64());
+    }
+
+    pub fn record_watermark(&self, watermark: SystemTime) {
+        let lag = SystemTime::now().duration_since(watermark).unwrap_or_default();
+        self.watermark_lag.set(lag.as_secs_f64());
+    }
+
+    /// Record the delivery results a sink reported. The series are created on
+    /// the first report, so stages that are not sinks do not export them.
+    pub fn record_delivery(&self, report: &DeliveryReport) {
+        let metrics = metrics();
+        let stage = [self.stage.as_str()];
+        metrics.delivered.with_label_values(&stage).inc_by(report.delivered);
+        metrics.retried.with_label_values(&stage).inc_by(report.retried);
+        metrics.delivery_failed.with_label_values(&stage).inc_by(report.failed);
+        metrics.dead_lettered.with_label_values(&stage).inc_by(report.dead_lettered);
+        if let Some(since) = report.last_success.and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok()) {
+            metrics.last_success.with_label_values(&stage).set(since.as_secs_f64());
+        }
+    }
+
+    pub fn record_input_depth(&mut self, input: &str, depth: usize) {
+        if !self.input_depth.contains_key(input) {
+            let gauge = metrics().input_depth.with_label_values(&[self.stage.as_str(), input]);
+            self.input_depth.insert(input.to_string(), gauge);
+        }
+        if let Some(gauge) = self.input_depth.get(input) {
+            gauge.set(depth as i64);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metrics_render_stage_and_channel_counters() {
+        let metrics = metrics();
+        let mut stage = metrics.stage("test_stage");
+        stage.record_received(3);
+        stage.record_error();
+        stage.record_lagged(4);
+        stage.record_input_depth("readings", 7);
+        stage.record_delivery(&DeliveryReport { delivered: 5, failed: 2, dead_lettered: 1, ..Default::default() });
+        metrics.record_channels(&[("test_channel".to_string(), ChannelStats { published: 5, dropped: 1, rejected: 0, ..Default::default() })]);
+        // Channel counters follow the channel's own totals
+        metrics.record_channels(&[("test_channel".to_string(), ChannelStats { published: 8, dropped: 1, rejected: 0, depth: 2, capacity: Some(16) })]);
+
+        let rendered = metrics.render();
+        assert!(rendered.contains(r#"liminal_stage_messages_in_total{stage="test_stage"} 3"#));
+        assert!(rendered.contains(r#"liminal_stage_errors_total{stage="test_stage"} 1"#));
+        assert!(rendered.contains(r#"liminal_stage_lagged_total{stage="test_stage"} 4"#));
+        assert!(rendered.contains(r#"liminal_stage_input_depth{input="readings",stage="test_stage"} 7"#));
+        assert!(rendered.contains(r#"liminal_sink_delivered_total{stage="test_stage"} 5"#));
+        assert!(rendered.contains(r#"liminal_sink_failed_total{stage="test_stage"} 2"#));
+        assert!(rendered.contains(r#"liminal_sink_dead_lettered_total{stage="test_stage"} 1"#));
+        assert!(rendered.contains(r#"liminal_channel_published_total{channel="test_channel"} 8"#));
+        assert!(rendered.contains(r#"liminal_channel_depth{channel="test_channel"} 2"#));
+        assert!(rendered.contains(r#"liminal_channel_capacity{channel="test_channel"} 16"#));
+    }
+
+    #[test]
+    fn test_stage_summary_estimates_latency_percentiles() {
+        let metrics = metrics();
+        let stage = metrics.stage("summary_stage");
+        assert_eq!(metrics.summarise_stage("summary_stage").latency, None);
+
+        stage.record_received(100);
+        for _ in 0..90 {
+            stage.record_processing(Duration::from_micros(200));
+        }
+        for _ in 0..10 {
+            stage.record_processing(Duration::from_millis(5));
+        }
+        stage.record_shed(2);
+
+        let summary = metrics.summarise_stage("summary_stage");
+        assert_eq!((summary.messages_in, summary.dropped, summary.errors), (100, 2, 0));
+        let latency = summary.latency.unwrap();
+        assert!((latency.mean_ms - 0.68).abs() < 1e-6);
+        // Each percentile falls in the bucket holding its observations
+        assert!(latency.p50_ms > 0.1 && latency.p50_ms <= 0.4, "{:?}", latency);
+        assert!(latency.p99_ms > 1.6 && latency.p99_ms <= 6.4, "{:?}", latency);
+    }
+}
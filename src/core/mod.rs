@@ -1,8 +1,27 @@
+pub mod admin;
+pub mod backfill;
+pub mod batch;
 pub mod channel;
+pub mod capture;
+pub mod checkpoint;
+pub mod conditions;
 pub mod context;
+pub mod control;
+pub mod fanin;
+pub mod lease;
+pub mod memory;
 pub mod message;
+pub mod metrics;
+pub mod pool;
 pub mod pipeline;
+pub mod queue;
 pub mod registry;
+pub mod replica;
+pub mod service;
 pub mod stage;
+pub mod state;
+pub mod summary;
+pub mod supervisor;
+pub mod tap;
 pub mod timing;
 pub mod timing_mixin;
\ No newline at end of file
@@ -1,13 +1,33 @@
-use super::registry::ChannelRegistry;
+use super::admin::{self, AdminState, StageInfo};
+use super::backfill::BackfillRange;
+use super::capture::Recorder;
+use super::checkpoint::CheckpointStore;
+use super::conditions::{self, ConditionChange};
+use super::context::DeliveryReport;
+use super::control::{self, ControlCommand, ControlRequest, Target};
+use super::lease::Lease;
+use super::memory::MemoryReport;
+use super::metrics::metrics;
+use super::pool::{self, WorkerPool};
+use super::registry::{ChannelRegistry, ProducerChannel};
+use super::replica::{self, replica_name};
+use super::service::{self, Signal};
 use super::stage::{ControlMessage, Stage, create_stage};
+use super::summary::RunSummary;
+use super::supervisor::{self, Health};
+use super::tap::Taps;
 use crate::config::{Config, StageConfig};
+use crate::config::types::{ChannelConfig, ConcurrencyConfig, ConcurrencyType, CredentialsConfig, RuntimeConfig};
 use crate::core::channel::PubSubChannel;
 use crate::core::message::Message;
+use crate::error::LiminalError;
 
-use anyhow::Result;
-use std::collections::HashMap;
+use anyhow::{Context, Result};
+use serde_json::{Value, json};
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
 
 /// Represents a pipeline consisting of multiple stages.
 struct Pipeline {
@@ -16,18 +36,100 @@ struct Pipeline {
     stage_names: Vec<String>,
 }
 
+/// A running stage: its control channel and the task that owns it.
+struct StageHandle {
+    control: mpsc::Sender<ControlMessage>,
+    task: tokio::task::JoinHandle<()>,
+}
+
+/// A runtime from the `[runtimes]` section, shut down without waiting for
+/// its tasks when the manager is dropped (from within another runtime).
+struct DedicatedRuntime(Option<tokio::runtime::Runtime>);
+
+impl DedicatedRuntime {
+    fn start(name: &str, config: &RuntimeConfig) -> Result<Self> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(config.worker_threads)
+            .thread_name(format!("liminal-{}", name))
+            .enable_all()
+            .build()
+            .with_context(|| format!("Failed to start runtime '{}'", name))?;
+        tracing::info!("Started runtime '{}' with {} worker thread(s)", name, config.worker_threads);
+        Ok(Self(Some(runtime)))
+    }
+
+    fn spawn<F>(&self, future: F) -> tokio::task::JoinHandle<()>
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        self.0.as_ref().expect("runtime is only taken on drop").spawn(future)
+    }
+}
+
+impl Drop for DedicatedRuntime {
+    fn drop(&mut self) {
+        if let Some(runtime) = self.0.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+/// Reported by a stage task when its stage stops.
+struct StageExit {
+    name: String,
+    /// Whether the stage stopped cleanly (completed, drained or terminated)
+    clean: bool,
+}
+
+/// Why a run ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exit {
+    /// The stages completed, or were shut down
+    Stopped,
+    /// The stages were shut down for the configuration to be reloaded
+    Reload,
+}
+
 /// Manages the creation and connection of stages and pipelines.
+///
+/// Stages are owned by the manager until they are started; each running stage
+/// is then owned exclusively by its task and managed through its control
+/// channel. A configured stage maps to one running stage per replica. Each
+/// running stage is supervised and restarted on failure as configured.
 pub struct PipelineManager {
     config: Config,
-    stages: HashMap<String, Arc<Mutex<Box<Stage>>>>,
+    stages: HashMap<String, Vec<Stage>>,
     pipelines: HashMap<String, Pipeline>,
     channel_registry: ChannelRegistry<Message>,
-    control_channel: Option<Arc<tokio::sync::broadcast::Sender<ControlMessage>>>,
-    stage_handles: HashMap<String, tokio::task::JoinHandle<()>>,
+    /// Channels published to by more than one stage, with their settings
+    shared_channels: HashMap<String, ChannelConfig>,
+    /// Records the channels listed in the `[record]` section, if any
+    recorder: Option<Recorder>,
+    /// Where the control API attaches taps to the channels stages publish to
+    taps: Arc<Taps>,
+    stage_handles: HashMap<String, StageHandle>,
+    /// Runtimes of the `[runtimes]` section, by name
+    runtimes: HashMap<String, DedicatedRuntime>,
+    health: Arc<Health>,
+    /// Lease held in active/standby operation
+    lease: Option<Lease>,
+    /// Event times the sources read in a back-fill run
+    backfill: Option<BackfillRange>,
+    /// When the stages were started, for the run summary
+    started: Option<Instant>,
+    /// File the run summary is also written to, as JSON
+    summary_path: Option<String>,
+    /// Whether to reload on SIGHUP and notify the service manager
+    service: bool,
+    /// Background renewal of the lease, and where its loss is reported
+    lease_keeper: Option<(tokio::task::JoinHandle<()>, mpsc::UnboundedReceiver<String>)>,
+    exit_sender: mpsc::UnboundedSender<StageExit>,
+    exit_receiver: mpsc::UnboundedReceiver<StageExit>,
 }
 
 impl PipelineManager {
-    /// Create a new `PipelineManager` with the given configuration.
+    /// Create a new `PipelineManager` with the given configuration. Stages
+    /// and pipelines turned off with `enabled = false` are left out.
     ///
     /// # Arguments
     ///
@@ -36,17 +138,74 @@ impl PipelineManager {
     /// # Returns
     ///
     /// A new instance of `PipelineManager`.
-    pub fn new(config: Config) -> Self {
+    pub fn new(mut config: Config) -> Self {
+        config.remove_disabled();
+        let (exit_sender, exit_receiver) = mpsc::unbounded_channel();
         Self {
             config,
             stages: HashMap::new(),
             pipelines: HashMap::new(),
             channel_registry: ChannelRegistry::new(),
-            control_channel: None,
+            shared_channels: HashMap::new(),
+            recorder: None,
+            taps: Arc::new(Taps::new()),
             stage_handles: HashMap::new(),
+            runtimes: HashMap::new(),
+            health: Arc::new(Health::default()),
+            lease: None,
+            backfill: None,
+            started: None,
+            summary_path: None,
+            service: false,
+            lease_keeper: None,
+            exit_sender,
+            exit_receiver,
         }
     }
 
+    /// Run as the active instance under `lease`. It is renewed from now on,
+    /// and the stages are stopped at once if it is lost.
+    pub fn with_lease(mut self, lease: Lease) -> Self {
+        self.lease_keeper = Some(lease.keep());
+        self.lease = Some(lease);
+        self
+    }
+
+    /// Write the run summary to `path` as JSON when the run ends, as well as
+    /// logging it.
+    pub fn with_summary(mut self, path: &str) -> Self {
+        self.summary_path = Some(path.to_string());
+        self
+    }
+
+    /// Run under a service manager: SIGHUP ends the run with `Exit::Reload`,
+    /// and systemd is told when shutdown or a reload begins.
+    pub fn with_service(mut self) -> Self {
+        self.service = true;
+        self
+    }
+
+    /// Run as a back-fill of `range`: every source reads its history, and
+    /// watermarks follow the event times of the data. Checkpoints are neither
+    /// restored nor written, so the run starts from empty state and leaves the
+    /// live pipeline's checkpoints alone. Call before `build_all`.
+    pub fn with_backfill(mut self, range: BackfillRange) -> Self {
+        if self.config.checkpoint.take().is_some() {
+            tracing::info!("Checkpointing is disabled for the back-fill");
+        }
+        let stages = self
+            .config
+            .inputs
+            .values_mut()
+            .chain(self.config.pipelines.values_mut().flat_map(|pipeline| pipeline.stages.values_mut()))
+            .chain(self.config.outputs.values_mut());
+        for stage_config in stages {
+            stage_config.timing.get_or_insert_with(Default::default).event_clock = true;
+        }
+        self.backfill = Some(range);
+        self
+    }
+
     /// Get all stage configurations from the config.
     fn get_all_stage_configs(&self) -> Vec<(String, StageConfig)> {
         let mut all_stages = Vec::new();
@@ -68,6 +227,51 @@ impl PipelineManager {
         all_stages
     }
 
+    /// Names the stage runs under: its own, or one per replica.
+    fn running_names(stage_name: &str, stage_config: &StageConfig) -> Vec<String> {
+        match stage_config.replicas.unwrap_or(1) {
+            1 => vec![stage_name.to_string()],
+            replicas => (0..replicas).map(|index| replica_name(stage_name, index)).collect(),
+        }
+    }
+
+    /// Channels published to by more than one stage, with the settings they
+    /// are created with: those of the first producer that sets any (validation
+    /// ensures the others agree). Replicas of a stage count as one producer.
+    fn find_shared_channels(&self) -> HashMap<String, ChannelConfig> {
+        let mut producers: HashMap<String, (usize, Option<ChannelConfig>)> = HashMap::new();
+        for (_, stage_config) in self.get_all_stage_configs() {
+            let outputs: HashSet<&String> = stage_config
+                .output
+                .iter()
+                .chain(stage_config.side_outputs.iter().flatten())
+                .collect();
+            for output in outputs {
+                let (count, channel_config) = producers.entry(output.clone()).or_default();
+                *count += 1;
+                if channel_config.is_none() {
+                    *channel_config = stage_config.channel.clone();
+                }
+            }
+        }
+
+        producers
+            .into_iter()
+            .filter(|(_, (count, _))| *count > 1)
+            .map(|(name, (_, channel_config))| (name, channel_config.unwrap_or_default()))
+            .collect()
+    }
+
+    /// Whether `upstream` publishes to any of the inputs of `stage_config`.
+    fn feeds(upstream: &StageConfig, stage_config: &StageConfig) -> bool {
+        let outputs: Vec<&String> = upstream.output.iter().chain(upstream.side_outputs.iter().flatten()).collect();
+        stage_config
+            .inputs
+            .iter()
+            .flatten()
+            .any(|input| outputs.contains(&input))
+    }
+
     /// Check if all inputs for a stage are available in the channel registry.
     fn are_all_inputs_available(
         channel_registry: &ChannelRegistry<Message>,
@@ -87,14 +291,14 @@ impl PipelineManager {
     /// Map inputs from the stage configuration to the channel registry.
     async fn map_inputs(
         channel_registry: &mut ChannelRegistry<Message>,
-        stage: &Arc<Mutex<Box<Stage>>>,
+        stage: &mut Stage,
         stage_config: &StageConfig,
     ) -> Result<()> {
         if let Some(inputs) = &stage_config.inputs {
             for input_name in inputs {
                 if let Some(channel) = channel_registry.get(input_name) {
                     let subscriber = channel.subscribe();
-                    stage.lock().await.add_input(input_name, subscriber).await;
+                    stage.add_input(input_name, subscriber).await;
                 } else {
                     return Err(anyhow::anyhow!("Input channel '{}' not found", input_name));
                 }
@@ -104,21 +308,79 @@ impl PipelineManager {
         Ok(())
     }
 
-    /// Create an output channel for the stage if specified in the configuration.
+    /// Map inputs shared by the replicas of a stage: each input is subscribed
+    /// to once and dispatched across the replicas.
+    async fn map_replica_inputs(
+        channel_registry: &mut ChannelRegistry<Message>,
+        stage_name: &str,
+        replicas: &mut [Stage],
+        stage_config: &StageConfig,
+    ) -> Result<()> {
+        let capacity = stage_config
+            .channel
+            .as_ref()
+            .map_or_else(|| ChannelConfig::default().capacity, |channel| channel.capacity);
+
+        for input_name in stage_config.inputs.iter().flatten() {
+            let Some(channel) = channel_registry.get(input_name) else {
+                return Err(anyhow::anyhow!("Input channel '{}' not found", input_name));
+            };
+
+            let subscribers = replica::dispatch(
+                stage_name,
+                channel.subscribe(),
+                replicas.len(),
+                stage_config.partition_by.clone(),
+                capacity,
+            );
+            for (stage, subscriber) in replicas.iter_mut().zip(subscribers) {
+                stage.add_input(input_name, subscriber).await;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Create an output channel for the stage if specified in the configuration,
+    /// along with any side output channels (which share the stage's channel settings).
+    /// The stage publishes to recorded channels through the recorder, to
+    /// shared channels under its configured name as their producer, and to
+    /// every channel through its tap point, counting what it publishes
+    /// unless its metrics are disabled.
     async fn create_output(
         channel_registry: &mut ChannelRegistry<Message>,
-        stage: &Arc<Mutex<Box<Stage>>>,
+        shared_channels: &HashMap<String, ChannelConfig>,
+        recorder: Option<&Recorder>,
+        taps: &Taps,
+        stage_name: &str,
+        stage: &mut Stage,
         stage_config: &StageConfig,
     ) -> Result<()> {
+        let counted = stage.has_metrics().then(|| stage.name().to_string());
+        let mut open = |name: &str| -> Result<Arc<dyn PubSubChannel<Message>>> {
+            let shared = shared_channels.get(name);
+            let channel_config = shared.cloned().or_else(|| stage_config.channel.clone()).unwrap_or_default();
+            let mut channel: Arc<dyn PubSubChannel<Message>> = channel_registry.get_or_create(name, &channel_config)?;
+            if shared.is_some() {
+                channel = Arc::new(ProducerChannel::new(stage_name, channel));
+            }
+            if let Some(recorder) = recorder {
+                channel = recorder.tap(name, channel);
+            }
+            if let Some(stage) = &counted {
+                channel = metrics().count_published(stage, channel);
+            }
+            Ok(taps.wrap(name, channel))
+        };
+
         if let Some(output_name) = &stage_config.output {
-            let channel_config = stage_config.channel.clone().unwrap_or_default();
-            let channel = channel_registry.get_or_create(
-                output_name,
-                channel_config.r#type.clone(),
-                channel_config.capacity,
-            );
+            let channel = open(output_name)?;
+            stage.add_output(output_name, channel).await;
+        }
 
-            stage.lock().await.add_output(&output_name, channel.clone()).await;
+        for side_output_name in stage_config.side_outputs.iter().flatten() {
+            let channel = open(side_output_name)?;
+            stage.add_side_output(side_output_name, channel).await;
         }
 
         Ok(())
@@ -130,7 +392,7 @@ impl PipelineManager {
         stage_name: &str,
         stage_config: &StageConfig,
     ) -> Result<()> {
-        let stage = self
+        let replicas = self
             .stages
             .get_mut(stage_name)
             .ok_or_else(|| anyhow::anyhow!("Stage not found: '{}'", stage_name))?;
@@ -142,9 +404,25 @@ impl PipelineManager {
             ));
         }
 
-        Self::map_inputs(&mut self.channel_registry, stage, stage_config).await?;
+        if let [stage] = replicas.as_mut_slice() {
+            Self::map_inputs(&mut self.channel_registry, stage, stage_config).await?;
+        } else {
+            Self::map_replica_inputs(&mut self.channel_registry, stage_name, replicas, stage_config).await?;
+        }
 
-        Self::create_output(&mut self.channel_registry, stage, stage_config).await?;
+        // Replicas publish to the same output channels
+        for stage in replicas.iter_mut() {
+            Self::create_output(
+                &mut self.channel_registry,
+                &self.shared_channels,
+                self.recorder.as_ref(),
+                &self.taps,
+                stage_name,
+                stage,
+                stage_config,
+            )
+            .await?;
+        }
 
         Ok(())
     }
@@ -177,20 +455,22 @@ impl PipelineManager {
         }
 
         if !deferred_stages.is_empty() {
-            return Err(anyhow::anyhow!(
-                "Unmet or circular dependencies detected in stages: {:?}",
-                deferred_stages
-                    .iter()
-                    .map(|(name, _)| name)
-                    .collect::<Vec<_>>()
-            ));
+            let mut stages: Vec<String> = deferred_stages.into_iter().map(|(name, _)| name).collect();
+            stages.sort();
+            return Err(LiminalError::UnresolvedInputs { stages }.into());
         }
 
         Ok(())
     }
 
-    /// Connect all stages by resolving their dependencies.
+    /// Connect all stages by resolving their dependencies, starting a
+    /// recording first if the configuration asks for one.
     pub async fn connect_stages(mut self) -> Result<Self> {
+        if let Some(record) = &self.config.record {
+            self.recorder = Some(Recorder::start(record)?);
+        }
+        self.shared_channels = self.find_shared_channels();
+
         let all_stages = self.get_all_stage_configs();
         let mut deferred_stages = Vec::new();
 
@@ -205,23 +485,26 @@ impl PipelineManager {
         Ok(self)
     }
 
-    /// Create stages based on the provided stage configurations.
+    /// Create stages based on the provided stage configurations, giving each
+    /// the credentials it names.
     fn create_stages(
         stage_configs: &HashMap<String, StageConfig>,
-    ) -> Result<HashMap<String, Arc<Mutex<Box<Stage>>>>> {
-        let mut stages: HashMap<String, Arc<Mutex<Box<Stage>>>> = HashMap::new();
+        credentials: &HashMap<String, CredentialsConfig>,
+    ) -> Result<HashMap<String, Vec<Stage>>> {
+        let mut stages: HashMap<String, Vec<Stage>> = HashMap::new();
 
         for (stage_name, stage_config) in stage_configs {
-            println!("{} => {:?}", stage_name, stage_config);
+            tracing::debug!("{} => {:?}", stage_name, stage_config);
             
             // Use the type as name of the stage
             // if let Some(stage) = create_stage(&stage_config.r#type, stage_config.clone()) {            
             
-            if let Some(stage) = create_stage(&stage_name, stage_config.clone()) {
-                stages.insert(stage_name.clone(), Arc::new(Mutex::new(stage)));
-            } else {
-                return Err(anyhow::anyhow!("Failed to create stage: '{}'", stage_name));
+            let stage_config = stage_config.with_credentials(credentials);
+            let mut replicas = Vec::new();
+            for name in Self::running_names(stage_name, &stage_config) {
+                replicas.push(*create_stage(&name, stage_config.clone())?);
             }
+            stages.insert(stage_name.clone(), replicas);
         }
 
         Ok(stages)
@@ -230,15 +513,12 @@ impl PipelineManager {
     /// Create pipelines and their stages based on the provided pipeline configurations.
     fn create_pipelines(
         &mut self,
-    ) -> Result<(
-        HashMap<String, Arc<Mutex<Box<Stage>>>>,
-        HashMap<String, Pipeline>,
-    )> {
+    ) -> Result<(HashMap<String, Vec<Stage>>, HashMap<String, Pipeline>)> {
         let mut pipelines = HashMap::new();
         let mut stages = HashMap::new();
 
         for (pipeline_name, pipeline_config) in &self.config.pipelines {
-            let created_stages = Self::create_stages(&pipeline_config.stages)?;
+            let created_stages = Self::create_stages(&pipeline_config.stages, &self.config.credentials)?;
 
             stages.extend(created_stages);
 
@@ -257,11 +537,11 @@ impl PipelineManager {
     /// Build all stages and pipelines based on the provided configuration.
     pub fn build_all(mut self) -> Result<Self> {
         // Create input stages
-        let input_stages = Self::create_stages(&self.config.inputs)?;
+        let input_stages = Self::create_stages(&self.config.inputs, &self.config.credentials)?;
         self.stages.extend(input_stages);
 
         // Create output stages
-        let output_stages = Self::create_stages(&self.config.outputs)?;
+        let output_stages = Self::create_stages(&self.config.outputs, &self.config.credentials)?;
         self.stages.extend(output_stages);
 
         // Create pipelines and pipeline stages
@@ -269,77 +549,788 @@ impl PipelineManager {
         self.stages.extend(pipeline_stages);
         self.pipelines.extend(pipelines);
 
-        // Create the control channel
-        let (control_channel, _) = tokio::sync::broadcast::channel::<ControlMessage>(128);
-        self.control_channel = Some(Arc::new(control_channel));
+        Ok(self)
+    }
+
+    /// Check that the brokers and servers the stages connect to are
+    /// reachable, so that a pipeline fails before it starts rather than
+    /// retrying in the background.
+    ///
+    /// The checks run concurrently, one per stage (replicas share their
+    /// endpoints), each bounded by `timeout`. Every failure is logged before
+    /// the failing stages are reported together.
+    pub async fn preflight(self, timeout: Duration) -> Result<Self> {
+        let checks = self.stages.iter().filter_map(|(name, replicas)| {
+            let stage = replicas.first()?;
+            Some(async move {
+                let result = match tokio::time::timeout(timeout, stage.preflight()).await {
+                    Ok(result) => result,
+                    Err(_) => Err(anyhow::anyhow!("no answer within {:?}", timeout)),
+                };
+                (name, result)
+            })
+        });
+
+        let mut failed = Vec::new();
+        for (name, result) in futures::future::join_all(checks).await {
+            if let Err(e) = result {
+                tracing::error!("Preflight check of stage '{}' failed: {:#}", name, e);
+                failed.push(name.clone());
+            }
+        }
+        if !failed.is_empty() {
+            failed.sort();
+            return Err(LiminalError::PreflightFailed { stages: failed }.into());
+        }
 
+        tracing::info!("Preflight checks passed");
         Ok(self)
     }
 
     /// Start all stages in the pipeline.
+    ///
+    /// When checkpointing is configured, each stage is restored from its last
+    /// checkpoint before it is initialised.
+    /// Each replica of a replicated stage runs as its own task under its
+    /// replica name (e.g. `scale[1]`), which also names its checkpoint.
+    /// Stages of a pipeline with a `runtime` run on that runtime's threads.
     pub async fn start_all(mut self) -> Result<Self> {
         tracing::info!("Starting all stages");
+        self.started = Some(Instant::now());
+        let checkpoint = match &self.config.checkpoint {
+            Some(config) => Some((
+                Arc::new(CheckpointStore::open(&config.directory)?),
+                Duration::from_millis(config.interval_ms),
+            )),
+            None => None,
+        };
+
+        for (name, config) in &self.config.runtimes {
+            self.runtimes.insert(name.clone(), DedicatedRuntime::start(name, config)?);
+        }
+        let stage_runtimes: HashMap<String, String> = self
+            .config
+            .pipelines
+            .values()
+            .filter_map(|pipeline| Some((pipeline, pipeline.runtime.as_ref()?)))
+            .flat_map(|(pipeline, runtime)| pipeline.stages.keys().map(move |stage| (stage.clone(), runtime.clone())))
+            .collect();
+        let stage_pools = self.worker_pools();
+
         let all_stages = self.get_all_stage_configs();
-        for (stage_name, _) in all_stages {
-            if let Some(stage) = self.stages.get_mut(&stage_name) {
-                // Setup stage and wire control channel
+        for (configured_name, stage_config) in all_stages {
+            let Some(replicas) = self.stages.remove(&configured_name) else {
+                continue;
+            };
+
+            for mut stage in replicas {
+                let stage_name = stage.name().to_string();
+
+                // Wire the stage's control channel
+                let (control, control_channel) = mpsc::channel::<ControlMessage>(16);
+                stage.attach_control_channel(control_channel);
+                stage.attach_health(Arc::clone(&self.health));
+                if let Some(range) = &self.backfill
+                    && self.config.inputs.contains_key(&configured_name)
                 {
-                    let stage_clone = Arc::clone(stage);
-                    let mut stage = stage_clone.lock().await;
+                    stage.backfill(range)?;
+                }
+                if let Some((pool, concurrency)) = stage_pools.get(&configured_name) {
+                    stage.attach_pool(
+                        Arc::clone(pool),
+                        concurrency.batch.unwrap_or(pool::DEFAULT_BATCH),
+                        concurrency.max_in_flight.unwrap_or(pool::DEFAULT_MAX_IN_FLIGHT),
+                    );
+                }
 
-                    // Attach the control channel if available
-                    if let Some(control_channel) = &self.control_channel {
-                        stage.attach_control_channel(control_channel.subscribe());
-                    }
+                // Attach checkpointing and restore the last checkpoint
+                if let Some((store, interval)) = &checkpoint {
+                    stage.attach_checkpoint(Arc::clone(store), *interval);
+                    stage.restore_checkpoint()?;
+                }
+
+                // Initialise stage (and processor)
+                stage.init().await?;
+
+                // Hand the stage over to its own supervised task
+                let stage_name_clone = stage_name.clone();
+                let restart = stage_config.restart.clone().unwrap_or_default();
+                let health = Arc::clone(&self.health);
+                let exits = self.exit_sender.clone();
+                let run = async move {
+                    let clean = supervisor::supervise(stage, restart, &health).await;
+                    let _ = exits.send(StageExit { name: stage_name_clone, clean });
+                };
+                let runtime = stage_runtimes.get(&configured_name).and_then(|name| self.runtimes.get(name));
+                let task = match runtime {
+                    Some(runtime) => runtime.spawn(run),
+                    None => tokio::spawn(run),
+                };
+
+                self.stage_handles.insert(stage_name, StageHandle { control, task });
+            }
+        }
+
+        Ok(self)
+    }
+
+    /// One worker pool per pipeline with `pipeline` concurrency stages, sized
+    /// by the most workers any of them asks for, by the stages sharing it.
+    fn worker_pools(&self) -> HashMap<String, (Arc<WorkerPool>, ConcurrencyConfig)> {
+        let mut stage_pools = HashMap::new();
+        for (pipeline_name, pipeline) in &self.config.pipelines {
+            let pooled: Vec<_> = pipeline
+                .stages
+                .iter()
+                .filter_map(|(name, stage)| Some((name, stage.concurrency.as_ref()?)))
+                .filter(|(_, concurrency)| concurrency.r#type == ConcurrencyType::Pipeline)
+                .collect();
+            if pooled.is_empty() {
+                continue;
+            }
+
+            let workers = pooled
+                .iter()
+                .filter_map(|(_, concurrency)| concurrency.workers)
+                .max()
+                .unwrap_or(1);
+            tracing::info!(
+                "Pipeline '{}' shares {} worker(s) between {} stage(s)",
+                pipeline_name,
+                workers,
+                pooled.len()
+            );
+            let pool = Arc::new(WorkerPool::new(workers));
+            for (name, concurrency) in pooled {
+                stage_pools.insert(name.clone(), (Arc::clone(&pool), concurrency.clone()));
+            }
+        }
+        stage_pools
+    }
+
+    /// Health of the running stages.
+    pub fn health(&self) -> Arc<Health> {
+        Arc::clone(&self.health)
+    }
+
+    /// The configured stages and pipelines, as JSON.
+    pub fn topology(&self) -> Value {
+        let stage_json = |stage_name: &String, stage_config: &StageConfig| {
+            json!({
+                "type": stage_config.r#type,
+                "inputs": stage_config.inputs.clone().unwrap_or_default(),
+                "output": stage_config.output,
+                "side_outputs": stage_config.side_outputs.clone().unwrap_or_default(),
+                "replicas": Self::running_names(stage_name, stage_config),
+            })
+        };
+        let stages_json = |stages: &HashMap<String, StageConfig>| -> serde_json::Map<String, Value> {
+            stages.iter().map(|(name, config)| (name.clone(), stage_json(name, config))).collect()
+        };
+
+        let pipelines: serde_json::Map<String, Value> = self
+            .config
+            .pipelines
+            .iter()
+            .map(|(name, pipeline_config)| {
+                (
+                    name.clone(),
+                    json!({
+                        "description": pipeline_config.description,
+                        "stages": stages_json(&pipeline_config.stages),
+                        "runtime": pipeline_config.runtime,
+                    }),
+                )
+            })
+            .collect();
+
+        json!({
+            "inputs": stages_json(&self.config.inputs),
+            "pipelines": pipelines,
+            "outputs": stages_json(&self.config.outputs),
+        })
+    }
+
+    /// State the admin server reads from.
+    fn admin_state(&self) -> AdminState {
+        let stages = self
+            .get_all_stage_configs()
+            .into_iter()
+            .map(|(stage_name, stage_config)| {
+                let info = StageInfo {
+                    replicas: Self::running_names(&stage_name, &stage_config),
+                    outputs: stage_config
+                        .output
+                        .iter()
+                        .chain(stage_config.side_outputs.iter().flatten())
+                        .cloned()
+                        .collect(),
+                };
+                (stage_name, info)
+            })
+            .collect();
+
+        AdminState {
+            health: Arc::clone(&self.health),
+            topology: self.topology(),
+            stages,
+            channels: self.channel_registry.clone(),
+        }
+    }
+
+    /// Names of the pipelines with a stage that has failed, sorted.
+    pub fn degraded_pipelines(&self) -> Vec<String> {
+        let mut degraded: Vec<String> = self
+            .config
+            .pipelines
+            .iter()
+            .filter(|(_, pipeline_config)| {
+                pipeline_config.stages.iter().any(|(stage_name, stage_config)| {
+                    Self::running_names(stage_name, stage_config)
+                        .iter()
+                        .any(|name| self.health.stage(name).is_some_and(|health| health.is_degraded()))
+                })
+            })
+            .map(|(name, _)| name.clone())
+            .collect();
+        degraded.sort();
+        degraded
+    }
 
-                    // Initialise stage (and processor)
-                    stage.init().await?;
+    /// Send a control message to a running stage.
+    pub async fn send_control(&self, stage_name: &str, message: ControlMessage) -> Result<()> {
+        let handle = self
+            .stage_handles
+            .get(stage_name)
+            .ok_or_else(|| anyhow::anyhow!("Stage not running: '{}'", stage_name))?;
+
+        handle
+            .control
+            .send(message)
+            .await
+            .map_err(|_| anyhow::anyhow!("Stage '{}' has stopped", stage_name))
+    }
+
+    /// Running names of the stages a control command targets.
+    fn resolve_target(&self, target: &Target) -> Result<Vec<String>> {
+        let all_stages = self.get_all_stage_configs();
+        match (&target.stage, &target.pipeline) {
+            (Some(stage_name), None) => {
+                if let Some((_, stage_config)) = all_stages.iter().find(|(name, _)| name == stage_name) {
+                    Ok(Self::running_names(stage_name, stage_config))
+                } else if self.stage_handles.contains_key(stage_name) {
+                    Ok(vec![stage_name.clone()])
+                } else {
+                    Err(anyhow::anyhow!("Unknown stage: '{}'", stage_name))
                 }
+            }
+            (None, Some(pipeline_name)) => {
+                let pipeline_config = self
+                    .config
+                    .pipelines
+                    .get(pipeline_name)
+                    .ok_or_else(|| anyhow::anyhow!("Unknown pipeline: '{}'", pipeline_name))?;
+                let mut names: Vec<String> = pipeline_config
+                    .stages
+                    .iter()
+                    .flat_map(|(stage_name, stage_config)| Self::running_names(stage_name, stage_config))
+                    .collect();
+                names.sort();
+                Ok(names)
+            }
+            _ => Err(anyhow::anyhow!("Specify either a stage or a pipeline")),
+        }
+    }
+
+    /// Send a control message to each named stage without waiting on a busy
+    /// stage. Returns the stages that took the message.
+    fn signal(&self, names: Vec<String>, message: ControlMessage) -> Result<Value> {
+        let mut signalled = Vec::new();
+        for name in names {
+            let Some(handle) = self.stage_handles.get(&name) else {
+                continue;
+            };
+            if handle.task.is_finished() {
+                continue;
+            }
+            handle
+                .control
+                .try_send(message.clone())
+                .map_err(|e| anyhow::anyhow!("Stage '{}' did not accept {:?}: {}", name, message, e))?;
+            match message {
+                ControlMessage::Pause => self.health.set_paused(&name, true),
+                ControlMessage::Resume => self.health.set_paused(&name, false),
+                _ => {}
+            }
+            signalled.push(name);
+        }
+        Ok(json!({ "stages": signalled }))
+    }
+
+    /// Status of the named stages, and of the pipelines when `pipelines` is set.
+    fn status(&self, names: Vec<String>, pipelines: bool) -> Value {
+        let stages: Vec<Value> = names
+            .iter()
+            .map(|name| match self.health.stage(name) {
+                Some(health) => json!({
+                    "name": name,
+                    "status": health.status,
+                    "restarts": health.restarts,
+                    "last_error": health.last_error,
+                    "slow": health.slow,
+                    "delivery": health.delivery.as_ref().map(DeliveryReport::to_json),
+                }),
+                None => json!({ "name": name, "status": null }),
+            })
+            .collect();
+        if !pipelines {
+            return json!({ "stages": stages });
+        }
+
+        let degraded = self.degraded_pipelines();
+        let mut pipelines: Vec<Value> = self
+            .pipelines
+            .values()
+            .map(|pipeline| {
+                let mut stage_names = pipeline.stage_names.clone();
+                stage_names.sort();
+                json!({
+                    "name": pipeline.name,
+                    "description": pipeline.description,
+                    "stages": stage_names,
+                    "degraded": degraded.contains(&pipeline.name),
+                })
+            })
+            .collect();
+        pipelines.sort_by(|a, b| a["name"].as_str().cmp(&b["name"].as_str()));
+        json!({ "stages": stages, "pipelines": pipelines })
+    }
+
+    /// Carry out a command received through the control API.
+    async fn handle_command(&self, command: ControlCommand) -> Result<Value> {
+        match command {
+            ControlCommand::Pause(target) => self.signal(self.resolve_target(&target)?, ControlMessage::Pause),
+            ControlCommand::Resume(target) => self.signal(self.resolve_target(&target)?, ControlMessage::Resume),
+            ControlCommand::Stop(target) => self.signal(self.resolve_target(&target)?, ControlMessage::Drain),
+            ControlCommand::Inject { channel, payload } => {
+                let output = self
+                    .channel_registry
+                    .get(&channel)
+                    .ok_or_else(|| anyhow::anyhow!("Unknown channel: '{}'", channel))?;
+                output
+                    .publish(Message::new("control", &channel, payload))
+                    .await
+                    .map_err(|e| anyhow::anyhow!("Failed to publish to channel '{}': {:?}", channel, e))?;
+                Ok(json!({ "channel": channel }))
+            }
+            ControlCommand::Status(target) if target.is_empty() => {
+                let mut names: Vec<String> = self.stage_handles.keys().cloned().collect();
+                names.sort();
+                Ok(self.status(names, true))
+            }
+            ControlCommand::Status(target) => Ok(self.status(self.resolve_target(&target)?, false)),
+            // The listener streams taps itself
+            ControlCommand::Tap { .. } => Err(anyhow::anyhow!("Taps are served by the control API listener")),
+        }
+    }
+
+    /// Ask every running stage to terminate.
+    pub async fn terminate_all(&self) {
+        for handle in self.stage_handles.values() {
+            // Stages that already stopped have dropped their receiver
+            let _ = handle.control.send(ControlMessage::Terminate).await;
+        }
+    }
 
-                // Run the stage
+    /// Shut the pipeline down in dependency order.
+    ///
+    /// Sources stop producing first; each downstream stage is then drained
+    /// and flushed once every stage feeding it has stopped, so no in-flight
+    /// message is lost. Stages still running when the drain timeout expires
+    /// are aborted.
+    pub async fn shutdown(&mut self) {
+        let drain_timeout = Duration::from_millis(self.config.shutdown.drain_timeout_ms);
+        let deadline = tokio::time::Instant::now() + drain_timeout;
+        let mut remaining: HashMap<String, StageConfig> = self.get_all_stage_configs().into_iter().collect();
+
+        while !remaining.is_empty() {
+            // Stop the stages nothing still running publishes to; if only a
+            // cycle is left, drain it all at once
+            let mut ready: Vec<String> = remaining
+                .iter()
+                .filter(|(_, config)| !remaining.values().any(|upstream| Self::feeds(upstream, config)))
+                .map(|(name, _)| name.clone())
+                .collect();
+            if ready.is_empty() {
+                ready = remaining.keys().cloned().collect();
+            }
+
+            let mut running = Vec::new();
+            for stage_name in &ready {
+                let Some(stage_config) = remaining.remove(stage_name) else {
+                    continue;
+                };
+                for name in Self::running_names(stage_name, &stage_config) {
+                    if let Some(handle) = self.stage_handles.get(&name) {
+                        // Stages that already stopped have dropped their receiver
+                        let _ = handle.control.try_send(ControlMessage::Drain);
+                        running.push(name);
+                    }
+                }
+            }
+
+            let tasks = self
+                .stage_handles
+                .iter_mut()
+                .filter(|(name, handle)| running.contains(name) && !handle.task.is_finished())
+                .map(|(_, handle)| &mut handle.task);
+            if tokio::time::timeout_at(deadline, futures::future::join_all(tasks)).await.is_err() {
+                break;
+            }
+        }
+
+        for (name, handle) in &self.stage_handles {
+            if !handle.task.is_finished() {
+                tracing::warn!("Stage '{}' did not drain within {:?}; aborting", name, drain_timeout);
+                handle.task.abort();
+            }
+        }
+    }
+
+    /// Drain every running stage whose upstream stages have all completed,
+    /// so end of stream flows from finite sources down to the sinks.
+    fn propagate_completion(&self, completed: &HashSet<String>, draining: &mut HashSet<String>) {
+        let all_stages = self.get_all_stage_configs();
+        for (stage_name, stage_config) in &all_stages {
+            let mut upstream = all_stages
+                .iter()
+                .filter(|(_, upstream)| Self::feeds(upstream, stage_config))
+                .flat_map(|(name, upstream)| Self::running_names(name, upstream))
+                .peekable();
+            if upstream.peek().is_none() || !upstream.all(|name| completed.contains(&name)) {
+                continue;
+            }
+
+            for name in Self::running_names(stage_name, stage_config) {
+                if let Some(handle) = self.stage_handles.get(&name)
+                    && !completed.contains(&name)
+                    && draining.insert(name)
                 {
-                    let stage_clone = Arc::clone(stage);
-                    let stage_name_clone = stage_name.clone();
-
-                    // Spawn a new task to run the stage
-                    let handle = tokio::spawn(async move {
-                        let mut stage_lock = stage_clone.lock().await;
-                        if let Err(e) = stage_lock.run().await {
-                            tracing::error!("Error running stage [{}]: {}", stage_name_clone, e);
-                        }
-                    });
+                    let _ = handle.control.try_send(ControlMessage::Drain);
+                }
+            }
+        }
+    }
 
-                    self.stage_handles.insert(stage_name, handle);
+    /// Wait until every stage has stopped, draining stages as the finite
+    /// sources upstream of them complete. Unlike `wait_for_all`, this neither
+    /// serves the control API and admin server nor handles signals, and the
+    /// manager can still be shut down afterwards, e.g. after a timeout.
+    pub async fn wait_until_stopped(&mut self) {
+        let mut stopped = 0;
+        let mut completed = HashSet::new();
+        let mut draining = HashSet::new();
+        while stopped < self.stage_handles.len() {
+            let Some(exit) = self.exit_receiver.recv().await else {
+                break;
+            };
+            stopped += 1;
+            if exit.clean {
+                completed.insert(exit.name);
+                self.propagate_completion(&completed, &mut draining);
+            }
+        }
+        self.flush_recording().await;
+    }
+
+    /// Write out what has been recorded so far, if recording.
+    async fn flush_recording(&self) {
+        if let Some(recorder) = &self.recorder {
+            recorder.flush(Duration::from_secs(5)).await;
+        }
+    }
+
+    /// Log the depth of every non-empty channel, warning about bounded
+    /// channels filled to at least `warn_fill` of their capacity.
+    fn report_channels(&self, warn_fill: f64) {
+        for (name, stats) in self.channel_registry.stats() {
+            match stats.capacity {
+                Some(capacity) if stats.depth as f64 >= warn_fill * capacity as f64 => tracing::warn!(
+                    "Channel '{}' is {}/{} full; its overflow policy will apply soon",
+                    name,
+                    stats.depth,
+                    capacity
+                ),
+                Some(capacity) if stats.depth > 0 => {
+                    tracing::info!("Channel '{}' depth {}/{}", name, stats.depth, capacity)
                 }
+                None if stats.depth > 0 => tracing::info!("Channel '{}' depth {}", name, stats.depth),
+                _ => {}
             }
         }
+    }
 
-        // futures::future::pending().await;
-        Ok(self)
+    /// Log what each running stage did, and write it to the summary file if
+    /// one was asked for.
+    fn report_summary(&self) {
+        let mut names: Vec<&String> = self.stage_handles.keys().collect();
+        names.sort();
+        let summary = RunSummary::new(
+            self.started.map(|started| started.elapsed()).unwrap_or_default(),
+            names.into_iter().map(|name| metrics().summarise_stage(name)).collect(),
+            self.channel_registry.stats(),
+        );
+        summary.log();
+        if let Some(path) = &self.summary_path {
+            match summary.write(path) {
+                Ok(()) => tracing::info!("Run summary written to '{}'", path),
+                Err(e) => tracing::warn!("{:#}", e),
+            }
+        }
+    }
+
+    /// Pause the stages that pause on a condition that started holding, and
+    /// resume those that no longer have a condition holding.
+    fn apply_condition(&self, change: ConditionChange, holding: &mut HashSet<String>) {
+        if change.holds {
+            tracing::warn!("Condition '{}' holds; pausing the stages that wait on it", change.name);
+            holding.insert(change.name.clone());
+        } else {
+            tracing::info!("Condition '{}' cleared", change.name);
+            holding.remove(&change.name);
+        }
+
+        for (stage_name, stage_config) in self.get_all_stage_configs() {
+            let Some(pause_when) = &stage_config.pause_when else {
+                continue;
+            };
+            if !pause_when.contains(&change.name) {
+                continue;
+            }
+            // Another condition that still holds keeps the stage paused
+            let paused = pause_when.iter().any(|condition| holding.contains(condition));
+            if !change.holds && paused {
+                continue;
+            }
+
+            let message = if paused { ControlMessage::Pause } else { ControlMessage::Resume };
+            if let Err(e) = self.signal(Self::running_names(&stage_name, &stage_config), message) {
+                tracing::warn!("Failed to apply condition '{}' to stage '{}': {}", change.name, stage_name, e);
+            }
+        }
     }
 
-    /// Wait for all stages to complete and handle termination signals.
+    /// Wait for all stages to complete, shutting down gracefully on Ctrl+C or
+    /// SIGTERM.
+    ///
+    /// When finite sources complete, the stages downstream of them drain and
+    /// stop in turn, and this returns once every stage has stopped. Meanwhile,
+    /// commands from the control API (if configured) are answered.
     pub async fn wait_for_all(self) -> Result<()> {
-        let control_channel_clone = self.control_channel.clone();
+        self.wait_for_exit().await.map(|_| ())
+    }
+
+    /// As `wait_for_all`, also reporting why the run ended: in service mode,
+    /// SIGHUP drains and stops the stages and returns `Exit::Reload`, for the
+    /// caller to start again with the reloaded configuration.
+    pub async fn wait_for_exit(mut self) -> Result<Exit> {
+        let control_socket = self.config.control.as_ref().map(|control| control.socket.clone());
+        let mut control_requests: Option<mpsc::Receiver<ControlRequest>> = match &control_socket {
+            Some(socket) => match control::serve(socket, self.taps.clone()) {
+                Ok(requests) => Some(requests),
+                Err(e) => {
+                    tracing::error!("Failed to start control API on '{}': {}", socket, e);
+                    None
+                }
+            },
+            None => None,
+        };
+        let admin_server = match &self.config.admin {
+            Some(config) => match admin::serve(config, self.config.security.as_ref(), self.admin_state()).await {
+                Ok(server) => Some(server),
+                Err(e) => {
+                    tracing::error!("Failed to start admin server on {}:{}: {}", config.host, config.port, e);
+                    None
+                }
+            },
+            None => None,
+        };
+
+        let signal = service::signal(self.service);
+        tokio::pin!(signal);
+
+        let mut channel_report = self
+            .config
+            .channel_report
+            .as_ref()
+            .map(|report| (tokio::time::interval(Duration::from_millis(report.interval_ms)), report.warn_fill));
+        let mut memory_report = self
+            .config
+            .memory_report
+            .as_ref()
+            .map(|report| tokio::time::interval(Duration::from_millis(report.interval_ms)));
+        let mut condition_changes =
+            (!self.config.conditions.is_empty()).then(|| conditions::watch(&self.config.conditions));
+        let mut holding = HashSet::new();
+        let mut lease_keeper = self.lease_keeper.take();
+        let mut fenced = None;
+        let mut exit = Exit::Stopped;
+
+        let mut stopped = 0;
+        let mut completed = HashSet::new();
+        let mut draining = HashSet::new();
+        while stopped < self.stage_handles.len() {
+            tokio::select! {
+                Some(exit) = self.exit_receiver.recv() => {
+                    stopped += 1;
+                    if exit.clean {
+                        completed.insert(exit.name);
+                        self.propagate_completion(&completed, &mut draining);
+                    }
+                }
+                Some(request) = async {
+                    match &mut control_requests {
+                        Some(requests) => requests.recv().await,
+                        None => None,
+                    }
+                }, if control_requests.is_some() => {
+                    let _ = request.reply.send(self.handle_command(request.command).await);
+                }
+                Some(warn_fill) = async {
+                    match &mut channel_report {
+                        Some((interval, warn_fill)) => {
+                            interval.tick().await;
+                            Some(*warn_fill)
+                        }
+                        None => None,
+                    }
+                }, if channel_report.is_some() => {
+                    self.report_channels(warn_fill);
+                }
+                Some(()) = async {
+                    match &mut memory_report {
+                        Some(interval) => {
+                            interval.tick().await;
+                            Some(())
+                        }
+                        None => None,
+                    }
+                }, if memory_report.is_some() => {
+                    let report = MemoryReport::collect();
+                    report.log();
+                    metrics().record_memory(&report);
+                }
+                Some(change) = async {
+                    match &mut condition_changes {
+                        Some(changes) => changes.recv().await,
+                        None => None,
+                    }
+                }, if condition_changes.is_some() => {
+                    self.apply_condition(change, &mut holding);
+                }
+                Some(reason) = async {
+                    match &mut lease_keeper {
+                        Some((_, lost)) => lost.recv().await,
+                        None => None,
+                    }
+                }, if lease_keeper.is_some() => {
+                    // Another instance is taking over: stop without flushing
+                    // or checkpointing, so nothing more is written
+                    tracing::error!("Lost the lease ({}); stopping all stages", reason);
+                    for handle in self.stage_handles.values() {
+                        handle.task.abort();
+                    }
+                    fenced = Some(reason);
+                    break;
+                }
+                received = &mut signal => {
+                    match received {
+                        Signal::Shutdown(name) => {
+                            tracing::info!("Received {} -> shutting down.", name);
+                            if self.service {
+                                service::notify_stopping();
+                            }
+                        }
+                        Signal::Reload => {
+                            tracing::info!("Received reload signal -> draining stages to reload.");
+                            service::notify_reloading();
+                            exit = Exit::Reload;
+                        }
+                    }
+                    self.shutdown().await;
+                    break;
+                }
+            }
+        }
+
+        if let Some(socket) = control_socket
+            && control_requests.is_some()
+        {
+            let _ = std::fs::remove_file(socket);
+        }
+        if let Some(server) = admin_server {
+            server.abort();
+        }
+        if let Some((task, _)) = lease_keeper {
+            task.abort();
+        }
+        if fenced.is_none()
+            && let Some(lease) = &self.lease
+            && let Err(e) = lease.release()
+        {
+            tracing::warn!("Failed to release lease: {}", e);
+        }
+        self.flush_recording().await;
 
-        // Listen for Ctrl+C signal
-        tokio::spawn(async move {
-            if let Err(e) = tokio::signal::ctrl_c().await {
-                tracing::error!("Failed to listen for Ctrl + C: {}", e);
+        // Report stages that failed along the way
+        for (name, health) in self.health.stages() {
+            if health.is_degraded() {
+                tracing::warn!(
+                    "Stage '{}' ended {:?} after {} restart(s); last error: {}",
+                    name,
+                    health.status,
+                    health.restarts,
+                    health.last_error.as_deref().unwrap_or("none")
+                );
             }
+        }
+        let degraded = self.degraded_pipelines();
+        if !degraded.is_empty() {
+            tracing::warn!("Degraded pipelines: {}", degraded.join(", "));
+        }
 
-            tracing::info!("Received Ctrl+C -> shutting down.");
-            let _ = control_channel_clone
-                .as_ref()
-                .unwrap()
-                .send(ControlMessage::Terminate);
-        });
+        // Report sinks that failed to deliver messages
+        for (name, health) in self.health.stages() {
+            if let Some(delivery) = health.delivery.filter(|delivery| delivery.failed > 0) {
+                tracing::warn!(
+                    "Stage '{}': {} delivered, {} failed ({} dead-lettered); last error: {}",
+                    name,
+                    delivery.delivered,
+                    delivery.failed,
+                    delivery.dead_lettered,
+                    delivery.last_error.as_deref().unwrap_or("none")
+                );
+            }
+        }
 
-        let handles: Vec<_> = self.stage_handles.into_values().collect();
+        // Report channels that shed or refused messages under their overflow policy
+        for (name, stats) in self.channel_registry.stats() {
+            if stats.dropped > 0 || stats.rejected > 0 {
+                tracing::warn!(
+                    "Channel '{}': {} published, {} dropped, {} rejected on overflow",
+                    name,
+                    stats.published,
+                    stats.dropped,
+                    stats.rejected
+                );
+            }
+        }
 
-        // Wait for all stage handles to complete
-        futures::future::join_all(handles).await;
+        self.report_summary();
 
-        Ok(())
+        match fenced {
+            Some(reason) => Err(anyhow::anyhow!("Lost the lease: {}", reason)),
+            None => Ok(exit),
+        }
     }
 }
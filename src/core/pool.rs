@@ -0,0 +1,158 @@
+//! Worker Pools
+//!
+//! Stages with `pipeline` concurrency share the workers of their pipeline's
+//! pool instead of each processing whenever input arrives. A stage holds a
+//! worker only while it works through a batch of queued input, so a chain of
+//! stages longer than the pool is scheduled cooperatively, turn by turn.
+
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{Notify, OwnedSemaphorePermit, Semaphore};
+
+/// Messages a stage processes per turn, unless configured.
+pub const DEFAULT_BATCH: usize = 32;
+
+/// Messages queued on a stage's output above which it waits, unless configured.
+pub const DEFAULT_MAX_IN_FLIGHT: usize = 256;
+
+/// How often an idle stage checks for input it was not woken for, such as
+/// input published by a stage outside the pool.
+pub const IDLE_POLL: Duration = Duration::from_millis(1);
+
+/// Longest a stage goes without a turn, so processors acting on the passing
+/// of time (timeouts, liveness) still run while their input is idle.
+pub const IDLE_TURN: Duration = Duration::from_millis(100);
+
+/// Workers shared by the stages of a pipeline.
+#[derive(Debug)]
+pub struct WorkerPool {
+    workers: Arc<Semaphore>,
+    size: usize,
+    /// Wakes idle stages when a turn ends, as it may have queued their input
+    turn_ended: Notify,
+}
+
+impl WorkerPool {
+    pub fn new(size: usize) -> Self {
+        Self {
+            workers: Arc::new(Semaphore::new(size)),
+            size,
+            turn_ended: Notify::new(),
+        }
+    }
+
+    /// Number of workers in the pool.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Wait for a free worker, held until the returned permit is dropped.
+    pub async fn acquire(&self) -> OwnedSemaphorePermit {
+        Arc::clone(&self.workers)
+            .acquire_owned()
+            .await
+            .expect("worker pool semaphore is never closed")
+    }
+
+    /// Wake the stages waiting in [`WorkerPool::idle`].
+    pub fn end_turn(&self) {
+        self.turn_ended.notify_waiters();
+    }
+
+    /// Wait until a turn ends or `IDLE_POLL` passes. `check` runs once the
+    /// wait is registered, so a turn ending meanwhile is not missed, and the
+    /// wait is skipped if it returns `true`.
+    pub async fn idle(&self, check: impl FnOnce() -> bool) {
+        let turn_ended = self.turn_ended.notified();
+        tokio::pin!(turn_ended);
+        turn_ended.as_mut().enable();
+        if check() {
+            return;
+        }
+        let _ = tokio::time::timeout(IDLE_POLL, turn_ended).await;
+    }
+}
+
+/// A stage's share of a worker pool.
+#[derive(Debug, Clone)]
+pub struct PoolShare {
+    pub pool: Arc<WorkerPool>,
+    /// Messages processed per turn
+    pub batch: usize,
+    /// Messages queued on the stage's output above which it waits
+    pub max_in_flight: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::loader::load_config_from_string;
+    use crate::testing::runner::{TestFile, run_test};
+
+    #[tokio::test]
+    async fn test_worker_pool_bounds_concurrent_turns() {
+        let pool = Arc::new(WorkerPool::new(2));
+        let busy = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let peak = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+
+        let turns: Vec<_> = (0..8)
+            .map(|_| {
+                let (pool, busy, peak) = (Arc::clone(&pool), Arc::clone(&busy), Arc::clone(&peak));
+                tokio::spawn(async move {
+                    let _worker = pool.acquire().await;
+                    let now = busy.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1;
+                    peak.fetch_max(now, std::sync::atomic::Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(5)).await;
+                    busy.fetch_sub(1, std::sync::atomic::Ordering::SeqCst);
+                })
+            })
+            .collect();
+        for turn in turns {
+            turn.await.unwrap();
+        }
+        assert_eq!(peak.load(std::sync::atomic::Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_pipeline_stages_share_one_worker() {
+        let config = load_config_from_string(
+            r#"
+            [inputs.sensor]
+            type = "mqtt_sub"
+            output = "raw"
+            parameters = { broker_url = "mqtt://localhost:1883", topics = ["plant/#"] }
+
+            [pipelines.main]
+            description = "Pooled chain"
+
+            [pipelines.main.stages.delta]
+            type = "delta"
+            inputs = ["raw"]
+            output = "deltas"
+            concurrency = { type = "pipeline", workers = 1, batch = 1 }
+            parameters = { field_in = "value", field_out = "change" }
+
+            [pipelines.main.stages.double]
+            type = "scale"
+            inputs = ["deltas"]
+            output = "doubled"
+            concurrency = { type = "pipeline", max_in_flight = 1 }
+            parameters = { field_in = "change", field_out = "doubled", scale_factor = 2.0 }
+            "#,
+        )
+        .unwrap();
+
+        let tests: TestFile = toml::from_str(
+            r#"
+            [[tests]]
+            name = "pooled"
+            inject = { raw = [{ value = 1.0 }, { value = 3.0 }, { value = 7.0 }] }
+            expect = { doubled = [{ doubled = 4.0 }, { doubled = 8.0 }] }
+            "#,
+        )
+        .unwrap();
+
+        let outcome = run_test(&config, &tests.tests[0]).await;
+        assert!(outcome.passed(), "{:?}", outcome.failures);
+    }
+}
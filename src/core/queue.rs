@@ -0,0 +1,238 @@
+//! Disk Queue
+//!
+//! Append-only segment log backing persistent channels. Records are stored one
+//! per line in numbered segment files (`00000000000000000000.log`, ...), and a
+//! `cursor` file records the position of the oldest record not yet consumed.
+//! Segments are deleted once every record in them has been consumed.
+//!
+//! A record's position is committed when the next record is requested, so a
+//! record being processed when the process dies is delivered again on restart
+//! (at-least-once). A record torn by a crash mid-append is discarded on open.
+
+use anyhow::{Context, Result};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// Segments roll over once they reach this size.
+const SEGMENT_BYTES: u64 = 16 * 1024 * 1024;
+
+const CURSOR_FILE: &str = "cursor";
+
+/// Position of a record: segment number and byte offset within it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct Position {
+    segment: u64,
+    offset: u64,
+}
+
+#[derive(Debug)]
+pub struct DiskQueue {
+    directory: PathBuf,
+    writer: File,
+    write: Position,
+    reader: BufReader<File>,
+    read: Position,
+    /// Position committed to the cursor file
+    committed: Position,
+    /// Records appended but not yet read
+    pending: usize,
+}
+
+impl DiskQueue {
+    /// Open (or create) the queue stored in `directory`, resuming from the
+    /// last committed position.
+    pub fn open(directory: impl AsRef<Path>) -> Result<Self> {
+        let directory = directory.as_ref().to_path_buf();
+        std::fs::create_dir_all(&directory)
+            .with_context(|| format!("Failed to create queue directory '{}'", directory.display()))?;
+
+        let mut segments = Self::segments(&directory)?;
+        if segments.is_empty() {
+            segments.push(0);
+        }
+        let first = Position { segment: segments[0], offset: 0 };
+        let last = *segments.last().expect("at least one segment");
+
+        // Resume from the cursor, unless it is missing, unreadable or stale
+        let read = Self::read_cursor(&directory)
+            .filter(|cursor| segments.contains(&cursor.segment))
+            .unwrap_or(first);
+
+        let writer = Self::open_writer(&directory, last)?;
+        let write = Position { segment: last, offset: writer.metadata()?.len() };
+
+        let mut queue = Self {
+            reader: Self::open_reader(&directory, read)?,
+            directory,
+            writer,
+            write,
+            read,
+            committed: read,
+            pending: 0,
+        };
+        queue.pending = queue.count_pending(&segments)?;
+        queue.commit(read)?;
+        Ok(queue)
+    }
+
+    /// Number of records appended but not yet read.
+    pub fn len(&self) -> usize {
+        self.pending
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending == 0
+    }
+
+    /// Append a record. Records must not contain newlines.
+    pub fn push(&mut self, record: &[u8]) -> Result<()> {
+        debug_assert!(!record.contains(&b'\n'));
+        if self.write.offset >= SEGMENT_BYTES {
+            let segment = self.write.segment + 1;
+            self.writer = Self::open_writer(&self.directory, segment)?;
+            self.write = Position { segment, offset: 0 };
+        }
+
+        let mut line = Vec::with_capacity(record.len() + 1);
+        line.extend_from_slice(record);
+        line.push(b'\n');
+        self.writer.write_all(&line)?;
+        self.write.offset += line.len() as u64;
+        self.pending += 1;
+        Ok(())
+    }
+
+    /// Take the oldest unread record, committing the one returned before it.
+    pub fn pop(&mut self) -> Result<Option<Vec<u8>>> {
+        self.commit(self.read)?;
+
+        loop {
+            let mut line = Vec::new();
+            let read = self.reader.read_until(b'\n', &mut line)?;
+            if read > 0 && line.ends_with(b"\n") {
+                line.pop();
+                self.read.offset += read as u64;
+                self.pending = self.pending.saturating_sub(1);
+                return Ok(Some(line));
+            }
+            if read > 0 {
+                // A record still being appended; retry from its start later
+                self.reader.seek(SeekFrom::Start(self.read.offset))?;
+            }
+            if self.read.segment >= self.write.segment {
+                return Ok(None);
+            }
+
+            // Segment exhausted: move on, then drop the consumed segment
+            let finished = self.read.segment;
+            self.read = Position { segment: finished + 1, offset: 0 };
+            self.reader = Self::open_reader(&self.directory, self.read)?;
+            self.commit(self.read)?;
+            std::fs::remove_file(Self::segment_path(&self.directory, finished))?;
+        }
+    }
+
+    fn commit(&mut self, position: Position) -> Result<()> {
+        if position == self.committed {
+            return Ok(());
+        }
+        let cursor = format!("{} {}", position.segment, position.offset);
+        std::fs::write(self.directory.join(CURSOR_FILE), cursor)?;
+        self.committed = position;
+        Ok(())
+    }
+
+    fn count_pending(&self, segments: &[u64]) -> Result<usize> {
+        let mut pending = 0;
+        for &segment in segments.iter().filter(|&&segment| segment >= self.read.segment) {
+            let mut file = File::open(Self::segment_path(&self.directory, segment))?;
+            if segment == self.read.segment {
+                file.seek(SeekFrom::Start(self.read.offset))?;
+            }
+            pending += BufReader::new(file).split(b'\n').count();
+        }
+        Ok(pending)
+    }
+
+    fn segments(directory: &Path) -> Result<Vec<u64>> {
+        let mut segments = Vec::new();
+        for entry in std::fs::read_dir(directory)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "log")
+                && let Some(segment) = path.file_stem().and_then(|s| s.to_str()?.parse().ok())
+            {
+                segments.push(segment);
+            }
+        }
+        segments.sort_unstable();
+        Ok(segments)
+    }
+
+    fn read_cursor(directory: &Path) -> Option<Position> {
+        let cursor = std::fs::read_to_string(directory.join(CURSOR_FILE)).ok()?;
+        let (segment, offset) = cursor.trim().split_once(' ')?;
+        Some(Position {
+            segment: segment.parse().ok()?,
+            offset: offset.parse().ok()?,
+        })
+    }
+
+    fn segment_path(directory: &Path, segment: u64) -> PathBuf {
+        directory.join(format!("{:020}.log", segment))
+    }
+
+    fn open_writer(directory: &Path, segment: u64) -> Result<File> {
+        let path = Self::segment_path(directory, segment);
+        let mut file = OpenOptions::new().create(true).read(true).append(true).open(&path)?;
+
+        // Discard a record torn by a crash mid-append
+        let contents_len = file.metadata()?.len();
+        if contents_len > 0 {
+            let mut contents = Vec::new();
+            std::io::Read::read_to_end(&mut file, &mut contents)?;
+            let complete = contents.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+            if (complete as u64) < contents_len {
+                tracing::warn!("Discarding torn record at the end of '{}'", path.display());
+                file.set_len(complete as u64)?;
+            }
+        }
+        Ok(file)
+    }
+
+    fn open_reader(directory: &Path, position: Position) -> Result<BufReader<File>> {
+        let mut file = File::open(Self::segment_path(directory, position.segment))?;
+        file.seek(SeekFrom::Start(position.offset))?;
+        Ok(BufReader::new(file))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disk_queue_resumes_after_reopen() {
+        let directory = std::env::temp_dir().join(format!("liminal-queue-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+
+        {
+            let mut queue = DiskQueue::open(&directory).unwrap();
+            for record in ["a", "b", "c"] {
+                queue.push(record.as_bytes()).unwrap();
+            }
+            assert_eq!(queue.pop().unwrap(), Some(b"a".to_vec()));
+            assert_eq!(queue.pop().unwrap(), Some(b"b".to_vec()));
+        }
+
+        // "b" was handed out but never committed, so it is delivered again
+        let mut queue = DiskQueue::open(&directory).unwrap();
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue.pop().unwrap(), Some(b"b".to_vec()));
+        assert_eq!(queue.pop().unwrap(), Some(b"c".to_vec()));
+        assert_eq!(queue.pop().unwrap(), None);
+        assert!(queue.is_empty());
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
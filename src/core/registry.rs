@@ -1,16 +1,28 @@
-use crate::config::types::ChannelType;
-use crate::core::channel::Channel;
+use crate::config::types::ChannelConfig;
+use crate::core::channel::{Channel, ChannelStats, Prioritised, PubSubChannel, PublishError, Subscriber};
+use crate::core::message::{Message, PRODUCER};
 
+use async_trait::async_trait;
 use std::collections::HashMap;
 use std::sync::Arc;
 
+#[derive(Clone)]
 pub struct ChannelRegistry<M> {
     channels: HashMap<String, Arc<Channel<M>>>,
 }
 
+impl<M> Default for ChannelRegistry<M>
+where
+    M: Clone + Prioritised + Send + Sync + 'static,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<M> ChannelRegistry<M>
 where
-    M: Clone + Send + Sync + 'static,
+    M: Clone + Prioritised + Send + Sync + 'static,
 {
     /// Create a new, empty ChannelRegistry.
     pub fn new() -> Self {
@@ -22,17 +34,19 @@ where
     /// Get or create a channel by name.
     ///
     /// If the channel already exists, it returns an `Arc` reference to the existing channel.
-    /// Otherwise, it creates a new channel with the specified type and capacity.
+    /// Otherwise, it creates a new channel from the given channel configuration.
     pub fn get_or_create(
         &mut self,
         name: &str,
-        channel_type: ChannelType,
-        capacity: usize,
-    ) -> Arc<Channel<M>> {
-        self.channels
-            .entry(name.to_string())
-            .or_insert_with(|| Arc::new(Channel::new(channel_type, capacity)))
-            .clone()
+        config: &ChannelConfig,
+    ) -> anyhow::Result<Arc<Channel<M>>> {
+        if let Some(channel) = self.channels.get(name) {
+            return Ok(channel.clone());
+        }
+
+        let channel = Arc::new(Channel::open(name, config)?);
+        self.channels.insert(name.to_string(), channel.clone());
+        Ok(channel)
     }
 
     /// Get an existing channel by name.
@@ -41,4 +55,101 @@ where
     pub fn get(&self, name: &str) -> Option<Arc<Channel<M>>> {
         self.channels.get(name).cloned()
     }
+
+    /// Message counters of every channel, ordered by channel name.
+    pub fn stats(&self) -> Vec<(String, ChannelStats)> {
+        let mut stats: Vec<_> = self
+            .channels
+            .iter()
+            .map(|(name, channel)| (name.clone(), channel.stats()))
+            .collect();
+        stats.sort_by(|a, b| a.0.cmp(&b.0));
+        stats
+    }
+}
+
+/// How a stage publishes to a channel shared with other stages: the channel
+/// merges their messages, each tagged with the `producer` metadata entry
+/// naming the stage that published it.
+pub struct ProducerChannel {
+    producer: String,
+    inner: Arc<dyn PubSubChannel<Message>>,
+}
+
+impl ProducerChannel {
+    pub fn new(producer: &str, inner: Arc<dyn PubSubChannel<Message>>) -> Self {
+        Self {
+            producer: producer.to_string(),
+            inner,
+        }
+    }
+}
+
+#[async_trait]
+impl PubSubChannel<Message> for ProducerChannel {
+    async fn publish(&self, msg: Message) -> Result<(), PublishError<Message>> {
+        self.inner.publish(msg.with_metadata(PRODUCER, self.producer.as_str())).await
+    }
+
+    fn subscribe(&self) -> Subscriber<Message> {
+        self.inner.subscribe()
+    }
+
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    fn capacity(&self) -> Option<usize> {
+        self.inner.capacity()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::loader::load_config_from_string;
+    use crate::config::validate_config;
+
+    #[tokio::test]
+    async fn test_shared_channel_attributes_each_producer() {
+        let mut registry = ChannelRegistry::<Message>::new();
+        let config = ChannelConfig {
+            r#type: crate::config::types::ChannelType::Shared,
+            ..ChannelConfig::default()
+        };
+        let channel: Arc<dyn PubSubChannel<Message>> = registry.get_or_create("merged", &config).unwrap();
+        let mut subscriber = channel.subscribe();
+
+        for producer in ["north", "south"] {
+            let shared = ProducerChannel::new(producer, Arc::clone(&channel));
+            shared.publish(Message::new(producer, "merged", serde_json::json!({}))).await.unwrap();
+        }
+        for producer in ["north", "south"] {
+            let message = subscriber.try_recv().await.into_message().unwrap();
+            assert_eq!(message.metadata.get(PRODUCER).map(String::as_str), Some(producer));
+        }
+
+        let conflicting = load_config_from_string(
+            r#"
+            [inputs.north]
+            type = "mqtt_sub"
+            output = "raw"
+            channel = { type = "direct", capacity = 64 }
+            parameters = { topics = ["north/#"] }
+
+            [inputs.south]
+            type = "mqtt_sub"
+            output = "raw"
+            channel = { type = "broadcast", capacity = 64 }
+            parameters = { topics = ["south/#"] }
+
+            [outputs.console]
+            type = "console"
+            inputs = ["raw"]
+            "#,
+        )
+        .unwrap();
+        let error = validate_config(&conflicting).unwrap_err().to_string();
+        assert!(error.contains("different channel settings"), "{}", error);
+    }
 }
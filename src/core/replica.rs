@@ -0,0 +1,129 @@
+//! Replicas
+//!
+//! A stage configured with `replicas = N` runs as N copies sharing its inputs.
+//! Each input channel is subscribed to once, and a dispatcher task hands every
+//! message to exactly one replica. Unkeyed, the replicas pull from one shared
+//! queue, so whichever replica is free takes the next message. With
+//! `partition_by`, each replica has its own queue and messages are routed by a
+//! hash of the key field, so all messages for a key are processed in order by
+//! the same replica.
+
+use super::channel::{RecvResult, Subscriber};
+use super::message::Message;
+use crate::processors::common::field_utils::FieldUtils;
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::time::Duration;
+
+/// Name of the `index`th replica of a stage.
+pub fn replica_name(stage: &str, index: usize) -> String {
+    format!("{}[{}]", stage, index)
+}
+
+/// Routes messages from one input to the replicas of a stage.
+struct Dispatcher {
+    /// One shared queue, or one queue per replica when partitioned
+    queues: Vec<flume::Sender<Message>>,
+    partition_by: Option<String>,
+}
+
+impl Dispatcher {
+    /// Create a dispatcher and the subscriber each replica reads from.
+    fn new(replicas: usize, partition_by: Option<String>, capacity: usize) -> (Self, Vec<Subscriber<Message>>) {
+        let (queues, subscribers) = if partition_by.is_some() {
+            (0..replicas)
+                .map(|_| {
+                    let (sender, receiver) = flume::bounded(capacity);
+                    (sender, Subscriber::Mpsc(receiver))
+                })
+                .unzip()
+        } else {
+            let (sender, receiver) = flume::bounded(capacity);
+            let subscribers = (0..replicas).map(|_| Subscriber::Flume(receiver.clone())).collect();
+            (vec![sender], subscribers)
+        };
+        (Self { queues, partition_by }, subscribers)
+    }
+
+    fn queue_for(&self, message: &Message) -> &flume::Sender<Message> {
+        if self.queues.len() == 1 {
+            return &self.queues[0];
+        }
+        let key = FieldUtils::extract_key(&message.payload, self.partition_by.as_deref());
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        &self.queues[(hasher.finish() % self.queues.len() as u64) as usize]
+    }
+
+    /// Hand a message to its replica, waiting while that replica's queue is
+    /// full. Fails once the replica has stopped.
+    async fn forward(&self, message: Message) -> Result<(), flume::SendError<Message>> {
+        self.queue_for(&message).send_async(message).await
+    }
+}
+
+/// Spread `input` across `replicas` subscribers, one per replica, forwarding
+/// from a background task until the replicas stop.
+pub fn dispatch(
+    stage: &str,
+    mut input: Subscriber<Message>,
+    replicas: usize,
+    partition_by: Option<String>,
+    capacity: usize,
+) -> Vec<Subscriber<Message>> {
+    let (dispatcher, subscribers) = Dispatcher::new(replicas, partition_by, capacity);
+
+    let stage = stage.to_string();
+    tokio::spawn(async move {
+        loop {
+            match input.recv().await {
+                RecvResult::Message(message) => {
+                    if dispatcher.forward(message).await.is_err() {
+                        tracing::debug!("Replicas of stage '{}' have stopped", stage);
+                        break;
+                    }
+                }
+                RecvResult::Lagged(skipped) => {
+                    tracing::warn!("Replicas of stage '{}' lagged behind their input and missed {} message(s)", stage, skipped);
+                }
+                // Momentarily unreadable; registry channels stay open
+                _ => tokio::time::sleep(Duration::from_millis(1)).await,
+            }
+        }
+    });
+
+    subscribers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use serde_json::json;
+
+    #[test]
+    fn test_partitioned_dispatch_keeps_keys_on_one_replica() {
+        let (dispatcher, mut subscribers) = Dispatcher::new(3, Some("device".to_string()), 64);
+        for i in 0..30 {
+            let payload = json!({"device": format!("d{}", i % 5), "seq": i});
+            block_on(dispatcher.forward(Message::new("src", "in", payload))).unwrap();
+        }
+
+        let mut owner = std::collections::HashMap::new();
+        let mut total = 0;
+        for (replica, subscriber) in subscribers.iter_mut().enumerate() {
+            let mut last_seq = std::collections::HashMap::new();
+            while let RecvResult::Message(message) = block_on(subscriber.try_recv()) {
+                let device = message.payload["device"].as_str().unwrap().to_string();
+                let seq = message.payload["seq"].as_i64().unwrap();
+
+                // Every key lands on a single replica, in publish order
+                assert_eq!(*owner.entry(device.clone()).or_insert(replica), replica);
+                assert!(last_seq.insert(device, seq).is_none_or(|previous| previous < seq));
+                total += 1;
+            }
+        }
+        assert_eq!(total, 30);
+    }
+}
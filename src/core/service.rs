@@ -0,0 +1,214 @@
+//! Service Mode
+//!
+//! Support for running under a service manager, behind `liminal --service`:
+//!
+//! - **Signals**: SIGTERM and SIGINT shut down gracefully, draining the stages
+//!   as Ctrl+C does. In service mode, SIGHUP reloads the configuration: the
+//!   stages drain and stop, and the pipeline is rebuilt from the reloaded file,
+//!   or from the previous configuration if the reloaded one fails to load or
+//!   validate. On platforms other than Unix, only Ctrl+C is handled.
+//! - **Readiness**: under systemd (`Type=notify` or `Type=notify-reload`), the
+//!   service manager is told when the stages have started (`READY=1`), when a
+//!   reload begins (`RELOADING=1`) and when shutdown begins (`STOPPING=1`),
+//!   through the socket named by `NOTIFY_SOCKET`. Nothing is sent when it is
+//!   unset.
+//! - **PID file**: `--pid-file` writes the process id to a file, removed when
+//!   the process exits, and refuses to start while another live process holds
+//!   it.
+//!
+//! ```ini
+//! [Service]
+//! Type=notify-reload
+//! ExecStart=/usr/local/bin/liminal --service -c /etc/liminal/site.toml
+//! ```
+
+use anyhow::{Context, Result, anyhow};
+use std::path::{Path, PathBuf};
+
+/// What a signal asks the running pipeline to do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Signal {
+    /// Drain and stop, naming the signal received
+    Shutdown(&'static str),
+    /// Drain, stop, and start again with the reloaded configuration
+    Reload,
+}
+
+/// Wait for the next signal. Reload signals are only listened for when
+/// `reload` is set, and otherwise keep their default behaviour.
+pub async fn signal(reload: bool) -> Signal {
+    match listen(reload).await {
+        Ok(signal) => signal,
+        Err(e) => {
+            tracing::error!("Failed to listen for signals: {}", e);
+            std::future::pending().await
+        }
+    }
+}
+
+#[cfg(unix)]
+async fn listen(reload: bool) -> std::io::Result<Signal> {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    let mut terminate = signal(SignalKind::terminate())?;
+    let mut interrupt = signal(SignalKind::interrupt())?;
+    let mut hangup = if reload { Some(signal(SignalKind::hangup())?) } else { None };
+    Ok(tokio::select! {
+        _ = terminate.recv() => Signal::Shutdown("SIGTERM"),
+        _ = interrupt.recv() => Signal::Shutdown("Ctrl+C"),
+        Some(_) = async {
+            match &mut hangup {
+                Some(hangup) => hangup.recv().await,
+                None => None,
+            }
+        }, if hangup.is_some() => Signal::Reload,
+    })
+}
+
+#[cfg(not(unix))]
+async fn listen(_reload: bool) -> std::io::Result<Signal> {
+    tokio::signal::ctrl_c().await?;
+    Ok(Signal::Shutdown("Ctrl+C"))
+}
+
+/// Tell the service manager the stages have started.
+pub fn notify_ready() {
+    notify("READY=1");
+}
+
+/// Tell the service manager a reload has begun.
+pub fn notify_reloading() {
+    notify(&format!("RELOADING=1\nMONOTONIC_USEC={}", monotonic_usec()));
+}
+
+/// Tell the service manager shutdown has begun.
+pub fn notify_stopping() {
+    notify("STOPPING=1");
+}
+
+/// Send a state to the systemd notification socket, if there is one.
+/// Failures are logged, as the service manager not hearing back is not a
+/// reason to stop.
+fn notify(state: &str) {
+    let Some(path) = std::env::var_os("NOTIFY_SOCKET") else {
+        return;
+    };
+    if let Err(e) = send_notification(Path::new(&path), state) {
+        tracing::warn!("Failed to notify the service manager ({}): {}", state.lines().next().unwrap_or(state), e);
+    }
+}
+
+#[cfg(unix)]
+fn send_notification(path: &Path, state: &str) -> std::io::Result<()> {
+    use std::os::unix::net::UnixDatagram;
+
+    let socket = UnixDatagram::unbound()?;
+    // A leading '@' names a socket in the abstract namespace
+    #[cfg(target_os = "linux")]
+    if let Some(name) = path.to_str().and_then(|path| path.strip_prefix('@')) {
+        use std::os::linux::net::SocketAddrExt;
+        let address = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+        return socket.send_to_addr(state.as_bytes(), &address).map(|_| ());
+    }
+    socket.send_to(state.as_bytes(), path).map(|_| ())
+}
+
+#[cfg(not(unix))]
+fn send_notification(_path: &Path, _state: &str) -> std::io::Result<()> {
+    Ok(())
+}
+
+/// The monotonic clock in microseconds, which systemd matches reloads against.
+#[cfg(unix)]
+fn monotonic_usec() -> u64 {
+    let mut now = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+    // SAFETY: clock_gettime only writes the timespec it is given
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut now) };
+    now.tv_sec as u64 * 1_000_000 + now.tv_nsec as u64 / 1_000
+}
+
+#[cfg(not(unix))]
+fn monotonic_usec() -> u64 {
+    0
+}
+
+/// A file holding the id of this process, removed when dropped.
+#[derive(Debug)]
+pub struct PidFile {
+    path: PathBuf,
+}
+
+impl PidFile {
+    /// Write the process id to `path`. Fails if the file names another
+    /// process that is still running; a file left by a process that has
+    /// exited is replaced.
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        if let Ok(contents) = std::fs::read_to_string(&path)
+            && let Ok(pid) = contents.trim().parse::<u32>()
+            && pid != std::process::id()
+            && is_running(pid)
+        {
+            return Err(anyhow!("'{}' names process {}, which is still running", path.display(), pid));
+        }
+        std::fs::write(&path, format!("{}\n", std::process::id()))
+            .with_context(|| format!("Failed to write PID file '{}'", path.display()))?;
+        Ok(Self { path })
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(unix)]
+fn is_running(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    // SAFETY: signal 0 only checks that the process exists
+    unsafe { libc::kill(pid, 0) == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM) }
+}
+
+#[cfg(not(unix))]
+fn is_running(_pid: u32) -> bool {
+    false
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_notifications_and_pid_files() {
+        let directory = std::env::temp_dir().join(format!("liminal_service_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+
+        let socket_path = directory.join("notify");
+        let listener = std::os::unix::net::UnixDatagram::bind(&socket_path).unwrap();
+        send_notification(&socket_path, "READY=1").unwrap();
+        let mut buffer = [0; 64];
+        let received = listener.recv(&mut buffer).unwrap();
+        assert_eq!(&buffer[..received], b"READY=1");
+
+        // A file left by a process that has exited is replaced
+        let pid_path = directory.join("liminal.pid");
+        std::fs::write(&pid_path, format!("{}\n", i32::MAX)).unwrap();
+        let pid_file = PidFile::create(&pid_path).unwrap();
+        assert_eq!(std::fs::read_to_string(&pid_path).unwrap(), format!("{}\n", std::process::id()));
+
+        // One naming a running process is not
+        let running = std::process::Command::new("sleep").arg("5").spawn().unwrap();
+        drop(pid_file);
+        assert!(!pid_path.exists());
+        std::fs::write(&pid_path, running.id().to_string()).unwrap();
+        assert!(PidFile::create(&pid_path).is_err());
+
+        let mut running = running;
+        let _ = running.kill();
+        let _ = running.wait();
+        let _ = std::fs::remove_dir_all(&directory);
+    }
+}
@@ -1,12 +1,20 @@
+use super::backfill::BackfillRange;
 use super::channel::PubSubChannel;
+use super::checkpoint::CheckpointStore;
 use super::channel::Subscriber;
 use super::message::Message;
-use super::context::ProcessingContext;
+use super::context::{ProcessingContext, ReceivedInput};
+use super::metrics::{StageMetrics, metrics};
+use super::pool::{IDLE_TURN, PoolShare, WorkerPool};
+use super::supervisor::Health;
 
 use crate::config::StageConfig;
 use crate::processors::processor::Processor;
+use crate::error::LiminalError;
 
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
 
 /// Creates a new stage with the given name and configuration.
 ///
@@ -15,43 +23,109 @@ use std::sync::Arc;
 /// * `config` - The configuration for the stage.
 ///
 /// # Returns
-/// An `Option` containing a `Box<Stage>` if the stage was created successfully, or `None` if the processor was not found.
+/// The stage, or the processor's error: a `LiminalError` attributed to the
+/// stage where the processor raised one, or else wrapped in
+/// `LiminalError::StageCreation`.
 ///
-pub fn create_stage(name: &str, config: StageConfig) -> Option<Box<Stage>> {
-    // Uncomment if the stage name is used as processor type
-    // if let Ok(processor) = crate::processors::create_processor(name, config) {
-    
-    if let Ok(processor) = crate::processors::create_processor(&config.r#type.clone(), config) {
-        Some(Box::new(Stage::new(name.to_string(), processor, None)))
-    } else {
-        tracing::error!("Stage processor '{}' not found", name);
-        None
+pub fn create_stage(name: &str, config: StageConfig) -> anyhow::Result<Box<Stage>> {
+    let max_errors = config.restart.as_ref().map_or(1, |restart| restart.max_errors);
+    let metrics_enabled = config.timing.as_ref().is_none_or(|timing| timing.metrics_enabled);
+    let sla = config.sla.clone();
+    let ttl = config.ttl.clone();
+    let limits = config.limits.clone().unwrap_or_default();
+    let processor = crate::processors::create_processor(&config.r#type.clone(), config).map_err(|e| {
+        match e.downcast::<LiminalError>() {
+            Ok(e) => anyhow::Error::new(e.in_stage(name)),
+            Err(e) => anyhow::Error::new(LiminalError::StageCreation { stage: name.to_string(), source: e }),
+        }
+    })?;
+
+    let mut stage = Stage::new(name.to_string(), processor, None);
+    stage.max_errors = max_errors;
+    stage.metrics = metrics_enabled.then(|| metrics().stage(name));
+    if let Some(sla) = sla {
+        stage.context.set_sla(sla);
+    }
+    if let Some(ttl) = ttl {
+        stage.context.set_ttl(ttl);
+    }
+    if limits.max_in_flight.is_some() {
+        stage.context.set_limits(limits.clone());
     }
+    stage.slow_after = limits.slow_processing_ms.map(Duration::from_millis);
+    Ok(Box::new(stage))
 }
 
+/// Commands sent to a running stage over its control channel.
 #[derive(Debug, Clone)]
 pub enum ControlMessage {
+    /// Flush, checkpoint (if enabled) and stop the stage, leaving any queued
+    /// input unprocessed
     Terminate,
+    /// Process the input already queued, then stop as for `Terminate`
+    Drain,
+    /// Write a checkpoint now, if checkpointing is enabled
+    Checkpoint,
+    /// Stop processing until resumed; input queues up on the stage's channels
+    Pause,
+    /// Carry on processing after a pause
+    Resume,
+}
+
+/// Periodic checkpointing state for a stage.
+struct StageCheckpoint {
+    store: Arc<CheckpointStore>,
+    interval: Duration,
+    last: Instant,
 }
 
 pub struct Stage {
     name: String,
     processor: Box<dyn Processor>,
     context: ProcessingContext,
-    control_channel: Option<tokio::sync::broadcast::Receiver<ControlMessage>>,
+    control_channel: Option<mpsc::Receiver<ControlMessage>>,
+    checkpoint: Option<StageCheckpoint>,
+    /// Consecutive processing errors tolerated before `run` fails
+    max_errors: u32,
+    /// Set once the stage has been told to stop
+    stopping: bool,
+    /// Set while the stage is paused
+    paused: bool,
+    metrics: Option<StageMetrics>,
+    /// Processing time above which the stage counts as slow
+    slow_after: Option<Duration>,
+    /// Set while the stage processes slower than `slow_after`
+    slow: bool,
+    health: Option<Arc<Health>>,
+    /// Workers shared with the other `pipeline` stages of its pipeline
+    pool: Option<PoolShare>,
+    /// When the stage last had a turn on a worker
+    last_turn: Instant,
 }
 
 impl Stage {
     pub fn new(
         name: String,
         processor: Box<dyn Processor>,
-        control_channel: Option<tokio::sync::broadcast::Receiver<ControlMessage>>,
+        control_channel: Option<mpsc::Receiver<ControlMessage>>,
     ) -> Self {
+        let mut context = ProcessingContext::new(name.clone());
+        context.accept_batches(processor.accepts_batches());
         Self {
-            name: name.clone(),
+            name,
             processor,
-            context: ProcessingContext::new(name),
+            context,
             control_channel: control_channel,
+            checkpoint: None,
+            max_errors: 1,
+            stopping: false,
+            paused: false,
+            metrics: None,
+            slow_after: None,
+            slow: false,
+            health: None,
+            pool: None,
+            last_turn: Instant::now(),
         }
     }
 
@@ -59,13 +133,171 @@ impl Stage {
         &self.name
     }
 
-    pub fn attach_control_channel(
-        &mut self,
-        control_channel: tokio::sync::broadcast::Receiver<ControlMessage>,
-    ) {
+    /// Whether the stage records metrics (`timing.metrics_enabled`).
+    pub fn has_metrics(&self) -> bool {
+        self.metrics.is_some()
+    }
+
+    /// Whether the stage has been told to stop (drain or terminate).
+    pub fn is_stopping(&self) -> bool {
+        self.stopping
+    }
+
+    /// Whether the stage has been paused.
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn attach_control_channel(&mut self, control_channel: mpsc::Receiver<ControlMessage>) {
         self.control_channel = Some(control_channel);
     }
 
+    /// Flag the stage in `health` while it processes slower than its limit,
+    /// and record the delivery results of its sink there.
+    pub fn attach_health(&mut self, health: Arc<Health>) {
+        self.health = Some(health);
+    }
+
+    /// Process input in turns on a worker of `pool`, `batch` messages at a
+    /// time, waiting while the output holds `max_in_flight` messages or more.
+    /// Sources are not pooled, as they wait on external data.
+    pub fn attach_pool(&mut self, pool: Arc<WorkerPool>, batch: usize, max_in_flight: usize) {
+        self.pool = Some(PoolShare { pool, batch, max_in_flight });
+    }
+
+    /// Checkpoint the processor's state to `store` every `interval`. Processors
+    /// that do not implement `Snapshot` are never checkpointed.
+    pub fn attach_checkpoint(&mut self, store: Arc<CheckpointStore>, interval: Duration) {
+        self.checkpoint = Some(StageCheckpoint {
+            store,
+            interval,
+            last: Instant::now(),
+        });
+    }
+
+    /// Restore the processor's state from the last checkpoint, if any.
+    /// Returns whether state was restored.
+    pub fn restore_checkpoint(&mut self) -> anyhow::Result<bool> {
+        let (Some(checkpoint), Some(snapshot)) = (&self.checkpoint, self.processor.as_snapshot()) else {
+            return Ok(false);
+        };
+        let Some(state) = checkpoint.store.load(&self.name)? else {
+            return Ok(false);
+        };
+
+        snapshot.restore(state).map_err(|e| {
+            anyhow::anyhow!("Failed to restore checkpoint for stage '{}': {}", self.name, e)
+        })?;
+        tracing::info!("Stage '{}' restored from checkpoint", self.name);
+        Ok(true)
+    }
+
+    /// Write a checkpoint of the processor's state now.
+    pub fn save_checkpoint(&mut self) -> anyhow::Result<()> {
+        let (Some(checkpoint), Some(snapshot)) = (&mut self.checkpoint, self.processor.as_snapshot()) else {
+            return Ok(());
+        };
+
+        checkpoint.store.save(&self.name, snapshot.snapshot()?)?;
+        checkpoint.last = Instant::now();
+        tracing::debug!("Stage '{}' checkpointed", self.name);
+        Ok(())
+    }
+
+    fn maybe_checkpoint(&mut self) {
+        let due = self
+            .checkpoint
+            .as_ref()
+            .is_some_and(|checkpoint| checkpoint.last.elapsed() >= checkpoint.interval);
+
+        if due && let Err(e) = self.save_checkpoint() {
+            tracing::warn!("Failed to checkpoint stage '{}': {}", self.name, e);
+            // Retry on the next interval rather than after every batch
+            if let Some(checkpoint) = &mut self.checkpoint {
+                checkpoint.last = Instant::now();
+            }
+        }
+    }
+
+    /// Record what the last `process` call received, and input queue depths.
+    fn record_metrics(&mut self, failed: bool) {
+        let received = self.context.take_received();
+        self.watch_processing(&received);
+        self.record_delivery();
+        let Some(metrics) = &mut self.metrics else {
+            return;
+        };
+
+        if received.count > 0 {
+            metrics.record_received(received.count);
+        }
+        if received.lagged > 0 {
+            metrics.record_lagged(received.lagged);
+        }
+        if received.sla_violations > 0 {
+            metrics.record_sla_violations(received.sla_violations);
+        }
+        if received.shed > 0 {
+            metrics.record_shed(received.shed);
+        }
+        if received.expired > 0 {
+            metrics.record_expired(received.expired);
+        }
+        if let Some(first_at) = received.first_at {
+            metrics.record_processing(first_at.elapsed());
+        }
+        if let Some(watermark) = received.watermark {
+            metrics.record_watermark(watermark);
+        }
+        if failed {
+            metrics.record_error();
+        }
+        for (name, input) in &self.context.inputs {
+            metrics.record_input_depth(name, input.len());
+        }
+    }
+
+    /// Pass the delivery results the processor reported on to the metrics
+    /// and the stage's health.
+    fn record_delivery(&mut self) {
+        let delivery = self.context.take_delivery();
+        if delivery.is_empty() {
+            return;
+        }
+        if let Some(metrics) = &self.metrics {
+            metrics.record_delivery(&delivery);
+        }
+        if let Some(health) = &self.health {
+            health.record_delivery(&self.name, &delivery);
+        }
+    }
+
+    /// Compare the time the last `process` call took over its input with the
+    /// slow processing limit, flagging the stage when it crosses it.
+    fn watch_processing(&mut self, received: &ReceivedInput) {
+        let (Some(slow_after), Some(first_at)) = (self.slow_after, received.first_at) else {
+            return;
+        };
+        let elapsed = first_at.elapsed();
+        let slow = elapsed > slow_after;
+        if slow && let Some(metrics) = &self.metrics {
+            metrics.record_slow();
+        }
+        if slow == self.slow {
+            return;
+        }
+
+        self.slow = slow;
+        if slow {
+            tracing::warn!("Stage '{}' is slow: processing took {:?} (limit {:?})", self.name, elapsed, slow_after);
+        } else {
+            tracing::info!("Stage '{}' is processing within its limit again", self.name);
+        }
+        if let Some(health) = &self.health {
+            health.set_slow(&self.name, slow);
+        }
+    }
+
     pub async fn add_input(&mut self, name: &str, input: Subscriber<Message>) {
         self.context.add_input(name.to_string(), input);
     }
@@ -74,37 +306,187 @@ impl Stage {
         self.context.attach_output(name.to_string(), output);
     }
 
+    pub async fn add_side_output(&mut self, name: &str, output: Arc<dyn PubSubChannel<Message>>) {
+        self.context.attach_side_output(name.to_string(), output);
+    }
+
+    /// Have the stage's source read the history of `range` instead of live data.
+    pub fn backfill(&mut self, range: &BackfillRange) -> anyhow::Result<()> {
+        self.processor
+            .backfill(range)
+            .map_err(|e| anyhow::anyhow!("Input '{}' cannot back-fill: {}", self.name, e))
+    }
+
+    /// Check that the services the stage's processor connects to are reachable.
+    pub async fn preflight(&self) -> anyhow::Result<()> {
+        self.processor.preflight().await
+    }
+
     pub async fn init(&mut self) -> anyhow::Result<()> {
         self.processor.init().await
     }
 
+    /// Process whatever is still queued on the stage's inputs. Sources have no
+    /// inputs, so they simply stop producing.
+    async fn drain(&mut self) -> anyhow::Result<()> {
+        if self.context.inputs.is_empty() {
+            return Ok(());
+        }
+
+        // Each call waits briefly for input, so a message still being handed
+        // over (e.g. by a replica dispatcher) is not left behind
+        loop {
+            let result = self.processor.process(&mut self.context).await;
+            self.record_metrics(result.is_err());
+            result?;
+            self.maybe_checkpoint();
+            if !self.context.has_pending_input() {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Flush the processor and write a final checkpoint.
+    async fn stop(&mut self) -> anyhow::Result<()> {
+        let flushed = self.processor.flush(&mut self.context).await;
+        self.record_delivery();
+        if let Err(e) = self.save_checkpoint() {
+            tracing::warn!("Failed to checkpoint stage '{}': {}", self.name, e);
+        }
+        flushed
+    }
+
+    /// Act on a control message. Returns `true` once the stage has stopped.
+    async fn handle_control(&mut self, message: ControlMessage) -> anyhow::Result<bool> {
+        match message {
+            ControlMessage::Terminate => {
+                tracing::info!("Stage '{}' received terminate signal", self.name);
+                self.stopping = true;
+                self.stop().await?;
+                Ok(true)
+            }
+            ControlMessage::Drain => {
+                tracing::info!("Stage '{}' draining", self.name);
+                self.stopping = true;
+                self.drain().await?;
+                self.stop().await?;
+                tracing::info!("Stage '{}' drained", self.name);
+                Ok(true)
+            }
+            ControlMessage::Checkpoint => {
+                if let Err(e) = self.save_checkpoint() {
+                    tracing::warn!("Failed to checkpoint stage '{}': {}", self.name, e);
+                }
+                Ok(false)
+            }
+            ControlMessage::Pause => {
+                tracing::info!("Stage '{}' paused", self.name);
+                self.paused = true;
+                Ok(false)
+            }
+            ControlMessage::Resume => {
+                tracing::info!("Stage '{}' resumed", self.name);
+                self.paused = false;
+                Ok(false)
+            }
+        }
+    }
+
+    /// Wait out a restart delay while still answering control messages.
+    /// Returns `false` if the stage was stopped meanwhile; a failed stage is
+    /// not drained, flushed or checkpointed, as its state may be inconsistent.
+    pub async fn pause(&mut self, delay: Duration) -> bool {
+        let deadline = tokio::time::Instant::now() + delay;
+        loop {
+            let Some(control_channel) = &mut self.control_channel else {
+                tokio::time::sleep_until(deadline).await;
+                return true;
+            };
+
+            match tokio::time::timeout_at(deadline, control_channel.recv()).await {
+                Ok(Some(ControlMessage::Checkpoint)) => {}
+                Ok(Some(ControlMessage::Pause)) => self.paused = true,
+                Ok(Some(ControlMessage::Resume)) => self.paused = false,
+                Ok(Some(ControlMessage::Terminate | ControlMessage::Drain)) => {
+                    tracing::info!("Stage '{}' stopped while waiting to restart", self.name);
+                    return false;
+                }
+                // The manager has gone; nothing can stop the stage now
+                Ok(None) => {
+                    tokio::time::sleep_until(deadline).await;
+                    return true;
+                }
+                Err(_) => return true,
+            }
+        }
+    }
+
     pub async fn run(&mut self) -> anyhow::Result<()> {
         tracing::info!("Stage '{}' is running", self.name);
 
+        // Processors with inputs return from `process` within a short receive
+        // timeout, so their control messages are handled between calls and no
+        // message is abandoned half-processed. Sources may wait much longer for
+        // data, so a control message interrupts them instead.
+        let interruptible = self.context.inputs.is_empty();
+        let mut errors = 0;
+
         loop {
+            let pending = self
+                .control_channel
+                .as_mut()
+                .and_then(|control_channel| control_channel.try_recv().ok());
+            if let Some(message) = pending {
+                if self.handle_control(message).await? {
+                    break;
+                }
+                continue;
+            }
+
+            // A paused stage only answers control messages
+            if self.paused {
+                let message = match &mut self.control_channel {
+                    Some(control_channel) => control_channel.recv().await,
+                    None => None,
+                };
+                match message {
+                    Some(message) => {
+                        if self.handle_control(message).await? {
+                            break;
+                        }
+                    }
+                    // The manager has gone; nothing can resume the stage
+                    None => self.paused = false,
+                }
+                continue;
+            }
+
+            // Pooled stages process in turns on a worker shared with their pipeline
+            if self.pool.is_some() && !interruptible {
+                if self.take_turn(&mut errors).await? {
+                    break;
+                }
+                continue;
+            }
+
             tokio::select! {
                 // Handle control messages
                 Some(message) = async {
                     if let Some(control_channel) = &mut self.control_channel {
-                        control_channel.recv().await.ok()
+                        control_channel.recv().await
                     } else {
                         None
                     }
-                } => {
-                    match message {
-                        ControlMessage::Terminate => {
-                            tracing::info!("Stage '{}' received terminate signal", self.name);
-                            break;
-                        }
+                }, if interruptible => {
+                    if self.handle_control(message).await? {
+                        break;
                     }
                 }
 
                 // Process messages
                 result = self.processor.process(&mut self.context) => {
-                    // Handle the result of the processor
-                    if let Err(e) = result {
-                        tracing::error!("Error in processor for stage '{}': {}", self.name, e);
-                        return Err(e);
+                    if self.handle_result(result, &mut errors).await? {
+                        break;
                     }
                 }
             }
@@ -112,4 +494,69 @@ impl Stage {
 
         Ok(())
     }
+
+    /// Take a turn on a worker of the stage's pool when input is queued and
+    /// the output has room, processing up to a batch of messages. Returns
+    /// `true` once the stage has stopped.
+    async fn take_turn(&mut self, errors: &mut u32) -> anyhow::Result<bool> {
+        let Some(share) = self.pool.clone() else {
+            return Ok(false);
+        };
+        let ready = |context: &ProcessingContext, last_turn: Instant| {
+            let backed_up = context
+                .output
+                .as_ref()
+                .is_some_and(|output| output.channel.len() >= share.max_in_flight);
+            !backed_up && (context.has_pending_input() || last_turn.elapsed() >= IDLE_TURN)
+        };
+        if !ready(&self.context, self.last_turn) {
+            share.pool.idle(|| ready(&self.context, self.last_turn)).await;
+            return Ok(false);
+        }
+
+        let worker = share.pool.acquire().await;
+        self.last_turn = Instant::now();
+        let mut stopped = false;
+        for _ in 0..share.batch {
+            let result = self.processor.process(&mut self.context).await;
+            stopped = self.handle_result(result, errors).await?;
+            if stopped || !self.context.has_pending_input() {
+                break;
+            }
+        }
+        drop(worker);
+        share.pool.end_turn();
+        Ok(stopped)
+    }
+
+    /// Record the result of a `process` call, failing once too many errors
+    /// occur in a row. Returns `true` once the stage has completed and stopped.
+    async fn handle_result(&mut self, result: anyhow::Result<()>, errors: &mut u32) -> anyhow::Result<bool> {
+        self.record_metrics(result.is_err());
+
+        if let Err(e) = result {
+            *errors += 1;
+            if *errors >= self.max_errors {
+                tracing::error!("Error in processor for stage '{}': {}", self.name, e);
+                return Err(e);
+            }
+            tracing::warn!(
+                "Error in processor for stage '{}' ({} of {} allowed in a row): {}",
+                self.name,
+                errors,
+                self.max_errors,
+                e
+            );
+            return Ok(false);
+        }
+        *errors = 0;
+        self.maybe_checkpoint();
+
+        if self.context.is_complete() {
+            tracing::info!("Stage '{}' completed", self.name);
+            self.stop().await?;
+            return Ok(true);
+        }
+        Ok(false)
+    }
 }
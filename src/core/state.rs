@@ -0,0 +1,565 @@
+//! Keyed State Store
+//!
+//! Shared storage for per-key processor state (running statistics, last values,
+//! windows). Entries are evicted once they have not been updated for the
+//! configured TTL, and the least recently updated entries are evicted first when
+//! the store reaches `max_keys` or its estimated size exceeds `max_bytes`.
+//! Sizes are estimated from the serialized entries, and only tracked when
+//! `max_bytes` is set. With a persistent backend, changes are mirrored
+//! to disk every `flush_interval_ms` and reloaded when the store is reopened.
+//!
+//! Only writes (`insert`, `get_mut`, `get_or_insert_with`) count as updates;
+//! reads leave an entry's age unchanged.
+
+use crate::config::StateConfig;
+use crate::config::types::StateBackendConfig;
+use crate::core::memory::{self, StoreUsage};
+
+use anyhow::{Result, anyhow};
+use serde::{Serialize, de::DeserializeOwned};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::Hash;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Byte-level storage behind a persistent state store.
+pub trait StateBackend: Send + Sync {
+    /// Read every stored entry.
+    fn load(&self) -> Result<Vec<(Vec<u8>, Vec<u8>)>>;
+
+    /// Apply a batch of upserts and deletions durably.
+    fn write(&self, upserts: Vec<(Vec<u8>, Vec<u8>)>, deletes: Vec<Vec<u8>>) -> Result<()>;
+}
+
+#[derive(Debug)]
+struct Slot<V> {
+    value: V,
+    tick: u64,
+    updated: Instant,
+    /// Estimated size in bytes (0 when sizes are not tracked)
+    size: usize,
+}
+
+pub struct StateStore<K, V>
+where
+    K: Eq + Hash + Clone + Serialize + DeserializeOwned,
+    V: Serialize + DeserializeOwned,
+{
+    entries: HashMap<K, Slot<V>>,
+    /// Keys ordered by last update, oldest first
+    order: BTreeMap<u64, K>,
+    next_tick: u64,
+    ttl: Option<Duration>,
+    max_keys: Option<usize>,
+    max_bytes: Option<usize>,
+    /// Estimated size of every entry
+    bytes: usize,
+    /// Keys updated in place, whose size is re-estimated on the next write
+    resized: HashSet<K>,
+    last_sweep: Instant,
+    backend: Option<Box<dyn StateBackend>>,
+    flush_interval: Duration,
+    last_flush: Instant,
+    dirty: HashSet<K>,
+    deleted: HashSet<K>,
+    /// Size reported to the memory report (stores opened for a stage only)
+    usage: Option<Arc<StoreUsage>>,
+}
+
+impl<K, V> StateStore<K, V>
+where
+    K: Eq + Hash + Clone + Serialize + DeserializeOwned,
+    V: Serialize + DeserializeOwned,
+{
+    /// Create an unbounded in-memory store.
+    pub fn new() -> Self {
+        Self {
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            next_tick: 0,
+            ttl: None,
+            max_keys: None,
+            max_bytes: None,
+            bytes: 0,
+            resized: HashSet::new(),
+            last_sweep: Instant::now(),
+            backend: None,
+            flush_interval: Duration::from_millis(StateConfig::default().flush_interval_ms),
+            last_flush: Instant::now(),
+            dirty: HashSet::new(),
+            deleted: HashSet::new(),
+            usage: None,
+        }
+    }
+
+    /// Open a store for a stage from its `state` configuration.
+    ///
+    /// `namespace` distinguishes the stores of different stages (and of several
+    /// stores within one stage) in a shared persistent backend. Existing entries
+    /// are loaded from the backend, if any.
+    pub fn open(namespace: &str, config: Option<&StateConfig>) -> Result<Self> {
+        let mut store = Self::new();
+        store.usage = Some(memory::register_store(namespace));
+        let Some(config) = config else {
+            return Ok(store);
+        };
+
+        if config.max_keys == Some(0) {
+            return Err(anyhow!("state.max_keys must be greater than 0"));
+        }
+        if config.max_bytes == Some(0) {
+            return Err(anyhow!("state.max_bytes must be greater than 0"));
+        }
+        store.ttl = config.ttl_ms.map(Duration::from_millis);
+        store.max_keys = config.max_keys;
+        store.max_bytes = config.max_bytes;
+        store.flush_interval = Duration::from_millis(config.flush_interval_ms);
+        store.backend = open_backend(&config.backend, namespace)?;
+
+        if let Some(backend) = &store.backend {
+            for (key, value) in backend.load()? {
+                let key: K = serde_json::from_slice(&key)
+                    .map_err(|e| anyhow!("Corrupt state key in '{}': {}", namespace, e))?;
+                let value: V = serde_json::from_slice(&value)
+                    .map_err(|e| anyhow!("Corrupt state value in '{}': {}", namespace, e))?;
+                store.insert(key, value);
+            }
+            store.dirty.clear();
+            tracing::debug!("Restored {} state entries for '{}'", store.len(), namespace);
+        }
+        Ok(store)
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.entries.get(key).map(|slot| &slot.value)
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        if !self.entries.contains_key(key) {
+            return None;
+        }
+        self.touch(key);
+        self.entries.get_mut(key).map(|slot| &mut slot.value)
+    }
+
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        self.maintain();
+        let tick = self.advance();
+        let size = self.estimate(&key, &value);
+        let slot = Slot {
+            value,
+            tick,
+            updated: Instant::now(),
+            size,
+        };
+
+        self.bytes += size;
+        let previous = self.entries.insert(key.clone(), slot);
+        if let Some(previous) = &previous {
+            self.order.remove(&previous.tick);
+            self.bytes -= previous.size;
+        }
+        self.resized.remove(&key);
+        self.order.insert(tick, key.clone());
+        self.mark_dirty(key);
+        self.enforce_capacity();
+        self.report_usage();
+        previous.map(|slot| slot.value)
+    }
+
+    pub fn get_or_insert_with(&mut self, key: K, default: impl FnOnce() -> V) -> &mut V {
+        if self.entries.contains_key(&key) {
+            self.touch(&key);
+        }
+        // Touching may have evicted the key to stay within `max_bytes`
+        if !self.entries.contains_key(&key) {
+            self.insert(key.clone(), default());
+        }
+        &mut self.entries.get_mut(&key).expect("entry present").value
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let slot = self.entries.remove(key)?;
+        self.order.remove(&slot.tick);
+        self.bytes -= slot.size;
+        self.resized.remove(key);
+        self.mark_deleted(key.clone());
+        self.report_usage();
+        Some(slot.value)
+    }
+
+    /// Keep only the entries for which `keep` returns true.
+    pub fn retain(&mut self, mut keep: impl FnMut(&K, &V) -> bool) {
+        let doomed: Vec<K> = self
+            .entries
+            .iter()
+            .filter(|(key, slot)| !keep(key, &slot.value))
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in doomed {
+            self.remove(&key);
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.entries.iter().map(|(key, slot)| (key, &slot.value))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Estimated size of the stored entries in bytes, as of the last write.
+    /// Always 0 unless `max_bytes` is set.
+    pub fn size_bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// Evict entries older than the TTL, returning how many were removed.
+    pub fn evict_expired(&mut self) -> usize {
+        let Some(ttl) = self.ttl else {
+            return 0;
+        };
+
+        let mut evicted = 0;
+        while let Some((_, key)) = self.order.first_key_value() {
+            let expired = self
+                .entries
+                .get(key)
+                .is_some_and(|slot| slot.updated.elapsed() > ttl);
+            if !expired {
+                break;
+            }
+            let key = key.clone();
+            self.remove(&key);
+            evicted += 1;
+        }
+        evicted
+    }
+
+    /// Capture every entry, least recently updated first, for a checkpoint.
+    pub fn snapshot(&self) -> Result<serde_json::Value> {
+        let entries: Vec<(&K, &V)> = self
+            .order
+            .values()
+            .filter_map(|key| self.entries.get(key).map(|slot| (key, &slot.value)))
+            .collect();
+        Ok(serde_json::to_value(entries)?)
+    }
+
+    /// Replace every entry with those of a checkpoint. Restored entries count
+    /// as freshly updated for TTL purposes.
+    pub fn restore(&mut self, state: serde_json::Value) -> Result<()> {
+        let entries: Vec<(K, V)> = serde_json::from_value(state)?;
+        self.retain(|_, _| false);
+        for (key, value) in entries {
+            self.insert(key, value);
+        }
+        Ok(())
+    }
+
+    /// Write pending changes to the persistent backend, if there is one.
+    pub fn flush(&mut self) -> Result<()> {
+        self.last_flush = Instant::now();
+        let Some(backend) = &self.backend else {
+            return Ok(());
+        };
+        if self.dirty.is_empty() && self.deleted.is_empty() {
+            return Ok(());
+        }
+
+        let mut upserts = Vec::with_capacity(self.dirty.len());
+        for key in self.dirty.drain() {
+            if let Some(slot) = self.entries.get(&key) {
+                upserts.push((serde_json::to_vec(&key)?, serde_json::to_vec(&slot.value)?));
+            }
+        }
+        let deletes = self
+            .deleted
+            .drain()
+            .map(|key| serde_json::to_vec(&key))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        backend.write(upserts, deletes)
+    }
+
+    /// Housekeeping before each write: size re-estimation, periodic TTL sweep
+    /// and backend flush.
+    fn maintain(&mut self) {
+        if !self.resized.is_empty() {
+            for key in std::mem::take(&mut self.resized) {
+                let Some(slot) = self.entries.get(&key) else {
+                    continue;
+                };
+                let size = self.estimate(&key, &slot.value);
+                if let Some(slot) = self.entries.get_mut(&key) {
+                    self.bytes = self.bytes - slot.size + size;
+                    slot.size = size;
+                }
+            }
+            self.enforce_capacity();
+        }
+
+        if let Some(ttl) = self.ttl
+            && self.last_sweep.elapsed() >= (ttl / 4).max(Duration::from_millis(100))
+        {
+            self.last_sweep = Instant::now();
+            self.evict_expired();
+        }
+
+        if self.backend.is_some()
+            && self.last_flush.elapsed() >= self.flush_interval
+            && let Err(e) = self.flush()
+        {
+            tracing::error!("Failed to flush state: {}", e);
+        }
+    }
+
+    fn advance(&mut self) -> u64 {
+        self.next_tick += 1;
+        self.next_tick
+    }
+
+    fn touch(&mut self, key: &K) {
+        self.maintain();
+        let tick = self.advance();
+        if let Some(slot) = self.entries.get_mut(key) {
+            self.order.remove(&slot.tick);
+            slot.tick = tick;
+            slot.updated = Instant::now();
+            self.order.insert(tick, key.clone());
+            if self.max_bytes.is_some() {
+                self.resized.insert(key.clone());
+            }
+        }
+        self.mark_dirty(key.clone());
+    }
+
+    /// Estimated size of an entry, if sizes are tracked.
+    fn estimate(&self, key: &K, value: &V) -> usize {
+        if self.max_bytes.is_none() {
+            return 0;
+        }
+        let key = serde_json::to_vec(key).map_or(0, |key| key.len());
+        let value = serde_json::to_vec(value).map_or(0, |value| value.len());
+        key + value
+    }
+
+    fn over_capacity(&self) -> bool {
+        self.max_keys.is_some_and(|max_keys| self.entries.len() > max_keys)
+            // The most recently updated entry is kept, however large
+            || self.max_bytes.is_some_and(|max_bytes| self.bytes > max_bytes && self.entries.len() > 1)
+    }
+
+    fn enforce_capacity(&mut self) {
+        while self.over_capacity() {
+            let Some((_, key)) = self.order.first_key_value() else {
+                break;
+            };
+            let key = key.clone();
+            self.remove(&key);
+        }
+    }
+
+    fn report_usage(&self) {
+        if let Some(usage) = &self.usage {
+            usage.update(self.entries.len(), self.max_bytes.map(|_| self.bytes));
+        }
+    }
+
+    fn mark_dirty(&mut self, key: K) {
+        if self.backend.is_some() {
+            self.deleted.remove(&key);
+            self.dirty.insert(key);
+        }
+    }
+
+    fn mark_deleted(&mut self, key: K) {
+        if self.backend.is_some() {
+            self.dirty.remove(&key);
+            self.deleted.insert(key);
+        }
+    }
+}
+
+impl<K, V> Default for StateStore<K, V>
+where
+    K: Eq + Hash + Clone + Serialize + DeserializeOwned,
+    V: Serialize + DeserializeOwned,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> Drop for StateStore<K, V>
+where
+    K: Eq + Hash + Clone + Serialize + DeserializeOwned,
+    V: Serialize + DeserializeOwned,
+{
+    fn drop(&mut self) {
+        if let Err(e) = self.flush() {
+            tracing::error!("Failed to flush state on drop: {}", e);
+        }
+    }
+}
+
+fn open_backend(config: &StateBackendConfig, namespace: &str) -> Result<Option<Box<dyn StateBackend>>> {
+    match config {
+        StateBackendConfig::Memory => Ok(None),
+        #[cfg(feature = "sled")]
+        StateBackendConfig::Sled { path } => Ok(Some(Box::new(sled_backend::SledBackend::open(path, namespace)?))),
+        #[cfg(not(feature = "sled"))]
+        StateBackendConfig::Sled { .. } => Err(anyhow!(
+            "state backend 'sled' for '{}' requires building with --features sled",
+            namespace
+        )),
+    }
+}
+
+#[cfg(feature = "sled")]
+mod sled_backend {
+    use super::StateBackend;
+
+    use anyhow::{Result, anyhow};
+    use std::collections::HashMap;
+    use std::sync::{Mutex, OnceLock};
+
+    /// Databases are opened once per path; sled locks the directory.
+    static DATABASES: OnceLock<Mutex<HashMap<String, sled::Db>>> = OnceLock::new();
+
+    pub struct SledBackend {
+        tree: sled::Tree,
+    }
+
+    impl SledBackend {
+        pub fn open(path: &str, namespace: &str) -> Result<Self> {
+            let mut databases = DATABASES
+                .get_or_init(|| Mutex::new(HashMap::new()))
+                .lock()
+                .map_err(|_| anyhow!("sled database registry poisoned"))?;
+
+            let db = match databases.get(path) {
+                Some(db) => db.clone(),
+                None => {
+                    let db = sled::open(path).map_err(|e| anyhow!("Failed to open state database '{}': {}", path, e))?;
+                    databases.insert(path.to_string(), db.clone());
+                    db
+                }
+            };
+
+            Ok(Self {
+                tree: db.open_tree(namespace)?,
+            })
+        }
+    }
+
+    impl StateBackend for SledBackend {
+        fn load(&self) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+            self.tree
+                .iter()
+                .map(|entry| {
+                    let (key, value) = entry?;
+                    Ok((key.to_vec(), value.to_vec()))
+                })
+                .collect()
+        }
+
+        fn write(&self, upserts: Vec<(Vec<u8>, Vec<u8>)>, deletes: Vec<Vec<u8>>) -> Result<()> {
+            let mut batch = sled::Batch::default();
+            for (key, value) in upserts {
+                batch.insert(key, value);
+            }
+            for key in deletes {
+                batch.remove(key);
+            }
+            self.tree.apply_batch(batch)?;
+            self.tree.flush()?;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_state_store_capacity_and_ttl() {
+        let config = StateConfig {
+            ttl_ms: Some(50),
+            max_keys: Some(2),
+            ..Default::default()
+        };
+        let mut store: StateStore<String, u32> = StateStore::open("test", Some(&config)).unwrap();
+
+        store.insert("a".to_string(), 1);
+        store.insert("b".to_string(), 2);
+        *store.get_mut(&"a".to_string()).unwrap() += 10;
+        store.insert("c".to_string(), 3);
+
+        // "b" was least recently updated
+        assert_eq!(store.len(), 2);
+        assert_eq!(store.get(&"a".to_string()), Some(&11));
+        assert!(!store.contains_key(&"b".to_string()));
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(store.evict_expired(), 2);
+        assert!(store.is_empty());
+    }
+
+    #[test]
+    fn test_state_store_max_bytes() {
+        let config = StateConfig {
+            max_bytes: Some(32),
+            ..Default::default()
+        };
+        let mut store: StateStore<String, Vec<u32>> = StateStore::open("test", Some(&config)).unwrap();
+
+        // Each entry is `"k"` plus `[1]`: 6 bytes
+        for key in ["a", "b", "c"] {
+            store.insert(key.to_string(), vec![1]);
+        }
+        assert_eq!(store.size_bytes(), 18);
+
+        // Growing "a" in place to 16 bytes is accounted for on the next write,
+        // which evicts "b", the least recently updated
+        store.get_mut(&"a".to_string()).unwrap().extend([1; 5]);
+        store.insert("d".to_string(), vec![1]);
+        assert_eq!(store.size_bytes(), 28);
+        assert!(!store.contains_key(&"b".to_string()));
+        assert_eq!(store.get(&"a".to_string()).map(Vec::len), Some(6));
+    }
+
+    #[cfg(feature = "sled")]
+    #[test]
+    fn test_state_store_sled_roundtrip() {
+        let path = std::env::temp_dir().join(format!("liminal-state-{}", std::process::id()));
+        let config = StateConfig {
+            backend: StateBackendConfig::Sled {
+                path: path.to_string_lossy().to_string(),
+            },
+            ..Default::default()
+        };
+
+        {
+            let mut store: StateStore<(String, String), f64> = StateStore::open("stage", Some(&config)).unwrap();
+            store.insert(("k".to_string(), "temp".to_string()), 21.5);
+            store.insert(("k".to_string(), "gone".to_string()), 0.0);
+            store.remove(&("k".to_string(), "gone".to_string()));
+        }
+
+        let store: StateStore<(String, String), f64> = StateStore::open("stage", Some(&config)).unwrap();
+        assert_eq!(store.len(), 1);
+        assert_eq!(store.get(&("k".to_string(), "temp".to_string())), Some(&21.5));
+        let _ = std::fs::remove_dir_all(&path);
+    }
+}
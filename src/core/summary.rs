@@ -0,0 +1,106 @@
+//! Run Summary
+//!
+//! When a run ends, the manager logs what each stage did: messages received
+//! and published, messages dropped, errors, restarts, processing latency and
+//! watermark lag, along with the run's duration. The same summary can be
+//! written to a JSON file, for comparing runs or checking them in CI.
+//!
+//! Counts come from the stage metrics (see [`super::metrics`]), so stages
+//! with `timing.metrics_enabled` off report none.
+
+use super::channel::ChannelStats;
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::time::Duration;
+
+/// Processing latency of a stage, in milliseconds.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LatencySummary {
+    pub mean_ms: f64,
+    /// Percentiles, estimated from the latency histogram's buckets
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+    pub p99_ms: f64,
+}
+
+/// What one running stage (or replica) did.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StageSummary {
+    pub stage: String,
+    /// Messages received from input channels
+    pub messages_in: u64,
+    /// Messages published to output and side output channels
+    pub messages_out: u64,
+    /// Messages missed by lagging behind broadcast inputs, shed or routed
+    /// over the in-flight limit or expired
+    pub dropped: u64,
+    pub errors: u64,
+    pub restarts: u64,
+    /// `None` if the stage processed no input
+    pub latency: Option<LatencySummary>,
+    /// Wall-clock time minus the latest input watermark when the run ended,
+    /// or `None` for sources
+    pub watermark_lag_ms: Option<f64>,
+}
+
+/// Counters of one channel when the run ended.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ChannelSummary {
+    pub channel: String,
+    #[serde(flatten)]
+    pub stats: ChannelStats,
+}
+
+/// What a run did, stage by stage.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RunSummary {
+    pub duration_ms: u64,
+    pub stages: Vec<StageSummary>,
+    pub channels: Vec<ChannelSummary>,
+}
+
+impl RunSummary {
+    pub fn new(duration: Duration, stages: Vec<StageSummary>, channels: Vec<(String, ChannelStats)>) -> Self {
+        Self {
+            duration_ms: duration.as_millis() as u64,
+            stages,
+            channels: channels.into_iter().map(|(channel, stats)| ChannelSummary { channel, stats }).collect(),
+        }
+    }
+
+    /// Log the summary, one line per stage.
+    pub fn log(&self) {
+        tracing::info!("Run summary after {:.1}s:", self.duration_ms as f64 / 1000.0);
+        for stage in &self.stages {
+            tracing::info!("  {}", stage);
+        }
+    }
+
+    /// Write the summary to `path` as JSON.
+    pub fn write(&self, path: &str) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, json).with_context(|| format!("Failed to write run summary to '{}'", path))
+    }
+}
+
+impl std::fmt::Display for StageSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: {} in, {} out, {} dropped, {} errors, {} restarts",
+            self.stage, self.messages_in, self.messages_out, self.dropped, self.errors, self.restarts
+        )?;
+        if let Some(latency) = &self.latency {
+            write!(
+                f,
+                "; latency mean {:.2}ms, p50 {:.2}ms, p95 {:.2}ms, p99 {:.2}ms",
+                latency.mean_ms, latency.p50_ms, latency.p95_ms, latency.p99_ms
+            )?;
+        }
+        if let Some(lag) = self.watermark_lag_ms {
+            write!(f, "; watermark lag {:.0}ms", lag)?;
+        }
+        Ok(())
+    }
+}
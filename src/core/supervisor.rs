@@ -0,0 +1,269 @@
+//! Supervision
+//!
+//! Each running stage is driven by [`supervise`], which catches a panicking
+//! processor or a failed `run`, and restarts the stage according to its
+//! [`RestartConfig`]. A restarted stage keeps its channels: it is restored from
+//! its last checkpoint (if any), initialised again and resumes consuming.
+//!
+//! Restarts are counted from the stage's last recovery: once a restarted stage
+//! has run for `stable_ms` without failing, its retries and backoff start over.
+//!
+//! Stage status and restart counts are recorded in a shared [`Health`], and a
+//! pipeline with a stage that has failed is reported as degraded. Stages also
+//! flag themselves there while they process slower than their limit, and
+//! sinks accumulate their delivery results there.
+
+use super::context::DeliveryReport;
+use super::metrics::metrics;
+use super::stage::Stage;
+use crate::config::types::{RestartConfig, RestartPolicy};
+
+use futures::FutureExt;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::panic::AssertUnwindSafe;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Lifecycle of a supervised stage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StageStatus {
+    Running,
+    /// Paused through the control API
+    Paused,
+    /// Failed and waiting to be restarted
+    Restarting,
+    /// Failed and not restarted
+    Failed,
+    /// Stopped normally
+    Stopped,
+}
+
+/// Health of one stage.
+#[derive(Debug, Clone)]
+pub struct StageHealth {
+    pub status: StageStatus,
+    /// Restarts since the stage started, including those before a recovery
+    pub restarts: u32,
+    pub last_error: Option<String>,
+    /// Processing slower than the stage's `limits.slow_processing_ms`
+    pub slow: bool,
+    /// Delivery results since start, for sinks that report them
+    pub delivery: Option<DeliveryReport>,
+}
+
+impl StageHealth {
+    /// A stage is degraded once it has failed, even if it recovered.
+    pub fn is_degraded(&self) -> bool {
+        self.restarts > 0 || self.status == StageStatus::Failed
+    }
+}
+
+/// Health of every running stage, shared by the supervisors and the manager.
+#[derive(Debug, Default)]
+pub struct Health {
+    stages: Mutex<HashMap<String, StageHealth>>,
+}
+
+impl Health {
+    fn update(&self, stage: &str, update: impl FnOnce(&mut StageHealth)) {
+        let mut stages = self.stages.lock().expect("health: lock failed, poisoned mutex!");
+        let health = stages.entry(stage.to_string()).or_insert(StageHealth {
+            status: StageStatus::Running,
+            restarts: 0,
+            last_error: None,
+            slow: false,
+            delivery: None,
+        });
+        update(health);
+    }
+
+    /// Record that a stage was paused or resumed.
+    pub fn set_paused(&self, stage: &str, paused: bool) {
+        self.update(stage, |health| {
+            if paused && health.status == StageStatus::Running {
+                health.status = StageStatus::Paused;
+            } else if !paused && health.status == StageStatus::Paused {
+                health.status = StageStatus::Running;
+            }
+        });
+    }
+
+    /// Record that a stage became slow or caught up again.
+    pub fn set_slow(&self, stage: &str, slow: bool) {
+        self.update(stage, |health| health.slow = slow);
+    }
+
+    /// Add delivery results reported by a sink.
+    pub fn record_delivery(&self, stage: &str, report: &DeliveryReport) {
+        self.update(stage, |health| health.delivery.get_or_insert_default().merge(report));
+    }
+
+    pub fn stage(&self, stage: &str) -> Option<StageHealth> {
+        self.stages.lock().expect("health: lock failed, poisoned mutex!").get(stage).cloned()
+    }
+
+    /// Health of every stage, sorted by stage name.
+    pub fn stages(&self) -> Vec<(String, StageHealth)> {
+        let stages = self.stages.lock().expect("health: lock failed, poisoned mutex!");
+        let mut stages: Vec<_> = stages.iter().map(|(name, health)| (name.clone(), health.clone())).collect();
+        stages.sort_by(|a, b| a.0.cmp(&b.0));
+        stages
+    }
+
+    pub fn is_degraded(&self) -> bool {
+        self.stages
+            .lock()
+            .expect("health: lock failed, poisoned mutex!")
+            .values()
+            .any(StageHealth::is_degraded)
+    }
+}
+
+/// Delay before restart number `attempt` (counting from 1), or `None` when
+/// the policy allows no further restarts.
+pub fn restart_delay(config: &RestartConfig, attempt: u32) -> Option<Duration> {
+    let within_retries = config.max_retries.is_none_or(|max_retries| attempt <= max_retries);
+    let delay_ms = match config.policy {
+        RestartPolicy::Never => return None,
+        RestartPolicy::Always => config.delay_ms,
+        RestartPolicy::Backoff if within_retries => {
+            let factor = 1_u64.checked_shl(attempt - 1).unwrap_or(u64::MAX);
+            config.delay_ms.saturating_mul(factor).min(config.max_delay_ms)
+        }
+        RestartPolicy::MaxRetries if within_retries => config.delay_ms,
+        RestartPolicy::Backoff | RestartPolicy::MaxRetries => return None,
+    };
+    Some(Duration::from_millis(delay_ms))
+}
+
+/// Restart number for a failure after the stage ran for `ran_for` since its
+/// last (re)start: counting starts over once it has run for `stable`.
+fn next_attempt(attempt: u32, ran_for: Duration, stable: Duration) -> u32 {
+    if ran_for >= stable { 1 } else { attempt + 1 }
+}
+
+fn panic_message(panic: &(dyn std::any::Any + Send)) -> String {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+/// Run a stage once, turning a panic into an error.
+async fn run_once(stage: &mut Stage) -> Result<(), String> {
+    match AssertUnwindSafe(stage.run()).catch_unwind().await {
+        Ok(result) => result.map_err(|e| e.to_string()),
+        Err(panic) => Err(format!("panicked: {}", panic_message(panic.as_ref()))),
+    }
+}
+
+/// Run a stage until it stops, restarting it when it fails as its restart
+/// policy allows. Returns `true` if the stage stopped cleanly.
+pub async fn supervise(mut stage: Stage, config: RestartConfig, health: &Health) -> bool {
+    let name = stage.name().to_string();
+    health.update(&name, |health| health.status = StageStatus::Running);
+
+    let stable = Duration::from_millis(config.stable_ms);
+    let mut started = Instant::now();
+    let mut outcome = run_once(&mut stage).await;
+    let mut attempt = 0;
+    loop {
+        let error = match outcome {
+            Ok(()) => {
+                health.update(&name, |health| health.status = StageStatus::Stopped);
+                return true;
+            }
+            Err(error) => error,
+        };
+        tracing::error!("Error running stage [{}]: {}", name, error);
+
+        if attempt > 0 && started.elapsed() >= stable {
+            tracing::info!("Stage '{}' ran for {:?} before failing; restart count reset", name, started.elapsed());
+        }
+
+        // A stage that fails while stopping is not brought back
+        attempt = next_attempt(attempt, started.elapsed(), stable);
+        let delay = if stage.is_stopping() { None } else { restart_delay(&config, attempt) };
+        let Some(delay) = delay else {
+            tracing::error!("Stage '{}' failed and will not be restarted; pipeline degraded", name);
+            health.update(&name, |health| {
+                health.status = StageStatus::Failed;
+                health.last_error = Some(error);
+            });
+            return false;
+        };
+
+        tracing::warn!("Restarting stage '{}' in {:?} (restart {}); pipeline degraded", name, delay, attempt);
+        metrics().record_restart(&name);
+        health.update(&name, |health| {
+            health.status = StageStatus::Restarting;
+            health.restarts += 1;
+            health.last_error = Some(error);
+        });
+        if !stage.pause(delay).await {
+            health.update(&name, |health| health.status = StageStatus::Stopped);
+            return true;
+        }
+
+        // Recover the last good state, then start over
+        if let Err(e) = stage.restore_checkpoint() {
+            tracing::warn!("Failed to restore stage '{}' from checkpoint: {}", name, e);
+        }
+        outcome = match stage.init().await {
+            Ok(()) => {
+                let status = if stage.is_paused() { StageStatus::Paused } else { StageStatus::Running };
+                health.update(&name, |health| health.status = status);
+                started = Instant::now();
+                run_once(&mut stage).await
+            }
+            Err(e) => Err(format!("failed to initialise: {}", e)),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_restart_delay_follows_policy() {
+        let backoff = RestartConfig {
+            policy: RestartPolicy::Backoff,
+            max_retries: Some(5),
+            delay_ms: 100,
+            max_delay_ms: 1000,
+            ..Default::default()
+        };
+        let delays: Vec<_> = (1..=6).map(|attempt| restart_delay(&backoff, attempt)).collect();
+        let ms = |ms| Some(Duration::from_millis(ms));
+        assert_eq!(delays, [ms(100), ms(200), ms(400), ms(800), ms(1000), None]);
+
+        let max_retries = RestartConfig {
+            policy: RestartPolicy::MaxRetries,
+            max_retries: Some(2),
+            ..Default::default()
+        };
+        assert!(restart_delay(&max_retries, 2).is_some());
+        assert!(restart_delay(&max_retries, 3).is_none());
+
+        let always = RestartConfig { policy: RestartPolicy::Always, ..Default::default() };
+        assert_eq!(restart_delay(&always, 1000), ms(500));
+        assert!(restart_delay(&RestartConfig::default(), 1).is_none());
+    }
+
+    #[test]
+    fn test_restart_count_resets_after_stable_run() {
+        let stable = Duration::from_secs(300);
+        assert_eq!(next_attempt(0, Duration::from_secs(1), stable), 1);
+        assert_eq!(next_attempt(4, Duration::from_secs(1), stable), 5);
+
+        // A stage that recovered for long enough starts again from the first
+        // retry and the shortest backoff
+        assert_eq!(next_attempt(4, Duration::from_secs(3600), stable), 1);
+    }
+}
@@ -0,0 +1,162 @@
+//! Channel Taps
+//!
+//! A tap streams copies of the messages published to a channel while a
+//! pipeline runs, for tracing where messages go without reconfiguring it.
+//! Stages publish to every channel through a [`TappedChannel`], which passes
+//! each message on and, only while a tap is attached, hands a copy to it. A
+//! tap never holds up the pipeline: a tap that falls behind misses messages,
+//! and one whose receiver is dropped is detached at the next publish.
+//!
+//! Taps are attached through the control API's `tap` command, which the
+//! `liminal tap` subcommand uses.
+
+use super::channel::{PubSubChannel, PublishError, Subscriber};
+use super::message::Message;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Messages a tap holds for its reader before it misses newer ones.
+const TAP_CAPACITY: usize = 1024;
+
+/// One attached tap.
+struct Tap {
+    sender: flume::Sender<Message>,
+    /// Pass on one message in `every`
+    every: u64,
+    seen: u64,
+}
+
+/// The taps attached to one channel.
+#[derive(Default)]
+struct TapPoint {
+    /// Whether any tap is attached, checked on every publish without locking
+    attached: AtomicBool,
+    taps: Mutex<Vec<Tap>>,
+}
+
+impl TapPoint {
+    /// Hand `message` to the taps whose sample it falls in, detaching the
+    /// taps nobody reads any more.
+    fn offer(&self, message: &Message) {
+        let mut taps = self.taps.lock().unwrap();
+        taps.retain_mut(|tap| {
+            tap.seen += 1;
+            if (tap.seen - 1) % tap.every != 0 {
+                return !tap.sender.is_disconnected();
+            }
+            !matches!(tap.sender.try_send(message.clone()), Err(flume::TrySendError::Disconnected(_)))
+        });
+        self.attached.store(!taps.is_empty(), Ordering::Relaxed);
+    }
+}
+
+/// The tap points of every channel stages publish to.
+#[derive(Default)]
+pub struct Taps {
+    points: Mutex<HashMap<String, Arc<TapPoint>>>,
+}
+
+impl Taps {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Wrap the channel a stage publishes to, so it can be tapped.
+    pub fn wrap(&self, name: &str, channel: Arc<dyn PubSubChannel<Message>>) -> Arc<dyn PubSubChannel<Message>> {
+        let point = self.points.lock().unwrap().entry(name.to_string()).or_default().clone();
+        Arc::new(TappedChannel { inner: channel, point })
+    }
+
+    /// Attach a tap to the channel `name`, receiving one published message in
+    /// `every`. The tap stays attached until the receiver is dropped.
+    pub fn attach(&self, name: &str, every: u64) -> Result<flume::Receiver<Message>> {
+        if every == 0 {
+            return Err(anyhow::anyhow!("Tap sample must be at least 1"));
+        }
+        let point = self
+            .points
+            .lock()
+            .unwrap()
+            .get(name)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("Unknown channel: '{}'", name))?;
+
+        let (sender, receiver) = flume::bounded(TAP_CAPACITY);
+        point.taps.lock().unwrap().push(Tap { sender, every, seen: 0 });
+        point.attached.store(true, Ordering::Relaxed);
+        tracing::info!("Tap attached to channel '{}'", name);
+        Ok(receiver)
+    }
+}
+
+/// A channel whose published messages can be tapped.
+pub struct TappedChannel {
+    inner: Arc<dyn PubSubChannel<Message>>,
+    point: Arc<TapPoint>,
+}
+
+#[async_trait]
+impl PubSubChannel<Message> for TappedChannel {
+    async fn publish(&self, msg: Message) -> Result<(), PublishError<Message>> {
+        if !self.point.attached.load(Ordering::Relaxed) {
+            return self.inner.publish(msg).await;
+        }
+        let copy = msg.clone();
+        self.inner.publish(msg).await?;
+        self.point.offer(&copy);
+        Ok(())
+    }
+
+    fn subscribe(&self) -> Subscriber<Message> {
+        self.inner.subscribe()
+    }
+
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    fn capacity(&self) -> Option<usize> {
+        self.inner.capacity()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::types::ChannelConfig;
+    use crate::core::channel::Channel;
+    use futures::executor::block_on;
+    use serde_json::json;
+
+    #[test]
+    fn test_taps_sample_published_messages_until_dropped() {
+        let taps = Taps::new();
+        let raw: Arc<dyn PubSubChannel<Message>> = Arc::new(Channel::open("raw", &ChannelConfig::default()).unwrap());
+        let mut subscriber = raw.subscribe();
+        let raw = taps.wrap("raw", raw);
+        assert!(taps.attach("missing", 1).is_err());
+
+        let publish = |value: i32| block_on(raw.publish(Message::new("sensor", "raw", json!({"value": value})))).unwrap();
+        publish(0);
+        let tap = taps.attach("raw", 2).unwrap();
+        for value in 1..=5 {
+            publish(value);
+        }
+
+        let tapped: Vec<_> = tap.drain().map(|message| message.payload["value"].clone()).collect();
+        assert_eq!(tapped, vec![json!(1), json!(3), json!(5)]);
+        // Readers of the channel still receive every message
+        for value in 0..=5 {
+            assert_eq!(block_on(subscriber.try_recv()).into_message().unwrap().payload, json!({"value": value}));
+        }
+
+        drop(tap);
+        publish(6);
+        let point = taps.points.lock().unwrap()["raw"].clone();
+        assert!(!point.attached.load(Ordering::Relaxed));
+    }
+}
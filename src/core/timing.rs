@@ -73,6 +73,9 @@ pub enum ClockSource {
     
     /// Hybrid logical clock (combines logical and wall-clock)
     Hybrid,
+    
+    /// Event times of the data, for reprocessing history (back-fill)
+    Event,
 }
 
 /// Manages watermark generation and propagation
@@ -82,6 +85,7 @@ pub struct WatermarkManager {
     last_watermark: Option<SystemTime>,
     last_periodic_update: SystemTime,
     event_timestamps: Vec<SystemTime>, // For heuristic watermarks
+    latest_event_time: Option<SystemTime>, // For periodic watermarks on the event clock
 }
 
 impl WatermarkManager {
@@ -91,6 +95,7 @@ impl WatermarkManager {
             last_watermark: None,
             last_periodic_update: SystemTime::now(),
             event_timestamps: Vec::new(),
+            latest_event_time: None,
         }
     }
     
@@ -100,7 +105,17 @@ impl WatermarkManager {
             WatermarkStrategy::None => None,
             
             WatermarkStrategy::Periodic { interval } => {
-                let now = SystemTime::now();
+                let now = match self.config.clock_source {
+                    ClockSource::Event => {
+                        let event_time = message.timing.event_time;
+                        let latest = self.latest_event_time.map_or(event_time, |latest| latest.max(event_time));
+                        if self.latest_event_time.replace(latest).is_none() {
+                            self.last_periodic_update = latest;
+                        }
+                        latest
+                    }
+                    _ => SystemTime::now(),
+                };
                 if now.duration_since(self.last_periodic_update).unwrap_or(Duration::ZERO) >= *interval {
                     self.last_periodic_update = now;
                     let watermark = now - self.config.max_lateness;
@@ -194,10 +209,11 @@ impl TimingHelpers {
     }
     
     /// Parse ISO 8601 timestamp string
-    pub fn parse_iso_timestamp(_timestamp_str: &str) -> Option<SystemTime> {
-        // Basic ISO 8601 parsing - could be enhanced with chrono crate
-        // For now, just handle simple cases
-        None // TODO: Implement proper ISO 8601 parsing
+    /// Timestamps without an offset are assumed to be UTC
+    pub fn parse_iso_timestamp(timestamp_str: &str) -> Option<SystemTime> {
+        use crate::processors::common::time_utils::TimeUtils;
+
+        TimeUtils::parse_iso8601(timestamp_str).map(SystemTime::from)
     }
     
     /// Create a message with timing information propagated from source
@@ -53,6 +53,11 @@ impl TimingMixin {
         self.sequence_counter
     }
     
+    /// Resume the sequence counter from a checkpointed value
+    pub fn restore_sequence_id(&mut self, sequence_id: u64) {
+        self.sequence_counter = sequence_id;
+    }
+    
     /// Create a message with timing semantics applied
     pub fn create_message_with_timing(
         &mut self,
@@ -64,6 +69,7 @@ impl TimingMixin {
         let sequence_id = self.next_sequence_id();
         
         let mut message = Message::new_with_event_time(source, topic, payload, event_time);
+        message.timing.ingestion_time = SystemTime::now();
         message = message.with_sequence_id(sequence_id);
         
         // Add processing deadline if configured
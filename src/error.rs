@@ -0,0 +1,162 @@
+//! Error Taxonomy
+//!
+//! Processors and the pipeline builder return `anyhow::Result`, but the
+//! failures a user can act on are raised as a [`LiminalError`], which
+//! `anyhow` carries unchanged. Callers recover it with
+//! `error.downcast_ref::<LiminalError>()` (or [`LiminalError::find`] anywhere
+//! in the chain), and the CLI prints its [`hint`](LiminalError::hint) below
+//! the error.
+//!
+//! Configuration errors are raised by the processor factory and by
+//! `ProcessorConfig` implementations, which do not know the name of their
+//! stage. The pipeline builder fills it in with [`LiminalError::in_stage`] as
+//! it creates each stage.
+
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum LiminalError {
+    /// A stage names a processor type that is not registered
+    #[error("{}unknown processor type '{name}'", stage_prefix(stage))]
+    UnknownProcessor {
+        stage: String,
+        name: String,
+        /// Registered processor type closest to `name`, if any is close
+        suggestion: Option<String>,
+    },
+
+    /// A required parameter is not set
+    #[error("{}missing required parameter '{key}'", stage_prefix(stage))]
+    MissingParameter { stage: String, key: String },
+
+    /// A parameter is set to a value the processor cannot use
+    #[error("{}invalid parameter '{key}': {reason}", stage_prefix(stage))]
+    InvalidParameter { stage: String, key: String, reason: String },
+
+    /// A stage could not be created for a reason other than its parameters
+    #[error("failed to create stage '{stage}'")]
+    StageCreation {
+        stage: String,
+        #[source]
+        source: anyhow::Error,
+    },
+
+    /// Stages whose inputs no stage produces, or that feed each other in a cycle
+    #[error("unmet or circular input dependencies in stages: {}", stages.join(", "))]
+    UnresolvedInputs { stages: Vec<String> },
+
+    /// Stages whose brokers or servers were unreachable before the pipeline started
+    #[error("preflight checks failed for stages: {}", stages.join(", "))]
+    PreflightFailed { stages: Vec<String> },
+
+    /// A connection to a broker or server failed and is not retried (further)
+    #[error("connection to {endpoint} failed")]
+    ConnectionError {
+        endpoint: String,
+        #[source]
+        source: anyhow::Error,
+    },
+
+    /// A channel has no subscribers left to receive from it
+    #[error("channel '{channel}' is closed")]
+    ChannelClosed { channel: String },
+
+    /// A channel with the `error` overflow policy is full
+    #[error("channel '{channel}' is full")]
+    ChannelFull { channel: String },
+}
+
+fn stage_prefix(stage: &str) -> String {
+    match stage {
+        "" => String::new(),
+        stage => format!("stage '{}': ", stage),
+    }
+}
+
+impl LiminalError {
+    /// A missing required parameter, for the builder to attribute to a stage.
+    pub fn missing_parameter(key: &str) -> Self {
+        Self::MissingParameter { stage: String::new(), key: key.to_string() }
+    }
+
+    /// An invalid parameter, for the builder to attribute to a stage.
+    pub fn invalid_parameter(key: &str, reason: impl std::fmt::Display) -> Self {
+        Self::InvalidParameter { stage: String::new(), key: key.to_string(), reason: reason.to_string() }
+    }
+
+    /// Attribute a configuration error to `stage`, unless it already names one.
+    pub fn in_stage(mut self, name: &str) -> Self {
+        if let Self::UnknownProcessor { stage, .. }
+        | Self::MissingParameter { stage, .. }
+        | Self::InvalidParameter { stage, .. } = &mut self
+            && stage.is_empty()
+        {
+            *stage = name.to_string();
+        }
+        self
+    }
+
+    /// The first `LiminalError` in the chain of `error`.
+    pub fn find(error: &anyhow::Error) -> Option<&LiminalError> {
+        error.chain().find_map(|cause| cause.downcast_ref::<LiminalError>())
+    }
+
+    /// What the user can do about the error.
+    pub fn hint(&self) -> Option<String> {
+        match self {
+            Self::UnknownProcessor { suggestion: Some(suggestion), .. } => {
+                Some(format!("did you mean '{}'? `liminal --list-processors` lists the available types", suggestion))
+            }
+            Self::UnknownProcessor { .. } => Some(
+                "`liminal --list-processors` lists the available types; some need a Cargo feature or a plugin".to_string(),
+            ),
+            Self::MissingParameter { stage, key } => Some(format!(
+                "add `{}` to the parameters of stage '{}'; `liminal schema` describes each processor's parameters",
+                key, stage
+            )),
+            Self::InvalidParameter { .. } => {
+                Some("`liminal validate` checks parameter types; `liminal schema` describes each processor's parameters".to_string())
+            }
+            Self::UnresolvedInputs { .. } => Some(
+                "check that every input channel is the output or side output of another stage".to_string(),
+            ),
+            Self::PreflightFailed { .. } => Some(
+                "the errors above name each unreachable endpoint; `--skip-preflight` starts the pipeline anyway".to_string(),
+            ),
+            Self::ConnectionError { endpoint, .. } => Some(format!(
+                "check that {} is reachable, and the connection's address, credentials and TLS settings",
+                endpoint
+            )),
+            Self::StageCreation { .. } | Self::ChannelClosed { .. } | Self::ChannelFull { .. } => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context as _;
+
+    #[test]
+    fn test_errors_survive_anyhow_and_name_their_stage() {
+        let error: anyhow::Error = LiminalError::missing_parameter("field_in").into();
+        let error = match error.downcast::<LiminalError>() {
+            Ok(error) => anyhow::Error::new(error.in_stage("smooth")),
+            Err(error) => error,
+        };
+        assert_eq!(error.to_string(), "stage 'smooth': missing required parameter 'field_in'");
+
+        let error = error.context("pipeline building");
+        let found = LiminalError::find(&error).unwrap();
+        assert!(matches!(found, LiminalError::MissingParameter { stage, key } if stage == "smooth" && key == "field_in"));
+        assert!(found.hint().unwrap().contains("add `field_in`"));
+
+        let io = std::io::Error::from(std::io::ErrorKind::ConnectionRefused);
+        let error: anyhow::Error = LiminalError::ConnectionError { endpoint: "localhost:1883".to_string(), source: io.into() }.into();
+        assert_eq!(format!("{:#}", error), "connection to localhost:1883 failed: connection refused");
+        assert!(error.chain().any(|cause| cause.downcast_ref::<std::io::Error>().is_some()));
+
+        let error = Err::<(), _>(anyhow::anyhow!("no route")).context("connecting").unwrap_err();
+        assert!(LiminalError::find(&error).is_none());
+    }
+}
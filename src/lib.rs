@@ -0,0 +1,40 @@
+//! Liminal - A framework for building data processing pipelines
+//!
+//! The `liminal` binary runs pipelines described in TOML. The same engine can
+//! be embedded in a Rust application, either from a `Config` (loaded from a
+//! file or built in code) or with `PipelineBuilder`:
+//!
+//! ```rust,ignore
+//! use liminal::{PipelineManager, config::load_config};
+//!
+//! let config = load_config("config/config.toml")?;
+//! PipelineManager::new(config)
+//!     .build_all()?
+//!     .connect_stages()
+//!     .await?
+//!     .start_all()
+//!     .await?
+//!     .wait_for_all()
+//!     .await?;
+//! ```
+//!
+//! Custom processors are registered with `register_processor_with_meta`
+//! before the pipeline is built, and are then available as a stage `type`.
+
+#![allow(dead_code)]
+
+pub mod builder;
+pub mod config;
+pub mod core;
+pub mod error;
+pub mod logging;
+pub mod processors;
+pub mod testing;
+
+pub use builder::PipelineBuilder;
+pub use config::{Config, ProcessorMetadata, StageConfig, load_config};
+pub use core::message::Message;
+pub use error::LiminalError;
+pub use core::pipeline::PipelineManager;
+pub use processors::Processor;
+pub use processors::factory::{ProcessorConstructor, register_processor, register_processor_with_meta};
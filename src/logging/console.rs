@@ -5,7 +5,10 @@ pub fn init_logging(default_level: &str) {
     let filter =
         EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(default_level));
 
+    // Logs go to stderr, keeping stdout for output that may be piped, such as
+    // `liminal schema` or the console sink
     fmt()
+        .with_writer(std::io::stderr)
         .with_env_filter(filter)
         .with_target(true)
         .with_level(true)
@@ -1,11 +1,19 @@
-#![allow(dead_code)]
+use clap::{Parser, Subcommand};
+use anyhow::Context as _;
+use liminal::{LiminalError, config, core, logging, processors};
 
-use clap::Parser;
+#[cfg(all(feature = "tracking-allocator", not(feature = "mimalloc")))]
+#[global_allocator]
+static ALLOCATOR: core::memory::TrackingAllocator = core::memory::TrackingAllocator::new(std::alloc::System);
 
-mod config;
-mod core;
-mod logging;
-mod processors;
+#[cfg(all(feature = "tracking-allocator", feature = "mimalloc"))]
+#[global_allocator]
+static ALLOCATOR: core::memory::TrackingAllocator<mimalloc::MiMalloc> =
+    core::memory::TrackingAllocator::new(mimalloc::MiMalloc);
+
+#[cfg(all(feature = "mimalloc", not(feature = "tracking-allocator")))]
+#[global_allocator]
+static ALLOCATOR: mimalloc::MiMalloc = mimalloc::MiMalloc;
 
 /// Liminal - A framework for building data processing pipelines
 #[derive(Parser)]
@@ -26,10 +34,24 @@ mod processors;
     time pipelines using TOML configuration files.
 ------------------------------------------------------------")]
 struct Cli {
-    /// Configuration file path
-    #[arg(short, long, default_value = "./config/config.toml")]
+    /// Configuration file path (LIMINAL_CONFIG, when set, is loaded instead)
+    #[arg(short, long, global = true, default_value = "./config/config.toml")]
     config: String,
 
+    /// Override a configuration value after loading, as KEY=VALUE with a
+    /// dotted KEY such as inputs.mqtt_in.parameters.qos (repeatable)
+    #[arg(long = "set", value_name = "KEY=VALUE", global = true)]
+    set: Vec<String>,
+
+    /// Run only this pipeline, with the sources and sinks connected to it
+    /// (repeatable)
+    #[arg(long = "pipeline", value_name = "NAME", global = true)]
+    pipelines: Vec<String>,
+
+    /// Leave out this stage, wherever it is in the configuration (repeatable)
+    #[arg(long = "exclude-stage", value_name = "NAME", global = true)]
+    exclude_stages: Vec<String>,
+
     /// Log level (trace, debug, info, warn, error)
     #[arg(short, long, default_value = "info")]
     log_level: String,
@@ -37,12 +59,209 @@ struct Cli {
     /// List available processor types
     #[arg(short = 'L', long)]
     list_processors: bool,
+
+    /// Reprocess history: sources read the messages between --from and
+    /// --until in event-time order, and the run exits once they are processed
+    #[arg(long)]
+    backfill: bool,
+
+    /// Start of the back-fill, inclusive (RFC 3339 or epoch milliseconds)
+    #[arg(long, requires = "backfill")]
+    from: Option<String>,
+
+    /// End of the back-fill, exclusive (RFC 3339 or epoch milliseconds)
+    #[arg(long, requires = "backfill")]
+    until: Option<String>,
+
+    /// Also write the summary logged when the run ends to this file, as JSON
+    #[arg(long)]
+    summary: Option<String>,
+
+    /// Start without first checking that brokers and servers are reachable
+    #[arg(long)]
+    skip_preflight: bool,
+
+    /// Run under a service manager: tell systemd when the pipeline is ready,
+    /// and reload the configuration on SIGHUP
+    #[arg(long)]
+    service: bool,
+
+    /// Write the process id to this file while running
+    #[arg(long, value_name = "PATH")]
+    pid_file: Option<String>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Render the stage and channel topology as Graphviz DOT or Mermaid
+    Graph {
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t = config::graph::GraphFormat::Dot)]
+        format: config::graph::GraphFormat,
+    },
+
+    /// Check the configuration for errors, including wiring between stages
+    Validate,
+
+    /// Print a JSON Schema for configuration files, for editor completion
+    Schema,
+
+    /// Run the pipeline tests of a test file against the configuration
+    Test {
+        /// Test file
+        #[arg(short, long)]
+        tests: String,
+    },
+
+    /// Stream the messages published to a channel of the running pipeline,
+    /// through its control API
+    Tap {
+        /// Channel to tap
+        channel: String,
+
+        /// Stream one message in every N
+        #[arg(short, long, default_value_t = 1)]
+        sample: u64,
+
+        /// Stop after this many messages
+        #[arg(short = 'n', long)]
+        limit: Option<u64>,
+
+        /// Append the messages to this file instead of printing them
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+
+    /// Measure throughput and latency of a built-in pipeline: a simulated
+    /// source, a chain of transforms and a discarding sink
+    Bench {
+        /// Messages to publish
+        #[arg(short = 'n', long, default_value_t = 100_000)]
+        messages: u64,
+
+        /// Transforms between the source and the sink
+        #[arg(short, long, default_value_t = 3)]
+        transforms: usize,
+
+        /// Type of every channel
+        #[arg(long, value_enum, default_value_t = config::types::ChannelType::Direct)]
+        channel: config::types::ChannelType,
+
+        /// Capacity of every channel
+        #[arg(long, default_value_t = 1024)]
+        capacity: usize,
+
+        /// Readings published per columnar batch (1 publishes JSON messages)
+        #[arg(long, default_value_t = 1)]
+        batch_size: usize,
+    },
+
+    /// Write a runnable starter configuration
+    Init {
+        /// Starter template
+        #[arg(value_enum, default_value_t = config::starter::StarterTemplate::SimulatedDemo)]
+        template: config::starter::StarterTemplate,
+
+        /// File to write
+        #[arg(short, long, default_value = "./liminal.toml")]
+        output: String,
+
+        /// Overwrite the file if it exists
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+/// How long each preflight check may wait for a broker or server to answer.
+const PREFLIGHT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Load the configuration with the `--set` overrides, narrowed to the
+/// `--pipeline` and `--exclude-stage` selection and without the stages
+/// turned off with `enabled = false`, exiting on failure.
+fn load_config_or_exit(cli: &Cli) -> config::Config {
+    load_config(cli).unwrap_or_else(|e| exit_with(&e))
+}
+
+/// Load the configuration like `load_config_or_exit`, keeping the disabled
+/// stages.
+fn load_all_stages_or_exit(cli: &Cli) -> config::Config {
+    load_all_stages(cli).unwrap_or_else(|e| exit_with(&e))
+}
+
+/// Load the configuration like `load_config_or_exit`, returning failures.
+fn load_config(cli: &Cli) -> anyhow::Result<config::Config> {
+    let mut config = load_all_stages(cli)?;
+    for (name, _) in config.remove_disabled() {
+        tracing::info!("Stage '{}' is disabled", name);
+    }
+    Ok(config)
+}
+
+fn load_all_stages(cli: &Cli) -> anyhow::Result<config::Config> {
+    let mut config = config::load_config_with_env(&cli.config, &cli.set)
+        .map_err(|e| anyhow::anyhow!("Failed to load config from '{}': {}", config_source(cli), e))?;
+    config::select::select(&mut config, &cli.pipelines, &cli.exclude_stages)?;
+    Ok(config)
+}
+
+/// Where the configuration is read from, for messages.
+fn config_source(cli: &Cli) -> String {
+    match std::env::var_os(config::env::CONFIG_VAR) {
+        Some(_) => config::env::CONFIG_VAR.to_string(),
+        None => cli.config.clone(),
+    }
 }
 
-#[tokio::main(flavor = "multi_thread", worker_threads = 32)]
+/// Validate the configuration and check stage parameters against what their
+/// processors accept, which only fails the check in strict mode.
+fn check_config(config: &config::Config) -> anyhow::Result<()> {
+    config::validate_config(config).context("Configuration error")?;
+
+    let parameter_errors = config::parameter_errors(config, processors::factory::processor_metadata);
+    for (_, e) in &parameter_errors {
+        if config.strict {
+            tracing::error!("Configuration error: {e}");
+        } else {
+            tracing::warn!("Configuration warning: {e}");
+        }
+    }
+    if config.strict && !parameter_errors.is_empty() {
+        anyhow::bail!("{} stage parameter error(s) in strict mode", parameter_errors.len());
+    }
+    Ok(())
+}
+
+fn exit_with(error: &anyhow::Error) -> ! {
+    tracing::error!("{:#}", error);
+    std::process::exit(1);
+}
+
+/// Load the processor plugins of the configuration, exiting on failure.
+fn load_plugins_or_exit(config: &config::Config) {
+    if let Some(dir) = &config.plugins_dir
+        && let Err(e) = processors::plugin::load_plugins(dir)
+    {
+        tracing::error!("{:#}", e);
+        std::process::exit(1);
+    }
+}
+
+/// Log an error with its causes, and what to do about it when it is a
+/// `LiminalError` with a hint.
+fn report_error(error: &anyhow::Error) {
+    tracing::error!("{:#}", error);
+    if let Some(hint) = LiminalError::find(error).and_then(LiminalError::hint) {
+        tracing::error!("hint: {}", hint);
+    }
+}
+
+#[tokio::main(flavor = "multi_thread")]
 async fn main() {
     // Parse command line arguments
-    let cli = Cli::parse();
+    let mut cli = Cli::parse();
 
     // Initialize logging with specified level
     logging::init_logging(&cli.log_level);
@@ -57,38 +276,245 @@ async fn main() {
         return;
     }
 
-    // Load configuration from specified file
-    let config = match config::load_config(&cli.config) {
-        Ok(cfg) => cfg,
-        Err(e) => {
-            tracing::error!("Failed to load config from '{}': {}", cli.config, e);
-            std::process::exit(1);
+    // Handle subcommands
+    match cli.command.take() {
+        Some(Command::Graph { format }) => {
+            let config = load_config_or_exit(&cli);
+            print!("{}", config::graph::render(&config, format));
+            return;
         }
-    };
+        Some(Command::Schema) => {
+            let schema = config::schema::config_schema(
+                &processors::factory::list_processors(),
+                processors::factory::processor_metadata,
+            );
+            println!("{}", serde_json::to_string_pretty(&schema).unwrap_or_default());
+            return;
+        }
+        Some(Command::Init { template, output, force }) => {
+            if !force && std::path::Path::new(&output).exists() {
+                tracing::error!("'{}' already exists; pass --force to overwrite it", output);
+                std::process::exit(1);
+            }
+            let written = config::starter::render(template)
+                .map_err(|e| e.to_string())
+                .and_then(|content| std::fs::write(&output, content).map_err(|e| e.to_string()));
+            match written {
+                Ok(()) => println!("Wrote starter configuration to '{}'; run it with `liminal -c {}`", output, output),
+                Err(e) => {
+                    tracing::error!("Failed to write '{}': {}", output, e);
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+        Some(Command::Tap { channel, sample, limit, output }) => {
+            let config = load_config_or_exit(&cli);
+            let Some(control) = &config.control else {
+                tracing::error!("Tapping needs the control API; add a [control] section to '{}'", cli.config);
+                std::process::exit(1);
+            };
+            let tapped = async {
+                match &output {
+                    Some(path) => {
+                        let mut file = tokio::fs::OpenOptions::new()
+                            .create(true)
+                            .append(true)
+                            .open(path)
+                            .await
+                            .with_context(|| format!("Failed to open '{}'", path))?;
+                        core::control::tap(&control.socket, &channel, sample, limit, &mut file).await
+                    }
+                    None => core::control::tap(&control.socket, &channel, sample, limit, &mut tokio::io::stdout()).await,
+                }
+            }
+            .await;
+            match tapped {
+                Ok(count) => tracing::info!("Tapped {} message(s) from channel '{}'", count, channel),
+                Err(e) => {
+                    report_error(&e);
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+        Some(Command::Bench { messages, transforms, channel, capacity, batch_size }) => {
+            let options = liminal::testing::bench::BenchOptions { messages, transforms, channel, capacity, batch_size };
+            match liminal::testing::bench::run(&options).await {
+                Ok(report) => print!("{}", report),
+                Err(e) => {
+                    report_error(&e);
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+        Some(Command::Test { tests }) => {
+            let config = load_config_or_exit(&cli);
+            load_plugins_or_exit(&config);
+            let test_file = match liminal::testing::runner::load_tests(&tests) {
+                Ok(test_file) => test_file,
+                Err(e) => {
+                    tracing::error!("{:#}", e);
+                    std::process::exit(1);
+                }
+            };
+
+            let mut failed = 0;
+            for test in &test_file.tests {
+                let outcome = liminal::testing::runner::run_test(&config, test).await;
+                if outcome.passed() {
+                    println!("test {} ... ok", outcome.name);
+                } else {
+                    failed += 1;
+                    println!("test {} ... FAILED", outcome.name);
+                    for failure in &outcome.failures {
+                        println!("    {}", failure);
+                    }
+                }
+            }
+            println!(
+                "\n{}: {} passed, {} failed",
+                tests,
+                test_file.tests.len() - failed,
+                failed
+            );
+            std::process::exit(if failed > 0 { 1 } else { 0 });
+        }
+        Some(Command::Validate) => {
+            let config = load_all_stages_or_exit(&cli);
+            load_plugins_or_exit(&config);
+            let source = match config::env::inline_config() {
+                Ok(Some(content)) => content,
+                _ => std::fs::read_to_string(&cli.config).unwrap_or_default(),
+            };
+            let problems = config::check::check_config(
+                &config,
+                &source,
+                processors::factory::processor_exists,
+                processors::factory::processor_metadata,
+            );
+
+            for problem in &problems {
+                println!("{}\n", problem);
+            }
+            let errors = problems
+                .iter()
+                .filter(|problem| problem.severity == config::check::Severity::Error)
+                .count();
+            println!(
+                "{}: {} error(s), {} warning(s)",
+                config_source(&cli),
+                errors,
+                problems.len() - errors
+            );
+            std::process::exit(if errors > 0 { 1 } else { 0 });
+        }
+        None => {}
+    }
+
+    // Load configuration from specified file
+    let mut config = load_config_or_exit(&cli);
+    load_plugins_or_exit(&config);
 
     // Validate configuration
-    if let Err(e) = config::validate_config(&config) {
-        tracing::error!("Configuration error: {e}");
-        std::process::exit(1);
+    if let Err(e) = check_config(&config) {
+        exit_with(&e);
     }
 
     // Configuration loaded and validated
     tracing::info!("Configuration loaded and validated successfully.");
 
-    // Initialize the pipeline manager
-    tracing::info!("Initialising pipeline manager...");
-    let _ = core::pipeline::PipelineManager::new(config)
-        .build_all()
-        .expect("pipeline building")
-        .connect_stages()
-        .await
-        .expect("pipeline connection")
-        .start_all()
-        .await
-        .expect("pipeline started")
-        .wait_for_all()
+    let backfill = if cli.backfill {
+        match core::backfill::BackfillRange::parse(cli.from.as_deref(), cli.until.as_deref()) {
+            Ok(range) => Some(range),
+            Err(e) => {
+                tracing::error!("{e}");
+                std::process::exit(1);
+            }
+        }
+    } else {
+        None
+    };
+
+    let pid_file = cli.pid_file.as_ref().map(|path| core::service::PidFile::create(path).unwrap_or_else(|e| exit_with(&e)));
+
+    // In service mode, a reload drains and stops the stages, and the loop
+    // starts them again from the reloaded configuration
+    loop {
+        // In active/standby operation, wait to become the active instance (a
+        // back-fill runs alongside the live instances instead)
+        let lease = match config.ha.as_ref().filter(|_| backfill.is_none()) {
+            Some(ha) => match core::lease::Lease::acquire(ha).await {
+                Ok(lease) => Some(lease),
+                Err(e) => {
+                    tracing::error!("Failed to acquire lease: {e}");
+                    drop(pid_file);
+                    std::process::exit(1);
+                }
+            },
+            None => None,
+        };
+
+        // Initialize the pipeline manager
+        tracing::info!("Initialising pipeline manager...");
+        let mut manager = core::pipeline::PipelineManager::new(config.clone());
+        if let Some(lease) = lease {
+            manager = manager.with_lease(lease);
+        }
+        if let Some(range) = backfill {
+            tracing::info!("Back-filling {:?}", range);
+            manager = manager.with_backfill(range);
+        }
+        if let Some(path) = &cli.summary {
+            manager = manager.with_summary(path);
+        }
+        if cli.service {
+            manager = manager.with_service();
+        }
+        let result = async {
+            let mut manager = manager.build_all().context("Failed to build the pipeline")?;
+            if !cli.skip_preflight {
+                tracing::info!("Running preflight checks...");
+                manager = manager.preflight(PREFLIGHT_TIMEOUT).await?;
+            }
+            let manager = manager
+                .connect_stages()
+                .await
+                .context("Failed to connect the pipeline")?
+                .start_all()
+                .await
+                .context("Failed to start the pipeline")?;
+            if cli.service {
+                core::service::notify_ready();
+            }
+            manager.wait_for_exit().await
+        }
         .await;
 
+        match result {
+            Ok(core::pipeline::Exit::Stopped) => break,
+            Ok(core::pipeline::Exit::Reload) => match load_config(&cli).and_then(|reloaded| {
+                check_config(&reloaded)?;
+                Ok(reloaded)
+            }) {
+                Ok(reloaded) => {
+                    tracing::info!("Configuration reloaded; restarting the pipeline.");
+                    config = reloaded;
+                }
+                Err(e) => {
+                    tracing::error!("Failed to reload the configuration; restarting with the previous one: {:#}", e);
+                }
+            },
+            Err(e) => {
+                report_error(&e);
+                drop(pid_file);
+                std::process::exit(1);
+            }
+        }
+    }
+    drop(pid_file);
+
     // Pipeline terminated
     tracing::info!("All input sources have been processed.");
 }
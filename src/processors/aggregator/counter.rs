@@ -0,0 +1,505 @@
+//! Counter Aggregator
+//!
+//! Keeps running count/sum/min/max of numeric fields per key since pipeline
+//! start or since the last scheduled reset (daily at a local time, or every
+//! fixed interval). Totals are emitted with every update, or periodically when
+//! `emit_interval_ms` is set, and a final snapshot is emitted when a period is
+//! reset. With `persist_path` the counters are written to disk periodically and
+//! reloaded at startup, so a restart does not zero a day's production count.
+//! Totals are held in the stage's state store, so `state.ttl_ms` and
+//! `state.max_keys` bound how many keys are tracked.
+
+use crate::config::{ParamSpec, ParamType, ProcessorConfig, ProcessorMetadata, StageConfig, extract_param};
+use crate::core::checkpoint::Snapshot;
+use crate::core::state::StateStore;
+use crate::core::timing_mixin::{TimingMixin, WithTimingMixin};
+use crate::core::{context::ProcessingContext, message::Message};
+use crate::processors::common::field_utils::FieldUtils;
+use crate::processors::processor::Processor;
+use crate::error::LiminalError;
+
+use anyhow::{Context, Result, anyhow};
+use chrono::{DateTime, Days, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+use tracing::{debug, error, warn};
+
+/// When accumulated counters return to zero.
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ResetSchedule {
+    Never,
+    /// Every day at local time `at` ("HH:MM") in `timezone`
+    Daily {
+        #[serde(default = "ResetSchedule::default_at")]
+        at: String,
+        #[serde(default = "ResetSchedule::default_timezone")]
+        timezone: String,
+    },
+    /// Every `interval_ms` from the start of the period
+    Interval { interval_ms: u64 },
+}
+
+impl ResetSchedule {
+    fn default_at() -> String {
+        "00:00".to_string()
+    }
+
+    fn default_timezone() -> String {
+        "UTC".to_string()
+    }
+}
+
+/// Accumulated totals for one field of one key.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct Totals {
+    pub count: u64,
+    pub sum: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl Totals {
+    fn new(value: f64) -> Self {
+        Self { count: 1, sum: value, min: value, max: value }
+    }
+
+    fn add(&mut self, value: f64) {
+        self.count += 1;
+        self.sum += value;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+}
+
+/// Totals of every field of one key.
+type KeyTotals = BTreeMap<String, Totals>;
+
+/// Counters for the current period, as persisted to disk.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+struct CounterSnapshot {
+    period_start_ms: i64,
+    counters: BTreeMap<String, KeyTotals>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CounterConfig {
+    pub fields: Vec<String>,
+    pub key_field: Option<String>,
+    pub reset: ResetSchedule,
+    /// Emit all keys periodically instead of on every update
+    pub emit_interval_ms: Option<u64>,
+    /// Emit final totals when a period is reset
+    pub emit_on_reset: bool,
+    pub persist_path: Option<String>,
+    pub persist_interval_ms: u64,
+    pub timing: Option<crate::config::TimingConfig>,
+}
+
+impl ProcessorConfig for CounterConfig {
+    fn from_stage_config(config: &StageConfig) -> Result<Self> {
+        let mut fields = extract_param(&config.parameters, "fields", Vec::<String>::new());
+        if let Some(field_in) = extract_param(&config.parameters, "field_in", None::<String>) {
+            fields.push(field_in);
+        }
+
+        let config = Self {
+            fields,
+            key_field: extract_param(&config.parameters, "key_field", None::<String>),
+            reset: extract_param(&config.parameters, "reset", ResetSchedule::Never),
+            emit_interval_ms: extract_param(&config.parameters, "emit_interval_ms", None::<u64>),
+            emit_on_reset: extract_param(&config.parameters, "emit_on_reset", true),
+            persist_path: extract_param(&config.parameters, "persist_path", None::<String>),
+            persist_interval_ms: extract_param(&config.parameters, "persist_interval_ms", 5_000_u64),
+            timing: config.timing.clone(),
+        };
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.fields.is_empty() {
+            return Err(anyhow!("counter processor requires 'fields' or 'field_in'"));
+        }
+        if self.emit_interval_ms == Some(0) || self.persist_interval_ms == 0 {
+            return Err(anyhow!("emit_interval_ms and persist_interval_ms must be greater than 0"));
+        }
+        match &self.reset {
+            ResetSchedule::Daily { at, timezone } => {
+                NaiveTime::parse_from_str(at, "%H:%M")
+                    .map_err(|_| anyhow!("reset.at must be HH:MM, got '{}'", at))?;
+                timezone
+                    .parse::<Tz>()
+                    .map_err(|_| anyhow!("Unknown timezone '{}'", timezone))?;
+            }
+            ResetSchedule::Interval { interval_ms: 0 } => {
+                return Err(LiminalError::invalid_parameter("reset.interval_ms", "must be greater than 0").into());
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+pub struct CounterProcessor {
+    name: String,
+    config: CounterConfig,
+    timing: TimingMixin,
+    period_start_ms: i64,
+    counters: StateStore<String, KeyTotals>,
+    next_reset: Option<DateTime<Utc>>,
+    dirty: bool,
+    last_emit: Instant,
+    last_persist: Instant,
+}
+
+impl CounterProcessor {
+    pub const METADATA: ProcessorMetadata = ProcessorMetadata {
+        name: "counter",
+        description: "Running per-key totals with reset schedules and optional persistence",
+        parameters: &[
+            ParamSpec::new("fields", ParamType::Array, "Numeric fields to total"),
+            ParamSpec::new("field_in", ParamType::String, "A single field to total, in addition to fields"),
+            ParamSpec::new("key_field", ParamType::String, "Field whose value keeps separate totals"),
+            ParamSpec::new("reset", ParamType::Object, "Reset schedule: never, daily (at, timezone) or interval (interval_ms)"),
+            ParamSpec::new("emit_interval_ms", ParamType::Integer, "Emit all keys periodically instead of on every update"),
+            ParamSpec::new("emit_on_reset", ParamType::Boolean, "Emit final totals when a period is reset"),
+            ParamSpec::new("persist_path", ParamType::String, "File that totals are persisted to"),
+            ParamSpec::new("persist_interval_ms", ParamType::Integer, "How often totals are persisted"),
+        ],
+        shared: &[],
+    };
+
+    pub fn new(name: &str, config: StageConfig) -> Result<Box<dyn Processor>> {
+        Ok(Box::new(Self::build(name, config)?))
+    }
+
+    fn build(name: &str, config: StageConfig) -> Result<Self> {
+        let processor_config = CounterConfig::from_stage_config(&config)?;
+        processor_config.validate()?;
+
+        let timing = TimingMixin::new(processor_config.timing.as_ref());
+
+        let persisted = match &processor_config.persist_path {
+            Some(path) => Self::load(path)?,
+            None => None,
+        };
+
+        let mut processor = Self {
+            name: name.to_string(),
+            config: processor_config,
+            timing,
+            period_start_ms: Utc::now().timestamp_millis(),
+            counters: StateStore::open(name, config.state.as_ref())?,
+            next_reset: None,
+            dirty: false,
+            last_emit: Instant::now(),
+            last_persist: Instant::now(),
+        };
+        if let Some(persisted) = persisted {
+            processor.load_snapshot(persisted);
+        }
+        processor.next_reset = processor.next_reset_after(processor.period_start());
+
+        Ok(processor)
+    }
+
+    fn load(path: &str) -> Result<Option<CounterSnapshot>> {
+        match std::fs::read_to_string(path) {
+            Ok(contents) => {
+                let snapshot = serde_json::from_str(&contents)
+                    .with_context(|| format!("Failed to parse counter state '{}'", path))?;
+                Ok(Some(snapshot))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(anyhow!("Failed to read counter state '{}': {}", path, e)),
+        }
+    }
+
+    fn to_snapshot(&self) -> CounterSnapshot {
+        CounterSnapshot {
+            period_start_ms: self.period_start_ms,
+            counters: self.counters.iter().map(|(key, totals)| (key.clone(), totals.clone())).collect(),
+        }
+    }
+
+    fn load_snapshot(&mut self, snapshot: CounterSnapshot) {
+        self.period_start_ms = snapshot.period_start_ms;
+        self.counters.retain(|_, _| false);
+        for (key, totals) in snapshot.counters {
+            self.counters.insert(key, totals);
+        }
+    }
+
+    fn persist(&mut self) -> Result<()> {
+        let Some(path) = &self.config.persist_path else {
+            return Ok(());
+        };
+
+        // Write then rename so a crash never leaves a truncated file
+        let temp_path = format!("{}.tmp", path);
+        std::fs::write(&temp_path, serde_json::to_vec(&self.to_snapshot())?)?;
+        std::fs::rename(&temp_path, path)?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    fn period_start(&self) -> DateTime<Utc> {
+        DateTime::from_timestamp_millis(self.period_start_ms).unwrap_or_else(Utc::now)
+    }
+
+    /// First reset boundary strictly after `after`.
+    fn next_reset_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match &self.config.reset {
+            ResetSchedule::Never => None,
+            ResetSchedule::Interval { interval_ms } => {
+                Some(after + chrono::Duration::milliseconds(*interval_ms as i64))
+            }
+            ResetSchedule::Daily { at, timezone } => {
+                let at = NaiveTime::parse_from_str(at, "%H:%M").ok()?;
+                let timezone = timezone.parse::<Tz>().ok()?;
+                let local_date = after.with_timezone(&timezone).date_naive();
+
+                [local_date, local_date.checked_add_days(Days::new(1))?]
+                    .into_iter()
+                    .filter_map(|date| timezone.from_local_datetime(&date.and_time(at)).earliest())
+                    .map(|local| local.with_timezone(&Utc))
+                    .find(|candidate| *candidate > after)
+            }
+        }
+    }
+
+    /// Start a new period if a reset boundary has passed, returning the final
+    /// totals of the period that ended.
+    fn maybe_reset(&mut self, now: DateTime<Utc>) -> Option<Vec<Value>> {
+        let boundary = self.next_reset.filter(|boundary| now >= *boundary)?;
+
+        let finals = self.snapshot_payloads(true);
+        self.period_start_ms = boundary.timestamp_millis();
+        self.counters.retain(|_, _| false);
+        self.next_reset = self.next_reset_after(now.max(boundary));
+        self.dirty = true;
+        debug!("Counters for '{}' reset at {}", self.name, boundary);
+        Some(finals)
+    }
+
+    fn record(&mut self, message: &Message) -> Option<String> {
+        let key = FieldUtils::extract_key(&message.payload, self.config.key_field.as_deref());
+        let mut updated = false;
+
+        for field in &self.config.fields {
+            let Some(value) = FieldUtils::extract_f64(&message.payload, field) else {
+                continue;
+            };
+            self.counters
+                .get_or_insert_with(key.clone(), KeyTotals::new)
+                .entry(field.clone())
+                .and_modify(|totals| totals.add(value))
+                .or_insert_with(|| Totals::new(value));
+            updated = true;
+        }
+
+        self.dirty |= updated;
+        updated.then_some(key)
+    }
+
+    fn key_payload(&self, key: &str, final_totals: bool) -> Option<Value> {
+        let fields = self.counters.get(&key.to_string())?;
+
+        let mut payload = json!({ "period_start_ms": self.period_start_ms });
+        if final_totals {
+            payload["final"] = Value::Bool(true);
+        }
+        if let Some(key_field) = &self.config.key_field {
+            let _ = FieldUtils::set_field_value(&mut payload, key_field, Value::String(key.to_string()));
+        }
+        for (field, totals) in fields {
+            let mut summary = serde_json::to_value(totals).ok()?;
+            summary["mean"] = json!(totals.sum / totals.count as f64);
+            if let Err(e) = FieldUtils::set_field_value(&mut payload, field, summary) {
+                warn!("Failed to write counter for '{}': {}", field, e);
+            }
+        }
+        Some(payload)
+    }
+
+    fn snapshot_payloads(&self, final_totals: bool) -> Vec<Value> {
+        let mut keys: Vec<&String> = self.counters.iter().map(|(key, _)| key).collect();
+        keys.sort();
+        keys.into_iter()
+            .filter_map(|key| self.key_payload(key, final_totals))
+            .collect()
+    }
+}
+
+#[async_trait::async_trait]
+impl Processor for CounterProcessor {
+    async fn init(&mut self) -> Result<()> {
+        tracing::info!(
+            "Counter processor '{}' initialised ({} keys restored, reset: {:?})",
+            self.name,
+            self.counters.len(),
+            self.config.reset
+        );
+        Ok(())
+    }
+
+    async fn process(&mut self, context: &mut ProcessingContext) -> Result<()> {
+        let mut payloads = Vec::new();
+
+        if let Some(finals) = self.maybe_reset(Utc::now())
+            && self.config.emit_on_reset
+        {
+            payloads.extend(finals);
+        }
+
+        if let Some((_, message)) = context.recv(tokio::time::Duration::from_millis(10)).await
+            && let Some(key) = self.record(&message)
+            && self.config.emit_interval_ms.is_none()
+        {
+            payloads.extend(self.key_payload(&key, false));
+        }
+
+        if let Some(interval_ms) = self.config.emit_interval_ms
+            && self.last_emit.elapsed() >= Duration::from_millis(interval_ms)
+        {
+            self.last_emit = Instant::now();
+            payloads.extend(self.snapshot_payloads(false));
+        }
+
+        if self.dirty && self.last_persist.elapsed() >= Duration::from_millis(self.config.persist_interval_ms) {
+            self.last_persist = Instant::now();
+            if let Err(e) = self.persist() {
+                error!("Failed to persist counters for '{}': {}", self.name, e);
+            }
+        }
+
+        if let Some(output_info) = &context.output {
+            for payload in payloads {
+                let message = self.timing.create_message_with_timing(
+                    &self.name,
+                    &output_info.name,
+                    payload,
+                    std::time::SystemTime::now(),
+                );
+
+                if let Err(e) = output_info.channel.publish(message).await {
+                    warn!("Failed to publish counters: {:?}", e);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn as_snapshot(&mut self) -> Option<&mut dyn Snapshot> {
+        Some(self)
+    }
+}
+
+impl Snapshot for CounterProcessor {
+    fn snapshot(&self) -> Result<Value> {
+        Ok(serde_json::to_value(self.to_snapshot())?)
+    }
+
+    fn restore(&mut self, state: Value) -> Result<()> {
+        self.load_snapshot(serde_json::from_value(state)?);
+        self.next_reset = self.next_reset_after(self.period_start());
+        self.dirty = true;
+        Ok(())
+    }
+}
+
+impl WithTimingMixin for CounterProcessor {
+    fn timing_mixin(&self) -> &TimingMixin {
+        &self.timing
+    }
+
+    fn timing_mixin_mut(&mut self) -> &mut TimingMixin {
+        &mut self.timing
+    }
+}
+
+impl Drop for CounterProcessor {
+    fn drop(&mut self) {
+        if self.dirty
+            && let Err(e) = self.persist()
+        {
+            error!("Failed to persist counters for '{}' on shutdown: {}", self.name, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn processor(parameters: Value) -> CounterProcessor {
+        let config = StageConfig {
+            r#type: "counter".to_string(),
+            parameters: Some(serde_json::from_value(parameters).unwrap()),
+            ..Default::default()
+        };
+        CounterProcessor::build("counter", config).unwrap()
+    }
+
+    #[test]
+    fn test_counter_accumulates_and_persists() {
+        let path = std::env::temp_dir().join(format!("liminal-counter-{}.json", std::process::id()));
+        let path_str = path.to_string_lossy().to_string();
+        let _ = std::fs::remove_file(&path);
+        let parameters = json!({"field_in": "pulses", "key_field": "line", "persist_path": path_str});
+
+        let mut counter = processor(parameters.clone());
+        for pulses in [3.0, 1.0, 5.0] {
+            counter.record(&Message::new("src", "topic", json!({"line": "L1", "pulses": pulses})));
+        }
+        let payload = counter.key_payload("L1", false).unwrap();
+        assert_eq!(payload["pulses"]["count"], json!(3));
+        assert_eq!(payload["pulses"]["sum"], json!(9.0));
+        assert_eq!(payload["pulses"]["min"], json!(1.0));
+        assert_eq!(payload["pulses"]["max"], json!(5.0));
+        drop(counter);
+
+        // Restart picks up where the previous run left off
+        let restored = processor(parameters);
+        assert_eq!(restored.counters.get(&"L1".to_string()).unwrap()["pulses"].sum, 9.0);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_counter_keys_bounded_by_state_store() {
+        let config = StageConfig {
+            r#type: "counter".to_string(),
+            parameters: Some(serde_json::from_value(json!({"field_in": "pulses", "key_field": "line"})).unwrap()),
+            state: Some(crate::config::StateConfig { max_keys: Some(2), ..Default::default() }),
+            ..Default::default()
+        };
+        let mut counter = CounterProcessor::build("counter-bounded", config).unwrap();
+        for line in ["L1", "L2", "L3"] {
+            counter.record(&Message::new("src", "topic", json!({"line": line, "pulses": 1.0})));
+        }
+
+        // The least recently updated line is evicted
+        let lines: Vec<Value> = counter.snapshot_payloads(false).into_iter().map(|payload| payload["line"].clone()).collect();
+        assert_eq!(lines, vec![json!("L2"), json!("L3")]);
+    }
+
+    #[test]
+    fn test_counter_daily_reset_boundary() {
+        let counter = processor(json!({
+            "field_in": "pulses",
+            "reset": {"type": "daily", "at": "06:00", "timezone": "Europe/Malta"},
+        }));
+
+        // 05:30 UTC in winter is 06:30 in Malta, so the next reset is tomorrow
+        let after = Utc.with_ymd_and_hms(2024, 1, 10, 5, 30, 0).unwrap();
+        assert_eq!(
+            counter.next_reset_after(after),
+            Some(Utc.with_ymd_and_hms(2024, 1, 11, 5, 0, 0).unwrap())
+        );
+    }
+}
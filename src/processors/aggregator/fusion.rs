@@ -1,37 +1,459 @@
-use crate::processors::Processor;
+//! Fusion Aggregator
+//!
+//! Combines readings of the same quantity from several inputs (e.g. redundant
+//! sensors) into one estimate per key. The stage keeps the latest reading from
+//! each input; once every expected input has a reading whose event time lies
+//! within `tolerance_ms` of the newest one, the numeric `fields` are fused with
+//! the configured strategy and emitted:
+//!
+//! - **latest**: value from the reading with the newest event time
+//! - **mean**: arithmetic mean across inputs
+//! - **weighted**: mean weighted by each reading's `confidence_field` (falling
+//!   back to the static per-input `weights`, then 1.0)
+//! - **kalman**: a scalar Kalman filter per key and field, treating each input
+//!   as a measurement with variance from `measurement_noise` (or
+//!   `1 / confidence` when a confidence field is present)
+//!
+//! If not every input reports within `timeout_ms` of the first pending reading,
+//! a partial fusion is emitted from the inputs that did, with the missing ones
+//! listed under `fusion_field`.
 
-use crate::config::StageConfig;
-use crate::core::context::ProcessingContext;
+use crate::config::{ParamSpec, ParamType, ProcessorConfig, ProcessorMetadata, StageConfig, extract_param};
+use crate::core::timing_mixin::{TimingMixin, WithTimingMixin};
+use crate::core::{context::ProcessingContext, message::Message};
+use crate::processors::common::field_utils::FieldUtils;
+use crate::processors::processor::Processor;
+use crate::error::LiminalError;
 
-use async_trait::async_trait;
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FusionStrategy {
+    Latest,
+    Mean,
+    Weighted,
+    Kalman,
+}
+
+#[derive(Debug, Clone)]
+pub struct FusionConfig {
+    /// Numeric fields to fuse
+    pub fields: Vec<String>,
+    pub key_field: Option<String>,
+    pub strategy: FusionStrategy,
+    /// Inputs expected in every fusion; defaults to all of the stage's inputs
+    pub inputs: Vec<String>,
+    /// Maximum event-time spread between readings fused together
+    pub tolerance_ms: u64,
+    /// Wall-clock wait before a partial fusion is emitted
+    pub timeout_ms: u64,
+    /// Per-reading confidence used by the weighted and kalman strategies
+    pub confidence_field: Option<String>,
+    /// Static per-input weights for the weighted strategy
+    pub weights: HashMap<String, f64>,
+    /// Per-input measurement variance for the kalman strategy
+    pub measurement_noise: HashMap<String, f64>,
+    /// Kalman process noise added between fusions
+    pub process_noise: f64,
+    /// Field receiving the fusion report (inputs used, missing, complete)
+    pub fusion_field: String,
+    pub timing: Option<crate::config::TimingConfig>,
+}
+
+impl ProcessorConfig for FusionConfig {
+    fn from_stage_config(config: &StageConfig) -> Result<Self> {
+        let mut fields = extract_param(&config.parameters, "fields", Vec::<String>::new());
+        if let Some(field_in) = extract_param(&config.parameters, "field_in", None::<String>) {
+            fields.push(field_in);
+        }
+
+        let config = Self {
+            fields,
+            key_field: extract_param(&config.parameters, "key_field", None::<String>),
+            strategy: extract_param(&config.parameters, "strategy", FusionStrategy::Mean),
+            inputs: extract_param(&config.parameters, "inputs", Vec::<String>::new()),
+            tolerance_ms: extract_param(&config.parameters, "tolerance_ms", 1_000_u64),
+            timeout_ms: extract_param(&config.parameters, "timeout_ms", 5_000_u64),
+            confidence_field: extract_param(&config.parameters, "confidence_field", None::<String>),
+            weights: extract_param(&config.parameters, "weights", HashMap::new()),
+            measurement_noise: extract_param(&config.parameters, "measurement_noise", HashMap::new()),
+            process_noise: extract_param(&config.parameters, "process_noise", 0.01),
+            fusion_field: extract_param(&config.parameters, "fusion_field", "fusion".to_string()),
+            timing: config.timing.clone(),
+        };
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.fields.is_empty() {
+            return Err(anyhow!("fusion processor requires 'fields' or 'field_in'"));
+        }
+        if self.fusion_field.is_empty() {
+            return Err(LiminalError::invalid_parameter("fusion_field", "cannot be empty").into());
+        }
+        if self.timeout_ms == 0 {
+            return Err(LiminalError::invalid_parameter("timeout_ms", "must be greater than 0").into());
+        }
+        if self.weights.values().any(|w| !w.is_finite() || *w < 0.0) {
+            return Err(LiminalError::invalid_parameter("weights", "must be finite and non-negative").into());
+        }
+        if self.measurement_noise.values().any(|r| !r.is_finite() || *r <= 0.0) {
+            return Err(anyhow!("measurement_noise values must be positive"));
+        }
+        if !self.process_noise.is_finite() || self.process_noise < 0.0 {
+            return Err(LiminalError::invalid_parameter("process_noise", "must be non-negative").into());
+        }
+        Ok(())
+    }
+}
+
+/// Scalar Kalman filter state for one key and field.
+#[derive(Debug, Clone, Copy)]
+struct KalmanState {
+    estimate: f64,
+    variance: f64,
+}
+
+/// Readings waiting to be fused for one key.
+struct PendingFusion {
+    readings: BTreeMap<String, Message>,
+    since: Instant,
+}
 
 pub struct FusionStage {
     name: String,
+    config: FusionConfig,
+    timing: TimingMixin,
+    pending: HashMap<String, PendingFusion>,
+    kalman: HashMap<(String, String), KalmanState>,
 }
 
 impl FusionStage {
-    pub fn new(name: &str, _: StageConfig) -> anyhow::Result<Box<dyn Processor>> {
+    pub const METADATA: ProcessorMetadata = ProcessorMetadata {
+        name: "fusion",
+        description: "Fuses aligned readings from multiple inputs",
+        parameters: &[
+            ParamSpec::new("fields", ParamType::Array, "Numeric fields to fuse"),
+            ParamSpec::new("field_in", ParamType::String, "A single field to fuse, in addition to fields"),
+            ParamSpec::new("key_field", ParamType::String, "Field whose value keeps separate fusions"),
+            ParamSpec::new("strategy", ParamType::Choice(&["latest", "mean", "weighted", "kalman"]), "How readings are combined"),
+            ParamSpec::new("inputs", ParamType::Array, "Inputs expected in every fusion; defaults to all of the stage's inputs"),
+            ParamSpec::new("tolerance_ms", ParamType::Integer, "Maximum event-time spread between readings fused together"),
+            ParamSpec::new("timeout_ms", ParamType::Integer, "Wall-clock wait before a partial fusion is emitted"),
+            ParamSpec::new("confidence_field", ParamType::String, "Per-reading confidence used by the weighted and kalman strategies"),
+            ParamSpec::new("weights", ParamType::Object, "Static per-input weights for the weighted strategy"),
+            ParamSpec::new("measurement_noise", ParamType::Object, "Per-input measurement variance for the kalman strategy"),
+            ParamSpec::new("process_noise", ParamType::Number, "Kalman process noise added between fusions"),
+            ParamSpec::new("fusion_field", ParamType::String, "Field receiving the fusion report"),
+        ],
+        shared: &[],
+    };
+
+    pub fn new(name: &str, config: StageConfig) -> anyhow::Result<Box<dyn Processor>> {
+        let processor_config = FusionConfig::from_stage_config(&config)?;
+        processor_config.validate()?;
+
+        let timing = TimingMixin::new(processor_config.timing.as_ref());
+
         Ok(Box::new(Self {
             name: name.to_string(),
+            config: processor_config,
+            timing,
+            pending: HashMap::new(),
+            kalman: HashMap::new(),
         }))
     }
+
+    fn confidence(&self, input: &str, message: &Message) -> Option<f64> {
+        self.config
+            .confidence_field
+            .as_ref()
+            .and_then(|field| FieldUtils::extract_f64(&message.payload, field))
+            .filter(|c| c.is_finite() && *c > 0.0)
+            .or_else(|| self.config.weights.get(input).copied())
+    }
+
+    /// Record a reading and return the key if that key is now ready to fuse.
+    fn record(&mut self, input: &str, message: Message, expected: &[String]) -> Option<String> {
+        let key = FieldUtils::extract_key(&message.payload, self.config.key_field.as_deref());
+        let pending = self.pending.entry(key.clone()).or_insert_with(|| PendingFusion {
+            readings: BTreeMap::new(),
+            since: Instant::now(),
+        });
+        pending.readings.insert(input.to_string(), message);
+
+        let tolerance = Duration::from_millis(self.config.tolerance_ms);
+        let complete = expected.iter().all(|input| pending.readings.contains_key(input));
+        (complete && Self::aligned(pending, tolerance).len() == pending.readings.len()).then_some(key)
+    }
+
+    /// Readings within the alignment tolerance of the newest reading.
+    fn aligned(pending: &PendingFusion, tolerance: Duration) -> Vec<(&String, &Message)> {
+        let Some(newest) = pending.readings.values().map(|m| m.timing.event_time).max() else {
+            return Vec::new();
+        };
+
+        pending
+            .readings
+            .iter()
+            .filter(|(_, message)| {
+                newest
+                    .duration_since(message.timing.event_time)
+                    .is_ok_and(|lag| lag <= tolerance)
+            })
+            .collect()
+    }
+
+    fn fuse_field(&mut self, key: &str, field: &str, readings: &[(&String, &Message)]) -> Option<f64> {
+        let values: Vec<(&String, &Message, f64)> = readings
+            .iter()
+            .filter_map(|(input, message)| {
+                Some((*input, *message, FieldUtils::extract_f64(&message.payload, field)?))
+            })
+            .collect();
+        if values.is_empty() {
+            return None;
+        }
+
+        match self.config.strategy {
+            FusionStrategy::Latest => values
+                .iter()
+                .max_by_key(|(_, message, _)| message.timing.event_time)
+                .map(|(_, _, value)| *value),
+            FusionStrategy::Mean => Some(values.iter().map(|(_, _, v)| v).sum::<f64>() / values.len() as f64),
+            FusionStrategy::Weighted => {
+                let weighted: Vec<(f64, f64)> = values
+                    .iter()
+                    .map(|(input, message, value)| (self.confidence(input, message).unwrap_or(1.0), *value))
+                    .collect();
+                let total: f64 = weighted.iter().map(|(w, _)| w).sum();
+                (total > 0.0).then(|| weighted.iter().map(|(w, v)| w * v).sum::<f64>() / total)
+            }
+            FusionStrategy::Kalman => {
+                let measurements: Vec<(f64, f64)> = values
+                    .iter()
+                    .map(|(input, message, value)| {
+                        let variance = match self.config.measurement_noise.get(*input) {
+                            Some(noise) => *noise,
+                            None => self
+                                .confidence(input, message)
+                                .map(|confidence| 1.0 / confidence)
+                                .unwrap_or(1.0),
+                        };
+                        (*value, variance)
+                    })
+                    .collect();
+
+                let process_noise = self.config.process_noise;
+                let state = self
+                    .kalman
+                    .entry((key.to_string(), field.to_string()))
+                    .or_insert(KalmanState {
+                        estimate: measurements[0].0,
+                        variance: f64::INFINITY,
+                    });
+
+                state.variance += process_noise;
+                for (measurement, noise) in measurements {
+                    if state.variance.is_infinite() {
+                        *state = KalmanState { estimate: measurement, variance: noise };
+                        continue;
+                    }
+                    let gain = state.variance / (state.variance + noise);
+                    state.estimate += gain * (measurement - state.estimate);
+                    state.variance *= 1.0 - gain;
+                }
+                Some(state.estimate)
+            }
+        }
+    }
+
+    /// Fuse and clear the pending readings for `key`.
+    fn fuse(&mut self, key: &str, expected: &[String]) -> Option<Message> {
+        let pending = self.pending.remove(key)?;
+        let aligned = Self::aligned(&pending, Duration::from_millis(self.config.tolerance_ms));
+        let (_, newest) = aligned.iter().max_by_key(|(_, message)| message.timing.event_time)?;
+
+        let mut message = (*newest).clone();
+        let mut payload = json!({});
+        if let Some(key_field) = &self.config.key_field
+            && let Some(value) = FieldUtils::extract_field_value(&newest.payload, key_field)
+        {
+            let _ = FieldUtils::set_field_value(&mut payload, key_field, value.clone());
+        }
+
+        let fields = self.config.fields.clone();
+        for field in &fields {
+            match self.fuse_field(key, field, &aligned) {
+                Some(value) => {
+                    if let Err(e) = FieldUtils::set_field_value(&mut payload, field, json!(value)) {
+                        warn!("Failed to write fused field '{}': {}", field, e);
+                    }
+                }
+                None => debug!("No readings of '{}' to fuse for key '{}'", field, key),
+            }
+        }
+
+        let used: Vec<&String> = aligned.iter().map(|(input, _)| *input).collect();
+        let missing: Vec<&String> = expected.iter().filter(|input| !used.contains(input)).collect();
+        let report = json!({
+            "strategy": self.config.strategy,
+            "inputs": used,
+            "missing": missing,
+            "complete": missing.is_empty(),
+        });
+        let _ = FieldUtils::set_field_value(&mut payload, &self.config.fusion_field, report);
+
+        message.payload = payload;
+        message.source = self.name.clone();
+        Some(message)
+    }
+
+    fn expected_inputs(&self, context: &ProcessingContext) -> Vec<String> {
+        if !self.config.inputs.is_empty() {
+            return self.config.inputs.clone();
+        }
+        let mut inputs: Vec<String> = context.inputs.keys().cloned().collect();
+        inputs.sort();
+        inputs
+    }
+
+    async fn publish(&mut self, context: &ProcessingContext, mut message: Message) {
+        if let Some(output_info) = &context.output {
+            message.topic = output_info.name.clone();
+            let message = self.timing.update_message_watermark(message);
+
+            if let Err(e) = output_info.channel.publish(message).await {
+                warn!("Failed to publish fused message: {:?}", e);
+            }
+        }
+    }
 }
 
-#[async_trait]
+#[async_trait::async_trait]
 impl Processor for FusionStage {
     async fn init(&mut self) -> anyhow::Result<()> {
-        tracing::info!("Fusion processor '{} initialised", self.name);
+        tracing::info!(
+            "Fusion processor '{}' initialised ({:?}, tolerance: {} ms, timeout: {} ms)",
+            self.name,
+            self.config.strategy,
+            self.config.tolerance_ms,
+            self.config.timeout_ms
+        );
         Ok(())
     }
 
     async fn process(&mut self, context: &mut ProcessingContext) -> anyhow::Result<()> {
-        for (_name, input) in context.inputs.iter_mut() {
-            if let Some(message) = input.recv().await {
-                if let Some(output_info) = &context.output {
-                    let _ = output_info.channel.publish(message).await;
-                }
+        let expected = self.expected_inputs(context);
+        let mut fused = Vec::new();
+
+        if let Some((channel_name, message)) = context.recv(tokio::time::Duration::from_millis(10)).await
+            && let Some(key) = self.record(&channel_name, message, &expected)
+            && let Some(output) = self.fuse(&key, &expected)
+        {
+            fused.push(output);
+        }
+
+        // Emit partial fusions for keys still waiting on inputs
+        let timeout = Duration::from_millis(self.config.timeout_ms);
+        let expired: Vec<String> = self
+            .pending
+            .iter()
+            .filter(|(_, pending)| pending.since.elapsed() >= timeout)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in expired {
+            if let Some(output) = self.fuse(&key, &expected) {
+                debug!("Emitting partial fusion for key '{}'", key);
+                fused.push(output);
             }
         }
+
+        for message in fused {
+            self.publish(context, message).await;
+        }
         Ok(())
     }
 }
+
+impl WithTimingMixin for FusionStage {
+    fn timing_mixin(&self) -> &TimingMixin {
+        &self.timing
+    }
+
+    fn timing_mixin_mut(&mut self) -> &mut TimingMixin {
+        &mut self.timing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+    use std::time::UNIX_EPOCH;
+
+    fn stage(parameters: Value) -> FusionStage {
+        let config = StageConfig {
+            r#type: "fusion".to_string(),
+            parameters: Some(serde_json::from_value(parameters).unwrap()),
+            ..Default::default()
+        };
+        FusionStage {
+            name: "fusion".to_string(),
+            config: FusionConfig::from_stage_config(&config).unwrap(),
+            timing: TimingMixin::new(None),
+            pending: HashMap::new(),
+            kalman: HashMap::new(),
+        }
+    }
+
+    fn reading(ms: u64, payload: Value) -> Message {
+        Message::new_with_event_time("src", "topic", payload, UNIX_EPOCH + Duration::from_millis(ms))
+    }
+
+    #[test]
+    fn test_fusion_strategies() {
+        let expected = vec!["a".to_string(), "b".to_string()];
+
+        let mut mean = stage(json!({"field_in": "temp", "strategy": "mean"}));
+        assert!(mean.record("a", reading(1000, json!({"temp": 20.0})), &expected).is_none());
+        let key = mean.record("b", reading(1100, json!({"temp": 22.0})), &expected).unwrap();
+        let output = mean.fuse(&key, &expected).unwrap();
+        assert_eq!(output.payload["temp"], json!(21.0));
+        assert_eq!(output.payload["fusion"]["complete"], json!(true));
+
+        let mut weighted = stage(json!({"field_in": "temp", "strategy": "weighted", "confidence_field": "conf"}));
+        weighted.record("a", reading(1000, json!({"temp": 20.0, "conf": 3.0})), &expected);
+        let key = weighted.record("b", reading(1000, json!({"temp": 24.0, "conf": 1.0})), &expected).unwrap();
+        assert_eq!(weighted.fuse(&key, &expected).unwrap().payload["temp"], json!(21.0));
+
+        // Equal-variance measurements converge on their mean
+        let mut kalman = stage(json!({"field_in": "temp", "strategy": "kalman", "process_noise": 0.0}));
+        kalman.record("a", reading(1000, json!({"temp": 20.0})), &expected);
+        let key = kalman.record("b", reading(1000, json!({"temp": 22.0})), &expected).unwrap();
+        assert_eq!(kalman.fuse(&key, &expected).unwrap().payload["temp"], json!(21.0));
+    }
+
+    #[test]
+    fn test_fusion_partial_outside_tolerance() {
+        let expected = vec!["a".to_string(), "b".to_string()];
+        let mut fusion = stage(json!({"field_in": "temp", "tolerance_ms": 100}));
+
+        fusion.record("a", reading(1000, json!({"temp": 20.0})), &expected);
+        // Both inputs present but too far apart in event time
+        assert!(fusion.record("b", reading(2000, json!({"temp": 30.0})), &expected).is_none());
+
+        let output = fusion.fuse("", &expected).unwrap();
+        assert_eq!(output.payload["temp"], json!(30.0));
+        assert_eq!(output.payload["fusion"]["missing"], json!(["a"]));
+        assert_eq!(output.payload["fusion"]["complete"], json!(false));
+    }
+}
@@ -0,0 +1,356 @@
+//! Histogram Aggregator
+//!
+//! Tracks the distribution of one or more numeric fields per key over a sliding
+//! event-time window and periodically emits a summary per key: count, min, max,
+//! mean, the configured quantiles (p50/p95/p99 by default) and, when `buckets`
+//! are given, Prometheus-style cumulative bucket counts (`le` upper bounds plus
+//! `+Inf`). Quantiles are exact over the retained samples; `max_samples` caps
+//! how many samples each field/key window holds.
+//!
+//! Without a `key_field`, batches are recorded a column at a time.
+
+use crate::config::{ParamSpec, ParamType, ProcessorConfig, ProcessorMetadata, StageConfig, extract_param};
+use crate::core::checkpoint::Snapshot;
+use crate::core::timing_mixin::{TimingMixin, WithTimingMixin};
+use crate::core::{context::ProcessingContext, message::Message};
+use crate::processors::common::field_utils::FieldUtils;
+use crate::processors::common::stats;
+use crate::processors::common::window::SlidingWindow;
+use crate::processors::processor::Processor;
+use crate::error::LiminalError;
+
+use anyhow::{Result, anyhow};
+use serde_json::{Map, Value, json};
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant, SystemTime};
+
+#[derive(Debug, Clone)]
+pub struct HistogramConfig {
+    pub fields: Vec<String>,
+    pub key_field: Option<String>,
+    pub window_ms: u64,
+    pub emit_interval_ms: u64,
+    /// Quantiles to report, each in 0.0..=1.0
+    pub quantiles: Vec<f64>,
+    /// Ascending bucket upper bounds; empty disables bucket counts
+    pub buckets: Vec<f64>,
+    pub max_samples: usize,
+    pub timing: Option<crate::config::TimingConfig>,
+}
+
+impl ProcessorConfig for HistogramConfig {
+    fn from_stage_config(config: &StageConfig) -> Result<Self> {
+        let mut fields = extract_param(&config.parameters, "fields", Vec::<String>::new());
+        if let Some(field_in) = extract_param(&config.parameters, "field_in", None::<String>) {
+            fields.push(field_in);
+        }
+
+        let config = Self {
+            fields,
+            key_field: extract_param(&config.parameters, "key_field", None::<String>),
+            window_ms: extract_param(&config.parameters, "window_ms", 60_000_u64),
+            emit_interval_ms: extract_param(&config.parameters, "emit_interval_ms", 10_000_u64),
+            quantiles: extract_param(&config.parameters, "quantiles", vec![0.5, 0.95, 0.99]),
+            buckets: extract_param(&config.parameters, "buckets", Vec::<f64>::new()),
+            max_samples: extract_param(&config.parameters, "max_samples", 10_000_usize),
+            timing: config.timing.clone(),
+        };
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.fields.is_empty() {
+            return Err(anyhow!("histogram processor requires 'fields' or 'field_in'"));
+        }
+        if self.window_ms == 0 || self.emit_interval_ms == 0 {
+            return Err(anyhow!("window_ms and emit_interval_ms must be greater than 0"));
+        }
+        if self.max_samples == 0 {
+            return Err(LiminalError::invalid_parameter("max_samples", "must be greater than 0").into());
+        }
+        if let Some(q) = self.quantiles.iter().find(|q| !(0.0..=1.0).contains(*q)) {
+            return Err(anyhow!("quantile {} must be between 0.0 and 1.0", q));
+        }
+        if self.buckets.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return Err(LiminalError::invalid_parameter("buckets", "must be strictly ascending").into());
+        }
+        Ok(())
+    }
+}
+
+/// Sample windows of one key, by field
+type FieldWindows = HashMap<String, SlidingWindow<f64>>;
+
+pub struct HistogramProcessor {
+    name: String,
+    config: HistogramConfig,
+    timing: TimingMixin,
+    /// Sample windows by key, then by field
+    windows: BTreeMap<String, FieldWindows>,
+    stream_time: Option<SystemTime>,
+    last_emit: Instant,
+}
+
+impl HistogramProcessor {
+    pub const METADATA: ProcessorMetadata = ProcessorMetadata {
+        name: "histogram",
+        description: "Windowed per-key distribution summaries with quantiles and buckets",
+        parameters: &[
+            ParamSpec::new("fields", ParamType::Array, "Numeric fields to summarise"),
+            ParamSpec::new("field_in", ParamType::String, "A single field to summarise, in addition to fields"),
+            ParamSpec::new("key_field", ParamType::String, "Field whose value keeps separate summaries"),
+            ParamSpec::new("window_ms", ParamType::Integer, "Length of the sliding window"),
+            ParamSpec::new("emit_interval_ms", ParamType::Integer, "How often summaries are emitted"),
+            ParamSpec::new("quantiles", ParamType::Array, "Quantiles to report, between 0 and 1"),
+            ParamSpec::new("buckets", ParamType::Array, "Upper bounds of histogram buckets"),
+            ParamSpec::new("max_samples", ParamType::Integer, "Samples kept per key and field"),
+        ],
+        shared: &[],
+    };
+
+    pub fn new(name: &str, config: StageConfig) -> Result<Box<dyn Processor>> {
+        let processor_config = HistogramConfig::from_stage_config(&config)?;
+        processor_config.validate()?;
+
+        let timing = TimingMixin::new(processor_config.timing.as_ref());
+
+        Ok(Box::new(Self {
+            name: name.to_string(),
+            config: processor_config,
+            timing,
+            windows: BTreeMap::new(),
+            stream_time: None,
+            last_emit: Instant::now(),
+        }))
+    }
+
+    fn record(&mut self, message: &Message) {
+        if let Some(batch) = &message.batch {
+            // Batches are only accepted without a key field, so all share the empty key
+            let span = Duration::from_millis(self.config.window_ms);
+            let fields = self.windows.entry(String::new()).or_default();
+            for field in &self.config.fields {
+                let Some(values) = batch.column(field) else {
+                    continue;
+                };
+                let window = fields
+                    .entry(field.clone())
+                    .or_insert_with(|| SlidingWindow::new(span));
+                for (event_time, value) in batch.event_times().zip(values).filter(|(_, value)| value.is_finite()) {
+                    window.push(event_time, *value);
+                }
+                while window.len() > self.config.max_samples {
+                    window.pop_front();
+                }
+            }
+            if let Some(latest) = batch.event_times().max() {
+                self.stream_time = Some(self.stream_time.map_or(latest, |t| t.max(latest)));
+            }
+            return;
+        }
+
+        let key = FieldUtils::extract_key(&message.payload, self.config.key_field.as_deref());
+        let event_time = message.timing.event_time;
+        let span = Duration::from_millis(self.config.window_ms);
+
+        let fields = self.windows.entry(key).or_default();
+        for field in &self.config.fields {
+            let Some(value) = FieldUtils::extract_f64(&message.payload, field) else {
+                continue;
+            };
+            let window = fields
+                .entry(field.clone())
+                .or_insert_with(|| SlidingWindow::new(span));
+            window.push(event_time, value);
+            while window.len() > self.config.max_samples {
+                window.pop_front();
+            }
+        }
+        self.stream_time = Some(self.stream_time.map_or(event_time, |t| t.max(event_time)));
+    }
+
+    fn summarise(&self, samples: &[f64]) -> Value {
+        let count = samples.len();
+        let min = samples.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = samples.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        let mean = samples.iter().sum::<f64>() / count as f64;
+
+        let mut summary = json!({ "count": count, "min": min, "max": max, "mean": mean });
+
+        let quantiles: Map<String, Value> = self
+            .config
+            .quantiles
+            .iter()
+            .filter_map(|q| Some((format!("p{}", (q * 1000.0).round() / 10.0), json!(stats::quantile(samples, *q)?))))
+            .collect();
+        summary["quantiles"] = Value::Object(quantiles);
+
+        if !self.config.buckets.is_empty() {
+            let mut buckets: Vec<Value> = self
+                .config
+                .buckets
+                .iter()
+                .map(|bound| json!({ "le": bound, "count": samples.iter().filter(|v| *v <= bound).count() }))
+                .collect();
+            buckets.push(json!({ "le": "+Inf", "count": count }));
+            summary["buckets"] = Value::Array(buckets);
+        }
+        summary
+    }
+
+    /// Evict expired samples and build one summary payload per key.
+    fn summaries(&mut self) -> Vec<Value> {
+        if let Some(now) = self.stream_time {
+            for fields in self.windows.values_mut() {
+                for window in fields.values_mut() {
+                    window.evict(now);
+                }
+                fields.retain(|_, window| !window.is_empty());
+            }
+        }
+        self.windows.retain(|_, fields| !fields.is_empty());
+
+        let mut payloads = Vec::new();
+        for (key, fields) in &self.windows {
+            let mut payload = json!({ "key": key, "window_ms": self.config.window_ms });
+            for (field, window) in fields {
+                let samples: Vec<f64> = window.values().copied().collect();
+                if let Err(e) = FieldUtils::set_field_value(&mut payload, field, self.summarise(&samples)) {
+                    tracing::warn!("Failed to write histogram for '{}': {}", field, e);
+                }
+            }
+            payloads.push(payload);
+        }
+        payloads
+    }
+}
+
+#[async_trait::async_trait]
+impl Processor for HistogramProcessor {
+    async fn init(&mut self) -> Result<()> {
+        tracing::info!(
+            "Histogram processor '{}' initialised (fields: {:?}, window: {} ms)",
+            self.name,
+            self.config.fields,
+            self.config.window_ms
+        );
+        Ok(())
+    }
+
+    fn accepts_batches(&self) -> bool {
+        self.config.key_field.is_none()
+    }
+
+    async fn process(&mut self, context: &mut ProcessingContext) -> Result<()> {
+        if let Some((_, message)) = context.recv(tokio::time::Duration::from_millis(10)).await {
+            self.record(&message);
+        }
+
+        if self.last_emit.elapsed() < Duration::from_millis(self.config.emit_interval_ms) {
+            return Ok(());
+        }
+        self.last_emit = Instant::now();
+
+        let payloads = self.summaries();
+        if let Some(output_info) = &context.output {
+            let event_time = self.stream_time.unwrap_or_else(SystemTime::now);
+            for payload in payloads {
+                let message =
+                    self.timing
+                        .create_message_with_timing(&self.name, &output_info.name, payload, event_time);
+
+                if let Err(e) = output_info.channel.publish(message).await {
+                    tracing::warn!("Failed to publish histogram: {:?}", e);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn as_snapshot(&mut self) -> Option<&mut dyn Snapshot> {
+        Some(self)
+    }
+}
+
+impl Snapshot for HistogramProcessor {
+    fn snapshot(&self) -> Result<Value> {
+        Ok(serde_json::to_value((&self.windows, self.stream_time))?)
+    }
+
+    fn restore(&mut self, state: Value) -> Result<()> {
+        let (mut windows, stream_time): (BTreeMap<String, FieldWindows>, Option<SystemTime>) =
+            serde_json::from_value(state)?;
+        let span = Duration::from_millis(self.config.window_ms);
+        for window in windows.values_mut().flat_map(|fields| fields.values_mut()) {
+            window.set_span(span);
+        }
+        self.windows = windows;
+        self.stream_time = stream_time;
+        Ok(())
+    }
+}
+
+impl WithTimingMixin for HistogramProcessor {
+    fn timing_mixin(&self) -> &TimingMixin {
+        &self.timing
+    }
+
+    fn timing_mixin_mut(&mut self) -> &mut TimingMixin {
+        &mut self.timing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::batch::RecordBatch;
+
+    #[test]
+    fn test_histogram_summary() {
+        let config = StageConfig {
+            r#type: "histogram".to_string(),
+            parameters: Some(HashMap::from([
+                ("field_in".to_string(), json!("latency")),
+                ("quantiles".to_string(), json!([0.25, 0.5])),
+                ("buckets".to_string(), json!([10.0, 50.0])),
+            ])),
+            ..Default::default()
+        };
+        let mut processor = HistogramProcessor {
+            name: "histogram".to_string(),
+            config: HistogramConfig::from_stage_config(&config).unwrap(),
+            timing: TimingMixin::new(None),
+            windows: BTreeMap::new(),
+            stream_time: None,
+            last_emit: Instant::now(),
+        };
+
+        for latency in 1..=50 {
+            processor.record(&Message::new("src", "topic", json!({ "latency": latency as f64 })));
+        }
+        // The rest arrive as one batch
+        let mut batch = RecordBatch::new(&["latency"]);
+        for latency in 51..=100 {
+            batch.push_row(SystemTime::now(), &[latency as f64]).unwrap();
+        }
+        processor.record(&Message::from_batch("src", "topic", batch));
+
+        let payloads = processor.summaries();
+        assert_eq!(payloads.len(), 1);
+        let summary = &payloads[0]["latency"];
+        assert_eq!(summary["count"], json!(100));
+        assert_eq!(summary["min"], json!(1.0));
+        assert_eq!(summary["quantiles"]["p50"], json!(50.5));
+        assert_eq!(summary["quantiles"]["p25"], json!(25.75));
+        assert_eq!(
+            summary["buckets"],
+            json!([
+                {"le": 10.0, "count": 10},
+                {"le": 50.0, "count": 50},
+                {"le": "+Inf", "count": 100},
+            ])
+        );
+    }
+}
@@ -1,3 +1,9 @@
+pub mod counter;
 pub mod fusion;
+pub mod histogram;
+pub mod topn;
 
-pub use fusion::FusionStage;
\ No newline at end of file
+pub use counter::CounterProcessor;
+pub use fusion::FusionStage;
+pub use histogram::HistogramProcessor;
+pub use topn::TopNProcessor;
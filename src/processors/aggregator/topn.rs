@@ -0,0 +1,330 @@
+//! Top-N Aggregator
+//!
+//! Maintains a per-key metric over a sliding event-time window and periodically
+//! emits the `n` highest (or lowest) ranked keys, e.g. the ten hottest sensors
+//! over the last five minutes. Each key's samples are reduced with the chosen
+//! `aggregation` before ranking. Keys with no samples left in the window drop out
+//! of the ranking.
+
+use crate::config::{ParamSpec, ParamType, ProcessorConfig, ProcessorMetadata, StageConfig, extract_param, require_param};
+use crate::core::checkpoint::Snapshot;
+use crate::core::timing_mixin::{TimingMixin, WithTimingMixin};
+use crate::core::{context::ProcessingContext, message::Message};
+use crate::processors::common::field_utils::FieldUtils;
+use crate::processors::common::window::SlidingWindow;
+use crate::processors::processor::Processor;
+use crate::error::LiminalError;
+
+use anyhow::{Result, anyhow};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::time::{Duration, Instant, SystemTime};
+use tracing::debug;
+
+/// How a key's samples in the window are reduced to a single score.
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TopNAggregation {
+    Latest,
+    Max,
+    Min,
+    Mean,
+    Sum,
+    Count,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RankOrder {
+    /// Highest score first
+    Desc,
+    /// Lowest score first
+    Asc,
+}
+
+#[derive(Debug, Clone)]
+pub struct TopNConfig {
+    pub key_field: String,
+    pub metric_field: String,
+    pub n: usize,
+    pub window_ms: u64,
+    pub emit_interval_ms: u64,
+    pub aggregation: TopNAggregation,
+    pub order: RankOrder,
+    pub output_field: String,
+    pub timing: Option<crate::config::TimingConfig>,
+}
+
+impl ProcessorConfig for TopNConfig {
+    fn from_stage_config(config: &StageConfig) -> Result<Self> {
+        let key_field = require_param::<String>(&config.parameters, "key_field")?;
+        let metric_field = require_param::<String>(&config.parameters, "metric_field")?;
+
+        let config = Self {
+            key_field,
+            metric_field,
+            n: extract_param(&config.parameters, "n", 10_usize),
+            window_ms: extract_param(&config.parameters, "window_ms", 300_000_u64),
+            emit_interval_ms: extract_param(&config.parameters, "emit_interval_ms", 5_000_u64),
+            aggregation: extract_param(&config.parameters, "aggregation", TopNAggregation::Max),
+            order: extract_param(&config.parameters, "order", RankOrder::Desc),
+            output_field: extract_param(&config.parameters, "output_field", "ranking".to_string()),
+            timing: config.timing.clone(),
+        };
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.key_field.is_empty() || self.metric_field.is_empty() || self.output_field.is_empty() {
+            return Err(anyhow!("key_field, metric_field and output_field cannot be empty"));
+        }
+        if self.n == 0 {
+            return Err(LiminalError::invalid_parameter("n", "must be greater than 0").into());
+        }
+        if self.window_ms == 0 || self.emit_interval_ms == 0 {
+            return Err(anyhow!("window_ms and emit_interval_ms must be greater than 0"));
+        }
+        Ok(())
+    }
+}
+
+pub struct TopNProcessor {
+    name: String,
+    config: TopNConfig,
+    timing: TimingMixin,
+    windows: HashMap<String, SlidingWindow<f64>>,
+    /// Latest event time observed, used as the end of the window
+    stream_time: Option<SystemTime>,
+    last_emit: Instant,
+}
+
+impl TopNProcessor {
+    pub const METADATA: ProcessorMetadata = ProcessorMetadata {
+        name: "topn",
+        description: "Periodically ranks the top N keys by a metric over a sliding window",
+        parameters: &[
+            ParamSpec::new("key_field", ParamType::String, "Field whose value is ranked"),
+            ParamSpec::new("metric_field", ParamType::String, "Numeric field keys are ranked by"),
+            ParamSpec::new("n", ParamType::Integer, "Number of keys in the ranking"),
+            ParamSpec::new("window_ms", ParamType::Integer, "Length of the sliding window"),
+            ParamSpec::new("emit_interval_ms", ParamType::Integer, "How often the ranking is emitted"),
+            ParamSpec::new("aggregation", ParamType::Choice(&["latest", "max", "min", "mean", "sum", "count"]), "How a key's samples are reduced to a score"),
+            ParamSpec::new("order", ParamType::Choice(&["desc", "asc"]), "Highest (desc) or lowest (asc) score first"),
+            ParamSpec::new("output_field", ParamType::String, "Field receiving the ranking"),
+        ],
+        shared: &[],
+    };
+
+    pub fn new(name: &str, config: StageConfig) -> Result<Box<dyn Processor>> {
+        let processor_config = TopNConfig::from_stage_config(&config)?;
+        processor_config.validate()?;
+
+        let timing = TimingMixin::new(processor_config.timing.as_ref());
+
+        Ok(Box::new(Self {
+            name: name.to_string(),
+            config: processor_config,
+            timing,
+            windows: HashMap::new(),
+            stream_time: None,
+            last_emit: Instant::now(),
+        }))
+    }
+
+    fn record(&mut self, message: &Message) {
+        let Some(value) = FieldUtils::extract_f64(&message.payload, &self.config.metric_field) else {
+            debug!("Field '{}' missing or not numeric", self.config.metric_field);
+            return;
+        };
+        let key = FieldUtils::extract_key(&message.payload, Some(self.config.key_field.as_str()));
+        let event_time = message.timing.event_time;
+
+        let span = Duration::from_millis(self.config.window_ms);
+        self.windows
+            .entry(key)
+            .or_insert_with(|| SlidingWindow::new(span))
+            .push(event_time, value);
+        self.stream_time = Some(self.stream_time.map_or(event_time, |t| t.max(event_time)));
+    }
+
+    fn score(&self, window: &SlidingWindow<f64>) -> Option<f64> {
+        let count = window.len();
+        match self.config.aggregation {
+            TopNAggregation::Latest => window.latest().copied(),
+            TopNAggregation::Max => window.values().copied().reduce(f64::max),
+            TopNAggregation::Min => window.values().copied().reduce(f64::min),
+            TopNAggregation::Sum => Some(window.values().sum()),
+            TopNAggregation::Mean => (count > 0).then(|| window.values().sum::<f64>() / count as f64),
+            TopNAggregation::Count => Some(count as f64),
+        }
+    }
+
+    /// Evict expired samples and build the current ranking payload.
+    fn ranking(&mut self) -> Value {
+        if let Some(now) = self.stream_time {
+            for window in self.windows.values_mut() {
+                window.evict(now);
+            }
+        }
+        self.windows.retain(|_, window| !window.is_empty());
+
+        let mut scored: Vec<(&String, f64, usize)> = self
+            .windows
+            .iter()
+            .filter_map(|(key, window)| Some((key, self.score(window)?, window.len())))
+            .collect();
+        scored.sort_by(|a, b| {
+            let ordering = a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal);
+            match self.config.order {
+                RankOrder::Desc => ordering.reverse(),
+                RankOrder::Asc => ordering,
+            }
+            .then_with(|| a.0.cmp(b.0))
+        });
+
+        let entries: Vec<Value> = scored
+            .iter()
+            .take(self.config.n)
+            .enumerate()
+            .map(|(index, (key, score, samples))| {
+                json!({ "rank": index + 1, "key": key, "value": score, "samples": samples })
+            })
+            .collect();
+
+        let mut payload = json!({
+            "key_count": self.windows.len(),
+            "window_ms": self.config.window_ms,
+        });
+        // Output field validated as non-empty, so setting it cannot fail
+        let _ = FieldUtils::set_field_value(&mut payload, &self.config.output_field, Value::Array(entries));
+        payload
+    }
+}
+
+#[async_trait::async_trait]
+impl Processor for TopNProcessor {
+    async fn init(&mut self) -> Result<()> {
+        tracing::info!(
+            "TopN processor '{}' initialised (top {} by '{}' over {} ms)",
+            self.name,
+            self.config.n,
+            self.config.metric_field,
+            self.config.window_ms
+        );
+        Ok(())
+    }
+
+    async fn process(&mut self, context: &mut ProcessingContext) -> Result<()> {
+        if let Some((_, message)) = context.recv(tokio::time::Duration::from_millis(10)).await {
+            self.record(&message);
+        }
+
+        if self.last_emit.elapsed() < Duration::from_millis(self.config.emit_interval_ms) {
+            return Ok(());
+        }
+        self.last_emit = Instant::now();
+
+        let payload = self.ranking();
+        if self.windows.is_empty() {
+            return Ok(());
+        }
+
+        if let Some(output_info) = &context.output {
+            let event_time = self.stream_time.unwrap_or_else(SystemTime::now);
+            let message =
+                self.timing
+                    .create_message_with_timing(&self.name, &output_info.name, payload, event_time);
+
+            if let Err(e) = output_info.channel.publish(message).await {
+                tracing::warn!("Failed to publish topn ranking: {:?}", e);
+            }
+        }
+        Ok(())
+    }
+
+    fn as_snapshot(&mut self) -> Option<&mut dyn Snapshot> {
+        Some(self)
+    }
+}
+
+impl Snapshot for TopNProcessor {
+    fn snapshot(&self) -> Result<Value> {
+        Ok(serde_json::to_value((&self.windows, self.stream_time))?)
+    }
+
+    fn restore(&mut self, state: Value) -> Result<()> {
+        let (mut windows, stream_time): (HashMap<String, SlidingWindow<f64>>, Option<SystemTime>) =
+            serde_json::from_value(state)?;
+        let span = Duration::from_millis(self.config.window_ms);
+        for window in windows.values_mut() {
+            window.set_span(span);
+        }
+        self.windows = windows;
+        self.stream_time = stream_time;
+        Ok(())
+    }
+}
+
+impl WithTimingMixin for TopNProcessor {
+    fn timing_mixin(&self) -> &TimingMixin {
+        &self.timing
+    }
+
+    fn timing_mixin_mut(&mut self) -> &mut TimingMixin {
+        &mut self.timing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::UNIX_EPOCH;
+
+    #[test]
+    fn test_topn_ranks_within_window() {
+        let config = StageConfig {
+            r#type: "topn".to_string(),
+            parameters: Some(HashMap::from([
+                ("key_field".to_string(), json!("sensor")),
+                ("metric_field".to_string(), json!("temp")),
+                ("n".to_string(), json!(2)),
+                ("window_ms".to_string(), json!(1000)),
+            ])),
+            ..Default::default()
+        };
+        let mut processor = TopNProcessor {
+            name: "topn".to_string(),
+            config: TopNConfig::from_stage_config(&config).unwrap(),
+            timing: TimingMixin::new(None),
+            windows: HashMap::new(),
+            stream_time: None,
+            last_emit: Instant::now(),
+        };
+
+        let readings = [(0, "a", 90.0), (100, "b", 50.0), (1500, "b", 60.0), (1600, "c", 70.0), (1700, "d", 10.0)];
+        for (ms, sensor, temp) in readings {
+            let time = UNIX_EPOCH + Duration::from_millis(ms);
+            processor.record(&Message::new_with_event_time(
+                "src",
+                "topic",
+                json!({"sensor": sensor, "temp": temp}),
+                time,
+            ));
+        }
+
+        // Sensor "a" has aged out of the window
+        let payload = processor.ranking();
+        assert_eq!(payload["key_count"], json!(3));
+        assert_eq!(
+            payload["ranking"],
+            json!([
+                {"rank": 1, "key": "c", "value": 70.0, "samples": 1},
+                {"rank": 2, "key": "b", "value": 60.0, "samples": 1},
+            ])
+        );
+    }
+}
@@ -1,3 +1,4 @@
+use regex::Regex;
 use serde_json::Value;
 
 /// Condition operations supported by processors
@@ -12,11 +13,15 @@ pub enum ConditionOperation {
     GreaterThanOrEqual,
     LessThan,
     LessThanOrEqual,
+    Exists,
+    NotExists,
+    In,
+    Matches,
 }
 
 impl ConditionOperation {
     /// Parse a condition operation from string
-    pub fn from_str(s: &str) -> Option<Self> {
+    pub fn parse(s: &str) -> Option<Self> {
         match s {
             "equals" | "==" => Some(Self::Equals),
             "not_equals" | "!=" => Some(Self::NotEquals),
@@ -27,6 +32,10 @@ impl ConditionOperation {
             ">=" => Some(Self::GreaterThanOrEqual),
             "<" => Some(Self::LessThan),
             "<=" => Some(Self::LessThanOrEqual),
+            "exists" => Some(Self::Exists),
+            "not_exists" => Some(Self::NotExists),
+            "in" => Some(Self::In),
+            "matches" => Some(Self::Matches),
             _ => None,
         }
     }
@@ -43,6 +52,10 @@ impl ConditionOperation {
             Self::GreaterThanOrEqual => ">=",
             Self::LessThan => "<",
             Self::LessThanOrEqual => "<=",
+            Self::Exists => "exists",
+            Self::NotExists => "not_exists",
+            Self::In => "in",
+            Self::Matches => "matches",
         }
     }
 }
@@ -51,6 +64,23 @@ impl ConditionOperation {
 pub struct ConditionEvaluator;
 
 impl ConditionEvaluator {
+    /// Evaluate a condition against a field value that may be missing
+    ///
+    /// `exists` and `not_exists` test for presence; every other operation is
+    /// false when the field is missing.
+    pub fn evaluate_optional(
+        field_value: Option<&Value>,
+        operation: &ConditionOperation,
+        expected_value: &Value,
+    ) -> bool {
+        match (operation, field_value) {
+            (ConditionOperation::Exists, value) => value.is_some(),
+            (ConditionOperation::NotExists, value) => value.is_none(),
+            (_, Some(value)) => Self::evaluate_condition(value, operation, expected_value),
+            (_, None) => false,
+        }
+    }
+
     /// Evaluate a condition against a field value
     /// 
     /// # Arguments
@@ -63,6 +93,21 @@ impl ConditionEvaluator {
         expected_value: &Value,
     ) -> bool {
         match operation {
+            ConditionOperation::Exists => true,
+            ConditionOperation::NotExists => false,
+
+            ConditionOperation::In => match expected_value {
+                Value::Array(candidates) => candidates
+                    .iter()
+                    .any(|candidate| Self::values_equal(field_value, candidate)),
+                _ => false,
+            },
+
+            ConditionOperation::Matches => match expected_value.as_str().map(Regex::new) {
+                Some(Ok(regex)) => Self::matches_regex(field_value, &regex),
+                _ => false,
+            },
+
             ConditionOperation::Equals => field_value == expected_value,
             ConditionOperation::NotEquals => field_value != expected_value,
             
@@ -203,6 +248,22 @@ impl ConditionEvaluator {
         false
     }
 
+    /// Test a string field against a compiled regular expression
+    ///
+    /// Callers evaluating the same pattern repeatedly should compile it once and
+    /// use this instead of `evaluate_condition` with `Matches`.
+    pub fn matches_regex(field_value: &Value, regex: &Regex) -> bool {
+        field_value.as_str().is_some_and(|s| regex.is_match(s))
+    }
+
+    /// Equality that treats numerically equal integers and floats as equal
+    fn values_equal(a: &Value, b: &Value) -> bool {
+        match (a, b) {
+            (Value::Number(x), Value::Number(y)) => x.as_f64() == y.as_f64(),
+            _ => a == b,
+        }
+    }
+
     /// Helper function to compare numeric values
     fn compare_numbers<F>(field_value: &Value, expected_value: &Value, comparator: F) -> bool
     where
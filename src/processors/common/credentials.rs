@@ -0,0 +1,83 @@
+//! Credentials
+//!
+//! Secrets defined once in a `[credentials.<name>]` section and shared by the
+//! stages naming them with a `credentials` parameter:
+//!
+//! ```toml
+//! [credentials.plant_broker]
+//! username = "edge"
+//! password = "${file:/run/secrets/mqtt_password}"
+//!
+//! [inputs.plant]
+//! type = "mqtt_sub"
+//! output = "raw"
+//! parameters = { broker_url = "mqtt://broker:1883", topics = ["plant/#"], credentials = "plant_broker" }
+//! ```
+//!
+//! The pipeline manager replaces the name with the credentials before the
+//! stage is built, and processors read them with [`credentials`]. Parameters
+//! set on the stage itself take precedence over its credentials.
+
+use crate::config::types::CredentialsConfig;
+use crate::config::{ParamSpec, ParamType, extract_param};
+
+use std::collections::HashMap;
+
+/// The `credentials` parameter, accepted by every processor that
+/// authenticates.
+pub const CREDENTIALS_PARAMS: &[ParamSpec] = &[ParamSpec::new(
+    "credentials",
+    ParamType::String,
+    "Name of the [credentials] entry to authenticate with",
+)];
+
+/// The credentials a stage references, or none.
+pub fn credentials(parameters: &Option<HashMap<String, serde_json::Value>>) -> CredentialsConfig {
+    extract_param(parameters, "credentials", CredentialsConfig::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::loader::load_config_from_string;
+    use crate::config::validate_config;
+    use crate::processors::common::MqttConnectionConfig;
+
+    #[test]
+    fn test_stages_share_named_credentials() {
+        let config = load_config_from_string(
+            r#"
+            [credentials.plant_broker]
+            username = "edge"
+            password = "hunter2"
+
+            [inputs.north]
+            type = "mqtt_sub"
+            output = "raw"
+            parameters = { topics = ["north/#"], credentials = "plant_broker" }
+
+            [outputs.uplink]
+            type = "mqtt_pub"
+            inputs = ["raw"]
+            parameters = { topic = "uplink", username = "uplink", credentials = "plant_broker" }
+            "#,
+        )
+        .unwrap();
+        validate_config(&config).unwrap();
+
+        let north = config.inputs["north"].with_credentials(&config.credentials);
+        let north = MqttConnectionConfig::from_parameters(&north.parameters, "north");
+        assert_eq!(north.username.as_deref(), Some("edge"));
+        assert_eq!(north.password.as_deref(), Some("hunter2"));
+
+        // The stage's own parameters take precedence
+        let uplink = config.outputs["uplink"].with_credentials(&config.credentials);
+        let uplink = MqttConnectionConfig::from_parameters(&uplink.parameters, "uplink");
+        assert_eq!(uplink.username.as_deref(), Some("uplink"));
+        assert_eq!(uplink.password.as_deref(), Some("hunter2"));
+
+        let mut unknown = config.clone();
+        unknown.credentials.clear();
+        let error = validate_config(&unknown).unwrap_err();
+        assert!(error.to_string().contains("credentials 'plant_broker'"));
+    }
+}
@@ -0,0 +1,40 @@
+//! Dead Letters
+//!
+//! Sinks that can fail to deliver a message (to an MQTT broker, a TCP peer)
+//! take a `dead_letter` parameter naming one of the stage's side outputs.
+//! Messages they cannot deliver are published there, tagged with the error in
+//! their `delivery_error` metadata, instead of only being logged:
+//!
+//! ```toml
+//! [outputs.cloud]
+//! type = "mqtt_pub"
+//! inputs = ["readings"]
+//! side_outputs = ["undelivered"]
+//! parameters = { broker_url = "mqtt://broker:1883", default_topic = "plant/readings", dead_letter = "undelivered" }
+//! ```
+
+use crate::config::{ParamSpec, ParamType, StageConfig, extract_param};
+
+use anyhow::{Result, anyhow};
+
+/// The `dead_letter` parameter, accepted by every sink that dead-letters.
+pub const DEAD_LETTER_PARAMS: &[ParamSpec] = &[ParamSpec::new(
+    "dead_letter",
+    ParamType::String,
+    "Side output receiving messages that could not be delivered",
+)];
+
+/// Read the `dead_letter` parameter, which must name a side output of the stage.
+pub fn dead_letter(config: &StageConfig) -> Result<Option<String>> {
+    let Some(output) = extract_param(&config.parameters, "dead_letter", None::<String>) else {
+        return Ok(None);
+    };
+    if !config.side_outputs.as_ref().is_some_and(|outputs| outputs.contains(&output)) {
+        return Err(anyhow!(
+            "dead_letter '{}' is not a side output of this stage (declared: {:?})",
+            output,
+            config.side_outputs.clone().unwrap_or_default()
+        ));
+    }
+    Ok(Some(output))
+}
@@ -118,4 +118,29 @@ impl FieldUtils {
     pub fn field_exists(payload: &Value, field_path: &str) -> bool {
         Self::extract_field_value(payload, field_path).is_some()
     }
+
+    /// Extract a numeric field value as f64 using dot notation path
+    ///
+    /// Returns `None` if the field is missing or not a number.
+    pub fn extract_f64(payload: &Value, field_path: &str) -> Option<f64> {
+        Self::extract_field_value(payload, field_path).and_then(Value::as_f64)
+    }
+
+    /// Extract a partition key from a JSON payload
+    ///
+    /// Stateful processors use this to keep independent state per device/sensor.
+    /// String values are used verbatim, other values are rendered as JSON. When no
+    /// key field is configured (or the field is missing) an empty key is returned,
+    /// which collapses all messages into a single partition.
+    ///
+    /// # Arguments
+    /// * `payload` - The JSON value to extract from
+    /// * `key_field` - Optional dot-separated path to the key field
+    pub fn extract_key(payload: &Value, key_field: Option<&str>) -> String {
+        match key_field.and_then(|field| Self::extract_field_value(payload, field)) {
+            Some(Value::String(s)) => s.clone(),
+            Some(value) => value.to_string(),
+            None => String::new(),
+        }
+    }
 }
\ No newline at end of file
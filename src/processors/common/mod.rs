@@ -1,6 +1,18 @@
 pub mod mqtt;
 pub mod field_utils;
+pub mod column;
 pub mod condition_utils;
+pub mod credentials;
+pub mod delivery;
+pub mod retry;
+pub mod stats;
 pub mod tcp;
+pub mod time_utils;
+pub mod tls;
+pub mod window;
 
-pub use mqtt::MqttConnectionConfig;
+pub use credentials::{CREDENTIALS_PARAMS, credentials};
+pub use delivery::{DEAD_LETTER_PARAMS, dead_letter};
+pub use mqtt::{MQTT_CONNECTION_PARAMS, MqttConnectionConfig};
+pub use retry::{RETRY_PARAMS, Retry, RetryPolicy};
+pub use tls::{TLS_PARAMS, TlsConfig};
@@ -1,7 +1,20 @@
-use crate::config::extract_param;
+use super::{RetryPolicy, credentials};
+use crate::config::{ParamSpec, ParamType, extract_param};
+use crate::error::LiminalError;
 use anyhow::Result;
-use rumqttc::{MqttOptions, QoS};
+use rumqttc::{AsyncClient, Event, MqttOptions, Packet, QoS};
 use std::collections::HashMap;
+use std::time::Duration;
+
+/// MQTT connection parameters shared by the input and output processors.
+pub const MQTT_CONNECTION_PARAMS: &[ParamSpec] = &[
+    ParamSpec::new("broker_url", ParamType::String, "Broker address, e.g. mqtt://localhost:1883"),
+    ParamSpec::new("client_id", ParamType::String, "MQTT client identifier"),
+    ParamSpec::new("qos", ParamType::Integer, "Quality of service level (0, 1 or 2)"),
+    ParamSpec::new("clean_session", ParamType::Boolean, "Start without session state from earlier connections"),
+    ParamSpec::new("username", ParamType::String, "Broker username"),
+    ParamSpec::new("password", ParamType::String, "Broker password"),
+];
 
 /// Common MQTT configuration shared between input and output processors
 #[derive(Debug, Clone)]
@@ -12,6 +25,8 @@ pub struct MqttConnectionConfig {
     pub clean_session: bool,
     pub username: Option<String>,
     pub password: Option<String>,
+    /// Reconnection backoff after the broker connection fails
+    pub retry: RetryPolicy,
 }
 
 impl MqttConnectionConfig {
@@ -24,8 +39,10 @@ impl MqttConnectionConfig {
         let client_id = extract_param(parameters, "client_id", None);
         let qos = extract_param(parameters, "qos", 0);
         let clean_session = extract_param(parameters, "clean_session", true);
-        let username = extract_param(parameters, "username", None);
-        let password = extract_param(parameters, "password", None);
+        let credentials = credentials(parameters);
+        let username = extract_param(parameters, "username", credentials.username);
+        let password = extract_param(parameters, "password", credentials.password);
+        let retry = RetryPolicy::from_parameters(parameters, RetryPolicy::default());
 
         Self {
             broker_url,
@@ -34,6 +51,7 @@ impl MqttConnectionConfig {
             clean_session,
             username,
             password,
+            retry,
         }
     }
 
@@ -45,7 +63,7 @@ impl MqttConnectionConfig {
         if self.broker_url.is_empty() {
             return Err(anyhow::anyhow!("Broker URL cannot be empty"));
         }
-        Ok(())
+        self.retry.validate()
     }
 
     /// Parse broker URL into host and port
@@ -91,4 +109,35 @@ impl MqttConnectionConfig {
 
         Ok(mqttoptions)
     }
+
+    /// Check that the broker accepts a connection with these credentials.
+    ///
+    /// The check connects under a client id of its own with a clean session,
+    /// so that it neither takes over nor clears the session of the configured
+    /// client id.
+    pub async fn preflight(&self, default_client_prefix: &str) -> Result<()> {
+        let probe = Self { client_id: None, clean_session: true, ..self.clone() };
+        let options = probe.create_mqtt_options(&format!("{}_preflight", default_client_prefix))?;
+        let (client, mut eventloop) = AsyncClient::new(options, 1);
+
+        loop {
+            match eventloop.poll().await {
+                Ok(Event::Incoming(Packet::ConnAck(_))) => break,
+                Ok(_) => {}
+                Err(e) => {
+                    let source = match e {
+                        rumqttc::ConnectionError::Io(e) => e.into(),
+                        e => e.into(),
+                    };
+                    return Err(LiminalError::ConnectionError { endpoint: self.broker_url.clone(), source }.into());
+                }
+            }
+        }
+
+        // Leave politely; the broker drops the connection either way
+        if client.try_disconnect().is_ok() {
+            let _ = tokio::time::timeout(Duration::from_secs(1), eventloop.poll()).await;
+        }
+        Ok(())
+    }
 }
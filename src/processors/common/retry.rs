@@ -0,0 +1,212 @@
+//! Retry Policy
+//!
+//! Shared retry behaviour for processors that talk to external systems (MQTT
+//! brokers, TCP peers). The delay before each retry grows exponentially from
+//! `base_delay_ms` by `multiplier`, is capped at `max_delay_ms`, and is spread
+//! by random `jitter` so that many clients do not reconnect in lockstep.
+//!
+//! Policies are configured per stage through a `retry` parameter table:
+//!
+//! ```toml
+//! parameters = { broker_url = "mqtt://broker:1883", retry = { max_attempts = 10, base_delay_ms = 500, max_delay_ms = 30000, multiplier = 2.0, jitter = 0.2 } }
+//! ```
+//!
+//! Only errors classified as transient (connection refused or reset, timeouts,
+//! unreachable hosts, ...) are retried; permanent ones such as rejected
+//! credentials fail immediately.
+
+use crate::config::{ParamSpec, ParamType, extract_param};
+
+use anyhow::{Result, anyhow};
+use rand::Rng;
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::time::Duration;
+
+/// The `retry` parameter, accepted by every processor that retries.
+pub const RETRY_PARAMS: &[ParamSpec] = &[ParamSpec::new(
+    "retry",
+    ParamType::Object,
+    "Reconnection backoff: max_attempts, base_delay_ms, max_delay_ms, multiplier, jitter",
+)];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// Retries before giving up (0 = retry forever)
+    pub max_attempts: u32,
+    /// Delay before the first retry (in milliseconds)
+    pub base_delay_ms: u64,
+    /// Longest delay between retries (in milliseconds)
+    pub max_delay_ms: u64,
+    /// Factor applied to the delay after each failed attempt
+    pub multiplier: f64,
+    /// Fraction of each delay that is randomised, from 0.0 to 1.0
+    pub jitter: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 0,
+            base_delay_ms: 1000,
+            max_delay_ms: 60_000,
+            multiplier: 2.0,
+            jitter: 0.1,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Read the `retry` parameter table, taking unset values from `defaults`.
+    pub fn from_parameters(parameters: &Option<HashMap<String, serde_json::Value>>, defaults: Self) -> Self {
+        let retry: Option<HashMap<String, serde_json::Value>> = extract_param(parameters, "retry", None);
+        Self {
+            max_attempts: extract_param(&retry, "max_attempts", defaults.max_attempts),
+            base_delay_ms: extract_param(&retry, "base_delay_ms", defaults.base_delay_ms),
+            max_delay_ms: extract_param(&retry, "max_delay_ms", defaults.max_delay_ms),
+            multiplier: extract_param(&retry, "multiplier", defaults.multiplier),
+            jitter: extract_param(&retry, "jitter", defaults.jitter),
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.base_delay_ms > self.max_delay_ms {
+            return Err(anyhow!("retry.base_delay_ms cannot be greater than retry.max_delay_ms"));
+        }
+        if self.multiplier < 1.0 {
+            return Err(anyhow!("retry.multiplier must be at least 1.0"));
+        }
+        if !(0.0..=1.0).contains(&self.jitter) {
+            return Err(anyhow!("retry.jitter must be between 0.0 and 1.0"));
+        }
+        Ok(())
+    }
+
+    /// Delay before retry number `attempt` (counting from 1), or `None` once
+    /// the attempts are used up.
+    pub fn delay(&self, attempt: u32) -> Option<Duration> {
+        if self.max_attempts > 0 && attempt > self.max_attempts {
+            return None;
+        }
+
+        let exponent = attempt.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let delay_ms = (self.base_delay_ms as f64 * self.multiplier.powi(exponent)).min(self.max_delay_ms as f64);
+        let spread = if self.jitter > 0.0 {
+            1.0 + rand::rng().random_range(-self.jitter..=self.jitter)
+        } else {
+            1.0
+        };
+        Some(Duration::from_millis((delay_ms * spread) as u64))
+    }
+
+    /// Whether an error is worth retrying. I/O errors are classified by kind;
+    /// errors of unknown origin are assumed to be transient.
+    pub fn is_retryable(error: &anyhow::Error) -> bool {
+        for cause in error.chain() {
+            if let Some(error) = cause.downcast_ref::<std::io::Error>() {
+                return is_transient(error.kind());
+            }
+            if let Some(error) = cause.downcast_ref::<rumqttc::ConnectionError>() {
+                return match error {
+                    rumqttc::ConnectionError::Io(error) => is_transient(error.kind()),
+                    rumqttc::ConnectionError::ConnectionRefused(code) => {
+                        *code == rumqttc::ConnectReturnCode::ServiceUnavailable
+                    }
+                    _ => true,
+                };
+            }
+        }
+        true
+    }
+}
+
+fn is_transient(kind: ErrorKind) -> bool {
+    matches!(
+        kind,
+        ErrorKind::ConnectionRefused
+            | ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionAborted
+            | ErrorKind::NotConnected
+            | ErrorKind::BrokenPipe
+            | ErrorKind::TimedOut
+            | ErrorKind::UnexpectedEof
+            | ErrorKind::Interrupted
+            | ErrorKind::WouldBlock
+            | ErrorKind::HostUnreachable
+            | ErrorKind::NetworkUnreachable
+            | ErrorKind::NetworkDown
+            | ErrorKind::AddrNotAvailable
+    )
+}
+
+/// Consecutive failures of one operation, retried under a policy.
+#[derive(Debug, Clone)]
+pub struct Retry {
+    policy: RetryPolicy,
+    attempt: u32,
+}
+
+impl Retry {
+    pub fn new(policy: RetryPolicy) -> Self {
+        Self { policy, attempt: 0 }
+    }
+
+    /// Record a failure. Returns how long to wait before trying again, or
+    /// `None` if the error is permanent or the attempts are used up.
+    pub fn failed(&mut self, error: &anyhow::Error) -> Option<Duration> {
+        if !RetryPolicy::is_retryable(error) {
+            return None;
+        }
+        self.attempt += 1;
+        self.policy.delay(self.attempt)
+    }
+
+    /// Record a success, starting the next failure from the base delay.
+    pub fn succeeded(&mut self) {
+        self.attempt = 0;
+    }
+
+    /// Failures since the last success.
+    pub fn attempts(&self) -> u32 {
+        self.attempt
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_delay_grows_and_gives_up() {
+        let policy = RetryPolicy {
+            max_attempts: 4,
+            base_delay_ms: 100,
+            max_delay_ms: 500,
+            multiplier: 2.0,
+            jitter: 0.0,
+        };
+        let delays: Vec<_> = (1..=5).map(|attempt| policy.delay(attempt).map(|d| d.as_millis())).collect();
+        assert_eq!(delays, [Some(100), Some(200), Some(400), Some(500), None]);
+
+        let jittered = RetryPolicy { jitter: 0.5, ..policy };
+        for _ in 0..20 {
+            let delay = jittered.delay(1).unwrap().as_millis();
+            assert!((50..=150).contains(&delay));
+        }
+    }
+
+    #[test]
+    fn test_retry_classifies_errors() {
+        let refused = anyhow::Error::new(std::io::Error::from(ErrorKind::ConnectionRefused));
+        let denied = anyhow::Error::new(std::io::Error::from(ErrorKind::PermissionDenied));
+        assert!(RetryPolicy::is_retryable(&refused.context("connecting")));
+        assert!(!RetryPolicy::is_retryable(&denied));
+
+        let mut retry = Retry::new(RetryPolicy { max_attempts: 1, ..Default::default() });
+        let timed_out = anyhow::Error::new(std::io::Error::from(ErrorKind::TimedOut));
+        assert!(retry.failed(&timed_out).is_some());
+        assert!(retry.failed(&timed_out).is_none());
+        retry.succeeded();
+        assert!(retry.failed(&timed_out).is_some());
+    }
+}
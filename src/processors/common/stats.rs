@@ -0,0 +1,35 @@
+//! Small statistics helpers shared by the analytic processors.
+
+use std::cmp::Ordering;
+
+/// Return the `q`-quantile (0.0..=1.0) of a set of samples using linear
+/// interpolation between closest ranks. Returns `None` for an empty input.
+pub fn quantile(values: &[f64], q: f64) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+
+    let rank = q.clamp(0.0, 1.0) * (sorted.len() - 1) as f64;
+    let lower = rank.floor() as usize;
+    let upper = rank.ceil() as usize;
+    let weight = rank - lower as f64;
+
+    Some(sorted[lower] + (sorted[upper] - sorted[lower]) * weight)
+}
+
+/// Return the median of a set of samples, or `None` for an empty input.
+pub fn median(values: &[f64]) -> Option<f64> {
+    quantile(values, 0.5)
+}
+
+/// Return the median absolute deviation around `center`.
+pub fn median_abs_deviation(values: &[f64], center: f64) -> Option<f64> {
+    let deviations: Vec<f64> = values.iter().map(|x| (x - center).abs()).collect();
+    median(&deviations)
+}
+
+/// Scale factor that makes the MAD a consistent estimator of the standard deviation.
+pub const MAD_CONSISTENCY: f64 = 0.6745;
@@ -1,14 +1,122 @@
 use anyhow::anyhow;
 use tokio::net::{TcpListener, TcpStream};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use tokio::time::{timeout, Duration};
-use crate::config::{extract_param, StageConfig};
+use crate::config::{extract_param, ParamSpec, ParamType, StageConfig};
+use crate::error::LiminalError;
+use super::{Retry, RetryPolicy, TlsConfig};
+
+/// A connected stream, plaintext or TLS.
+trait Stream: AsyncRead + AsyncWrite + Unpin + Send + Sync {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send + Sync> Stream for T {}
+
+/// TCP connection parameters shared by the input and output processors.
+pub const TCP_PARAMS: &[ParamSpec] = &[
+    ParamSpec::new("mode", ParamType::Choice(&["client", "server"]), "Connect to a peer, or listen for one"),
+    ParamSpec::new("host", ParamType::String, "Host to connect to, or address to listen on"),
+    ParamSpec::new("port", ParamType::Integer, "Port to connect to or listen on"),
+    ParamSpec::new("reconnect", ParamType::Boolean, "Reconnect after the connection fails"),
+    ParamSpec::new("reconnect_interval_ms", ParamType::Integer, "Delay before the first reconnection attempt"),
+    ParamSpec::new("compression", ParamType::Choice(&["none", "zlib", "zstd", "lz4"]), "Compress each frame; both peers must agree"),
+    ParamSpec::new("compression_level", ParamType::Integer, "Compression level from 1 (fastest): up to 9 for zlib (default 6), 22 for zstd (default 3); lz4 has none"),
+];
+
+/// Largest frame accepted after decompression, guarding against
+/// decompression bombs.
+const MAX_DECOMPRESSED_BYTES: usize = 64 * 1024 * 1024;
+
+/// Frame compression. Each length-prefixed frame carries one message,
+/// compressed on its own so frames can be decoded independently.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    /// zlib-wrapped deflate, as produced by Erlang's `zlib:compress/1`
+    Zlib { level: u8 },
+    /// A Zstandard frame; better ratios than zlib at a lower CPU cost
+    Zstd { level: u8 },
+    /// An LZ4 block prefixed with its uncompressed size (little-endian `u32`),
+    /// as produced by `lz4_flex::compress_prepend_size`; the cheapest to run
+    Lz4,
+}
+
+impl Compression {
+    pub fn compress(&self, data: &[u8]) -> anyhow::Result<Vec<u8>> {
+        match self {
+            Compression::None => Ok(data.to_vec()),
+            Compression::Zlib { level } => Ok(miniz_oxide::deflate::compress_to_vec_zlib(data, *level)),
+            Compression::Zstd { level } => zstd::bulk::compress(data, *level as i32)
+                .map_err(|e| anyhow!("Failed to compress frame: {}", e)),
+            Compression::Lz4 => Ok(lz4_flex::compress_prepend_size(data)),
+        }
+    }
+
+    pub fn decompress(&self, data: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+        match self {
+            Compression::None => Ok(data),
+            Compression::Zlib { .. } => {
+                miniz_oxide::inflate::decompress_to_vec_zlib_with_limit(&data, MAX_DECOMPRESSED_BYTES)
+                    .map_err(|e| anyhow!("Failed to decompress frame: {}", e))
+            }
+            Compression::Zstd { .. } => {
+                use std::io::Read;
+
+                let decoder = zstd::stream::read::Decoder::new(data.as_slice())
+                    .map_err(|e| anyhow!("Failed to decompress frame: {}", e))?;
+                let mut message = Vec::new();
+                decoder
+                    .take(MAX_DECOMPRESSED_BYTES as u64 + 1)
+                    .read_to_end(&mut message)
+                    .map_err(|e| anyhow!("Failed to decompress frame: {}", e))?;
+                if message.len() > MAX_DECOMPRESSED_BYTES {
+                    return Err(anyhow!("Decompressed frame exceeds {} bytes", MAX_DECOMPRESSED_BYTES));
+                }
+                Ok(message)
+            }
+            Compression::Lz4 => {
+                // Check the declared size before it is allocated
+                let size = data
+                    .first_chunk::<4>()
+                    .map(|size| u32::from_le_bytes(*size) as usize)
+                    .ok_or_else(|| anyhow!("Failed to decompress frame: missing size prefix"))?;
+                if size > MAX_DECOMPRESSED_BYTES {
+                    return Err(anyhow!("Decompressed frame exceeds {} bytes", MAX_DECOMPRESSED_BYTES));
+                }
+                lz4_flex::decompress_size_prepended(&data).map_err(|e| anyhow!("Failed to decompress frame: {}", e))
+            }
+        }
+    }
+}
+
+/// Bytes passed through a connection before and after compression.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CompressionStats {
+    pub raw_bytes: u64,
+    pub wire_bytes: u64,
+}
+
+impl CompressionStats {
+    fn add(&mut self, raw: usize, wire: usize) {
+        self.raw_bytes += raw as u64;
+        self.wire_bytes += wire as u64;
+    }
+
+    /// Uncompressed size over compressed size, or `None` before any traffic.
+    pub fn ratio(&self) -> Option<f64> {
+        (self.wire_bytes > 0).then(|| self.raw_bytes as f64 / self.wire_bytes as f64)
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct TcpConfig {
     pub mode: TcpMode,
     pub reconnect: bool,
     pub reconnect_interval_ms: u64,
+    pub compression: Compression,
+    /// TLS settings, or `None` for plaintext
+    pub tls: Option<TlsConfig>,
+    /// Reconnection backoff, starting from `reconnect_interval_ms` by default
+    pub retry: RetryPolicy,
 }
 
 #[derive(Debug, Clone)]
@@ -37,11 +145,30 @@ impl TcpConfig {
 
         let reconnect: bool = extract_param(&config.parameters, "reconnect", true);
         let reconnect_interval_ms: u64 = extract_param(&config.parameters, "reconnect_interval_ms", 5000);
+        let compression_str: String = extract_param(&config.parameters, "compression", "none".to_string());
+        let level: Option<u8> = extract_param(&config.parameters, "compression_level", None);
+        let compression = match compression_str.as_str() {
+            "none" => Compression::None,
+            "zlib" => Compression::Zlib { level: level.unwrap_or(6) },
+            "zstd" => Compression::Zstd { level: level.unwrap_or(3) },
+            "lz4" => Compression::Lz4,
+            _ => return Err(anyhow!("Invalid TCP compression: {}. Must be 'none', 'zlib', 'zstd' or 'lz4'", compression_str)),
+        };
+
+        let defaults = RetryPolicy::default();
+        let retry = RetryPolicy::from_parameters(&config.parameters, RetryPolicy {
+            base_delay_ms: reconnect_interval_ms,
+            max_delay_ms: defaults.max_delay_ms.max(reconnect_interval_ms),
+            ..defaults
+        });
 
         Ok(Self {
             mode,
             reconnect,
             reconnect_interval_ms,
+            compression,
+            tls: TlsConfig::from_parameters(&config.parameters),
+            retry,
         })
     }
 
@@ -56,22 +183,102 @@ impl TcpConfig {
                 }
             }
         }
-        Ok(())
+        match self.compression {
+            Compression::Zlib { level } if !(1..=9).contains(&level) => {
+                return Err(anyhow!("TCP compression_level must be between 1 and 9 for zlib"));
+            }
+            Compression::Zstd { level } if !(1..=22).contains(&level) => {
+                return Err(anyhow!("TCP compression_level must be between 1 and 22 for zstd"));
+            }
+            _ => {}
+        }
+        if let Some(tls) = &self.tls {
+            tls.validate()?;
+            // Load the certificates now, so a missing file fails the stage
+            // before it starts
+            match &self.mode {
+                TcpMode::Client { host, .. } => {
+                    tls.connector()?;
+                    tls.server_name(host)?;
+                }
+                TcpMode::Server { .. } => {
+                    tls.acceptor()?;
+                }
+            }
+        }
+        self.retry.validate()
+    }
+
+    /// Check that the peer accepts connections. A server has no peer to check.
+    pub async fn preflight(&self) -> anyhow::Result<()> {
+        match &self.mode {
+            TcpMode::Client { host, port } => check_reachable(host, *port, self.tls.as_ref()).await,
+            TcpMode::Server { .. } => Ok(()),
+        }
+    }
+}
+
+/// Open a TCP connection to `host:port`, giving up after 10 seconds.
+async fn dial(host: &str, port: u16) -> anyhow::Result<TcpStream> {
+    let endpoint = format!("{}:{}", host, port);
+    match timeout(Duration::from_secs(10), TcpStream::connect(&endpoint)).await {
+        Ok(Ok(stream)) => Ok(stream),
+        Ok(Err(e)) => Err(LiminalError::ConnectionError { endpoint, source: e.into() }.into()),
+        Err(_) => {
+            let timed_out = std::io::Error::from(std::io::ErrorKind::TimedOut);
+            Err(LiminalError::ConnectionError { endpoint, source: timed_out.into() }.into())
+        }
     }
 }
 
+/// Perform the client side of a TLS handshake with `host`.
+async fn handshake(
+    tls: &TlsConfig,
+    host: &str,
+    stream: TcpStream,
+) -> anyhow::Result<tokio_rustls::client::TlsStream<TcpStream>> {
+    let connector = tls.connector()?;
+    let server_name = tls.server_name(host)?;
+    match timeout(Duration::from_secs(10), connector.connect(server_name, stream)).await {
+        Ok(Ok(stream)) => Ok(stream),
+        Ok(Err(e)) => {
+            let message = format!("TLS handshake failed: {}", e);
+            Err(anyhow::Error::new(e).context(message))
+        }
+        Err(_) => Err(anyhow!("TLS handshake timeout")),
+    }
+}
+
+/// Check that the server at `host:port` accepts connections, and completes
+/// a TLS handshake when `tls` is set.
+pub async fn check_reachable(host: &str, port: u16, tls: Option<&TlsConfig>) -> anyhow::Result<()> {
+    let stream = dial(host, port).await?;
+    if let Some(tls) = tls {
+        handshake(tls, host, stream).await.map_err(|e| LiminalError::ConnectionError {
+            endpoint: format!("{}:{}", host, port),
+            source: e,
+        })?;
+    }
+    Ok(())
+}
+
 pub struct TcpConnection {
     name: String,
     config: TcpConfig,
-    stream: Option<TcpStream>,
+    stream: Option<Box<dyn Stream>>,
+    retry: Retry,
+    /// Traffic on the current connection
+    stats: CompressionStats,
 }
 
 impl TcpConnection {
     pub fn new(name: String, config: TcpConfig) -> Self {
         Self {
             name,
+            retry: Retry::new(config.retry.clone()),
             config,
             stream: None,
+            stats: CompressionStats::default(),
         }
     }
 
@@ -79,25 +286,40 @@ impl TcpConnection {
         self.stream.is_some()
     }
 
+    /// Check that the peer accepts connections, without connecting this one.
+    pub async fn preflight(&self) -> anyhow::Result<()> {
+        self.config.preflight().await
+    }
+
     async fn connect_client(&mut self) -> anyhow::Result<()> {
         if let TcpMode::Client { host, port } = &self.config.mode {
             tracing::info!("{}: Attempting to connect to TCP server at {}:{}", self.name, host, port);
             
-            match timeout(Duration::from_secs(10), TcpStream::connect(format!("{}:{}", host, port))).await {
-                Ok(Ok(stream)) => {
-                    self.stream = Some(stream);
+            let stream = match dial(host, *port).await {
+                Ok(stream) => stream,
+                Err(e) => {
+                    tracing::error!("{}: Failed to connect to TCP server at {}:{} - {:#}", self.name, host, port, e);
+                    return Err(e);
+                }
+            };
+            match &self.config.tls {
+                Some(tls) => {
+                    let stream = match handshake(tls, host, stream).await {
+                        Ok(stream) => stream,
+                        Err(e) => {
+                            tracing::error!("{}: TLS handshake with {}:{} failed - {:#}", self.name, host, port, e);
+                            return Err(e);
+                        }
+                    };
+                    self.stream = Some(Box::new(stream));
+                    tracing::info!("{}: Connected to TCP server at {}:{} over TLS", self.name, host, port);
+                }
+                None => {
+                    self.stream = Some(Box::new(stream));
                     tracing::info!("{}: Connected to TCP server at {}:{}", self.name, host, port);
-                    Ok(())
-                },
-                Ok(Err(e)) => {
-                    tracing::error!("{}: Failed to connect to TCP server at {}:{} - {}", self.name, host, port, e);
-                    Err(anyhow!("Failed to connect to TCP server: {}", e))
-                },
-                Err(_) => {
-                    tracing::error!("{}: Connection to TCP server at {}:{} timed out", self.name, host, port);
-                    Err(anyhow!("Connection timeout"))
                 }
             }
+            Ok(())
         } else {
             Err(anyhow!("connect_client called on server mode"))
         }
@@ -109,12 +331,28 @@ impl TcpConnection {
             let listener = TcpListener::bind(&bind_address).await?;
             tracing::info!("{}: TCP server listening on {}", self.name, bind_address);
             
-            // Accept one connection (P2P)
-            let (stream, addr) = listener.accept().await?;
-            tracing::info!("{}: Accepted TCP connection from {}", self.name, addr);
-            
-            self.stream = Some(stream);
-            Ok(())
+            let acceptor = self.config.tls.as_ref().map(TlsConfig::acceptor).transpose()?;
+            loop {
+                // Accept one connection (P2P)
+                let (stream, addr) = listener.accept().await?;
+                let Some(acceptor) = &acceptor else {
+                    tracing::info!("{}: Accepted TCP connection from {}", self.name, addr);
+                    self.stream = Some(Box::new(stream));
+                    return Ok(());
+                };
+
+                // A client failing the handshake is turned away, and the
+                // server keeps listening
+                match timeout(Duration::from_secs(10), acceptor.accept(stream)).await {
+                    Ok(Ok(stream)) => {
+                        tracing::info!("{}: Accepted TLS connection from {}", self.name, addr);
+                        self.stream = Some(Box::new(stream));
+                        return Ok(());
+                    }
+                    Ok(Err(e)) => tracing::warn!("{}: TLS handshake with {} failed: {}", self.name, addr, e),
+                    Err(_) => tracing::warn!("{}: TLS handshake with {} timed out", self.name, addr),
+                }
+            }
         } else {
             Err(anyhow!("wait_for_client called on client mode"))
         }
@@ -130,24 +368,45 @@ impl TcpConnection {
                     self.wait_for_client().await?;
                 }
             }
+            self.retry.succeeded();
+            self.stats = CompressionStats::default();
         }
         Ok(())
     }
 
     pub fn disconnect(&mut self) {
-        self.stream = None;
+        if self.stream.take().is_some()
+            && self.config.compression != Compression::None
+            && let Some(ratio) = self.stats.ratio()
+        {
+            tracing::info!(
+                "{}: Connection closed after {} bytes ({} compressed, ratio {:.2})",
+                self.name,
+                self.stats.raw_bytes,
+                self.stats.wire_bytes,
+                ratio
+            );
+        }
+    }
+
+    /// Traffic on the current connection.
+    pub fn compression_stats(&self) -> CompressionStats {
+        self.stats
     }
 
     pub async fn send_message_with_length_prefix(&mut self, message: &[u8]) -> anyhow::Result<()> {
         if let Some(ref mut stream) = self.stream {
+            let frame = self.config.compression.compress(message)?;
+
             // Send 4-byte length prefix (big-endian)
-            let length = message.len() as u32;
+            let length = frame.len() as u32;
             let length_bytes = length.to_be_bytes();
             
             stream.write_all(&length_bytes).await?;
-            stream.write_all(message).await?;
+            stream.write_all(&frame).await?;
             stream.flush().await?;
             
+            self.stats.add(message.len(), frame.len());
             Ok(())
         } else {
             Err(anyhow!("No TCP connection available"))
@@ -167,7 +426,9 @@ impl TcpConnection {
             let mut message_buf = vec![0u8; message_length];
             stream.read_exact(&mut message_buf).await?;
             
-            Ok(message_buf)
+            let message = self.config.compression.decompress(message_buf)?;
+            self.stats.add(message.len(), message_length);
+            Ok(message)
         } else {
             Err(anyhow!("No TCP connection available"))
         }
@@ -177,7 +438,115 @@ impl TcpConnection {
         self.config.reconnect
     }
 
-    pub fn reconnect_interval(&self) -> u64 {
-        self.config.reconnect_interval_ms
+    /// How long to wait before reconnecting after `error`, or `None` if the
+    /// connection should not be retried.
+    pub fn retry_delay(&mut self, error: &anyhow::Error) -> Option<Duration> {
+        if !self.config.reconnect {
+            return None;
+        }
+        self.retry.failed(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compressed_frames_round_trip() {
+        let message = serde_json::to_vec(&serde_json::json!({
+            "readings": vec![serde_json::json!({"sensor": "temperature", "value": 21.5}); 32]
+        }))
+        .unwrap();
+
+        for compression in [Compression::Zlib { level: 6 }, Compression::Zstd { level: 3 }, Compression::Lz4] {
+            let frame = compression.compress(&message).unwrap();
+            assert!(frame.len() < message.len(), "{:?}", compression);
+            assert_eq!(compression.decompress(frame.clone()).unwrap(), message, "{:?}", compression);
+            assert!(compression.decompress(message.clone()).is_err(), "{:?}", compression);
+        }
+        assert_eq!(Compression::None.compress(&message).unwrap(), message);
+
+        // An lz4 frame declaring more than the limit is refused before allocating
+        let mut bomb = (MAX_DECOMPRESSED_BYTES as u32 + 1).to_le_bytes().to_vec();
+        bomb.extend_from_slice(&[0; 8]);
+        assert!(Compression::Lz4.decompress(bomb).is_err());
+
+        let frame = Compression::Zlib { level: 6 }.compress(&message).unwrap();
+
+        let mut stats = CompressionStats::default();
+        assert_eq!(stats.ratio(), None);
+        stats.add(message.len(), frame.len());
+        assert!(stats.ratio().unwrap() > 1.0);
+    }
+
+    fn fixture(name: &str) -> Option<String> {
+        Some(format!("{}/tests/fixtures/tls/{}", env!("CARGO_MANIFEST_DIR"), name))
+    }
+
+    fn config(mode: TcpMode, tls: Option<TlsConfig>) -> TcpConfig {
+        TcpConfig {
+            mode,
+            reconnect: false,
+            reconnect_interval_ms: 100,
+            compression: Compression::Zlib { level: 6 },
+            tls,
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_preflight_fails_when_nothing_listens() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let client = config(TcpMode::Client { host: "127.0.0.1".to_string(), port }, None);
+        client.preflight().await.unwrap();
+
+        drop(listener);
+        let error = client.preflight().await.unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<LiminalError>(),
+            Some(LiminalError::ConnectionError { endpoint, .. }) if *endpoint == format!("127.0.0.1:{}", port)
+        ));
+        let server = config(TcpMode::Server { host: "127.0.0.1".to_string(), port }, None);
+        server.preflight().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_mutual_tls_turns_away_clients_without_certificates() {
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let server_tls = TlsConfig {
+            cert_file: fixture("server.pem"),
+            key_file: fixture("server.key"),
+            ca_file: fixture("ca.pem"),
+            server_name: None,
+        };
+        let server = config(TcpMode::Server { host: "127.0.0.1".to_string(), port }, Some(server_tls));
+        server.validate().unwrap();
+        let server = tokio::spawn(async move {
+            let mut connection = TcpConnection::new("server".to_string(), server);
+            connection.ensure_connection().await.unwrap();
+            connection.receive_message_with_length_prefix().await.unwrap()
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let client = |cert_file, key_file| {
+            let tls = TlsConfig { cert_file, key_file, ca_file: fixture("ca.pem"), server_name: None };
+            let config = config(TcpMode::Client { host: "localhost".to_string(), port }, Some(tls));
+            config.validate().unwrap();
+            TcpConnection::new("client".to_string(), config)
+        };
+
+        // Without a client certificate the server rejects the handshake
+        let mut anonymous = client(None, None);
+        if anonymous.ensure_connection().await.is_ok() {
+            let _ = anonymous.send_message_with_length_prefix(b"anonymous").await;
+        }
+
+        let mut trusted = client(fixture("client.pem"), fixture("client.key"));
+        trusted.ensure_connection().await.unwrap();
+        trusted.send_message_with_length_prefix(b"trusted").await.unwrap();
+        assert_eq!(server.await.unwrap(), b"trusted");
+        assert!(trusted.compression_stats().wire_bytes > 0);
     }
 }
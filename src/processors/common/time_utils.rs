@@ -0,0 +1,170 @@
+use chrono::{DateTime, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde::Deserialize;
+
+/// Unit of a numeric epoch timestamp
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EpochUnit {
+    /// Guess the unit from the magnitude of the value
+    Auto,
+    #[serde(alias = "s")]
+    Seconds,
+    #[serde(alias = "ms")]
+    Millis,
+    #[serde(alias = "us")]
+    Micros,
+    #[serde(alias = "ns")]
+    Nanos,
+}
+
+/// Utility functions for parsing and formatting timestamps
+pub struct TimeUtils;
+
+impl TimeUtils {
+    /// Parse a timestamp string into UTC
+    ///
+    /// Tries, in order: RFC 3339 / ISO 8601 (with or without offset), RFC 2822,
+    /// each of the custom strftime `formats`, and finally a numeric epoch string
+    /// with unit autodetection. Timestamps without an offset are interpreted in
+    /// `timezone`.
+    ///
+    /// # Arguments
+    /// * `input` - The timestamp string
+    /// * `formats` - Additional strftime patterns, e.g. `"%d/%m/%Y %H:%M:%S"`
+    /// * `timezone` - Zone used for timestamps that carry no offset
+    pub fn parse(input: &str, formats: &[String], timezone: Tz) -> Option<DateTime<Utc>> {
+        let input = input.trim();
+
+        if let Ok(dt) = DateTime::parse_from_rfc3339(input) {
+            return Some(dt.with_timezone(&Utc));
+        }
+        if let Some(dt) = Self::parse_naive_iso(input) {
+            return Self::localise(dt, timezone);
+        }
+        if let Ok(dt) = DateTime::parse_from_rfc2822(input) {
+            return Some(dt.with_timezone(&Utc));
+        }
+
+        for format in formats {
+            if let Ok(dt) = DateTime::parse_from_str(input, format) {
+                return Some(dt.with_timezone(&Utc));
+            }
+            if let Ok(dt) = NaiveDateTime::parse_from_str(input, format) {
+                return Self::localise(dt, timezone);
+            }
+            if let Ok(date) = NaiveDate::parse_from_str(input, format) {
+                return Self::localise(date.and_hms_opt(0, 0, 0)?, timezone);
+            }
+        }
+
+        input
+            .parse::<f64>()
+            .ok()
+            .and_then(|value| Self::from_epoch(value, EpochUnit::Auto))
+    }
+
+    /// Parse an ISO 8601 timestamp, assuming UTC when no offset is given
+    pub fn parse_iso8601(input: &str) -> Option<DateTime<Utc>> {
+        let input = input.trim();
+        DateTime::parse_from_rfc3339(input)
+            .map(|dt| dt.with_timezone(&Utc))
+            .ok()
+            .or_else(|| Self::parse_naive_iso(input).map(|dt| dt.and_utc()))
+    }
+
+    /// Convert a numeric epoch value into UTC
+    ///
+    /// With [`EpochUnit::Auto`] the unit is inferred from magnitude: values below
+    /// 1e11 are seconds, below 1e14 milliseconds, below 1e17 microseconds, and
+    /// anything larger nanoseconds.
+    pub fn from_epoch(value: f64, unit: EpochUnit) -> Option<DateTime<Utc>> {
+        if !value.is_finite() {
+            return None;
+        }
+
+        let unit = match unit {
+            EpochUnit::Auto => Self::detect_unit(value),
+            unit => unit,
+        };
+
+        let nanos = match unit {
+            EpochUnit::Seconds => value * 1e9,
+            EpochUnit::Millis => value * 1e6,
+            EpochUnit::Micros => value * 1e3,
+            EpochUnit::Nanos | EpochUnit::Auto => value,
+        };
+
+        if nanos.abs() >= i64::MAX as f64 {
+            return None;
+        }
+        Some(DateTime::from_timestamp_nanos(nanos as i64))
+    }
+
+    /// Render a UTC timestamp with a strftime pattern in the given timezone.
+    /// The special pattern `"rfc3339"` produces an RFC 3339 string.
+    pub fn format(dt: DateTime<Utc>, format: &str, timezone: Tz) -> String {
+        let local = dt.with_timezone(&timezone);
+        match format {
+            "rfc3339" => local.to_rfc3339(),
+            "rfc2822" => local.to_rfc2822(),
+            _ => local.format(format).to_string(),
+        }
+    }
+
+    fn detect_unit(value: f64) -> EpochUnit {
+        match value.abs() {
+            v if v < 1e11 => EpochUnit::Seconds,
+            v if v < 1e14 => EpochUnit::Millis,
+            v if v < 1e17 => EpochUnit::Micros,
+            _ => EpochUnit::Nanos,
+        }
+    }
+
+    fn parse_naive_iso(input: &str) -> Option<NaiveDateTime> {
+        const FORMATS: [&str; 4] = [
+            "%Y-%m-%dT%H:%M:%S%.f",
+            "%Y-%m-%d %H:%M:%S%.f",
+            "%Y-%m-%dT%H:%M",
+            "%Y-%m-%d %H:%M",
+        ];
+
+        FORMATS
+            .iter()
+            .find_map(|format| NaiveDateTime::parse_from_str(input, format).ok())
+            .or_else(|| {
+                NaiveDate::parse_from_str(input, "%Y-%m-%d")
+                    .ok()
+                    .and_then(|date| date.and_hms_opt(0, 0, 0))
+            })
+    }
+
+    fn localise(dt: NaiveDateTime, timezone: Tz) -> Option<DateTime<Utc>> {
+        // Ambiguous local times (DST fall-back) resolve to the earlier instant
+        timezone
+            .from_local_datetime(&dt)
+            .earliest()
+            .map(|local| local.with_timezone(&Utc))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_formats_agree() {
+        let expected = Utc.with_ymd_and_hms(2024, 3, 1, 12, 30, 0).unwrap();
+
+        assert_eq!(TimeUtils::parse("2024-03-01T12:30:00Z", &[], Tz::UTC), Some(expected));
+        assert_eq!(TimeUtils::parse("2024-03-01T14:30:00+02:00", &[], Tz::UTC), Some(expected));
+        assert_eq!(TimeUtils::parse("Fri, 01 Mar 2024 12:30:00 +0000", &[], Tz::UTC), Some(expected));
+        assert_eq!(
+            TimeUtils::parse("01/03/2024 13:30", &["%d/%m/%Y %H:%M".to_string()], Tz::Europe__Malta),
+            Some(expected)
+        );
+        assert_eq!(TimeUtils::parse("1709296200", &[], Tz::UTC), Some(expected));
+        assert_eq!(TimeUtils::parse("1709296200000", &[], Tz::UTC), Some(expected));
+        assert_eq!(TimeUtils::parse("1709296200000000", &[], Tz::UTC), Some(expected));
+    }
+}
@@ -0,0 +1,156 @@
+//! TLS
+//!
+//! TLS for processors that open their own connections, configured per stage
+//! through a `tls` parameter table:
+//!
+//! ```toml
+//! parameters = { mode = "client", host = "gateway", port = 9001, tls = { ca_file = "certs/ca.pem", cert_file = "certs/edge.pem", key_file = "certs/edge.key" } }
+//! ```
+//!
+//! Clients verify the server against `ca_file`, or the system's trusted roots
+//! if it is not set, and present `cert_file`/`key_file` if the server asks for
+//! a client certificate. Servers present `cert_file`/`key_file`, and with a
+//! `ca_file` require every client to present a certificate signed by it.
+//!
+//! [`acceptor`] also secures the admin server, as its `[security]` section
+//! configures.
+
+use crate::config::types::ClientAuth;
+use crate::config::{ParamSpec, ParamType, extract_param};
+use super::credentials;
+
+use anyhow::{Result, anyhow};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::Arc;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use tokio_rustls::rustls::server::WebPkiClientVerifier;
+use tokio_rustls::rustls::{ClientConfig, RootCertStore, ServerConfig};
+use tokio_rustls::{TlsAcceptor, TlsConnector};
+
+/// The `tls` parameter, accepted by every processor that supports TLS.
+pub const TLS_PARAMS: &[ParamSpec] = &[ParamSpec::new(
+    "tls",
+    ParamType::Object,
+    "TLS settings: cert_file, key_file, ca_file, server_name",
+)];
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TlsConfig {
+    /// Certificate chain (PEM) presented to the peer
+    pub cert_file: Option<String>,
+    /// Private key (PEM) of `cert_file`
+    pub key_file: Option<String>,
+    /// CA certificates (PEM) the peer is verified against
+    pub ca_file: Option<String>,
+    /// Name the server is verified under (and sent as SNI), if not the host
+    pub server_name: Option<String>,
+}
+
+impl TlsConfig {
+    /// Read the `tls` parameter table, or `None` for plaintext.
+    pub fn from_parameters(parameters: &Option<HashMap<String, serde_json::Value>>) -> Option<Self> {
+        let tls: Option<HashMap<String, serde_json::Value>> = extract_param(parameters, "tls", None);
+        tls.as_ref()?;
+        // A client certificate may come from the stage's credentials
+        let credentials = credentials(parameters);
+        Some(Self {
+            cert_file: extract_param(&tls, "cert_file", credentials.cert_file),
+            key_file: extract_param(&tls, "key_file", credentials.key_file),
+            ca_file: extract_param(&tls, "ca_file", None),
+            server_name: extract_param(&tls, "server_name", None),
+        })
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.cert_file.is_some() != self.key_file.is_some() {
+            return Err(anyhow!("tls.cert_file and tls.key_file must be set together"));
+        }
+        Ok(())
+    }
+
+    /// Connector for client connections.
+    pub fn connector(&self) -> Result<TlsConnector> {
+        let roots = match &self.ca_file {
+            Some(ca_file) => root_store(load_certs(ca_file)?)?,
+            None => {
+                let certs = rustls_native_certs::load_native_certs()
+                    .map_err(|e| anyhow!("Failed to load system root certificates: {}", e))?;
+                root_store(certs)?
+            }
+        };
+        let builder = ClientConfig::builder().with_root_certificates(roots);
+        let config = match (&self.cert_file, &self.key_file) {
+            (Some(cert_file), Some(key_file)) => builder
+                .with_client_auth_cert(load_certs(cert_file)?, load_key(key_file)?)
+                .map_err(|e| anyhow!("Invalid TLS client certificate: {}", e))?,
+            _ => builder.with_no_client_auth(),
+        };
+        Ok(TlsConnector::from(Arc::new(config)))
+    }
+
+    /// Acceptor for server connections.
+    pub fn acceptor(&self) -> Result<TlsAcceptor> {
+        let (Some(cert_file), Some(key_file)) = (&self.cert_file, &self.key_file) else {
+            return Err(anyhow!("TLS servers need tls.cert_file and tls.key_file"));
+        };
+        let client_auth = if self.ca_file.is_some() { ClientAuth::Required } else { ClientAuth::None };
+        acceptor(cert_file, key_file, self.ca_file.as_deref(), client_auth)
+    }
+
+    /// Name to verify a server connected to as `host` under.
+    pub fn server_name(&self, host: &str) -> Result<ServerName<'static>> {
+        let name = self.server_name.as_deref().unwrap_or(host).to_string();
+        ServerName::try_from(name.clone()).map_err(|_| anyhow!("Invalid TLS server name '{}'", name))
+    }
+}
+
+/// Acceptor for a server presenting `cert_file`, verifying client
+/// certificates against `client_ca_file` as `client_auth` asks.
+pub fn acceptor(cert_file: &str, key_file: &str, client_ca_file: Option<&str>, client_auth: ClientAuth) -> Result<TlsAcceptor> {
+    let builder = match (client_auth, client_ca_file) {
+        (ClientAuth::None, _) => ServerConfig::builder().with_no_client_auth(),
+        (_, None) => return Err(anyhow!("Verifying TLS client certificates needs a client CA file")),
+        (client_auth, Some(ca_file)) => {
+            let roots = root_store(load_certs(ca_file)?)?;
+            let builder = WebPkiClientVerifier::builder(Arc::new(roots));
+            let builder = if client_auth == ClientAuth::Optional { builder.allow_unauthenticated() } else { builder };
+            let verifier = builder
+                .build()
+                .map_err(|e| anyhow!("Invalid TLS client CA '{}': {}", ca_file, e))?;
+            ServerConfig::builder().with_client_cert_verifier(verifier)
+        }
+    };
+    let config = builder
+        .with_single_cert(load_certs(cert_file)?, load_key(key_file)?)
+        .map_err(|e| anyhow!("Invalid TLS server certificate: {}", e))?;
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+fn root_store(certs: Vec<CertificateDer<'static>>) -> Result<RootCertStore> {
+    let mut roots = RootCertStore::empty();
+    let (added, _) = roots.add_parsable_certificates(certs);
+    if added == 0 {
+        return Err(anyhow!("No usable CA certificates found"));
+    }
+    Ok(roots)
+}
+
+fn load_certs(path: &str) -> Result<Vec<CertificateDer<'static>>> {
+    let file = File::open(path).map_err(|e| anyhow!("Failed to open certificate file '{}': {}", path, e))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<std::io::Result<Vec<_>>>()
+        .map_err(|e| anyhow!("Failed to read certificate file '{}': {}", path, e))?;
+    if certs.is_empty() {
+        return Err(anyhow!("No certificates found in '{}'", path));
+    }
+    Ok(certs)
+}
+
+fn load_key(path: &str) -> Result<PrivateKeyDer<'static>> {
+    let file = File::open(path).map_err(|e| anyhow!("Failed to open key file '{}': {}", path, e))?;
+    rustls_pemfile::private_key(&mut BufReader::new(file))
+        .map_err(|e| anyhow!("Failed to read key file '{}': {}", path, e))?
+        .ok_or_else(|| anyhow!("No private key found in '{}'", path))
+}
@@ -0,0 +1,67 @@
+//! Event-time sliding window shared by the windowed aggregators.
+
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::time::{Duration, SystemTime};
+
+/// Samples stamped with their event time, retaining only the last `span` of
+/// event time. Samples are expected in roughly ascending time order; eviction
+/// stops at the first sample still inside the window.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SlidingWindow<T> {
+    span: Duration,
+    samples: VecDeque<(SystemTime, T)>,
+}
+
+impl<T> SlidingWindow<T> {
+    pub fn new(span: Duration) -> Self {
+        Self {
+            span,
+            samples: VecDeque::new(),
+        }
+    }
+
+    /// Change the window span, e.g. after restoring a window created under a
+    /// different configuration. Takes effect at the next eviction.
+    pub fn set_span(&mut self, span: Duration) {
+        self.span = span;
+    }
+
+    pub fn push(&mut self, time: SystemTime, value: T) {
+        self.samples.push_back((time, value));
+    }
+
+    /// Drop samples that fell out of the window ending at `now`.
+    pub fn evict(&mut self, now: SystemTime) {
+        let Some(cutoff) = now.checked_sub(self.span) else {
+            return;
+        };
+        while let Some((time, _)) = self.samples.front() {
+            if *time >= cutoff {
+                break;
+            }
+            self.samples.pop_front();
+        }
+    }
+
+    /// Drop the oldest sample, used to cap window size.
+    pub fn pop_front(&mut self) -> Option<T> {
+        self.samples.pop_front().map(|(_, value)| value)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &T> {
+        self.samples.iter().map(|(_, value)| value)
+    }
+
+    pub fn latest(&self) -> Option<&T> {
+        self.samples.back().map(|(_, value)| value)
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+}
@@ -21,7 +21,7 @@
 //! let processor = create_processor("rule", config)?;
 //! 
 //! // Register and create a custom processor
-//! register_processor("custom", Box::new(MyProcessor::new));
+//! register_processor_with_meta(&MyProcessor::METADATA, Box::new(MyProcessor::new));
 //! let custom = create_processor("custom", config)?;
 //! ```
 //! 
@@ -30,35 +30,12 @@
 //! All functions in this module are thread-safe and can be called concurrently
 //! from multiple threads without external synchronisation.
 //!
-//! # Note (TODO):
-//! The factory could be extended to include metadata for each processor, such as
-//! name, description, and required/optional parameters. This would allow for more
-//! comprehensive introspection and documentation of available processors.
-//! ```
-//! pub struct ProcessorMetadata {
-//!     pub name: &'static str,
-//!     pub description: &'static str,
-//!     pub required_params: &'static [&'static str],
-//!     pub optional_params: &'static [&'static str],
-//! }
-//! 
-//! type ProcessorConstructorWithMeta = (
-//!     ProcessorMetadata,
-//!     Box<dyn Fn(&str, StageConfig) -> anyhow::Result<Box<dyn Processor>> + Send + Sync>
-//! );
-//! 
-//! // Registration becomes:
-//! register_processor_with_meta(
-//!     "scale",
-//!     ProcessorMetadata {
-//!         name: "scale",
-//!         description: "Scales numeric field values",
-//!         required_params: &["field_in", "field_out"],
-//!         optional_params: &["scale_factor"],
-//!     },
-//!     Box::new(ScaleProcessor::new)
-//! );
-//! ```
+//! # Processor Metadata
+//!
+//! Processors may be registered with a `ProcessorMetadata` describing their
+//! parameters (see `register_processor_with_meta`). Every built-in processor is;
+//! the metadata is used to reject unknown and mistyped parameters before any
+//! stage is created.
 
 use crate::processors::{ 
     Processor,
@@ -66,12 +43,40 @@ use crate::processors::{
         MqttInputProcessor, 
         TcpInputProcessor,
         SimulatedSignalProcessor,
+        ReplayProcessor,
     },
     transform::{
-        RuleProcessor
+        AnomalyProcessor,
+        CalculusProcessor,
+        CepProcessor,
+        ClockSkewProcessor,
+        CorrelateProcessor,
+        CryptoProcessor,
+        DeltaProcessor,
+        ExprProcessor,
+        FlatlineProcessor,
+        GeoProcessor,
+        HysteresisProcessor,
+        LivenessProcessor,
+        MapValuesProcessor,
+        MergeProcessor,
+        OutlierProcessor,
+        QualityProcessor,
+        RbeProcessor,
+        RedactProcessor,
+        ReorderProcessor,
+        RouteProcessor,
+        RuleProcessor,
+        ScaleProcessor,
+        ScriptProcessor,
+        SplitFieldsProcessor,
+        TimeParseProcessor,
     },
     aggregator::{
+        CounterProcessor,
         FusionStage,
+        HistogramProcessor,
+        TopNProcessor,
     },
     output::{
         MqttOutputProcessor,
@@ -81,7 +86,8 @@ use crate::processors::{
     },
 };
 
-use crate::config::StageConfig;
+use crate::config::{ProcessorMetadata, StageConfig};
+use crate::error::LiminalError;
 
 use std::collections::HashMap;
 use std::sync::{Mutex, OnceLock};
@@ -98,7 +104,7 @@ use std::sync::{Mutex, OnceLock};
 /// 
 /// # Returns
 /// - `anyhow::Result<Box<dyn Processor>>`: The created processor or an error
-type ProcessorConstructor = Box<dyn Fn(&str, StageConfig) -> anyhow::Result<Box<dyn Processor>> + Send + Sync>;
+pub type ProcessorConstructor = Box<dyn Fn(&str, StageConfig) -> anyhow::Result<Box<dyn Processor>> + Send + Sync>;
 
 /// Global registry for processor constructors.
 /// 
@@ -120,6 +126,14 @@ fn get_processor_registry() -> &'static Mutex<HashMap<String, ProcessorConstruct
     PROCESSOR_REGISTRY.get_or_init(|| Mutex::new(HashMap::new()))
 }
 
+/// Global registry of processor metadata, keyed like the constructor registry.
+/// Processors registered without metadata have no entry.
+static METADATA_REGISTRY: OnceLock<Mutex<HashMap<String, &'static ProcessorMetadata>>> = OnceLock::new();
+
+fn get_metadata_registry() -> &'static Mutex<HashMap<String, &'static ProcessorMetadata>> {
+    METADATA_REGISTRY.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
 /// Lists all registered processor type names.
 /// 
 /// Returns a vector containing the names of all processor types that are currently
@@ -194,6 +208,32 @@ pub fn processor_exists(name: &str) -> bool {
 pub fn register_processor(name: &str, constructor: ProcessorConstructor) {
     let mut registry = get_processor_registry().lock().unwrap();
     registry.insert(name.to_string(), constructor);
+
+    // Metadata of a replaced processor no longer applies
+    get_metadata_registry().lock().unwrap().remove(name);
+}
+
+/// Registers a processor constructor along with metadata describing it.
+/// 
+/// The processor is registered under `metadata.name`. The metadata is returned
+/// by `processor_metadata()` and used to validate stage parameters.
+/// 
+/// # Example
+/// ```rust
+/// register_processor_with_meta(&DeltaProcessor::METADATA, Box::new(DeltaProcessor::new));
+/// ```
+pub fn register_processor_with_meta(metadata: &'static ProcessorMetadata, constructor: ProcessorConstructor) {
+    register_processor(metadata.name, constructor);
+    get_metadata_registry().lock().unwrap().insert(metadata.name.to_string(), metadata);
+}
+
+/// Returns the metadata of a registered processor type, if it was registered
+/// with any.
+pub fn processor_metadata(name: &str) -> Option<&'static ProcessorMetadata> {
+    ensure_default_processors();
+
+    let registry = get_metadata_registry().lock().unwrap();
+    registry.get(name).copied()
 }
 
 /// Ensures that the default built-in processors are registered.
@@ -205,12 +245,46 @@ pub fn register_processor(name: &str, constructor: ProcessorConstructor) {
 /// 
 /// # Registered Processors
 /// - `"simulated"` - Generates simulated signal data
+/// - `"replay"` - Replays a recorded capture file with its original timing
 /// - `"rule"` - Applies conditional transformations and filtering
-/// - `"fusion"` - Combines data from multiple inputs
+/// - `"calculus"` - Computes derivatives and integrals of numeric fields
+/// - `"fusion"` - Fuses aligned readings from multiple inputs
 /// - `"console"` - Outputs received messages to console
 /// - `"file"` - Outputs received messages to file
 /// - `"mqtt_sub"` - Subscribes to MQTT topics for input
 /// - `"mqtt_pub"` - Publishes messages to MQTT topics
+/// - `"delta"` - Emits differences between consecutive values
+/// - `"hysteresis"` - Debounces numeric signals into stable states
+/// - `"anomaly"` - Detects statistical anomalies (z-score, MAD, EWMA)
+/// - `"outlier"` - Drops, clamps, or tags out-of-range readings
+/// - `"geo"` - Adds distance, geofence membership, and speed from GPS fixes
+/// - `"clock_skew"` - Estimates per-key device clock skew and optionally corrects event times
+/// - `"time_parse"` - Parses timestamps into epoch milliseconds or formats epochs as strings
+/// - `"script"` - Runs a user-supplied Rhai script against each payload
+/// - `"wasm"` - Runs a sandboxed WebAssembly plugin (requires the `wasm` feature)
+/// - `"protobuf_decode"` / `"protobuf_encode"` - Converts binary protobuf payloads to and from JSON (requires the `protobuf` feature)
+/// - `"avro_decode"` / `"avro_encode"` - Converts binary Avro payloads to and from JSON, optionally via a schema registry (requires the `avro` feature)
+/// - `"sparkplug_decode"` - Decodes Sparkplug B payloads to JSON with alias resolution (requires the `sparkplug` feature)
+/// - `"route"` - Routes messages to named side outputs by field value or condition
+/// - `"merge"` - Fans several inputs into one output with optional source tagging and priority
+/// - `"reorder"` - Buffers messages per key and re-emits them in event-time order
+/// - `"topn"` - Periodically ranks the top N keys by a metric over a sliding window
+/// - `"histogram"` - Windowed per-key distribution summaries with quantiles and buckets
+/// - `"cep"` - Detects ordered event sequences per key with time limits and negation
+/// - `"liveness"` - Emits silent/recovered events when keys stop or resume reporting
+/// - `"counter"` - Running per-key totals with reset schedules and optional persistence
+/// - `"crypto"` - Encrypts, decrypts, signs, or verifies payload fields
+/// - `"redact"` - Hashes, truncates, or removes personal data fields
+/// - `"quality"` - Scores messages by missing, stale, out-of-range and flatlined readings
+/// - `"flatline"` - Flags or routes readings of stuck sensors
+/// - `"scale"` - Scales and offsets numeric fields, with per-field factors and rounding
+/// - `"expr"` - Computes fields from expressions, without a rule around them
+/// - `"map_values"` - Maps values through a lookup table or piecewise-linear breakpoints
+/// - `"split_fields"` - Splits payloads into groups of fields published to separate outputs
+/// - `"rbe"` - Forwards messages only when fields change beyond a deadband (report by exception)
+/// - `"correlate"` - Rolling correlation and difference of two measurements
+/// - `"onnx_infer"` - Runs an ONNX model on payload fields (requires the `onnx` feature)
+/// - `"http_call"` - Enriches messages with the response of an HTTP service (requires the `http` feature)
 /// 
 /// # Thread Safety
 /// This function is thread-safe and idempotent - calling it multiple times
@@ -218,15 +292,63 @@ pub fn register_processor(name: &str, constructor: ProcessorConstructor) {
 fn ensure_default_processors() {
     static INITIALIZED: OnceLock<()> = OnceLock::new();
     INITIALIZED.get_or_init(|| {
-        register_processor("mqtt_sub", Box::new(MqttInputProcessor::new));
-        register_processor("mqtt_pub", Box::new(MqttOutputProcessor::new));
-        register_processor("tcp_input", Box::new(TcpInputProcessor::new));
-        register_processor("tcp_output", Box::new(TcpOutputProcessor::new));
-        register_processor("simulated", Box::new(SimulatedSignalProcessor::new));
-        register_processor("rule", Box::new(RuleProcessor::new));
-        register_processor("fusion", Box::new(FusionStage::new));
-        register_processor("console", Box::new(ConsoleOutputProcessor::new));
-        register_processor("file", Box::new(FileOutputProcessor::new));
+        register_processor_with_meta(&MqttInputProcessor::METADATA, Box::new(MqttInputProcessor::new));
+        register_processor_with_meta(&MqttOutputProcessor::METADATA, Box::new(MqttOutputProcessor::new));
+        register_processor_with_meta(&TcpInputProcessor::METADATA, Box::new(TcpInputProcessor::new));
+        register_processor_with_meta(&TcpOutputProcessor::METADATA, Box::new(TcpOutputProcessor::new));
+        register_processor_with_meta(&SimulatedSignalProcessor::METADATA, Box::new(SimulatedSignalProcessor::new));
+        register_processor_with_meta(&ReplayProcessor::METADATA, Box::new(ReplayProcessor::new));
+        register_processor_with_meta(&RuleProcessor::METADATA, Box::new(RuleProcessor::new));
+        register_processor_with_meta(&CalculusProcessor::METADATA, Box::new(CalculusProcessor::new));
+        register_processor_with_meta(&FusionStage::METADATA, Box::new(FusionStage::new));
+        register_processor_with_meta(&ConsoleOutputProcessor::METADATA, Box::new(ConsoleOutputProcessor::new));
+        register_processor_with_meta(&FileOutputProcessor::METADATA, Box::new(FileOutputProcessor::new));
+        register_processor_with_meta(&DeltaProcessor::METADATA, Box::new(DeltaProcessor::new));
+        register_processor_with_meta(&HysteresisProcessor::METADATA, Box::new(HysteresisProcessor::new));
+        register_processor_with_meta(&AnomalyProcessor::METADATA, Box::new(AnomalyProcessor::new));
+        register_processor_with_meta(&OutlierProcessor::METADATA, Box::new(OutlierProcessor::new));
+        register_processor_with_meta(&GeoProcessor::METADATA, Box::new(GeoProcessor::new));
+        register_processor_with_meta(&TimeParseProcessor::METADATA, Box::new(TimeParseProcessor::new));
+        register_processor_with_meta(&ClockSkewProcessor::METADATA, Box::new(ClockSkewProcessor::new));
+        register_processor_with_meta(&ScriptProcessor::METADATA, Box::new(ScriptProcessor::new));
+        #[cfg(feature = "wasm")]
+        register_processor_with_meta(&crate::processors::transform::WasmProcessor::METADATA, Box::new(crate::processors::transform::WasmProcessor::new));
+        #[cfg(feature = "protobuf")]
+        {
+            use crate::processors::transform::ProtobufProcessor;
+            register_processor_with_meta(&ProtobufProcessor::DECODE_METADATA, Box::new(ProtobufProcessor::new_decoder));
+            register_processor_with_meta(&ProtobufProcessor::ENCODE_METADATA, Box::new(ProtobufProcessor::new_encoder));
+        }
+        #[cfg(feature = "avro")]
+        {
+            use crate::processors::transform::AvroProcessor;
+            register_processor_with_meta(&AvroProcessor::DECODE_METADATA, Box::new(AvroProcessor::new_decoder));
+            register_processor_with_meta(&AvroProcessor::ENCODE_METADATA, Box::new(AvroProcessor::new_encoder));
+        }
+        #[cfg(feature = "sparkplug")]
+        register_processor_with_meta(&crate::processors::transform::SparkplugProcessor::METADATA, Box::new(crate::processors::transform::SparkplugProcessor::new));
+        register_processor_with_meta(&RouteProcessor::METADATA, Box::new(RouteProcessor::new));
+        register_processor_with_meta(&MergeProcessor::METADATA, Box::new(MergeProcessor::new));
+        register_processor_with_meta(&ReorderProcessor::METADATA, Box::new(ReorderProcessor::new));
+        register_processor_with_meta(&TopNProcessor::METADATA, Box::new(TopNProcessor::new));
+        register_processor_with_meta(&HistogramProcessor::METADATA, Box::new(HistogramProcessor::new));
+        register_processor_with_meta(&CepProcessor::METADATA, Box::new(CepProcessor::new));
+        register_processor_with_meta(&LivenessProcessor::METADATA, Box::new(LivenessProcessor::new));
+        register_processor_with_meta(&CounterProcessor::METADATA, Box::new(CounterProcessor::new));
+        register_processor_with_meta(&CryptoProcessor::METADATA, Box::new(CryptoProcessor::new));
+        register_processor_with_meta(&RedactProcessor::METADATA, Box::new(RedactProcessor::new));
+        register_processor_with_meta(&QualityProcessor::METADATA, Box::new(QualityProcessor::new));
+        register_processor_with_meta(&FlatlineProcessor::METADATA, Box::new(FlatlineProcessor::new));
+        register_processor_with_meta(&ScaleProcessor::METADATA, Box::new(ScaleProcessor::new));
+        register_processor_with_meta(&ExprProcessor::METADATA, Box::new(ExprProcessor::new));
+        register_processor_with_meta(&MapValuesProcessor::METADATA, Box::new(MapValuesProcessor::new));
+        register_processor_with_meta(&SplitFieldsProcessor::METADATA, Box::new(SplitFieldsProcessor::new));
+        register_processor_with_meta(&RbeProcessor::METADATA, Box::new(RbeProcessor::new));
+        register_processor_with_meta(&CorrelateProcessor::METADATA, Box::new(CorrelateProcessor::new));
+        #[cfg(feature = "onnx")]
+        register_processor_with_meta(&crate::processors::transform::OnnxProcessor::METADATA, Box::new(crate::processors::transform::OnnxProcessor::new));
+        #[cfg(feature = "http")]
+        register_processor_with_meta(&crate::processors::transform::HttpCallProcessor::METADATA, Box::new(crate::processors::transform::HttpCallProcessor::new));
 
         tracing::info!("Default processors registered!");
     });
@@ -273,7 +395,10 @@ fn ensure_default_processors() {
 /// # Error Handling
 /// This function can fail in several ways:
 /// - **Unknown processor type**: The requested type is not registered
+///   (`LiminalError::UnknownProcessor`, naming the closest registered type)
 /// - **Configuration errors**: Invalid or missing required parameters
+///   (`LiminalError::InvalidParameter` and `LiminalError::MissingParameter`
+///   where the processor raises them)
 /// - **Resource errors**: Insufficient resources or system constraints
 /// 
 /// # Thread Safety
@@ -285,8 +410,9 @@ pub fn create_processor(name: &str, config: StageConfig) -> anyhow::Result<Box<d
 
     let registry = get_processor_registry().lock().unwrap();
 
-    registry
-        .get(name)
-        .ok_or_else(|| anyhow::anyhow!("Processor '{}' not found", name))
-        .and_then(|constructor| constructor(name, config))
+    let Some(constructor) = registry.get(name) else {
+        let suggestion = crate::config::params::closest(name, registry.keys().map(String::as_str)).map(str::to_string);
+        return Err(LiminalError::UnknownProcessor { stage: String::new(), name: name.to_string(), suggestion }.into());
+    };
+    constructor(name, config)
 }
\ No newline at end of file
@@ -0,0 +1,361 @@
+//! MQTT Device Discovery
+//!
+//! Turns the topics and payloads of self-describing devices into flat,
+//! per-device readings for `mqtt_sub` with `discovery` set.
+//!
+//! - **Home Assistant**: retained entity configs on
+//!   `<prefix>/<component>/[<node_id>/]<object_id>/config` name each entity's
+//!   state topic, value template and device. State topics are subscribed to
+//!   as their configs arrive, and each state message becomes one reading with
+//!   a field for every entity published on that topic (as Zigbee2MQTT does
+//!   with one JSON state per device).
+//! - **Tasmota**: `tele/<topic>/SENSOR` and `tele/<topic>/STATE` telemetry is
+//!   flattened to snake_case fields (`{"AM2301": {"Temperature": 21.3}}`
+//!   becomes `am2301_temperature`), and `tele/<topic>/LWT` becomes an
+//!   `online` flag. Devices are named by their Tasmota discovery config
+//!   (`tasmota/discovery/<mac>/config`), or by their topic.
+
+use anyhow::{Result, anyhow};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+/// Payload field naming the device a reading came from
+pub const DEVICE_FIELD: &str = "device";
+
+/// Discovery conventions understood by `mqtt_sub`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiscoveryScheme {
+    HomeAssistant,
+    Tasmota,
+}
+
+impl DiscoveryScheme {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value {
+            "homeassistant" => Ok(Self::HomeAssistant),
+            "tasmota" => Ok(Self::Tasmota),
+            other => Err(anyhow!("Unknown discovery '{}' (expected homeassistant or tasmota)", other)),
+        }
+    }
+}
+
+/// What a message on a discovery-related topic amounts to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Discovered {
+    /// A device description, with any state topics to subscribe to
+    Config(Vec<String>),
+    /// A device reading to publish in place of the raw payload
+    Reading(Value),
+    /// Not a discovery topic: the message is handled as usual
+    Unrelated,
+}
+
+/// A Home Assistant entity publishing on a state topic.
+#[derive(Debug, Clone, PartialEq)]
+struct Entity {
+    device: String,
+    field: String,
+    /// Payload path of the value (`value_json.a.b`), or the whole payload
+    path: Option<Vec<String>>,
+    /// Payloads meaning on and off, for binary sensors and switches
+    on_off: Option<(String, String)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Discovery {
+    schemes: Vec<DiscoveryScheme>,
+    prefix: String,
+    /// Home Assistant entities by config topic, and the state topic of each
+    entities: HashMap<String, (String, Entity)>,
+    /// Tasmota device names by topic
+    tasmota_names: HashMap<String, String>,
+}
+
+impl Discovery {
+    pub fn new(schemes: Vec<DiscoveryScheme>, prefix: &str) -> Self {
+        Self {
+            schemes,
+            prefix: prefix.to_string(),
+            entities: HashMap::new(),
+            tasmota_names: HashMap::new(),
+        }
+    }
+
+    /// Topic filters to subscribe to from the start.
+    pub fn filters(&self) -> Vec<String> {
+        let mut filters = Vec::new();
+        for scheme in &self.schemes {
+            match scheme {
+                DiscoveryScheme::HomeAssistant => filters.push(format!("{}/#", self.prefix)),
+                DiscoveryScheme::Tasmota => {
+                    filters.extend(["tasmota/discovery/+/config", "tele/+/SENSOR", "tele/+/STATE", "tele/+/LWT"].map(String::from))
+                }
+            }
+        }
+        filters
+    }
+
+    pub fn handle(&mut self, topic: &str, payload: &Value) -> Discovered {
+        let levels: Vec<&str> = topic.split('/').collect();
+        if self.schemes.contains(&DiscoveryScheme::HomeAssistant) {
+            if levels.first() == Some(&self.prefix.as_str()) && levels.last() == Some(&"config") {
+                return self.home_assistant_config(topic, &levels, payload);
+            }
+            let entities: Vec<&Entity> =
+                self.entities.values().filter(|(state_topic, _)| state_topic == topic).map(|(_, entity)| entity).collect();
+            if !entities.is_empty() {
+                return Discovered::Reading(home_assistant_reading(&entities, payload));
+            }
+            // Anything else under the prefix, such as Home Assistant's own status
+            if levels.first() == Some(&self.prefix.as_str()) {
+                return Discovered::Config(Vec::new());
+            }
+        }
+        if self.schemes.contains(&DiscoveryScheme::Tasmota) {
+            match levels.as_slice() {
+                ["tasmota", "discovery", _, "config"] => {
+                    if let (Some(topic), Some(name)) = (payload["t"].as_str(), payload["dn"].as_str()) {
+                        self.tasmota_names.insert(topic.to_string(), name.to_string());
+                    }
+                    return Discovered::Config(Vec::new());
+                }
+                ["tele", device, kind @ ("SENSOR" | "STATE" | "LWT")] => {
+                    let device = self.tasmota_names.get(*device).map_or(*device, String::as_str);
+                    return Discovered::Reading(tasmota_reading(device, kind, payload));
+                }
+                _ => {}
+            }
+        }
+        Discovered::Unrelated
+    }
+
+    /// Record (or with an empty payload, forget) a Home Assistant entity,
+    /// returning its state topic if no other entity publishes there yet.
+    fn home_assistant_config(&mut self, topic: &str, levels: &[&str], payload: &Value) -> Discovered {
+        let Some(config) = payload.as_object() else {
+            self.entities.remove(topic);
+            return Discovered::Config(Vec::new());
+        };
+        let get = |long: &str, short: &str| config.get(long).or_else(|| config.get(short)).and_then(Value::as_str);
+        let base = config.get("~").and_then(Value::as_str);
+        let Some(state_topic) = get("state_topic", "stat_t").map(|state| expand(state, base)) else {
+            return Discovered::Config(Vec::new());
+        };
+
+        // <prefix>/<component>/[<node_id>/]<object_id>/config
+        let component = levels.get(1).copied().unwrap_or_default();
+        let object_id = levels.get(levels.len().saturating_sub(2)).copied().unwrap_or_default();
+        let node_id = (levels.len() == 5).then(|| levels[2]);
+
+        let device = config
+            .get("device")
+            .or_else(|| config.get("dev"))
+            .and_then(|device| device["name"].as_str())
+            .or(node_id)
+            .unwrap_or(object_id)
+            .to_string();
+        let path = get("value_template", "val_tpl").and_then(template_path);
+        let field = path
+            .as_ref()
+            .and_then(|path| path.last().cloned())
+            .or_else(|| get("device_class", "dev_cla").map(str::to_string))
+            .or_else(|| get("name", "name").map(str::to_string))
+            .unwrap_or_else(|| object_id.to_string());
+        let on_off = matches!(component, "binary_sensor" | "switch" | "light" | "fan").then(|| {
+            (
+                get("payload_on", "pl_on").unwrap_or("ON").to_string(),
+                get("payload_off", "pl_off").unwrap_or("OFF").to_string(),
+            )
+        });
+
+        let known = self.entities.values().any(|(existing, _)| *existing == state_topic);
+        let entity = Entity { device, field: snake_case(&field), path, on_off };
+        self.entities.insert(topic.to_string(), (state_topic.clone(), entity));
+        Discovered::Config(if known { Vec::new() } else { vec![state_topic] })
+    }
+}
+
+/// Substitute the `~` base topic at the start or end of a topic.
+fn expand(topic: &str, base: Option<&str>) -> String {
+    match base {
+        Some(base) if topic.starts_with('~') => format!("{}{}", base, &topic[1..]),
+        Some(base) if topic.ends_with('~') => format!("{}{}", &topic[..topic.len() - 1], base),
+        _ => topic.to_string(),
+    }
+}
+
+/// The payload path of a value template: `{{ value_json.a.b }}`,
+/// `{{ value_json['a'] }}`, with any filters (`| float`) ignored. `{{ value }}`
+/// and templates that do more than select a value use the whole payload.
+fn template_path(template: &str) -> Option<Vec<String>> {
+    let expression = template.trim().strip_prefix("{{")?.strip_suffix("}}")?;
+    let expression = expression.split('|').next()?.trim();
+    let rest = expression.strip_prefix("value_json")?;
+
+    let mut path = Vec::new();
+    let mut rest = rest;
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix('.') {
+            let end = after.find(['.', '[']).unwrap_or(after.len());
+            path.push(after[..end].to_string());
+            rest = &after[end..];
+        } else if let Some(after) = rest.strip_prefix('[') {
+            let end = after.find(']')?;
+            path.push(after[..end].trim_matches(['\'', '"']).to_string());
+            rest = &after[end + 1..];
+        } else {
+            return None;
+        }
+    }
+    (!path.is_empty() && path.iter().all(|key| !key.is_empty())).then_some(path)
+}
+
+fn home_assistant_reading(entities: &[&Entity], payload: &Value) -> Value {
+    let mut reading = Map::new();
+    for entity in entities {
+        reading.entry(DEVICE_FIELD).or_insert_with(|| Value::String(entity.device.clone()));
+        let value = match &entity.path {
+            Some(path) => path.iter().try_fold(payload, |value, key| value.get(key)),
+            // Templates that compute values are not evaluated: take plain payloads only
+            None => (!payload.is_object()).then_some(payload),
+        };
+        let Some(value) = value else { continue };
+        let value = match (&entity.on_off, value.as_str()) {
+            (Some((on, _)), Some(state)) if state == on => Value::Bool(true),
+            (Some((_, off)), Some(state)) if state == off => Value::Bool(false),
+            (_, Some(state)) => match state.parse::<f64>() {
+                Ok(number) if number.is_finite() => Value::from(number),
+                _ => value.clone(),
+            },
+            _ => value.clone(),
+        };
+        reading.insert(entity.field.clone(), value);
+    }
+    Value::Object(reading)
+}
+
+fn tasmota_reading(device: &str, kind: &str, payload: &Value) -> Value {
+    let mut reading = Map::new();
+    reading.insert(DEVICE_FIELD.to_string(), Value::String(device.to_string()));
+    match (kind, payload) {
+        ("LWT", Value::String(state)) => {
+            reading.insert("online".to_string(), Value::Bool(state == "Online"));
+        }
+        (_, Value::Object(fields)) => flatten("", fields, &mut reading),
+        _ => {
+            reading.insert(snake_case(kind), payload.clone());
+        }
+    }
+    Value::Object(reading)
+}
+
+/// Flatten nested objects into `parent_child` snake_case fields.
+fn flatten(prefix: &str, fields: &Map<String, Value>, reading: &mut Map<String, Value>) {
+    for (key, value) in fields {
+        let name = match prefix {
+            "" => snake_case(key),
+            _ => format!("{}_{}", prefix, snake_case(key)),
+        };
+        match value {
+            Value::Object(inner) => flatten(&name, inner, reading),
+            _ => {
+                reading.insert(name, value.clone());
+            }
+        }
+    }
+}
+
+/// `ApparentPower` to `apparent_power`, `DS18B20-1` to `ds18b20_1`,
+/// `Living Room` to `living_room`.
+pub fn snake_case(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let mut snake = String::new();
+    for (index, &c) in chars.iter().enumerate() {
+        if !c.is_alphanumeric() {
+            if !snake.is_empty() && !snake.ends_with('_') {
+                snake.push('_');
+            }
+            continue;
+        }
+        if c.is_uppercase() && index > 0 && !snake.ends_with('_') {
+            let previous = chars[index - 1];
+            let next_lower = chars.get(index + 1).is_some_and(|next| next.is_lowercase());
+            if previous.is_lowercase() || (previous.is_uppercase() && next_lower) {
+                snake.push('_');
+            }
+        }
+        snake.extend(c.to_lowercase());
+    }
+    snake.trim_end_matches('_').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_discovery_names_device_readings() {
+        let mut discovery = Discovery::new(vec![DiscoveryScheme::HomeAssistant, DiscoveryScheme::Tasmota], "homeassistant");
+        assert_eq!(
+            discovery.filters(),
+            ["homeassistant/#", "tasmota/discovery/+/config", "tele/+/SENSOR", "tele/+/STATE", "tele/+/LWT"]
+        );
+
+        // Two entities of one Zigbee2MQTT device share a JSON state topic
+        let temperature = json!({
+            "~": "zigbee2mqtt/Office Sensor", "stat_t": "~", "val_tpl": "{{ value_json.temperature | float }}",
+            "dev": { "name": "Office Sensor" }, "unit_of_meas": "°C",
+        });
+        let occupancy = json!({
+            "state_topic": "zigbee2mqtt/Office Sensor", "value_template": "{{ value_json['occupancy'] }}",
+            "payload_on": true, "device": { "name": "Office Sensor" },
+        });
+        assert_eq!(
+            discovery.handle("homeassistant/sensor/0x1234/temperature/config", &temperature),
+            Discovered::Config(vec!["zigbee2mqtt/Office Sensor".to_string()])
+        );
+        assert_eq!(
+            discovery.handle("homeassistant/binary_sensor/0x1234/occupancy/config", &occupancy),
+            Discovered::Config(Vec::new())
+        );
+        assert_eq!(
+            discovery.handle("zigbee2mqtt/Office Sensor", &json!({ "temperature": 21.5, "occupancy": true, "linkquality": 80 })),
+            Discovered::Reading(json!({ "device": "Office Sensor", "temperature": 21.5, "occupancy": true }))
+        );
+
+        // A plain state topic, named by device class, with a text number
+        let power = json!({ "state_topic": "esphome/pump/sensor/power/state", "device_class": "power" });
+        discovery.handle("homeassistant/sensor/pump/power/config", &power);
+        assert_eq!(
+            discovery.handle("esphome/pump/sensor/power/state", &json!("41.5")),
+            Discovered::Reading(json!({ "device": "pump", "power": 41.5 }))
+        );
+
+        // An empty config removes the entity
+        discovery.handle("homeassistant/sensor/pump/power/config", &json!(""));
+        assert_eq!(discovery.handle("esphome/pump/sensor/power/state", &json!("41.5")), Discovered::Unrelated);
+
+        // Tasmota telemetry is flattened and named by its discovery config
+        discovery.handle("tasmota/discovery/A4CF12/config", &json!({ "t": "tasmota_A4CF12", "dn": "Kitchen Plug" }));
+        assert_eq!(
+            discovery.handle(
+                "tele/tasmota_A4CF12/SENSOR",
+                &json!({ "Time": "2024-05-01T12:00:00", "ENERGY": { "ApparentPower": 40, "Total": 1.25 }, "DS18B20-1": { "Temperature": 22.1 } })
+            ),
+            Discovered::Reading(json!({
+                "device": "Kitchen Plug", "time": "2024-05-01T12:00:00",
+                "energy_apparent_power": 40, "energy_total": 1.25, "ds18b20_1_temperature": 22.1,
+            }))
+        );
+        assert_eq!(
+            discovery.handle("tele/garage/LWT", &json!("Offline")),
+            Discovered::Reading(json!({ "device": "garage", "online": false }))
+        );
+        assert_eq!(discovery.handle("plant/line1/temperature", &json!(20)), Discovered::Unrelated);
+
+        assert_eq!(snake_case("TempUnit"), "temp_unit");
+        assert_eq!(snake_case("HTTPServer"), "http_server");
+        assert_eq!(snake_case("Living Room"), "living_room");
+    }
+}
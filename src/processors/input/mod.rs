@@ -1,7 +1,10 @@
 pub mod simulated;
 pub mod mqtt;
+pub mod discovery;
 pub mod tcp;
+pub mod replay;
 
 pub use simulated::SimulatedSignalProcessor;
 pub use mqtt::MqttInputProcessor;
-pub use tcp::TcpInputProcessor;
\ No newline at end of file
+pub use tcp::TcpInputProcessor;
+pub use replay::ReplayProcessor;
\ No newline at end of file
@@ -1,23 +1,131 @@
+//! MQTT Input Processor
+//!
+//! Subscribes to MQTT topics and publishes each received message to the
+//! stage's output, with the topic it arrived on in its `mqtt_topic` metadata.
+//! With a `topic_map` of topic filters to channels, one broker connection
+//! feeds several channels: a message is published to every channel whose
+//! filter matches its topic, and to the stage's output when none does.
+//!
+//! ```toml
+//! [inputs.plant]
+//! type = "mqtt_sub"
+//! output = "other"
+//! side_outputs = ["temperatures", "alarms"]
+//! parameters = { broker_url = "mqtt://localhost:1883", topic_map = { "plant/+/temperature" = "temperatures", "plant/+/alarm/#" = "alarms" } }
+//! ```
+//!
+//! Wildcards in `topics` and `topic_map` filters can be named, as in
+//! `sensors/+device/+metric` or `plant/#path`, to capture the topic levels
+//! they match into fields of the payload (or into metadata, with
+//! `capture_into = "metadata"`). The broker is subscribed to with the names
+//! removed.
+//!
+//! With `discovery = ["homeassistant", "tasmota"]`, devices that describe
+//! themselves are ingested without listing their topics: see
+//! [`discovery`](super::discovery).
+
 use crate::config::{
-    FieldConfig, ProcessorConfig, StageConfig, extract_field_params, extract_param,
+    FieldConfig, ParamSpec, ParamType, ProcessorConfig, ProcessorMetadata, StageConfig,
+    FIELD_PARAMS, extract_field_params, extract_param,
 };
-use crate::core::context::ProcessingContext;
+use crate::core::context::{OutputInfo, ProcessingContext};
+use crate::core::message::{CONTENT_ENCODING, ENCODING_BASE64, MQTT_TOPIC};
 use crate::core::timing_mixin::{TimingMixin, WithTimingMixin};
 use crate::processors::Processor;
-use crate::processors::common::MqttConnectionConfig;
+use crate::error::LiminalError;
+use crate::processors::input::discovery::{Discovered, Discovery, DiscoveryScheme};
+use crate::processors::common::{CREDENTIALS_PARAMS, MQTT_CONNECTION_PARAMS, MqttConnectionConfig, RETRY_PARAMS, Retry};
 
 use async_trait::async_trait;
 use rumqttc::{AsyncClient, Event, Packet};
 use serde_json::Value;
+use std::collections::{BTreeMap, HashSet};
 use tokio::sync::Mutex;
 use tokio::time::Duration;
 
 use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
 
+/// A topic filter whose wildcards may be named to capture the topic levels
+/// they match: `+name` one level, `#name` the remaining levels.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TopicPattern {
+    /// The filter as subscribed to, with the names removed
+    pub filter: String,
+    /// Capture name of each level of the filter
+    names: Vec<Option<String>>,
+}
+
+impl TopicPattern {
+    pub fn parse(pattern: &str) -> anyhow::Result<Self> {
+        let mut levels = Vec::new();
+        let mut names = Vec::new();
+        let mut seen = HashSet::new();
+        for level in pattern.split('/') {
+            let (wildcard, name) = match level.chars().next() {
+                Some(wildcard @ ('+' | '#')) if level.len() > 1 => (wildcard.to_string(), Some(level[1..].to_string())),
+                _ => (level.to_string(), None),
+            };
+            if let Some(name) = &name
+                && !seen.insert(name.clone())
+            {
+                return Err(anyhow::anyhow!("Topic filter '{}' captures '{}' twice", pattern, name));
+            }
+            levels.push(wildcard);
+            names.push(name);
+        }
+        let filter = levels.join("/");
+        if !rumqttc::valid_filter(&filter) {
+            return Err(anyhow::anyhow!("Invalid topic filter '{}'", pattern));
+        }
+        Ok(Self { filter, names })
+    }
+
+    /// Whether the pattern names any of its wildcards.
+    pub fn captures(&self) -> bool {
+        self.names.iter().any(Option::is_some)
+    }
+
+    /// The topic levels captured by the named wildcards, if `topic` matches.
+    pub fn capture(&self, topic: &str) -> Option<Vec<(String, String)>> {
+        if !rumqttc::matches(topic, &self.filter) {
+            return None;
+        }
+        let levels: Vec<&str> = topic.split('/').collect();
+        let captured = self
+            .names
+            .iter()
+            .enumerate()
+            .filter_map(|(index, name)| {
+                let name = name.as_ref()?;
+                // `#` also matches its parent level, capturing nothing
+                let value = if index + 1 == self.names.len() && self.filter.ends_with('#') {
+                    levels.get(index..).map(|rest| rest.join("/")).unwrap_or_default()
+                } else {
+                    levels.get(index)?.to_string()
+                };
+                Some((name.clone(), value))
+            })
+            .collect();
+        Some(captured)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct MqttInputConfig {
     pub connection: MqttConnectionConfig,
     pub topics: Vec<String>,
+    /// Channel receiving the messages of each topic filter
+    pub topic_map: BTreeMap<String, String>,
+    /// Channels the stage publishes to: its output and side outputs
+    pub outputs: Vec<String>,
+    /// Carry every payload as a base64 string, even if it parses as JSON or text
+    pub binary: bool,
+    /// Write named wildcard captures to metadata rather than the payload
+    pub capture_metadata: bool,
+    /// Device discovery conventions to follow
+    pub discovery: Vec<DiscoveryScheme>,
+    /// Topic prefix of Home Assistant discovery configs
+    pub discovery_prefix: String,
     pub field: FieldConfig,
     pub timing: Option<crate::config::TimingConfig>,
 }
@@ -25,8 +133,30 @@ pub struct MqttInputConfig {
 impl ProcessorConfig for MqttInputConfig {
     fn from_stage_config(config: &StageConfig) -> anyhow::Result<Self> {
         let connection = MqttConnectionConfig::from_parameters(&config.parameters, "liminal");
-        let topics: Vec<String> =
-            extract_param(&config.parameters, "topics", vec!["#".to_string()]);
+        let topic_map: BTreeMap<String, String> =
+            extract_param(&config.parameters, "topic_map", BTreeMap::new());
+
+        let discovery = extract_param(&config.parameters, "discovery", Vec::<String>::new())
+            .iter()
+            .map(|scheme| DiscoveryScheme::parse(scheme))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        // Subscribe to the mapped filters unless told otherwise, and with
+        // discovery only to the topics it finds
+        let default_topics = if !topic_map.is_empty() {
+            topic_map.keys().cloned().collect()
+        } else if discovery.is_empty() {
+            vec!["#".to_string()]
+        } else {
+            Vec::new()
+        };
+        let topics: Vec<String> = extract_param(&config.parameters, "topics", default_topics);
+        let binary = extract_param(&config.parameters, "binary", false);
+        let capture_metadata = match extract_param(&config.parameters, "capture_into", "payload".to_string()).as_str() {
+            "payload" => false,
+            "metadata" => true,
+            other => return Err(anyhow::anyhow!("Unknown capture_into '{}' (expected payload or metadata)", other)),
+        };
 
         // |KB|Todo: Field configuration will be removed. Any payload parameter renaming should be handled by
         // a separate rename processor. Will be changing this to None in the future.
@@ -38,6 +168,12 @@ impl ProcessorConfig for MqttInputConfig {
         Ok(Self {
             connection,
             topics,
+            topic_map,
+            outputs: config.output.iter().chain(config.side_outputs.iter().flatten()).cloned().collect(),
+            binary,
+            capture_metadata,
+            discovery,
+            discovery_prefix: extract_param(&config.parameters, "discovery_prefix", "homeassistant".to_string()),
             field: field_config,
             timing: timing_config,
         })
@@ -45,9 +181,29 @@ impl ProcessorConfig for MqttInputConfig {
 
     fn validate(&self) -> anyhow::Result<()> {
         self.connection.validate()?;
-        if self.topics.is_empty() {
+        if self.topics.is_empty() && self.discovery.is_empty() {
             return Err(anyhow::anyhow!("At least one topic must be specified"));
         }
+        if !self.discovery.is_empty() && self.binary {
+            return Err(anyhow::anyhow!("discovery reads JSON and text payloads, and cannot be combined with binary"));
+        }
+        if self.discovery_prefix.is_empty() || !rumqttc::valid_topic(&self.discovery_prefix) {
+            return Err(anyhow::anyhow!("Invalid discovery_prefix '{}'", self.discovery_prefix));
+        }
+        for topic in &self.topics {
+            TopicPattern::parse(topic)?;
+        }
+        for (filter, channel) in &self.topic_map {
+            TopicPattern::parse(filter).map_err(|e| anyhow::anyhow!("{} in topic_map", e))?;
+            if !self.outputs.contains(channel) {
+                return Err(anyhow::anyhow!(
+                    "topic_map sends '{}' to '{}', which is not an output or side output of this stage (declared: {:?})",
+                    filter,
+                    channel,
+                    self.outputs
+                ));
+            }
+        }
         Ok(())
     }
 }
@@ -58,9 +214,55 @@ pub struct MqttInputProcessor {
     timing: TimingMixin,
     client: Option<AsyncClient>,
     event_loop: Option<Mutex<rumqttc::EventLoop>>,
+    retry: Retry,
+    /// `topic_map` filters and the channel each routes to
+    routes: Vec<(TopicPattern, String)>,
+    /// Patterns with named wildcards, from `topics` and `topic_map`
+    captures: Vec<TopicPattern>,
+    discovery: Option<Discovery>,
+    /// Discovered state topics not yet subscribed to
+    pending_subscriptions: Vec<String>,
 }
 
 impl MqttInputProcessor {
+    pub const METADATA: ProcessorMetadata = ProcessorMetadata {
+        name: "mqtt_sub",
+        description: "Subscribes to MQTT topics for input",
+        parameters: &[
+            ParamSpec::new("topics", ParamType::Array, "Topics to subscribe to, with MQTT wildcards (default: the topic_map filters, or #)"),
+            ParamSpec::new("topic_map", ParamType::Object, "Channel (the output or a side output) for each topic filter"),
+            ParamSpec::new("binary", ParamType::Boolean, "Carry payloads as base64 strings, e.g. for protobuf_decode"),
+            ParamSpec::new("capture_into", ParamType::Choice(&["payload", "metadata"]), "Where named wildcards (+device, #path) write the topic levels they capture"),
+            ParamSpec::new("discovery", ParamType::Array, "Device discovery conventions to follow: homeassistant, tasmota"),
+            ParamSpec::new("discovery_prefix", ParamType::String, "Topic prefix of Home Assistant discovery configs (default: homeassistant)"),
+        ],
+        shared: &[FIELD_PARAMS, MQTT_CONNECTION_PARAMS, RETRY_PARAMS, CREDENTIALS_PARAMS],
+    };
+
+    /// Channels a message received on `topic` is published to: those mapped
+    /// from every matching filter, or else the stage's output.
+    fn route<'a>(&'a self, topic: &str, context: &'a ProcessingContext) -> Vec<&'a OutputInfo> {
+        let mut routes: Vec<&OutputInfo> = Vec::new();
+        for (pattern, channel) in &self.routes {
+            if !rumqttc::matches(topic, &pattern.filter) {
+                continue;
+            }
+            let output = match &context.output {
+                Some(output) if output.name == *channel => Some(output),
+                _ => context.side_outputs.get(channel),
+            };
+            if let Some(output) = output
+                && !routes.iter().any(|route| route.name == output.name)
+            {
+                routes.push(output);
+            }
+        }
+        if routes.is_empty() {
+            routes.extend(context.output.as_ref());
+        }
+        routes
+    }
+
     pub fn new(name: &str, config: StageConfig) -> anyhow::Result<Box<dyn Processor>> {
         let processor_config = MqttInputConfig::from_stage_config(&config)?;
         processor_config.validate()?;
@@ -68,13 +270,48 @@ impl MqttInputProcessor {
         // Create timing mixin from processor configuration
         let timing = TimingMixin::new(processor_config.timing.as_ref());
 
-        Ok(Box::new(Self {
+        Ok(Box::new(Self::with_config(name, processor_config, timing)?))
+    }
+
+    fn with_config(name: &str, config: MqttInputConfig, timing: TimingMixin) -> anyhow::Result<Self> {
+        let routes = config
+            .topic_map
+            .iter()
+            .map(|(filter, channel)| Ok((TopicPattern::parse(filter)?, channel.clone())))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let mut captures: Vec<TopicPattern> = Vec::new();
+        for topic in config.topics.iter().chain(config.topic_map.keys()) {
+            let pattern = TopicPattern::parse(topic)?;
+            if pattern.captures() && !captures.contains(&pattern) {
+                captures.push(pattern);
+            }
+        }
+        let discovery = (!config.discovery.is_empty())
+            .then(|| Discovery::new(config.discovery.clone(), &config.discovery_prefix));
+        Ok(Self {
             name: name.to_string(),
-            config: processor_config,
+            retry: Retry::new(config.connection.retry.clone()),
+            config,
             timing,
             client: None,
             event_loop: None,
-        }))
+            routes,
+            captures,
+            discovery,
+            pending_subscriptions: Vec::new(),
+        })
+    }
+
+    /// Topic levels captured by the named wildcards of every pattern matching
+    /// `topic`, the first pattern capturing a name taking precedence.
+    fn capture(&self, topic: &str) -> Vec<(String, String)> {
+        let mut captured: Vec<(String, String)> = Vec::new();
+        for (name, value) in self.captures.iter().filter_map(|pattern| pattern.capture(topic)).flatten() {
+            if !captured.iter().any(|(existing, _)| *existing == name) {
+                captured.push((name, value));
+            }
+        }
+        captured
     }
 }
 
@@ -84,9 +321,11 @@ impl Processor for MqttInputProcessor {
         let mqttoptions = self.config.connection.create_mqtt_options("liminal")?;
         let (client, eventloop) = AsyncClient::new(mqttoptions, 10);
 
-        for topic in &self.config.topics {
+        let discovery_filters = self.discovery.as_ref().map(Discovery::filters).unwrap_or_default();
+        for topic in self.config.topics.iter().chain(&discovery_filters) {
+            let topic = TopicPattern::parse(topic)?.filter;
             client
-                .subscribe(topic, self.config.connection.qos())
+                .subscribe(&topic, self.config.connection.qos())
                 .await
                 .map_err(|e| anyhow::anyhow!("Failed to subscribe to topic '{}': {}", topic, e))?;
             tracing::info!(
@@ -108,55 +347,120 @@ impl Processor for MqttInputProcessor {
         Ok(())
     }
 
+    async fn preflight(&self) -> anyhow::Result<()> {
+        self.config.connection.preflight("liminal").await
+    }
+
     async fn process(&mut self, context: &mut ProcessingContext) -> anyhow::Result<()> {
+        // Subscribe to discovered topics without waiting on the request queue,
+        // which only drains while the event loop is polled below
+        if let Some(client) = &self.client {
+            while let Some(topic) = self.pending_subscriptions.last() {
+                if client.try_subscribe(topic.as_str(), self.config.connection.qos()).is_err() {
+                    break;
+                }
+                tracing::info!("Subscribed to discovered MQTT topic: {}", topic);
+                self.pending_subscriptions.pop();
+            }
+        }
+
         if let Some(ref event_loop_mutex) = self.event_loop {
             // |KB| Changing logic to poll under the lock but then drop it before
             // any downstram awaits, to avoid convoying stages.
-            let (maybe_topic, maybe_payload_bytes) = {
+            let event_result = {
                 let mut eventloop = event_loop_mutex.lock().await;
 
                 tokio::select! {
-                    event_result = eventloop.poll() => {
-                        match event_result {
-                            Ok(Event::Incoming(Packet::Publish(publish))) => {
-                                (Some(publish.topic.clone()), Some(publish.payload.to_vec()))
-                            }
-                            Ok(_) => (None, None),
-                            Err(e) => {
-                                tracing::error!("MQTT connection error: {}", e);
-                                (None, None)
-                            }
+                    event_result = eventloop.poll() => Some(event_result),
+                    _ = tokio::time::sleep(Duration::from_millis(100)) => None,
+                }
+            };
+
+            let (maybe_topic, maybe_payload_bytes) = match event_result {
+                Some(Ok(Event::Incoming(Packet::Publish(publish)))) => {
+                    self.retry.succeeded();
+                    (Some(publish.topic.clone()), Some(publish.payload.to_vec()))
+                }
+                Some(Ok(_)) => {
+                    self.retry.succeeded();
+                    (None, None)
+                }
+                Some(Err(e)) => {
+                    // The event loop reconnects on the next poll, once the backoff has passed
+                    let error = anyhow::Error::new(e);
+                    let Some(delay) = self.retry.failed(&error) else {
+                        return Err(LiminalError::ConnectionError {
+                            endpoint: self.config.connection.broker_url.clone(),
+                            source: error.context(format!("gave up after {} attempts", self.retry.attempts())),
                         }
-                    }
-                    _ = tokio::time::sleep(Duration::from_millis(100)) => (None, None),
+                        .into());
+                    };
+                    tracing::error!("MQTT connection error, retrying in {:?}: {}", delay, error);
+                    tokio::time::sleep(delay).await;
+                    (None, None)
                 }
+                None => (None, None),
             };
 
             // Process downstream messages, if any
             if let (Some(topic), Some(payload_bytes)) = (maybe_topic, maybe_payload_bytes) {
-                let payload = match serde_json::from_slice::<Value>(&payload_bytes) {
-                    Ok(json_value) => json_value,
-                    Err(_) => match std::str::from_utf8(&payload_bytes) {
-                        Ok(s) => Value::String(s.to_owned()),
-                        Err(_) => Value::String(BASE64.encode(&payload_bytes)),
-                    },
+                // Payloads that are neither JSON nor text are carried as base64
+                let (payload, binary) = if self.config.binary {
+                    (Value::String(BASE64.encode(&payload_bytes)), true)
+                } else if let Ok(json_value) = serde_json::from_slice::<Value>(&payload_bytes) {
+                    (json_value, false)
+                } else if let Ok(s) = std::str::from_utf8(&payload_bytes) {
+                    (Value::String(s.to_owned()), false)
+                } else {
+                    (Value::String(BASE64.encode(&payload_bytes)), true)
                 };
 
                 tracing::debug!("MQTT '{}' payload: {},", topic, payload);
 
-                if let Some(output_info) = &context.output {
-                    // Generate sequence ID and create message with timing semantics
-                    let sequence_id = self.timing.next_sequence_id();
-                    let message = self
-                        .timing
-                        .create_message_with_event_time_extraction(
-                            &self.name,
-                            &output_info.name,
-                            payload,
-                            std::time::SystemTime::now(),
-                        )
-                        .with_sequence_id(sequence_id);
+                // Discovery consumes device descriptions and renames readings
+                let payload = match self.discovery.as_mut().map(|discovery| discovery.handle(&topic, &payload)) {
+                    Some(Discovered::Config(topics)) => {
+                        self.pending_subscriptions.extend(topics);
+                        return Ok(());
+                    }
+                    Some(Discovered::Reading(reading)) => reading,
+                    Some(Discovered::Unrelated) | None => payload,
+                };
 
+                // Named wildcards capture topic levels into the payload, or
+                // into metadata when asked to or the payload is not an object
+                let captured = self.capture(&topic);
+                let mut payload = payload;
+                let mut captured_metadata = Vec::new();
+                match payload.as_object_mut() {
+                    Some(fields) if !self.config.capture_metadata => {
+                        for (name, value) in captured {
+                            fields.insert(name, Value::String(value));
+                        }
+                    }
+                    _ => captured_metadata = captured,
+                }
+
+                // Generate sequence ID and create message with timing semantics
+                let sequence_id = self.timing.next_sequence_id();
+                let message = self
+                    .timing
+                    .create_message_with_event_time_extraction(
+                        &self.name,
+                        "",
+                        payload,
+                        std::time::SystemTime::now(),
+                    )
+                    .with_sequence_id(sequence_id)
+                    .with_metadata(MQTT_TOPIC, topic.as_str());
+                let mut message = if binary { message.with_metadata(CONTENT_ENCODING, ENCODING_BASE64) } else { message };
+                for (name, value) in captured_metadata {
+                    message = message.with_metadata(&name, value);
+                }
+
+                for output_info in self.route(&topic, context) {
+                    let mut message = message.clone();
+                    message.topic = output_info.name.clone();
                     if let Err(e) = output_info.channel.publish(message).await {
                         tracing::warn!("Downstream publish failed: {:?}", e);
                     } else {
@@ -179,3 +483,91 @@ impl WithTimingMixin for MqttInputProcessor {
         &mut self.timing
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::types::ChannelConfig;
+    use crate::core::channel::Channel;
+    use serde_json::json;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_topic_map_routes_topics_to_channels() {
+        let mut stage = StageConfig {
+            r#type: "mqtt_sub".to_string(),
+            output: Some("other".to_string()),
+            side_outputs: Some(vec!["temperatures".to_string(), "alarms".to_string()]),
+            parameters: serde_json::from_value(json!({
+                "broker_url": "mqtt://localhost:1883",
+                "topic_map": { "plant/+/temperature": "temperatures", "plant/#": "other", "plant/+/alarm/#": "alarms" },
+            }))
+            .ok(),
+            ..Default::default()
+        };
+        let config = MqttInputConfig::from_stage_config(&stage).unwrap();
+        config.validate().unwrap();
+        assert_eq!(config.topics, ["plant/#", "plant/+/alarm/#", "plant/+/temperature"]);
+
+        let processor = MqttInputProcessor::with_config("plant", config, TimingMixin::new(None)).unwrap();
+        let mut context = ProcessingContext::new("plant".to_string());
+        for name in ["other", "temperatures", "alarms"] {
+            let channel = Arc::new(Channel::<crate::Message>::open(name, &ChannelConfig::default()).unwrap());
+            if name == "other" {
+                context.attach_output(name.to_string(), channel);
+            } else {
+                context.attach_side_output(name.to_string(), channel);
+            }
+        }
+        let routes = |topic: &str| -> Vec<String> {
+            processor.route(topic, &context).iter().map(|output| output.name.clone()).collect()
+        };
+
+        assert_eq!(routes("plant/line1/temperature"), ["other", "temperatures"]);
+        assert_eq!(routes("plant/line1/alarm/high"), ["other", "alarms"]);
+        assert_eq!(routes("site/status"), ["other"]);
+
+        stage.parameters = serde_json::from_value(json!({
+            "broker_url": "mqtt://localhost:1883",
+            "topic_map": { "plant/#": "missing" },
+        }))
+        .ok();
+        assert!(MqttInputConfig::from_stage_config(&stage).unwrap().validate().is_err());
+    }
+
+    #[test]
+    fn test_named_wildcards_capture_topic_levels() {
+        let pattern = TopicPattern::parse("sensors/+device/+metric").unwrap();
+        assert_eq!(pattern.filter, "sensors/+/+");
+        assert_eq!(
+            pattern.capture("sensors/pump-7/pressure").unwrap(),
+            [("device".to_string(), "pump-7".to_string()), ("metric".to_string(), "pressure".to_string())]
+        );
+        assert!(pattern.capture("sensors/pump-7").is_none());
+
+        let rest = TopicPattern::parse("plant/+/#path").unwrap();
+        assert_eq!(rest.filter, "plant/+/#");
+        assert_eq!(rest.capture("plant/line1/a/b").unwrap(), [("path".to_string(), "a/b".to_string())]);
+        assert_eq!(rest.capture("plant/line1").unwrap(), [("path".to_string(), String::new())]);
+
+        assert!(!TopicPattern::parse("plant/+").unwrap().captures());
+        assert!(TopicPattern::parse("a/+x/+x").is_err());
+        assert!(TopicPattern::parse("a/#rest/b").is_err());
+
+        let stage = StageConfig {
+            r#type: "mqtt_sub".to_string(),
+            output: Some("readings".to_string()),
+            parameters: serde_json::from_value(json!({
+                "broker_url": "mqtt://localhost:1883",
+                "topics": ["sensors/+device/+metric", "sensors/+site/#"],
+            }))
+            .ok(),
+            ..Default::default()
+        };
+        let config = MqttInputConfig::from_stage_config(&stage).unwrap();
+        let processor = MqttInputProcessor::with_config("sensors", config, TimingMixin::new(None)).unwrap();
+        let captured = processor.capture("sensors/pump-7/pressure");
+        assert_eq!(captured.len(), 3);
+        assert!(captured.contains(&("site".to_string(), "pump-7".to_string())));
+    }
+}
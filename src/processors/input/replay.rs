@@ -0,0 +1,279 @@
+//! Replay Input Processor
+//!
+//! Feeds a capture written by `[record]` back into a pipeline, publishing the
+//! recorded messages with their original spacing (or faster, with `speed`).
+//! Messages keep the timing metadata they were recorded with, so event-time
+//! processing downstream sees the incident exactly as it happened.
+//!
+//! ```toml
+//! [inputs.incident]
+//! type = "replay"
+//! output = "raw"
+//! parameters = { file = "captures/capture-20260301T101500.000Z.jsonl", channel = "raw", speed = 10.0 }
+//! ```
+//!
+//! The stage completes once the whole capture has been replayed. In a
+//! back-fill run the stage replays only the messages of the back-fill's range,
+//! sorted by event time and without pauses.
+
+use crate::config::params::extract_param;
+use crate::config::{ParamSpec, ParamType, ProcessorConfig, ProcessorMetadata, StageConfig, require_param};
+use crate::core::backfill::BackfillRange;
+use crate::core::capture::CaptureEntry;
+use crate::core::context::ProcessingContext;
+use crate::processors::Processor;
+use crate::error::LiminalError;
+
+use anyhow::Context;
+use async_trait::async_trait;
+use std::collections::VecDeque;
+use tokio::fs::File;
+use tokio::io::{AsyncBufReadExt, BufReader, Lines};
+use tokio::time::{Duration, Instant};
+
+#[derive(Debug, Clone)]
+pub struct ReplayConfig {
+    /// Capture file to replay
+    pub file: String,
+    /// Replay only the messages recorded from this channel (all when unset)
+    pub channel: Option<String>,
+    /// Playback speed relative to the recording; 0 replays without pauses
+    pub speed: f64,
+}
+
+impl ProcessorConfig for ReplayConfig {
+    fn from_stage_config(config: &StageConfig) -> anyhow::Result<Self> {
+        let file = require_param::<String>(&config.parameters, "file")?;
+        let channel = extract_param(&config.parameters, "channel", None::<String>);
+        let speed = extract_param(&config.parameters, "speed", 1.0);
+
+        let config = Self { file, channel, speed };
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        if !self.speed.is_finite() || self.speed < 0.0 {
+            return Err(LiminalError::invalid_parameter("speed", "must be zero or positive").into());
+        }
+        Ok(())
+    }
+}
+
+pub struct ReplayProcessor {
+    name: String,
+    config: ReplayConfig,
+    lines: Option<Lines<BufReader<File>>>,
+    /// When replay started, and the offset of the first replayed message
+    start: Option<(Instant, u64)>,
+    replayed: u64,
+    /// Range of a back-fill run
+    backfill: Option<BackfillRange>,
+    /// Entries of the back-fill range, in event-time order
+    backlog: VecDeque<CaptureEntry>,
+}
+
+impl ReplayProcessor {
+    pub const METADATA: ProcessorMetadata = ProcessorMetadata {
+        name: "replay",
+        description: "Replays a recorded capture file with its original timing",
+        parameters: &[
+            ParamSpec::new("file", ParamType::String, "Capture file to replay"),
+            ParamSpec::new("channel", ParamType::String, "Replay only messages recorded from this channel"),
+            ParamSpec::new("speed", ParamType::Number, "Playback speed relative to the recording (0 for no pauses)"),
+        ],
+        shared: &[],
+    };
+
+    pub fn new(name: &str, config: StageConfig) -> anyhow::Result<Box<dyn Processor>> {
+        let config = ReplayConfig::from_stage_config(&config)?;
+        Ok(Box::new(Self {
+            name: name.to_string(),
+            config,
+            lines: None,
+            start: None,
+            replayed: 0,
+            backfill: None,
+            backlog: VecDeque::new(),
+        }))
+    }
+
+    /// The next entry of the capture to replay, if any are left.
+    async fn next_entry(&mut self) -> anyhow::Result<Option<CaptureEntry>> {
+        if self.backfill.is_some() {
+            return Ok(self.backlog.pop_front());
+        }
+        self.read_entry().await
+    }
+
+    /// The next entry of the capture file from the selected channel.
+    async fn read_entry(&mut self) -> anyhow::Result<Option<CaptureEntry>> {
+        let Some(lines) = self.lines.as_mut() else {
+            return Ok(None);
+        };
+        while let Some(line) = lines.next_line().await? {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry: CaptureEntry = serde_json::from_str(&line)
+                .with_context(|| format!("Invalid capture entry in '{}'", self.config.file))?;
+            if self.config.channel.as_ref().is_none_or(|channel| *channel == entry.channel) {
+                return Ok(Some(entry));
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[async_trait]
+impl Processor for ReplayProcessor {
+    async fn init(&mut self) -> anyhow::Result<()> {
+        let file = File::open(&self.config.file)
+            .await
+            .with_context(|| format!("Failed to open capture '{}'", self.config.file))?;
+        self.lines = Some(BufReader::new(file).lines());
+
+        if let Some(range) = self.backfill {
+            let mut backlog = Vec::new();
+            while let Some(entry) = self.read_entry().await? {
+                if range.contains(entry.message.timing.event_time) {
+                    backlog.push(entry);
+                }
+            }
+            // A stable sort keeps messages of equal event time in recorded order
+            backlog.sort_by_key(|entry| entry.message.timing.event_time);
+            self.backlog = backlog.into();
+            tracing::info!(
+                "Replay processor '{}' back-filling {} message(s) from '{}'",
+                self.name,
+                self.backlog.len(),
+                self.config.file
+            );
+            return Ok(());
+        }
+        tracing::info!("Replay processor '{}' replaying '{}'", self.name, self.config.file);
+        Ok(())
+    }
+
+    fn backfill(&mut self, range: &BackfillRange) -> anyhow::Result<()> {
+        self.backfill = Some(*range);
+        Ok(())
+    }
+
+    async fn process(&mut self, context: &mut ProcessingContext) -> anyhow::Result<()> {
+        let Some(entry) = self.next_entry().await? else {
+            tracing::info!("Replay processor '{}' replayed {} message(s)", self.name, self.replayed);
+            self.lines = None;
+            context.complete();
+            return Ok(());
+        };
+
+        // Keep the recorded spacing, measured from the first replayed message
+        let (started, first_offset) = *self.start.get_or_insert((Instant::now(), entry.offset_ms));
+        if self.config.speed > 0.0 && self.backfill.is_none() {
+            let elapsed = entry.offset_ms.saturating_sub(first_offset) as f64 / self.config.speed;
+            tokio::time::sleep_until(started + Duration::from_secs_f64(elapsed / 1000.0)).await;
+        }
+
+        let mut message = entry.message;
+        if self.backfill.is_some() {
+            // Sorted by event time, the history is complete up to each message
+            message.timing.watermark = Some(message.timing.event_time);
+        }
+        if let Some(output) = &context.output {
+            message.topic = output.name.clone();
+            self.replayed += 1;
+            let _ = output.channel.publish(message).await;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::message::Message;
+    use crate::testing::{TestContext, assert_field};
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_replay_publishes_entries_of_the_selected_channel() {
+        let path = std::env::temp_dir().join(format!("liminal-replay-{}.jsonl", std::process::id()));
+        let entries = [("raw", 0, 1), ("other", 5, 2), ("raw", 20, 3)].map(|(channel, offset_ms, value)| {
+            serde_json::to_string(&CaptureEntry {
+                channel: channel.to_string(),
+                offset_ms,
+                message: Message::new("sensor", channel, json!({ "value": value })),
+            })
+            .unwrap()
+        });
+        std::fs::write(&path, entries.join("\n")).unwrap();
+
+        let stage = StageConfig {
+            r#type: "replay".to_string(),
+            output: Some("replayed".to_string()),
+            parameters: serde_json::from_value(json!({ "file": path, "channel": "raw" })).ok(),
+            ..Default::default()
+        };
+        let mut processor = ReplayProcessor::new("incident", stage).unwrap();
+        processor.init().await.unwrap();
+
+        let mut test = TestContext::new("incident").output("replayed");
+        let started = Instant::now();
+        let outputs = test.collect_n_outputs(processor.as_mut(), 2, Duration::from_secs(1)).await.unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(20));
+        assert_field(&outputs[0], "value", json!(1));
+        assert_field(&outputs[1], "value", json!(3));
+        assert_eq!(outputs[1].topic, "replayed");
+
+        test.process(processor.as_mut()).await.unwrap();
+        assert!(test.try_output().await.is_none());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_replay_backfills_range_in_event_time_order() {
+        let path = std::env::temp_dir().join(format!("liminal-backfill-{}.jsonl", std::process::id()));
+        let base = std::time::UNIX_EPOCH + Duration::from_secs(1_000);
+        let entries = [(0, 30, 1), (1000, 10, 2), (2000, 50, 3), (3000, 20, 4)].map(|(offset_ms, event_secs, value)| {
+            let mut message = Message::new("sensor", "raw", json!({ "value": value }));
+            message.timing.event_time = base + Duration::from_secs(event_secs);
+            serde_json::to_string(&CaptureEntry {
+                channel: "raw".to_string(),
+                offset_ms,
+                message,
+            })
+            .unwrap()
+        });
+        std::fs::write(&path, entries.join("\n")).unwrap();
+
+        let stage = StageConfig {
+            r#type: "replay".to_string(),
+            output: Some("replayed".to_string()),
+            parameters: serde_json::from_value(json!({ "file": path })).ok(),
+            ..Default::default()
+        };
+        let mut processor = ReplayProcessor::new("history", stage).unwrap();
+        let range = BackfillRange {
+            from: Some(base + Duration::from_secs(10)),
+            until: Some(base + Duration::from_secs(50)),
+        };
+        processor.backfill(&range).unwrap();
+        processor.init().await.unwrap();
+
+        let mut test = TestContext::new("history").output("replayed");
+        let started = Instant::now();
+        let outputs = test.collect_n_outputs(processor.as_mut(), 3, Duration::from_secs(1)).await.unwrap();
+        assert!(started.elapsed() < Duration::from_millis(500));
+        assert_field(&outputs[0], "value", json!(2));
+        assert_field(&outputs[1], "value", json!(4));
+        assert_field(&outputs[2], "value", json!(1));
+        assert_eq!(outputs[2].timing.watermark, Some(base + Duration::from_secs(30)));
+
+        test.process(processor.as_mut()).await.unwrap();
+        assert!(test.try_output().await.is_none());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
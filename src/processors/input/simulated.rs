@@ -1,7 +1,11 @@
 use crate::config::{
-    FieldConfig, ProcessorConfig, StageConfig, extract_field_params, extract_param,
+    FieldConfig, ParamSpec, ParamType, ProcessorConfig, ProcessorMetadata, StageConfig,
+    FIELD_PARAMS, extract_field_params, extract_param,
 };
+use crate::core::batch::RecordBatch;
+use crate::core::checkpoint::Snapshot;
 use crate::core::context::ProcessingContext;
+use crate::core::message::Message;
 use crate::core::timing_mixin::{TimingMixin, WithTimingMixin};
 use crate::processors::Processor;
 
@@ -17,6 +21,11 @@ pub struct SimulatedSignalConfig {
     pub min_value: f64,
     pub max_value: f64,
     pub value_name: String,
+    /// Stop after this many messages in total (unbounded when unset)
+    pub max_messages: Option<u64>,
+    /// Readings published together as one columnar batch (1 publishes each
+    /// as a JSON message)
+    pub batch_size: usize,
     pub field: FieldConfig,
     pub timing: Option<crate::config::TimingConfig>,
 }
@@ -28,6 +37,8 @@ impl ProcessorConfig for SimulatedSignalConfig {
         let distribution = extract_param(&config.parameters, "distribution", "uniform".to_string());
         let min_value = extract_param(&config.parameters, "min_value", 0.0);
         let max_value = extract_param(&config.parameters, "max_value", 100.0);
+        let max_messages = extract_param(&config.parameters, "max_messages", None::<u64>);
+        let batch_size = extract_param(&config.parameters, "batch_size", 1usize);
 
         // Extract field configuration
         let field_config = extract_field_params(&config.parameters);
@@ -46,12 +57,14 @@ impl ProcessorConfig for SimulatedSignalConfig {
             min_value,
             max_value,
             value_name,
+            max_messages,
+            batch_size,
             field: field_config,
             timing: timing_config,
         };
 
         // Validate the configuration
-        if config.validate().is_err() {
+        if config.batch_size == 0 || config.validate().is_err() {
             tracing::warn!(
                 "Invalid configuration for simulated signal stage: {:?}",
                 config
@@ -69,9 +82,27 @@ pub struct SimulatedSignalProcessor {
     name: String,
     config: SimulatedSignalConfig,
     timing: TimingMixin,
+    /// Readings published so far, checked against `max_messages`
+    emitted: u64,
+    /// Readings waiting for a full batch, with `batch_size` above 1
+    pending: Option<RecordBatch>,
 }
 
 impl SimulatedSignalProcessor {
+    pub const METADATA: ProcessorMetadata = ProcessorMetadata {
+        name: "simulated",
+        description: "Generates simulated signal data",
+        parameters: &[
+            ParamSpec::new("interval_ms", ParamType::Integer, "Time between generated messages; 0 generates as fast as the output takes them"),
+            ParamSpec::new("distribution", ParamType::Choice(&["uniform", "normal"]), "Distribution values are drawn from"),
+            ParamSpec::new("min_value", ParamType::Number, "Lower bound of generated values"),
+            ParamSpec::new("max_value", ParamType::Number, "Upper bound of generated values"),
+            ParamSpec::new("max_messages", ParamType::Integer, "Stop after this many messages"),
+            ParamSpec::new("batch_size", ParamType::Integer, "Readings published together as one columnar batch"),
+        ],
+        shared: &[FIELD_PARAMS],
+    };
+
     pub fn new(name: &str, config: StageConfig) -> anyhow::Result<Box<dyn Processor>> {
         let processor_config = SimulatedSignalConfig::from_stage_config(&config)?;
         processor_config.validate()?;
@@ -83,8 +114,25 @@ impl SimulatedSignalProcessor {
             name: name.to_string(),
             config: processor_config,
             timing,
+            emitted: 0,
+            pending: None,
         }))
     }
+
+    /// Publish the readings waiting in a batch, if any.
+    async fn flush(&mut self, context: &ProcessingContext) {
+        let Some(batch) = self.pending.take().filter(|batch| !batch.is_empty()) else {
+            return;
+        };
+        let topic = context.output.as_ref().map_or("simulated", |output| output.name.as_str());
+        self.emitted += batch.len() as u64;
+        let sequence_id = self.timing.next_sequence_id();
+        let message = Message::from_batch(&self.name, topic, batch).with_sequence_id(sequence_id);
+        let message = self.timing.update_message_watermark(message);
+        if let Some(output_info) = &context.output {
+            let _ = output_info.channel.publish(message).await;
+        }
+    }
 }
 
 #[async_trait]
@@ -98,6 +146,14 @@ impl Processor for SimulatedSignalProcessor {
     }
 
     async fn process(&mut self, context: &mut ProcessingContext) -> anyhow::Result<()> {
+        // A bounded simulation ends once it has produced all its messages
+        let generated = self.emitted + self.pending.as_ref().map_or(0, |batch| batch.len() as u64);
+        if self.config.max_messages.is_some_and(|max_messages| generated >= max_messages) {
+            self.flush(context).await;
+            context.complete();
+            return Ok(());
+        }
+
         // Generate a random value based on the specified distribution
         // and send it to the output channel. The rng is dropped before
         // the select statement to avoid blocking the async runtime.
@@ -130,48 +186,85 @@ impl Processor for SimulatedSignalProcessor {
             }
         };
 
-        tokio::select! {
-            _ = tokio::time::sleep(Duration::from_millis(self.config.interval_ms)) => {
-                // Single time capture to ensure consistency
-                let event_time = SystemTime::now();
-
-                let topic = if let Some(output_info) = &context.output {
-                    output_info.name.clone()
-                } else {
-                    "simulated".to_string()
-                };
-
-                // Create payload (field : value)
-                let payload = serde_json::json!({
-                    self.config.value_name.clone(): value
-                });
-
-                // Create message using timing mixin
-                let sequence_id = self.timing.next_sequence_id();
-                let message = self.timing.create_message_with_event_time_extraction(
-                    &self.name,
-                    &topic,
-                    payload,
-                    event_time,
-                ).with_sequence_id(sequence_id);
-
-                tracing::debug!(
-                    "Simulated signal generated: {} = {}, at {:?}, seq: {}, event_time: {:?}",
-                    self.config.value_name,
-                    value,
-                    event_time,
-                    sequence_id,
-                    message.timing.event_time
-                );
-
-                if let Some(output_info) = &context.output {
-                    let _ = output_info.channel.publish(message).await;
-                }
+        // An interval of 0 publishes as fast as the output channel takes messages
+        if self.config.interval_ms > 0 {
+            tokio::time::sleep(Duration::from_millis(self.config.interval_ms)).await;
+        }
+
+        // Single time capture to ensure consistency
+        let event_time = SystemTime::now();
+
+        if self.config.batch_size > 1 {
+            let value_name = self.config.value_name.as_str();
+            let batch = self.pending.get_or_insert_with(|| RecordBatch::new(&[value_name]));
+            batch.push_row(event_time, &[value])?;
+            if batch.len() >= self.config.batch_size {
+                self.flush(context).await;
             }
+            return Ok(());
+        }
+
+        let topic = if let Some(output_info) = &context.output {
+            output_info.name.clone()
+        } else {
+            "simulated".to_string()
+        };
+
+        // Create payload (field : value)
+        let payload = serde_json::json!({
+            self.config.value_name.clone(): value
+        });
+
+        // Create message using timing mixin
+        let sequence_id = self.timing.next_sequence_id();
+        let message = self.timing.create_message_with_event_time_extraction(
+            &self.name,
+            &topic,
+            payload,
+            event_time,
+        ).with_sequence_id(sequence_id);
+
+        tracing::debug!(
+            "Simulated signal generated: {} = {}, at {:?}, seq: {}, event_time: {:?}",
+            self.config.value_name,
+            value,
+            event_time,
+            sequence_id,
+            message.timing.event_time
+        );
+
+        self.emitted += 1;
+        if let Some(output_info) = &context.output {
+            let _ = output_info.channel.publish(message).await;
         }
 
         Ok(())
     }
+
+    fn as_snapshot(&mut self) -> Option<&mut dyn Snapshot> {
+        Some(self)
+    }
+}
+
+/// The source offset of a simulated signal is its message sequence number,
+/// so sequence ids continue across restarts rather than starting over.
+impl Snapshot for SimulatedSignalProcessor {
+    fn snapshot(&self) -> anyhow::Result<serde_json::Value> {
+        Ok(serde_json::json!({
+            "sequence_id": self.timing.current_sequence_id(),
+            "emitted": self.emitted,
+        }))
+    }
+
+    fn restore(&mut self, state: serde_json::Value) -> anyhow::Result<()> {
+        let sequence_id = state["sequence_id"]
+            .as_u64()
+            .ok_or_else(|| anyhow::anyhow!("Checkpoint is missing 'sequence_id'"))?;
+        self.timing.restore_sequence_id(sequence_id);
+        // Checkpoints written before `max_messages` existed have no count
+        self.emitted = state["emitted"].as_u64().unwrap_or(0);
+        Ok(())
+    }
 }
 
 impl WithTimingMixin for SimulatedSignalProcessor {
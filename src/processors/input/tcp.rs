@@ -1,8 +1,9 @@
-use crate::config::{ProcessorConfig, StageConfig};
+use crate::config::{ProcessorConfig, ProcessorMetadata, StageConfig};
 use crate::core::context::ProcessingContext;
 use crate::core::timing_mixin::{TimingMixin, WithTimingMixin};
 use crate::processors::Processor;
-use crate::processors::common::tcp::{TcpConfig, TcpConnection};
+use crate::processors::common::tcp::{TCP_PARAMS, TcpConfig, TcpConnection};
+use crate::processors::common::{CREDENTIALS_PARAMS, RETRY_PARAMS, TLS_PARAMS};
 
 use async_trait::async_trait;
 use std::time::SystemTime;
@@ -33,6 +34,13 @@ pub struct TcpInputProcessor {
 }
 
 impl TcpInputProcessor {
+    pub const METADATA: ProcessorMetadata = ProcessorMetadata {
+        name: "tcp_input",
+        description: "Receives JSON messages over TCP",
+        parameters: &[],
+        shared: &[TCP_PARAMS, RETRY_PARAMS, TLS_PARAMS, CREDENTIALS_PARAMS],
+    };
+
     pub fn new(name: &str, config: StageConfig) -> anyhow::Result<Box<dyn Processor>> {
         let processor_config = TcpInputConfig::from_stage_config(&config)?;
         processor_config.validate()?;
@@ -61,24 +69,19 @@ impl Processor for TcpInputProcessor {
         Ok(())
     }
 
+    async fn preflight(&self) -> anyhow::Result<()> {
+        self.connection.preflight().await
+    }
+
     async fn process(&mut self, context: &mut ProcessingContext) -> anyhow::Result<()> {
         // Ensure we have a connection
         if let Err(e) = self.connection.ensure_connection().await {
-            if self.connection.should_reconnect() {
-                tracing::debug!(
-                    "{}: Connection failed, will retry in {}ms: {}",
-                    self.name,
-                    self.connection.reconnect_interval(),
-                    e
-                );
-                tokio::time::sleep(tokio::time::Duration::from_millis(
-                    self.connection.reconnect_interval(),
-                ))
-                .await;
-                return Ok(());
-            } else {
+            let Some(delay) = self.connection.retry_delay(&e) else {
                 return Err(e);
-            }
+            };
+            tracing::debug!("{}: Connection failed, will retry in {:?}: {}", self.name, delay, e);
+            tokio::time::sleep(delay).await;
+            return Ok(());
         }
 
         // Try to receive a message (non-blocking)
@@ -1,5 +1,6 @@
 pub mod processor;
 pub mod factory;
+pub mod plugin;
 pub mod common;
 
 pub mod input;
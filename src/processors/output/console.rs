@@ -1,4 +1,4 @@
-use crate::config::StageConfig;
+use crate::config::{ProcessorMetadata, StageConfig};
 use crate::core::context::ProcessingContext;
 use crate::processors::Processor;
 
@@ -9,6 +9,13 @@ pub struct ConsoleOutputProcessor {
 }
 
 impl ConsoleOutputProcessor {
+    pub const METADATA: ProcessorMetadata = ProcessorMetadata {
+        name: "console",
+        description: "Outputs received messages to console",
+        parameters: &[],
+        shared: &[],
+    };
+
     pub fn new(name: &str, _config: StageConfig) -> anyhow::Result<Box<dyn Processor>> {
         Ok(Box::new(Self {
             name: name.to_string(),
@@ -29,24 +36,21 @@ impl Processor for ConsoleOutputProcessor {
             return Ok(());
         }
 
-        for (name, input) in context.inputs.iter_mut() {
-            if let Some(message) = input.try_recv().await {
-                tracing::info!(
-                    "'{}' => Message(source: {}, topic: {}, event_time: {:?}, ingestion_time: {:?}, sequence_id: {:?}, payload: {:?})",
-                    name,
-                    message.source,
-                    message.topic,
-                    message.timing.event_time,
-                    message.timing.ingestion_time,
-                    message.timing.sequence_id,
-                    message.payload
-                );
-            }
+        if let Some((name, message)) = context.recv(tokio::time::Duration::from_millis(10)).await {
+            tracing::info!(
+                "'{}' => Message(source: {}, topic: {}, event_time: {:?}, ingestion_time: {:?}, sequence_id: {:?}, metadata: {:?}, payload: {:?})",
+                name,
+                message.source,
+                message.topic,
+                message.timing.event_time,
+                message.timing.ingestion_time,
+                message.timing.sequence_id,
+                message.metadata,
+                message.payload
+            );
+            context.report_delivered(1);
         }
 
-        // Small delay to prevent busy-waiting
-        tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
-
         Ok(())
     }
 }
@@ -3,17 +3,32 @@
 //! Writes messages to files with configurable formatting and rotation options.
 //! Supports JSON, CSV, and plain text output formats with automatic file
 //! creation and directory handling.
+//!
+//! In durable mode, messages are written to numbered segments instead of a
+//! single file, so that a crash never leaves partial or duplicated lines in
+//! the output. A segment is written as `<stem>.<index>.<ext>.tmp`; on rotation
+//! it is fsynced, the offsets it covers are recorded in
+//! `<file_path>.offsets`, and it is renamed to `<stem>.<index>.<ext>`. Only
+//! completed segments carry the final name, and the offsets record (the
+//! highest sequence id written per source) lets the sink skip messages that a
+//! replaying source sends again after a restart. A segment left incomplete by
+//! a crash is discarded on startup, as its messages were never acknowledged
+//! as durable.
 
 use crate::config::params::extract_param;
-use crate::config::{ProcessorConfig, StageConfig};
+use crate::config::{ParamSpec, ParamType, ProcessorConfig, ProcessorMetadata, StageConfig, require_param};
 use crate::core::context::ProcessingContext;
 use crate::processors::Processor;
+use crate::error::LiminalError;
 
+use anyhow::Context;
 use async_trait::async_trait;
-use serde::Deserialize;
-use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use tokio::fs::{File, OpenOptions};
 use tokio::io::{AsyncWriteExt, BufWriter};
+use tokio::time::{Duration, Instant};
 
 /// Output format for file writing.
 #[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
@@ -50,15 +65,20 @@ pub struct FileOutputConfig {
     pub buffer_size: usize,
     /// Whether to flush after each message
     pub auto_flush: bool,
+    /// Write crash-consistent segments instead of a single file
+    pub durable: bool,
+    /// Size in bytes at which a segment is completed (durable mode)
+    pub segment_bytes: u64,
+    /// Age at which a non-empty segment is completed (durable mode)
+    pub segment_ms: Option<u64>,
+    /// Skip messages whose sequence id was already written (durable mode)
+    pub dedupe: bool,
 }
 
 impl ProcessorConfig for FileOutputConfig {
     fn from_stage_config(config: &StageConfig) -> anyhow::Result<Self> {
         // Extract file path (required)
-        let file_path =
-            extract_param(&config.parameters, "file_path", None::<String>).ok_or_else(|| {
-                anyhow::anyhow!("file_path parameter is required for file output processor")
-            })?;
+        let file_path = require_param::<String>(&config.parameters, "file_path")?;
 
         let file_path = PathBuf::from(file_path);
 
@@ -68,6 +88,10 @@ impl ProcessorConfig for FileOutputConfig {
         let create_dirs = extract_param(&config.parameters, "create_dirs", true);
         let buffer_size = extract_param(&config.parameters, "buffer_size", 8192_usize);
         let auto_flush = extract_param(&config.parameters, "auto_flush", false);
+        let durable = extract_param(&config.parameters, "durable", false);
+        let segment_bytes = extract_param(&config.parameters, "segment_bytes", 64 * 1024 * 1024_u64);
+        let segment_ms = extract_param(&config.parameters, "segment_ms", None::<u64>);
+        let dedupe = extract_param(&config.parameters, "dedupe", durable);
 
         let config = Self {
             file_path,
@@ -76,6 +100,10 @@ impl ProcessorConfig for FileOutputConfig {
             create_dirs,
             buffer_size,
             auto_flush,
+            durable,
+            segment_bytes,
+            segment_ms,
+            dedupe,
         };
 
         config.validate()?;
@@ -85,7 +113,17 @@ impl ProcessorConfig for FileOutputConfig {
     fn validate(&self) -> anyhow::Result<()> {
         // Validate file path
         if self.file_path.to_string_lossy().is_empty() {
-            return Err(anyhow::anyhow!("file_path cannot be empty"));
+            return Err(LiminalError::invalid_parameter("file_path", "cannot be empty").into());
+        }
+
+        if self.durable && self.file_path.file_name().is_none() {
+            return Err(LiminalError::invalid_parameter("file_path", "must name a file in durable mode").into());
+        }
+        if self.segment_bytes == 0 || self.segment_ms == Some(0) {
+            return Err(anyhow::anyhow!("segment_bytes and segment_ms must be greater than zero"));
+        }
+        if self.dedupe && !self.durable {
+            return Err(anyhow::anyhow!("dedupe requires durable mode"));
         }
 
         // Validate parent directory if create_dirs is false
@@ -117,6 +155,12 @@ impl ProcessorConfig for FileOutputConfig {
 /// - `create_dirs`: Whether to create parent directories (default: true)
 /// - `buffer_size`: Write buffer size in bytes (default: 8192)
 /// - `auto_flush`: Whether to flush after each message (default: false)
+/// - `durable`: Write crash-consistent segments instead of one file (default: false)
+/// - `segment_bytes`: Segment size at which it is completed (default: 64 MiB)
+/// - `segment_ms`: Segment age at which it is completed (default: none)
+/// - `dedupe`: Skip messages already written, by source and sequence id
+///   (default: `durable`). Only for sources whose sequence ids survive a
+///   restart, such as `replay` or a checkpointed `simulated` source.
 ///
 /// # Example Configuration
 ///
@@ -138,9 +182,51 @@ pub struct FileOutputProcessor {
     config: FileOutputConfig,
     writer: Option<BufWriter<File>>,
     csv_headers_written: bool,
+    /// Segment being written, in durable mode
+    segment: Option<Segment>,
+    /// Last completed segment and the offsets it covers
+    durable: DurableOffsets,
+    /// Highest sequence id written per source, including the open segment
+    offsets: HashMap<String, u64>,
+}
+
+/// The segment a durable file sink is writing.
+#[derive(Debug)]
+struct Segment {
+    index: u64,
+    opened: Instant,
+    bytes: u64,
+    messages: u64,
+}
+
+/// Write-ahead record of a durable file sink, in `<file_path>.offsets`.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+struct DurableOffsets {
+    /// Index of the last completed segment
+    segment: u64,
+    /// Highest sequence id written per source, up to that segment
+    offsets: HashMap<String, u64>,
 }
 
 impl FileOutputProcessor {
+    pub const METADATA: ProcessorMetadata = ProcessorMetadata {
+        name: "file",
+        description: "Outputs received messages to file",
+        parameters: &[
+            ParamSpec::new("file_path", ParamType::String, "File messages are written to"),
+            ParamSpec::new("format", ParamType::Choice(&["json", "csv", "text", "pretty"]), "Output format"),
+            ParamSpec::new("append", ParamType::Boolean, "Append to an existing file instead of truncating it"),
+            ParamSpec::new("create_dirs", ParamType::Boolean, "Create missing parent directories"),
+            ParamSpec::new("buffer_size", ParamType::Integer, "Write buffer size in bytes"),
+            ParamSpec::new("auto_flush", ParamType::Boolean, "Flush after every message"),
+            ParamSpec::new("durable", ParamType::Boolean, "Write crash-consistent segments renamed into place once complete"),
+            ParamSpec::new("segment_bytes", ParamType::Integer, "Segment size in bytes at which it is completed (durable mode)"),
+            ParamSpec::new("segment_ms", ParamType::Integer, "Segment age in milliseconds at which it is completed (durable mode)"),
+            ParamSpec::new("dedupe", ParamType::Boolean, "Skip messages whose source and sequence id were already written (durable mode)"),
+        ],
+        shared: &[],
+    };
+
     /// Creates a new file output processor.
     ///
     /// # Arguments
@@ -167,9 +253,147 @@ impl FileOutputProcessor {
             config: processor_config,
             writer: None,
             csv_headers_written: false,
+            segment: None,
+            durable: DurableOffsets::default(),
+            offsets: HashMap::new(),
         }))
     }
 
+    /// Path of a durable segment, or of its temporary file while written.
+    fn segment_path(&self, index: u64, temporary: bool) -> PathBuf {
+        let path = &self.config.file_path;
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let mut name = match path.extension() {
+            Some(extension) => format!("{}.{:06}.{}", stem, index, extension.to_string_lossy()),
+            None => format!("{}.{:06}", stem, index),
+        };
+        if temporary {
+            name.push_str(".tmp");
+        }
+        path.with_file_name(name)
+    }
+
+    fn offsets_path(&self) -> PathBuf {
+        let mut name = self.config.file_path.file_name().unwrap_or_default().to_os_string();
+        name.push(".offsets");
+        self.config.file_path.with_file_name(name)
+    }
+
+    /// Recover from the previous run: complete the rename of a segment whose
+    /// offsets were recorded, and discard segments left incomplete.
+    async fn recover(&mut self) -> anyhow::Result<()> {
+        let offsets_path = self.offsets_path();
+        match tokio::fs::read(&offsets_path).await {
+            Ok(contents) => {
+                self.durable = serde_json::from_slice(&contents)
+                    .with_context(|| format!("Invalid offsets record '{}'", offsets_path.display()))?;
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e).with_context(|| format!("Failed to read '{}'", offsets_path.display())),
+        }
+        self.offsets = self.durable.offsets.clone();
+
+        let committed = self.segment_path(self.durable.segment, true);
+        if self.durable.segment > 0 && tokio::fs::try_exists(&committed).await? {
+            tokio::fs::rename(&committed, self.segment_path(self.durable.segment, false)).await?;
+            tracing::info!("File output processor '{}' completed segment {}", self.name, self.durable.segment);
+        }
+
+        // Only one segment is written at a time, the one after the last completed
+        let incomplete = self.segment_path(self.durable.segment + 1, true);
+        if tokio::fs::try_exists(&incomplete).await? {
+            tracing::warn!(
+                "File output processor '{}' discarding incomplete segment '{}'",
+                self.name,
+                incomplete.display()
+            );
+            tokio::fs::remove_file(&incomplete).await?;
+        }
+        Ok(())
+    }
+
+    fn directory(&self) -> PathBuf {
+        match self.config.file_path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        }
+    }
+
+    /// Start the next segment.
+    async fn open_segment(&mut self) -> anyhow::Result<()> {
+        let index = self.durable.segment + 1;
+        let path = self.segment_path(index, true);
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&path)
+            .await
+            .with_context(|| format!("Failed to open segment '{}'", path.display()))?;
+        self.writer = Some(BufWriter::with_capacity(self.config.buffer_size.max(1), file));
+        self.segment = Some(Segment {
+            index,
+            opened: Instant::now(),
+            bytes: 0,
+            messages: 0,
+        });
+        Ok(())
+    }
+
+    /// Complete the open segment: make it durable, record the offsets it
+    /// covers, then rename it into place. Returns the number of messages the
+    /// segment holds.
+    async fn complete_segment(&mut self) -> anyhow::Result<u64> {
+        let (Some(segment), Some(mut writer)) = (self.segment.take(), self.writer.take()) else {
+            return Ok(0);
+        };
+        writer.flush().await?;
+        writer.get_mut().sync_all().await?;
+        drop(writer);
+
+        // Recording the offsets first means a crash before the rename is
+        // completed by `recover`, rather than leaving lines to be written twice
+        let durable = DurableOffsets {
+            segment: segment.index,
+            offsets: self.offsets.clone(),
+        };
+        let offsets_path = self.offsets_path();
+        let mut temporary = offsets_path.clone().into_os_string();
+        temporary.push(".tmp");
+        let mut file = File::create(&temporary).await?;
+        file.write_all(&serde_json::to_vec(&durable)?).await?;
+        file.sync_all().await?;
+        tokio::fs::rename(&temporary, &offsets_path).await?;
+
+        tokio::fs::rename(self.segment_path(segment.index, true), self.segment_path(segment.index, false)).await?;
+        sync_directory(&self.directory()).await?;
+        self.durable = durable;
+        tracing::debug!(
+            "File output processor '{}' completed segment {} ({} bytes)",
+            self.name,
+            segment.index,
+            segment.bytes
+        );
+        Ok(segment.messages)
+    }
+
+    /// Whether the open segment is due to be completed.
+    fn segment_full(&self) -> bool {
+        self.segment.as_ref().is_some_and(|segment| {
+            segment.bytes >= self.config.segment_bytes
+                || self
+                    .config
+                    .segment_ms
+                    .is_some_and(|ms| segment.opened.elapsed() >= Duration::from_millis(ms))
+        })
+    }
+
+    /// Whether a message from `source` with `sequence_id` was already written.
+    fn already_written(&self, source: &str, sequence_id: Option<u64>) -> bool {
+        self.config.dedupe
+            && sequence_id.is_some_and(|sequence| self.offsets.get(source).is_some_and(|last| sequence <= *last))
+    }
+
     /// Opens the output file and creates the buffered writer.
     async fn open_file(&mut self) -> anyhow::Result<()> {
         // Create parent directories if needed
@@ -217,23 +441,25 @@ impl FileOutputProcessor {
         Ok(())
     }
 
-    /// Writes a JSON payload to the file in the configured format.
+    /// Writes a JSON payload to the file in the configured format, returning
+    /// the number of bytes written.
     async fn write_message(
         &mut self,
         channel_name: &str,
         payload: &serde_json::Value,
-    ) -> anyhow::Result<()> {
+    ) -> anyhow::Result<usize> {
         let writer = self
             .writer
             .as_mut()
             .ok_or_else(|| anyhow::anyhow!("File writer not initialised"))?;
 
-        match self.config.format {
+        let written = match self.config.format {
             OutputFormat::Json => {
                 let json_line = serde_json::to_string(payload)
                     .map_err(|e| anyhow::anyhow!("Failed to serialise payload to JSON: {}", e))?;
                 writer.write_all(json_line.as_bytes()).await?;
                 writer.write_all(b"\n").await?;
+                json_line.len() + 1
             }
 
             OutputFormat::Pretty => {
@@ -242,6 +468,7 @@ impl FileOutputProcessor {
                 })?;
                 writer.write_all(json_pretty.as_bytes()).await?;
                 writer.write_all(b"\n").await?;
+                json_pretty.len() + 1
             }
 
             OutputFormat::Csv => {
@@ -260,27 +487,45 @@ impl FileOutputProcessor {
                 let csv_line = values.join(",");
                 writer.write_all(csv_line.as_bytes()).await?;
                 writer.write_all(b"\n").await?;
+                csv_line.len() + 1
             }
 
             OutputFormat::Text => {
                 let text_line = format!("[{}] {}", channel_name, serde_json::to_string(payload)?);
                 writer.write_all(text_line.as_bytes()).await?;
                 writer.write_all(b"\n").await?;
+                text_line.len() + 1
             }
-        }
+        };
 
         // Auto-flush if configured
         if self.config.auto_flush {
             writer.flush().await?;
         }
 
-        Ok(())
+        Ok(written)
     }
 }
 
 #[async_trait]
 impl Processor for FileOutputProcessor {
     async fn init(&mut self) -> anyhow::Result<()> {
+        if self.config.durable {
+            let directory = self.directory();
+            if self.config.create_dirs {
+                tokio::fs::create_dir_all(&directory).await.map_err(|e| {
+                    anyhow::anyhow!("Failed to create directory '{}': {}", directory.display(), e)
+                })?;
+            }
+            self.recover().await?;
+            tracing::info!(
+                "File output processor '{}' writing durable segments of '{}' from segment {}",
+                self.name,
+                self.config.file_path.display(),
+                self.durable.segment + 1
+            );
+            return Ok(());
+        }
         self.open_file().await?;
         tracing::info!("File output processor '{}' initialised", self.name);
         Ok(())
@@ -295,21 +540,57 @@ impl Processor for FileOutputProcessor {
         let mut messages_written = 0;
 
         // Process messages from all input channels
-        for (channel_name, input) in context.inputs.iter_mut() {
-            while let Some(message) = input.try_recv().await {
-                let payload = message.payload;
-
-                self.write_message(channel_name, &payload)
-                    .await
-                    .map_err(|e| {
-                        anyhow::anyhow!(
-                            "Failed to write message from channel '{}': {}",
-                            channel_name,
-                            e
-                        )
-                    })?;
-                messages_written += 1;
+        while let Some((channel_name, message)) = context.try_recv().await {
+            if self.already_written(&message.source, message.timing.sequence_id) {
+                tracing::debug!(
+                    "File output processor '{}' skipping message {:?} from '{}', already written",
+                    self.name,
+                    message.timing.sequence_id,
+                    message.source
+                );
+                continue;
+            }
+            if self.config.durable && self.segment.is_none() {
+                self.open_segment().await?;
+            }
+
+            let payload = message.payload;
+
+            let written = match self.write_message(&channel_name, &payload).await {
+                Ok(written) => written,
+                Err(e) => {
+                    context.report_failed(&e);
+                    return Err(anyhow::anyhow!(
+                        "Failed to write message from channel '{}': {}",
+                        channel_name,
+                        e
+                    ));
+                }
+            };
+            messages_written += 1;
+
+            if let Some(sequence_id) = message.timing.sequence_id {
+                let last = self.offsets.entry(message.source).or_default();
+                *last = (*last).max(sequence_id);
             }
+            // Messages in durable mode are delivered once their segment is complete
+            match self.segment.as_mut() {
+                Some(segment) => {
+                    segment.bytes += written as u64;
+                    segment.messages += 1;
+                }
+                None => context.report_delivered(1),
+            }
+            if self.segment_full() {
+                let completed = self.complete_segment().await?;
+                context.report_delivered(completed);
+            }
+        }
+
+        // Complete an aged segment even while input is idle
+        if self.segment_full() {
+            let completed = self.complete_segment().await?;
+            context.report_delivered(completed);
         }
 
         // Flush periodically even if auto_flush is disabled
@@ -326,4 +607,92 @@ impl Processor for FileOutputProcessor {
 
         Ok(())
     }
+
+    async fn flush(&mut self, context: &mut ProcessingContext) -> anyhow::Result<()> {
+        if self.config.durable {
+            let completed = self.complete_segment().await?;
+            if completed > 0 {
+                context.report_delivered(completed);
+            }
+            return Ok(());
+        }
+        if let Some(writer) = self.writer.as_mut() {
+            writer.flush().await?;
+        }
+        Ok(())
+    }
+}
+
+/// Make renames within `directory` durable.
+#[cfg(unix)]
+async fn sync_directory(directory: &Path) -> anyhow::Result<()> {
+    File::open(directory).await?.sync_all().await?;
+    Ok(())
+}
+
+/// Directories cannot be opened for syncing on this platform.
+#[cfg(not(unix))]
+async fn sync_directory(_directory: &Path) -> anyhow::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{MessageBuilder, TestContext};
+    use serde_json::json;
+
+    fn durable_sink(directory: &Path) -> Box<dyn Processor> {
+        let stage = StageConfig {
+            r#type: "file".to_string(),
+            inputs: Some(vec!["readings".to_string()]),
+            parameters: serde_json::from_value(json!({
+                "file_path": directory.join("out.jsonl"),
+                "durable": true,
+            }))
+            .ok(),
+            ..Default::default()
+        };
+        FileOutputProcessor::new("sink", stage).unwrap()
+    }
+
+    async fn send(test: &TestContext, sequence_id: u64) {
+        let message = MessageBuilder::new(json!({ "n": sequence_id })).source("sensor").sequence_id(sequence_id).build();
+        test.send("readings", message).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_durable_segments_survive_a_crash_without_duplicates() {
+        let directory = std::env::temp_dir().join(format!("liminal-file-{}", std::process::id()));
+        let segment = |index: u64| std::fs::read_to_string(directory.join(format!("out.{:06}.jsonl", index)));
+
+        let mut sink = durable_sink(&directory);
+        sink.init().await.unwrap();
+        let mut test = TestContext::new("sink").input("readings");
+        send(&test, 1).await;
+        send(&test, 2).await;
+        test.process(sink.as_mut()).await.unwrap();
+        sink.flush(&mut test.context).await.unwrap();
+        assert_eq!(segment(1).unwrap(), "{\"n\":1}\n{\"n\":2}\n");
+
+        // Crash with message 3 written to an incomplete segment
+        send(&test, 3).await;
+        test.process(sink.as_mut()).await.unwrap();
+        drop(sink);
+        assert!(directory.join("out.000002.jsonl.tmp").exists());
+
+        // The source replays from the start after the restart
+        let mut sink = durable_sink(&directory);
+        sink.init().await.unwrap();
+        assert!(!directory.join("out.000002.jsonl.tmp").exists());
+        for sequence_id in 1..=3 {
+            send(&test, sequence_id).await;
+        }
+        test.process(sink.as_mut()).await.unwrap();
+        sink.flush(&mut test.context).await.unwrap();
+        assert_eq!(segment(2).unwrap(), "{\"n\":3}\n");
+        assert!(segment(3).is_err());
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
 }
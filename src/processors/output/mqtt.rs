@@ -1,21 +1,30 @@
-use crate::config::ProcessorConfig;
+use crate::config::{ParamSpec, ParamType, ProcessorConfig, ProcessorMetadata};
 use crate::config::{StageConfig, extract_param};
 use crate::core::context::ProcessingContext;
+use crate::core::message::{CONTENT_ENCODING, ENCODING_BASE64, Message};
 use crate::processors::Processor;
-use crate::processors::common::MqttConnectionConfig;
+use crate::processors::common::{
+    CREDENTIALS_PARAMS, DEAD_LETTER_PARAMS, MQTT_CONNECTION_PARAMS, MqttConnectionConfig, RETRY_PARAMS, Retry, dead_letter,
+};
 
 use async_trait::async_trait;
-use rumqttc::AsyncClient;
+use rumqttc::{AsyncClient, Event, Outgoing};
 use serde_json::Value;
 use std::collections::HashMap;
-use tokio::select;
+
+use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
 
 #[derive(Debug, Clone)]
 pub struct MqttOutputConfig {
     pub connection: MqttConnectionConfig,
     pub topic_map: HashMap<String, String>,
     pub default_topic: Option<String>,
+    /// Metadata key holding the topic to publish to, taking precedence over
+    /// the topic map when the message has it
+    pub topic_metadata: Option<String>,
     pub retain: bool,
+    /// Side output receiving messages that could not be published
+    pub dead_letter: Option<String>,
 }
 
 impl ProcessorConfig for MqttOutputConfig {
@@ -30,22 +39,27 @@ impl ProcessorConfig for MqttOutputConfig {
         let default_topic: Option<String> =
             extract_param(&config.parameters, "default_topic", None);
 
+        let topic_metadata: Option<String> =
+            extract_param(&config.parameters, "topic_metadata", None);
+
         let retain = extract_param(&config.parameters, "retain", false);
 
         Ok(Self {
             connection,
             topic_map,
             default_topic,
+            topic_metadata,
             retain,
+            dead_letter: dead_letter(config)?,
         })
     }
 
     fn validate(&self) -> anyhow::Result<()> {
         self.connection.validate()?;
 
-        if self.topic_map.is_empty() && self.default_topic.is_none() {
+        if self.topic_map.is_empty() && self.default_topic.is_none() && self.topic_metadata.is_none() {
             return Err(anyhow::anyhow!(
-                "Must specify either topic_map or default_topic"
+                "Must specify topic_map, default_topic or topic_metadata"
             ));
         }
 
@@ -67,9 +81,23 @@ pub struct MqttOutputProcessor {
     name: String,
     config: MqttOutputConfig,
     client: Option<AsyncClient>,
+    /// Event loop task, which ends once the client has disconnected
+    event_loop: Option<tokio::task::JoinHandle<()>>,
 }
 
 impl MqttOutputProcessor {
+    pub const METADATA: ProcessorMetadata = ProcessorMetadata {
+        name: "mqtt_pub",
+        description: "Publishes messages to MQTT topics",
+        parameters: &[
+            ParamSpec::new("topic_map", ParamType::Object, "Topic to publish to for each input"),
+            ParamSpec::new("default_topic", ParamType::String, "Topic for inputs not in topic_map"),
+            ParamSpec::new("topic_metadata", ParamType::String, "Metadata key holding the topic to publish to (e.g. mqtt_topic)"),
+            ParamSpec::new("retain", ParamType::Boolean, "Publish retained messages"),
+        ],
+        shared: &[MQTT_CONNECTION_PARAMS, RETRY_PARAMS, CREDENTIALS_PARAMS, DEAD_LETTER_PARAMS],
+    };
+
     pub fn new(name: &str, config: StageConfig) -> anyhow::Result<Box<dyn Processor>> {
         let processor_config = MqttOutputConfig::from_stage_config(&config)?;
         processor_config.validate()?;
@@ -78,21 +106,32 @@ impl MqttOutputProcessor {
             name: name.to_string(),
             config: processor_config,
             client: None,
+            event_loop: None,
         }))
     }
 
-    fn resolve_topic(&self, channel_name: &str) -> Option<&str> {
-        // First try the topic map, then fall back to default
+    fn resolve_topic<'a>(&'a self, channel_name: &str, message: &'a Message) -> Option<&'a str> {
+        // First try the message's metadata, then the topic map, then fall back to default
         self.config
-            .topic_map
-            .get(channel_name)
-            .map(|s| s.as_str())
-            .or_else(|| self.config.default_topic.as_deref())
+            .topic_metadata
+            .as_deref()
+            .and_then(|key| message.get_metadata(key))
+            .or_else(|| self.config.topic_map.get(channel_name).map(|s| s.as_str()))
+            .or(self.config.default_topic.as_deref())
     }
 
-    fn format_payload(&self, payload: &Value) -> anyhow::Result<String> {
+    fn format_payload(&self, message: &Message) -> anyhow::Result<Vec<u8>> {
+        // Binary payloads carried as base64 are published as the original bytes
+        if let Value::String(encoded) = &message.payload
+            && message.get_metadata(CONTENT_ENCODING) == Some(ENCODING_BASE64)
+        {
+            return BASE64
+                .decode(encoded)
+                .map_err(|e| anyhow::anyhow!("Failed to decode base64 payload: {}", e));
+        }
+
         // Convert payload to JSON string for MQTT transmission
-        serde_json::to_string(payload)
+        serde_json::to_vec(&message.payload)
             .map_err(|e| anyhow::anyhow!("Failed to serialize payload: {}", e))
     }
 }
@@ -106,22 +145,36 @@ impl Processor for MqttOutputProcessor {
         let (client, mut eventloop) = AsyncClient::new(mqttoptions, 10);
 
         // Spawn the event loop in a background task to handle MQTT connection
-        tokio::spawn(async move {
+        let name = self.name.clone();
+        let mut retry = Retry::new(self.config.connection.retry.clone());
+        let event_loop = tokio::spawn(async move {
             loop {
                 match eventloop.poll().await {
+                    Ok(Event::Outgoing(Outgoing::Disconnect)) => break,
                     Ok(_) => {
                         // Event loop running normally
+                        retry.succeeded();
                     }
                     Err(e) => {
-                        tracing::error!("MQTT event loop error: {:?}", e);
-                        // Small delay before retrying
-                        tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
+                        let error = anyhow::Error::new(e);
+                        let Some(delay) = retry.failed(&error) else {
+                            tracing::error!(
+                                "MQTT publisher '{}' giving up after {} attempts: {:?}",
+                                name,
+                                retry.attempts(),
+                                error
+                            );
+                            break;
+                        };
+                        tracing::error!("MQTT event loop error, retrying in {:?}: {:?}", delay, error);
+                        tokio::time::sleep(delay).await;
                     }
                 }
             }
         });
 
         self.client = Some(client);
+        self.event_loop = Some(event_loop);
 
         tracing::info!(
             "MQTT publisher '{}' initialised (broker: {}, topic_map: {:?}, default: {:?}, QoS: {}, retain: {})",
@@ -135,44 +188,58 @@ impl Processor for MqttOutputProcessor {
         Ok(())
     }
 
+    async fn preflight(&self) -> anyhow::Result<()> {
+        self.config.connection.preflight("liminal_out").await
+    }
+
     async fn process(&mut self, context: &mut ProcessingContext) -> anyhow::Result<()> {
+        if self.event_loop.as_ref().is_some_and(|event_loop| event_loop.is_finished()) {
+            self.client = None;
+            self.event_loop = None;
+            return Err(anyhow::anyhow!("MQTT connection to '{}' was lost", self.config.connection.broker_url));
+        }
+
         if let Some(ref client) = self.client {
             let mut messages_published = 0;
 
             // Process all input channels
-            for (channel_name, input) in context.inputs.iter_mut() {
-                select! {
-                    message = input.recv() => {
-                        if let Some(message) = message {
-                            // Resolve topic using channel name
-                            if let Some(topic) = self.resolve_topic(channel_name) {
-                                // Format payload as JSON string
-                                let payload_str = self.format_payload(&message.payload)?;
-
-                                // Publish to MQTT broker
-                                if let Err(e) = client.publish(
-                                    topic,
-                                    self.config.connection.qos(),
-                                    self.config.retain,
-                                    payload_str.as_bytes()
-                                ).await {
-                                    tracing::error!("Failed to publish to MQTT topic '{}': {:?}", topic, e);
-                                } else {
-                                    tracing::debug!(
-                                        "Published message from '{}' to MQTT topic: {} (payload: {})",
-                                        channel_name, topic, payload_str
-                                    );
-                                    messages_published += 1;
-                                }
-                            } else {
-                                tracing::warn!("No topic mapping found for input channel: {}", channel_name);
-                            }
-                        }
-                    }
-                    _ = tokio::time::sleep(tokio::time::Duration::from_millis(10)) => {
-                        // Timeout - no messages received, continue processing
-                        break;
+            if let Some((channel_name, message)) = context.recv(tokio::time::Duration::from_millis(10)).await {
+                let dead_letter = self.config.dead_letter.as_deref();
+
+                // Resolve topic using channel name
+                let Some(topic) = self.resolve_topic(&channel_name, &message) else {
+                    tracing::warn!("No topic mapping found for input channel: {}", channel_name);
+                    let error = format!("no topic for input channel '{}'", channel_name);
+                    context.delivery_failed(message, dead_letter, error).await;
+                    return Ok(());
+                };
+
+                // Format payload as JSON string
+                let payload = match self.format_payload(&message) {
+                    Ok(payload) => payload,
+                    Err(e) => {
+                        tracing::error!("Dropping message from '{}': {}", channel_name, e);
+                        context.delivery_failed(message, dead_letter, e).await;
+                        return Ok(());
                     }
+                };
+
+                // Publish to MQTT broker
+                if let Err(e) = client.publish(
+                    topic,
+                    self.config.connection.qos(),
+                    self.config.retain,
+                    payload.as_slice()
+                ).await {
+                    tracing::error!("Failed to publish to MQTT topic '{}': {:?}", topic, e);
+                    context.delivery_failed(message, dead_letter, e).await;
+                } else {
+                    tracing::debug!(
+                        "Published message from '{}' to MQTT topic: {} (payload: {})",
+                        channel_name, topic, String::from_utf8_lossy(&payload)
+                    );
+                    context.report_delivered(1);
+                    messages_published += 1;
                 }
             }
 
@@ -184,4 +251,16 @@ impl Processor for MqttOutputProcessor {
 
         Ok(())
     }
+
+    async fn flush(&mut self, _context: &mut ProcessingContext) -> anyhow::Result<()> {
+        // The disconnect is queued behind any pending publishes, so the event
+        // loop sends those before it stops
+        if let Some(client) = self.client.take() {
+            client.disconnect().await?;
+        }
+        if let Some(event_loop) = self.event_loop.take() {
+            event_loop.await?;
+        }
+        Ok(())
+    }
 }
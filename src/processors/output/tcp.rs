@@ -1,19 +1,25 @@
-use crate::config::{ProcessorConfig, StageConfig};
+use crate::config::{ProcessorConfig, ProcessorMetadata, StageConfig};
 use crate::core::context::ProcessingContext;
 use crate::processors::Processor;
-use crate::processors::common::tcp::{TcpConfig, TcpConnection};
+use crate::processors::common::tcp::{TCP_PARAMS, TcpConfig, TcpConnection};
+use crate::processors::common::{CREDENTIALS_PARAMS, DEAD_LETTER_PARAMS, RETRY_PARAMS, TLS_PARAMS, dead_letter};
 
 use async_trait::async_trait;
 
 #[derive(Debug, Clone)]
 pub struct TcpOutputConfig {
     tcp_config: TcpConfig,
+    /// Side output receiving messages that could not be sent
+    dead_letter: Option<String>,
 }
 
 impl ProcessorConfig for TcpOutputConfig {
     fn from_stage_config(config: &StageConfig) -> anyhow::Result<Self> {
         let tcp_config = TcpConfig::from_stage_config(config)?;
-        Ok(Self { tcp_config })
+        Ok(Self {
+            tcp_config,
+            dead_letter: dead_letter(config)?,
+        })
     }
 
     fn validate(&self) -> anyhow::Result<()> {
@@ -24,9 +30,17 @@ impl ProcessorConfig for TcpOutputConfig {
 pub struct TcpOutputProcessor {
     name: String,
     connection: TcpConnection,
+    dead_letter: Option<String>,
 }
 
 impl TcpOutputProcessor {
+    pub const METADATA: ProcessorMetadata = ProcessorMetadata {
+        name: "tcp_output",
+        description: "Sends messages as JSON over TCP",
+        parameters: &[],
+        shared: &[TCP_PARAMS, RETRY_PARAMS, TLS_PARAMS, CREDENTIALS_PARAMS, DEAD_LETTER_PARAMS],
+    };
+
     pub fn new(name: &str, config: StageConfig) -> anyhow::Result<Box<dyn Processor>> {
         let processor_config = TcpOutputConfig::from_stage_config(&config)?;
         processor_config.validate()?;
@@ -36,6 +50,7 @@ impl TcpOutputProcessor {
         Ok(Box::new(Self {
             name: name.to_string(),
             connection,
+            dead_letter: processor_config.dead_letter,
         }))
     }
 }
@@ -47,6 +62,10 @@ impl Processor for TcpOutputProcessor {
         Ok(())
     }
 
+    async fn preflight(&self) -> anyhow::Result<()> {
+        self.connection.preflight().await
+    }
+
     async fn process(&mut self, context: &mut ProcessingContext) -> anyhow::Result<()> {
         // Check if we have any messages to process first
         let mut has_messages = false;
@@ -61,58 +80,50 @@ impl Processor for TcpOutputProcessor {
         // Only try to connect if we have messages to send or we're already connected
         if has_messages || self.connection.is_connected() {
             if let Err(e) = self.connection.ensure_connection().await {
-                if self.connection.should_reconnect() {
-                    tracing::debug!(
-                        "{}: Connection failed, will retry in {}ms: {}",
-                        self.name,
-                        self.connection.reconnect_interval(),
-                        e
-                    );
-                    tokio::time::sleep(tokio::time::Duration::from_millis(
-                        self.connection.reconnect_interval(),
-                    ))
-                    .await;
-                    return Ok(());
-                } else {
+                let Some(delay) = self.connection.retry_delay(&e) else {
                     return Err(e);
-                }
+                };
+                tracing::debug!("{}: Connection failed, will retry in {:?}: {}", self.name, delay, e);
+                context.report_retry();
+                tokio::time::sleep(delay).await;
+                return Ok(());
             }
         }
 
         // Process messages from inputs
-        for (_input_name, subscriber) in &mut context.inputs {
-            while let Some(message) = subscriber.try_recv().await {
-                tracing::debug!("{}: Processing message from {}", self.name, message.source);
-
-                // Convert message to JSON and encode as UTF-8
-                let json_value = serde_json::json!({
-                    "source": message.source,
-                    "topic": message.topic,
-                    "payload": message.payload,
-                    "timestamp": message.timestamp
-                });
-                let json_string = serde_json::to_string(&json_value)?;
-                let json_bytes = json_string.into_bytes(); // UTF-8 encoding
-
-                tracing::debug!("{}: Sending {} byte message", self.name, json_bytes.len());
-
-                if let Err(e) = self
-                    .connection
-                    .send_message_with_length_prefix(&json_bytes)
-                    .await
-                {
-                    tracing::error!("{}: Failed to send message: {}", self.name, e);
-
-                    // Reset connection for reconnection attempt
-                    self.connection.disconnect();
-
-                    if !self.connection.should_reconnect() {
-                        return Err(e);
-                    }
-                    break; // Exit message processing loop to attempt reconnection
-                } else {
-                    tracing::debug!("{}: Successfully sent message", self.name);
+        while let Some((_, message)) = context.try_recv().await {
+            tracing::debug!("{}: Processing message from {}", self.name, message.source);
+
+            // Convert message to JSON and encode as UTF-8
+            let json_value = serde_json::json!({
+                "source": message.source,
+                "topic": message.topic,
+                "payload": message.payload,
+                "timestamp": message.timestamp
+            });
+            let json_string = serde_json::to_string(&json_value)?;
+            let json_bytes = json_string.into_bytes(); // UTF-8 encoding
+
+            tracing::debug!("{}: Sending {} byte message", self.name, json_bytes.len());
+
+            if let Err(e) = self
+                .connection
+                .send_message_with_length_prefix(&json_bytes)
+                .await
+            {
+                tracing::error!("{}: Failed to send message: {}", self.name, e);
+                context.delivery_failed(message, self.dead_letter.as_deref(), &e).await;
+
+                // Reset connection for reconnection attempt
+                self.connection.disconnect();
+
+                if !self.connection.should_reconnect() {
+                    return Err(e);
                 }
+                break; // Exit message processing loop to attempt reconnection
+            } else {
+                tracing::debug!("{}: Successfully sent message", self.name);
+                context.report_delivered(1);
             }
         }
 
@@ -0,0 +1,256 @@
+//! Processor Plugins
+//!
+//! Loads processors from dynamic libraries at startup, so custom processors
+//! don't need a fork of Liminal. Every library in the configured `plugins_dir`
+//! (`.so`, `.dylib` or `.dll`, by platform) is loaded, and the processors it
+//! registers become available as stage types. Loading requires building with
+//! `--features plugins`.
+//!
+//! A plugin is a `cdylib` crate depending on `liminal`, which declares its
+//! processors with `declare_plugin!`:
+//!
+//! ```rust,ignore
+//! liminal::declare_plugin!(|registrar| {
+//!     registrar.register(&MyProcessor::METADATA, Box::new(MyProcessor::new));
+//! });
+//! ```
+//!
+//! The macro exports three C functions: the plugin ABI version, the Liminal
+//! version the plugin was built against, and the registration function, which
+//! is only called when both versions match. Processors cross the library
+//! boundary as Rust trait objects, so a plugin must also be built with the
+//! same Rust toolchain as the host.
+//!
+//! A plugin links its own copies of its dependencies, so it can't see the
+//! host's tokio runtime or logging: its processors are polled within a runtime
+//! of the plugin's own, and its log events are not shown.
+//!
+//! Applications embedding Liminal as a library can instead register their
+//! processors directly with `register_processor_with_meta`.
+
+use super::factory::ProcessorConstructor;
+use super::Processor;
+use crate::config::{ProcessorMetadata, StageConfig};
+use crate::core::backfill::BackfillRange;
+use crate::core::checkpoint::Snapshot;
+use crate::core::context::ProcessingContext;
+
+use async_trait::async_trait;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::OnceLock;
+use tokio::runtime::Handle;
+
+/// Version of the plugin interface: the exported functions and
+/// `PluginRegistrar`. Bumped whenever either changes.
+pub const PLUGIN_ABI_VERSION: u32 = 1;
+
+/// Liminal version, nul-terminated for the exported version function.
+pub const LIMINAL_VERSION: &str = concat!(env!("CARGO_PKG_VERSION"), "\0");
+
+/// Collects the processors a plugin registers. The host registers them once
+/// the plugin returns, as a plugin has its own copy of the factory registry.
+#[derive(Default)]
+pub struct PluginRegistrar {
+    processors: Vec<(&'static ProcessorMetadata, ProcessorConstructor)>,
+}
+
+impl PluginRegistrar {
+    /// Register a processor under `metadata.name`.
+    pub fn register(&mut self, metadata: &'static ProcessorMetadata, constructor: ProcessorConstructor) {
+        // Runs in the plugin, so the wrapper enters the plugin's runtime
+        let constructor: ProcessorConstructor = Box::new(move |name: &str, config: StageConfig| {
+            let inner = constructor(name, config)?;
+            Ok(Box::new(PluginProcessor { inner, runtime: plugin_runtime() }) as Box<dyn Processor>)
+        });
+        self.processors.push((metadata, constructor));
+    }
+}
+
+/// Runtime driving the timers and I/O of a plugin's processors.
+fn plugin_runtime() -> Handle {
+    static RUNTIME: OnceLock<tokio::runtime::Runtime> = OnceLock::new();
+    RUNTIME
+        .get_or_init(|| {
+            tokio::runtime::Builder::new_multi_thread()
+                .worker_threads(1)
+                .thread_name("liminal-plugin")
+                .enable_all()
+                .build()
+                .expect("Failed to start the plugin runtime")
+        })
+        .handle()
+        .clone()
+}
+
+/// A processor from a plugin, polled with the plugin's runtime entered.
+struct PluginProcessor {
+    inner: Box<dyn Processor>,
+    runtime: Handle,
+}
+
+/// Poll `future` with `runtime` entered.
+async fn entered<T>(runtime: &Handle, mut future: Pin<Box<dyn Future<Output = T> + Send + '_>>) -> T {
+    std::future::poll_fn(|cx| {
+        let _guard = runtime.enter();
+        future.as_mut().poll(cx)
+    })
+    .await
+}
+
+#[async_trait]
+impl Processor for PluginProcessor {
+    async fn init(&mut self) -> anyhow::Result<()> {
+        entered(&self.runtime, self.inner.init()).await
+    }
+
+    async fn process(&mut self, context: &mut ProcessingContext) -> anyhow::Result<()> {
+        entered(&self.runtime, self.inner.process(context)).await
+    }
+
+    async fn flush(&mut self, context: &mut ProcessingContext) -> anyhow::Result<()> {
+        entered(&self.runtime, self.inner.flush(context)).await
+    }
+
+    fn backfill(&mut self, range: &BackfillRange) -> anyhow::Result<()> {
+        self.inner.backfill(range)
+    }
+
+    fn as_snapshot(&mut self) -> Option<&mut dyn Snapshot> {
+        self.inner.as_snapshot()
+    }
+}
+
+/// Export the functions Liminal looks for in a plugin library. The argument
+/// is a function, or a closure without captures, taking the registrar.
+#[macro_export]
+macro_rules! declare_plugin {
+    ($register:expr) => {
+        #[unsafe(no_mangle)]
+        pub extern "C" fn liminal_plugin_abi_version() -> u32 {
+            $crate::processors::plugin::PLUGIN_ABI_VERSION
+        }
+
+        #[unsafe(no_mangle)]
+        pub extern "C" fn liminal_plugin_version() -> *const ::std::ffi::c_char {
+            $crate::processors::plugin::LIMINAL_VERSION.as_ptr().cast()
+        }
+
+        #[unsafe(no_mangle)]
+        pub extern "C" fn liminal_plugin_register(registrar: &mut $crate::processors::plugin::PluginRegistrar) {
+            let register: fn(&mut $crate::processors::plugin::PluginRegistrar) = $register;
+            register(registrar);
+        }
+    };
+}
+
+/// Load every plugin library in `dir`, in name order, and register its
+/// processors. Returns the processor types registered.
+#[cfg(feature = "plugins")]
+pub fn load_plugins(dir: impl AsRef<std::path::Path>) -> anyhow::Result<Vec<String>> {
+    use super::factory::{processor_exists, register_processor_with_meta};
+    use anyhow::{Context, anyhow};
+    use std::ffi::{CStr, c_char};
+    use std::sync::Mutex;
+
+    /// Loaded libraries stay loaded, as their processors and metadata live in them.
+    static LIBRARIES: OnceLock<Mutex<Vec<libloading::Library>>> = OnceLock::new();
+
+    let dir = dir.as_ref();
+    let mut paths: Vec<_> = std::fs::read_dir(dir)
+        .with_context(|| format!("Failed to read plugins directory '{}'", dir.display()))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|extension| extension == std::env::consts::DLL_EXTENSION))
+        .collect();
+    paths.sort();
+
+    let mut registered = Vec::new();
+    for path in paths {
+        // SAFETY: loading runs the library's initialisers; plugins directories
+        // are trusted in the same way as the binary itself.
+        let library = unsafe { libloading::Library::new(&path) }
+            .with_context(|| format!("Failed to load plugin '{}'", path.display()))?;
+
+        // SAFETY: the symbol types match those exported by `declare_plugin!`,
+        // checked through the ABI version before registration is called.
+        let registrar = unsafe {
+            let symbol = |name: &str| anyhow!("'{}' is not a Liminal plugin: it exports no {}", path.display(), name);
+            let abi_version = library
+                .get::<extern "C" fn() -> u32>(b"liminal_plugin_abi_version")
+                .map_err(|_| symbol("liminal_plugin_abi_version"))?;
+            if abi_version() != PLUGIN_ABI_VERSION {
+                return Err(anyhow!(
+                    "Plugin '{}' uses plugin ABI version {}, but this build of Liminal uses version {}",
+                    path.display(),
+                    abi_version(),
+                    PLUGIN_ABI_VERSION
+                ));
+            }
+
+            let version = library
+                .get::<extern "C" fn() -> *const c_char>(b"liminal_plugin_version")
+                .map_err(|_| symbol("liminal_plugin_version"))?;
+            let version = CStr::from_ptr(version()).to_string_lossy();
+            let expected = LIMINAL_VERSION.trim_end_matches('\0');
+            if version != expected {
+                return Err(anyhow!(
+                    "Plugin '{}' was built against Liminal {}, but this is Liminal {}",
+                    path.display(),
+                    version,
+                    expected
+                ));
+            }
+
+            let register = library
+                .get::<extern "C" fn(&mut PluginRegistrar)>(b"liminal_plugin_register")
+                .map_err(|_| symbol("liminal_plugin_register"))?;
+            let mut registrar = PluginRegistrar::default();
+            register(&mut registrar);
+            registrar
+        };
+
+        for (metadata, constructor) in registrar.processors {
+            if processor_exists(metadata.name) {
+                tracing::warn!("Plugin '{}' replaces processor '{}'", path.display(), metadata.name);
+            }
+            tracing::info!("Registered processor '{}' from plugin '{}'", metadata.name, path.display());
+            register_processor_with_meta(metadata, constructor);
+            registered.push(metadata.name.to_string());
+        }
+
+        LIBRARIES
+            .get_or_init(|| Mutex::new(Vec::new()))
+            .lock()
+            .map_err(|_| anyhow!("plugin library registry poisoned"))?
+            .push(library);
+    }
+
+    Ok(registered)
+}
+
+#[cfg(not(feature = "plugins"))]
+pub fn load_plugins(dir: impl AsRef<std::path::Path>) -> anyhow::Result<Vec<String>> {
+    Err(anyhow::anyhow!(
+        "loading plugins from '{}' requires building with --features plugins",
+        dir.as_ref().display()
+    ))
+}
+
+#[cfg(all(test, feature = "plugins"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_plugins_rejects_libraries_that_are_not_plugins() {
+        let directory = std::env::temp_dir().join(format!("liminal-plugins-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        assert!(load_plugins(&directory).unwrap().is_empty());
+
+        std::fs::write(directory.join(format!("broken.{}", std::env::consts::DLL_EXTENSION)), b"not a library").unwrap();
+        std::fs::write(directory.join("README.txt"), b"ignored").unwrap();
+        let error = load_plugins(&directory).unwrap_err();
+        assert!(error.to_string().starts_with("Failed to load plugin"), "{}", error);
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -1,3 +1,5 @@
+use crate::core::backfill::BackfillRange;
+use crate::core::checkpoint::Snapshot;
 use crate::core::context::ProcessingContext;
 
 use async_trait::async_trait;
@@ -19,4 +21,50 @@ pub trait Processor: Send + Sync {
     /// # Returns
     /// A result indicating success or failure of the processing.
     async fn process(&mut self, context: &mut ProcessingContext) -> anyhow::Result<()>;
+
+    /// Flushes buffered or in-flight output before the stage stops.
+    ///
+    /// Called once on shutdown, after the stage has drained its inputs. Sinks
+    /// that buffer writes or hold unsent messages override this; the default
+    /// does nothing.
+    async fn flush(&mut self, _context: &mut ProcessingContext) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Checks that the brokers or servers the processor connects to are
+    /// reachable, before the pipeline starts.
+    ///
+    /// Called after the stage is built and before `init`, unless preflight
+    /// checks are skipped. Checks connect and disconnect again without
+    /// changing the processor; the default has nothing to check.
+    async fn preflight(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Whether the processor works on batches of rows (see
+    /// [`crate::core::batch`]) as they are.
+    ///
+    /// Processors that cannot, the default, receive each row of a batch as a
+    /// separate message instead.
+    fn accepts_batches(&self) -> bool {
+        false
+    }
+
+    /// Switches a source to reading the history of `range`, before `init`.
+    ///
+    /// Sources that can read history override this to publish the messages
+    /// whose event times fall in the range, in event-time order and without
+    /// pauses, and then complete. The default refuses, so that a back-fill
+    /// never runs with a source that only sees live data.
+    fn backfill(&mut self, _range: &BackfillRange) -> anyhow::Result<()> {
+        Err(anyhow::anyhow!("source cannot read history"))
+    }
+
+    /// Returns the processor's checkpointable state, if it keeps any.
+    ///
+    /// Stateful processors implement [`Snapshot`] and override this to return
+    /// `Some(self)`; stateless processors keep the default.
+    fn as_snapshot(&mut self) -> Option<&mut dyn Snapshot> {
+        None
+    }
 }
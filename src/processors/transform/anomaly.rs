@@ -0,0 +1,437 @@
+//! Anomaly Detection Transform
+//!
+//! Flags statistically anomalous readings per field and key using one of three
+//! detectors:
+//!
+//! - **zscore**: rolling mean/standard deviation over the last `window_size` samples
+//! - **mad**: rolling median and median absolute deviation (robust to outliers)
+//! - **ewma**: exponentially weighted moving average control chart
+//!
+//! Anomalous messages can be tagged with a flag and per-field scores, or the stage
+//! can act as a filter that forwards only anomalies (or only normal readings).
+//!
+//! A baseline with no spread (a perfectly flat series) flags any reading further
+//! than `flat_tolerance` from it; such readings have no finite score and are left
+//! out of the scores. Unless `learn_anomalies` is set, anomalies are kept out of
+//! the baseline, but after `relearn_after` consecutive anomalies the series is
+//! taken to have shifted and its baseline restarts from those readings.
+
+use crate::config::{ParamSpec, ParamType, ProcessorConfig, ProcessorMetadata, StageConfig, extract_param};
+use crate::core::checkpoint::Snapshot;
+use crate::core::state::StateStore;
+use crate::core::timing_mixin::{TimingMixin, WithTimingMixin};
+use crate::core::{context::ProcessingContext, message::Message};
+use crate::processors::common::field_utils::FieldUtils;
+use crate::processors::common::stats::{self, MAD_CONSISTENCY};
+use crate::processors::processor::Processor;
+use crate::error::LiminalError;
+
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Number, Value};
+use std::collections::VecDeque;
+use tracing::{debug, error};
+
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AnomalyMethod {
+    Zscore,
+    Mad,
+    Ewma,
+}
+
+/// What to do with messages once they have been scored.
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AnomalyAction {
+    /// Forward every message, tagged with the anomaly flag and scores
+    Tag,
+    /// Forward only anomalous messages (tagged)
+    OnlyAnomalies,
+    /// Forward only normal messages
+    DropAnomalies,
+}
+
+#[derive(Debug, Clone)]
+pub struct AnomalyConfig {
+    pub fields: Vec<String>,
+    pub key_field: Option<String>,
+    pub method: AnomalyMethod,
+    pub window_size: usize,
+    pub alpha: f64,
+    pub threshold: f64,
+    pub min_samples: usize,
+    pub action: AnomalyAction,
+    pub anomaly_field: String,
+    pub score_field: String,
+    /// Whether anomalous readings are added to the baseline statistics
+    pub learn_anomalies: bool,
+    /// Deviation from a flat baseline above which a reading is anomalous
+    pub flat_tolerance: f64,
+    /// Consecutive anomalies after which the baseline restarts (0 disables)
+    pub relearn_after: usize,
+    pub timing: Option<crate::config::TimingConfig>,
+}
+
+impl ProcessorConfig for AnomalyConfig {
+    fn from_stage_config(config: &StageConfig) -> Result<Self> {
+        let mut fields = extract_param(&config.parameters, "fields", Vec::<String>::new());
+        if let Some(field_in) = extract_param(&config.parameters, "field_in", None::<String>) {
+            fields.push(field_in);
+        }
+
+        let config = Self {
+            fields,
+            key_field: extract_param(&config.parameters, "key_field", None::<String>),
+            method: extract_param(&config.parameters, "method", AnomalyMethod::Zscore),
+            window_size: extract_param(&config.parameters, "window_size", 100_usize),
+            alpha: extract_param(&config.parameters, "alpha", 0.3),
+            threshold: extract_param(&config.parameters, "threshold", 3.0),
+            min_samples: extract_param(&config.parameters, "min_samples", 10_usize),
+            action: extract_param(&config.parameters, "action", AnomalyAction::Tag),
+            anomaly_field: extract_param(&config.parameters, "anomaly_field", "anomaly".to_string()),
+            score_field: extract_param(
+                &config.parameters,
+                "score_field",
+                "anomaly_scores".to_string(),
+            ),
+            learn_anomalies: extract_param(&config.parameters, "learn_anomalies", false),
+            flat_tolerance: extract_param(&config.parameters, "flat_tolerance", 1e-9),
+            relearn_after: extract_param(&config.parameters, "relearn_after", 10_usize),
+            timing: config.timing.clone(),
+        };
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.fields.is_empty() {
+            return Err(anyhow!(
+                "anomaly processor requires 'fields' (or 'field_in') to be specified"
+            ));
+        }
+        if self.fields.iter().any(|f| f.is_empty()) {
+            return Err(LiminalError::invalid_parameter("fields", "cannot contain empty field paths").into());
+        }
+        if self.window_size < 2 {
+            return Err(LiminalError::invalid_parameter("window_size", format!("must be at least 2 (got {})", self.window_size)).into());
+        }
+        if !(self.alpha > 0.0 && self.alpha <= 1.0) {
+            return Err(LiminalError::invalid_parameter("alpha", format!("must be in (0, 1] (got {})", self.alpha)).into());
+        }
+        if self.threshold <= 0.0 {
+            return Err(LiminalError::invalid_parameter("threshold", format!("must be positive (got {})", self.threshold)).into());
+        }
+        if self.flat_tolerance < 0.0 {
+            return Err(LiminalError::invalid_parameter("flat_tolerance", "must not be negative").into());
+        }
+        if self.min_samples < 2 {
+            return Err(LiminalError::invalid_parameter("min_samples", format!("must be at least 2 (got {})", self.min_samples)).into());
+        }
+        Ok(())
+    }
+}
+
+/// Running statistics for one (key, field) series.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct SeriesState {
+    window: VecDeque<f64>,
+    ewma_mean: f64,
+    ewma_var: f64,
+    count: usize,
+    /// Consecutive anomalies not yet learned
+    #[serde(default)]
+    streak: Vec<f64>,
+}
+
+impl SeriesState {
+    /// Score a sample against the current baseline without updating it.
+    /// Returns `None` while warming up, and an infinite score for a reading
+    /// that moves away from a baseline with no spread.
+    fn score(&self, config: &AnomalyConfig, value: f64) -> Option<f64> {
+        if self.count < config.min_samples {
+            return None;
+        }
+
+        let (center, spread) = match config.method {
+            AnomalyMethod::Zscore => {
+                let n = self.window.len() as f64;
+                let mean = self.window.iter().sum::<f64>() / n;
+                let var = self.window.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1.0);
+                (mean, var.sqrt())
+            }
+            AnomalyMethod::Mad => {
+                let window: Vec<f64> = self.window.iter().copied().collect();
+                let median = stats::median(&window)?;
+                let mad = stats::median_abs_deviation(&window, median)?;
+                (median, mad / MAD_CONSISTENCY)
+            }
+            AnomalyMethod::Ewma => (self.ewma_mean, self.ewma_var.sqrt()),
+        };
+
+        let deviation = (value - center).abs();
+        if spread <= f64::EPSILON {
+            return Some(if deviation > config.flat_tolerance { f64::INFINITY } else { 0.0 });
+        }
+
+        Some(deviation / spread)
+    }
+
+    fn learn(&mut self, config: &AnomalyConfig, value: f64) {
+        if self.count == 0 {
+            self.ewma_mean = value;
+            self.ewma_var = 0.0;
+        } else {
+            let diff = value - self.ewma_mean;
+            self.ewma_mean += config.alpha * diff;
+            self.ewma_var = (1.0 - config.alpha) * (self.ewma_var + config.alpha * diff * diff);
+        }
+
+        self.window.push_back(value);
+        while self.window.len() > config.window_size {
+            self.window.pop_front();
+        }
+        self.count += 1;
+    }
+
+    /// Record an anomaly that is kept out of the baseline. Once enough have
+    /// arrived in a row the baseline restarts from them.
+    fn hold(&mut self, config: &AnomalyConfig, value: f64) {
+        self.streak.push(value);
+        if config.relearn_after == 0 || self.streak.len() < config.relearn_after {
+            return;
+        }
+
+        debug!("{} consecutive anomalies, restarting the baseline", self.streak.len());
+        let streak = std::mem::take(&mut self.streak);
+        *self = Self::default();
+        for value in streak {
+            self.learn(config, value);
+        }
+    }
+}
+
+pub struct AnomalyProcessor {
+    name: String,
+    config: AnomalyConfig,
+    timing: TimingMixin,
+    series: StateStore<(String, String), SeriesState>,
+}
+
+impl AnomalyProcessor {
+    pub const METADATA: ProcessorMetadata = ProcessorMetadata {
+        name: "anomaly",
+        description: "Detects statistical anomalies (z-score, MAD, EWMA)",
+        parameters: &[
+            ParamSpec::new("fields", ParamType::Array, "Numeric fields to check"),
+            ParamSpec::new("field_in", ParamType::String, "A single field to check, in addition to fields"),
+            ParamSpec::new("key_field", ParamType::String, "Field whose value keeps separate statistics"),
+            ParamSpec::new("method", ParamType::Choice(&["zscore", "mad", "ewma"]), "Detection method"),
+            ParamSpec::new("window_size", ParamType::Integer, "Samples kept for the zscore and mad methods"),
+            ParamSpec::new("alpha", ParamType::Number, "Smoothing factor of the ewma method"),
+            ParamSpec::new("threshold", ParamType::Number, "Score above which a reading is anomalous"),
+            ParamSpec::new("min_samples", ParamType::Integer, "Samples needed before scoring starts"),
+            ParamSpec::new("action", ParamType::Choice(&["tag", "only_anomalies", "drop_anomalies"]), "What to do with anomalous readings"),
+            ParamSpec::new("anomaly_field", ParamType::String, "Field receiving the anomaly flag"),
+            ParamSpec::new("score_field", ParamType::String, "Field receiving the per-field scores"),
+            ParamSpec::new("learn_anomalies", ParamType::Boolean, "Include anomalous readings in the statistics"),
+            ParamSpec::new("flat_tolerance", ParamType::Number, "Deviation from a baseline with no spread that counts as anomalous"),
+            ParamSpec::new("relearn_after", ParamType::Integer, "Consecutive anomalies after which the baseline restarts (0 disables)"),
+        ],
+        shared: &[],
+    };
+
+    pub fn new(name: &str, config: StageConfig) -> Result<Box<dyn Processor>> {
+        let processor_config = AnomalyConfig::from_stage_config(&config)?;
+        processor_config.validate()?;
+
+        let timing = TimingMixin::new(processor_config.timing.as_ref());
+
+        Ok(Box::new(Self {
+            name: name.to_string(),
+            config: processor_config,
+            timing,
+            series: StateStore::open(name, config.state.as_ref())?,
+        }))
+    }
+
+    fn process_message(&mut self, mut message: Message) -> Result<Option<Message>> {
+        let key = FieldUtils::extract_key(&message.payload, self.config.key_field.as_deref());
+
+        let mut is_anomaly = false;
+        let mut scores = Map::new();
+
+        for field in &self.config.fields {
+            let Some(value) = FieldUtils::extract_f64(&message.payload, field) else {
+                continue;
+            };
+
+            let state = self
+                .series
+                .get_or_insert_with((key.clone(), field.clone()), SeriesState::default);
+
+            let score = state.score(&self.config, value);
+            let field_anomaly = score.is_some_and(|s| s > self.config.threshold);
+
+            if let Some(score) = score.and_then(Number::from_f64) {
+                scores.insert(field.clone(), Value::Number(score));
+            }
+
+            if field_anomaly {
+                debug!("Anomaly on '{}' for key '{}': value {} (score {:?})", field, key, value, score);
+                is_anomaly = true;
+            }
+
+            if !field_anomaly || self.config.learn_anomalies {
+                state.streak.clear();
+                state.learn(&self.config, value);
+            } else {
+                state.hold(&self.config, value);
+            }
+        }
+
+        match self.config.action {
+            AnomalyAction::OnlyAnomalies if !is_anomaly => return Ok(None),
+            AnomalyAction::DropAnomalies if is_anomaly => return Ok(None),
+            AnomalyAction::DropAnomalies => {}
+            AnomalyAction::Tag | AnomalyAction::OnlyAnomalies => {
+                FieldUtils::set_field_value(
+                    &mut message.payload,
+                    &self.config.anomaly_field,
+                    Value::Bool(is_anomaly),
+                )?;
+                FieldUtils::set_field_value(
+                    &mut message.payload,
+                    &self.config.score_field,
+                    Value::Object(scores),
+                )?;
+            }
+        }
+
+        message.source = self.name.clone();
+        Ok(Some(message))
+    }
+}
+
+#[async_trait::async_trait]
+impl Processor for AnomalyProcessor {
+    async fn init(&mut self) -> Result<()> {
+        tracing::info!(
+            "Anomaly processor '{}' initialised ({:?} on {:?}, threshold {})",
+            self.name,
+            self.config.method,
+            self.config.fields,
+            self.config.threshold
+        );
+        Ok(())
+    }
+
+    async fn process(&mut self, context: &mut ProcessingContext) -> Result<()> {
+        if let Some((channel_name, message)) = context.recv(tokio::time::Duration::from_millis(10)).await {
+            match self.process_message(message) {
+                Ok(Some(mut output_message)) => {
+                    if let Some(output_info) = &context.output {
+                        output_message.topic = output_info.name.clone();
+                        let output_message = self.timing.update_message_watermark(output_message);
+
+                        if let Err(e) = output_info.channel.publish(output_message).await {
+                            tracing::warn!("Failed to publish anomaly output: {:?}", e);
+                        }
+                    }
+                }
+                Ok(None) => {
+                    debug!("Message from '{}' filtered by anomaly processor", channel_name);
+                }
+                Err(e) => {
+                    error!("Failed to score message: {}", e);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn as_snapshot(&mut self) -> Option<&mut dyn Snapshot> {
+        Some(self)
+    }
+}
+
+impl Snapshot for AnomalyProcessor {
+    fn snapshot(&self) -> Result<Value> {
+        self.series.snapshot()
+    }
+
+    fn restore(&mut self, state: Value) -> Result<()> {
+        self.series.restore(state)
+    }
+}
+
+impl WithTimingMixin for AnomalyProcessor {
+    fn timing_mixin(&self) -> &TimingMixin {
+        &self.timing
+    }
+
+    fn timing_mixin_mut(&mut self) -> &mut TimingMixin {
+        &mut self.timing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn processor(parameters: Value) -> AnomalyProcessor {
+        let stage = StageConfig {
+            r#type: "anomaly".to_string(),
+            parameters: serde_json::from_value(parameters).ok(),
+            ..Default::default()
+        };
+        AnomalyProcessor {
+            name: "anomaly".to_string(),
+            config: AnomalyConfig::from_stage_config(&stage).unwrap(),
+            timing: TimingMixin::new(None),
+            series: StateStore::new(),
+        }
+    }
+
+    fn is_anomaly(processor: &mut AnomalyProcessor, value: f64) -> bool {
+        let message = Message::new("src", "topic", json!({ "value": value }));
+        let tagged = processor.process_message(message).unwrap().unwrap();
+        tagged.payload["anomaly"].as_bool().unwrap()
+    }
+
+    #[test]
+    fn test_anomaly_jump_after_flat_baseline() {
+        for method in ["zscore", "mad", "ewma"] {
+            let mut anomaly = processor(json!({ "field_in": "value", "method": method, "min_samples": 5 }));
+            for _ in 0..10 {
+                assert!(!is_anomaly(&mut anomaly, 20.0), "{method}");
+            }
+            assert!(!is_anomaly(&mut anomaly, 20.0), "{method}");
+            assert!(is_anomaly(&mut anomaly, 25.0), "{method}");
+        }
+    }
+
+    #[test]
+    fn test_anomaly_baseline_relearns_after_level_shift() {
+        let mut anomaly = processor(json!({
+            "field_in": "value",
+            "min_samples": 5,
+            "relearn_after": 4,
+        }));
+        for i in 0..20 {
+            is_anomaly(&mut anomaly, 10.0 + (i % 3) as f64 * 0.1);
+        }
+
+        // The first readings at the new level are anomalous; the baseline then
+        // restarts from them and warms up at the new level
+        for _ in 0..4 {
+            assert!(is_anomaly(&mut anomaly, 50.0));
+        }
+        for _ in 0..10 {
+            assert!(!is_anomaly(&mut anomaly, 50.0));
+        }
+        assert!(is_anomaly(&mut anomaly, 10.0));
+    }
+}
@@ -0,0 +1,440 @@
+//! Avro Transforms
+//!
+//! `avro_decode` converts binary Avro payloads to JSON, and `avro_encode` does
+//! the reverse. Available with the `avro` Cargo feature.
+//!
+//! The schema is given inline (`schema`) or as a file (`schema_file`), or is
+//! looked up in a Confluent Schema Registry (`registry_url`). With a registry,
+//! payloads use the Confluent wire format that Kafka clients expect: a zero
+//! magic byte and the 4-byte big-endian schema id, then the Avro datum. The
+//! decoder fetches each writer schema by id once and caches it; a local schema
+//! given alongside the registry is used as the reader schema, so old and new
+//! records resolve to the same shape. The encoder registers its local schema
+//! under `subject`, or without one uses the subject's latest schema.
+//!
+//! As with the protobuf transforms, binary payloads are carried as base64
+//! strings marked with `content_encoding = "base64"` metadata, and `field`
+//! converts a single payload field in place.
+
+use crate::config::types::CredentialsConfig;
+use crate::config::{ParamSpec, ParamType, ProcessorConfig, ProcessorMetadata, StageConfig, extract_param};
+use crate::core::message::{CONTENT_ENCODING, ENCODING_BASE64};
+use crate::core::timing_mixin::{TimingMixin, WithTimingMixin};
+use crate::core::{context::ProcessingContext, message::Message};
+use crate::processors::common::field_utils::FieldUtils;
+use crate::processors::common::{CREDENTIALS_PARAMS, credentials};
+use crate::processors::processor::Processor;
+use crate::error::LiminalError;
+
+use anyhow::{Result, anyhow};
+use apache_avro::Schema;
+use apache_avro::types::Value as AvroValue;
+use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use tracing::error;
+
+/// First byte of a Confluent-framed payload
+const MAGIC_BYTE: u8 = 0;
+
+#[derive(Debug, Clone)]
+pub struct AvroConfig {
+    /// Inline Avro schema (JSON)
+    pub schema: Option<String>,
+    /// File holding the Avro schema
+    pub schema_file: Option<String>,
+    /// Confluent Schema Registry base URL
+    pub registry_url: Option<String>,
+    /// Registry subject to encode with
+    pub subject: Option<String>,
+    /// Registry credentials: a username and password, or a token
+    pub credentials: CredentialsConfig,
+    /// Payload field to convert in place (the whole payload when unset)
+    pub field: Option<String>,
+    pub timing: Option<crate::config::TimingConfig>,
+}
+
+impl ProcessorConfig for AvroConfig {
+    fn from_stage_config(config: &StageConfig) -> Result<Self> {
+        let config = Self {
+            schema: extract_param(&config.parameters, "schema", None::<String>),
+            schema_file: extract_param(&config.parameters, "schema_file", None::<String>),
+            registry_url: extract_param(&config.parameters, "registry_url", None::<String>),
+            subject: extract_param(&config.parameters, "subject", None::<String>),
+            credentials: credentials(&config.parameters),
+            field: extract_param(&config.parameters, "field", None::<String>),
+            timing: config.timing.clone(),
+        };
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.schema.is_some() && self.schema_file.is_some() {
+            return Err(anyhow!("avro processors take either 'schema' or 'schema_file', not both"));
+        }
+        if self.schema.is_none() && self.schema_file.is_none() && self.registry_url.is_none() {
+            return Err(anyhow!("avro processors require 'schema', 'schema_file' or 'registry_url'"));
+        }
+        if self.field.as_ref().is_some_and(|field| field.is_empty()) {
+            return Err(LiminalError::invalid_parameter("field", "cannot be empty").into());
+        }
+        Ok(())
+    }
+}
+
+/// A Confluent Schema Registry client caching the schemas it has fetched.
+struct SchemaRegistry {
+    url: String,
+    credentials: CredentialsConfig,
+    client: reqwest::Client,
+    schemas: HashMap<u32, Schema>,
+}
+
+#[derive(Deserialize)]
+struct RegistrySchema {
+    #[serde(default)]
+    id: Option<u32>,
+    #[serde(default)]
+    schema: Option<String>,
+}
+
+impl SchemaRegistry {
+    fn new(url: &str, credentials: CredentialsConfig) -> Self {
+        Self {
+            url: url.trim_end_matches('/').to_string(),
+            credentials,
+            client: reqwest::Client::new(),
+            schemas: HashMap::new(),
+        }
+    }
+
+    async fn request(&self, request: reqwest::RequestBuilder) -> Result<RegistrySchema> {
+        let request = match (&self.credentials.username, &self.credentials.token) {
+            (Some(username), _) => request.basic_auth(username, self.credentials.password.as_ref()),
+            (None, Some(token)) => request.bearer_auth(token),
+            (None, None) => request,
+        };
+        let response = request
+            .header("Accept", "application/vnd.schemaregistry.v1+json")
+            .send()
+            .await
+            .map_err(|e| anyhow!("schema registry '{}' is unreachable: {}", self.url, e))?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(anyhow!("schema registry returned {}: {}", status, body));
+        }
+        Ok(response.json().await?)
+    }
+
+    /// The schema registered under `id`.
+    async fn schema(&mut self, id: u32) -> Result<&Schema> {
+        if !self.schemas.contains_key(&id) {
+            let found = self.request(self.client.get(format!("{}/schemas/ids/{}", self.url, id))).await?;
+            let schema = found.schema.ok_or_else(|| anyhow!("schema registry returned no schema for id {}", id))?;
+            self.schemas.insert(id, Schema::parse_str(&schema)?);
+        }
+        Ok(&self.schemas[&id])
+    }
+
+    /// Register `schema` under `subject`, returning its id. Registering a
+    /// schema the subject already has returns the existing id.
+    async fn register(&self, subject: &str, schema: &Schema) -> Result<u32> {
+        let body = json!({ "schema": schema.canonical_form() });
+        let url = format!("{}/subjects/{}/versions", self.url, subject);
+        let registered = self.request(self.client.post(url).json(&body)).await?;
+        registered.id.ok_or_else(|| anyhow!("schema registry returned no id for subject '{}'", subject))
+    }
+
+    /// The id and schema of the latest version of `subject`.
+    async fn latest(&self, subject: &str) -> Result<(u32, Schema)> {
+        let url = format!("{}/subjects/{}/versions/latest", self.url, subject);
+        let latest = self.request(self.client.get(url)).await?;
+        match (latest.id, latest.schema) {
+            (Some(id), Some(schema)) => Ok((id, Schema::parse_str(&schema)?)),
+            _ => Err(anyhow!("schema registry returned no schema for subject '{}'", subject)),
+        }
+    }
+}
+
+/// Which way an Avro stage converts payloads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    Decode,
+    Encode,
+}
+
+const AVRO_PARAMS: &[ParamSpec] = &[
+    ParamSpec::new("schema", ParamType::String, "Inline Avro schema (JSON)"),
+    ParamSpec::new("schema_file", ParamType::String, "File holding the Avro schema"),
+    ParamSpec::new("registry_url", ParamType::String, "Confluent Schema Registry URL (Confluent wire format)"),
+    ParamSpec::new("subject", ParamType::String, "Registry subject to encode with"),
+    ParamSpec::new("field", ParamType::String, "Payload field to convert in place (whole payload when unset)"),
+];
+
+pub struct AvroProcessor {
+    name: String,
+    config: AvroConfig,
+    direction: Direction,
+    /// Local schema, the reader schema when decoding with a registry
+    schema: Option<Schema>,
+    registry: Option<SchemaRegistry>,
+    /// Registry id and schema the encoder writes with, once looked up
+    writer: Option<(u32, Schema)>,
+    timing: TimingMixin,
+}
+
+impl AvroProcessor {
+    pub const DECODE_METADATA: ProcessorMetadata = ProcessorMetadata {
+        name: "avro_decode",
+        description: "Decodes binary Avro payloads to JSON, optionally via a schema registry",
+        parameters: AVRO_PARAMS,
+        shared: &[CREDENTIALS_PARAMS],
+    };
+
+    pub const ENCODE_METADATA: ProcessorMetadata = ProcessorMetadata {
+        name: "avro_encode",
+        description: "Encodes JSON payloads as binary Avro, optionally via a schema registry",
+        parameters: AVRO_PARAMS,
+        shared: &[CREDENTIALS_PARAMS],
+    };
+
+    pub fn new_decoder(name: &str, config: StageConfig) -> Result<Box<dyn Processor>> {
+        Ok(Box::new(Self::build(name, config, Direction::Decode)?))
+    }
+
+    pub fn new_encoder(name: &str, config: StageConfig) -> Result<Box<dyn Processor>> {
+        Ok(Box::new(Self::build(name, config, Direction::Encode)?))
+    }
+
+    fn build(name: &str, config: StageConfig, direction: Direction) -> Result<Self> {
+        let processor_config = AvroConfig::from_stage_config(&config)?;
+        if direction == Direction::Encode
+            && processor_config.registry_url.is_some()
+            && processor_config.subject.is_none()
+        {
+            return Err(anyhow!("avro_encode requires 'subject' with 'registry_url'"));
+        }
+
+        let schema = match (&processor_config.schema, &processor_config.schema_file) {
+            (Some(schema), _) => Some(Schema::parse_str(schema).map_err(|e| anyhow!("Invalid Avro schema: {}", e))?),
+            (None, Some(path)) => {
+                let schema = std::fs::read_to_string(path)
+                    .map_err(|e| anyhow!("Failed to read Avro schema '{}': {}", path, e))?;
+                Some(Schema::parse_str(&schema).map_err(|e| anyhow!("Invalid Avro schema '{}': {}", path, e))?)
+            }
+            (None, None) => None,
+        };
+
+        let timing = TimingMixin::new(processor_config.timing.as_ref());
+
+        Ok(Self {
+            name: name.to_string(),
+            registry: processor_config
+                .registry_url
+                .as_deref()
+                .map(|url| SchemaRegistry::new(url, processor_config.credentials.clone())),
+            config: processor_config,
+            direction,
+            schema,
+            writer: None,
+            timing,
+        })
+    }
+
+    /// Decode a base64 string of Avro bytes to JSON.
+    async fn decode(&mut self, value: &Value) -> Result<Value> {
+        let encoded = value.as_str().ok_or_else(|| anyhow!("expected a base64 string, got {}", value))?;
+        let bytes = BASE64.decode(encoded).map_err(|e| anyhow!("invalid base64: {}", e))?;
+
+        let decoded = match &mut self.registry {
+            Some(registry) => {
+                let (id, mut datum) = match bytes.as_slice() {
+                    [MAGIC_BYTE, a, b, c, d, datum @ ..] => (u32::from_be_bytes([*a, *b, *c, *d]), datum),
+                    _ => return Err(anyhow!("payload is not in the Confluent wire format")),
+                };
+                let writer = registry.schema(id).await?;
+                apache_avro::from_avro_datum(writer, &mut datum, self.schema.as_ref())?
+            }
+            None => {
+                let schema = self.schema.as_ref().ok_or_else(|| anyhow!("no Avro schema configured"))?;
+                apache_avro::from_avro_datum(schema, &mut bytes.as_slice(), None)?
+            }
+        };
+        Ok(Value::try_from(decoded)?)
+    }
+
+    /// Encode JSON as a base64 string of Avro bytes.
+    async fn encode(&mut self, value: &Value) -> Result<Value> {
+        let bytes = match &self.registry {
+            Some(registry) => {
+                let (id, schema) = match &mut self.writer {
+                    Some(writer) => writer,
+                    None => {
+                        let subject = self.config.subject.as_deref().unwrap_or_default();
+                        let writer = match &self.schema {
+                            Some(schema) => (registry.register(subject, schema).await?, schema.clone()),
+                            None => registry.latest(subject).await?,
+                        };
+                        self.writer.insert(writer)
+                    }
+                };
+                let datum = apache_avro::to_avro_datum(schema, AvroValue::from(value.clone()).resolve(schema)?)?;
+                let mut bytes = vec![MAGIC_BYTE];
+                bytes.extend_from_slice(&id.to_be_bytes());
+                bytes.extend(datum);
+                bytes
+            }
+            None => {
+                let schema = self.schema.as_ref().ok_or_else(|| anyhow!("no Avro schema configured"))?;
+                apache_avro::to_avro_datum(schema, AvroValue::from(value.clone()).resolve(schema)?)?
+            }
+        };
+        Ok(Value::String(BASE64.encode(bytes)))
+    }
+
+    async fn convert(&mut self, value: &Value) -> Result<Value> {
+        match self.direction {
+            Direction::Decode => self.decode(value).await,
+            Direction::Encode => self.encode(value).await,
+        }
+    }
+
+    async fn process_message(&mut self, mut message: Message) -> Result<Message> {
+        match self.config.field.clone() {
+            Some(field) => {
+                let value = FieldUtils::extract_field_value(&message.payload, &field)
+                    .ok_or_else(|| anyhow!("payload has no field '{}'", field))?
+                    .clone();
+                let converted = self.convert(&value).await?;
+                FieldUtils::set_field_value(&mut message.payload, &field, converted)?;
+            }
+            None => {
+                message.payload = self.convert(&message.payload).await?;
+                match self.direction {
+                    Direction::Decode => message.metadata.remove(CONTENT_ENCODING),
+                    Direction::Encode => message.metadata.insert(CONTENT_ENCODING.to_string(), ENCODING_BASE64.to_string()),
+                };
+            }
+        }
+
+        message.source = self.name.clone();
+        Ok(message)
+    }
+}
+
+#[async_trait::async_trait]
+impl Processor for AvroProcessor {
+    async fn init(&mut self) -> Result<()> {
+        tracing::info!(
+            "Avro processor '{}' initialised ({:?}, registry: {:?})",
+            self.name,
+            self.direction,
+            self.config.registry_url
+        );
+        Ok(())
+    }
+
+    async fn process(&mut self, context: &mut ProcessingContext) -> Result<()> {
+        if let Some((channel_name, message)) = context.recv(tokio::time::Duration::from_millis(10)).await {
+            match self.process_message(message).await {
+                Ok(mut output_message) => {
+                    if let Some(output_info) = &context.output {
+                        output_message.topic = output_info.name.clone();
+                        let output_message = self.timing.update_message_watermark(output_message);
+
+                        if let Err(e) = output_info.channel.publish(output_message).await {
+                            tracing::warn!("Failed to publish avro output: {:?}", e);
+                        }
+                    }
+                }
+                Err(e) => {
+                    error!("Avro stage '{}' dropped a message from '{}': {}", self.name, channel_name, e);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl WithTimingMixin for AvroProcessor {
+    fn timing_mixin(&self) -> &TimingMixin {
+        &self.timing
+    }
+
+    fn timing_mixin_mut(&mut self) -> &mut TimingMixin {
+        &mut self.timing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::extract::{Path, State};
+    use axum::routing::{get, post};
+    use axum::{Json, Router};
+    use std::sync::{Arc, Mutex};
+
+    const READING: &str = r#"{
+        "type": "record", "name": "Reading",
+        "fields": [
+            { "name": "device_id", "type": "string" },
+            { "name": "temperature", "type": "double" },
+            { "name": "unit", "type": ["null", "string"], "default": null }
+        ]
+    }"#;
+
+    fn stage(kind: &str, parameters: Value) -> StageConfig {
+        StageConfig {
+            r#type: kind.to_string(),
+            parameters: serde_json::from_value(parameters).ok(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_avro_roundtrip_through_a_schema_registry() {
+        // A registry that assigns id 7 to whatever is registered
+        let registered: Arc<Mutex<Option<String>>> = Arc::default();
+        let registry = Router::new()
+            .route(
+                "/subjects/{subject}/versions",
+                post(|State(registered): State<Arc<Mutex<Option<String>>>>, Json(body): Json<Value>| async move {
+                    *registered.lock().unwrap() = body["schema"].as_str().map(str::to_string);
+                    Json(json!({ "id": 7 }))
+                }),
+            )
+            .route(
+                "/schemas/ids/{id}",
+                get(|State(registered): State<Arc<Mutex<Option<String>>>>, Path(id): Path<u32>| async move {
+                    assert_eq!(id, 7);
+                    Json(json!({ "schema": registered.lock().unwrap().clone() }))
+                }),
+            )
+            .with_state(Arc::clone(&registered));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, registry).await });
+
+        let parameters = json!({ "schema": READING, "registry_url": url, "subject": "readings-value" });
+        let mut encoder = AvroProcessor::build("encode", stage("avro_encode", parameters), Direction::Encode).unwrap();
+        let parameters = json!({ "registry_url": url });
+        let mut decoder = AvroProcessor::build("decode", stage("avro_decode", parameters), Direction::Decode).unwrap();
+
+        let reading = json!({ "device_id": "boiler-1", "temperature": 71.5, "unit": "C" });
+        let encoded = encoder.process_message(Message::new("src", "topic", reading.clone())).await.unwrap();
+        assert_eq!(encoded.get_metadata(CONTENT_ENCODING), Some(ENCODING_BASE64));
+        let bytes = BASE64.decode(encoded.payload.as_str().unwrap()).unwrap();
+        assert_eq!(bytes[..5], [MAGIC_BYTE, 0, 0, 0, 7]);
+
+        let decoded = decoder.process_message(encoded).await.unwrap();
+        assert_eq!(decoded.payload, reading);
+        assert_eq!(decoded.get_metadata(CONTENT_ENCODING), None);
+
+        let unframed = Message::new("src", "topic", json!(BASE64.encode([2, 4, 6])));
+        assert!(decoder.process_message(unframed).await.is_err());
+    }
+}
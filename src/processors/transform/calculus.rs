@@ -0,0 +1,424 @@
+//! Calculus Transform
+//!
+//! Computes the rate of change (derivative) and/or the cumulative integral of a
+//! numeric field, keeping independent state per key. Time deltas are taken from
+//! message event time, so the results are correct even when messages are delayed
+//! in transit.
+//!
+//! Typical uses are flow rate from totaliser counters (derivative) and energy from
+//! power readings (integral).
+
+use crate::config::{ParamSpec, ParamType, ProcessorConfig, ProcessorMetadata, StageConfig, extract_param, require_param};
+use crate::core::checkpoint::Snapshot;
+use crate::core::state::StateStore;
+use crate::core::timing_mixin::{TimingMixin, WithTimingMixin};
+use crate::core::{context::ProcessingContext, message::Message};
+use crate::processors::common::field_utils::FieldUtils;
+use crate::processors::processor::Processor;
+use crate::error::LiminalError;
+
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use serde_json::{Number, Value};
+use std::time::{Duration, SystemTime};
+use tracing::{debug, error};
+
+/// Which quantities the calculus transform computes.
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CalculusMode {
+    Derivative,
+    Integral,
+    Both,
+}
+
+/// Numerical integration method.
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum IntegrationMethod {
+    /// Average of the previous and current sample times the time delta
+    Trapezoidal,
+    /// Previous sample held constant over the time delta (zero-order hold)
+    Left,
+}
+
+/// Policy that decides when per-key state is discarded.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ResetPolicy {
+    /// Never reset; the integral accumulates for the lifetime of the stage
+    Never,
+    /// Reset when the value decreases (counter rollover or device reboot)
+    OnDecrease,
+    /// Reset when the gap between two samples exceeds `max_gap_ms`
+    Gap { max_gap_ms: u64 },
+    /// Reset the integral every `interval_ms` of event time
+    Interval { interval_ms: u64 },
+}
+
+#[derive(Debug, Clone)]
+pub struct CalculusConfig {
+    pub field_in: String,
+    pub mode: CalculusMode,
+    pub derivative_field: String,
+    pub integral_field: String,
+    pub key_field: Option<String>,
+    pub method: IntegrationMethod,
+    pub rate_scale: f64,
+    pub integral_scale: f64,
+    pub reset: ResetPolicy,
+    pub timing: Option<crate::config::TimingConfig>,
+}
+
+impl ProcessorConfig for CalculusConfig {
+    fn from_stage_config(config: &StageConfig) -> Result<Self> {
+        let field_in = require_param::<String>(&config.parameters, "field_in")?;
+
+        let mode = extract_param(&config.parameters, "mode", CalculusMode::Derivative);
+        let derivative_field = extract_param(
+            &config.parameters,
+            "derivative_field",
+            format!("{}_rate", field_in),
+        );
+        let integral_field = extract_param(
+            &config.parameters,
+            "integral_field",
+            format!("{}_integral", field_in),
+        );
+        let key_field = extract_param(&config.parameters, "key_field", None::<String>);
+        let method = extract_param(&config.parameters, "method", IntegrationMethod::Trapezoidal);
+        let rate_scale = extract_param(&config.parameters, "rate_scale", 1.0);
+        let integral_scale = extract_param(&config.parameters, "integral_scale", 1.0);
+        let reset = extract_param(&config.parameters, "reset", ResetPolicy::Never);
+
+        let config = Self {
+            field_in,
+            mode,
+            derivative_field,
+            integral_field,
+            key_field,
+            method,
+            rate_scale,
+            integral_scale,
+            reset,
+            timing: config.timing.clone(),
+        };
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.field_in.is_empty() {
+            return Err(LiminalError::invalid_parameter("field_in", "cannot be empty").into());
+        }
+        if self.derivative_field.is_empty() || self.integral_field.is_empty() {
+            return Err(anyhow!("derivative_field and integral_field cannot be empty"));
+        }
+        match self.reset {
+            ResetPolicy::Gap { max_gap_ms: 0 } => {
+                return Err(LiminalError::invalid_parameter("reset.max_gap_ms", "must be greater than 0").into());
+            }
+            ResetPolicy::Interval { interval_ms: 0 } => {
+                return Err(LiminalError::invalid_parameter("reset.interval_ms", "must be greater than 0").into());
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// Per-key running state.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CalculusState {
+    last_value: f64,
+    last_time: SystemTime,
+    integral: f64,
+    period_start: SystemTime,
+}
+
+pub struct CalculusProcessor {
+    name: String,
+    config: CalculusConfig,
+    timing: TimingMixin,
+    state: StateStore<String, CalculusState>,
+}
+
+impl CalculusProcessor {
+    pub const METADATA: ProcessorMetadata = ProcessorMetadata {
+        name: "calculus",
+        description: "Computes derivatives and integrals of numeric fields",
+        parameters: &[
+            ParamSpec::new("field_in", ParamType::String, "Numeric field to differentiate or integrate"),
+            ParamSpec::new("mode", ParamType::Choice(&["derivative", "integral", "both"]), "What to compute"),
+            ParamSpec::new("derivative_field", ParamType::String, "Field receiving the rate of change"),
+            ParamSpec::new("integral_field", ParamType::String, "Field receiving the running integral"),
+            ParamSpec::new("key_field", ParamType::String, "Field whose value keeps separate state"),
+            ParamSpec::new("method", ParamType::Choice(&["trapezoidal", "left"]), "Integration rule"),
+            ParamSpec::new("rate_scale", ParamType::Number, "Multiplier applied to the rate (per second by default)"),
+            ParamSpec::new("integral_scale", ParamType::Number, "Multiplier applied to the integral"),
+            ParamSpec::new("reset", ParamType::Object, "Integral reset policy: never, on_decrease, gap (max_gap_ms) or interval (interval_ms)"),
+        ],
+        shared: &[],
+    };
+
+    pub fn new(name: &str, config: StageConfig) -> Result<Box<dyn Processor>> {
+        let processor_config = CalculusConfig::from_stage_config(&config)?;
+        processor_config.validate()?;
+
+        let timing = TimingMixin::new(processor_config.timing.as_ref());
+
+        Ok(Box::new(Self {
+            name: name.to_string(),
+            config: processor_config,
+            timing,
+            state: StateStore::open(name, config.state.as_ref())?,
+        }))
+    }
+
+    fn wants_derivative(&self) -> bool {
+        self.config.mode != CalculusMode::Integral
+    }
+
+    fn wants_integral(&self) -> bool {
+        self.config.mode != CalculusMode::Derivative
+    }
+
+    /// Decide whether the previous state must be discarded before applying a new sample.
+    fn should_reset(&self, state: &CalculusState, value: f64, now: SystemTime) -> bool {
+        match &self.config.reset {
+            ResetPolicy::Never => false,
+            ResetPolicy::OnDecrease => value < state.last_value,
+            ResetPolicy::Gap { max_gap_ms } => {
+                now.duration_since(state.last_time).unwrap_or(Duration::ZERO)
+                    > Duration::from_millis(*max_gap_ms)
+            }
+            ResetPolicy::Interval { interval_ms } => {
+                now.duration_since(state.period_start).unwrap_or(Duration::ZERO)
+                    >= Duration::from_millis(*interval_ms)
+            }
+        }
+    }
+
+    fn process_message(&mut self, mut message: Message) -> Result<Message> {
+        let Some(value) = FieldUtils::extract_f64(&message.payload, &self.config.field_in) else {
+            debug!(
+                "Field '{}' missing or not numeric, passing message through",
+                self.config.field_in
+            );
+            return Ok(message);
+        };
+
+        let key = FieldUtils::extract_key(&message.payload, self.config.key_field.as_deref());
+        let now = message.timing.event_time;
+
+        let mut derivative = None;
+        let integral;
+
+        match self.state.get(&key).cloned() {
+            Some(previous) if !self.should_reset(&previous, value, now) => {
+                let dt = now
+                    .duration_since(previous.last_time)
+                    .unwrap_or(Duration::ZERO)
+                    .as_secs_f64();
+
+                let area = match self.config.method {
+                    IntegrationMethod::Trapezoidal => (previous.last_value + value) / 2.0 * dt,
+                    IntegrationMethod::Left => previous.last_value * dt,
+                };
+                integral = previous.integral + area * self.config.integral_scale;
+
+                // Out-of-order or duplicate timestamps carry no rate information
+                if dt > 0.0 {
+                    derivative = Some((value - previous.last_value) / dt * self.config.rate_scale);
+                }
+
+                self.state.insert(
+                    key,
+                    CalculusState {
+                        last_value: value,
+                        last_time: now.max(previous.last_time),
+                        integral,
+                        period_start: previous.period_start,
+                    },
+                );
+            }
+            previous => {
+                if previous.is_some() {
+                    debug!("Resetting calculus state for key '{}'", key);
+                }
+                integral = 0.0;
+                self.state.insert(
+                    key,
+                    CalculusState {
+                        last_value: value,
+                        last_time: now,
+                        integral,
+                        period_start: now,
+                    },
+                );
+            }
+        }
+
+        if self.wants_derivative()
+            && let Some(rate) = derivative.and_then(Number::from_f64)
+        {
+            FieldUtils::set_field_value(
+                &mut message.payload,
+                &self.config.derivative_field,
+                Value::Number(rate),
+            )?;
+        }
+
+        if self.wants_integral()
+            && let Some(total) = Number::from_f64(integral)
+        {
+            FieldUtils::set_field_value(
+                &mut message.payload,
+                &self.config.integral_field,
+                Value::Number(total),
+            )?;
+        }
+
+        message.source = self.name.clone();
+        Ok(message)
+    }
+}
+
+#[async_trait::async_trait]
+impl Processor for CalculusProcessor {
+    async fn init(&mut self) -> Result<()> {
+        tracing::info!(
+            "Calculus processor '{}' initialised ({:?} of '{}')",
+            self.name,
+            self.config.mode,
+            self.config.field_in
+        );
+        Ok(())
+    }
+
+    async fn process(&mut self, context: &mut ProcessingContext) -> Result<()> {
+        if let Some((channel_name, message)) = context.recv(tokio::time::Duration::from_millis(10)).await {
+            match self.process_message(message) {
+                Ok(mut output_message) => {
+                    if let Some(output_info) = &context.output {
+                        output_message.topic = output_info.name.clone();
+                        let output_message = self.timing.update_message_watermark(output_message);
+
+                        if let Err(e) = output_info.channel.publish(output_message).await {
+                            tracing::warn!("Failed to publish calculus output: {:?}", e);
+                        } else {
+                            debug!("Message from '{}' processed by calculus", channel_name);
+                        }
+                    }
+                }
+                Err(e) => {
+                    error!("Failed to apply calculus transform: {}", e);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn as_snapshot(&mut self) -> Option<&mut dyn Snapshot> {
+        Some(self)
+    }
+}
+
+impl Snapshot for CalculusProcessor {
+    fn snapshot(&self) -> Result<Value> {
+        self.state.snapshot()
+    }
+
+    fn restore(&mut self, state: Value) -> Result<()> {
+        self.state.restore(state)
+    }
+}
+
+impl WithTimingMixin for CalculusProcessor {
+    fn timing_mixin(&self) -> &TimingMixin {
+        &self.timing
+    }
+
+    fn timing_mixin_mut(&mut self) -> &mut TimingMixin {
+        &mut self.timing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::time::UNIX_EPOCH;
+
+    fn processor(parameters: Value) -> CalculusProcessor {
+        let stage = StageConfig {
+            r#type: "calculus".to_string(),
+            parameters: serde_json::from_value(parameters).ok(),
+            ..Default::default()
+        };
+        CalculusProcessor {
+            name: "calculus".to_string(),
+            config: CalculusConfig::from_stage_config(&stage).unwrap(),
+            timing: TimingMixin::new(None),
+            state: StateStore::new(),
+        }
+    }
+
+    fn reading(processor: &mut CalculusProcessor, meter: &str, seconds: u64, value: f64) -> Value {
+        let message = Message::new_with_event_time(
+            "src",
+            "topic",
+            json!({ "meter": meter, "power": value }),
+            UNIX_EPOCH + Duration::from_secs(seconds),
+        );
+        processor.process_message(message).unwrap().payload
+    }
+
+    #[test]
+    fn test_calculus_derivative_skips_zero_and_negative_time_deltas() {
+        let mut calculus = processor(json!({ "field_in": "power" }));
+
+        assert_eq!(reading(&mut calculus, "m1", 10, 100.0).get("power_rate"), None);
+        assert_eq!(reading(&mut calculus, "m1", 20, 150.0)["power_rate"], json!(5.0));
+
+        // A duplicate or older timestamp carries no rate, and does not move
+        // the clock back for the next reading
+        assert_eq!(reading(&mut calculus, "m1", 20, 160.0).get("power_rate"), None);
+        assert_eq!(reading(&mut calculus, "m1", 15, 170.0).get("power_rate"), None);
+        assert_eq!(reading(&mut calculus, "m1", 30, 190.0)["power_rate"], json!(2.0));
+    }
+
+    #[test]
+    fn test_calculus_trapezoidal_integral() {
+        let mut calculus = processor(json!({ "field_in": "power", "mode": "integral", "integral_scale": 0.5 }));
+
+        assert_eq!(reading(&mut calculus, "m1", 0, 10.0)["power_integral"], json!(0.0));
+        // (10 + 30) / 2 * 4 s = 80, scaled by 0.5
+        assert_eq!(reading(&mut calculus, "m1", 4, 30.0)["power_integral"], json!(40.0));
+        // + (30 + 10) / 2 * 2 s = 40, scaled by 0.5
+        assert_eq!(reading(&mut calculus, "m1", 6, 10.0)["power_integral"], json!(60.0));
+    }
+
+    #[test]
+    fn test_calculus_resets_each_key_independently() {
+        let mut calculus = processor(json!({
+            "field_in": "power",
+            "mode": "both",
+            "key_field": "meter",
+            "reset": { "type": "on_decrease" },
+        }));
+
+        reading(&mut calculus, "m1", 0, 10.0);
+        reading(&mut calculus, "m2", 0, 50.0);
+        assert_eq!(reading(&mut calculus, "m1", 10, 20.0)["power_integral"], json!(150.0));
+        assert_eq!(reading(&mut calculus, "m2", 10, 50.0)["power_integral"], json!(500.0));
+
+        // m1 decreases and starts over; m2 keeps accumulating
+        let reset = reading(&mut calculus, "m1", 20, 5.0);
+        assert_eq!(reset["power_integral"], json!(0.0));
+        assert_eq!(reset.get("power_rate"), None);
+        assert_eq!(reading(&mut calculus, "m2", 20, 50.0)["power_integral"], json!(1000.0));
+        assert_eq!(reading(&mut calculus, "m1", 30, 15.0)["power_integral"], json!(100.0));
+    }
+}
@@ -0,0 +1,394 @@
+//! Complex Event Processing Transform
+//!
+//! Detects ordered sequences of messages per key, such as "A then B within
+//! 30 s without C", and emits a synthetic event when a sequence completes. Each
+//! pattern lists its `steps` in order, an optional `within_ms` limit measured
+//! from the first step, and `without` conditions that abandon a partial match
+//! when seen. Matching skips unrelated messages between steps.
+//!
+//! Partial matches time out on event time as messages for the key arrive, and on
+//! wall-clock time when the key goes quiet. With `emit_timeouts` a timed-out
+//! match also produces an event recording the steps reached.
+//!
+//! ```toml
+//! [[pipelines.faults.stages.detect.parameters.patterns]]
+//! name = "pump_cavitation"
+//! within_ms = 30000
+//! steps = [
+//!     { name = "pressure_drop", field_path = "pressure", operation = "<", value = 1.2 },
+//!     { name = "vibration", field_path = "vibration", operation = ">", value = 8.0 },
+//! ]
+//! without = [{ field_path = "state", operation = "==", value = "maintenance" }]
+//! ```
+
+use crate::config::{ParamSpec, ParamType, ProcessorConfig, ProcessorMetadata, StageConfig, extract_param};
+use crate::core::timing::TimingHelpers;
+use crate::core::timing_mixin::{TimingMixin, WithTimingMixin};
+use crate::core::{context::ProcessingContext, message::Message};
+use crate::processors::common::condition_utils::{ConditionEvaluator, ConditionOperation};
+use crate::processors::common::field_utils::FieldUtils;
+use crate::processors::processor::Processor;
+
+use anyhow::{Result, anyhow};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::debug;
+
+/// A single field test against a message payload.
+#[derive(Debug, Clone, Deserialize)]
+pub struct EventCondition {
+    /// Optional step label used in emitted events
+    #[serde(default)]
+    pub name: Option<String>,
+    pub field_path: String,
+    pub operation: String,
+    #[serde(default)]
+    pub value: Value,
+}
+
+impl EventCondition {
+    fn matches(&self, payload: &Value) -> bool {
+        let Some(operation) = ConditionOperation::parse(&self.operation) else {
+            return false;
+        };
+        let field_value = FieldUtils::extract_field_value(payload, &self.field_path);
+        ConditionEvaluator::evaluate_optional(field_value, &operation, &self.value)
+    }
+
+    fn label(&self, index: usize) -> String {
+        self.name.clone().unwrap_or_else(|| format!("step_{}", index + 1))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Pattern {
+    pub name: String,
+    pub steps: Vec<EventCondition>,
+    /// Maximum time from the first step to the last
+    #[serde(default)]
+    pub within_ms: Option<u64>,
+    /// Conditions that abandon a partial match
+    #[serde(default)]
+    pub without: Vec<EventCondition>,
+    /// Emit an event when a partial match times out
+    #[serde(default)]
+    pub emit_timeouts: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct CepConfig {
+    pub patterns: Vec<Pattern>,
+    pub key_field: Option<String>,
+    pub timing: Option<crate::config::TimingConfig>,
+}
+
+impl ProcessorConfig for CepConfig {
+    fn from_stage_config(config: &StageConfig) -> Result<Self> {
+        let config = Self {
+            patterns: extract_param(&config.parameters, "patterns", Vec::<Pattern>::new()),
+            key_field: extract_param(&config.parameters, "key_field", None::<String>),
+            timing: config.timing.clone(),
+        };
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.patterns.is_empty() {
+            return Err(anyhow!("cep processor requires at least one pattern"));
+        }
+        for pattern in &self.patterns {
+            if pattern.name.is_empty() {
+                return Err(anyhow!("pattern name cannot be empty"));
+            }
+            if pattern.steps.is_empty() {
+                return Err(anyhow!("pattern '{}' must have at least one step", pattern.name));
+            }
+            if pattern.within_ms == Some(0) {
+                return Err(anyhow!("pattern '{}' within_ms must be greater than 0", pattern.name));
+            }
+            for condition in pattern.steps.iter().chain(&pattern.without) {
+                if condition.field_path.is_empty() {
+                    return Err(anyhow!("pattern '{}' has a condition with empty field_path", pattern.name));
+                }
+                if ConditionOperation::parse(&condition.operation).is_none() {
+                    return Err(anyhow!(
+                        "pattern '{}' has unsupported operation: '{}'",
+                        pattern.name,
+                        condition.operation
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A sequence in progress for one pattern and key.
+#[derive(Debug, Clone)]
+struct PartialMatch {
+    /// Event time of each matched step
+    matched: Vec<SystemTime>,
+    started: Instant,
+    /// Latest message in the sequence, used as the timing template for output
+    last: Message,
+}
+
+impl PartialMatch {
+    fn first_event_time(&self) -> SystemTime {
+        self.matched[0]
+    }
+}
+
+pub struct CepProcessor {
+    name: String,
+    config: CepConfig,
+    timing: TimingMixin,
+    /// Partial matches by pattern index and key
+    partials: HashMap<(usize, String), PartialMatch>,
+}
+
+impl CepProcessor {
+    pub const METADATA: ProcessorMetadata = ProcessorMetadata {
+        name: "cep",
+        description: "Detects ordered event sequences per key with time limits and negation",
+        parameters: &[
+            ParamSpec::new("patterns", ParamType::Array, "Event sequences to detect"),
+            ParamSpec::new("key_field", ParamType::String, "Field whose value keeps separate matches"),
+        ],
+        shared: &[],
+    };
+
+    pub fn new(name: &str, config: StageConfig) -> Result<Box<dyn Processor>> {
+        let processor_config = CepConfig::from_stage_config(&config)?;
+        processor_config.validate()?;
+
+        let timing = TimingMixin::new(processor_config.timing.as_ref());
+
+        Ok(Box::new(Self {
+            name: name.to_string(),
+            config: processor_config,
+            timing,
+            partials: HashMap::new(),
+        }))
+    }
+
+    fn expired(pattern: &Pattern, partial: &PartialMatch, event_time: Option<SystemTime>) -> bool {
+        let Some(within_ms) = pattern.within_ms else {
+            return false;
+        };
+        let within = Duration::from_millis(within_ms);
+
+        let event_time_expired = event_time
+            .and_then(|now| now.duration_since(partial.first_event_time()).ok())
+            .is_some_and(|elapsed| elapsed > within);
+        event_time_expired || partial.started.elapsed() > within
+    }
+
+    fn build_event(&self, pattern: &Pattern, key: &str, partial: &PartialMatch, status: &str) -> Message {
+        let steps: Vec<Value> = partial
+            .matched
+            .iter()
+            .enumerate()
+            .map(|(index, time)| {
+                json!({
+                    "step": pattern.steps[index].label(index),
+                    "event_time_ms": time.duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or_default(),
+                })
+            })
+            .collect();
+        let last_time = partial.matched.last().copied().unwrap_or(partial.first_event_time());
+        let duration_ms = last_time
+            .duration_since(partial.first_event_time())
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+
+        let mut payload = json!({
+            "pattern": pattern.name,
+            "status": status,
+            "steps": steps,
+            "duration_ms": duration_ms,
+        });
+        if let Some(key_field) = &self.config.key_field {
+            let _ = FieldUtils::set_field_value(&mut payload, key_field, Value::String(key.to_string()));
+        }
+        if partial.matched.len() < pattern.steps.len() {
+            let next = partial.matched.len();
+            payload["expected"] = Value::String(pattern.steps[next].label(next));
+        }
+
+        TimingHelpers::propagate_timing(&partial.last, &self.name, "", payload)
+    }
+
+    /// Advance every pattern with `message`, returning any events produced.
+    fn process_message(&mut self, message: &Message) -> Vec<Message> {
+        let key = FieldUtils::extract_key(&message.payload, self.config.key_field.as_deref());
+        let event_time = message.timing.event_time;
+        let mut events = Vec::new();
+
+        for index in 0..self.config.patterns.len() {
+            let pattern = &self.config.patterns[index];
+            let slot = (index, key.clone());
+
+            if let Some(partial) = self.partials.get(&slot) {
+                if Self::expired(pattern, partial, Some(event_time)) {
+                    let partial = self.partials.remove(&slot).expect("partial exists");
+                    if pattern.emit_timeouts {
+                        events.push(self.build_event(pattern, &key, &partial, "timed_out"));
+                    }
+                } else if pattern.without.iter().any(|c| c.matches(&message.payload)) {
+                    debug!("Pattern '{}' for key '{}' abandoned", pattern.name, key);
+                    self.partials.remove(&slot);
+                    continue;
+                }
+            }
+
+            let next_step = self.partials.get(&slot).map_or(0, |p| p.matched.len());
+            if !pattern.steps[next_step].matches(&message.payload) {
+                continue;
+            }
+
+            let partial = self.partials.entry(slot.clone()).or_insert_with(|| PartialMatch {
+                matched: Vec::new(),
+                started: Instant::now(),
+                last: message.clone(),
+            });
+            partial.matched.push(event_time);
+            partial.last = message.clone();
+
+            if partial.matched.len() == pattern.steps.len() {
+                let partial = self.partials.remove(&slot).expect("partial exists");
+                events.push(self.build_event(pattern, &key, &partial, "matched"));
+            }
+        }
+        events
+    }
+
+    /// Expire partial matches whose keys have gone quiet.
+    fn check_timeouts(&mut self) -> Vec<Message> {
+        let expired: Vec<(usize, String)> = self
+            .partials
+            .iter()
+            .filter(|((index, _), partial)| Self::expired(&self.config.patterns[*index], partial, None))
+            .map(|(slot, _)| slot.clone())
+            .collect();
+
+        let mut events = Vec::new();
+        for slot in expired {
+            let Some(partial) = self.partials.remove(&slot) else {
+                continue;
+            };
+            let pattern = &self.config.patterns[slot.0];
+            if pattern.emit_timeouts {
+                events.push(self.build_event(pattern, &slot.1, &partial, "timed_out"));
+            }
+        }
+        events
+    }
+}
+
+#[async_trait::async_trait]
+impl Processor for CepProcessor {
+    async fn init(&mut self) -> Result<()> {
+        tracing::info!(
+            "CEP processor '{}' initialised ({} patterns)",
+            self.name,
+            self.config.patterns.len()
+        );
+        Ok(())
+    }
+
+    async fn process(&mut self, context: &mut ProcessingContext) -> Result<()> {
+        let mut events = Vec::new();
+
+        if let Some((_, message)) = context.recv(tokio::time::Duration::from_millis(10)).await {
+            events.extend(self.process_message(&message));
+        }
+        events.extend(self.check_timeouts());
+
+        if let Some(output_info) = &context.output {
+            for mut event in events {
+                event.topic = output_info.name.clone();
+                let event = self.timing.update_message_watermark(event);
+
+                if let Err(e) = output_info.channel.publish(event).await {
+                    tracing::warn!("Failed to publish cep event: {:?}", e);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl WithTimingMixin for CepProcessor {
+    fn timing_mixin(&self) -> &TimingMixin {
+        &self.timing
+    }
+
+    fn timing_mixin_mut(&mut self) -> &mut TimingMixin {
+        &mut self.timing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(ms: u64, payload: Value) -> Message {
+        Message::new_with_event_time("src", "topic", payload, UNIX_EPOCH + Duration::from_millis(ms))
+    }
+
+    #[test]
+    fn test_cep_sequence_within_without() {
+        let config = StageConfig {
+            r#type: "cep".to_string(),
+            parameters: Some(HashMap::from([
+                ("key_field".to_string(), json!("device")),
+                (
+                    "patterns".to_string(),
+                    json!([{
+                        "name": "a_then_b",
+                        "within_ms": 30000,
+                        "emit_timeouts": true,
+                        "steps": [
+                            { "name": "a", "field_path": "kind", "operation": "==", "value": "A" },
+                            { "name": "b", "field_path": "kind", "operation": "==", "value": "B" },
+                        ],
+                        "without": [{ "field_path": "kind", "operation": "==", "value": "C" }],
+                    }]),
+                ),
+            ])),
+            ..Default::default()
+        };
+        let mut processor = CepProcessor {
+            name: "cep".to_string(),
+            config: CepConfig::from_stage_config(&config).unwrap(),
+            timing: TimingMixin::new(None),
+            partials: HashMap::new(),
+        };
+
+        // A, noise, B within the window: matched
+        assert!(processor.process_message(&event(0, json!({"device": "d1", "kind": "A"}))).is_empty());
+        assert!(processor.process_message(&event(5_000, json!({"device": "d1", "kind": "X"}))).is_empty());
+        let events = processor.process_message(&event(10_000, json!({"device": "d1", "kind": "B"})));
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].payload["status"], json!("matched"));
+        assert_eq!(events[0].payload["device"], json!("d1"));
+        assert_eq!(events[0].payload["duration_ms"], json!(10_000));
+
+        // A, C, B: abandoned by the negation
+        processor.process_message(&event(20_000, json!({"device": "d1", "kind": "A"})));
+        processor.process_message(&event(21_000, json!({"device": "d1", "kind": "C"})));
+        assert!(processor.process_message(&event(22_000, json!({"device": "d1", "kind": "B"}))).is_empty());
+
+        // A then B too late: timeout reported
+        processor.process_message(&event(40_000, json!({"device": "d1", "kind": "A"})));
+        let events = processor.process_message(&event(80_000, json!({"device": "d1", "kind": "B"})));
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].payload["status"], json!("timed_out"));
+        assert_eq!(events[0].payload["expected"], json!("b"));
+    }
+}
@@ -0,0 +1,265 @@
+//! Clock Skew Transform
+//!
+//! Estimates how far each device's clock is off, by comparing the event time
+//! it reports with the time its messages were ingested. The skew of a key is
+//! the median of `ingestion time - event time` over its last `window`
+//! messages, so it is positive when the device clock is behind, and includes
+//! the (usually small) transit delay. The estimate is exported as the
+//! `liminal_clock_skew_seconds` metric and, with `skew_field`, added to each
+//! payload in milliseconds.
+//!
+//! With `correct`, the event time of messages from keys whose skew exceeds
+//! `tolerance_ms` is shifted by the skew, so windows and watermarks
+//! downstream see them at the time they were (approximately) produced. Place
+//! the stage before any windowing, right after the source.
+
+use crate::config::{ParamSpec, ParamType, ProcessorConfig, ProcessorMetadata, StageConfig, extract_param};
+use crate::core::checkpoint::Snapshot;
+use crate::core::metrics::metrics;
+use crate::core::state::StateStore;
+use crate::core::timing_mixin::{TimingMixin, WithTimingMixin};
+use crate::core::{context::ProcessingContext, message::Message};
+use crate::processors::common::field_utils::FieldUtils;
+use crate::processors::processor::Processor;
+use crate::error::LiminalError;
+
+use anyhow::Result;
+use serde_json::{Value, json};
+use std::collections::VecDeque;
+use std::time::{Duration, SystemTime};
+use tracing::debug;
+
+#[derive(Debug, Clone)]
+pub struct ClockSkewConfig {
+    pub key_field: Option<String>,
+    /// Messages per key the skew is estimated over
+    pub window: usize,
+    /// Shift event times by the estimated skew
+    pub correct: bool,
+    /// Skew (in milliseconds, either way) below which event times are left alone
+    pub tolerance_ms: u64,
+    /// Payload field receiving the estimated skew in milliseconds
+    pub skew_field: Option<String>,
+    pub timing: Option<crate::config::TimingConfig>,
+}
+
+impl ProcessorConfig for ClockSkewConfig {
+    fn from_stage_config(config: &StageConfig) -> Result<Self> {
+        let config = Self {
+            key_field: extract_param(&config.parameters, "key_field", None::<String>),
+            window: extract_param(&config.parameters, "window", 50_usize),
+            correct: extract_param(&config.parameters, "correct", false),
+            tolerance_ms: extract_param(&config.parameters, "tolerance_ms", 1000_u64),
+            skew_field: extract_param(&config.parameters, "skew_field", None::<String>),
+            timing: config.timing.clone(),
+        };
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.window == 0 {
+            return Err(LiminalError::invalid_parameter("window", "must be greater than 0").into());
+        }
+        if self.skew_field.as_ref().is_some_and(|field| field.is_empty()) {
+            return Err(LiminalError::invalid_parameter("skew_field", "cannot be empty").into());
+        }
+        Ok(())
+    }
+}
+
+pub struct ClockSkewProcessor {
+    name: String,
+    config: ClockSkewConfig,
+    timing: TimingMixin,
+    metrics_enabled: bool,
+    /// Recent offsets (ingestion minus event time, in milliseconds) per key
+    offsets: StateStore<String, VecDeque<i64>>,
+}
+
+impl ClockSkewProcessor {
+    pub const METADATA: ProcessorMetadata = ProcessorMetadata {
+        name: "clock_skew",
+        description: "Estimates per-key clock skew and optionally corrects event times",
+        parameters: &[
+            ParamSpec::new("key_field", ParamType::String, "Field whose value identifies a device"),
+            ParamSpec::new("window", ParamType::Integer, "Messages per key the skew is estimated over"),
+            ParamSpec::new("correct", ParamType::Boolean, "Shift event times by the estimated skew"),
+            ParamSpec::new("tolerance_ms", ParamType::Integer, "Skew below which event times are left alone"),
+            ParamSpec::new("skew_field", ParamType::String, "Payload field receiving the estimated skew in milliseconds"),
+        ],
+        shared: &[],
+    };
+
+    pub fn new(name: &str, config: StageConfig) -> Result<Box<dyn Processor>> {
+        let processor_config = ClockSkewConfig::from_stage_config(&config)?;
+        processor_config.validate()?;
+
+        let timing = TimingMixin::new(processor_config.timing.as_ref());
+        let metrics_enabled = processor_config.timing.as_ref().is_none_or(|timing| timing.metrics_enabled);
+
+        Ok(Box::new(Self {
+            name: name.to_string(),
+            config: processor_config,
+            timing,
+            metrics_enabled,
+            offsets: StateStore::open(name, config.state.as_ref())?,
+        }))
+    }
+
+    /// Milliseconds from `earlier` to `later`, negative if `later` is earlier.
+    fn signed_millis(later: SystemTime, earlier: SystemTime) -> i64 {
+        match later.duration_since(earlier) {
+            Ok(ahead) => ahead.as_millis() as i64,
+            Err(behind) => -(behind.duration().as_millis() as i64),
+        }
+    }
+
+    /// Record a message's offset and return the updated skew of its key.
+    fn observe(&mut self, key: &str, message: &Message) -> i64 {
+        let offset = Self::signed_millis(message.timing.ingestion_time, message.timing.event_time);
+        let offsets = self.offsets.get_or_insert_with(key.to_string(), VecDeque::new);
+        offsets.push_back(offset);
+        while offsets.len() > self.config.window {
+            offsets.pop_front();
+        }
+
+        let mut sorted: Vec<i64> = offsets.iter().copied().collect();
+        sorted.sort_unstable();
+        sorted[sorted.len() / 2]
+    }
+
+    fn process_message(&mut self, mut message: Message) -> Result<Message> {
+        let key = FieldUtils::extract_key(&message.payload, self.config.key_field.as_deref());
+        let skew_ms = self.observe(&key, &message);
+
+        if self.metrics_enabled {
+            metrics().record_clock_skew(&self.name, &key, skew_ms as f64 / 1000.0);
+        }
+        if let Some(field) = &self.config.skew_field {
+            FieldUtils::set_field_value(&mut message.payload, field, json!(skew_ms))?;
+        }
+
+        if self.config.correct && skew_ms.unsigned_abs() > self.config.tolerance_ms {
+            let shift = Duration::from_millis(skew_ms.unsigned_abs());
+            let event_time = &mut message.timing.event_time;
+            *event_time = if skew_ms > 0 { *event_time + shift } else { *event_time - shift };
+            debug!("Corrected event time of key '{}' by {} ms", key, skew_ms);
+        }
+
+        message.source = self.name.clone();
+        Ok(message)
+    }
+}
+
+#[async_trait::async_trait]
+impl Processor for ClockSkewProcessor {
+    async fn init(&mut self) -> Result<()> {
+        tracing::info!(
+            "Clock skew processor '{}' initialised (window: {}, correct: {}, tolerance: {} ms)",
+            self.name,
+            self.config.window,
+            self.config.correct,
+            self.config.tolerance_ms
+        );
+        Ok(())
+    }
+
+    async fn process(&mut self, context: &mut ProcessingContext) -> Result<()> {
+        if let Some((channel_name, message)) = context.recv(tokio::time::Duration::from_millis(10)).await {
+            match self.process_message(message) {
+                Ok(mut output_message) => {
+                    if let Some(output_info) = &context.output {
+                        output_message.topic = output_info.name.clone();
+                        let output_message = self.timing.update_message_watermark(output_message);
+
+                        if let Err(e) = output_info.channel.publish(output_message).await {
+                            tracing::warn!("Failed to publish clock skew output: {:?}", e);
+                        }
+                    }
+                }
+                Err(e) => {
+                    tracing::error!("Clock skew stage '{}' dropped a message from '{}': {}", self.name, channel_name, e);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn as_snapshot(&mut self) -> Option<&mut dyn Snapshot> {
+        Some(self)
+    }
+}
+
+impl Snapshot for ClockSkewProcessor {
+    fn snapshot(&self) -> Result<Value> {
+        self.offsets.snapshot()
+    }
+
+    fn restore(&mut self, state: Value) -> Result<()> {
+        self.offsets.restore(state)
+    }
+}
+
+impl WithTimingMixin for ClockSkewProcessor {
+    fn timing_mixin(&self) -> &TimingMixin {
+        &self.timing
+    }
+
+    fn timing_mixin_mut(&mut self) -> &mut TimingMixin {
+        &mut self.timing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::message::TimingInfo;
+    use std::collections::HashMap;
+    use std::time::UNIX_EPOCH;
+
+    fn reading(device: &str, event_ms: u64, ingested_ms: u64) -> Message {
+        let mut message = Message::new("src", "topic", json!({ "device": device }));
+        message.timing = TimingInfo::with_times(
+            UNIX_EPOCH + Duration::from_millis(event_ms),
+            UNIX_EPOCH + Duration::from_millis(ingested_ms),
+        );
+        message
+    }
+
+    #[test]
+    fn test_clock_skew_is_estimated_and_corrected_per_key() {
+        let config = StageConfig {
+            r#type: "clock_skew".to_string(),
+            parameters: Some(HashMap::from([
+                ("key_field".to_string(), json!("device")),
+                ("window".to_string(), json!(3)),
+                ("correct".to_string(), json!(true)),
+                ("tolerance_ms".to_string(), json!(500)),
+                ("skew_field".to_string(), json!("skew_ms")),
+            ])),
+            ..Default::default()
+        };
+        let mut processor = ClockSkewProcessor {
+            name: "skew".to_string(),
+            config: ClockSkewConfig::from_stage_config(&config).unwrap(),
+            timing: TimingMixin::new(None),
+            metrics_enabled: false,
+            offsets: StateStore::new(),
+        };
+
+        // Device "slow" runs two minutes behind, with one transit outlier
+        for (event, ingested) in [(0, 120_000), (1_000, 121_050), (2_000, 150_000)] {
+            processor.process_message(reading("slow", event, ingested)).unwrap();
+        }
+        let corrected = processor.process_message(reading("slow", 3_000, 123_000)).unwrap();
+        assert_eq!(corrected.payload["skew_ms"], json!(120_050));
+        assert_eq!(corrected.timing.event_time, UNIX_EPOCH + Duration::from_millis(123_050));
+
+        // A key within tolerance keeps its event time, even ahead of ingestion
+        let fine = processor.process_message(reading("fine", 10_200, 10_000)).unwrap();
+        assert_eq!(fine.payload["skew_ms"], json!(-200));
+        assert_eq!(fine.timing.event_time, UNIX_EPOCH + Duration::from_millis(10_200));
+    }
+}
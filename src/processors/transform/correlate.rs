@@ -0,0 +1,389 @@
+//! Correlate Transform
+//!
+//! Checks two measurements of the same quantity against each other, such as a
+//! duplicate sensor pair, over a sliding event-time window per key. Each new
+//! pair of values updates the window, and the message completing it is
+//! emitted with consistency metrics under `metrics_field`:
+//!
+//! - **correlation**: Pearson correlation of the pairs in the window (`null`
+//!   while either side is constant)
+//! - **difference**: `a - b` of the newest pair
+//! - **mean_difference** / **mean_abs_difference**: the bias and spread of
+//!   `a - b` across the window, where a drifting mean difference reveals a
+//!   miscalibrated sensor
+//! - **samples**: pairs in the window
+//! - **consistent**: whether the window is within `max_difference` and
+//!   `min_correlation`, when either is set
+//!
+//! The values are `field_a` and `field_b` of the same message, or, with
+//! `input_a` and `input_b`, of messages from two inputs. Readings from two
+//! inputs are paired with the latest reading of the other input when their
+//! event times lie within `tolerance_ms`, each reading being paired once.
+
+use crate::config::{ParamSpec, ParamType, ProcessorConfig, ProcessorMetadata, StageConfig, extract_param, require_param};
+use crate::core::checkpoint::Snapshot;
+use crate::core::state::StateStore;
+use crate::core::timing_mixin::{TimingMixin, WithTimingMixin};
+use crate::core::{context::ProcessingContext, message::Message};
+use crate::processors::common::field_utils::FieldUtils;
+use crate::processors::common::window::SlidingWindow;
+use crate::processors::processor::Processor;
+use crate::error::LiminalError;
+
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::time::{Duration, SystemTime};
+use tracing::{debug, error};
+
+#[derive(Debug, Clone)]
+pub struct CorrelateConfig {
+    pub field_a: String,
+    pub field_b: String,
+    /// Inputs `field_a` and `field_b` are read from, when not the same message
+    pub inputs: Option<(String, String)>,
+    pub key_field: Option<String>,
+    /// Maximum event-time spread of readings paired across inputs
+    pub tolerance_ms: u64,
+    /// Event time covered by the window
+    pub window_ms: u64,
+    /// Pairs needed before metrics are emitted
+    pub min_samples: usize,
+    /// Largest mean absolute difference still consistent
+    pub max_difference: Option<f64>,
+    /// Smallest correlation still consistent
+    pub min_correlation: Option<f64>,
+    pub metrics_field: String,
+    pub timing: Option<crate::config::TimingConfig>,
+}
+
+impl ProcessorConfig for CorrelateConfig {
+    fn from_stage_config(config: &StageConfig) -> Result<Self> {
+        let field_a = require_param::<String>(&config.parameters, "field_a")?;
+        let field_b = require_param::<String>(&config.parameters, "field_b")?;
+        let inputs = match (
+            extract_param(&config.parameters, "input_a", None::<String>),
+            extract_param(&config.parameters, "input_b", None::<String>),
+        ) {
+            (Some(input_a), Some(input_b)) => Some((input_a, input_b)),
+            (None, None) => None,
+            _ => return Err(anyhow!("input_a and input_b must be set together")),
+        };
+
+        let config = Self {
+            field_a,
+            field_b,
+            inputs,
+            key_field: extract_param(&config.parameters, "key_field", None::<String>),
+            tolerance_ms: extract_param(&config.parameters, "tolerance_ms", 1_000_u64),
+            window_ms: extract_param(&config.parameters, "window_ms", 60_000_u64),
+            min_samples: extract_param(&config.parameters, "min_samples", 2_usize),
+            max_difference: extract_param(&config.parameters, "max_difference", None::<f64>),
+            min_correlation: extract_param(&config.parameters, "min_correlation", None::<f64>),
+            metrics_field: extract_param(&config.parameters, "metrics_field", "correlation".to_string()),
+            timing: config.timing.clone(),
+        };
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.field_a.is_empty() || self.field_b.is_empty() || self.metrics_field.is_empty() {
+            return Err(anyhow!("field_a, field_b and metrics_field cannot be empty"));
+        }
+        if self.inputs.is_none() && self.field_a == self.field_b {
+            return Err(anyhow!("field_a and field_b must differ unless input_a and input_b are set"));
+        }
+        if let Some((input_a, input_b)) = &self.inputs
+            && input_a == input_b
+        {
+            return Err(anyhow!("input_a and input_b must differ"));
+        }
+        if self.window_ms == 0 {
+            return Err(LiminalError::invalid_parameter("window_ms", "must be greater than 0").into());
+        }
+        if self.min_samples < 2 {
+            return Err(LiminalError::invalid_parameter("min_samples", "must be at least 2").into());
+        }
+        if self.max_difference.is_some_and(|max| max < 0.0) {
+            return Err(LiminalError::invalid_parameter("max_difference", "cannot be negative").into());
+        }
+        if self.min_correlation.is_some_and(|min| !(-1.0..=1.0).contains(&min)) {
+            return Err(LiminalError::invalid_parameter("min_correlation", "must be between -1 and 1").into());
+        }
+        Ok(())
+    }
+}
+
+/// Window and unpaired readings of one key.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Series {
+    pairs: SlidingWindow<(f64, f64)>,
+    /// Latest unpaired reading of each input
+    pending_a: Option<(SystemTime, f64)>,
+    pending_b: Option<(SystemTime, f64)>,
+}
+
+pub struct CorrelateProcessor {
+    name: String,
+    config: CorrelateConfig,
+    timing: TimingMixin,
+    series: StateStore<String, Series>,
+}
+
+impl CorrelateProcessor {
+    pub const METADATA: ProcessorMetadata = ProcessorMetadata {
+        name: "correlate",
+        description: "Rolling correlation and difference of two measurements",
+        parameters: &[
+            ParamSpec::new("field_a", ParamType::String, "First measurement"),
+            ParamSpec::new("field_b", ParamType::String, "Second measurement"),
+            ParamSpec::new("input_a", ParamType::String, "Input field_a is read from, with input_b"),
+            ParamSpec::new("input_b", ParamType::String, "Input field_b is read from, with input_a"),
+            ParamSpec::new("key_field", ParamType::String, "Field whose value keeps separate windows"),
+            ParamSpec::new("tolerance_ms", ParamType::Integer, "Maximum event-time spread of readings paired across inputs"),
+            ParamSpec::new("window_ms", ParamType::Integer, "Event time covered by the window"),
+            ParamSpec::new("min_samples", ParamType::Integer, "Pairs needed before metrics are emitted"),
+            ParamSpec::new("max_difference", ParamType::Number, "Largest mean absolute difference still consistent"),
+            ParamSpec::new("min_correlation", ParamType::Number, "Smallest correlation still consistent"),
+            ParamSpec::new("metrics_field", ParamType::String, "Field receiving the metrics"),
+        ],
+        shared: &[],
+    };
+
+    pub fn new(name: &str, config: StageConfig) -> Result<Box<dyn Processor>> {
+        let processor_config = CorrelateConfig::from_stage_config(&config)?;
+        let timing = TimingMixin::new(processor_config.timing.as_ref());
+
+        Ok(Box::new(Self {
+            name: name.to_string(),
+            config: processor_config,
+            timing,
+            series: StateStore::open(name, config.state.as_ref())?,
+        }))
+    }
+
+    /// Record the message's values, returning it with metrics if it completed
+    /// a pair and the window holds enough of them.
+    fn process_message(&mut self, input: &str, mut message: Message) -> Result<Option<Message>> {
+        let key = FieldUtils::extract_key(&message.payload, self.config.key_field.as_deref());
+        let time = message.timing.event_time;
+        let window = Duration::from_millis(self.config.window_ms);
+        let tolerance = Duration::from_millis(self.config.tolerance_ms);
+
+        let series = self.series.get_or_insert_with(key.clone(), || Series {
+            pairs: SlidingWindow::new(window),
+            pending_a: None,
+            pending_b: None,
+        });
+
+        let pair = match &self.config.inputs {
+            None => FieldUtils::extract_f64(&message.payload, &self.config.field_a)
+                .zip(FieldUtils::extract_f64(&message.payload, &self.config.field_b)),
+            Some((input_a, input_b)) => {
+                let (field, own, other, is_a) = if input == input_a {
+                    (&self.config.field_a, &mut series.pending_a, &mut series.pending_b, true)
+                } else if input == input_b {
+                    (&self.config.field_b, &mut series.pending_b, &mut series.pending_a, false)
+                } else {
+                    debug!("{}: Ignoring message from unexpected input '{}'", self.name, input);
+                    return Ok(None);
+                };
+                let Some(value) = FieldUtils::extract_f64(&message.payload, field) else {
+                    return Ok(None);
+                };
+                match other.take() {
+                    Some((other_time, other_value)) if within(time, other_time, tolerance) => {
+                        Some(if is_a { (value, other_value) } else { (other_value, value) })
+                    }
+                    stale => {
+                        *other = stale;
+                        *own = Some((time, value));
+                        None
+                    }
+                }
+            }
+        };
+        let Some(pair) = pair else {
+            return Ok(None);
+        };
+
+        series.pairs.set_span(window);
+        series.pairs.push(time, pair);
+        series.pairs.evict(time);
+        if series.pairs.len() < self.config.min_samples {
+            return Ok(None);
+        }
+
+        let metrics = self.metrics(&self.series.get(&key).expect("series was just updated").pairs, pair);
+        FieldUtils::set_field_value(&mut message.payload, &self.config.metrics_field, metrics)?;
+        message.source = self.name.clone();
+        Ok(Some(message))
+    }
+
+    fn metrics(&self, pairs: &SlidingWindow<(f64, f64)>, (a, b): (f64, f64)) -> Value {
+        let n = pairs.len() as f64;
+        let mean_a = pairs.values().map(|(a, _)| a).sum::<f64>() / n;
+        let mean_b = pairs.values().map(|(_, b)| b).sum::<f64>() / n;
+        let (mut covariance, mut variance_a, mut variance_b) = (0.0, 0.0, 0.0);
+        for (a, b) in pairs.values() {
+            covariance += (a - mean_a) * (b - mean_b);
+            variance_a += (a - mean_a).powi(2);
+            variance_b += (b - mean_b).powi(2);
+        }
+        let correlation = (variance_a > 0.0 && variance_b > 0.0).then(|| covariance / (variance_a * variance_b).sqrt());
+        let mean_difference = pairs.values().map(|(a, b)| a - b).sum::<f64>() / n;
+        let mean_abs_difference = pairs.values().map(|(a, b)| (a - b).abs()).sum::<f64>() / n;
+
+        let mut metrics = json!({
+            "correlation": correlation,
+            "difference": a - b,
+            "mean_difference": mean_difference,
+            "mean_abs_difference": mean_abs_difference,
+            "samples": pairs.len(),
+        });
+        if self.config.max_difference.is_some() || self.config.min_correlation.is_some() {
+            let consistent = self.config.max_difference.is_none_or(|max| mean_abs_difference <= max)
+                && self
+                    .config
+                    .min_correlation
+                    .is_none_or(|min| correlation.is_none_or(|correlation| correlation >= min));
+            metrics["consistent"] = Value::Bool(consistent);
+        }
+        metrics
+    }
+}
+
+/// Whether two event times lie within `tolerance` of each other.
+fn within(a: SystemTime, b: SystemTime, tolerance: Duration) -> bool {
+    a.duration_since(b).unwrap_or_else(|e| e.duration()) <= tolerance
+}
+
+#[async_trait::async_trait]
+impl Processor for CorrelateProcessor {
+    async fn init(&mut self) -> Result<()> {
+        tracing::info!(
+            "Correlate processor '{}' initialised ({} ~ {}, window: {} ms)",
+            self.name,
+            self.config.field_a,
+            self.config.field_b,
+            self.config.window_ms
+        );
+        Ok(())
+    }
+
+    async fn process(&mut self, context: &mut ProcessingContext) -> Result<()> {
+        if let Some((channel_name, message)) = context.recv(tokio::time::Duration::from_millis(10)).await {
+            match self.process_message(&channel_name, message) {
+                Ok(Some(mut output_message)) => {
+                    if let Some(output_info) = &context.output {
+                        output_message.topic = output_info.name.clone();
+                        let output_message = self.timing.update_message_watermark(output_message);
+
+                        if let Err(e) = output_info.channel.publish(output_message).await {
+                            tracing::warn!("Failed to publish correlate output: {:?}", e);
+                        }
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    error!("{}: Failed to correlate message: {}", self.name, e);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn as_snapshot(&mut self) -> Option<&mut dyn Snapshot> {
+        Some(self)
+    }
+}
+
+impl Snapshot for CorrelateProcessor {
+    fn snapshot(&self) -> Result<Value> {
+        self.series.snapshot()
+    }
+
+    fn restore(&mut self, state: Value) -> Result<()> {
+        self.series.restore(state)
+    }
+}
+
+impl WithTimingMixin for CorrelateProcessor {
+    fn timing_mixin(&self) -> &TimingMixin {
+        &self.timing
+    }
+
+    fn timing_mixin_mut(&mut self) -> &mut TimingMixin {
+        &mut self.timing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{MessageBuilder, TestContext};
+
+    fn stage(parameters: Value) -> StageConfig {
+        StageConfig {
+            r#type: "correlate".to_string(),
+            inputs: Some(vec!["a".to_string(), "b".to_string()]),
+            output: Some("out".to_string()),
+            parameters: serde_json::from_value(parameters).ok(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_correlate_fields_of_one_message() {
+        let parameters = json!({ "field_a": "t1", "field_b": "t2", "min_samples": 3, "max_difference": 0.5 });
+        let mut processor = CorrelateProcessor::new("correlate", stage(parameters)).unwrap();
+        let mut test = TestContext::new("correlate").input("a").output("out");
+
+        for (t1, t2) in [(20.0, 21.0), (21.0, 22.0), (22.0, 23.0)] {
+            test.send("a", MessageBuilder::new(json!({ "t1": t1, "t2": t2 })).build()).await.unwrap();
+        }
+        test.process_pending(processor.as_mut()).await.unwrap();
+
+        let metrics = test.try_output().await.unwrap().payload["correlation"].clone();
+        assert!(test.try_output().await.is_none());
+        assert!((metrics["correlation"].as_f64().unwrap() - 1.0).abs() < 1e-9);
+        assert_eq!(metrics["mean_difference"], json!(-1.0));
+        assert_eq!(metrics["samples"], 3);
+        assert_eq!(metrics["consistent"], false);
+    }
+
+    #[tokio::test]
+    async fn test_correlate_pairs_readings_across_inputs() {
+        let parameters = json!({
+            "field_a": "value",
+            "field_b": "value",
+            "input_a": "a",
+            "input_b": "b",
+            "key_field": "room",
+            "tolerance_ms": 500,
+        });
+        let mut processor = CorrelateProcessor::new("correlate", stage(parameters)).unwrap();
+        let mut test = TestContext::new("correlate").input("a").input("b").output("out");
+
+        let readings = [
+            ("a", 0, 10.0),
+            ("b", 100, 10.5),  // paired with a at 0
+            ("a", 1000, 12.0),
+            ("b", 2000, 13.0), // too late for a at 1000
+            ("a", 2200, 12.0), // paired with b at 2000
+        ];
+        for (input, ms, value) in readings {
+            let reading = MessageBuilder::new(json!({ "room": "lab", "value": value })).event_time_ms(ms).build();
+            test.send(input, reading).await.unwrap();
+            test.process_pending(processor.as_mut()).await.unwrap();
+        }
+
+        let metrics = test.try_output().await.unwrap().payload["correlation"].clone();
+        assert!(test.try_output().await.is_none());
+        assert_eq!(metrics["samples"], 2);
+        assert_eq!(metrics["difference"], json!(-1.0));
+        assert_eq!(metrics["mean_abs_difference"], json!(0.75));
+    }
+}
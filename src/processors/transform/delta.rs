@@ -0,0 +1,312 @@
+//! Delta Transform
+//!
+//! Emits the difference between consecutive values of a numeric field, keeping
+//! independent state per key. Designed for pulse-counter sensors where the useful
+//! quantity is the increment rather than the running total, so it handles counter
+//! wraparound and resets, and can suppress messages whose change is insignificant.
+//! Deltas are measured from the last emitted value, so a slow drift is reported
+//! once it adds up to `min_change`.
+
+use crate::config::{ParamSpec, ParamType, ProcessorConfig, ProcessorMetadata, StageConfig, extract_param, require_param};
+use crate::core::checkpoint::Snapshot;
+use crate::core::state::StateStore;
+use crate::core::timing_mixin::{TimingMixin, WithTimingMixin};
+use crate::core::{context::ProcessingContext, message::Message};
+use crate::processors::common::field_utils::FieldUtils;
+use crate::processors::processor::Processor;
+use crate::error::LiminalError;
+
+use anyhow::{Result, anyhow};
+use serde::Deserialize;
+use serde_json::{Number, Value};
+use tracing::{debug, error};
+
+/// How to interpret a decrease in value when no wraparound is configured.
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum NegativeDeltaPolicy {
+    /// Emit the negative delta as-is
+    Keep,
+    /// Treat the decrease as a counter reset, so the delta is the new value itself
+    Reset,
+    /// Drop the message and re-baseline on the new value
+    Drop,
+}
+
+#[derive(Debug, Clone)]
+pub struct DeltaConfig {
+    pub field_in: String,
+    pub field_out: String,
+    pub key_field: Option<String>,
+    /// Counter modulus; a decrease is interpreted as a wrap past this value
+    pub wrap_at: Option<f64>,
+    pub on_negative: NegativeDeltaPolicy,
+    /// Suppress messages whose absolute delta is below this threshold
+    pub min_change: f64,
+    /// Emit the first message for a key (without a delta field)
+    pub emit_first: bool,
+    pub timing: Option<crate::config::TimingConfig>,
+}
+
+impl ProcessorConfig for DeltaConfig {
+    fn from_stage_config(config: &StageConfig) -> Result<Self> {
+        let field_in = require_param::<String>(&config.parameters, "field_in")?;
+        let field_out =
+            extract_param(&config.parameters, "field_out", format!("{}_delta", field_in));
+        let key_field = extract_param(&config.parameters, "key_field", None::<String>);
+        let wrap_at = extract_param(&config.parameters, "wrap_at", None::<f64>);
+        let on_negative =
+            extract_param(&config.parameters, "on_negative", NegativeDeltaPolicy::Keep);
+        let min_change = extract_param(&config.parameters, "min_change", 0.0);
+        let emit_first = extract_param(&config.parameters, "emit_first", false);
+
+        let config = Self {
+            field_in,
+            field_out,
+            key_field,
+            wrap_at,
+            on_negative,
+            min_change,
+            emit_first,
+            timing: config.timing.clone(),
+        };
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.field_in.is_empty() || self.field_out.is_empty() {
+            return Err(anyhow!("field_in and field_out cannot be empty"));
+        }
+        if let Some(wrap_at) = self.wrap_at
+            && wrap_at <= 0.0
+        {
+            return Err(LiminalError::invalid_parameter("wrap_at", format!("must be positive (got {})", wrap_at)).into());
+        }
+        if self.min_change < 0.0 {
+            return Err(LiminalError::invalid_parameter("min_change", format!("cannot be negative (got {})", self.min_change)).into());
+        }
+        Ok(())
+    }
+}
+
+pub struct DeltaProcessor {
+    name: String,
+    config: DeltaConfig,
+    timing: TimingMixin,
+    last_values: StateStore<String, f64>,
+}
+
+impl DeltaProcessor {
+    pub const METADATA: ProcessorMetadata = ProcessorMetadata {
+        name: "delta",
+        description: "Emits differences between consecutive values",
+        parameters: &[
+            ParamSpec::new("field_in", ParamType::String, "Numeric field to difference"),
+            ParamSpec::new("field_out", ParamType::String, "Field receiving the delta"),
+            ParamSpec::new("key_field", ParamType::String, "Field whose value keeps separate state"),
+            ParamSpec::new("wrap_at", ParamType::Number, "Counter modulus; a decrease is a wrap past this value"),
+            ParamSpec::new("on_negative", ParamType::Choice(&["keep", "reset", "drop"]), "How a decrease is treated when no wrap_at is set"),
+            ParamSpec::new("min_change", ParamType::Number, "Drop messages whose delta is smaller than this"),
+            ParamSpec::new("emit_first", ParamType::Boolean, "Emit the first reading of each key unchanged"),
+        ],
+        shared: &[],
+    };
+
+    pub fn new(name: &str, config: StageConfig) -> Result<Box<dyn Processor>> {
+        let processor_config = DeltaConfig::from_stage_config(&config)?;
+        processor_config.validate()?;
+
+        let timing = TimingMixin::new(processor_config.timing.as_ref());
+
+        Ok(Box::new(Self {
+            name: name.to_string(),
+            config: processor_config,
+            timing,
+            last_values: StateStore::open(name, config.state.as_ref())?,
+        }))
+    }
+
+    /// Compute the delta between two consecutive readings, or `None` if the
+    /// reading should not produce a message.
+    fn compute_delta(&self, previous: f64, current: f64) -> Option<f64> {
+        let delta = current - previous;
+        if delta >= 0.0 {
+            return Some(delta);
+        }
+
+        if let Some(wrap_at) = self.config.wrap_at {
+            return Some(delta + wrap_at);
+        }
+
+        match self.config.on_negative {
+            NegativeDeltaPolicy::Keep => Some(delta),
+            NegativeDeltaPolicy::Reset => Some(current),
+            NegativeDeltaPolicy::Drop => None,
+        }
+    }
+
+    fn process_message(&mut self, mut message: Message) -> Result<Option<Message>> {
+        let Some(value) = FieldUtils::extract_f64(&message.payload, &self.config.field_in) else {
+            debug!(
+                "Field '{}' missing or not numeric, passing message through",
+                self.config.field_in
+            );
+            return Ok(Some(message));
+        };
+
+        let key = FieldUtils::extract_key(&message.payload, self.config.key_field.as_deref());
+
+        // Reading through `get_mut` keeps a key with suppressed readings alive
+        let Some(previous) = self.last_values.get_mut(&key).map(|previous| *previous) else {
+            self.last_values.insert(key, value);
+            return Ok(self.config.emit_first.then_some(message));
+        };
+
+        let Some(delta) = self.compute_delta(previous, value) else {
+            debug!("Negative delta dropped ({} -> {})", previous, value);
+            self.last_values.insert(key, value);
+            return Ok(None);
+        };
+
+        if delta.abs() < self.config.min_change {
+            debug!("Delta {} below min_change, suppressing message", delta);
+            return Ok(None);
+        }
+        self.last_values.insert(key, value);
+
+        let delta = Number::from_f64(delta).ok_or_else(|| anyhow!("Delta is not finite"))?;
+        FieldUtils::set_field_value(&mut message.payload, &self.config.field_out, Value::Number(delta))?;
+
+        message.source = self.name.clone();
+        Ok(Some(message))
+    }
+}
+
+#[async_trait::async_trait]
+impl Processor for DeltaProcessor {
+    async fn init(&mut self) -> Result<()> {
+        tracing::info!(
+            "Delta processor '{}' initialised ('{}' -> '{}')",
+            self.name,
+            self.config.field_in,
+            self.config.field_out
+        );
+        Ok(())
+    }
+
+    async fn process(&mut self, context: &mut ProcessingContext) -> Result<()> {
+        if let Some((channel_name, message)) = context.recv(tokio::time::Duration::from_millis(10)).await {
+            match self.process_message(message) {
+                Ok(Some(mut output_message)) => {
+                    if let Some(output_info) = &context.output {
+                        output_message.topic = output_info.name.clone();
+                        let output_message = self.timing.update_message_watermark(output_message);
+
+                        if let Err(e) = output_info.channel.publish(output_message).await {
+                            tracing::warn!("Failed to publish delta output: {:?}", e);
+                        }
+                    }
+                }
+                Ok(None) => {
+                    debug!("Message from '{}' suppressed by delta processor", channel_name);
+                }
+                Err(e) => {
+                    error!("Failed to compute delta: {}", e);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn as_snapshot(&mut self) -> Option<&mut dyn Snapshot> {
+        Some(self)
+    }
+}
+
+impl Snapshot for DeltaProcessor {
+    fn snapshot(&self) -> Result<Value> {
+        self.last_values.snapshot()
+    }
+
+    fn restore(&mut self, state: Value) -> Result<()> {
+        self.last_values.restore(state)
+    }
+}
+
+impl WithTimingMixin for DeltaProcessor {
+    fn timing_mixin(&self) -> &TimingMixin {
+        &self.timing
+    }
+
+    fn timing_mixin_mut(&mut self) -> &mut TimingMixin {
+        &mut self.timing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn processor(parameters: Value) -> DeltaProcessor {
+        let stage = StageConfig {
+            r#type: "delta".to_string(),
+            parameters: serde_json::from_value(parameters).ok(),
+            ..Default::default()
+        };
+        DeltaProcessor {
+            name: "delta".to_string(),
+            config: DeltaConfig::from_stage_config(&stage).unwrap(),
+            timing: TimingMixin::new(None),
+            last_values: StateStore::new(),
+        }
+    }
+
+    /// Feed readings through the processor and collect the emitted deltas,
+    /// rounded to one decimal place.
+    fn deltas(processor: &mut DeltaProcessor, readings: &[f64]) -> Vec<Option<f64>> {
+        readings
+            .iter()
+            .map(|value| {
+                let message = Message::new("src", "topic", json!({ "count": value }));
+                let output = processor.process_message(message).unwrap()?;
+                Some(output.payload["count_delta"].as_f64().map_or(f64::NAN, |d| (d * 10.0).round() / 10.0))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_delta_min_change_accumulates_drift() {
+        let mut delta = processor(json!({ "field_in": "count", "min_change": 1.0 }));
+
+        // Each step is below min_change, but the drift since the last emitted
+        // value is reported once it reaches it
+        assert_eq!(
+            deltas(&mut delta, &[10.0, 10.4, 10.8, 11.2, 11.5, 13.0]),
+            vec![None, None, None, Some(1.2), None, Some(1.8)]
+        );
+    }
+
+    #[test]
+    fn test_delta_counter_wraparound() {
+        let mut delta = processor(json!({ "field_in": "count", "wrap_at": 1000.0 }));
+        assert_eq!(deltas(&mut delta, &[990.0, 998.0, 4.0, 10.0]), vec![None, Some(8.0), Some(6.0), Some(6.0)]);
+    }
+
+    #[test]
+    fn test_delta_on_negative_modes() {
+        let readings = [100.0, 120.0, 5.0, 15.0];
+
+        let mut keep = processor(json!({ "field_in": "count", "on_negative": "keep" }));
+        assert_eq!(deltas(&mut keep, &readings), vec![None, Some(20.0), Some(-115.0), Some(10.0)]);
+
+        let mut reset = processor(json!({ "field_in": "count", "on_negative": "reset" }));
+        assert_eq!(deltas(&mut reset, &readings), vec![None, Some(20.0), Some(5.0), Some(10.0)]);
+
+        // Dropping re-baselines on the new value
+        let mut drop = processor(json!({ "field_in": "count", "on_negative": "drop" }));
+        assert_eq!(deltas(&mut drop, &readings), vec![None, Some(20.0), None, Some(10.0)]);
+    }
+}
@@ -0,0 +1,238 @@
+//! Expression Transform
+//!
+//! Computes payload fields from expressions, evaluated with the same
+//! machinery (variables, math functions and result types) as the `rule`
+//! processor's `compute_field` action, but without a rule around them:
+//!
+//! ```toml
+//! parameters = { expressions = [
+//!     { field = "power", expression = "voltage * current" },
+//!     { field = "power_kw", expression = "power / 1000" },
+//! ] }
+//! ```
+//!
+//! Expressions are evaluated in order, each seeing the fields computed before
+//! it. An expression that fails, such as one referring to a missing field,
+//! leaves its field unset; with `drop_on_error` the message is dropped instead.
+
+use crate::config::{ParamSpec, ParamType, ProcessorConfig, ProcessorMetadata, StageConfig, extract_param};
+use crate::core::timing_mixin::{TimingMixin, WithTimingMixin};
+use crate::core::{context::ProcessingContext, message::Message};
+use crate::processors::common::field_utils::FieldUtils;
+use crate::processors::processor::Processor;
+use crate::processors::transform::rule::RuleProcessor;
+
+use anyhow::{Result, anyhow};
+use evalexpr::{DefaultNumericTypes, Node};
+use serde::Deserialize;
+use tracing::{debug, error};
+
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+pub struct Expression {
+    /// Field receiving the result
+    pub field: String,
+    pub expression: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct ExprConfig {
+    pub expressions: Vec<Expression>,
+    /// Drop messages any expression fails on, rather than passing them on
+    pub drop_on_error: bool,
+    pub timing: Option<crate::config::TimingConfig>,
+}
+
+impl ProcessorConfig for ExprConfig {
+    fn from_stage_config(config: &StageConfig) -> Result<Self> {
+        let expressions = config
+            .parameters
+            .as_ref()
+            .and_then(|parameters| parameters.get("expressions"))
+            .map(|expressions| serde_json::from_value::<Vec<Expression>>(expressions.clone()))
+            .transpose()
+            .map_err(|e| anyhow!("Invalid expressions: {}", e))?
+            .unwrap_or_default();
+
+        let config = Self {
+            expressions,
+            drop_on_error: extract_param(&config.parameters, "drop_on_error", false),
+            timing: config.timing.clone(),
+        };
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.expressions.is_empty() {
+            return Err(anyhow!("expr processor requires at least one expression"));
+        }
+        for expression in &self.expressions {
+            if expression.field.is_empty() {
+                return Err(anyhow!("expression field cannot be empty"));
+            }
+            compile(&expression.expression)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse an expression, with math functions rewritten as the rule processor does.
+fn compile(expression: &str) -> Result<Node<DefaultNumericTypes>> {
+    let processed = RuleProcessor::preprocess_expression(expression);
+    evalexpr::build_operator_tree(&processed).map_err(|e| anyhow!("Expression '{}' is invalid: {}", expression, e))
+}
+
+pub struct ExprProcessor {
+    name: String,
+    config: ExprConfig,
+    /// Compiled expressions, by output field
+    compiled: Vec<(String, Node<DefaultNumericTypes>)>,
+    timing: TimingMixin,
+}
+
+impl ExprProcessor {
+    pub const METADATA: ProcessorMetadata = ProcessorMetadata {
+        name: "expr",
+        description: "Computes fields from expressions",
+        parameters: &[
+            ParamSpec::new("expressions", ParamType::Array, "Expressions of field and expression, evaluated in order"),
+            ParamSpec::new("drop_on_error", ParamType::Boolean, "Drop messages an expression fails on"),
+        ],
+        shared: &[],
+    };
+
+    pub fn new(name: &str, config: StageConfig) -> Result<Box<dyn Processor>> {
+        let processor_config = ExprConfig::from_stage_config(&config)?;
+        let timing = TimingMixin::new(processor_config.timing.as_ref());
+        let compiled = processor_config
+            .expressions
+            .iter()
+            .map(|expression| Ok((expression.field.clone(), compile(&expression.expression)?)))
+            .collect::<Result<_>>()?;
+
+        Ok(Box::new(Self {
+            name: name.to_string(),
+            config: processor_config,
+            compiled,
+            timing,
+        }))
+    }
+
+    /// The message with its computed fields, or `None` if it is dropped.
+    fn process_message(&self, mut message: Message) -> Result<Option<Message>> {
+        let mut context = RuleProcessor::build_expression_context(&message.payload);
+
+        for (field, node) in &self.compiled {
+            let result = node
+                .eval_with_context(&context)
+                .map_err(|e| anyhow!("Expression evaluation failed: {}", e))
+                .and_then(RuleProcessor::expression_value_to_json);
+            match result {
+                Ok(value) => {
+                    RuleProcessor::add_payload_to_context(&value, field, &mut context);
+                    FieldUtils::set_field_value(&mut message.payload, field, value)?;
+                }
+                Err(e) if self.config.drop_on_error => {
+                    debug!("{}: Dropping message, '{}' failed: {}", self.name, field, e);
+                    return Ok(None);
+                }
+                Err(e) => debug!("{}: Leaving '{}' unset: {}", self.name, field, e),
+            }
+        }
+
+        message.source = self.name.clone();
+        Ok(Some(message))
+    }
+}
+
+#[async_trait::async_trait]
+impl Processor for ExprProcessor {
+    async fn init(&mut self) -> Result<()> {
+        tracing::info!("Expr processor '{}' initialised ({} expressions)", self.name, self.compiled.len());
+        Ok(())
+    }
+
+    async fn process(&mut self, context: &mut ProcessingContext) -> Result<()> {
+        if let Some((_, message)) = context.recv(tokio::time::Duration::from_millis(10)).await {
+            match self.process_message(message) {
+                Ok(Some(mut output_message)) => {
+                    if let Some(output_info) = &context.output {
+                        output_message.topic = output_info.name.clone();
+                        let output_message = self.timing.update_message_watermark(output_message);
+
+                        if let Err(e) = output_info.channel.publish(output_message).await {
+                            tracing::warn!("Failed to publish expr output: {:?}", e);
+                        }
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    error!("{}: Failed to compute fields: {}", self.name, e);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl WithTimingMixin for ExprProcessor {
+    fn timing_mixin(&self) -> &TimingMixin {
+        &self.timing
+    }
+
+    fn timing_mixin_mut(&mut self) -> &mut TimingMixin {
+        &mut self.timing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{MessageBuilder, TestContext};
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_expr_computes_fields_in_order() {
+        let stage = StageConfig {
+            r#type: "expr".to_string(),
+            inputs: Some(vec!["in".to_string()]),
+            output: Some("out".to_string()),
+            parameters: serde_json::from_value(json!({
+                "expressions": [
+                    { "field": "power", "expression": "voltage * current" },
+                    { "field": "load.kw", "expression": "power / 1000" },
+                    { "field": "state", "expression": "if(power > 2000, \"high\", \"normal\")" },
+                    { "field": "phase", "expression": "sqrt(missing)" },
+                ],
+            }))
+            .ok(),
+            ..Default::default()
+        };
+        let mut processor = ExprProcessor::new("expr", stage).unwrap();
+        let mut test = TestContext::new("expr").input("in").output("out");
+
+        let payload = json!({ "voltage": 230.0, "current": 10.0 });
+        test.send("in", MessageBuilder::new(payload).build()).await.unwrap();
+        test.process_pending(processor.as_mut()).await.unwrap();
+
+        let computed = test.try_output().await.unwrap().payload;
+        assert_eq!(computed["power"], json!(2300.0));
+        assert_eq!(computed["load"]["kw"], json!(2.3));
+        assert_eq!(computed["state"], "high");
+        assert!(computed.get("phase").is_none());
+    }
+
+    #[test]
+    fn test_expr_rejects_invalid_expressions() {
+        let stage = StageConfig {
+            r#type: "expr".to_string(),
+            parameters: serde_json::from_value(json!({
+                "expressions": [{ "field": "power", "expression": "voltage * (current" }],
+            }))
+            .ok(),
+            ..Default::default()
+        };
+        assert!(ExprConfig::from_stage_config(&stage).is_err());
+    }
+}
@@ -0,0 +1,311 @@
+//! Flatline Transform
+//!
+//! Detects stuck sensors: a numeric field that has not changed (by more than
+//! `epsilon`) for `samples` readings or for `duration_ms` of event time, per
+//! key. A run of unchanged readings starts at the first of them, so a sensor
+//! reporting the same value for `duration_ms` is flatlined from that reading
+//! on, until the value changes.
+//!
+//! Flatlined messages are either tagged (`flatline_field` is set on every
+//! message, `true` while flatlined) or routed to the `flatline_output` side
+//! output, with the others continuing on the main output.
+
+use crate::config::{ParamSpec, ParamType, ProcessorConfig, ProcessorMetadata, StageConfig, extract_param};
+use crate::core::checkpoint::Snapshot;
+use crate::core::state::StateStore;
+use crate::core::timing_mixin::{TimingMixin, WithTimingMixin};
+use crate::core::{context::ProcessingContext, message::Message};
+use crate::processors::common::field_utils::FieldUtils;
+use crate::processors::processor::Processor;
+use crate::error::LiminalError;
+
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::time::UNIX_EPOCH;
+use tracing::debug;
+
+/// What to do with a flatlined reading.
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FlatlineAction {
+    /// Flag every message with whether it is flatlined
+    Tag,
+    /// Send flatlined messages to `flatline_output`
+    Route,
+}
+
+#[derive(Debug, Clone)]
+pub struct FlatlineConfig {
+    pub fields: Vec<String>,
+    pub key_field: Option<String>,
+    /// Largest difference still counted as unchanged
+    pub epsilon: f64,
+    /// Unchanged readings that make a flatline
+    pub samples: Option<u64>,
+    /// Unchanged event time (in milliseconds) that makes a flatline
+    pub duration_ms: Option<u64>,
+    pub action: FlatlineAction,
+    pub flatline_field: String,
+    pub flatline_output: Option<String>,
+    pub side_outputs: Vec<String>,
+    pub timing: Option<crate::config::TimingConfig>,
+}
+
+impl ProcessorConfig for FlatlineConfig {
+    fn from_stage_config(config: &StageConfig) -> Result<Self> {
+        let mut fields = extract_param(&config.parameters, "fields", Vec::<String>::new());
+        if let Some(field_in) = extract_param(&config.parameters, "field_in", None::<String>) {
+            fields.push(field_in);
+        }
+
+        let config = Self {
+            fields,
+            key_field: extract_param(&config.parameters, "key_field", None::<String>),
+            epsilon: extract_param(&config.parameters, "epsilon", 0.0),
+            samples: extract_param(&config.parameters, "samples", None::<u64>),
+            duration_ms: extract_param(&config.parameters, "duration_ms", None::<u64>),
+            action: extract_param(&config.parameters, "action", FlatlineAction::Tag),
+            flatline_field: extract_param(&config.parameters, "flatline_field", "flatline".to_string()),
+            flatline_output: extract_param(&config.parameters, "flatline_output", None::<String>),
+            side_outputs: config.side_outputs.clone().unwrap_or_default(),
+            timing: config.timing.clone(),
+        };
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.fields.is_empty() {
+            return Err(anyhow!("flatline processor requires 'fields' (or 'field_in') to be specified"));
+        }
+        if self.samples.is_none() && self.duration_ms.is_none() {
+            return Err(anyhow!("flatline processor requires 'samples' or 'duration_ms'"));
+        }
+        if self.samples.is_some_and(|samples| samples < 2) {
+            return Err(LiminalError::invalid_parameter("samples", "must be at least 2").into());
+        }
+        if self.duration_ms == Some(0) {
+            return Err(LiminalError::invalid_parameter("duration_ms", "must be greater than 0").into());
+        }
+        if self.epsilon < 0.0 {
+            return Err(LiminalError::invalid_parameter("epsilon", "cannot be negative").into());
+        }
+        if self.action == FlatlineAction::Route {
+            match &self.flatline_output {
+                None => return Err(LiminalError::missing_parameter("flatline_output").into()),
+                Some(output) if !self.side_outputs.contains(output) => {
+                    return Err(anyhow!("flatline_output '{}' is not one of the stage's side_outputs", output));
+                }
+                Some(_) => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A run of unchanged readings of one series.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Run {
+    /// Value the run started with
+    value: f64,
+    /// Event time of the run's first reading (milliseconds since the epoch)
+    since_ms: u64,
+    /// Readings in the run
+    samples: u64,
+}
+
+pub struct FlatlineProcessor {
+    name: String,
+    config: FlatlineConfig,
+    timing: TimingMixin,
+    runs: StateStore<(String, String), Run>,
+}
+
+impl FlatlineProcessor {
+    pub const METADATA: ProcessorMetadata = ProcessorMetadata {
+        name: "flatline",
+        description: "Flags or routes readings of stuck sensors",
+        parameters: &[
+            ParamSpec::new("fields", ParamType::Array, "Numeric fields to watch"),
+            ParamSpec::new("field_in", ParamType::String, "A single field to watch, in addition to fields"),
+            ParamSpec::new("key_field", ParamType::String, "Field whose value keeps separate series"),
+            ParamSpec::new("epsilon", ParamType::Number, "Largest change still counted as unchanged"),
+            ParamSpec::new("samples", ParamType::Integer, "Unchanged readings that make a flatline"),
+            ParamSpec::new("duration_ms", ParamType::Integer, "Unchanged event time that makes a flatline"),
+            ParamSpec::new("action", ParamType::Choice(&["tag", "route"]), "Flag flatlined messages, or route them"),
+            ParamSpec::new("flatline_field", ParamType::String, "Field receiving the flatline flag"),
+            ParamSpec::new("flatline_output", ParamType::String, "Side output receiving flatlined messages"),
+        ],
+        shared: &[],
+    };
+
+    pub fn new(name: &str, config: StageConfig) -> Result<Box<dyn Processor>> {
+        let processor_config = FlatlineConfig::from_stage_config(&config)?;
+        let timing = TimingMixin::new(processor_config.timing.as_ref());
+
+        Ok(Box::new(Self {
+            name: name.to_string(),
+            config: processor_config,
+            timing,
+            runs: StateStore::open(name, config.state.as_ref())?,
+        }))
+    }
+
+    /// Track the message's readings, returning whether any is flatlined.
+    fn observe(&mut self, message: &Message) -> bool {
+        let key = FieldUtils::extract_key(&message.payload, self.config.key_field.as_deref());
+        let now_ms = message
+            .timing
+            .event_time
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        let mut flatlined = false;
+
+        for field in &self.config.fields {
+            let Some(value) = FieldUtils::extract_f64(&message.payload, field) else {
+                continue;
+            };
+            let run = self.runs.get_or_insert_with((key.clone(), field.clone()), || Run {
+                value,
+                since_ms: now_ms,
+                samples: 0,
+            });
+            if (value - run.value).abs() <= self.config.epsilon {
+                run.samples += 1;
+            } else {
+                *run = Run { value, since_ms: now_ms, samples: 1 };
+            }
+
+            let stuck = self.config.samples.is_some_and(|samples| run.samples >= samples)
+                || self
+                    .config
+                    .duration_ms
+                    .is_some_and(|duration_ms| run.samples > 1 && now_ms.saturating_sub(run.since_ms) >= duration_ms);
+            if stuck {
+                debug!("{}: '{}' of key '{}' flatlined at {} ({} readings)", self.name, field, key, value, run.samples);
+                flatlined = true;
+            }
+        }
+        flatlined
+    }
+}
+
+#[async_trait::async_trait]
+impl Processor for FlatlineProcessor {
+    async fn init(&mut self) -> Result<()> {
+        tracing::info!(
+            "Flatline processor '{}' initialised ({:?}, action: {:?})",
+            self.name,
+            self.config.fields,
+            self.config.action
+        );
+        Ok(())
+    }
+
+    async fn process(&mut self, context: &mut ProcessingContext) -> Result<()> {
+        if let Some((_, mut message)) = context.recv(tokio::time::Duration::from_millis(10)).await {
+            let flatlined = self.observe(&message);
+            message.source = self.name.clone();
+
+            let output = match self.config.action {
+                FlatlineAction::Tag => {
+                    FieldUtils::set_field_value(&mut message.payload, &self.config.flatline_field, Value::Bool(flatlined))?;
+                    context.output.as_ref()
+                }
+                FlatlineAction::Route if flatlined => self
+                    .config
+                    .flatline_output
+                    .as_ref()
+                    .and_then(|output| context.side_outputs.get(output)),
+                FlatlineAction::Route => context.output.as_ref(),
+            };
+
+            if let Some(output_info) = output {
+                message.topic = output_info.name.clone();
+                let message = self.timing.update_message_watermark(message);
+                if let Err(e) = output_info.channel.publish(message).await {
+                    tracing::warn!("Failed to publish flatline output: {:?}", e);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn as_snapshot(&mut self) -> Option<&mut dyn Snapshot> {
+        Some(self)
+    }
+}
+
+impl Snapshot for FlatlineProcessor {
+    fn snapshot(&self) -> Result<Value> {
+        self.runs.snapshot()
+    }
+
+    fn restore(&mut self, state: Value) -> Result<()> {
+        self.runs.restore(state)
+    }
+}
+
+impl WithTimingMixin for FlatlineProcessor {
+    fn timing_mixin(&self) -> &TimingMixin {
+        &self.timing
+    }
+
+    fn timing_mixin_mut(&mut self) -> &mut TimingMixin {
+        &mut self.timing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{MessageBuilder, TestContext};
+    use serde_json::json;
+    use std::time::{Duration, SystemTime};
+
+    #[tokio::test]
+    async fn test_flatline_routes_stuck_readings_by_duration() {
+        let stage = StageConfig {
+            r#type: "flatline".to_string(),
+            inputs: Some(vec!["in".to_string()]),
+            output: Some("out".to_string()),
+            side_outputs: Some(vec!["stuck".to_string()]),
+            parameters: serde_json::from_value(json!({
+                "field_in": "value",
+                "key_field": "sensor",
+                "epsilon": 0.01,
+                "duration_ms": 60000,
+                "action": "route",
+                "flatline_output": "stuck",
+            }))
+            .ok(),
+            ..Default::default()
+        };
+        let mut processor = FlatlineProcessor::new("flatline", stage).unwrap();
+        let mut test = TestContext::new("flatline").input("in").output("out").side_output("stuck");
+
+        let start = SystemTime::now();
+        let readings = [("a", 0, 5.0), ("b", 0, 1.0), ("a", 30, 5.001), ("b", 30, 2.0), ("a", 60, 5.0), ("b", 80, 2.0), ("a", 70, 5.5)];
+        for (sensor, seconds, value) in readings {
+            let reading = MessageBuilder::new(json!({ "sensor": sensor, "value": value }))
+                .event_time(start + Duration::from_secs(seconds))
+                .build();
+            test.send("in", reading).await.unwrap();
+        }
+        test.process_pending(processor.as_mut()).await.unwrap();
+
+        // Sensor a stayed within epsilon for 60 s; b changed at 30 s and had
+        // not been stuck for long enough by 80 s
+        let stuck = test.try_side_output("stuck").await.unwrap().payload;
+        assert_eq!(stuck, json!({ "sensor": "a", "value": 5.0 }));
+        assert!(test.try_side_output("stuck").await.is_none());
+        let mut passed = 0;
+        while test.try_output().await.is_some() {
+            passed += 1;
+        }
+        assert_eq!(passed, 6);
+    }
+}
@@ -0,0 +1,361 @@
+//! Geospatial Transform
+//!
+//! Enriches GPS fixes for asset-tracking pipelines. Depending on configuration
+//! it adds:
+//!
+//! - distance (metres) from a fixed reference point
+//! - names of the geofences that contain the fix, from inline polygons or a GeoJSON file
+//! - ground speed (m/s) computed from consecutive fixes of the same key
+//!
+//! Coordinates in polygons follow GeoJSON ordering, `[longitude, latitude]`.
+
+use crate::config::{ParamSpec, ParamType, ProcessorConfig, ProcessorMetadata, StageConfig, extract_param};
+use crate::core::checkpoint::Snapshot;
+use crate::core::state::StateStore;
+use crate::core::timing_mixin::{TimingMixin, WithTimingMixin};
+use crate::core::{context::ProcessingContext, message::Message};
+use crate::processors::common::field_utils::FieldUtils;
+use crate::processors::processor::Processor;
+
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use serde_json::{Number, Value};
+use std::time::SystemTime;
+use tracing::{debug, error};
+
+/// Mean Earth radius used by the haversine formula.
+const EARTH_RADIUS_M: f64 = 6_371_008.8;
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
+pub struct GeoPoint {
+    pub lat: f64,
+    pub lon: f64,
+}
+
+/// A named polygon. Only the outer ring is considered.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Geofence {
+    pub name: String,
+    pub polygon: Vec<[f64; 2]>,
+}
+
+#[derive(Debug, Clone)]
+pub struct GeoConfig {
+    pub lat_field: String,
+    pub lon_field: String,
+    pub key_field: Option<String>,
+    pub reference: Option<GeoPoint>,
+    pub distance_field: String,
+    pub geofences: Vec<Geofence>,
+    pub geofence_field: String,
+    pub speed: bool,
+    pub speed_field: String,
+    pub timing: Option<crate::config::TimingConfig>,
+}
+
+impl ProcessorConfig for GeoConfig {
+    fn from_stage_config(config: &StageConfig) -> Result<Self> {
+        let mut geofences = extract_param(&config.parameters, "geofences", Vec::<Geofence>::new());
+        if let Some(path) = extract_param(&config.parameters, "geofence_file", None::<String>) {
+            geofences.extend(load_geojson(&path)?);
+        }
+
+        let config = Self {
+            lat_field: extract_param(&config.parameters, "lat_field", "lat".to_string()),
+            lon_field: extract_param(&config.parameters, "lon_field", "lon".to_string()),
+            key_field: extract_param(&config.parameters, "key_field", None::<String>),
+            reference: extract_param(&config.parameters, "reference", None::<GeoPoint>),
+            distance_field: extract_param(&config.parameters, "distance_field", "distance_m".to_string()),
+            geofences,
+            geofence_field: extract_param(&config.parameters, "geofence_field", "geofences".to_string()),
+            speed: extract_param(&config.parameters, "speed", false),
+            speed_field: extract_param(&config.parameters, "speed_field", "speed_mps".to_string()),
+            timing: config.timing.clone(),
+        };
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.lat_field.is_empty() || self.lon_field.is_empty() {
+            return Err(anyhow!("lat_field and lon_field cannot be empty"));
+        }
+        if self.reference.is_none() && self.geofences.is_empty() && !self.speed {
+            return Err(anyhow!(
+                "geo processor requires at least one of 'reference', 'geofences'/'geofence_file', or 'speed'"
+            ));
+        }
+        if let Some(reference) = &self.reference
+            && !valid_coordinate(reference.lat, reference.lon)
+        {
+            return Err(anyhow!("reference point {:?} is out of range", reference));
+        }
+        for fence in &self.geofences {
+            if fence.polygon.len() < 3 {
+                return Err(anyhow!("geofence '{}' needs at least 3 vertices", fence.name));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn valid_coordinate(lat: f64, lon: f64) -> bool {
+    (-90.0..=90.0).contains(&lat) && (-180.0..=180.0).contains(&lon)
+}
+
+/// Load polygons from a GeoJSON Feature or FeatureCollection. Each feature's
+/// `properties.name` (or its index) names the fence; MultiPolygons contribute
+/// one fence per member polygon under the same name.
+fn load_geojson(path: &str) -> Result<Vec<Geofence>> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read geofence file '{}'", path))?;
+    let document: Value = serde_json::from_str(&contents)
+        .with_context(|| format!("Failed to parse geofence file '{}'", path))?;
+
+    let features = match document.get("type").and_then(Value::as_str) {
+        Some("FeatureCollection") => document
+            .get("features")
+            .and_then(Value::as_array)
+            .cloned()
+            .unwrap_or_default(),
+        Some("Feature") => vec![document],
+        other => return Err(anyhow!("Unsupported GeoJSON type {:?} in '{}'", other, path)),
+    };
+
+    let mut fences = Vec::new();
+    for (index, feature) in features.iter().enumerate() {
+        let name = feature
+            .pointer("/properties/name")
+            .and_then(Value::as_str)
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("feature_{}", index));
+        let geometry = feature
+            .get("geometry")
+            .ok_or_else(|| anyhow!("Feature '{}' has no geometry", name))?;
+        let coordinates = geometry.get("coordinates").cloned().unwrap_or(Value::Null);
+
+        let polygons: Vec<Vec<Vec<[f64; 2]>>> = match geometry.get("type").and_then(Value::as_str) {
+            Some("Polygon") => vec![serde_json::from_value(coordinates)?],
+            Some("MultiPolygon") => serde_json::from_value(coordinates)?,
+            other => {
+                debug!("Skipping feature '{}' with geometry {:?}", name, other);
+                continue;
+            }
+        };
+
+        for rings in polygons {
+            if let Some(outer) = rings.into_iter().next() {
+                fences.push(Geofence {
+                    name: name.clone(),
+                    polygon: outer,
+                });
+            }
+        }
+    }
+
+    Ok(fences)
+}
+
+/// Great-circle distance between two points in metres.
+pub fn haversine_m(a: GeoPoint, b: GeoPoint) -> f64 {
+    let (lat1, lat2) = (a.lat.to_radians(), b.lat.to_radians());
+    let dlat = lat2 - lat1;
+    let dlon = (b.lon - a.lon).to_radians();
+
+    let h = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_M * h.sqrt().asin()
+}
+
+/// Ray-casting point-in-polygon test on `[lon, lat]` vertices.
+pub fn point_in_polygon(point: GeoPoint, polygon: &[[f64; 2]]) -> bool {
+    let (x, y) = (point.lon, point.lat);
+    let mut inside = false;
+    let mut j = polygon.len().wrapping_sub(1);
+
+    for i in 0..polygon.len() {
+        let [xi, yi] = polygon[i];
+        let [xj, yj] = polygon[j];
+        if (yi > y) != (yj > y) && x < (xj - xi) * (y - yi) / (yj - yi) + xi {
+            inside = !inside;
+        }
+        j = i;
+    }
+
+    inside
+}
+
+pub struct GeoProcessor {
+    name: String,
+    config: GeoConfig,
+    timing: TimingMixin,
+    last_fixes: StateStore<String, (GeoPoint, SystemTime)>,
+}
+
+impl GeoProcessor {
+    pub const METADATA: ProcessorMetadata = ProcessorMetadata {
+        name: "geo",
+        description: "Adds distance, geofence membership, and speed from GPS fixes",
+        parameters: &[
+            ParamSpec::new("geofences", ParamType::Array, "Polygons to test fixes against"),
+            ParamSpec::new("geofence_file", ParamType::String, "GeoJSON file of further geofences"),
+            ParamSpec::new("lat_field", ParamType::String, "Latitude field"),
+            ParamSpec::new("lon_field", ParamType::String, "Longitude field"),
+            ParamSpec::new("key_field", ParamType::String, "Field whose value keeps separate tracks"),
+            ParamSpec::new("reference", ParamType::Object, "Point distances are measured from"),
+            ParamSpec::new("distance_field", ParamType::String, "Field receiving the distance in metres"),
+            ParamSpec::new("geofence_field", ParamType::String, "Field receiving the geofences containing the fix"),
+            ParamSpec::new("speed", ParamType::Boolean, "Compute speed between consecutive fixes"),
+            ParamSpec::new("speed_field", ParamType::String, "Field receiving the speed in metres per second"),
+        ],
+        shared: &[],
+    };
+
+    pub fn new(name: &str, config: StageConfig) -> Result<Box<dyn Processor>> {
+        let processor_config = GeoConfig::from_stage_config(&config)?;
+        processor_config.validate()?;
+
+        let timing = TimingMixin::new(processor_config.timing.as_ref());
+
+        Ok(Box::new(Self {
+            name: name.to_string(),
+            config: processor_config,
+            timing,
+            last_fixes: StateStore::open(name, config.state.as_ref())?,
+        }))
+    }
+
+    fn process_message(&mut self, mut message: Message) -> Result<Message> {
+        let lat = FieldUtils::extract_f64(&message.payload, &self.config.lat_field);
+        let lon = FieldUtils::extract_f64(&message.payload, &self.config.lon_field);
+
+        let point = match (lat, lon) {
+            (Some(lat), Some(lon)) if valid_coordinate(lat, lon) => GeoPoint { lat, lon },
+            _ => {
+                debug!("Message has no valid position, passing through");
+                return Ok(message);
+            }
+        };
+
+        if let Some(reference) = self.config.reference {
+            let distance = Number::from_f64(haversine_m(reference, point))
+                .ok_or_else(|| anyhow!("Distance is not finite"))?;
+            FieldUtils::set_field_value(
+                &mut message.payload,
+                &self.config.distance_field,
+                Value::Number(distance),
+            )?;
+        }
+
+        if !self.config.geofences.is_empty() {
+            let mut inside: Vec<Value> = Vec::new();
+            for fence in &self.config.geofences {
+                let name = Value::String(fence.name.clone());
+                if !inside.contains(&name) && point_in_polygon(point, &fence.polygon) {
+                    inside.push(name);
+                }
+            }
+            FieldUtils::set_field_value(
+                &mut message.payload,
+                &self.config.geofence_field,
+                Value::Array(inside),
+            )?;
+        }
+
+        if self.config.speed {
+            let key = FieldUtils::extract_key(&message.payload, self.config.key_field.as_deref());
+            let now = message.timing.event_time;
+
+            if let Some((previous, at)) = self.last_fixes.insert(key, (point, now))
+                && let Ok(elapsed) = now.duration_since(at)
+                && !elapsed.is_zero()
+                && let Some(speed) = Number::from_f64(haversine_m(previous, point) / elapsed.as_secs_f64())
+            {
+                FieldUtils::set_field_value(&mut message.payload, &self.config.speed_field, Value::Number(speed))?;
+            }
+        }
+
+        message.source = self.name.clone();
+        Ok(message)
+    }
+}
+
+#[async_trait::async_trait]
+impl Processor for GeoProcessor {
+    async fn init(&mut self) -> Result<()> {
+        tracing::info!(
+            "Geo processor '{}' initialised ({} geofences, reference: {:?}, speed: {})",
+            self.name,
+            self.config.geofences.len(),
+            self.config.reference,
+            self.config.speed
+        );
+        Ok(())
+    }
+
+    async fn process(&mut self, context: &mut ProcessingContext) -> Result<()> {
+        if let Some((_, message)) = context.recv(tokio::time::Duration::from_millis(10)).await {
+            match self.process_message(message) {
+                Ok(mut output_message) => {
+                    if let Some(output_info) = &context.output {
+                        output_message.topic = output_info.name.clone();
+                        let output_message = self.timing.update_message_watermark(output_message);
+
+                        if let Err(e) = output_info.channel.publish(output_message).await {
+                            tracing::warn!("Failed to publish geo output: {:?}", e);
+                        }
+                    }
+                }
+                Err(e) => {
+                    error!("Failed to apply geo transform: {}", e);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn as_snapshot(&mut self) -> Option<&mut dyn Snapshot> {
+        Some(self)
+    }
+}
+
+impl Snapshot for GeoProcessor {
+    fn snapshot(&self) -> Result<Value> {
+        self.last_fixes.snapshot()
+    }
+
+    fn restore(&mut self, state: Value) -> Result<()> {
+        self.last_fixes.restore(state)
+    }
+}
+
+impl WithTimingMixin for GeoProcessor {
+    fn timing_mixin(&self) -> &TimingMixin {
+        &self.timing
+    }
+
+    fn timing_mixin_mut(&mut self) -> &mut TimingMixin {
+        &mut self.timing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_haversine_distance() {
+        let london = GeoPoint { lat: 51.5007, lon: -0.1246 };
+        let paris = GeoPoint { lat: 48.8584, lon: 2.2945 };
+        let distance = haversine_m(london, paris);
+        assert!((distance - 340_600.0).abs() < 1_000.0, "got {}", distance);
+    }
+
+    #[test]
+    fn test_point_in_polygon() {
+        let square = [[0.0, 0.0], [10.0, 0.0], [10.0, 10.0], [0.0, 10.0], [0.0, 0.0]];
+        assert!(point_in_polygon(GeoPoint { lat: 5.0, lon: 5.0 }, &square));
+        assert!(!point_in_polygon(GeoPoint { lat: 5.0, lon: 15.0 }, &square));
+        assert!(!point_in_polygon(GeoPoint { lat: -1.0, lon: 5.0 }, &square));
+    }
+}
@@ -0,0 +1,524 @@
+//! HTTP Call Transform
+//!
+//! Enriches messages with the response of an external service: the payload,
+//! or just its `fields`, is sent as a JSON body to `url` and the JSON response
+//! is merged back into the message, either under `response_field` or, when
+//! that is unset, into the top level of the payload. Available with the
+//! `http` Cargo feature.
+//!
+//! ```toml
+//! parameters = { url = "http://scoring:8080/v1/risk", fields = ["temperature", "vibration"],
+//!     response_field = "risk", timeout_ms = 2000, max_concurrency = 8,
+//!     retry = { max_attempts = 2, base_delay_ms = 200 }, on_error = "route", error_output = "unscored" }
+//! ```
+//!
+//! Up to `max_concurrency` calls are in flight at once; messages are still
+//! published in the order they arrived. Connection failures, timeouts, and
+//! `408`, `429` and `5xx` responses are retried under the `retry` policy;
+//! other responses fail immediately. A message whose call fails is handled by
+//! `on_error`:
+//!
+//! - **pass**: forwarded, with `fallback` merged in place of the response if set
+//! - **drop**: discarded
+//! - **route**: published, like **pass**, to the `error_output` side output
+//!
+//! Failed messages that are kept record the error in `error_field` when it is set.
+
+use crate::config::{ParamSpec, ParamType, ProcessorConfig, ProcessorMetadata, StageConfig, extract_param, require_param};
+use crate::config::types::CredentialsConfig;
+use crate::core::timing_mixin::{TimingMixin, WithTimingMixin};
+use crate::core::{
+    context::{OutputInfo, ProcessingContext},
+    message::Message,
+};
+use crate::processors::common::field_utils::FieldUtils;
+use crate::processors::common::tcp::check_reachable;
+use crate::processors::common::{CREDENTIALS_PARAMS, RETRY_PARAMS, RetryPolicy, credentials};
+use crate::processors::processor::Processor;
+use crate::error::LiminalError;
+
+use anyhow::{Result, anyhow};
+use serde_json::{Map, Value};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::{debug, error, warn};
+
+/// What happens to a message whose call failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnError {
+    Pass,
+    Drop,
+    Route,
+}
+
+#[derive(Debug, Clone)]
+pub struct HttpCallConfig {
+    pub url: String,
+    /// `POST` or `PUT`
+    pub method: String,
+    /// Fields sent as the request body (the whole payload if empty)
+    pub fields: Vec<String>,
+    /// Field receiving the response (merged into the payload if unset)
+    pub response_field: Option<String>,
+    pub headers: HashMap<String, String>,
+    pub timeout_ms: u64,
+    /// Calls in flight at once
+    pub max_concurrency: usize,
+    pub retry: RetryPolicy,
+    pub credentials: CredentialsConfig,
+    pub on_error: OnError,
+    /// Value merged in place of the response of failed calls
+    pub fallback: Option<Value>,
+    /// Field recording the error of failed calls
+    pub error_field: Option<String>,
+    /// Side output receiving failed messages with `on_error = "route"`
+    pub error_output: Option<String>,
+    pub side_outputs: Vec<String>,
+    pub timing: Option<crate::config::TimingConfig>,
+}
+
+impl ProcessorConfig for HttpCallConfig {
+    fn from_stage_config(config: &StageConfig) -> Result<Self> {
+        let url = require_param::<String>(&config.parameters, "url")?;
+        let on_error = match extract_param(&config.parameters, "on_error", "pass".to_string()).as_str() {
+            "pass" => OnError::Pass,
+            "drop" => OnError::Drop,
+            "route" => OnError::Route,
+            other => return Err(anyhow!("Unknown on_error '{}' (expected pass, drop or route)", other)),
+        };
+        let default_retry = RetryPolicy {
+            max_attempts: 2,
+            base_delay_ms: 200,
+            max_delay_ms: 5000,
+            ..RetryPolicy::default()
+        };
+
+        let config = Self {
+            url,
+            method: extract_param(&config.parameters, "method", "POST".to_string()).to_uppercase(),
+            fields: extract_param(&config.parameters, "fields", Vec::<String>::new()),
+            response_field: extract_param(&config.parameters, "response_field", None::<String>),
+            headers: extract_param(&config.parameters, "headers", HashMap::new()),
+            timeout_ms: extract_param(&config.parameters, "timeout_ms", 5000),
+            max_concurrency: extract_param(&config.parameters, "max_concurrency", 1),
+            retry: RetryPolicy::from_parameters(&config.parameters, default_retry),
+            credentials: credentials(&config.parameters),
+            on_error,
+            fallback: extract_param(&config.parameters, "fallback", None::<Value>),
+            error_field: extract_param(&config.parameters, "error_field", None::<String>),
+            error_output: extract_param(&config.parameters, "error_output", None::<String>),
+            side_outputs: config.side_outputs.clone().unwrap_or_default(),
+            timing: config.timing.clone(),
+        };
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if !self.url.starts_with("http://") && !self.url.starts_with("https://") {
+            return Err(anyhow!("http_call url must start with http:// or https://"));
+        }
+        if self.method != "POST" && self.method != "PUT" {
+            return Err(anyhow!("http_call method must be POST or PUT"));
+        }
+        if self.timeout_ms == 0 {
+            return Err(LiminalError::invalid_parameter("timeout_ms", "must be greater than 0").into());
+        }
+        if self.max_concurrency == 0 {
+            return Err(LiminalError::invalid_parameter("max_concurrency", "must be greater than 0").into());
+        }
+        self.retry.validate()?;
+        if self.on_error == OnError::Route {
+            match &self.error_output {
+                None => return Err(anyhow!("on_error = \"route\" requires error_output")),
+                Some(output) if !self.side_outputs.contains(output) => {
+                    return Err(anyhow!(
+                        "error_output '{}' is not a side output of this stage (declared: {:?})",
+                        output,
+                        self.side_outputs
+                    ));
+                }
+                Some(_) => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A failed call, and whether it is worth retrying.
+struct CallError {
+    message: String,
+    retryable: bool,
+}
+
+/// The request side of the processor, shared with the tasks making calls.
+struct Caller {
+    client: reqwest::Client,
+    config: HttpCallConfig,
+}
+
+impl Caller {
+    /// The request body: the payload, or the configured fields of it.
+    fn body(&self, payload: &Value) -> Result<Value> {
+        if self.config.fields.is_empty() {
+            return Ok(payload.clone());
+        }
+        let mut body = Value::Object(Map::new());
+        for field in &self.config.fields {
+            if let Some(value) = FieldUtils::extract_field_value(payload, field) {
+                FieldUtils::set_field_value(&mut body, field, value.clone())?;
+            }
+        }
+        Ok(body)
+    }
+
+    /// Call the service, retrying transient failures.
+    async fn call(&self, body: &Value) -> Result<Value> {
+        let mut attempt = 0;
+        loop {
+            let error = match self.attempt(body).await {
+                Ok(response) => return Ok(response),
+                Err(error) => error,
+            };
+            attempt += 1;
+            let delay = if error.retryable { self.config.retry.delay(attempt) } else { None };
+            match delay {
+                Some(delay) => {
+                    debug!("HTTP call to '{}' failed ({}), retrying in {:?}", self.config.url, error.message, delay);
+                    tokio::time::sleep(delay).await;
+                }
+                None => return Err(anyhow!(error.message)),
+            }
+        }
+    }
+
+    async fn attempt(&self, body: &Value) -> std::result::Result<Value, CallError> {
+        let request = match self.config.method.as_str() {
+            "PUT" => self.client.put(&self.config.url),
+            _ => self.client.post(&self.config.url),
+        };
+        let request = match (&self.config.credentials.username, &self.config.credentials.token) {
+            (Some(username), _) => request.basic_auth(username, self.config.credentials.password.as_ref()),
+            (None, Some(token)) => request.bearer_auth(token),
+            (None, None) => request,
+        };
+        let request = self
+            .config
+            .headers
+            .iter()
+            .fold(request, |request, (name, value)| request.header(name, value));
+
+        let response = request.json(body).send().await.map_err(|e| CallError {
+            message: format!("request failed: {}", e),
+            retryable: true,
+        })?;
+        let status = response.status();
+        if !status.is_success() {
+            let text = response.text().await.unwrap_or_default();
+            return Err(CallError {
+                message: format!("service returned {}: {}", status, text),
+                retryable: status.is_server_error() || status.as_u16() == 408 || status.as_u16() == 429,
+            });
+        }
+        response.json().await.map_err(|e| CallError {
+            message: format!("invalid JSON response: {}", e),
+            retryable: false,
+        })
+    }
+}
+
+/// A call in flight, resolving to the message and the service's response.
+type Call = JoinHandle<(Message, Result<Value>)>;
+
+pub struct HttpCallProcessor {
+    name: String,
+    caller: Arc<Caller>,
+    in_flight: VecDeque<Call>,
+    timing: TimingMixin,
+}
+
+impl HttpCallProcessor {
+    pub const METADATA: ProcessorMetadata = ProcessorMetadata {
+        name: "http_call",
+        description: "Enriches messages with the JSON response of an HTTP service",
+        parameters: &[
+            ParamSpec::new("url", ParamType::String, "URL of the service"),
+            ParamSpec::new("method", ParamType::Choice(&["POST", "PUT"]), "HTTP method"),
+            ParamSpec::new("fields", ParamType::Array, "Fields sent as the body (defaults to the whole payload)"),
+            ParamSpec::new("response_field", ParamType::String, "Field receiving the response (merged into the payload if unset)"),
+            ParamSpec::new("headers", ParamType::Object, "Extra request headers"),
+            ParamSpec::new("timeout_ms", ParamType::Integer, "Timeout of each attempt"),
+            ParamSpec::new("max_concurrency", ParamType::Integer, "Calls in flight at once"),
+            ParamSpec::new("on_error", ParamType::Choice(&["pass", "drop", "route"]), "What happens to messages whose call failed"),
+            ParamSpec::new("fallback", ParamType::Any, "Value merged in place of the response of failed calls"),
+            ParamSpec::new("error_field", ParamType::String, "Field recording the error of failed calls"),
+            ParamSpec::new("error_output", ParamType::String, "Side output receiving failed messages (on_error = \"route\")"),
+        ],
+        shared: &[RETRY_PARAMS, CREDENTIALS_PARAMS],
+    };
+
+    pub fn new(name: &str, config: StageConfig) -> Result<Box<dyn Processor>> {
+        let processor_config = HttpCallConfig::from_stage_config(&config)?;
+        let timing = TimingMixin::new(processor_config.timing.as_ref());
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_millis(processor_config.timeout_ms))
+            .build()?;
+
+        Ok(Box::new(Self {
+            name: name.to_string(),
+            caller: Arc::new(Caller {
+                client,
+                config: processor_config,
+            }),
+            in_flight: VecDeque::new(),
+            timing,
+        }))
+    }
+
+    fn start(&mut self, message: Message) {
+        let caller = self.caller.clone();
+        self.in_flight.push_back(tokio::spawn(async move {
+            let response = match caller.body(&message.payload) {
+                Ok(body) => caller.call(&body).await,
+                Err(e) => Err(e),
+            };
+            (message, response)
+        }));
+    }
+
+    /// Wait for the oldest call and publish its message.
+    async fn finish_next(&mut self, context: &ProcessingContext) {
+        let Some(call) = self.in_flight.pop_front() else {
+            return;
+        };
+        let (mut message, response) = match call.await {
+            Ok(result) => result,
+            Err(e) => {
+                error!("{}: HTTP call task failed: {}", self.name, e);
+                return;
+            }
+        };
+        message.source = self.name.clone();
+        let config = &self.caller.config;
+
+        let output = match response {
+            Ok(response) => match merge(&mut message.payload, config.response_field.as_deref(), response) {
+                Ok(()) => context.output.as_ref(),
+                Err(e) => {
+                    error!("{}: Failed to merge response: {}", self.name, e);
+                    return;
+                }
+            },
+            Err(e) => {
+                warn!("{}: HTTP call to '{}' failed: {}", self.name, config.url, e);
+                if config.on_error == OnError::Drop {
+                    return;
+                }
+                if let Some(fallback) = &config.fallback
+                    && let Err(e) = merge(&mut message.payload, config.response_field.as_deref(), fallback.clone())
+                {
+                    error!("{}: Failed to merge fallback: {}", self.name, e);
+                }
+                if let Some(error_field) = &config.error_field
+                    && let Err(e) = FieldUtils::set_field_value(&mut message.payload, error_field, Value::String(e.to_string()))
+                {
+                    error!("{}: Failed to record call error: {}", self.name, e);
+                }
+                match (&config.on_error, &config.error_output) {
+                    (OnError::Route, Some(error_output)) => context.side_outputs.get(error_output),
+                    _ => context.output.as_ref(),
+                }
+            }
+        };
+
+        if let Some(output_info) = output {
+            self.publish(output_info, message).await;
+        }
+    }
+
+    async fn publish(&mut self, output_info: &OutputInfo, mut message: Message) {
+        message.topic = output_info.name.clone();
+        let message = self.timing.update_message_watermark(message);
+
+        if let Err(e) = output_info.channel.publish(message).await {
+            warn!("Failed to publish http_call output to '{}': {:?}", output_info.name, e);
+        }
+    }
+}
+
+/// Merge a response into a payload, under `field` or, when unset, key by key.
+fn merge(payload: &mut Value, field: Option<&str>, response: Value) -> Result<()> {
+    if let Some(field) = field {
+        return FieldUtils::set_field_value(payload, field, response);
+    }
+    match (payload, response) {
+        (Value::Object(payload), Value::Object(response)) => {
+            payload.extend(response);
+            Ok(())
+        }
+        _ => Err(anyhow!("responses merged without response_field must be JSON objects")),
+    }
+}
+
+#[async_trait::async_trait]
+impl Processor for HttpCallProcessor {
+    async fn init(&mut self) -> Result<()> {
+        tracing::info!(
+            "HTTP call processor '{}' initialised ({} {})",
+            self.name,
+            self.caller.config.method,
+            self.caller.config.url
+        );
+        Ok(())
+    }
+
+    /// Check that the service's host accepts connections. Whether the
+    /// service answers requests is left to the calls themselves.
+    async fn preflight(&self) -> Result<()> {
+        let url = reqwest::Url::parse(&self.caller.config.url)
+            .map_err(|e| LiminalError::invalid_parameter("url", e))?;
+        let host = url.host_str().ok_or_else(|| LiminalError::invalid_parameter("url", "no host"))?;
+        let port = url.port_or_known_default().unwrap_or(80);
+        check_reachable(host, port, None).await
+    }
+
+    async fn process(&mut self, context: &mut ProcessingContext) -> Result<()> {
+        if self.in_flight.len() < self.caller.config.max_concurrency {
+            if let Some((_, message)) = context.recv(tokio::time::Duration::from_millis(10)).await {
+                self.start(message);
+            }
+        } else {
+            self.finish_next(context).await;
+        }
+
+        while self.in_flight.front().is_some_and(|call| call.is_finished()) {
+            self.finish_next(context).await;
+        }
+        Ok(())
+    }
+
+    async fn flush(&mut self, context: &mut ProcessingContext) -> Result<()> {
+        while !self.in_flight.is_empty() {
+            self.finish_next(context).await;
+        }
+        Ok(())
+    }
+}
+
+impl WithTimingMixin for HttpCallProcessor {
+    fn timing_mixin(&self) -> &TimingMixin {
+        &self.timing
+    }
+
+    fn timing_mixin_mut(&mut self) -> &mut TimingMixin {
+        &mut self.timing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{MessageBuilder, TestContext};
+    use serde_json::json;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// A service doubling `temperature` into `risk`, failing for negative readings.
+    async fn serve() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut request = Vec::new();
+                    let mut buffer = [0u8; 1024];
+                    let body = loop {
+                        let read = stream.read(&mut buffer).await.unwrap();
+                        if read == 0 {
+                            return;
+                        }
+                        request.extend_from_slice(&buffer[..read]);
+                        let text = String::from_utf8_lossy(&request);
+                        if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                            let length = head
+                                .lines()
+                                .find_map(|line| line.to_lowercase().strip_prefix("content-length:").map(|v| v.trim().to_string()))
+                                .and_then(|v| v.parse::<usize>().ok())
+                                .unwrap_or(0);
+                            if body.len() >= length {
+                                break body.to_string();
+                            }
+                        }
+                    };
+                    let body: Value = serde_json::from_str(&body).unwrap();
+                    let temperature = body["temperature"].as_f64().unwrap();
+                    let (status, reply) = if temperature < 0.0 {
+                        ("400 Bad Request", json!({ "error": "negative" }))
+                    } else {
+                        ("200 OK", json!({ "risk": temperature * 2.0, "fields": body.as_object().unwrap().len() }))
+                    };
+                    let reply = reply.to_string();
+                    let response = format!(
+                        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        status,
+                        reply.len(),
+                        reply
+                    );
+                    let _ = stream.write_all(response.as_bytes()).await;
+                });
+            }
+        });
+        format!("http://{}/score", address)
+    }
+
+    #[tokio::test]
+    async fn test_http_call_merges_responses_in_order() {
+        let stage = StageConfig {
+            r#type: "http_call".to_string(),
+            inputs: Some(vec!["in".to_string()]),
+            output: Some("out".to_string()),
+            side_outputs: Some(vec!["unscored".to_string()]),
+            parameters: serde_json::from_value(json!({
+                "url": serve().await,
+                "fields": ["temperature"],
+                "response_field": "score",
+                "max_concurrency": 4,
+                "on_error": "route",
+                "error_output": "unscored",
+                "error_field": "score_error",
+                "fallback": { "risk": 0.0 },
+            }))
+            .ok(),
+            ..Default::default()
+        };
+        let mut processor = HttpCallProcessor::new("score", stage).unwrap();
+        let mut test = TestContext::new("score").input("in").output("out").side_output("unscored");
+
+        for temperature in [10.0, -1.0, 20.0, 30.0] {
+            let payload = json!({ "sensor": "a", "temperature": temperature });
+            test.send("in", MessageBuilder::new(payload).build()).await.unwrap();
+        }
+        test.process_pending(processor.as_mut()).await.unwrap();
+        processor.flush(&mut test.context).await.unwrap();
+
+        let mut scored = Vec::new();
+        while let Some(message) = test.try_output().await {
+            scored.push(message.payload["score"].clone());
+        }
+        assert_eq!(
+            scored,
+            vec![
+                json!({ "risk": 20.0, "fields": 1 }),
+                json!({ "risk": 40.0, "fields": 1 }),
+                json!({ "risk": 60.0, "fields": 1 }),
+            ]
+        );
+
+        let unscored = test.try_side_output("unscored").await.unwrap().payload;
+        assert_eq!(unscored["score"], json!({ "risk": 0.0 }));
+        assert!(unscored["score_error"].as_str().unwrap().contains("400"));
+    }
+}
@@ -0,0 +1,345 @@
+//! Hysteresis Transform
+//!
+//! Converts a noisy numeric stream into a stable two-level state using separate
+//! rising and falling thresholds (a Schmitt trigger) and an optional minimum hold
+//! time, so that threshold-based alerts stop flapping. State is tracked per key
+//! and, by default, messages are only emitted on state transitions.
+
+use crate::config::{ParamSpec, ParamType, ProcessorConfig, ProcessorMetadata, StageConfig, extract_param, require_param};
+use crate::core::checkpoint::Snapshot;
+use crate::core::state::StateStore;
+use crate::core::timing_mixin::{TimingMixin, WithTimingMixin};
+use crate::core::{context::ProcessingContext, message::Message};
+use crate::processors::common::field_utils::FieldUtils;
+use crate::processors::processor::Processor;
+
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::time::{Duration, SystemTime};
+use tracing::{debug, error};
+
+/// Discrete output level.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Level {
+    Low,
+    High,
+}
+
+/// Which messages the hysteresis transform forwards.
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EmitMode {
+    /// Only forward the message that caused a state change
+    Transitions,
+    /// Forward every message, annotated with the current state
+    All,
+}
+
+#[derive(Debug, Clone)]
+pub struct HysteresisConfig {
+    pub field_in: String,
+    pub field_out: String,
+    pub key_field: Option<String>,
+    pub rising_threshold: f64,
+    pub falling_threshold: f64,
+    pub min_hold_ms: u64,
+    pub initial_state: Level,
+    pub high_value: Value,
+    pub low_value: Value,
+    pub emit: EmitMode,
+    pub timing: Option<crate::config::TimingConfig>,
+}
+
+impl ProcessorConfig for HysteresisConfig {
+    fn from_stage_config(config: &StageConfig) -> Result<Self> {
+        let field_in = require_param::<String>(&config.parameters, "field_in")?;
+        let field_out =
+            extract_param(&config.parameters, "field_out", format!("{}_state", field_in));
+        let key_field = extract_param(&config.parameters, "key_field", None::<String>);
+        let rising_threshold = require_param::<f64>(&config.parameters, "rising_threshold")?;
+        let falling_threshold = require_param::<f64>(&config.parameters, "falling_threshold")?;
+        let min_hold_ms = extract_param(&config.parameters, "min_hold_ms", 0_u64);
+        let initial_state = extract_param(&config.parameters, "initial_state", Level::Low);
+        let high_value = extract_param(&config.parameters, "high_value", Value::Bool(true));
+        let low_value = extract_param(&config.parameters, "low_value", Value::Bool(false));
+        let emit = extract_param(&config.parameters, "emit", EmitMode::Transitions);
+
+        let config = Self {
+            field_in,
+            field_out,
+            key_field,
+            rising_threshold,
+            falling_threshold,
+            min_hold_ms,
+            initial_state,
+            high_value,
+            low_value,
+            emit,
+            timing: config.timing.clone(),
+        };
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.field_in.is_empty() || self.field_out.is_empty() {
+            return Err(anyhow!("field_in and field_out cannot be empty"));
+        }
+        if self.falling_threshold > self.rising_threshold {
+            return Err(anyhow!(
+                "falling_threshold ({}) must not exceed rising_threshold ({})",
+                self.falling_threshold,
+                self.rising_threshold
+            ));
+        }
+        if self.high_value == self.low_value {
+            return Err(anyhow!("high_value and low_value must differ"));
+        }
+        Ok(())
+    }
+}
+
+/// Per-key state machine.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct HysteresisState {
+    level: Level,
+    /// Level the signal is trying to move to, and since when
+    pending: Option<(Level, SystemTime)>,
+}
+
+pub struct HysteresisProcessor {
+    name: String,
+    config: HysteresisConfig,
+    timing: TimingMixin,
+    states: StateStore<String, HysteresisState>,
+}
+
+impl HysteresisProcessor {
+    pub const METADATA: ProcessorMetadata = ProcessorMetadata {
+        name: "hysteresis",
+        description: "Debounces numeric signals into stable states",
+        parameters: &[
+            ParamSpec::new("field_in", ParamType::String, "Numeric field to debounce"),
+            ParamSpec::new("field_out", ParamType::String, "Field receiving the state"),
+            ParamSpec::new("key_field", ParamType::String, "Field whose value keeps separate state"),
+            ParamSpec::new("rising_threshold", ParamType::Number, "Value at or above which the state goes high"),
+            ParamSpec::new("falling_threshold", ParamType::Number, "Value at or below which the state goes low"),
+            ParamSpec::new("min_hold_ms", ParamType::Integer, "Minimum time a state is held before it can change"),
+            ParamSpec::new("initial_state", ParamType::Choice(&["low", "high"]), "State before the first reading"),
+            ParamSpec::new("high_value", ParamType::Any, "Value written for the high state"),
+            ParamSpec::new("low_value", ParamType::Any, "Value written for the low state"),
+            ParamSpec::new("emit", ParamType::Choice(&["transitions", "all"]), "Emit only state changes, or every reading"),
+        ],
+        shared: &[],
+    };
+
+    pub fn new(name: &str, config: StageConfig) -> Result<Box<dyn Processor>> {
+        let processor_config = HysteresisConfig::from_stage_config(&config)?;
+        processor_config.validate()?;
+
+        let timing = TimingMixin::new(processor_config.timing.as_ref());
+
+        Ok(Box::new(Self {
+            name: name.to_string(),
+            config: processor_config,
+            timing,
+            states: StateStore::open(name, config.state.as_ref())?,
+        }))
+    }
+
+    /// Feed a sample into the state machine, returning the current level and
+    /// whether this sample caused a transition.
+    fn update(&mut self, key: String, value: f64, now: SystemTime) -> (Level, bool) {
+        let initial_state = self.config.initial_state;
+        let state = self.states.get_or_insert_with(key, || HysteresisState {
+            level: initial_state,
+            pending: None,
+        });
+
+        // Which level does this sample argue for? Values inside the dead band
+        // do not argue for anything and leave any pending transition untouched.
+        let target = if value >= self.config.rising_threshold {
+            Some(Level::High)
+        } else if value <= self.config.falling_threshold {
+            Some(Level::Low)
+        } else {
+            None
+        };
+
+        match target {
+            Some(level) if level == state.level => {
+                // Signal went back: cancel any pending transition
+                state.pending = None;
+            }
+            Some(level) if !matches!(state.pending, Some((pending, _)) if pending == level) => {
+                state.pending = Some((level, now));
+            }
+            _ => {}
+        }
+
+        if let Some((level, since)) = state.pending {
+            let held = now.duration_since(since).unwrap_or(Duration::ZERO);
+            if held >= Duration::from_millis(self.config.min_hold_ms) {
+                state.level = level;
+                state.pending = None;
+                return (level, true);
+            }
+        }
+
+        (state.level, false)
+    }
+
+    fn process_message(&mut self, mut message: Message) -> Result<Option<Message>> {
+        let Some(value) = FieldUtils::extract_f64(&message.payload, &self.config.field_in) else {
+            debug!(
+                "Field '{}' missing or not numeric, skipping message",
+                self.config.field_in
+            );
+            return Ok(None);
+        };
+
+        let key = FieldUtils::extract_key(&message.payload, self.config.key_field.as_deref());
+        let (level, transitioned) = self.update(key, value, message.timing.event_time);
+
+        if !transitioned && self.config.emit == EmitMode::Transitions {
+            return Ok(None);
+        }
+
+        if transitioned {
+            debug!("Hysteresis state changed to {:?} (value {})", level, value);
+        }
+
+        let state_value = match level {
+            Level::High => self.config.high_value.clone(),
+            Level::Low => self.config.low_value.clone(),
+        };
+        FieldUtils::set_field_value(&mut message.payload, &self.config.field_out, state_value)?;
+
+        message.source = self.name.clone();
+        Ok(Some(message))
+    }
+}
+
+#[async_trait::async_trait]
+impl Processor for HysteresisProcessor {
+    async fn init(&mut self) -> Result<()> {
+        tracing::info!(
+            "Hysteresis processor '{}' initialised (rising: {}, falling: {}, hold: {}ms)",
+            self.name,
+            self.config.rising_threshold,
+            self.config.falling_threshold,
+            self.config.min_hold_ms
+        );
+        Ok(())
+    }
+
+    async fn process(&mut self, context: &mut ProcessingContext) -> Result<()> {
+        if let Some((channel_name, message)) = context.recv(tokio::time::Duration::from_millis(10)).await {
+            match self.process_message(message) {
+                Ok(Some(mut output_message)) => {
+                    if let Some(output_info) = &context.output {
+                        output_message.topic = output_info.name.clone();
+                        let output_message = self.timing.update_message_watermark(output_message);
+
+                        if let Err(e) = output_info.channel.publish(output_message).await {
+                            tracing::warn!("Failed to publish hysteresis output: {:?}", e);
+                        }
+                    }
+                }
+                Ok(None) => {
+                    debug!("No state transition for message from '{}'", channel_name);
+                }
+                Err(e) => {
+                    error!("Failed to apply hysteresis: {}", e);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn as_snapshot(&mut self) -> Option<&mut dyn Snapshot> {
+        Some(self)
+    }
+}
+
+impl Snapshot for HysteresisProcessor {
+    fn snapshot(&self) -> Result<Value> {
+        self.states.snapshot()
+    }
+
+    fn restore(&mut self, state: Value) -> Result<()> {
+        self.states.restore(state)
+    }
+}
+
+impl WithTimingMixin for HysteresisProcessor {
+    fn timing_mixin(&self) -> &TimingMixin {
+        &self.timing
+    }
+
+    fn timing_mixin_mut(&mut self) -> &mut TimingMixin {
+        &mut self.timing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    fn processor(min_hold_ms: u64) -> HysteresisProcessor {
+        let stage_config = StageConfig {
+            r#type: "hysteresis".to_string(),
+            parameters: Some(HashMap::from([
+                ("field_in".to_string(), json!("temperature")),
+                ("rising_threshold".to_string(), json!(30.0)),
+                ("falling_threshold".to_string(), json!(25.0)),
+                ("min_hold_ms".to_string(), json!(min_hold_ms)),
+            ])),
+            ..Default::default()
+        };
+        let config = HysteresisConfig::from_stage_config(&stage_config).unwrap();
+        HysteresisProcessor {
+            name: "test".to_string(),
+            timing: TimingMixin::new(None),
+            config,
+            states: StateStore::new(),
+        }
+    }
+
+    #[test]
+    fn test_hysteresis_ignores_dead_band() {
+        let mut processor = processor(0);
+        let t = SystemTime::now();
+
+        assert_eq!(processor.update("k".into(), 31.0, t), (Level::High, true));
+        assert_eq!(processor.update("k".into(), 27.0, t), (Level::High, false));
+        assert_eq!(processor.update("k".into(), 29.9, t), (Level::High, false));
+        assert_eq!(processor.update("k".into(), 24.0, t), (Level::Low, true));
+    }
+
+    #[test]
+    fn test_hysteresis_min_hold() {
+        let mut processor = processor(1000);
+        let t = SystemTime::now();
+
+        assert_eq!(processor.update("k".into(), 31.0, t), (Level::Low, false));
+        // Excursion cancelled before the hold time elapsed
+        assert_eq!(
+            processor.update("k".into(), 20.0, t + Duration::from_millis(500)),
+            (Level::Low, false)
+        );
+        assert_eq!(
+            processor.update("k".into(), 31.0, t + Duration::from_millis(600)),
+            (Level::Low, false)
+        );
+        assert_eq!(
+            processor.update("k".into(), 32.0, t + Duration::from_millis(1700)),
+            (Level::High, true)
+        );
+    }
+}
@@ -0,0 +1,305 @@
+//! Liveness Transform
+//!
+//! Heartbeat monitor that tracks when each key (e.g. each sensor) last produced
+//! a message and emits an event when it goes silent for longer than
+//! `timeout_ms`, and another when it reports again. Silence is measured on the
+//! wall clock, since absence of data has no event time. Keys listed in
+//! `expected_keys` are monitored from startup even if they never report.
+//!
+//! Data messages are forwarded to the main output unchanged (unless `forward` is
+//! false). Events go to `event_output` when set, which must be one of the
+//! stage's `side_outputs`, and to the main output otherwise.
+
+use crate::config::{ParamSpec, ParamType, ProcessorConfig, ProcessorMetadata, StageConfig, extract_param};
+use crate::core::timing_mixin::{TimingMixin, WithTimingMixin};
+use crate::core::{
+    context::{OutputInfo, ProcessingContext},
+    message::Message,
+};
+use crate::processors::common::field_utils::FieldUtils;
+use crate::processors::processor::Processor;
+use crate::error::LiminalError;
+
+use anyhow::{Result, anyhow};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::{debug, error};
+
+#[derive(Debug, Clone)]
+pub struct LivenessConfig {
+    pub key_field: Option<String>,
+    /// Silence longer than this marks a key as silent
+    pub timeout_ms: u64,
+    /// Keys monitored from startup, before their first message
+    pub expected_keys: Vec<String>,
+    /// Forward data messages to the main output
+    pub forward: bool,
+    /// Side output that receives liveness events
+    pub event_output: Option<String>,
+    pub silent_event: String,
+    pub recovered_event: String,
+    pub side_outputs: Vec<String>,
+    pub timing: Option<crate::config::TimingConfig>,
+}
+
+impl ProcessorConfig for LivenessConfig {
+    fn from_stage_config(config: &StageConfig) -> Result<Self> {
+        let config = Self {
+            key_field: extract_param(&config.parameters, "key_field", None::<String>),
+            timeout_ms: extract_param(&config.parameters, "timeout_ms", 30_000_u64),
+            expected_keys: extract_param(&config.parameters, "expected_keys", Vec::<String>::new()),
+            forward: extract_param(&config.parameters, "forward", true),
+            event_output: extract_param(&config.parameters, "event_output", None::<String>),
+            silent_event: extract_param(&config.parameters, "silent_event", "sensor_silent".to_string()),
+            recovered_event: extract_param(&config.parameters, "recovered_event", "sensor_recovered".to_string()),
+            side_outputs: config.side_outputs.clone().unwrap_or_default(),
+            timing: config.timing.clone(),
+        };
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.timeout_ms == 0 {
+            return Err(LiminalError::invalid_parameter("timeout_ms", "must be greater than 0").into());
+        }
+        if self.silent_event.is_empty() || self.recovered_event.is_empty() {
+            return Err(anyhow!("silent_event and recovered_event cannot be empty"));
+        }
+        if let Some(output) = &self.event_output
+            && !self.side_outputs.contains(output)
+        {
+            return Err(anyhow!(
+                "event_output '{}' is not a side output of this stage (declared: {:?})",
+                output,
+                self.side_outputs
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+struct KeyState {
+    last_seen: Instant,
+    /// Event time of the last message, if any has arrived
+    last_event_time: Option<SystemTime>,
+    /// When the silent event was raised, while the key is silent
+    silent_since: Option<Instant>,
+}
+
+pub struct LivenessProcessor {
+    name: String,
+    config: LivenessConfig,
+    timing: TimingMixin,
+    keys: HashMap<String, KeyState>,
+}
+
+impl LivenessProcessor {
+    pub const METADATA: ProcessorMetadata = ProcessorMetadata {
+        name: "liveness",
+        description: "Emits silent/recovered events when keys stop or resume reporting",
+        parameters: &[
+            ParamSpec::new("key_field", ParamType::String, "Field whose value identifies a reporter"),
+            ParamSpec::new("timeout_ms", ParamType::Integer, "Silence longer than this marks a key as silent"),
+            ParamSpec::new("expected_keys", ParamType::Array, "Keys monitored from startup, before their first message"),
+            ParamSpec::new("forward", ParamType::Boolean, "Forward data messages to the main output"),
+            ParamSpec::new("event_output", ParamType::String, "Side output that receives liveness events"),
+            ParamSpec::new("silent_event", ParamType::String, "Event name when a key goes silent"),
+            ParamSpec::new("recovered_event", ParamType::String, "Event name when a key reports again"),
+        ],
+        shared: &[],
+    };
+
+    pub fn new(name: &str, config: StageConfig) -> Result<Box<dyn Processor>> {
+        let processor_config = LivenessConfig::from_stage_config(&config)?;
+        processor_config.validate()?;
+
+        let timing = TimingMixin::new(processor_config.timing.as_ref());
+        let keys = Self::expected_state(&processor_config, Instant::now());
+
+        Ok(Box::new(Self {
+            name: name.to_string(),
+            config: processor_config,
+            timing,
+            keys,
+        }))
+    }
+
+    fn expected_state(config: &LivenessConfig, now: Instant) -> HashMap<String, KeyState> {
+        config
+            .expected_keys
+            .iter()
+            .map(|key| {
+                let state = KeyState {
+                    last_seen: now,
+                    last_event_time: None,
+                    silent_since: None,
+                };
+                (key.clone(), state)
+            })
+            .collect()
+    }
+
+    fn build_event(&self, event: &str, key: &str, state: &KeyState, silent_for: Duration) -> Message {
+        let mut payload = json!({
+            "event": event,
+            "silent_for_ms": silent_for.as_millis() as u64,
+        });
+        let key_field = self.config.key_field.as_deref().unwrap_or("key");
+        let _ = FieldUtils::set_field_value(&mut payload, key_field, Value::String(key.to_string()));
+        if let Some(time) = state.last_event_time
+            && let Ok(since_epoch) = time.duration_since(UNIX_EPOCH)
+        {
+            payload["last_event_time_ms"] = json!(since_epoch.as_millis() as u64);
+        }
+
+        Message::new(&self.name, "", payload)
+    }
+
+    /// Record a message, returning a recovery event if its key was silent.
+    fn observe(&mut self, message: &Message, now: Instant) -> Option<Message> {
+        let key = FieldUtils::extract_key(&message.payload, self.config.key_field.as_deref());
+        let state = self.keys.entry(key.clone()).or_insert(KeyState {
+            last_seen: now,
+            last_event_time: None,
+            silent_since: None,
+        });
+
+        let silent_for = now.saturating_duration_since(state.last_seen);
+        let was_silent = state.silent_since.take().is_some();
+        state.last_seen = now;
+        state.last_event_time = Some(message.timing.event_time);
+
+        let state = state.clone();
+        was_silent.then(|| self.build_event(&self.config.recovered_event, &key, &state, silent_for))
+    }
+
+    /// Raise silent events for keys that have exceeded the timeout.
+    fn check_silence(&mut self, now: Instant) -> Vec<Message> {
+        let timeout = Duration::from_millis(self.config.timeout_ms);
+        let mut silenced = Vec::new();
+
+        for (key, state) in self.keys.iter_mut() {
+            let silent_for = now.saturating_duration_since(state.last_seen);
+            if state.silent_since.is_none() && silent_for > timeout {
+                state.silent_since = Some(now);
+                silenced.push((key.clone(), state.clone(), silent_for));
+            }
+        }
+
+        silenced
+            .into_iter()
+            .map(|(key, state, silent_for)| self.build_event(&self.config.silent_event, &key, &state, silent_for))
+            .collect()
+    }
+
+    async fn publish(&mut self, output_info: &OutputInfo, mut message: Message) {
+        message.topic = output_info.name.clone();
+        let message = self.timing.update_message_watermark(message);
+
+        if let Err(e) = output_info.channel.publish(message).await {
+            tracing::warn!("Failed to publish liveness output to '{}': {:?}", output_info.name, e);
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Processor for LivenessProcessor {
+    async fn init(&mut self) -> Result<()> {
+        tracing::info!(
+            "Liveness processor '{}' initialised (timeout: {} ms, {} expected keys)",
+            self.name,
+            self.config.timeout_ms,
+            self.config.expected_keys.len()
+        );
+        Ok(())
+    }
+
+    async fn process(&mut self, context: &mut ProcessingContext) -> Result<()> {
+        let mut events = Vec::new();
+
+        if let Some((channel_name, mut message)) = context.recv(tokio::time::Duration::from_millis(10)).await {
+            events.extend(self.observe(&message, Instant::now()));
+
+            if self.config.forward
+                && let Some(output_info) = &context.output
+            {
+                message.source = self.name.clone();
+                self.publish(output_info, message).await;
+            } else {
+                debug!("Message from '{}' consumed by liveness monitor", channel_name);
+            }
+        }
+        events.extend(self.check_silence(Instant::now()));
+
+        let event_output = match &self.config.event_output {
+            Some(name) => context.side_outputs.get(name),
+            None => context.output.as_ref(),
+        };
+        match event_output {
+            Some(output_info) => {
+                for event in events {
+                    self.publish(output_info, event).await;
+                }
+            }
+            None if !events.is_empty() => error!("Liveness events from '{}' have no output", self.name),
+            None => {}
+        }
+        Ok(())
+    }
+}
+
+impl WithTimingMixin for LivenessProcessor {
+    fn timing_mixin(&self) -> &TimingMixin {
+        &self.timing
+    }
+
+    fn timing_mixin_mut(&mut self) -> &mut TimingMixin {
+        &mut self.timing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_liveness_silent_and_recovered() {
+        let config = StageConfig {
+            r#type: "liveness".to_string(),
+            parameters: Some(HashMap::from([
+                ("key_field".to_string(), json!("sensor")),
+                ("timeout_ms".to_string(), json!(1000)),
+                ("expected_keys".to_string(), json!(["s2"])),
+            ])),
+            ..Default::default()
+        };
+        let config = LivenessConfig::from_stage_config(&config).unwrap();
+        let start = Instant::now();
+        let mut processor = LivenessProcessor {
+            name: "liveness".to_string(),
+            keys: LivenessProcessor::expected_state(&config, start),
+            config,
+            timing: TimingMixin::new(None),
+        };
+
+        let reading = Message::new("src", "topic", json!({"sensor": "s1"}));
+        assert!(processor.observe(&reading, start).is_none());
+        assert!(processor.check_silence(start + Duration::from_millis(500)).is_empty());
+
+        // s1 and the never-seen s2 both go silent, and are reported only once
+        let mut events = processor.check_silence(start + Duration::from_millis(1500));
+        events.sort_by_key(|e| e.payload["sensor"].to_string());
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].payload["event"], json!("sensor_silent"));
+        assert_eq!(events[1].payload["sensor"], json!("s2"));
+        assert!(processor.check_silence(start + Duration::from_millis(3000)).is_empty());
+
+        let recovered = processor.observe(&reading, start + Duration::from_millis(4000)).unwrap();
+        assert_eq!(recovered.payload["event"], json!("sensor_recovered"));
+        assert_eq!(recovered.payload["silent_for_ms"], json!(4000));
+    }
+}
@@ -0,0 +1,277 @@
+//! Map Values Transform
+//!
+//! Converts a field through a lookup table, in one of two ways:
+//!
+//! - **mapping**: replaces discrete values, such as status codes, with the
+//!   value they map to (`{ "0" = "ok", "1" = "warning", "2" = "fault" }`);
+//!   numbers and booleans are looked up by their text
+//! - **breakpoints**: interpolates piecewise-linearly between `[x, y]` points,
+//!   such as raw ADC counts against a thermistor's temperatures; readings
+//!   outside the table are clamped to its ends, or extended along its first
+//!   and last segments with `extrapolate`
+//!
+//! ```toml
+//! parameters = { field_in = "adc", field_out = "temperature",
+//!     breakpoints = [[120, 85.0], [410, 50.0], [1650, 0.0], [3350, -40.0]] }
+//! ```
+//!
+//! Values without a mapping are given `default` when it is set, and otherwise
+//! leave `field_out` unset.
+
+use crate::config::{ParamSpec, ParamType, ProcessorConfig, ProcessorMetadata, StageConfig, extract_param, require_param};
+use crate::core::timing_mixin::{TimingMixin, WithTimingMixin};
+use crate::core::{context::ProcessingContext, message::Message};
+use crate::processors::common::field_utils::FieldUtils;
+use crate::processors::processor::Processor;
+use crate::error::LiminalError;
+
+use anyhow::{Result, anyhow};
+use serde_json::{Number, Value};
+use std::collections::HashMap;
+use tracing::error;
+
+#[derive(Debug, Clone)]
+pub struct MapValuesConfig {
+    pub field_in: String,
+    pub field_out: String,
+    /// Values by the text of the values they replace
+    pub mapping: Option<HashMap<String, Value>>,
+    /// `[x, y]` points, in increasing order of x
+    pub breakpoints: Option<Vec<(f64, f64)>>,
+    /// Extend the first and last segments past the ends of the table
+    pub extrapolate: bool,
+    /// Value of unmapped values
+    pub default: Option<Value>,
+    pub timing: Option<crate::config::TimingConfig>,
+}
+
+impl ProcessorConfig for MapValuesConfig {
+    fn from_stage_config(config: &StageConfig) -> Result<Self> {
+        let field_in = require_param::<String>(&config.parameters, "field_in")?;
+        let field_out = extract_param(&config.parameters, "field_out", field_in.clone());
+
+        let config = Self {
+            field_in,
+            field_out,
+            mapping: extract_param(&config.parameters, "mapping", None::<HashMap<String, Value>>),
+            breakpoints: extract_param(&config.parameters, "breakpoints", None::<Vec<(f64, f64)>>),
+            extrapolate: extract_param(&config.parameters, "extrapolate", false),
+            default: extract_param(&config.parameters, "default", None::<Value>),
+            timing: config.timing.clone(),
+        };
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.field_in.is_empty() || self.field_out.is_empty() {
+            return Err(anyhow!("field_in and field_out cannot be empty"));
+        }
+        match (&self.mapping, &self.breakpoints) {
+            (Some(_), Some(_)) | (None, None) => {
+                return Err(anyhow!("map_values processor requires exactly one of 'mapping' or 'breakpoints'"));
+            }
+            (Some(mapping), None) if mapping.is_empty() => {
+                return Err(LiminalError::invalid_parameter("mapping", "cannot be empty").into());
+            }
+            (None, Some(breakpoints)) => {
+                if breakpoints.len() < 2 {
+                    return Err(anyhow!("breakpoints requires at least two points"));
+                }
+                if breakpoints.iter().any(|(x, y)| !x.is_finite() || !y.is_finite()) {
+                    return Err(LiminalError::invalid_parameter("breakpoints", "must be finite numbers").into());
+                }
+                if breakpoints.windows(2).any(|pair| pair[0].0 >= pair[1].0) {
+                    return Err(LiminalError::invalid_parameter("breakpoints", "must be in strictly increasing order of x").into());
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+pub struct MapValuesProcessor {
+    name: String,
+    config: MapValuesConfig,
+    timing: TimingMixin,
+}
+
+impl MapValuesProcessor {
+    pub const METADATA: ProcessorMetadata = ProcessorMetadata {
+        name: "map_values",
+        description: "Maps values through a lookup table or piecewise-linear breakpoints",
+        parameters: &[
+            ParamSpec::new("field_in", ParamType::String, "Field to map"),
+            ParamSpec::new("field_out", ParamType::String, "Field receiving the result (defaults to field_in)"),
+            ParamSpec::new("mapping", ParamType::Object, "Values by the text of the values they replace"),
+            ParamSpec::new("breakpoints", ParamType::Array, "[x, y] points interpolated between, in increasing order of x"),
+            ParamSpec::new("extrapolate", ParamType::Boolean, "Extend the end segments rather than clamping to them"),
+            ParamSpec::new("default", ParamType::Any, "Value of values without a mapping"),
+        ],
+        shared: &[],
+    };
+
+    pub fn new(name: &str, config: StageConfig) -> Result<Box<dyn Processor>> {
+        let processor_config = MapValuesConfig::from_stage_config(&config)?;
+        let timing = TimingMixin::new(processor_config.timing.as_ref());
+
+        Ok(Box::new(Self {
+            name: name.to_string(),
+            config: processor_config,
+            timing,
+        }))
+    }
+
+    fn process_message(&self, mut message: Message) -> Result<Message> {
+        let mapped = FieldUtils::extract_field_value(&message.payload, &self.config.field_in)
+            .and_then(|value| self.map(value))
+            .or_else(|| self.config.default.clone());
+        if let Some(mapped) = mapped {
+            FieldUtils::set_field_value(&mut message.payload, &self.config.field_out, mapped)?;
+        }
+
+        message.source = self.name.clone();
+        Ok(message)
+    }
+
+    fn map(&self, value: &Value) -> Option<Value> {
+        if let Some(mapping) = &self.config.mapping {
+            let key = match value {
+                Value::String(text) => text.clone(),
+                Value::Number(number) => match number.as_f64() {
+                    // 2.0 is looked up as "2"
+                    Some(float) if float.fract() == 0.0 && float.abs() < 1e15 => format!("{}", float as i64),
+                    _ => number.to_string(),
+                },
+                Value::Bool(flag) => flag.to_string(),
+                _ => return None,
+            };
+            return mapping.get(&key).cloned();
+        }
+
+        let breakpoints = self.config.breakpoints.as_deref()?;
+        let x = value.as_f64()?;
+        Number::from_f64(interpolate(breakpoints, x, self.config.extrapolate)).map(Value::Number)
+    }
+}
+
+/// Piecewise-linear interpolation of `x` between `breakpoints`.
+fn interpolate(breakpoints: &[(f64, f64)], x: f64, extrapolate: bool) -> f64 {
+    let (first, last) = (breakpoints[0], breakpoints[breakpoints.len() - 1]);
+    if !extrapolate {
+        if x <= first.0 {
+            return first.1;
+        }
+        if x >= last.0 {
+            return last.1;
+        }
+    }
+
+    // The segment containing x, or the end segment nearest it
+    let upper = breakpoints.partition_point(|(bx, _)| *bx < x).clamp(1, breakpoints.len() - 1);
+    let ((x0, y0), (x1, y1)) = (breakpoints[upper - 1], breakpoints[upper]);
+    y0 + (x - x0) * (y1 - y0) / (x1 - x0)
+}
+
+#[async_trait::async_trait]
+impl Processor for MapValuesProcessor {
+    async fn init(&mut self) -> Result<()> {
+        tracing::info!(
+            "Map values processor '{}' initialised ({} -> {})",
+            self.name,
+            self.config.field_in,
+            self.config.field_out
+        );
+        Ok(())
+    }
+
+    async fn process(&mut self, context: &mut ProcessingContext) -> Result<()> {
+        if let Some((_, message)) = context.recv(tokio::time::Duration::from_millis(10)).await {
+            match self.process_message(message) {
+                Ok(mut output_message) => {
+                    if let Some(output_info) = &context.output {
+                        output_message.topic = output_info.name.clone();
+                        let output_message = self.timing.update_message_watermark(output_message);
+
+                        if let Err(e) = output_info.channel.publish(output_message).await {
+                            tracing::warn!("Failed to publish map_values output: {:?}", e);
+                        }
+                    }
+                }
+                Err(e) => {
+                    error!("{}: Failed to map message: {}", self.name, e);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl WithTimingMixin for MapValuesProcessor {
+    fn timing_mixin(&self) -> &TimingMixin {
+        &self.timing
+    }
+
+    fn timing_mixin_mut(&mut self) -> &mut TimingMixin {
+        &mut self.timing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{MessageBuilder, TestContext};
+    use serde_json::json;
+
+    async fn map_all(parameters: Value, values: &[Value]) -> Vec<Value> {
+        let stage = StageConfig {
+            r#type: "map_values".to_string(),
+            inputs: Some(vec!["in".to_string()]),
+            output: Some("out".to_string()),
+            parameters: serde_json::from_value(parameters).ok(),
+            ..Default::default()
+        };
+        let mut processor = MapValuesProcessor::new("map_values", stage).unwrap();
+        let mut test = TestContext::new("map_values").input("in").output("out");
+
+        for value in values {
+            test.send("in", MessageBuilder::new(json!({ "raw": value })).build()).await.unwrap();
+        }
+        test.process_pending(processor.as_mut()).await.unwrap();
+
+        let mut mapped = Vec::new();
+        while let Some(message) = test.try_output().await {
+            mapped.push(message.payload.get("mapped").cloned().unwrap_or(Value::Null));
+        }
+        mapped
+    }
+
+    #[tokio::test]
+    async fn test_map_values_looks_up_codes() {
+        let parameters = json!({
+            "field_in": "raw",
+            "field_out": "mapped",
+            "mapping": { "0": "ok", "2": "fault", "true": "on" },
+            "default": "unknown",
+        });
+        let mapped = map_all(parameters, &[json!(0), json!(2.0), json!(true), json!(7), json!("x")]).await;
+        assert_eq!(mapped, vec![json!("ok"), json!("fault"), json!("on"), json!("unknown"), json!("unknown")]);
+    }
+
+    #[tokio::test]
+    async fn test_map_values_interpolates_breakpoints() {
+        let breakpoints = json!([[100, 80.0], [400, 50.0], [1600, 0.0]]);
+        let values = [json!(50), json!(250), json!(400), json!(1000), json!(2800)];
+
+        let clamped = json!({ "field_in": "raw", "field_out": "mapped", "breakpoints": breakpoints });
+        let mapped = map_all(clamped, &values).await;
+        assert_eq!(mapped, vec![json!(80.0), json!(65.0), json!(50.0), json!(25.0), json!(0.0)]);
+
+        let extended = json!({ "field_in": "raw", "field_out": "mapped", "breakpoints": breakpoints, "extrapolate": true });
+        let mapped = map_all(extended, &values).await;
+        assert_eq!(mapped[0], json!(85.0));
+        assert_eq!(mapped[4], json!(-50.0));
+    }
+}
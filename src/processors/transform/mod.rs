@@ -1,3 +1,75 @@
+pub mod anomaly;
+#[cfg(feature = "avro")]
+pub mod avro;
+pub mod calculus;
+pub mod cep;
+pub mod clock_skew;
+pub mod correlate;
+pub mod crypto;
+pub mod delta;
+pub mod expr;
+pub mod flatline;
+pub mod geo;
+#[cfg(feature = "http")]
+pub mod http_call;
+pub mod hysteresis;
+pub mod liveness;
+pub mod map_values;
+pub mod merge;
+#[cfg(feature = "onnx")]
+pub mod onnx;
+pub mod outlier;
+#[cfg(feature = "protobuf")]
+pub mod protobuf;
+pub mod quality;
+pub mod rbe;
+pub mod redact;
+pub mod reorder;
+pub mod route;
 pub mod rule;
+pub mod scale;
+pub mod script;
+pub mod split_fields;
+#[cfg(feature = "sparkplug")]
+pub mod sparkplug;
+pub mod time_parse;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
-pub use rule::RuleProcessor;
\ No newline at end of file
+pub use anomaly::AnomalyProcessor;
+#[cfg(feature = "avro")]
+pub use avro::AvroProcessor;
+pub use calculus::CalculusProcessor;
+pub use cep::CepProcessor;
+pub use clock_skew::ClockSkewProcessor;
+pub use correlate::CorrelateProcessor;
+pub use crypto::CryptoProcessor;
+pub use delta::DeltaProcessor;
+pub use expr::ExprProcessor;
+pub use flatline::FlatlineProcessor;
+pub use geo::GeoProcessor;
+#[cfg(feature = "http")]
+pub use http_call::HttpCallProcessor;
+pub use hysteresis::HysteresisProcessor;
+pub use liveness::LivenessProcessor;
+pub use map_values::MapValuesProcessor;
+pub use merge::MergeProcessor;
+#[cfg(feature = "onnx")]
+pub use onnx::OnnxProcessor;
+pub use outlier::OutlierProcessor;
+#[cfg(feature = "protobuf")]
+pub use protobuf::ProtobufProcessor;
+pub use quality::QualityProcessor;
+pub use rbe::RbeProcessor;
+pub use redact::RedactProcessor;
+pub use reorder::ReorderProcessor;
+pub use route::RouteProcessor;
+pub use rule::RuleProcessor;
+pub use scale::ScaleProcessor;
+pub use script::ScriptProcessor;
+pub use split_fields::SplitFieldsProcessor;
+#[cfg(feature = "sparkplug")]
+pub use sparkplug::SparkplugProcessor;
+pub use time_parse::TimeParseProcessor;
+#[cfg(feature = "wasm")]
+pub use wasm::WasmProcessor;
@@ -0,0 +1,857 @@
+//! ONNX Inference Transform
+//!
+//! Runs a model trained offline, such as an anomaly detector, on every message.
+//! The input tensor is built from the numeric `fields` of the message, or of
+//! the last `window` messages of its key (oldest first, one row per message),
+//! and the model's outputs are written back to payload fields. Available with
+//! the `onnx` Cargo feature.
+//!
+//! Models are evaluated by a small built-in interpreter covering the float
+//! operators of multilayer perceptrons and autoencoders exported from PyTorch
+//! or Keras: `Gemm`, `MatMul`, `Add`, `Sub`, `Mul`, `Div`, `Relu`,
+//! `LeakyRelu`, `Sigmoid`, `Tanh`, `Exp`, `Abs`, `Neg`, `Sqrt`, `Softmax`,
+//! `Flatten`, `Reshape`, `Identity` and `Constant`. Models using other
+//! operators, or whose graph reads a value nothing defines, are rejected when
+//! the stage starts.
+//!
+//! Messages that cannot be scored, while their key's window fills or when
+//! inference fails, are counted in `liminal_inference_skipped_total` and
+//! dropped, or forwarded unscored with `pass_through`.
+//!
+//! ```toml
+//! parameters = { model_file = "models/pump_autoencoder.onnx", fields = ["vibration", "current"],
+//!     key_field = "pump_id", window = 10, outputs = { reconstruction_error = "anomaly_score" } }
+//! ```
+//!
+//! Without `outputs`, every model output is written to the field of its name.
+//! Outputs of one element are written as numbers, and larger ones as arrays.
+
+use crate::config::{ParamSpec, ParamType, ProcessorConfig, ProcessorMetadata, StageConfig, extract_param};
+use crate::core::checkpoint::Snapshot;
+use crate::core::metrics::metrics;
+use crate::core::state::StateStore;
+use crate::core::timing_mixin::{TimingMixin, WithTimingMixin};
+use crate::core::{context::ProcessingContext, message::Message};
+use crate::processors::common::field_utils::FieldUtils;
+use crate::processors::processor::Processor;
+use crate::error::LiminalError;
+
+use anyhow::{Result, anyhow};
+use prost::Message as _;
+use serde_json::{Number, Value};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use tracing::{debug, error};
+
+#[derive(Debug, Clone)]
+pub struct OnnxConfig {
+    /// Path to the `.onnx` model
+    pub model_file: String,
+    /// Numeric fields forming each row of the input tensor
+    pub fields: Vec<String>,
+    pub key_field: Option<String>,
+    /// Messages per key forming the input tensor
+    pub window: usize,
+    /// Model input fed the tensor; defaults to the model's first input
+    pub input_name: Option<String>,
+    /// Dimensions of the input tensor; defaults to `[1, window * fields]`
+    pub shape: Option<Vec<usize>>,
+    /// Payload fields receiving model outputs, by output name
+    pub outputs: BTreeMap<String, String>,
+    /// Forward messages that cannot be scored unchanged instead of dropping them
+    pub pass_through: bool,
+    pub timing: Option<crate::config::TimingConfig>,
+}
+
+impl ProcessorConfig for OnnxConfig {
+    fn from_stage_config(config: &StageConfig) -> Result<Self> {
+        let config = Self {
+            model_file: extract_param(&config.parameters, "model_file", String::new()),
+            fields: extract_param(&config.parameters, "fields", Vec::<String>::new()),
+            key_field: extract_param(&config.parameters, "key_field", None::<String>),
+            window: extract_param(&config.parameters, "window", 1_usize),
+            input_name: extract_param(&config.parameters, "input_name", None::<String>),
+            shape: extract_param(&config.parameters, "shape", None::<Vec<usize>>),
+            outputs: extract_param(&config.parameters, "outputs", BTreeMap::new()),
+            pass_through: extract_param(&config.parameters, "pass_through", false),
+            timing: config.timing.clone(),
+        };
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.model_file.is_empty() {
+            return Err(LiminalError::missing_parameter("model_file").into());
+        }
+        if self.fields.is_empty() {
+            return Err(LiminalError::missing_parameter("fields").into());
+        }
+        if self.window == 0 {
+            return Err(LiminalError::invalid_parameter("window", "must be at least 1").into());
+        }
+        if let Some(shape) = &self.shape
+            && shape.iter().product::<usize>() != self.window * self.fields.len()
+        {
+            return Err(anyhow!(
+                "shape {:?} holds {} values, but window and fields give {}",
+                shape,
+                shape.iter().product::<usize>(),
+                self.window * self.fields.len()
+            ));
+        }
+        Ok(())
+    }
+}
+
+pub struct OnnxProcessor {
+    name: String,
+    config: OnnxConfig,
+    model: Model,
+    input_name: String,
+    shape: Vec<usize>,
+    /// Output fields, by model output name
+    outputs: Vec<(String, String)>,
+    timing: TimingMixin,
+    windows: StateStore<String, VecDeque<Vec<f32>>>,
+}
+
+impl OnnxProcessor {
+    pub const METADATA: ProcessorMetadata = ProcessorMetadata {
+        name: "onnx_infer",
+        description: "Runs an ONNX model on payload fields",
+        parameters: &[
+            ParamSpec::new("model_file", ParamType::String, "Path to the .onnx model"),
+            ParamSpec::new("fields", ParamType::Array, "Numeric fields forming each row of the input tensor"),
+            ParamSpec::new("key_field", ParamType::String, "Field whose value keeps separate windows"),
+            ParamSpec::new("window", ParamType::Integer, "Messages per key forming the input tensor"),
+            ParamSpec::new("input_name", ParamType::String, "Model input fed the tensor (defaults to the first)"),
+            ParamSpec::new("shape", ParamType::Array, "Dimensions of the input tensor (defaults to [1, window * fields])"),
+            ParamSpec::new("outputs", ParamType::Object, "Payload fields receiving model outputs, by output name"),
+            ParamSpec::new("pass_through", ParamType::Boolean, "Forward messages that cannot be scored unchanged instead of dropping them"),
+        ],
+        shared: &[],
+    };
+
+    pub fn new(name: &str, config: StageConfig) -> Result<Box<dyn Processor>> {
+        let processor_config = OnnxConfig::from_stage_config(&config)?;
+        let bytes = std::fs::read(&processor_config.model_file)
+            .map_err(|e| anyhow!("Failed to read model '{}': {}", processor_config.model_file, e))?;
+        let model = Model::load(&bytes).map_err(|e| anyhow!("Invalid model '{}': {}", processor_config.model_file, e))?;
+
+        let input_name = match &processor_config.input_name {
+            Some(input) if model.inputs.contains(input) => input.clone(),
+            Some(input) => return Err(anyhow!("model has no input '{}' (inputs: {:?})", input, model.inputs)),
+            None => model
+                .inputs
+                .first()
+                .cloned()
+                .ok_or_else(|| anyhow!("model has no inputs"))?,
+        };
+        let outputs = if processor_config.outputs.is_empty() {
+            model.outputs.iter().map(|output| (output.clone(), output.clone())).collect()
+        } else {
+            for output in processor_config.outputs.keys() {
+                if !model.outputs.contains(output) {
+                    return Err(anyhow!("model has no output '{}' (outputs: {:?})", output, model.outputs));
+                }
+            }
+            processor_config.outputs.clone().into_iter().collect()
+        };
+        let shape = processor_config
+            .shape
+            .clone()
+            .unwrap_or_else(|| vec![1, processor_config.window * processor_config.fields.len()]);
+        let timing = TimingMixin::new(processor_config.timing.as_ref());
+
+        Ok(Box::new(Self {
+            name: name.to_string(),
+            config: processor_config,
+            model,
+            input_name,
+            shape,
+            outputs,
+            timing,
+            windows: StateStore::open(name, config.state.as_ref())?,
+        }))
+    }
+
+    /// The payload with the model's outputs, or `None` while its window fills.
+    fn infer(&mut self, payload: &Value) -> Result<Option<Value>> {
+        let row = self
+            .config
+            .fields
+            .iter()
+            .map(|field| {
+                FieldUtils::extract_f64(payload, field)
+                    .map(|value| value as f32)
+                    .ok_or_else(|| anyhow!("field '{}' is missing or not numeric", field))
+            })
+            .collect::<Result<Vec<f32>>>()?;
+
+        let key = FieldUtils::extract_key(payload, self.config.key_field.as_deref());
+        let window = self.windows.get_or_insert_with(key, VecDeque::new);
+        window.push_back(row);
+        while window.len() > self.config.window {
+            window.pop_front();
+        }
+        if window.len() < self.config.window {
+            return Ok(None);
+        }
+
+        let input = Tensor {
+            dims: self.shape.clone(),
+            data: window.iter().flatten().copied().collect(),
+        };
+        let mut results = self.model.run(&self.input_name, input)?;
+        let mut payload = payload.clone();
+        for (output, field) in &self.outputs {
+            let tensor = results
+                .remove(output)
+                .ok_or_else(|| anyhow!("model did not produce output '{}'", output))?;
+            let values = tensor
+                .data
+                .iter()
+                .map(|value| Number::from_f64(*value as f64).map(Value::Number).unwrap_or(Value::Null));
+            let value = match tensor.data.len() {
+                1 => values.into_iter().next().unwrap_or(Value::Null),
+                _ => Value::Array(values.collect()),
+            };
+            FieldUtils::set_field_value(&mut payload, field, value)?;
+        }
+        Ok(Some(payload))
+    }
+
+    /// The message with the model's outputs, or as received with
+    /// `pass_through` when it cannot be scored.
+    fn process_message(&mut self, mut message: Message) -> Option<Message> {
+        match self.infer(&message.payload) {
+            Ok(Some(payload)) => message.payload = payload,
+            Ok(None) => {
+                debug!("{}: Window not yet full", self.name);
+                metrics().record_inference_skipped(&self.name, "warm_up");
+                if !self.config.pass_through {
+                    return None;
+                }
+            }
+            Err(e) => {
+                error!("{}: Failed to run model: {}", self.name, e);
+                metrics().record_inference_skipped(&self.name, "error");
+                if !self.config.pass_through {
+                    return None;
+                }
+            }
+        }
+
+        message.source = self.name.clone();
+        Some(message)
+    }
+}
+
+#[async_trait::async_trait]
+impl Processor for OnnxProcessor {
+    async fn init(&mut self) -> Result<()> {
+        tracing::info!(
+            "ONNX processor '{}' initialised ({}, {} operators, input '{}' {:?})",
+            self.name,
+            self.config.model_file,
+            self.model.nodes.len(),
+            self.input_name,
+            self.shape
+        );
+        Ok(())
+    }
+
+    async fn process(&mut self, context: &mut ProcessingContext) -> Result<()> {
+        if let Some((_, message)) = context.recv(tokio::time::Duration::from_millis(10)).await
+            && let Some(mut output_message) = self.process_message(message)
+            && let Some(output_info) = &context.output
+        {
+            output_message.topic = output_info.name.clone();
+            let output_message = self.timing.update_message_watermark(output_message);
+
+            if let Err(e) = output_info.channel.publish(output_message).await {
+                tracing::warn!("Failed to publish onnx_infer output: {:?}", e);
+            }
+        }
+        Ok(())
+    }
+
+    fn as_snapshot(&mut self) -> Option<&mut dyn Snapshot> {
+        Some(self)
+    }
+}
+
+impl Snapshot for OnnxProcessor {
+    fn snapshot(&self) -> Result<Value> {
+        self.windows.snapshot()
+    }
+
+    fn restore(&mut self, state: Value) -> Result<()> {
+        self.windows.restore(state)
+    }
+}
+
+impl WithTimingMixin for OnnxProcessor {
+    fn timing_mixin(&self) -> &TimingMixin {
+        &self.timing
+    }
+
+    fn timing_mixin_mut(&mut self) -> &mut TimingMixin {
+        &mut self.timing
+    }
+}
+
+/// The parts of the ONNX protobuf schema (onnx.proto) the interpreter reads.
+mod proto {
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ModelProto {
+        #[prost(int64, tag = "1")]
+        pub ir_version: i64,
+        #[prost(message, optional, tag = "7")]
+        pub graph: Option<GraphProto>,
+        #[prost(message, repeated, tag = "8")]
+        pub opset_import: Vec<OperatorSetIdProto>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct OperatorSetIdProto {
+        #[prost(string, tag = "1")]
+        pub domain: String,
+        #[prost(int64, tag = "2")]
+        pub version: i64,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct GraphProto {
+        #[prost(message, repeated, tag = "1")]
+        pub node: Vec<NodeProto>,
+        #[prost(message, repeated, tag = "5")]
+        pub initializer: Vec<TensorProto>,
+        #[prost(message, repeated, tag = "11")]
+        pub input: Vec<ValueInfoProto>,
+        #[prost(message, repeated, tag = "12")]
+        pub output: Vec<ValueInfoProto>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct NodeProto {
+        #[prost(string, repeated, tag = "1")]
+        pub input: Vec<String>,
+        #[prost(string, repeated, tag = "2")]
+        pub output: Vec<String>,
+        #[prost(string, tag = "4")]
+        pub op_type: String,
+        #[prost(message, repeated, tag = "5")]
+        pub attribute: Vec<AttributeProto>,
+        #[prost(string, tag = "7")]
+        pub domain: String,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct AttributeProto {
+        #[prost(string, tag = "1")]
+        pub name: String,
+        #[prost(float, tag = "2")]
+        pub f: f32,
+        #[prost(int64, tag = "3")]
+        pub i: i64,
+        #[prost(message, optional, tag = "5")]
+        pub t: Option<TensorProto>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct TensorProto {
+        #[prost(int64, repeated, tag = "1")]
+        pub dims: Vec<i64>,
+        #[prost(int32, tag = "2")]
+        pub data_type: i32,
+        #[prost(float, repeated, tag = "4")]
+        pub float_data: Vec<f32>,
+        #[prost(int32, repeated, tag = "5")]
+        pub int32_data: Vec<i32>,
+        #[prost(int64, repeated, tag = "7")]
+        pub int64_data: Vec<i64>,
+        #[prost(string, tag = "8")]
+        pub name: String,
+        #[prost(bytes = "vec", tag = "9")]
+        pub raw_data: Vec<u8>,
+        #[prost(double, repeated, tag = "10")]
+        pub double_data: Vec<f64>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ValueInfoProto {
+        #[prost(string, tag = "1")]
+        pub name: String,
+    }
+}
+
+/// Operators the interpreter evaluates.
+const SUPPORTED_OPERATORS: &[&str] = &[
+    "Gemm", "MatMul", "Add", "Sub", "Mul", "Div", "Relu", "LeakyRelu", "Sigmoid", "Tanh", "Exp", "Abs", "Neg", "Sqrt",
+    "Softmax", "Flatten", "Reshape", "Identity", "Constant",
+];
+
+/// A dense tensor, held as `f32` whatever its ONNX element type.
+#[derive(Debug, Clone, PartialEq)]
+struct Tensor {
+    dims: Vec<usize>,
+    data: Vec<f32>,
+}
+
+impl Tensor {
+    fn from_proto(tensor: &proto::TensorProto) -> Result<Self> {
+        const FLOAT: i32 = 1;
+        const INT32: i32 = 6;
+        const INT64: i32 = 7;
+        const DOUBLE: i32 = 11;
+
+        let raw = &tensor.raw_data;
+        let data: Vec<f32> = match tensor.data_type {
+            FLOAT if !raw.is_empty() => raw.chunks_exact(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect(),
+            FLOAT => tensor.float_data.clone(),
+            INT32 if !raw.is_empty() => {
+                raw.chunks_exact(4).map(|b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32).collect()
+            }
+            INT32 => tensor.int32_data.iter().map(|v| *v as f32).collect(),
+            INT64 if !raw.is_empty() => raw
+                .chunks_exact(8)
+                .map(|b| i64::from_le_bytes(b.try_into().unwrap_or_default()) as f32)
+                .collect(),
+            INT64 => tensor.int64_data.iter().map(|v| *v as f32).collect(),
+            DOUBLE if !raw.is_empty() => raw
+                .chunks_exact(8)
+                .map(|b| f64::from_le_bytes(b.try_into().unwrap_or_default()) as f32)
+                .collect(),
+            DOUBLE => tensor.double_data.iter().map(|v| *v as f32).collect(),
+            other => return Err(anyhow!("tensor '{}' has unsupported element type {}", tensor.name, other)),
+        };
+
+        let dims: Vec<usize> = tensor.dims.iter().map(|dim| *dim as usize).collect();
+        if dims.iter().product::<usize>() != data.len() {
+            return Err(anyhow!("tensor '{}' has {} values for dimensions {:?}", tensor.name, data.len(), dims));
+        }
+        Ok(Self { dims, data })
+    }
+
+    fn map(mut self, f: impl Fn(f32) -> f32) -> Self {
+        self.data.iter_mut().for_each(|value| *value = f(*value));
+        self
+    }
+
+    /// Rows and columns of the tensor as a matrix.
+    fn matrix(&self, op: &str) -> Result<(usize, usize)> {
+        match self.dims[..] {
+            [rows, columns] => Ok((rows, columns)),
+            _ => Err(anyhow!("{} expects matrices, got dimensions {:?}", op, self.dims)),
+        }
+    }
+
+    fn transpose(&self) -> Self {
+        let (rows, columns) = (self.dims[0], self.dims[1]);
+        let mut data = vec![0.0; self.data.len()];
+        for row in 0..rows {
+            for column in 0..columns {
+                data[column * rows + row] = self.data[row * columns + column];
+            }
+        }
+        Self { dims: vec![columns, rows], data }
+    }
+}
+
+/// Elementwise `f(a, b)` with NumPy-style broadcasting.
+fn broadcast(a: &Tensor, b: &Tensor, f: impl Fn(f32, f32) -> f32) -> Result<Tensor> {
+    let rank = a.dims.len().max(b.dims.len());
+    let pad = |dims: &[usize]| -> Vec<usize> { std::iter::repeat_n(1, rank - dims.len()).chain(dims.iter().copied()).collect() };
+    let (a_dims, b_dims) = (pad(&a.dims), pad(&b.dims));
+
+    let mut dims = Vec::with_capacity(rank);
+    for (x, y) in a_dims.iter().zip(&b_dims) {
+        match (x, y) {
+            _ if x == y => dims.push(*x),
+            (1, _) => dims.push(*y),
+            (_, 1) => dims.push(*x),
+            _ => return Err(anyhow!("cannot broadcast dimensions {:?} and {:?}", a.dims, b.dims)),
+        }
+    }
+
+    // Strides of each operand, zero along broadcast dimensions
+    let strides = |dims: &[usize]| -> Vec<usize> {
+        let mut strides = vec![0; rank];
+        let mut stride = 1;
+        for axis in (0..rank).rev() {
+            strides[axis] = if dims[axis] == 1 { 0 } else { stride };
+            stride *= dims[axis];
+        }
+        strides
+    };
+    let (a_strides, b_strides) = (strides(&a_dims), strides(&b_dims));
+
+    let count = dims.iter().product::<usize>();
+    let mut data = Vec::with_capacity(count);
+    let mut index = vec![0; rank];
+    for _ in 0..count {
+        let a_offset: usize = index.iter().zip(&a_strides).map(|(i, s)| i * s).sum();
+        let b_offset: usize = index.iter().zip(&b_strides).map(|(i, s)| i * s).sum();
+        data.push(f(a.data[a_offset], b.data[b_offset]));
+        for axis in (0..rank).rev() {
+            index[axis] += 1;
+            if index[axis] < dims[axis] {
+                break;
+            }
+            index[axis] = 0;
+        }
+    }
+    Ok(Tensor { dims, data })
+}
+
+fn matmul(a: &Tensor, b: &Tensor) -> Result<Tensor> {
+    let (rows, inner) = a.matrix("MatMul")?;
+    let (b_inner, columns) = b.matrix("MatMul")?;
+    if inner != b_inner {
+        return Err(anyhow!("cannot multiply {:?} by {:?}", a.dims, b.dims));
+    }
+    let mut data = vec![0.0; rows * columns];
+    for row in 0..rows {
+        for k in 0..inner {
+            let a_value = a.data[row * inner + k];
+            for column in 0..columns {
+                data[row * columns + column] += a_value * b.data[k * columns + column];
+            }
+        }
+    }
+    Ok(Tensor { dims: vec![rows, columns], data })
+}
+
+/// A loaded model graph.
+struct Model {
+    nodes: Vec<proto::NodeProto>,
+    initializers: HashMap<String, Tensor>,
+    /// Graph inputs that are not initializers
+    inputs: Vec<String>,
+    outputs: Vec<String>,
+}
+
+impl Model {
+    fn load(bytes: &[u8]) -> Result<Self> {
+        let model = proto::ModelProto::decode(bytes)?;
+        let graph = model.graph.ok_or_else(|| anyhow!("model has no graph"))?;
+
+        for node in &graph.node {
+            if !(node.domain.is_empty() || node.domain == "ai.onnx") || !SUPPORTED_OPERATORS.contains(&node.op_type.as_str()) {
+                let domain = if node.domain.is_empty() { "ai.onnx" } else { &node.domain };
+                return Err(anyhow!("unsupported operator {}::{}", domain, node.op_type));
+            }
+        }
+
+        let initializers = graph
+            .initializer
+            .iter()
+            .map(|tensor| Ok((tensor.name.clone(), Tensor::from_proto(tensor)?)))
+            .collect::<Result<HashMap<_, _>>>()?;
+        let inputs: Vec<String> = graph
+            .input
+            .iter()
+            .map(|input| input.name.clone())
+            .filter(|name| !initializers.contains_key(name))
+            .collect();
+        let outputs: Vec<String> = graph.output.iter().map(|output| output.name.clone()).collect();
+
+        Self::check_graph(&graph.node, &initializers, &inputs, &outputs)?;
+        Ok(Self { nodes: graph.node, initializers, inputs, outputs })
+    }
+
+    /// Reject graphs that would fail on every inference: constants the
+    /// interpreter cannot read, and values read before anything defines them.
+    fn check_graph(nodes: &[proto::NodeProto], initializers: &HashMap<String, Tensor>, inputs: &[String], outputs: &[String]) -> Result<()> {
+        let mut defined: HashSet<&str> = initializers.keys().chain(inputs).map(String::as_str).collect();
+        for node in nodes {
+            if node.op_type == "Constant" {
+                let value = node
+                    .attribute
+                    .iter()
+                    .find(|attribute| attribute.name == "value")
+                    .and_then(|attribute| attribute.t.as_ref())
+                    .ok_or_else(|| anyhow!("operator Constant without a tensor value is not supported"))?;
+                Tensor::from_proto(value).map_err(|e| anyhow!("operator Constant: {}", e))?;
+            }
+            if let Some(input) = node.input.iter().find(|input| !input.is_empty() && !defined.contains(input.as_str())) {
+                return Err(anyhow!("operator {} reads '{}', which nothing defines before it", node.op_type, input));
+            }
+            let output = node.output.first().ok_or_else(|| anyhow!("operator {} has no output", node.op_type))?;
+            defined.insert(output);
+        }
+        if let Some(output) = outputs.iter().find(|output| !defined.contains(output.as_str())) {
+            return Err(anyhow!("model output '{}' is never computed", output));
+        }
+        Ok(())
+    }
+
+    /// Evaluate the graph, returning every tensor it computed by name.
+    fn run(&self, input_name: &str, input: Tensor) -> Result<HashMap<String, Tensor>> {
+        let mut values: HashMap<String, Tensor> = HashMap::new();
+        values.insert(input_name.to_string(), input);
+
+        for node in &self.nodes {
+            let operand = |index: usize| -> Result<&Tensor> {
+                let name = node
+                    .input
+                    .get(index)
+                    .filter(|name| !name.is_empty())
+                    .ok_or_else(|| anyhow!("{} is missing input {}", node.op_type, index))?;
+                values
+                    .get(name)
+                    .or_else(|| self.initializers.get(name))
+                    .ok_or_else(|| anyhow!("{} input '{}' is not defined", node.op_type, name))
+            };
+            let attribute = |name: &str| node.attribute.iter().find(|attribute| attribute.name == name);
+            let float = |name: &str, default: f32| attribute(name).map_or(default, |attribute| attribute.f);
+            let int = |name: &str, default: i64| attribute(name).map_or(default, |attribute| attribute.i);
+
+            let result = match node.op_type.as_str() {
+                "Gemm" => {
+                    let mut a = operand(0)?.clone();
+                    let mut b = operand(1)?.clone();
+                    a.matrix("Gemm")?;
+                    b.matrix("Gemm")?;
+                    if int("transA", 0) != 0 {
+                        a = a.transpose();
+                    }
+                    if int("transB", 0) != 0 {
+                        b = b.transpose();
+                    }
+                    let (alpha, beta) = (float("alpha", 1.0), float("beta", 1.0));
+                    let product = matmul(&a, &b)?.map(|value| value * alpha);
+                    match node.input.get(2).filter(|name| !name.is_empty()) {
+                        Some(_) => broadcast(&product, operand(2)?, |p, c| p + beta * c)?,
+                        None => product,
+                    }
+                }
+                "MatMul" => matmul(operand(0)?, operand(1)?)?,
+                "Add" => broadcast(operand(0)?, operand(1)?, |a, b| a + b)?,
+                "Sub" => broadcast(operand(0)?, operand(1)?, |a, b| a - b)?,
+                "Mul" => broadcast(operand(0)?, operand(1)?, |a, b| a * b)?,
+                "Div" => broadcast(operand(0)?, operand(1)?, |a, b| a / b)?,
+                "Relu" => operand(0)?.clone().map(|x| x.max(0.0)),
+                "LeakyRelu" => {
+                    let alpha = float("alpha", 0.01);
+                    operand(0)?.clone().map(|x| if x < 0.0 { alpha * x } else { x })
+                }
+                "Sigmoid" => operand(0)?.clone().map(|x| 1.0 / (1.0 + (-x).exp())),
+                "Tanh" => operand(0)?.clone().map(f32::tanh),
+                "Exp" => operand(0)?.clone().map(f32::exp),
+                "Abs" => operand(0)?.clone().map(f32::abs),
+                "Neg" => operand(0)?.clone().map(|x| -x),
+                "Sqrt" => operand(0)?.clone().map(f32::sqrt),
+                "Identity" => operand(0)?.clone(),
+                "Softmax" => {
+                    // Over the trailing dimensions from `axis`, one row per leading index
+                    let mut tensor = operand(0)?.clone();
+                    let rank = tensor.dims.len() as i64;
+                    let axis = int("axis", -1);
+                    let axis = if axis < 0 { axis + rank } else { axis }.clamp(0, rank.max(1) - 1) as usize;
+                    let row = tensor.dims[axis..].iter().product::<usize>().max(1);
+                    for values in tensor.data.chunks_mut(row) {
+                        let max = values.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+                        values.iter_mut().for_each(|value| *value = (*value - max).exp());
+                        let sum: f32 = values.iter().sum();
+                        values.iter_mut().for_each(|value| *value /= sum);
+                    }
+                    tensor
+                }
+                "Flatten" => {
+                    let mut tensor = operand(0)?.clone();
+                    let rank = tensor.dims.len() as i64;
+                    let axis = int("axis", 1);
+                    let axis = if axis < 0 { axis + rank } else { axis }.clamp(0, rank) as usize;
+                    tensor.dims = vec![tensor.dims[..axis].iter().product(), tensor.dims[axis..].iter().product()];
+                    tensor
+                }
+                "Reshape" => {
+                    let mut tensor = operand(0)?.clone();
+                    let target = operand(1)?;
+                    let mut dims: Vec<i64> = target.data.iter().map(|dim| *dim as i64).collect();
+                    for (axis, dim) in dims.iter_mut().enumerate() {
+                        if *dim == 0 {
+                            *dim = *tensor.dims.get(axis).ok_or_else(|| anyhow!("Reshape copies a missing dimension"))? as i64;
+                        }
+                    }
+                    let known: i64 = dims.iter().filter(|dim| **dim != -1).product();
+                    if let Some(inferred) = dims.iter_mut().find(|dim| **dim == -1) {
+                        *inferred = tensor.data.len() as i64 / known.max(1);
+                    }
+                    let dims: Vec<usize> = dims.into_iter().map(|dim| dim as usize).collect();
+                    if dims.iter().product::<usize>() != tensor.data.len() {
+                        return Err(anyhow!("cannot reshape {:?} to {:?}", tensor.dims, dims));
+                    }
+                    tensor.dims = dims;
+                    tensor
+                }
+                "Constant" => {
+                    let value = attribute("value")
+                        .and_then(|attribute| attribute.t.as_ref())
+                        .ok_or_else(|| anyhow!("Constant without a tensor value is not supported"))?;
+                    Tensor::from_proto(value)?
+                }
+                other => return Err(anyhow!("unsupported operator {}", other)),
+            };
+
+            let output = node.output.first().ok_or_else(|| anyhow!("{} has no output", node.op_type))?;
+            values.insert(output.clone(), result);
+        }
+        Ok(values)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::proto::*;
+    use super::*;
+    use crate::testing::{MessageBuilder, TestContext};
+    use serde_json::json;
+
+    fn tensor(name: &str, dims: &[i64], values: &[f32]) -> TensorProto {
+        TensorProto {
+            dims: dims.to_vec(),
+            data_type: 1,
+            float_data: values.to_vec(),
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn node(op_type: &str, inputs: &[&str], output: &str) -> NodeProto {
+        NodeProto {
+            input: inputs.iter().map(|input| input.to_string()).collect(),
+            output: vec![output.to_string()],
+            op_type: op_type.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn write_model(file: &str, nodes: Vec<NodeProto>, initializers: Vec<TensorProto>, output: &str) -> String {
+        let model = ModelProto {
+            ir_version: 8,
+            opset_import: vec![OperatorSetIdProto { domain: String::new(), version: 17 }],
+            graph: Some(GraphProto {
+                node: nodes,
+                initializer: initializers,
+                input: vec![ValueInfoProto { name: "x".to_string() }],
+                output: vec![ValueInfoProto { name: output.to_string() }],
+            }),
+        };
+        let path = std::env::temp_dir().join(format!("liminal-{}-{}.onnx", file, std::process::id()));
+        std::fs::write(&path, model.encode_to_vec()).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[tokio::test]
+    async fn test_onnx_runs_model_over_window() {
+        // score = relu(x · [1, -1, 2, -2]ᵀ - 1) over windows of two (a, b) rows
+        let model_file = write_model(
+            "window",
+            vec![node("MatMul", &["x", "w"], "y"), node("Sub", &["y", "one"], "z"), node("Relu", &["z"], "score")],
+            vec![tensor("w", &[4, 1], &[1.0, -1.0, 2.0, -2.0]), tensor("one", &[], &[1.0])],
+            "score",
+        );
+        let stage = StageConfig {
+            r#type: "onnx_infer".to_string(),
+            inputs: Some(vec!["in".to_string()]),
+            output: Some("out".to_string()),
+            parameters: serde_json::from_value(json!({
+                "model_file": model_file,
+                "fields": ["a", "b"],
+                "key_field": "pump",
+                "window": 2,
+                "outputs": { "score": "anomaly.score" },
+            }))
+            .ok(),
+            ..Default::default()
+        };
+        let mut processor = OnnxProcessor::new("onnx", stage).unwrap();
+        let mut test = TestContext::new("onnx").input("in").output("out");
+
+        for (pump, a, b) in [("p1", 1.0, 0.0), ("p2", 5.0, 5.0), ("p1", 3.0, 1.0), ("p1", 0.0, 2.0)] {
+            test.send("in", MessageBuilder::new(json!({ "pump": pump, "a": a, "b": b })).build()).await.unwrap();
+        }
+        test.process_pending(processor.as_mut()).await.unwrap();
+
+        // [1, 0, 3, 1]: 1 + 6 - 2 - 1 = 4; [3, 1, 0, 2]: 3 - 1 - 4 - 1 < 0
+        let first = test.try_output().await.unwrap().payload;
+        assert_eq!(first["anomaly"]["score"], json!(4.0));
+        let second = test.try_output().await.unwrap().payload;
+        assert_eq!(second["anomaly"]["score"], json!(0.0));
+        assert!(test.try_output().await.is_none());
+        let _ = std::fs::remove_file(&model_file);
+    }
+
+    #[test]
+    fn test_onnx_evaluates_gemm_and_softmax() {
+        let mut gemm = node("Gemm", &["x", "w", "b"], "logits");
+        gemm.attribute = vec![AttributeProto { name: "transB".to_string(), i: 1, ..Default::default() }];
+        let model_file = write_model(
+            "gemm",
+            vec![gemm, node("Softmax", &["logits"], "p")],
+            vec![tensor("w", &[2, 2], &[1.0, 0.0, 0.0, 1.0]), tensor("b", &[2], &[0.0, 1.0])],
+            "p",
+        );
+        let model = Model::load(&std::fs::read(&model_file).unwrap()).unwrap();
+        let _ = std::fs::remove_file(&model_file);
+
+        let outputs = model.run("x", Tensor { dims: vec![1, 2], data: vec![1.0, 0.0] }).unwrap();
+        let p = &outputs["p"];
+        assert_eq!(p.dims, vec![1, 2]);
+        assert!((p.data[0] - 0.5).abs() < 1e-6 && (p.data[1] - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_onnx_rejects_unsupported_operators() {
+        let model_file = write_model("unsupported", vec![node("Conv", &["x", "w"], "y")], Vec::new(), "y");
+        let error = Model::load(&std::fs::read(&model_file).unwrap()).err().unwrap();
+        let _ = std::fs::remove_file(&model_file);
+        assert!(error.to_string().contains("Conv"));
+
+        // Graphs that could only fail at inference are rejected when loaded
+        let model_file = write_model("constant", vec![node("Constant", &[], "c"), node("Add", &["x", "c"], "y")], Vec::new(), "y");
+        let error = Model::load(&std::fs::read(&model_file).unwrap()).err().unwrap();
+        let _ = std::fs::remove_file(&model_file);
+        assert!(error.to_string().contains("Constant"));
+
+        let model_file = write_model("undefined", vec![node("MatMul", &["x", "w"], "y")], Vec::new(), "y");
+        let error = Model::load(&std::fs::read(&model_file).unwrap()).err().unwrap();
+        let _ = std::fs::remove_file(&model_file);
+        assert!(error.to_string().contains("MatMul reads 'w'"));
+    }
+
+    #[tokio::test]
+    async fn test_onnx_passes_through_unscored_messages() {
+        let model_file = write_model("pass", vec![node("Neg", &["x"], "y")], Vec::new(), "y");
+        let stage = StageConfig {
+            r#type: "onnx_infer".to_string(),
+            inputs: Some(vec!["in".to_string()]),
+            output: Some("out".to_string()),
+            parameters: serde_json::from_value(json!({
+                "model_file": model_file,
+                "fields": ["a"],
+                "window": 2,
+                "pass_through": true,
+            }))
+            .ok(),
+            ..Default::default()
+        };
+        let mut processor = OnnxProcessor::new("onnx-pass", stage).unwrap();
+        let mut test = TestContext::new("onnx-pass").input("in").output("out");
+
+        for payload in [json!({ "a": 1.0 }), json!({ "a": 2.0 }), json!({ "b": 3.0 })] {
+            test.send("in", MessageBuilder::new(payload).build()).await.unwrap();
+        }
+        test.process_pending(processor.as_mut()).await.unwrap();
+
+        // Warming up and failing messages are forwarded as received
+        assert_eq!(test.try_output().await.unwrap().payload, json!({ "a": 1.0 }));
+        assert_eq!(test.try_output().await.unwrap().payload, json!({ "a": 2.0, "y": [-1.0, -2.0] }));
+        assert_eq!(test.try_output().await.unwrap().payload, json!({ "b": 3.0 }));
+        let _ = std::fs::remove_file(&model_file);
+    }
+}
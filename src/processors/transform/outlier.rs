@@ -0,0 +1,508 @@
+//! Outlier Transform
+//!
+//! Detects out-of-range readings and drops, clamps, or tags them before they
+//! reach downstream aggregations. Bounds come from one of three strategies:
+//!
+//! - **range**: fixed `min`/`max` limits
+//! - **iqr**: rolling interquartile range, `[Q1 - k·IQR, Q3 + k·IQR]`
+//! - **mad**: rolling median ± k·MAD (scaled to be comparable to a standard deviation)
+//!
+//! Rolling strategies keep a window per field and key, and outliers are not
+//! added to the window so a burst of glitches cannot widen the bounds. The
+//! bounds are never narrower than `min_tolerance` (absolute) or
+//! `relative_tolerance` (a fraction of the median) either side of the median,
+//! so a flat stretch cannot collapse them to a single value. After `shift_after`
+//! consecutive outliers the series is taken to have moved to a new level: the
+//! window restarts from those readings and the baseline follows the shift.
+//!
+//! The range strategy drops or clamps batches a column at a time, with
+//! vectorised comparisons; rows with an outlier in any field are dropped.
+
+use crate::config::{ParamSpec, ParamType, ProcessorConfig, ProcessorMetadata, StageConfig, extract_param, require_param};
+use crate::core::checkpoint::Snapshot;
+use crate::core::state::StateStore;
+use crate::core::timing_mixin::{TimingMixin, WithTimingMixin};
+use crate::core::{context::ProcessingContext, message::Message};
+use crate::processors::common::column;
+use crate::processors::common::field_utils::FieldUtils;
+use crate::processors::common::stats::{self, MAD_CONSISTENCY};
+use crate::processors::processor::Processor;
+use crate::error::LiminalError;
+
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use serde_json::{Number, Value};
+use std::collections::VecDeque;
+use tracing::{debug, error};
+
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum OutlierStrategy {
+    /// Fixed limits; either bound may be omitted
+    Range { min: Option<f64>, max: Option<f64> },
+    /// Rolling interquartile range fences
+    Iqr {
+        #[serde(default = "default_iqr_k")]
+        k: f64,
+    },
+    /// Rolling median ± k scaled MADs
+    Mad {
+        #[serde(default = "default_mad_k")]
+        k: f64,
+    },
+}
+
+fn default_iqr_k() -> f64 {
+    1.5
+}
+
+fn default_mad_k() -> f64 {
+    3.0
+}
+
+/// What to do with a reading that falls outside the bounds.
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OutlierAction {
+    /// Drop the whole message
+    Drop,
+    /// Replace the value with the nearest bound
+    Clamp,
+    /// Keep the value and flag the message
+    Tag,
+}
+
+#[derive(Debug, Clone)]
+pub struct OutlierConfig {
+    pub fields: Vec<String>,
+    pub key_field: Option<String>,
+    pub strategy: OutlierStrategy,
+    pub action: OutlierAction,
+    pub window_size: usize,
+    pub min_samples: usize,
+    pub outlier_field: String,
+    pub min_tolerance: f64,
+    pub relative_tolerance: f64,
+    pub shift_after: usize,
+    pub timing: Option<crate::config::TimingConfig>,
+}
+
+impl ProcessorConfig for OutlierConfig {
+    fn from_stage_config(config: &StageConfig) -> Result<Self> {
+        let mut fields = extract_param(&config.parameters, "fields", Vec::<String>::new());
+        if let Some(field_in) = extract_param(&config.parameters, "field_in", None::<String>) {
+            fields.push(field_in);
+        }
+
+        let strategy = require_param::<OutlierStrategy>(&config.parameters, "strategy")?;
+
+        let config = Self {
+            fields,
+            key_field: extract_param(&config.parameters, "key_field", None::<String>),
+            strategy,
+            action: extract_param(&config.parameters, "action", OutlierAction::Drop),
+            window_size: extract_param(&config.parameters, "window_size", 50_usize),
+            min_samples: extract_param(&config.parameters, "min_samples", 10_usize),
+            outlier_field: extract_param(&config.parameters, "outlier_field", "outlier".to_string()),
+            min_tolerance: extract_param(&config.parameters, "min_tolerance", 0.0),
+            relative_tolerance: extract_param(&config.parameters, "relative_tolerance", 0.001),
+            shift_after: extract_param(&config.parameters, "shift_after", 10_usize),
+            timing: config.timing.clone(),
+        };
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.fields.is_empty() {
+            return Err(anyhow!(
+                "outlier processor requires 'fields' (or 'field_in') to be specified"
+            ));
+        }
+        match &self.strategy {
+            OutlierStrategy::Range { min: None, max: None } => {
+                return Err(anyhow!("range strategy requires at least one of min or max"));
+            }
+            OutlierStrategy::Range {
+                min: Some(min),
+                max: Some(max),
+            } if min > max => {
+                return Err(anyhow!("range min ({}) must not exceed max ({})", min, max));
+            }
+            OutlierStrategy::Iqr { k } | OutlierStrategy::Mad { k } if *k <= 0.0 => {
+                return Err(anyhow!("strategy k must be positive (got {})", k));
+            }
+            _ => {}
+        }
+        if self.window_size < 4 {
+            return Err(LiminalError::invalid_parameter("window_size", format!("must be at least 4 (got {})", self.window_size)).into());
+        }
+        if self.min_tolerance < 0.0 {
+            return Err(LiminalError::invalid_parameter("min_tolerance", "must not be negative").into());
+        }
+        if self.relative_tolerance < 0.0 {
+            return Err(LiminalError::invalid_parameter("relative_tolerance", "must not be negative").into());
+        }
+        if self.min_samples > self.window_size {
+            return Err(LiminalError::invalid_parameter("min_samples", "cannot exceed window_size").into());
+        }
+        Ok(())
+    }
+}
+
+/// Rolling statistics of one field of one key.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Series {
+    /// Recent accepted readings
+    window: VecDeque<f64>,
+    /// Consecutive outliers since the last accepted reading
+    #[serde(default)]
+    streak: Vec<f64>,
+}
+
+pub struct OutlierProcessor {
+    name: String,
+    config: OutlierConfig,
+    timing: TimingMixin,
+    windows: StateStore<(String, String), Series>,
+}
+
+impl OutlierProcessor {
+    pub const METADATA: ProcessorMetadata = ProcessorMetadata {
+        name: "outlier",
+        description: "Drops, clamps, or tags out-of-range readings",
+        parameters: &[
+            ParamSpec::new("fields", ParamType::Array, "Numeric fields to check"),
+            ParamSpec::new("field_in", ParamType::String, "A single field to check, in addition to fields"),
+            ParamSpec::new("strategy", ParamType::Object, "Outlier test: range (min, max), iqr (k) or mad (k)"),
+            ParamSpec::new("key_field", ParamType::String, "Field whose value keeps separate statistics"),
+            ParamSpec::new("action", ParamType::Choice(&["drop", "clamp", "tag"]), "What to do with outliers"),
+            ParamSpec::new("window_size", ParamType::Integer, "Samples kept for the iqr and mad strategies"),
+            ParamSpec::new("min_samples", ParamType::Integer, "Samples needed before testing starts"),
+            ParamSpec::new("outlier_field", ParamType::String, "Field receiving the outlier flag"),
+            ParamSpec::new("min_tolerance", ParamType::Number, "Smallest distance from the median treated as an outlier (iqr and mad)"),
+            ParamSpec::new("relative_tolerance", ParamType::Number, "Smallest distance from the median as a fraction of the median (iqr and mad)"),
+            ParamSpec::new("shift_after", ParamType::Integer, "Consecutive outliers after which the window restarts at the new level (0 disables)"),
+        ],
+        shared: &[],
+    };
+
+    pub fn new(name: &str, config: StageConfig) -> Result<Box<dyn Processor>> {
+        let processor_config = OutlierConfig::from_stage_config(&config)?;
+        processor_config.validate()?;
+
+        let timing = TimingMixin::new(processor_config.timing.as_ref());
+
+        Ok(Box::new(Self {
+            name: name.to_string(),
+            config: processor_config,
+            timing,
+            windows: StateStore::open(name, config.state.as_ref())?,
+        }))
+    }
+
+    /// Compute the current acceptance bounds for a series, or `None` while a
+    /// rolling strategy is still warming up.
+    fn bounds(&self, window: Option<&VecDeque<f64>>) -> Option<(f64, f64)> {
+        match &self.config.strategy {
+            OutlierStrategy::Range { min, max } => Some((
+                min.unwrap_or(f64::NEG_INFINITY),
+                max.unwrap_or(f64::INFINITY),
+            )),
+            OutlierStrategy::Iqr { k } => {
+                let samples = Self::warm_samples(window, self.config.min_samples)?;
+                let q1 = stats::quantile(&samples, 0.25)?;
+                let q3 = stats::quantile(&samples, 0.75)?;
+                let iqr = q3 - q1;
+                let median = stats::median(&samples)?;
+                let floor = self.tolerance(median);
+                Some(((q1 - k * iqr).min(median - floor), (q3 + k * iqr).max(median + floor)))
+            }
+            OutlierStrategy::Mad { k } => {
+                let samples = Self::warm_samples(window, self.config.min_samples)?;
+                let median = stats::median(&samples)?;
+                let mad = stats::median_abs_deviation(&samples, median)? / MAD_CONSISTENCY;
+                let spread = (k * mad).max(self.tolerance(median));
+                Some((median - spread, median + spread))
+            }
+        }
+    }
+
+    /// Smallest margin allowed around the median of a rolling window.
+    fn tolerance(&self, median: f64) -> f64 {
+        self.config.min_tolerance.max(self.config.relative_tolerance * median.abs())
+    }
+
+    fn warm_samples(window: Option<&VecDeque<f64>>, min_samples: usize) -> Option<Vec<f64>> {
+        window
+            .filter(|w| w.len() >= min_samples)
+            .map(|w| w.iter().copied().collect())
+    }
+
+    /// Record a reading of a rolling series: accepted readings join the window,
+    /// and a long enough run of outliers replaces it.
+    fn observe(&mut self, series: (String, String), value: f64, outlier: bool) {
+        let shift_after = self.config.shift_after;
+        let window_size = self.config.window_size;
+        let state = self.windows.get_or_insert_with(series, Series::default);
+
+        if !outlier {
+            state.streak.clear();
+            state.window.push_back(value);
+        } else {
+            state.streak.push(value);
+            if shift_after == 0 || state.streak.len() < shift_after {
+                return;
+            }
+            debug!("{} consecutive outliers, restarting the window at the new level", state.streak.len());
+            state.window = state.streak.drain(..).collect();
+        }
+        while state.window.len() > window_size {
+            state.window.pop_front();
+        }
+    }
+
+    /// Drop or clamp the rows of a batch against the range bounds. Only
+    /// called for the range strategy, see `accepts_batches`.
+    fn process_batch(&self, mut message: Message) -> Option<Message> {
+        let (lo, hi) = self.bounds(None)?;
+        let batch = message.batch_mut()?;
+        let mut keep = vec![true; batch.len()];
+
+        for field in &self.config.fields {
+            let Some(values) = batch.column_mut(field) else {
+                continue;
+            };
+            match self.config.action {
+                OutlierAction::Clamp => column::clamp(values, lo, hi),
+                _ => column::keep_within(values, lo, hi, &mut keep),
+            }
+        }
+
+        if keep.contains(&false) {
+            batch.retain(&keep);
+            debug!("Dropped {} outlier rows from a batch", keep.iter().filter(|kept| !**kept).count());
+        }
+        if batch.is_empty() {
+            return None;
+        }
+        message.source = self.name.clone();
+        Some(message)
+    }
+
+    fn process_message(&mut self, mut message: Message) -> Result<Option<Message>> {
+        if message.batch.is_some() {
+            return Ok(self.process_batch(message));
+        }
+
+        let key = FieldUtils::extract_key(&message.payload, self.config.key_field.as_deref());
+        let rolling = !matches!(self.config.strategy, OutlierStrategy::Range { .. });
+        let mut any_outlier = false;
+
+        for field in self.config.fields.clone() {
+            let Some(value) = FieldUtils::extract_f64(&message.payload, &field) else {
+                continue;
+            };
+
+            let series = (key.clone(), field.clone());
+            let bounds = self.bounds(self.windows.get(&series).map(|state| &state.window));
+
+            let outlier = bounds.is_some_and(|(lo, hi)| value < lo || value > hi);
+            if rolling {
+                self.observe(series, value, outlier);
+            }
+
+            if outlier {
+                let (lo, hi) = bounds.unwrap_or_default();
+                debug!("Outlier on '{}' for key '{}': {} outside [{}, {}]", field, key, value, lo, hi);
+                any_outlier = true;
+
+                if self.config.action == OutlierAction::Clamp {
+                    let clamped = Number::from_f64(value.clamp(lo, hi))
+                        .ok_or_else(|| anyhow!("Clamped value is not finite"))?;
+                    FieldUtils::set_field_value(&mut message.payload, &field, Value::Number(clamped))?;
+                }
+            }
+        }
+
+        // Every field is observed before dropping, so each series still
+        // counts the outlier towards a level shift
+        if any_outlier && self.config.action == OutlierAction::Drop {
+            return Ok(None);
+        }
+
+        if self.config.action == OutlierAction::Tag {
+            FieldUtils::set_field_value(
+                &mut message.payload,
+                &self.config.outlier_field,
+                Value::Bool(any_outlier),
+            )?;
+        }
+
+        message.source = self.name.clone();
+        Ok(Some(message))
+    }
+}
+
+#[async_trait::async_trait]
+impl Processor for OutlierProcessor {
+    async fn init(&mut self) -> Result<()> {
+        tracing::info!(
+            "Outlier processor '{}' initialised ({:?}, action: {:?})",
+            self.name,
+            self.config.strategy,
+            self.config.action
+        );
+        Ok(())
+    }
+
+    fn accepts_batches(&self) -> bool {
+        matches!(self.config.strategy, OutlierStrategy::Range { .. }) && self.config.action != OutlierAction::Tag
+    }
+
+    async fn process(&mut self, context: &mut ProcessingContext) -> Result<()> {
+        if let Some((channel_name, message)) = context.recv(tokio::time::Duration::from_millis(10)).await {
+            match self.process_message(message) {
+                Ok(Some(mut output_message)) => {
+                    if let Some(output_info) = &context.output {
+                        output_message.topic = output_info.name.clone();
+                        let output_message = self.timing.update_message_watermark(output_message);
+
+                        if let Err(e) = output_info.channel.publish(output_message).await {
+                            tracing::warn!("Failed to publish outlier output: {:?}", e);
+                        }
+                    }
+                }
+                Ok(None) => {
+                    debug!("Outlier message from '{}' dropped", channel_name);
+                }
+                Err(e) => {
+                    error!("Failed to apply outlier filter: {}", e);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn as_snapshot(&mut self) -> Option<&mut dyn Snapshot> {
+        Some(self)
+    }
+}
+
+impl Snapshot for OutlierProcessor {
+    fn snapshot(&self) -> Result<Value> {
+        self.windows.snapshot()
+    }
+
+    fn restore(&mut self, state: Value) -> Result<()> {
+        self.windows.restore(state)
+    }
+}
+
+impl WithTimingMixin for OutlierProcessor {
+    fn timing_mixin(&self) -> &TimingMixin {
+        &self.timing
+    }
+
+    fn timing_mixin_mut(&mut self) -> &mut TimingMixin {
+        &mut self.timing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn processor(parameters: Value) -> OutlierProcessor {
+        let stage = StageConfig {
+            r#type: "outlier".to_string(),
+            parameters: serde_json::from_value(parameters).ok(),
+            ..Default::default()
+        };
+        OutlierProcessor {
+            name: "outlier".to_string(),
+            config: OutlierConfig::from_stage_config(&stage).unwrap(),
+            timing: TimingMixin::new(None),
+            windows: StateStore::new(),
+        }
+    }
+
+    fn reading(value: f64) -> Message {
+        Message::new("src", "topic", json!({ "value": value }))
+    }
+
+    fn value(message: &Message) -> f64 {
+        message.payload["value"].as_f64().unwrap()
+    }
+
+    #[test]
+    fn test_outlier_flat_window_keeps_a_tolerance() {
+        let mut outlier = processor(json!({
+            "field_in": "value",
+            "strategy": { "type": "mad" },
+            "min_samples": 4,
+            "relative_tolerance": 0.01,
+        }));
+        for _ in 0..10 {
+            assert!(outlier.process_message(reading(100.0)).unwrap().is_some());
+        }
+
+        // The MAD of a flat window is zero, but small noise is still accepted
+        // and a step is dropped rather than everything but the median
+        assert!(outlier.process_message(reading(100.5)).unwrap().is_some());
+        assert!(outlier.process_message(reading(99.5)).unwrap().is_some());
+        assert!(outlier.process_message(reading(120.0)).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_outlier_window_follows_a_level_shift() {
+        let mut outlier = processor(json!({
+            "field_in": "value",
+            "strategy": { "type": "iqr" },
+            "window_size": 10,
+            "min_samples": 4,
+            "shift_after": 3,
+        }));
+        for _ in 0..10 {
+            outlier.process_message(reading(10.0)).unwrap();
+        }
+
+        // The first readings at the new level are dropped, then the window
+        // restarts from them and later readings are accepted
+        for _ in 0..3 {
+            assert!(outlier.process_message(reading(50.0)).unwrap().is_none());
+        }
+        for _ in 0..5 {
+            assert!(outlier.process_message(reading(50.0)).unwrap().is_some());
+        }
+        assert!(outlier.process_message(reading(10.0)).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_outlier_clamp_and_tag() {
+        let mut clamp = processor(json!({
+            "field_in": "value",
+            "strategy": { "type": "range", "min": 0.0, "max": 100.0 },
+            "action": "clamp",
+        }));
+        assert_eq!(value(&clamp.process_message(reading(150.0)).unwrap().unwrap()), 100.0);
+        assert_eq!(value(&clamp.process_message(reading(-5.0)).unwrap().unwrap()), 0.0);
+        assert_eq!(value(&clamp.process_message(reading(42.0)).unwrap().unwrap()), 42.0);
+
+        let mut tag = processor(json!({
+            "field_in": "value",
+            "strategy": { "type": "mad" },
+            "min_samples": 4,
+            "action": "tag",
+            "min_tolerance": 1.0,
+        }));
+        for reading_value in [10.0, 10.2, 9.8, 10.1, 9.9] {
+            let tagged = tag.process_message(reading(reading_value)).unwrap().unwrap();
+            assert_eq!(tagged.payload["outlier"], json!(false));
+        }
+        let tagged = tag.process_message(reading(30.0)).unwrap().unwrap();
+        assert_eq!(tagged.payload, json!({ "value": 30.0, "outlier": true }));
+    }
+}
@@ -0,0 +1,314 @@
+//! Quality Transform
+//!
+//! Scores every message by the data quality checks it passes, attaching the
+//! score (the fraction of checks passed, from 0.0 to 1.0) and the names of the
+//! failed checks, so downstream stages can route or filter by quality:
+//!
+//! - **required**: each listed field is present
+//! - **max_age_ms**: the message's event time is no older than this
+//! - **ranges**: each field with a value lies within its `min`/`max`
+//! - **flatline**: each field has changed (by more than `epsilon`) within the
+//!   last `samples` readings of its key
+//!
+//! ```toml
+//! parameters = { required = ["temperature", "humidity"], max_age_ms = 60000, key_field = "sensor_id",
+//!     ranges = { temperature = { min = -40.0, max = 85.0 } },
+//!     flatline = { fields = ["temperature"], samples = 10, epsilon = 0.001 } }
+//! ```
+//!
+//! Failed checks are named `missing:<field>`, `stale`, `out_of_range:<field>`
+//! and `flatline:<field>`. Checks that do not apply, such as the range of a
+//! missing field, are not counted.
+
+use crate::config::{ParamSpec, ParamType, ProcessorConfig, ProcessorMetadata, StageConfig, extract_param};
+use crate::core::checkpoint::Snapshot;
+use crate::core::state::StateStore;
+use crate::core::timing_mixin::{TimingMixin, WithTimingMixin};
+use crate::core::{context::ProcessingContext, message::Message};
+use crate::processors::common::field_utils::FieldUtils;
+use crate::processors::processor::Processor;
+use crate::error::LiminalError;
+
+use anyhow::{Result, anyhow};
+use serde::Deserialize;
+use serde_json::{Number, Value};
+use std::collections::BTreeMap;
+use std::time::{Duration, SystemTime};
+use tracing::error;
+
+/// Limits of a range check; either may be omitted.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct RangeCheck {
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct FlatlineCheck {
+    pub fields: Vec<String>,
+    /// Consecutive unchanged readings that fail the check
+    #[serde(default = "default_flatline_samples")]
+    pub samples: u64,
+    /// Largest difference still counted as unchanged
+    #[serde(default)]
+    pub epsilon: f64,
+}
+
+fn default_flatline_samples() -> u64 {
+    10
+}
+
+#[derive(Debug, Clone)]
+pub struct QualityConfig {
+    pub required: Vec<String>,
+    pub max_age_ms: Option<u64>,
+    pub ranges: BTreeMap<String, RangeCheck>,
+    pub flatline: Option<FlatlineCheck>,
+    pub key_field: Option<String>,
+    pub score_field: String,
+    pub issues_field: String,
+    pub timing: Option<crate::config::TimingConfig>,
+}
+
+impl ProcessorConfig for QualityConfig {
+    fn from_stage_config(config: &StageConfig) -> Result<Self> {
+        let config = Self {
+            required: extract_param(&config.parameters, "required", Vec::<String>::new()),
+            max_age_ms: extract_param(&config.parameters, "max_age_ms", None::<u64>),
+            ranges: extract_param(&config.parameters, "ranges", BTreeMap::new()),
+            flatline: extract_param(&config.parameters, "flatline", None::<FlatlineCheck>),
+            key_field: extract_param(&config.parameters, "key_field", None::<String>),
+            score_field: extract_param(&config.parameters, "score_field", "quality".to_string()),
+            issues_field: extract_param(&config.parameters, "issues_field", "quality_issues".to_string()),
+            timing: config.timing.clone(),
+        };
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.required.is_empty() && self.max_age_ms.is_none() && self.ranges.is_empty() && self.flatline.is_none() {
+            return Err(anyhow!(
+                "quality processor requires at least one check (required, max_age_ms, ranges, flatline)"
+            ));
+        }
+        for (field, range) in &self.ranges {
+            if let (Some(min), Some(max)) = (range.min, range.max)
+                && min > max
+            {
+                return Err(anyhow!("range of '{}': min ({}) must not exceed max ({})", field, min, max));
+            }
+        }
+        if let Some(flatline) = &self.flatline {
+            if flatline.fields.is_empty() {
+                return Err(LiminalError::missing_parameter("fields").into());
+            }
+            if flatline.samples < 2 {
+                return Err(anyhow!("flatline samples must be at least 2"));
+            }
+            if flatline.epsilon < 0.0 {
+                return Err(anyhow!("flatline epsilon cannot be negative"));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The last distinct reading of a series, and how many readings in a row
+/// have matched it.
+type Unchanged = (f64, u64);
+
+pub struct QualityProcessor {
+    name: String,
+    config: QualityConfig,
+    timing: TimingMixin,
+    series: StateStore<(String, String), Unchanged>,
+}
+
+impl QualityProcessor {
+    pub const METADATA: ProcessorMetadata = ProcessorMetadata {
+        name: "quality",
+        description: "Scores messages by data quality checks",
+        parameters: &[
+            ParamSpec::new("required", ParamType::Array, "Fields that must be present"),
+            ParamSpec::new("max_age_ms", ParamType::Integer, "Oldest event time accepted, in milliseconds before now"),
+            ParamSpec::new("ranges", ParamType::Object, "Valid min/max of fields, by field"),
+            ParamSpec::new("flatline", ParamType::Object, "Readings that must change: fields, samples, epsilon"),
+            ParamSpec::new("key_field", ParamType::String, "Field whose value keeps separate flatline series"),
+            ParamSpec::new("score_field", ParamType::String, "Field receiving the quality score"),
+            ParamSpec::new("issues_field", ParamType::String, "Field receiving the failed checks"),
+        ],
+        shared: &[],
+    };
+
+    pub fn new(name: &str, config: StageConfig) -> Result<Box<dyn Processor>> {
+        let processor_config = QualityConfig::from_stage_config(&config)?;
+        let timing = TimingMixin::new(processor_config.timing.as_ref());
+
+        Ok(Box::new(Self {
+            name: name.to_string(),
+            config: processor_config,
+            timing,
+            series: StateStore::open(name, config.state.as_ref())?,
+        }))
+    }
+
+    fn process_message(&mut self, mut message: Message) -> Result<Message> {
+        let payload = &message.payload;
+        let mut checks = 0usize;
+        let mut issues = Vec::new();
+
+        for field in &self.config.required {
+            checks += 1;
+            if FieldUtils::extract_field_value(payload, field).is_none_or(Value::is_null) {
+                issues.push(format!("missing:{}", field));
+            }
+        }
+
+        if let Some(max_age_ms) = self.config.max_age_ms {
+            checks += 1;
+            let age = SystemTime::now().duration_since(message.timing.event_time).unwrap_or_default();
+            if age > Duration::from_millis(max_age_ms) {
+                issues.push("stale".to_string());
+            }
+        }
+
+        for (field, range) in &self.config.ranges {
+            let Some(value) = FieldUtils::extract_f64(payload, field) else {
+                continue;
+            };
+            checks += 1;
+            if range.min.is_some_and(|min| value < min) || range.max.is_some_and(|max| value > max) {
+                issues.push(format!("out_of_range:{}", field));
+            }
+        }
+
+        if let Some(flatline) = &self.config.flatline {
+            let key = FieldUtils::extract_key(payload, self.config.key_field.as_deref());
+            for field in &flatline.fields {
+                let Some(value) = FieldUtils::extract_f64(payload, field) else {
+                    continue;
+                };
+                checks += 1;
+                let (last, unchanged) = self.series.get_or_insert_with((key.clone(), field.clone()), || (value, 0));
+                if (value - *last).abs() <= flatline.epsilon {
+                    *unchanged += 1;
+                } else {
+                    *last = value;
+                    *unchanged = 1;
+                }
+                if *unchanged >= flatline.samples {
+                    issues.push(format!("flatline:{}", field));
+                }
+            }
+        }
+
+        let score = if checks == 0 { 1.0 } else { (checks - issues.len()) as f64 / checks as f64 };
+        let score = Number::from_f64(score).map(Value::Number).unwrap_or(Value::Null);
+        let issues = Value::Array(issues.into_iter().map(Value::String).collect());
+        FieldUtils::set_field_value(&mut message.payload, &self.config.score_field, score)?;
+        FieldUtils::set_field_value(&mut message.payload, &self.config.issues_field, issues)?;
+
+        message.source = self.name.clone();
+        Ok(message)
+    }
+}
+
+#[async_trait::async_trait]
+impl Processor for QualityProcessor {
+    async fn init(&mut self) -> Result<()> {
+        tracing::info!("Quality processor '{}' initialised", self.name);
+        Ok(())
+    }
+
+    async fn process(&mut self, context: &mut ProcessingContext) -> Result<()> {
+        if let Some((_, message)) = context.recv(tokio::time::Duration::from_millis(10)).await {
+            match self.process_message(message) {
+                Ok(mut output_message) => {
+                    if let Some(output_info) = &context.output {
+                        output_message.topic = output_info.name.clone();
+                        let output_message = self.timing.update_message_watermark(output_message);
+
+                        if let Err(e) = output_info.channel.publish(output_message).await {
+                            tracing::warn!("Failed to publish quality output: {:?}", e);
+                        }
+                    }
+                }
+                Err(e) => {
+                    error!("{}: Failed to score message: {}", self.name, e);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn as_snapshot(&mut self) -> Option<&mut dyn Snapshot> {
+        Some(self)
+    }
+}
+
+impl Snapshot for QualityProcessor {
+    fn snapshot(&self) -> Result<Value> {
+        self.series.snapshot()
+    }
+
+    fn restore(&mut self, state: Value) -> Result<()> {
+        self.series.restore(state)
+    }
+}
+
+impl WithTimingMixin for QualityProcessor {
+    fn timing_mixin(&self) -> &TimingMixin {
+        &self.timing
+    }
+
+    fn timing_mixin_mut(&mut self) -> &mut TimingMixin {
+        &mut self.timing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{MessageBuilder, TestContext};
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_quality_scores_failed_checks() {
+        let stage = StageConfig {
+            r#type: "quality".to_string(),
+            inputs: Some(vec!["in".to_string()]),
+            output: Some("out".to_string()),
+            parameters: serde_json::from_value(json!({
+                "required": ["temperature", "humidity"],
+                "max_age_ms": 60000,
+                "ranges": { "temperature": { "min": -40.0, "max": 85.0 } },
+                "flatline": { "fields": ["temperature"], "samples": 3 },
+            }))
+            .ok(),
+            ..Default::default()
+        };
+        let mut processor = QualityProcessor::new("quality", stage).unwrap();
+        let mut test = TestContext::new("quality").input("in").output("out");
+
+        let mut score = async |payload: Value| {
+            test.send("in", MessageBuilder::new(payload).build()).await.unwrap();
+            test.process_pending(processor.as_mut()).await.unwrap();
+            test.try_output().await.unwrap().payload
+        };
+
+        let good = score(json!({ "temperature": 21.0, "humidity": 40 })).await;
+        assert_eq!(good["quality"], 1.0);
+        assert_eq!(good["quality_issues"], json!([]));
+
+        // Five checks: two required, age, range and flatline
+        let bad = score(json!({ "temperature": 120.0 })).await;
+        assert_eq!(bad["quality"], 0.6);
+        assert_eq!(bad["quality_issues"], json!(["missing:humidity", "out_of_range:temperature"]));
+
+        score(json!({ "temperature": 120.0, "humidity": 40 })).await;
+        let stuck = score(json!({ "temperature": 120.0, "humidity": 40 })).await;
+        assert_eq!(stuck["quality_issues"], json!(["out_of_range:temperature", "flatline:temperature"]));
+    }
+}
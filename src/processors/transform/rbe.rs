@@ -0,0 +1,287 @@
+//! Report-by-Exception Transform
+//!
+//! Forwards a message only when one of its watched fields has changed since
+//! the last message forwarded for its key, or when `max_interval_ms` of event
+//! time has passed since then, so slowly varying signals are stored as their
+//! changes plus a periodic heartbeat. Numeric fields count as changed when
+//! they move by more than `deadband` (or `deadband_percent` of the last
+//! forwarded value); other values when they differ at all.
+//!
+//! ```toml
+//! parameters = { fields = ["temperature", "state"], key_field = "sensor_id",
+//!     deadband = 0.5, max_interval_ms = 900000 }
+//! ```
+//!
+//! Changes are measured against the last forwarded value rather than the last
+//! reading, so a slow drift is still reported once it crosses the deadband.
+//! The first message of each key is always forwarded.
+
+use crate::config::{ParamSpec, ParamType, ProcessorConfig, ProcessorMetadata, StageConfig, extract_param};
+use crate::core::checkpoint::Snapshot;
+use crate::core::state::StateStore;
+use crate::core::timing_mixin::{TimingMixin, WithTimingMixin};
+use crate::core::{context::ProcessingContext, message::Message};
+use crate::processors::common::field_utils::FieldUtils;
+use crate::processors::processor::Processor;
+use crate::error::LiminalError;
+
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::time::UNIX_EPOCH;
+use tracing::debug;
+
+#[derive(Debug, Clone)]
+pub struct RbeConfig {
+    pub fields: Vec<String>,
+    pub key_field: Option<String>,
+    /// Largest absolute change of a numeric field that is not reported
+    pub deadband: f64,
+    /// Largest change, in percent of the last forwarded value, that is not reported
+    pub deadband_percent: Option<f64>,
+    /// Event time after which a message is forwarded even if unchanged
+    pub max_interval_ms: Option<u64>,
+    pub timing: Option<crate::config::TimingConfig>,
+}
+
+impl ProcessorConfig for RbeConfig {
+    fn from_stage_config(config: &StageConfig) -> Result<Self> {
+        let mut fields = extract_param(&config.parameters, "fields", Vec::<String>::new());
+        if let Some(field_in) = extract_param(&config.parameters, "field_in", None::<String>) {
+            fields.push(field_in);
+        }
+
+        let config = Self {
+            fields,
+            key_field: extract_param(&config.parameters, "key_field", None::<String>),
+            deadband: extract_param(&config.parameters, "deadband", 0.0),
+            deadband_percent: extract_param(&config.parameters, "deadband_percent", None::<f64>),
+            max_interval_ms: extract_param(&config.parameters, "max_interval_ms", None::<u64>),
+            timing: config.timing.clone(),
+        };
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.fields.is_empty() {
+            return Err(anyhow!("rbe processor requires 'fields' (or 'field_in') to be specified"));
+        }
+        if self.deadband < 0.0 || self.deadband_percent.is_some_and(|percent| percent < 0.0) {
+            return Err(LiminalError::invalid_parameter("deadband", "cannot be negative").into());
+        }
+        if self.deadband > 0.0 && self.deadband_percent.is_some() {
+            return Err(anyhow!("deadband and deadband_percent cannot both be set"));
+        }
+        if self.max_interval_ms == Some(0) {
+            return Err(LiminalError::invalid_parameter("max_interval_ms", "must be greater than 0").into());
+        }
+        Ok(())
+    }
+}
+
+/// The last message forwarded for a key.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Reported {
+    /// Watched field values, by field
+    values: BTreeMap<String, Value>,
+    /// Event time (milliseconds since the epoch)
+    at_ms: u64,
+}
+
+pub struct RbeProcessor {
+    name: String,
+    config: RbeConfig,
+    timing: TimingMixin,
+    reported: StateStore<String, Reported>,
+}
+
+impl RbeProcessor {
+    pub const METADATA: ProcessorMetadata = ProcessorMetadata {
+        name: "rbe",
+        description: "Forwards messages only when fields change beyond a deadband (report by exception)",
+        parameters: &[
+            ParamSpec::new("fields", ParamType::Array, "Fields whose changes are reported"),
+            ParamSpec::new("field_in", ParamType::String, "A single field to watch, in addition to fields"),
+            ParamSpec::new("key_field", ParamType::String, "Field whose value keeps separate state"),
+            ParamSpec::new("deadband", ParamType::Number, "Largest absolute change not reported"),
+            ParamSpec::new("deadband_percent", ParamType::Number, "Largest change not reported, in percent of the last reported value"),
+            ParamSpec::new("max_interval_ms", ParamType::Integer, "Event time after which unchanged messages are forwarded"),
+        ],
+        shared: &[],
+    };
+
+    pub fn new(name: &str, config: StageConfig) -> Result<Box<dyn Processor>> {
+        let processor_config = RbeConfig::from_stage_config(&config)?;
+        let timing = TimingMixin::new(processor_config.timing.as_ref());
+
+        Ok(Box::new(Self {
+            name: name.to_string(),
+            config: processor_config,
+            timing,
+            reported: StateStore::open(name, config.state.as_ref())?,
+        }))
+    }
+
+    /// Whether the message is forwarded, recording it as reported if so.
+    fn should_forward(&mut self, message: &Message) -> bool {
+        let key = FieldUtils::extract_key(&message.payload, self.config.key_field.as_deref());
+        let now_ms = message
+            .timing
+            .event_time
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        let values: BTreeMap<String, Value> = self
+            .config
+            .fields
+            .iter()
+            .filter_map(|field| Some((field.clone(), FieldUtils::extract_field_value(&message.payload, field)?.clone())))
+            .collect();
+
+        let forward = match self.reported.get(&key) {
+            None => true,
+            Some(last) => {
+                self.config
+                    .max_interval_ms
+                    .is_some_and(|interval| now_ms.saturating_sub(last.at_ms) >= interval)
+                    || values.iter().any(|(field, value)| self.changed(last.values.get(field), value))
+            }
+        };
+
+        if forward {
+            self.reported.insert(key, Reported { values, at_ms: now_ms });
+        } else {
+            debug!("{}: Unchanged message of key '{}' suppressed", self.name, key);
+        }
+        forward
+    }
+
+    fn changed(&self, last: Option<&Value>, value: &Value) -> bool {
+        let Some(last) = last else {
+            return true;
+        };
+        match (last.as_f64(), value.as_f64()) {
+            (Some(last), Some(value)) => {
+                let deadband = match self.config.deadband_percent {
+                    Some(percent) => last.abs() * percent / 100.0,
+                    None => self.config.deadband,
+                };
+                if deadband == 0.0 { value != last } else { (value - last).abs() > deadband }
+            }
+            _ => last != value,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Processor for RbeProcessor {
+    async fn init(&mut self) -> Result<()> {
+        tracing::info!("RBE processor '{}' initialised ({:?})", self.name, self.config.fields);
+        Ok(())
+    }
+
+    async fn process(&mut self, context: &mut ProcessingContext) -> Result<()> {
+        if let Some((_, mut message)) = context.recv(tokio::time::Duration::from_millis(10)).await {
+            if !self.should_forward(&message) {
+                return Ok(());
+            }
+            message.source = self.name.clone();
+
+            if let Some(output_info) = &context.output {
+                message.topic = output_info.name.clone();
+                let message = self.timing.update_message_watermark(message);
+
+                if let Err(e) = output_info.channel.publish(message).await {
+                    tracing::warn!("Failed to publish rbe output: {:?}", e);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn as_snapshot(&mut self) -> Option<&mut dyn Snapshot> {
+        Some(self)
+    }
+}
+
+impl Snapshot for RbeProcessor {
+    fn snapshot(&self) -> Result<Value> {
+        self.reported.snapshot()
+    }
+
+    fn restore(&mut self, state: Value) -> Result<()> {
+        self.reported.restore(state)
+    }
+}
+
+impl WithTimingMixin for RbeProcessor {
+    fn timing_mixin(&self) -> &TimingMixin {
+        &self.timing
+    }
+
+    fn timing_mixin_mut(&mut self) -> &mut TimingMixin {
+        &mut self.timing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{MessageBuilder, TestContext};
+    use serde_json::json;
+    use std::time::{Duration, SystemTime};
+
+    #[tokio::test]
+    async fn test_rbe_forwards_changes_and_heartbeats() {
+        let stage = StageConfig {
+            r#type: "rbe".to_string(),
+            inputs: Some(vec!["in".to_string()]),
+            output: Some("out".to_string()),
+            parameters: serde_json::from_value(json!({
+                "fields": ["temperature", "state"],
+                "key_field": "sensor",
+                "deadband": 0.5,
+                "max_interval_ms": 60000,
+            }))
+            .ok(),
+            ..Default::default()
+        };
+        let mut processor = RbeProcessor::new("rbe", stage).unwrap();
+        let mut test = TestContext::new("rbe").input("in").output("out");
+
+        let start = SystemTime::now();
+        let readings = [
+            ("a", 0, 20.0, "ok"),   // first of a
+            ("b", 0, 20.0, "ok"),   // first of b
+            ("a", 10, 20.3, "ok"),  // within the deadband
+            ("a", 20, 20.6, "ok"),  // drifted 0.6 from the last forwarded
+            ("a", 30, 20.6, "hot"), // state changed
+            ("a", 40, 20.7, "hot"), // within the deadband
+            ("a", 90, 20.7, "hot"), // heartbeat, 60 s after the last forwarded
+        ];
+        for (sensor, seconds, temperature, state) in readings {
+            let payload = json!({ "sensor": sensor, "temperature": temperature, "state": state });
+            let reading = MessageBuilder::new(payload).event_time(start + Duration::from_secs(seconds)).build();
+            test.send("in", reading).await.unwrap();
+        }
+        test.process_pending(processor.as_mut()).await.unwrap();
+
+        let mut forwarded = Vec::new();
+        while let Some(message) = test.try_output().await {
+            forwarded.push((message.payload["sensor"].clone(), message.payload["temperature"].clone()));
+        }
+        assert_eq!(
+            forwarded,
+            vec![
+                (json!("a"), json!(20.0)),
+                (json!("b"), json!(20.0)),
+                (json!("a"), json!(20.6)),
+                (json!("a"), json!(20.6)),
+                (json!("a"), json!(20.7)),
+            ]
+        );
+    }
+}
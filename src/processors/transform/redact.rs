@@ -0,0 +1,270 @@
+//! Redact Transform
+//!
+//! Masks personal data before it reaches external sinks. Each rule names a
+//! payload field and what to do with it:
+//!
+//! - **remove**: drop the field
+//! - **hash**: replace the value with the hex HMAC-SHA256 of its JSON under
+//!   `salt`, so records stay joinable by the field without revealing it
+//! - **truncate**: keep the first `length` characters of a string, or
+//!   `decimals` decimal places of a number (e.g. 2 for ~1 km GPS precision)
+//!
+//! ```toml
+//! parameters = { salt = "${file:/run/secrets/redact_salt}", rules = [
+//!     { field = "user_id", action = "hash" },
+//!     { field = "lat", action = "truncate", decimals = 2 },
+//!     { field = "lon", action = "truncate", decimals = 2 },
+//!     { field = "email", action = "remove" },
+//! ] }
+//! ```
+//!
+//! Missing fields are skipped, and values a rule does not apply to (such as a
+//! `truncate` by `length` of a number) are removed rather than passed on.
+
+use crate::config::{ParamSpec, ParamType, ProcessorConfig, ProcessorMetadata, StageConfig, extract_param};
+use crate::core::timing_mixin::{TimingMixin, WithTimingMixin};
+use crate::core::{context::ProcessingContext, message::Message};
+use crate::processors::common::field_utils::FieldUtils;
+use crate::processors::processor::Processor;
+
+use anyhow::{Result, anyhow};
+use ring::hmac;
+use serde::Deserialize;
+use serde_json::{Number, Value};
+use std::fmt::Write as _;
+use tracing::error;
+
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum RedactAction {
+    /// Drop the field
+    Remove,
+    /// Replace the value with its salted hash
+    Hash,
+    /// Shorten a string to `length` characters, or a number to `decimals`
+    /// decimal places
+    Truncate { length: Option<usize>, decimals: Option<u32> },
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+pub struct RedactRule {
+    pub field: String,
+    #[serde(flatten)]
+    pub action: RedactAction,
+}
+
+#[derive(Debug, Clone)]
+pub struct RedactConfig {
+    pub rules: Vec<RedactRule>,
+    /// Key of the `hash` HMAC
+    pub salt: String,
+    pub timing: Option<crate::config::TimingConfig>,
+}
+
+impl ProcessorConfig for RedactConfig {
+    fn from_stage_config(config: &StageConfig) -> Result<Self> {
+        let rules = config
+            .parameters
+            .as_ref()
+            .and_then(|parameters| parameters.get("rules"))
+            .map(|rules| serde_json::from_value::<Vec<RedactRule>>(rules.clone()))
+            .transpose()
+            .map_err(|e| anyhow!("Invalid redact rules: {}", e))?
+            .unwrap_or_default();
+
+        let config = Self {
+            rules,
+            salt: extract_param(&config.parameters, "salt", String::new()),
+            timing: config.timing.clone(),
+        };
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.rules.is_empty() {
+            return Err(anyhow!("redact processor requires at least one rule"));
+        }
+        for rule in &self.rules {
+            if rule.field.is_empty() {
+                return Err(anyhow!("redact rule field cannot be empty"));
+            }
+            match rule.action {
+                RedactAction::Truncate { length: None, decimals: None } => {
+                    return Err(anyhow!("truncate rule for '{}' requires length or decimals", rule.field));
+                }
+                RedactAction::Hash if self.salt.is_empty() => {
+                    return Err(anyhow!("hash rule for '{}' requires a salt", rule.field));
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct RedactProcessor {
+    name: String,
+    config: RedactConfig,
+    key: hmac::Key,
+    timing: TimingMixin,
+}
+
+impl RedactProcessor {
+    pub const METADATA: ProcessorMetadata = ProcessorMetadata {
+        name: "redact",
+        description: "Hashes, truncates, or removes personal data fields",
+        parameters: &[
+            ParamSpec::new("rules", ParamType::Array, "Rules of field and action (remove, hash, truncate with length or decimals)"),
+            ParamSpec::new("salt", ParamType::String, "Secret key of hashed values"),
+        ],
+        shared: &[],
+    };
+
+    pub fn new(name: &str, config: StageConfig) -> Result<Box<dyn Processor>> {
+        let processor_config = RedactConfig::from_stage_config(&config)?;
+        let key = hmac::Key::new(hmac::HMAC_SHA256, processor_config.salt.as_bytes());
+        let timing = TimingMixin::new(processor_config.timing.as_ref());
+
+        Ok(Box::new(Self {
+            name: name.to_string(),
+            config: processor_config,
+            key,
+            timing,
+        }))
+    }
+
+    fn process_message(&self, mut message: Message) -> Result<Message> {
+        for rule in &self.config.rules {
+            let Some(value) = FieldUtils::extract_field_value(&message.payload, &rule.field) else {
+                continue;
+            };
+            match self.redact(value, &rule.action)? {
+                Some(value) => FieldUtils::set_field_value(&mut message.payload, &rule.field, value)?,
+                None => FieldUtils::remove_field_value(&mut message.payload, &rule.field)?,
+            }
+        }
+
+        message.source = self.name.clone();
+        Ok(message)
+    }
+
+    /// The redacted value, or `None` to remove the field.
+    fn redact(&self, value: &Value, action: &RedactAction) -> Result<Option<Value>> {
+        Ok(match (action, value) {
+            (RedactAction::Remove, _) => None,
+            (RedactAction::Hash, value) => {
+                let tag = hmac::sign(&self.key, &serde_json::to_vec(value)?);
+                let mut hex = String::with_capacity(64);
+                for byte in tag.as_ref() {
+                    let _ = write!(hex, "{:02x}", byte);
+                }
+                Some(Value::String(hex))
+            }
+            (RedactAction::Truncate { length: Some(length), .. }, Value::String(text)) => {
+                Some(Value::String(text.chars().take(*length).collect()))
+            }
+            (RedactAction::Truncate { decimals: Some(decimals), .. }, Value::Number(number)) => {
+                if number.is_f64() {
+                    let scale = 10f64.powi(*decimals as i32);
+                    let truncated = (number.as_f64().unwrap_or_default() * scale).trunc() / scale;
+                    Number::from_f64(truncated).map(Value::Number)
+                } else {
+                    Some(Value::Number(number.clone()))
+                }
+            }
+            (RedactAction::Truncate { .. }, _) => None,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl Processor for RedactProcessor {
+    async fn init(&mut self) -> Result<()> {
+        tracing::info!("Redact processor '{}' initialised ({} rules)", self.name, self.config.rules.len());
+        Ok(())
+    }
+
+    async fn process(&mut self, context: &mut ProcessingContext) -> Result<()> {
+        if let Some((_, message)) = context.recv(tokio::time::Duration::from_millis(10)).await {
+            match self.process_message(message) {
+                Ok(mut output_message) => {
+                    if let Some(output_info) = &context.output {
+                        output_message.topic = output_info.name.clone();
+                        let output_message = self.timing.update_message_watermark(output_message);
+
+                        if let Err(e) = output_info.channel.publish(output_message).await {
+                            tracing::warn!("Failed to publish redact output: {:?}", e);
+                        }
+                    }
+                }
+                Err(e) => {
+                    error!("{}: Failed to redact message: {}", self.name, e);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl WithTimingMixin for RedactProcessor {
+    fn timing_mixin(&self) -> &TimingMixin {
+        &self.timing
+    }
+
+    fn timing_mixin_mut(&mut self) -> &mut TimingMixin {
+        &mut self.timing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{MessageBuilder, TestContext};
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_redact_hashes_truncates_and_removes() {
+        let stage = StageConfig {
+            r#type: "redact".to_string(),
+            inputs: Some(vec!["in".to_string()]),
+            output: Some("out".to_string()),
+            parameters: serde_json::from_value(json!({
+                "salt": "pepper",
+                "rules": [
+                    { "field": "user.id", "action": "hash" },
+                    { "field": "lat", "action": "truncate", "decimals": 2 },
+                    { "field": "postcode", "action": "truncate", "length": 3 },
+                    { "field": "user.email", "action": "remove" },
+                    { "field": "missing", "action": "remove" },
+                ],
+            }))
+            .ok(),
+            ..Default::default()
+        };
+        let mut processor = RedactProcessor::new("redact", stage).unwrap();
+        let mut test = TestContext::new("redact").input("in").output("out");
+
+        for id in ["alice", "alice", "bob"] {
+            let payload = json!({
+                "user": { "id": id, "email": format!("{}@example.com", id) },
+                "lat": 35.89764,
+                "postcode": "VLT 1117",
+                "value": 7,
+            });
+            test.send("in", MessageBuilder::new(payload).build()).await.unwrap();
+        }
+        test.process_pending(processor.as_mut()).await.unwrap();
+
+        let first = test.try_output().await.unwrap().payload;
+        let second = test.try_output().await.unwrap().payload;
+        let third = test.try_output().await.unwrap().payload;
+        assert_eq!(first["user"], json!({ "id": second["user"]["id"] }));
+        assert_ne!(first["user"]["id"], third["user"]["id"]);
+        assert_eq!(first["user"]["id"].as_str().unwrap().len(), 64);
+        assert_eq!(first["lat"], json!(35.89));
+        assert_eq!(first["postcode"], "VLT");
+        assert_eq!(first["value"], 7);
+    }
+}
@@ -0,0 +1,306 @@
+//! Reorder Transform
+//!
+//! Restores event-time order to messages delivered out of order (e.g. by MQTT
+//! with several publishers or QoS 1 redelivery), so that `delta`, `calculus`
+//! and windowed stages downstream see each key's readings in sequence.
+//!
+//! Messages are buffered per key, sorted by event time, and released once the
+//! watermark has passed their event time: the latest watermark carried by the
+//! input messages, or the stage's own `timing.watermark_strategy`. Without a
+//! watermark, or while it stalls, a message is held for at most `max_delay_ms`
+//! of wall-clock time. Messages released early for that reason release the
+//! earlier buffered messages of their key with them, so output per key is
+//! always in event-time order.
+//!
+//! A message arriving after a later message of its key was released is late,
+//! and is dropped or emitted as-is according to `on_late`. A key's buffer is
+//! removed once it drains; the event time released per key is kept in the
+//! stage's state store, so `state.ttl_ms` and `state.max_keys` bound it.
+
+use crate::config::{ParamSpec, ParamType, ProcessorConfig, ProcessorMetadata, StageConfig, extract_param};
+use crate::core::state::StateStore;
+use crate::core::timing_mixin::{TimingMixin, WithTimingMixin};
+use crate::core::{context::ProcessingContext, message::Message};
+use crate::processors::common::field_utils::FieldUtils;
+use crate::processors::processor::Processor;
+use crate::error::LiminalError;
+
+use anyhow::Result;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::time::{Duration, Instant, SystemTime};
+use tracing::debug;
+
+/// What to do with a message older than one already released for its key.
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LatePolicy {
+    /// Drop the message
+    Drop,
+    /// Emit it immediately, out of order
+    Emit,
+}
+
+#[derive(Debug, Clone)]
+pub struct ReorderConfig {
+    pub key_field: Option<String>,
+    /// Longest a message is held waiting for the watermark (in milliseconds)
+    pub max_delay_ms: u64,
+    /// Most messages buffered per key; the earliest is released beyond this
+    pub max_buffered: usize,
+    pub on_late: LatePolicy,
+    pub timing: Option<crate::config::TimingConfig>,
+}
+
+impl ProcessorConfig for ReorderConfig {
+    fn from_stage_config(config: &StageConfig) -> Result<Self> {
+        let config = Self {
+            key_field: extract_param(&config.parameters, "key_field", None::<String>),
+            max_delay_ms: extract_param(&config.parameters, "max_delay_ms", 1000_u64),
+            max_buffered: extract_param(&config.parameters, "max_buffered", 1000_usize),
+            on_late: extract_param(&config.parameters, "on_late", LatePolicy::Drop),
+            timing: config.timing.clone(),
+        };
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.max_buffered == 0 {
+            return Err(LiminalError::invalid_parameter("max_buffered", "must be greater than 0").into());
+        }
+        Ok(())
+    }
+}
+
+struct Held {
+    message: Message,
+    arrived: Instant,
+}
+
+pub struct ReorderProcessor {
+    name: String,
+    config: ReorderConfig,
+    timing: TimingMixin,
+    /// Held messages per key, ordered by event time (ties in arrival order)
+    buffers: HashMap<String, Vec<Held>>,
+    /// Event time of the last message released per key
+    released_until: StateStore<String, SystemTime>,
+    /// Latest watermark seen on the input or generated by the stage
+    watermark: Option<SystemTime>,
+}
+
+impl ReorderProcessor {
+    pub const METADATA: ProcessorMetadata = ProcessorMetadata {
+        name: "reorder",
+        description: "Buffers messages per key and re-emits them in event-time order",
+        parameters: &[
+            ParamSpec::new("key_field", ParamType::String, "Field whose value keeps a separate order"),
+            ParamSpec::new("max_delay_ms", ParamType::Integer, "Longest a message is held waiting for the watermark"),
+            ParamSpec::new("max_buffered", ParamType::Integer, "Most messages buffered per key"),
+            ParamSpec::new("on_late", ParamType::Choice(&["drop", "emit"]), "What to do with messages older than one already released"),
+        ],
+        shared: &[],
+    };
+
+    pub fn new(name: &str, config: StageConfig) -> Result<Box<dyn Processor>> {
+        let processor_config = ReorderConfig::from_stage_config(&config)?;
+        processor_config.validate()?;
+
+        let timing = TimingMixin::new(processor_config.timing.as_ref());
+
+        Ok(Box::new(Self {
+            name: name.to_string(),
+            config: processor_config,
+            timing,
+            buffers: HashMap::new(),
+            released_until: StateStore::open(name, config.state.as_ref())?,
+            watermark: None,
+        }))
+    }
+
+    /// Buffer a message, returning it straight away if it is late and emitted.
+    fn insert(&mut self, message: Message, now: Instant) -> Option<Message> {
+        let generated = self.timing.watermark_manager().update_watermark(&message);
+        self.watermark = self.watermark.max(message.timing.watermark).max(generated);
+
+        let key = FieldUtils::extract_key(&message.payload, self.config.key_field.as_deref());
+        let event_time = message.timing.event_time;
+
+        if self.released_until.get(&key).is_some_and(|released| event_time < *released) {
+            return match self.config.on_late {
+                LatePolicy::Drop => {
+                    debug!("Reorder stage '{}' dropped a late message", self.name);
+                    None
+                }
+                LatePolicy::Emit => Some(message),
+            };
+        }
+
+        let held = self.buffers.entry(key).or_default();
+        let position = held.partition_point(|held| held.message.timing.event_time <= event_time);
+        held.insert(position, Held { message, arrived: now });
+        None
+    }
+
+    /// Release the messages that are due, in event-time order per key.
+    fn release(&mut self, now: Instant) -> Vec<Message> {
+        let max_delay = Duration::from_millis(self.config.max_delay_ms);
+        let mut released = Vec::new();
+
+        for (key, buffer) in self.buffers.iter_mut() {
+            let due = |held: &Held| {
+                self.watermark.is_some_and(|watermark| held.message.timing.event_time <= watermark)
+                    || now.saturating_duration_since(held.arrived) >= max_delay
+            };
+            // Everything up to the last due message goes, so order is kept
+            let mut count = buffer.iter().rposition(due).map_or(0, |last| last + 1);
+            count = count.max(buffer.len().saturating_sub(self.config.max_buffered));
+
+            if count > 0 {
+                self.released_until.insert(key.clone(), buffer[count - 1].message.timing.event_time);
+                released.extend(buffer.drain(..count).map(|held| held.message));
+            }
+        }
+        self.buffers.retain(|_, buffer| !buffer.is_empty());
+
+        released
+    }
+
+    /// Release every buffered message, in event-time order per key.
+    fn release_all(&mut self) -> Vec<Message> {
+        let mut released = Vec::new();
+        for (key, buffer) in self.buffers.drain() {
+            if let Some(last) = buffer.last() {
+                self.released_until.insert(key, last.message.timing.event_time);
+            }
+            released.extend(buffer.into_iter().map(|held| held.message));
+        }
+        released
+    }
+
+    async fn publish(&mut self, context: &ProcessingContext, messages: Vec<Message>) {
+        let Some(output_info) = &context.output else {
+            return;
+        };
+        for mut message in messages {
+            message.source = self.name.clone();
+            message.topic = output_info.name.clone();
+            let message = self.timing.update_message_watermark(message);
+
+            if let Err(e) = output_info.channel.publish(message).await {
+                tracing::warn!("Failed to publish reorder output: {:?}", e);
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Processor for ReorderProcessor {
+    async fn init(&mut self) -> Result<()> {
+        tracing::info!(
+            "Reorder processor '{}' initialised (max delay: {} ms, late messages: {:?})",
+            self.name,
+            self.config.max_delay_ms,
+            self.config.on_late
+        );
+        Ok(())
+    }
+
+    async fn process(&mut self, context: &mut ProcessingContext) -> Result<()> {
+        let mut messages = Vec::new();
+        if let Some((_, message)) = context.recv(tokio::time::Duration::from_millis(10)).await {
+            messages.extend(self.insert(message, Instant::now()));
+        }
+        messages.extend(self.release(Instant::now()));
+        self.publish(context, messages).await;
+        Ok(())
+    }
+
+    async fn flush(&mut self, context: &mut ProcessingContext) -> Result<()> {
+        let messages = self.release_all();
+        self.publish(context, messages).await;
+        Ok(())
+    }
+}
+
+impl WithTimingMixin for ReorderProcessor {
+    fn timing_mixin(&self) -> &TimingMixin {
+        &self.timing
+    }
+
+    fn timing_mixin_mut(&mut self) -> &mut TimingMixin {
+        &mut self.timing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::message::TimingInfo;
+    use serde_json::json;
+    use std::time::UNIX_EPOCH;
+
+    fn reading(sensor: &str, seconds: u64) -> Message {
+        let mut message = Message::new("src", "topic", json!({ "sensor": sensor, "t": seconds }));
+        message.timing = TimingInfo::with_event_time(UNIX_EPOCH + Duration::from_secs(seconds));
+        message
+    }
+
+    fn times(messages: &[Message]) -> Vec<(String, u64)> {
+        let mut times: Vec<_> = messages
+            .iter()
+            .map(|m| (m.payload["sensor"].as_str().unwrap().to_string(), m.payload["t"].as_u64().unwrap()))
+            .collect();
+        // Keys are independent; order within a key is what matters
+        times.sort_by(|a, b| a.0.cmp(&b.0));
+        times
+    }
+
+    #[test]
+    fn test_reorder_releases_in_event_time_order() {
+        let config = StageConfig {
+            r#type: "reorder".to_string(),
+            parameters: Some(HashMap::from([
+                ("key_field".to_string(), json!("sensor")),
+                ("max_delay_ms".to_string(), json!(500)),
+            ])),
+            ..Default::default()
+        };
+        let mut processor = ReorderProcessor {
+            name: "reorder".to_string(),
+            config: ReorderConfig::from_stage_config(&config).unwrap(),
+            timing: TimingMixin::new(None),
+            buffers: HashMap::new(),
+            released_until: StateStore::new(),
+            watermark: None,
+        };
+        let start = Instant::now();
+
+        for (sensor, seconds) in [("a", 3), ("b", 2), ("a", 1), ("a", 2), ("b", 1)] {
+            assert!(processor.insert(reading(sensor, seconds), start).is_none());
+        }
+        assert!(processor.release(start).is_empty());
+
+        // Held for max_delay_ms without a watermark
+        let released = processor.release(start + Duration::from_millis(500));
+        assert_eq!(times(&released), [("a".into(), 1), ("a".into(), 2), ("a".into(), 3), ("b".into(), 1), ("b".into(), 2)]);
+
+        // A watermark releases what it has passed, and nothing later
+        let mut watermarked = reading("a", 5);
+        watermarked.timing.watermark = Some(UNIX_EPOCH + Duration::from_secs(4));
+        processor.insert(watermarked, start);
+        processor.insert(reading("a", 4), start);
+        assert_eq!(times(&processor.release(start)), [("a".into(), 4)]);
+
+        // Older than what was released: dropped by default
+        assert!(processor.insert(reading("a", 3), start).is_none());
+        assert_eq!(times(&processor.release_all()), [("a".into(), 5)]);
+
+        // Drained buffers are removed, but late messages are still recognised
+        assert!(processor.buffers.is_empty());
+        assert!(processor.insert(reading("b", 1), start).is_none());
+        assert!(processor.buffers.is_empty());
+    }
+}
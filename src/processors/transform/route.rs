@@ -0,0 +1,269 @@
+//! Route Transform
+//!
+//! Content-based router that directs each message to one of the stage's named
+//! `side_outputs`. Targets are chosen either by the value of a field
+//! (`route_by`) or of a metadata entry (`route_by_metadata`, e.g. the MQTT
+//! topic a message arrived on), optionally translated through `mapping`, or by
+//! an ordered list of conditions (`routes`). Messages that match no route go to the stage's
+//! main `output`, which acts as the default/fallback; without one they are
+//! dropped.
+
+use crate::config::{ParamSpec, ParamType, ProcessorConfig, ProcessorMetadata, StageConfig, extract_param};
+use crate::core::timing_mixin::{TimingMixin, WithTimingMixin};
+use crate::core::{
+    context::{OutputInfo, ProcessingContext},
+    message::Message,
+};
+use crate::processors::common::condition_utils::{ConditionEvaluator, ConditionOperation};
+use crate::processors::common::field_utils::FieldUtils;
+use crate::processors::processor::Processor;
+
+use anyhow::{Result, anyhow};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use tracing::{debug, error};
+
+/// A conditional route: messages whose field satisfies the test go to `output`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Route {
+    pub field_path: String,
+    pub operation: String,
+    #[serde(default)]
+    pub value: Value,
+    pub output: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct RouteConfig {
+    /// Field whose value names the target output
+    pub route_by: Option<String>,
+    /// Metadata key whose value names the target output
+    pub route_by_metadata: Option<String>,
+    /// Optional translation from field value to output name
+    pub mapping: HashMap<String, String>,
+    /// Conditional routes, evaluated in order
+    pub routes: Vec<Route>,
+    /// Send to every matching route instead of only the first
+    pub all_matches: bool,
+    pub side_outputs: Vec<String>,
+    pub timing: Option<crate::config::TimingConfig>,
+}
+
+impl ProcessorConfig for RouteConfig {
+    fn from_stage_config(config: &StageConfig) -> Result<Self> {
+        let config = Self {
+            route_by: extract_param(&config.parameters, "route_by", None::<String>),
+            route_by_metadata: extract_param(&config.parameters, "route_by_metadata", None::<String>),
+            mapping: extract_param(&config.parameters, "mapping", HashMap::new()),
+            routes: extract_param(&config.parameters, "routes", Vec::<Route>::new()),
+            all_matches: extract_param(&config.parameters, "all_matches", false),
+            side_outputs: config.side_outputs.clone().unwrap_or_default(),
+            timing: config.timing.clone(),
+        };
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<()> {
+        let selectors = [self.route_by.is_some(), self.route_by_metadata.is_some(), !self.routes.is_empty()];
+        if selectors.iter().filter(|selected| **selected).count() != 1 {
+            return Err(anyhow!(
+                "route processor requires exactly one of 'route_by', 'route_by_metadata' or 'routes'"
+            ));
+        }
+        if self.side_outputs.is_empty() {
+            return Err(anyhow!("route processor requires 'side_outputs' on the stage"));
+        }
+
+        let targets = self
+            .routes
+            .iter()
+            .map(|route| &route.output)
+            .chain(self.mapping.values());
+        for target in targets {
+            if !self.side_outputs.contains(target) {
+                return Err(anyhow!(
+                    "route target '{}' is not a side output of this stage (declared: {:?})",
+                    target,
+                    self.side_outputs
+                ));
+            }
+        }
+
+        for route in &self.routes {
+            if route.field_path.is_empty() {
+                return Err(anyhow!("route has empty field_path"));
+            }
+            if ConditionOperation::parse(&route.operation).is_none() {
+                return Err(anyhow!("route has unsupported operation: '{}'", route.operation));
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct RouteProcessor {
+    name: String,
+    config: RouteConfig,
+    timing: TimingMixin,
+}
+
+impl RouteProcessor {
+    pub const METADATA: ProcessorMetadata = ProcessorMetadata {
+        name: "route",
+        description: "Routes messages to named side outputs by field value or condition",
+        parameters: &[
+            ParamSpec::new("route_by", ParamType::String, "Field whose value names the target output"),
+            ParamSpec::new("route_by_metadata", ParamType::String, "Metadata key whose value names the target output"),
+            ParamSpec::new("mapping", ParamType::Object, "Optional translation from field value to output name"),
+            ParamSpec::new("routes", ParamType::Array, "Conditional routes, evaluated in order"),
+            ParamSpec::new("all_matches", ParamType::Boolean, "Send to every matching route instead of only the first"),
+        ],
+        shared: &[],
+    };
+
+    pub fn new(name: &str, config: StageConfig) -> Result<Box<dyn Processor>> {
+        let processor_config = RouteConfig::from_stage_config(&config)?;
+        processor_config.validate()?;
+
+        let timing = TimingMixin::new(processor_config.timing.as_ref());
+
+        Ok(Box::new(Self {
+            name: name.to_string(),
+            config: processor_config,
+            timing,
+        }))
+    }
+
+    /// Side outputs the message should go to; empty means the default output.
+    fn select_targets(&self, message: &Message) -> Vec<String> {
+        let value = if let Some(route_by) = &self.config.route_by {
+            match FieldUtils::extract_field_value(&message.payload, route_by) {
+                Some(Value::String(s)) => Some(s.clone()),
+                Some(Value::Null) | None => return Vec::new(),
+                Some(other) => Some(other.to_string()),
+            }
+        } else if let Some(key) = &self.config.route_by_metadata {
+            match message.get_metadata(key) {
+                Some(value) => Some(value.to_string()),
+                None => return Vec::new(),
+            }
+        } else {
+            None
+        };
+        if let Some(value) = value {
+            let target = self.config.mapping.get(&value).cloned().unwrap_or(value);
+            return if self.config.side_outputs.contains(&target) {
+                vec![target]
+            } else {
+                Vec::new()
+            };
+        }
+
+        let mut targets = Vec::new();
+        for route in &self.config.routes {
+            let Some(operation) = ConditionOperation::parse(&route.operation) else {
+                continue;
+            };
+            let field_value = FieldUtils::extract_field_value(&message.payload, &route.field_path);
+            if ConditionEvaluator::evaluate_optional(field_value, &operation, &route.value) {
+                targets.push(route.output.clone());
+                if !self.config.all_matches {
+                    break;
+                }
+            }
+        }
+        targets
+    }
+
+    async fn publish(&mut self, output_info: &OutputInfo, mut message: Message) {
+        message.topic = output_info.name.clone();
+        let message = self.timing.update_message_watermark(message);
+
+        if let Err(e) = output_info.channel.publish(message).await {
+            tracing::warn!("Failed to publish routed message to '{}': {:?}", output_info.name, e);
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Processor for RouteProcessor {
+    async fn init(&mut self) -> Result<()> {
+        tracing::info!(
+            "Route processor '{}' initialised ({} side outputs)",
+            self.name,
+            self.config.side_outputs.len()
+        );
+        Ok(())
+    }
+
+    async fn process(&mut self, context: &mut ProcessingContext) -> Result<()> {
+        if let Some((channel_name, mut message)) = context.recv(tokio::time::Duration::from_millis(10)).await {
+            message.source = self.name.clone();
+            let targets = self.select_targets(&message);
+
+            if targets.is_empty() {
+                match &context.output {
+                    Some(output_info) => self.publish(output_info, message).await,
+                    None => debug!("Unrouted message from '{}' dropped", channel_name),
+                }
+                return Ok(());
+            }
+
+            for target in targets {
+                match context.side_outputs.get(&target) {
+                    Some(output_info) => self.publish(output_info, message.clone()).await,
+                    None => error!("Route target '{}' is not connected", target),
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl WithTimingMixin for RouteProcessor {
+    fn timing_mixin(&self) -> &TimingMixin {
+        &self.timing
+    }
+
+    fn timing_mixin_mut(&mut self) -> &mut TimingMixin {
+        &mut self.timing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{MessageBuilder, TestContext};
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_route_by_metadata_uses_the_mqtt_topic() {
+        let stage = StageConfig {
+            r#type: "route".to_string(),
+            inputs: Some(vec!["raw".to_string()]),
+            output: Some("other".to_string()),
+            side_outputs: Some(vec!["boilers".to_string()]),
+            parameters: serde_json::from_value(json!({
+                "route_by_metadata": "mqtt_topic",
+                "mapping": { "plant/boiler/1": "boilers" },
+            }))
+            .ok(),
+            ..Default::default()
+        };
+        let mut processor = RouteProcessor::new("route", stage).unwrap();
+        let mut test = TestContext::new("route").input("raw").output("other").side_output("boilers");
+
+        let boiler = MessageBuilder::new(json!({ "value": 1 })).metadata("mqtt_topic", "plant/boiler/1").build();
+        test.send("raw", boiler).await.unwrap();
+        test.send("raw", MessageBuilder::new(json!({ "value": 2 })).build()).await.unwrap();
+        test.process_pending(processor.as_mut()).await.unwrap();
+
+        let routed = test.try_side_output("boilers").await.unwrap();
+        assert_eq!(routed.get_metadata("mqtt_topic"), Some("plant/boiler/1"));
+        assert_eq!(test.try_output().await.unwrap().payload, json!({ "value": 2 }));
+        assert!(test.try_side_output("boilers").await.is_none());
+    }
+}
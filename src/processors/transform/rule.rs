@@ -1,15 +1,22 @@
-use crate::config::{ProcessorConfig, StageConfig, extract_param};
+use crate::config::{ParamSpec, ParamType, ProcessorConfig, ProcessorMetadata, StageConfig, extract_param};
+use crate::core::state::StateStore;
+use crate::core::timing::TimingHelpers;
 use crate::core::timing_mixin::{TimingMixin, WithTimingMixin};
-use crate::core::{context::ProcessingContext, message::Message};
+use crate::core::{
+    context::{OutputInfo, ProcessingContext},
+    message::Message,
+};
 use crate::processors::common::condition_utils::{ConditionEvaluator, ConditionOperation};
 use crate::processors::common::field_utils::FieldUtils;
 use crate::processors::processor::Processor;
 
 use anyhow::{Result, anyhow};
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 use serde_json::{Number, Value};
-use std::collections::HashMap;
-use tokio::select;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
 use tracing::{debug, error, warn};
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -19,6 +26,12 @@ pub struct RuleConfig {
     pub error_strategy: ErrorStrategy,
     #[serde(skip)]
     pub timing: Option<crate::config::TimingConfig>,
+    /// Side outputs declared on the stage, available to `route_to`/`emit_message`
+    #[serde(skip)]
+    pub side_outputs: Vec<String>,
+    /// Field that partitions state for stateful conditions (e.g. device id)
+    #[serde(default)]
+    pub key_field: Option<String>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -36,7 +49,7 @@ fn default_error_strategy() -> ErrorStrategy {
 
 impl ProcessorConfig for RuleConfig {
     fn from_stage_config(config: &StageConfig) -> Result<Self> {
-        let rules = extract_param::<Vec<Rule>>(&config.parameters, "rules", vec![]);
+        let mut rules = extract_param::<Vec<Rule>>(&config.parameters, "rules", vec![]);
         if rules.is_empty() {
             return Err(anyhow!("rule_transformer requires at least one rule"));
         }
@@ -50,10 +63,17 @@ impl ProcessorConfig for RuleConfig {
         // Extract timing configuration
         let timing_config = config.timing.clone();
 
+        let mut next_state_id = 0;
+        for rule in &mut rules {
+            rule.condition.assign_state_ids(&mut next_state_id);
+        }
+
         Ok(Self {
             rules,
             error_strategy,
             timing : timing_config,
+            side_outputs: config.side_outputs.clone().unwrap_or_default(),
+            key_field: extract_param(&config.parameters, "key_field", None::<String>),
         })
     }
     fn validate(&self) -> Result<()> {
@@ -62,20 +82,14 @@ impl ProcessorConfig for RuleConfig {
         }
 
         for (i, rule) in self.rules.iter().enumerate() {
-            if rule.condition.field_path.is_empty() {
-                return Err(anyhow!("Rule {} has empty field_path", i));
-            }
-            if rule.condition.operation.is_empty() {
-                return Err(anyhow!("Rule {} has empty operation", i));
-            }
-
-            // Validate operation is supported
-            if ConditionOperation::from_str(&rule.condition.operation).is_none() {
-                return Err(anyhow!(
-                    "Rule {} has unsupported operation: '{}'",
-                    i,
-                    rule.condition.operation
-                ));
+            // Silence is driven by the absence of messages, so it cannot be
+            // combined with per-message conditions
+            if !matches!(rule.condition, Condition::Silence { .. }) {
+                RuleConfig::validate_condition(&rule.condition, &format!("Rule {}", i))?;
+            } else if let Condition::Silence { silence } = &rule.condition
+                && silence.gap_ms == 0
+            {
+                return Err(anyhow!("Rule {}: silence gap_ms must be greater than 0", i));
             }
 
             if rule.actions.is_empty() {
@@ -84,12 +98,12 @@ impl ProcessorConfig for RuleConfig {
 
             // Validate actions
             for (j, action) in rule.actions.iter().enumerate() {
-                RuleConfig::validate_action(action, &format!("Rule {}, Action {}", i, j))?;
+                self.validate_action(action, &format!("Rule {}, Action {}", i, j))?;
             }
 
             // Validate else_actions
             for (j, action) in rule.else_actions.iter().enumerate() {
-                RuleConfig::validate_action(action, &format!("Rule {}, Else Action {}", i, j))?;
+                self.validate_action(action, &format!("Rule {}, Else Action {}", i, j))?;
             }
         }
 
@@ -98,7 +112,97 @@ impl ProcessorConfig for RuleConfig {
 }
 
 impl RuleConfig {
-    fn validate_action(action: &Action, context: &str) -> Result<()> {
+    fn validate_condition(condition: &Condition, context: &str) -> Result<()> {
+        match condition {
+            Condition::All { all: conditions } | Condition::Any { any: conditions } => {
+                if conditions.is_empty() {
+                    return Err(anyhow!("{}: all/any requires at least one condition", context));
+                }
+                for condition in conditions {
+                    RuleConfig::validate_condition(condition, context)?;
+                }
+            }
+            Condition::Not { not } => RuleConfig::validate_condition(not, context)?,
+            Condition::Count { count } => {
+                if count.at_least == 0 {
+                    return Err(anyhow!("{}: count at_least must be greater than 0", context));
+                }
+                if count.window_ms == Some(0) {
+                    return Err(anyhow!("{}: count window_ms must be greater than 0", context));
+                }
+                RuleConfig::validate_condition(&count.condition, context)?;
+            }
+            Condition::Consecutive { consecutive } => {
+                if consecutive.n == 0 {
+                    return Err(anyhow!("{}: consecutive n must be greater than 0", context));
+                }
+                RuleConfig::validate_condition(&consecutive.condition, context)?;
+            }
+            Condition::Rate { rate } => {
+                if rate.field_path.is_empty() {
+                    return Err(anyhow!("{}: rate has empty field_path", context));
+                }
+                if rate.window_ms == 0 {
+                    return Err(anyhow!("{}: rate window_ms must be greater than 0", context));
+                }
+                if !matches!(
+                    ConditionOperation::parse(&rate.operation),
+                    Some(
+                        ConditionOperation::GreaterThan
+                            | ConditionOperation::GreaterThanOrEqual
+                            | ConditionOperation::LessThan
+                            | ConditionOperation::LessThanOrEqual
+                    )
+                ) || !rate.value.is_number()
+                {
+                    return Err(anyhow!(
+                        "{}: rate requires a numeric comparison (>, >=, <, <=) against a number",
+                        context
+                    ));
+                }
+            }
+            Condition::Silence { .. } => {
+                return Err(anyhow!(
+                    "{}: silence can only be used as a rule's top-level condition",
+                    context
+                ));
+            }
+            Condition::Field(field) => {
+                if field.field_path.is_empty() {
+                    return Err(anyhow!("{} has empty field_path", context));
+                }
+                if field.operation.is_empty() {
+                    return Err(anyhow!("{} has empty operation", context));
+                }
+
+                // Validate operation is supported
+                match ConditionOperation::parse(&field.operation) {
+                    None => {
+                        return Err(anyhow!(
+                            "{} has unsupported operation: '{}'",
+                            context,
+                            field.operation
+                        ));
+                    }
+                    Some(ConditionOperation::In) if !field.value.is_array() => {
+                        return Err(anyhow!("{}: 'in' requires a list value", context));
+                    }
+                    Some(ConditionOperation::Matches) => {
+                        let pattern = field.value.as_str().ok_or_else(|| {
+                            anyhow!("{}: 'matches' requires a string pattern", context)
+                        })?;
+                        Regex::new(pattern).map_err(|e| {
+                            anyhow!("{}: invalid regex '{}': {}", context, pattern, e)
+                        })?;
+                    }
+                    Some(_) => {}
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn validate_action(&self, action: &Action, context: &str) -> Result<()> {
         match action {
             Action::SetField { field_path, .. } => {
                 if field_path.is_empty() {
@@ -154,7 +258,15 @@ impl RuleConfig {
                 if expression.is_empty() {
                     return Err(anyhow!("{}: ComputeField has empty expression", context));
                 }
-                // Could add expression syntax validation here using evalexpr
+                let processed = RuleProcessor::preprocess_expression(expression);
+                if let Err(e) = evalexpr::build_operator_tree::<evalexpr::DefaultNumericTypes>(&processed) {
+                    return Err(anyhow!(
+                        "{}: ComputeField expression '{}' is invalid: {}",
+                        context,
+                        expression,
+                        e
+                    ));
+                }
             }
             Action::KeepOnlyFields { field_paths } => {
                 // Empty field_paths is valid - it means "keep no fields" (clear everything)
@@ -167,6 +279,16 @@ impl RuleConfig {
                     }
                 }
             }
+            Action::RouteTo { output } | Action::EmitMessage { output, .. } => {
+                if !self.side_outputs.contains(output) {
+                    return Err(anyhow!(
+                        "{}: '{}' is not a side output of this stage (declared: {:?})",
+                        context,
+                        output,
+                        self.side_outputs
+                    ));
+                }
+            }
             Action::DropMessage | Action::PassThrough => {
                 // These actions have no parameters to validate
             }
@@ -183,13 +305,141 @@ pub struct Rule {
     pub else_actions: Vec<Action>,
 }
 
+/// A rule condition: either a single field test or a boolean combination of
+/// nested conditions (`all`, `any`, `not`).
+///
+/// ```toml
+/// [pipelines.alerts.stages.classify.parameters.rules.condition]
+/// all = [
+///     { field_path = "status", operation = "in", value = ["alarm", "fault"] },
+///     { not = { field_path = "maintenance", operation = "exists" } },
+///     { any = [{ field_path = "device", operation = "matches", value = "^esp32-" }, { field_path = "temperature", operation = ">", value = 80.0 }] },
+/// ]
+/// ```
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum Condition {
+    All { all: Vec<Condition> },
+    Any { any: Vec<Condition> },
+    Not { not: Box<Condition> },
+    Count { count: CountCondition },
+    Consecutive { consecutive: ConsecutiveCondition },
+    Rate { rate: RateCondition },
+    Silence { silence: SilenceCondition },
+    Field(FieldCondition),
+}
+
+/// True once `condition` has matched at least `at_least` times (within
+/// `window_ms`, if given) for the current key.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CountCondition {
+    pub condition: Box<Condition>,
+    pub at_least: usize,
+    pub window_ms: Option<u64>,
+    /// Start counting afresh after the condition fires
+    #[serde(default)]
+    pub reset_on_trigger: bool,
+    #[serde(skip)]
+    state_id: usize,
+}
+
+/// True when `condition` has matched for the last `n` messages of the current key.
 #[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct Condition {
+pub struct ConsecutiveCondition {
+    pub condition: Box<Condition>,
+    pub n: u64,
+    #[serde(skip)]
+    state_id: usize,
+}
+
+/// Compares the rate of change (units per second) of a numeric field over the
+/// last `window_ms` against `value`, e.g. "temperature rising faster than 0.5/s".
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RateCondition {
+    pub field_path: String,
+    pub window_ms: u64,
+    pub operation: String,
+    pub value: Value,
+    #[serde(skip)]
+    state_id: usize,
+}
+
+/// Fires when no message has arrived for a key for `gap_ms`. Only valid as a
+/// rule's top-level condition; the rule's actions are applied to a copy of the
+/// key's last message, once per silent period.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SilenceCondition {
+    pub gap_ms: u64,
+}
+
+/// Per-key state backing a stateful condition
+#[derive(Debug)]
+enum ConditionState {
+    Count(VecDeque<SystemTime>),
+    Consecutive(u64),
+    Rate(VecDeque<(SystemTime, f64)>),
+}
+
+/// Partition key and event time used when evaluating stateful conditions
+struct ConditionScope {
+    key: String,
+    time: SystemTime,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct FieldCondition {
     pub field_path: String,
     pub operation: String,
+    /// Comparison value; unused by `exists`/`not_exists`, a list for `in`,
+    /// and a regular expression for `matches`
+    #[serde(default)]
     pub value: Value,
 }
 
+impl Condition {
+    /// Give every stateful node in this tree a unique state slot
+    fn assign_state_ids(&mut self, next_id: &mut usize) {
+        let mut take = || {
+            *next_id += 1;
+            *next_id
+        };
+        match self {
+            Condition::All { all: conditions } | Condition::Any { any: conditions } => {
+                for condition in conditions {
+                    condition.assign_state_ids(next_id);
+                }
+            }
+            Condition::Not { not } => not.assign_state_ids(next_id),
+            Condition::Count { count } => {
+                count.state_id = take();
+                count.condition.assign_state_ids(next_id);
+            }
+            Condition::Consecutive { consecutive } => {
+                consecutive.state_id = take();
+                consecutive.condition.assign_state_ids(next_id);
+            }
+            Condition::Rate { rate } => rate.state_id = take(),
+            Condition::Silence { .. } | Condition::Field(_) => {}
+        }
+    }
+
+    /// Visit every field-level test in this condition tree
+    fn for_each_field<'a>(&'a self, visit: &mut impl FnMut(&'a FieldCondition)) {
+        match self {
+            Condition::All { all: conditions } | Condition::Any { any: conditions } => {
+                for condition in conditions {
+                    condition.for_each_field(visit);
+                }
+            }
+            Condition::Not { not } => not.for_each_field(visit),
+            Condition::Count { count } => count.condition.for_each_field(visit),
+            Condition::Consecutive { consecutive } => consecutive.condition.for_each_field(visit),
+            Condition::Rate { .. } | Condition::Silence { .. } => {}
+            Condition::Field(field) => visit(field),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(tag = "type")]
 pub enum Action {
@@ -218,6 +468,19 @@ pub enum Action {
     PassThrough,
     #[serde(rename = "keep_only_fields")]
     KeepOnlyFields { field_paths: Vec<String> },
+    /// Send a copy of the message (as transformed so far) to a side output
+    #[serde(rename = "route_to")]
+    RouteTo { output: String },
+    /// Send a new message to a side output, built from a literal payload plus
+    /// fields copied from the current message
+    #[serde(rename = "emit_message")]
+    EmitMessage {
+        output: String,
+        #[serde(default)]
+        payload: Value,
+        #[serde(default)]
+        copy_fields: Vec<String>,
+    },
 }
 
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
@@ -234,7 +497,10 @@ impl Action {
         match self {
             Action::KeepOnlyFields { .. } => ActionPriority::Reset,
             Action::ComputeField { .. } => ActionPriority::Transform, // Will be handled specially
-            Action::DropMessage | Action::PassThrough => ActionPriority::Control,
+            Action::DropMessage
+            | Action::PassThrough
+            | Action::RouteTo { .. }
+            | Action::EmitMessage { .. } => ActionPriority::Control,
             _ => ActionPriority::Transform,
         }
     }
@@ -248,34 +514,237 @@ pub struct RuleProcessor {
     name: String,
     config: RuleConfig,
     timing: TimingMixin,
+    /// `matches` patterns compiled once at construction
+    regexes: HashMap<String, Regex>,
+    /// State for stateful conditions, keyed by (state slot, partition key)
+    condition_state: Mutex<HashMap<(usize, String), ConditionState>>,
+    /// Last message per key, for silence rules
+    last_seen: StateStore<String, LastSeen>,
+}
+
+/// The last message of a key and the silence rules it has fired.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct LastSeen {
+    message: Message,
+    arrived: SystemTime,
+    /// Indices of the silence rules that have already fired for this key
+    fired: Vec<usize>,
 }
 
 impl RuleProcessor {
+    pub const METADATA: ProcessorMetadata = ProcessorMetadata {
+        name: "rule",
+        description: "Applies conditional transformations and filtering",
+        parameters: &[
+            ParamSpec::new("rules", ParamType::Array, "Rules applied to each message, in order"),
+            ParamSpec::new("error_strategy", ParamType::Choice(&["continue", "skip", "abort", "use_default"]), "What to do when an action fails"),
+            ParamSpec::new("key_field", ParamType::String, "Field whose value keeps separate state for stateful conditions"),
+        ],
+        shared: &[],
+    };
+
     pub fn new(name: &str, config: StageConfig) -> Result<Box<dyn Processor>> {
         let processor_config = RuleConfig::from_stage_config(&config)?;
         processor_config.validate()?;
 
         // Create timing mixin from processor configuration
         let timing = TimingMixin::new(processor_config.timing.as_ref());
+        let regexes = Self::compile_regexes(&processor_config)?;
 
         Ok(Box::new(Self {
             name: name.to_string(),
             config: processor_config,
             timing,
+            regexes,
+            condition_state: Mutex::new(HashMap::new()),
+            last_seen: StateStore::open(name, config.state.as_ref())?,
         }))
     }
 
-    fn evaluate_condition(&self, payload: &Value, condition: &Condition) -> bool {
-        let field_value = match FieldUtils::extract_field_value(payload, &condition.field_path) {
-            Some(value) => value,
-            None => {
-                debug!("Field '{}' not found in payload", condition.field_path);
-                return false;
+    fn compile_regexes(config: &RuleConfig) -> Result<HashMap<String, Regex>> {
+        let mut patterns = Vec::new();
+        for rule in &config.rules {
+            rule.condition.for_each_field(&mut |field| {
+                if field.operation == ConditionOperation::Matches.as_str()
+                    && let Some(pattern) = field.value.as_str()
+                {
+                    patterns.push(pattern.to_string());
+                }
+            });
+        }
+
+        let mut regexes = HashMap::new();
+        for pattern in patterns {
+            let regex = Regex::new(&pattern)?;
+            regexes.insert(pattern, regex);
+        }
+        Ok(regexes)
+    }
+
+    /// Evaluate a condition tree. Stateful conditions only update their state
+    /// when they are actually reached, so `all`/`any` short-circuiting applies.
+    fn evaluate_condition(&self, payload: &Value, condition: &Condition, scope: &ConditionScope) -> bool {
+        match condition {
+            Condition::All { all } => all.iter().all(|c| self.evaluate_condition(payload, c, scope)),
+            Condition::Any { any } => any.iter().any(|c| self.evaluate_condition(payload, c, scope)),
+            Condition::Not { not } => !self.evaluate_condition(payload, not, scope),
+            Condition::Count { count } => {
+                let matched = self.evaluate_condition(payload, &count.condition, scope);
+                self.update_count(count, matched, scope)
+            }
+            Condition::Consecutive { consecutive } => {
+                let matched = self.evaluate_condition(payload, &consecutive.condition, scope);
+                let mut states = self.condition_state.lock().unwrap();
+                let state = states
+                    .entry((consecutive.state_id, scope.key.clone()))
+                    .or_insert(ConditionState::Consecutive(0));
+                let ConditionState::Consecutive(run) = state else {
+                    return false;
+                };
+                *run = if matched { *run + 1 } else { 0 };
+                *run >= consecutive.n
+            }
+            Condition::Rate { rate } => self.evaluate_rate(payload, rate, scope),
+            // Silence rules are evaluated on a timer, never per message
+            Condition::Silence { .. } => false,
+            Condition::Field(field) => self.evaluate_field_condition(payload, field),
+        }
+    }
+
+    fn update_count(&self, count: &CountCondition, matched: bool, scope: &ConditionScope) -> bool {
+        let mut states = self.condition_state.lock().unwrap();
+        let state = states
+            .entry((count.state_id, scope.key.clone()))
+            .or_insert_with(|| ConditionState::Count(VecDeque::new()));
+        let ConditionState::Count(hits) = state else {
+            return false;
+        };
+
+        if matched {
+            hits.push_back(scope.time);
+        }
+        if let Some(window_ms) = count.window_ms {
+            let horizon = scope.time - Duration::from_millis(window_ms);
+            while hits.front().is_some_and(|t| *t < horizon) {
+                hits.pop_front();
             }
+        }
+        // Only the most recent `at_least` hits can matter
+        while hits.len() > count.at_least {
+            hits.pop_front();
+        }
+
+        let triggered = hits.len() >= count.at_least;
+        if triggered && count.reset_on_trigger {
+            hits.clear();
+        }
+        triggered
+    }
+
+    fn evaluate_rate(&self, payload: &Value, rate: &RateCondition, scope: &ConditionScope) -> bool {
+        let Some(value) = FieldUtils::extract_f64(payload, &rate.field_path) else {
+            return false;
+        };
+
+        let mut states = self.condition_state.lock().unwrap();
+        let state = states
+            .entry((rate.state_id, scope.key.clone()))
+            .or_insert_with(|| ConditionState::Rate(VecDeque::new()));
+        let ConditionState::Rate(samples) = state else {
+            return false;
         };
 
+        samples.push_back((scope.time, value));
+        let horizon = scope.time - Duration::from_millis(rate.window_ms);
+        while samples.front().is_some_and(|(t, _)| *t < horizon) {
+            samples.pop_front();
+        }
+
+        let (Some((t0, v0)), Some((t1, v1))) = (samples.front(), samples.back()) else {
+            return false;
+        };
+        let elapsed = t1.duration_since(*t0).unwrap_or(Duration::ZERO).as_secs_f64();
+        if elapsed <= 0.0 {
+            return false;
+        }
+
+        let per_second = (v1 - v0) / elapsed;
+        match (ConditionOperation::parse(&rate.operation), Number::from_f64(per_second)) {
+            (Some(operation), Some(per_second)) => {
+                ConditionEvaluator::evaluate_condition(&Value::Number(per_second), &operation, &rate.value)
+            }
+            _ => false,
+        }
+    }
+
+    /// Apply silence rules to keys that have gone quiet, returning the
+    /// messages to forward and any side output messages.
+    fn check_silence(&mut self, side_messages: &mut Vec<(String, Message)>) -> Vec<Message> {
+        let mut outputs = Vec::new();
+
+        for (index, rule) in self.config.rules.iter().enumerate() {
+            let Condition::Silence { silence } = &rule.condition else {
+                continue;
+            };
+            let gap = Duration::from_millis(silence.gap_ms);
+
+            let silent: Vec<String> = self
+                .last_seen
+                .iter()
+                .filter(|(_, seen)| {
+                    seen.arrived.elapsed().is_ok_and(|elapsed| elapsed >= gap) && !seen.fired.contains(&index)
+                })
+                .map(|(key, _)| key.clone())
+                .collect();
+
+            for key in silent {
+                let Some(seen) = self.last_seen.get_mut(&key) else {
+                    continue;
+                };
+                seen.fired.push(index);
+                debug!("Silence rule {} fired for key '{}'", index, key);
+
+                let mut message = seen.message.clone();
+                if let Err(e) = self.execute_actions(&mut message.payload, &rule.actions) {
+                    error!("Failed to execute silence actions: {}", e);
+                }
+                self.collect_side_messages(&message, &rule.actions, side_messages);
+
+                if !rule.actions.iter().any(|a| matches!(a, Action::DropMessage)) {
+                    message.source = self.name.clone();
+                    outputs.push(message);
+                }
+            }
+        }
+
+        outputs
+    }
+
+    /// Record a message's arrival for silence rules
+    fn track_arrival(&mut self, message: &Message) {
+        if !self
+            .config
+            .rules
+            .iter()
+            .any(|rule| matches!(rule.condition, Condition::Silence { .. }))
+        {
+            return;
+        }
+
+        let key = FieldUtils::extract_key(&message.payload, self.config.key_field.as_deref());
+        let seen = LastSeen {
+            message: message.clone(),
+            arrived: SystemTime::now(),
+            fired: Vec::new(),
+        };
+        self.last_seen.insert(key, seen);
+    }
+
+    fn evaluate_field_condition(&self, payload: &Value, condition: &FieldCondition) -> bool {
+        let field_value = FieldUtils::extract_field_value(payload, &condition.field_path);
+
         // Parse the operation string to ConditionOperation enum
-        let operation = match ConditionOperation::from_str(&condition.operation) {
+        let operation = match ConditionOperation::parse(&condition.operation) {
             Some(op) => op,
             None => {
                 warn!("Unknown condition operation: {}", condition.operation);
@@ -283,8 +752,19 @@ impl RuleProcessor {
             }
         };
 
+        if field_value.is_none() {
+            debug!("Field '{}' not found in payload", condition.field_path);
+        }
+
+        // Use precompiled regexes rather than compiling per message
+        if operation == ConditionOperation::Matches
+            && let Some(regex) = condition.value.as_str().and_then(|p| self.regexes.get(p))
+        {
+            return field_value.is_some_and(|value| ConditionEvaluator::matches_regex(value, regex));
+        }
+
         // Use ConditionEvaluator to evaluate the condition
-        ConditionEvaluator::evaluate_condition(field_value, &operation, &condition.value)
+        ConditionEvaluator::evaluate_optional(field_value, &operation, &condition.value)
     }
 
     fn execute_action(&self, payload: &mut Value, action: &Action) -> Result<()> {
@@ -337,11 +817,7 @@ impl RuleProcessor {
                     field_path, expression
                 );
                 match self.evaluate_expression(payload, expression) {
-                    Ok(result) => FieldUtils::set_field_value(
-                        payload,
-                        field_path,
-                        Value::Number(Number::from_f64(result).unwrap_or(Number::from(0))),
-                    ),
+                    Ok(result) => FieldUtils::set_field_value(payload, field_path, result),
                     Err(e) => {
                         return self.handle_action_error(e, action);
                     }
@@ -359,6 +835,10 @@ impl RuleProcessor {
                 debug!("KeepOnlyFields action - handled in execute_actions");
                 Ok(())
             }
+            Action::RouteTo { .. } | Action::EmitMessage { .. } => {
+                debug!("Side output action - handled in process_message");
+                Ok(())
+            }
         };
 
         match result {
@@ -417,16 +897,16 @@ impl RuleProcessor {
         Ok(())
     }
 
-    fn build_expression_context(&self, payload: &Value) -> evalexpr::HashMapContext {
+    /// Expose the payload's fields (nested ones by dotted path) as expression variables
+    pub(crate) fn build_expression_context(payload: &Value) -> evalexpr::HashMapContext {
         use evalexpr::HashMapContext;
 
         let mut context = HashMapContext::new();
-        self.add_payload_to_context(payload, "", &mut context);
+        Self::add_payload_to_context(payload, "", &mut context);
         context
     }
 
-    fn add_payload_to_context(
-        &self,
+    pub(crate) fn add_payload_to_context(
         value: &Value,
         prefix: &str,
         context: &mut evalexpr::HashMapContext,
@@ -455,7 +935,7 @@ impl RuleProcessor {
                     } else {
                         format!("{}.{}", prefix, key)
                     };
-                    self.add_payload_to_context(val, &field_path, context);
+                    Self::add_payload_to_context(val, &field_path, context);
                 }
             }
             Value::Array(arr) => {
@@ -465,7 +945,7 @@ impl RuleProcessor {
                     } else {
                         format!("{}[{}]", prefix, index)
                     };
-                    self.add_payload_to_context(val, &field_path, context);
+                    Self::add_payload_to_context(val, &field_path, context);
                 }
             }
             Value::Null => {
@@ -474,13 +954,8 @@ impl RuleProcessor {
         }
     }
 
-    fn evaluate_expression(&self, payload: &Value, expression: &str) -> Result<f64> {
-        debug!("Evaluating expression: '{}'", expression);
-
-        // Build context with all payload fields
-        let context = self.build_expression_context(payload);
-
-        // Transform common math functions to their namespaced versions for compatibility
+    /// Rewrite bare math function names to their evalexpr namespaced versions
+    pub(crate) fn preprocess_expression(expression: &str) -> String {
         let mut processed_expression = expression.to_string();
 
         let math_functions = [
@@ -505,13 +980,29 @@ impl RuleProcessor {
             }
         }
 
+        processed_expression
+    }
+
+    /// Evaluate an expression against the payload.
+    ///
+    /// Results keep their type: numbers, strings (e.g. `"id-" + device`),
+    /// booleans (e.g. `temperature > 30`), and conditionals
+    /// (`if(temperature > 30, "hot", "ok")`) all map onto the equivalent JSON
+    /// value. Non-finite numbers are reported as errors rather than coerced.
+    fn evaluate_expression(&self, payload: &Value, expression: &str) -> Result<Value> {
+        debug!("Evaluating expression: '{}'", expression);
+
+        // Build context with all payload fields
+        let context = Self::build_expression_context(payload);
+
+        let processed_expression = Self::preprocess_expression(expression);
         debug!("Processed expression: '{}'", processed_expression);
 
         // Evaluate using context
-        match evalexpr::eval_float_with_context(&processed_expression, &context) {
+        match evalexpr::eval_with_context(&processed_expression, &context) {
             Ok(result) => {
-                debug!("Expression '{}' evaluated to: {}", expression, result);
-                Ok(result)
+                debug!("Expression '{}' evaluated to: {:?}", expression, result);
+                Self::expression_value_to_json(result)
             }
             Err(e) => {
                 error!("Failed to evaluate expression '{}': {}", expression, e);
@@ -520,16 +1011,84 @@ impl RuleProcessor {
         }
     }
 
-    fn process_message(&self, mut message: Message) -> Result<Option<Message>> {
+    pub(crate) fn expression_value_to_json(value: evalexpr::Value) -> Result<Value> {
+        use evalexpr::Value as EvalValue;
+
+        match value {
+            EvalValue::Float(f) => Number::from_f64(f)
+                .map(Value::Number)
+                .ok_or_else(|| anyhow!("Expression produced a non-finite number ({})", f)),
+            EvalValue::Int(i) => Ok(Value::from(i)),
+            EvalValue::Boolean(b) => Ok(Value::Bool(b)),
+            EvalValue::String(s) => Ok(Value::String(s)),
+            EvalValue::Tuple(values) => values
+                .into_iter()
+                .map(Self::expression_value_to_json)
+                .collect::<Result<Vec<_>>>()
+                .map(Value::Array),
+            EvalValue::Empty => Ok(Value::Null),
+        }
+    }
+
+    /// Build the side output messages requested by a rule's actions
+    fn collect_side_messages(
+        &self,
+        message: &Message,
+        actions: &[Action],
+        side_messages: &mut Vec<(String, Message)>,
+    ) {
+        for action in actions {
+            match action {
+                Action::RouteTo { output } => {
+                    let mut copy = message.clone();
+                    copy.source = self.name.clone();
+                    side_messages.push((output.clone(), copy));
+                }
+                Action::EmitMessage {
+                    output,
+                    payload,
+                    copy_fields,
+                } => {
+                    let mut payload = match payload {
+                        Value::Null => Value::Object(serde_json::Map::new()),
+                        payload => payload.clone(),
+                    };
+                    for field in copy_fields {
+                        if let Some(value) = FieldUtils::extract_field_value(&message.payload, field)
+                            && let Err(e) = FieldUtils::set_field_value(&mut payload, field, value.clone())
+                        {
+                            warn!("Failed to copy field '{}' into emitted message: {}", field, e);
+                        }
+                    }
+                    let emitted = TimingHelpers::propagate_timing(message, &self.name, output, payload);
+                    side_messages.push((output.clone(), emitted));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Apply the rules to a message. Messages destined for side outputs are
+    /// appended to `side_messages` as `(output, message)` pairs.
+    fn process_message(
+        &self,
+        mut message: Message,
+        side_messages: &mut Vec<(String, Message)>,
+    ) -> Result<Option<Message>> {
         let mut should_drop = false;
+        let scope = ConditionScope {
+            key: FieldUtils::extract_key(&message.payload, self.config.key_field.as_deref()),
+            time: message.timing.event_time,
+        };
 
         for rule in &self.config.rules {
-            if self.evaluate_condition(&message.payload, &rule.condition) {
+            if self.evaluate_condition(&message.payload, &rule.condition, &scope) {
                 debug!("Rule condition matched for message from {}", message.source);
 
                 if let Err(e) = self.execute_actions(&mut message.payload, &rule.actions) {
                     error!("Failed to execute actions: {}", e);
                 }
+                self.collect_side_messages(&message, &rule.actions, side_messages);
 
                 // Check if any action was a drop message
                 for action in &rule.actions {
@@ -547,6 +1106,7 @@ impl RuleProcessor {
                 if let Err(e) = self.execute_actions(&mut message.payload, &rule.else_actions) {
                     error!("Failed to execute else_actions: {}", e);
                 }
+                self.collect_side_messages(&message, &rule.else_actions, side_messages);
 
                 // Check if any else_action was a drop message
                 for action in &rule.else_actions {
@@ -588,15 +1148,12 @@ impl RuleProcessor {
                     );
                     match self.evaluate_expression(payload, expression) {
                         Ok(result) => {
-                            computed_values.insert(field_path.clone(), result);
                             debug!("Pre-computed '{}' = {}", field_path, result);
+                            computed_values.insert(field_path.clone(), result);
                         }
                         Err(e) => {
-                            error!(
-                                "Failed to pre-compute field '{}' with expression '{}': {}",
-                                field_path, expression, e
-                            );
-                            computed_values.insert(field_path.clone(), 0.0); // Fallback value
+                            // Leave the field untouched rather than writing a fallback value
+                            self.handle_action_error(e, action)?;
                         }
                     }
                 }
@@ -616,18 +1173,12 @@ impl RuleProcessor {
             match action {
                 Action::ComputeField { field_path, .. } => {
                     // Use pre-computed value instead of re-evaluating
-                    if let Some(computed_value) = computed_values.get(field_path) {
+                    if let Some(computed_value) = computed_values.remove(field_path) {
                         debug!(
                             "Setting pre-computed field '{}' to {}",
                             field_path, computed_value
                         );
-                        FieldUtils::set_field_value(
-                            payload,
-                            field_path,
-                            Value::Number(
-                                Number::from_f64(*computed_value).unwrap_or(Number::from(0)),
-                            ),
-                        )?;
+                        FieldUtils::set_field_value(payload, field_path, computed_value)?;
                     }
                 }
                 Action::KeepOnlyFields { field_paths } => {
@@ -648,6 +1199,27 @@ impl RuleProcessor {
     }
 }
 
+impl RuleProcessor {
+    async fn publish_side_messages(
+        &mut self,
+        side_outputs: &HashMap<String, OutputInfo>,
+        side_messages: Vec<(String, Message)>,
+    ) {
+        for (side_output, mut side_message) in side_messages {
+            let Some(output_info) = side_outputs.get(&side_output) else {
+                tracing::warn!("Side output '{}' is not connected", side_output);
+                continue;
+            };
+            side_message.topic = output_info.name.clone();
+            let side_message = self.timing.update_message_watermark(side_message);
+
+            if let Err(e) = output_info.channel.publish(side_message).await {
+                tracing::warn!("Failed to publish to side output '{}': {:?}", side_output, e);
+            }
+        }
+    }
+}
+
 #[async_trait::async_trait]
 impl Processor for RuleProcessor {
     async fn init(&mut self) -> Result<()> {
@@ -661,50 +1233,64 @@ impl Processor for RuleProcessor {
 
     async fn process(&mut self, context: &mut ProcessingContext) -> Result<()> {
         // Process all input channels
-        for (channel_name, input) in context.inputs.iter_mut() {
-            select! {
-                message = input.recv() => {
-                    if let Some(message) = message {
-                        match self.process_message(message) {
-                            Ok(Some(transformed_message)) => {
-                                if let Some(output_info) = &context.output {
-                                    // Preserve timing information when forwarding
-                                    let output_message = Message {
-                                        source: transformed_message.source,
-                                        topic: output_info.name.clone(),
-                                        payload: transformed_message.payload,
-                                        timestamp: transformed_message.timestamp,
-                                        timing: transformed_message.timing,
-                                    };
-
-                                    // Update watermark using timing mixin
-                                    let output_message = self.timing.update_message_watermark(output_message);
-
-                                    if let Err(e) = output_info.channel.publish(output_message).await {
-                                        tracing::warn!("Failed to publish transformed message: {:?}", e);
-                                    } else {
-                                        tracing::debug!(
-                                            "Message from '{}' transformed and forwarded",
-                                            channel_name
-                                        );
-                                    }
-                                }
-                            }
-                            Ok(None) => {
-                                tracing::debug!("Message from '{}' was dropped by rule processor", channel_name);
-                            }
-                            Err(e) => {
-                                error!("Failed to transform message: {}", e);
-                            }
+        if let Some((channel_name, message)) = context.recv(tokio::time::Duration::from_millis(10)).await {
+            self.track_arrival(&message);
+
+            let mut side_messages = Vec::new();
+            let result = self.process_message(message, &mut side_messages);
+
+            self.publish_side_messages(&context.side_outputs, side_messages).await;
+
+            match result {
+                Ok(Some(transformed_message)) => {
+                    if let Some(output_info) = &context.output {
+                        // Preserve timing information when forwarding
+                        let output_message = Message {
+                            source: transformed_message.source,
+                            topic: output_info.name.clone(),
+                            payload: transformed_message.payload,
+                            timestamp: transformed_message.timestamp,
+                            timing: transformed_message.timing,
+                            metadata: transformed_message.metadata,
+                            batch: None,
+                        };
+
+                        // Update watermark using timing mixin
+                        let output_message = self.timing.update_message_watermark(output_message);
+
+                        if let Err(e) = output_info.channel.publish(output_message).await {
+                            tracing::warn!("Failed to publish transformed message: {:?}", e);
+                        } else {
+                            tracing::debug!(
+                                "Message from '{}' transformed and forwarded",
+                                channel_name
+                            );
                         }
                     }
                 }
-                _ = tokio::time::sleep(tokio::time::Duration::from_millis(10)) => {
-                    // Timeout - no messages received, continue processing
-                    break;
+                Ok(None) => {
+                    tracing::debug!("Message from '{}' was dropped by rule processor", channel_name);
+                }
+                Err(e) => {
+                    error!("Failed to transform message: {}", e);
+                }
+            }
+        }
+
+        // Fire silence rules for keys that have gone quiet
+        let mut side_messages = Vec::new();
+        for mut silence_message in self.check_silence(&mut side_messages) {
+            if let Some(output_info) = &context.output {
+                silence_message.topic = output_info.name.clone();
+                let silence_message = self.timing.update_message_watermark(silence_message);
+
+                if let Err(e) = output_info.channel.publish(silence_message).await {
+                    tracing::warn!("Failed to publish silence message: {:?}", e);
                 }
             }
         }
+        self.publish_side_messages(&context.side_outputs, side_messages).await;
+
         Ok(())
     }
 }
@@ -718,3 +1304,175 @@ impl WithTimingMixin for RuleProcessor {
         &mut self.timing
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn processor(rules: Value) -> RuleProcessor {
+        let stage_config = StageConfig {
+            r#type: "rule".to_string(),
+            side_outputs: Some(vec!["alerts".to_string()]),
+            parameters: Some(HashMap::from([("rules".to_string(), rules)])),
+            ..Default::default()
+        };
+        let config = RuleConfig::from_stage_config(&stage_config).unwrap();
+        config.validate().unwrap();
+        RuleProcessor {
+            name: "test".to_string(),
+            regexes: RuleProcessor::compile_regexes(&config).unwrap(),
+            config,
+            timing: TimingMixin::new(None),
+            condition_state: Mutex::new(HashMap::new()),
+            last_seen: StateStore::new(),
+        }
+    }
+
+    #[test]
+    fn test_compute_field_preserves_result_types() {
+        let processor = processor(json!([{
+            "condition": { "field_path": "device", "operation": "startswith", "value": "esp" },
+            "actions": [
+                { "type": "compute_field", "field_path": "label", "expression": "\"id-\" + device" },
+                { "type": "compute_field", "field_path": "hot", "expression": "temperature > 30" },
+                { "type": "compute_field", "field_path": "band", "expression": "if(temperature > 30, \"high\", \"normal\")" },
+                { "type": "compute_field", "field_path": "fahrenheit", "expression": "temperature * 9 / 5 + 32" }
+            ]
+        }]));
+
+        let message = Message::new("src", "topic", json!({"device": "esp32", "temperature": 35.0}));
+        let output = processor.process_message(message, &mut Vec::new()).unwrap().unwrap();
+
+        assert_eq!(output.payload["label"], json!("id-esp32"));
+        assert_eq!(output.payload["hot"], json!(true));
+        assert_eq!(output.payload["band"], json!("high"));
+        assert_eq!(output.payload["fahrenheit"], json!(95.0));
+    }
+
+    #[test]
+    fn test_failed_expression_does_not_write_fallback() {
+        let processor = processor(json!([{
+            "condition": { "field_path": "value", "operation": ">=", "value": 0 },
+            "actions": [
+                { "type": "compute_field", "field_path": "ratio", "expression": "missing / value" }
+            ]
+        }]));
+
+        let message = Message::new("src", "topic", json!({"value": 2.0}));
+        let output = processor.process_message(message, &mut Vec::new()).unwrap().unwrap();
+
+        assert!(output.payload.get("ratio").is_none());
+    }
+
+    #[test]
+    fn test_compound_conditions() {
+        let condition: Condition = toml::from_str::<toml::Value>(
+            r#"
+            all = [
+                { field_path = "status", operation = "in", value = ["alarm", "fault"] },
+                { not = { field_path = "maintenance", operation = "exists" } },
+                { any = [{ field_path = "device", operation = "matches", value = "^esp32-\\d+$" }, { field_path = "temperature", operation = ">", value = 80.0 }] },
+            ]
+            "#,
+        )
+        .and_then(|value| value.try_into())
+        .unwrap();
+
+        let processor = processor(json!([{
+            "condition": serde_json::to_value(&condition).unwrap(),
+            "actions": [{ "type": "set_field", "field_path": "alert", "value": true }]
+        }]));
+
+        let scope = ConditionScope {
+            key: String::new(),
+            time: SystemTime::now(),
+        };
+        let matches = |payload: Value| processor.evaluate_condition(&payload, &condition, &scope);
+
+        assert!(matches(json!({"status": "alarm", "device": "esp32-7"})));
+        assert!(matches(json!({"status": "fault", "device": "pi", "temperature": 90})));
+        assert!(!matches(json!({"status": "alarm", "device": "pi", "temperature": 20})));
+        assert!(!matches(json!({"status": "ok", "device": "esp32-7"})));
+        assert!(!matches(json!({"status": "alarm", "device": "esp32-7", "maintenance": true})));
+    }
+
+    #[test]
+    fn test_side_output_actions() {
+        let processor = processor(json!([{
+            "condition": { "field_path": "temperature", "operation": ">", "value": 80.0 },
+            "actions": [
+                { "type": "set_field", "field_path": "status", "value": "hot" },
+                { "type": "route_to", "output": "alerts" },
+                { "type": "emit_message", "output": "alerts", "payload": { "alert": "overheat" }, "copy_fields": ["device"] }
+            ]
+        }]));
+
+        let mut side_messages = Vec::new();
+        let message = Message::new("src", "topic", json!({"device": "esp32", "temperature": 95.0}));
+        let output = processor.process_message(message, &mut side_messages).unwrap().unwrap();
+
+        assert_eq!(output.payload["status"], json!("hot"));
+        assert_eq!(side_messages.len(), 2);
+        assert_eq!(side_messages[0].0, "alerts");
+        assert_eq!(side_messages[0].1.payload["status"], json!("hot"));
+        assert_eq!(side_messages[1].1.payload, json!({"alert": "overheat", "device": "esp32"}));
+    }
+
+    #[test]
+    fn test_stateful_conditions_are_per_key() {
+        let mut processor = processor(json!([
+            {
+                "condition": { "consecutive": { "n": 3, "condition": { "field_path": "temperature", "operation": ">", "value": 30.0 } } },
+                "actions": [{ "type": "set_field", "field_path": "sustained", "value": true }]
+            },
+            {
+                "condition": { "count": { "at_least": 2, "window_ms": 1000, "condition": { "field_path": "fault", "operation": "exists" } } },
+                "actions": [{ "type": "set_field", "field_path": "repeated_fault", "value": true }]
+            },
+            {
+                "condition": { "rate": { "field_path": "temperature", "window_ms": 10000, "operation": ">", "value": 1.0 } },
+                "actions": [{ "type": "set_field", "field_path": "rising", "value": true }]
+            }
+        ]));
+        processor.config.key_field = Some("device".to_string());
+
+        let start = SystemTime::now();
+        let run = |device: &str, offset_ms: u64, payload: Value| {
+            let mut payload = payload;
+            payload["device"] = json!(device);
+            let mut message = Message::new("src", "topic", payload);
+            message.timing.event_time = start + Duration::from_millis(offset_ms);
+            processor.process_message(message, &mut Vec::new()).unwrap().unwrap().payload
+        };
+
+        assert!(run("a", 0, json!({"temperature": 31.0})).get("sustained").is_none());
+        assert!(run("b", 0, json!({"temperature": 35.0})).get("sustained").is_none());
+        assert!(run("a", 1000, json!({"temperature": 32.0})).get("sustained").is_none());
+        let third = run("a", 2000, json!({"temperature": 33.0}));
+        assert_eq!(third["sustained"], json!(true));
+        // 2 degrees over 2 seconds is 1.0/s, which is not > 1.0
+        assert!(third.get("rising").is_none());
+        assert_eq!(run("a", 3000, json!({"temperature": 40.0}))["rising"], json!(true));
+
+        assert!(run("c", 0, json!({"fault": 1})).get("repeated_fault").is_none());
+        assert!(run("c", 5000, json!({"fault": 2})).get("repeated_fault").is_none());
+        assert_eq!(run("c", 5500, json!({"fault": 3}))["repeated_fault"], json!(true));
+    }
+
+    #[test]
+    fn test_silence_rule_fires_once_per_gap() {
+        let mut processor = processor(json!([{
+            "condition": { "silence": { "gap_ms": 1 } },
+            "actions": [{ "type": "set_field", "field_path": "offline", "value": true }]
+        }]));
+
+        processor.track_arrival(&Message::new("src", "topic", json!({"device": "a"})));
+        std::thread::sleep(Duration::from_millis(5));
+
+        let fired = processor.check_silence(&mut Vec::new());
+        assert_eq!(fired.len(), 1);
+        assert_eq!(fired[0].payload, json!({"device": "a", "offline": true}));
+        assert!(processor.check_silence(&mut Vec::new()).is_empty());
+    }
+}
@@ -0,0 +1,322 @@
+//! Scale Transform
+//!
+//! Converts raw sensor readings to engineering units with a linear
+//! `value * factor + offset`, optionally rounded to a number of decimal places.
+//! One `scale_factor` and `offset` apply to every mapped field; with
+//! `fields_in`/`fields_out`, `scale_factors` and `offsets` give each field its
+//! own, in the order of `fields_in`:
+//!
+//! ```toml
+//! parameters = { fields_in = ["temp_raw", "rh_raw"], fields_out = ["temp_c", "rh"],
+//!     scale_factors = [0.0625, 0.1], offsets = [-40.0, 0.0], precision = 2 }
+//! ```
+//!
+//! Fields that are missing or not numeric are left untouched. Batches are
+//! scaled a column at a time, with vectorised arithmetic.
+
+use crate::config::{
+    FIELD_PARAMS, FieldConfig, ParamSpec, ParamType, ProcessorConfig, ProcessorMetadata, StageConfig,
+    extract_field_params, extract_param,
+};
+use crate::core::timing_mixin::{TimingMixin, WithTimingMixin};
+use crate::core::{context::ProcessingContext, message::Message};
+use crate::processors::common::column;
+use crate::processors::common::field_utils::FieldUtils;
+use crate::processors::processor::Processor;
+use crate::error::LiminalError;
+
+use anyhow::{Result, anyhow};
+use serde_json::{Number, Value};
+use tracing::error;
+
+#[derive(Debug, Clone)]
+pub struct ScaleConfig {
+    pub field: FieldConfig,
+    pub scale_factor: f64,
+    pub offset: f64,
+    /// Factors of each of `fields_in`, overriding `scale_factor`
+    pub scale_factors: Option<Vec<f64>>,
+    /// Offsets of each of `fields_in`, overriding `offset`
+    pub offsets: Option<Vec<f64>>,
+    /// Decimal places results are rounded to
+    pub precision: Option<u32>,
+    pub timing: Option<crate::config::TimingConfig>,
+}
+
+impl ProcessorConfig for ScaleConfig {
+    fn from_stage_config(config: &StageConfig) -> Result<Self> {
+        let config = Self {
+            field: extract_field_params(&config.parameters),
+            scale_factor: extract_param(&config.parameters, "scale_factor", 1.0),
+            offset: extract_param(&config.parameters, "offset", 0.0),
+            scale_factors: extract_param(&config.parameters, "scale_factors", None::<Vec<f64>>),
+            offsets: extract_param(&config.parameters, "offsets", None::<Vec<f64>>),
+            precision: extract_param(&config.parameters, "precision", None::<u32>),
+            timing: config.timing.clone(),
+        };
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<()> {
+        match &self.field {
+            FieldConfig::Single { .. } | FieldConfig::Multiple { .. } | FieldConfig::Mapping(_) => {}
+            _ => {
+                return Err(anyhow!(
+                    "scale processor requires field_in/field_out, fields_in/fields_out or field_mapping"
+                ));
+            }
+        }
+        self.field.validate()?;
+
+        for (name, values) in [("scale_factors", &self.scale_factors), ("offsets", &self.offsets)] {
+            let Some(values) = values else {
+                continue;
+            };
+            let FieldConfig::Multiple { inputs, .. } = &self.field else {
+                return Err(anyhow!("{} requires fields_in and fields_out", name));
+            };
+            if values.len() != inputs.len() {
+                return Err(anyhow!(
+                    "{} has {} values but fields_in has {} fields",
+                    name,
+                    values.len(),
+                    inputs.len()
+                ));
+            }
+        }
+
+        let mut coefficients = [self.scale_factor, self.offset]
+            .into_iter()
+            .chain(self.scale_factors.iter().chain(&self.offsets).flatten().copied());
+        if coefficients.any(|c| !c.is_finite()) {
+            return Err(anyhow!("scale factors and offsets must be finite numbers"));
+        }
+        if self.precision.is_some_and(|precision| precision > 15) {
+            return Err(LiminalError::invalid_parameter("precision", "cannot exceed 15 decimal places").into());
+        }
+        Ok(())
+    }
+}
+
+/// One field conversion: input field, output field, factor and offset.
+type Conversion = (String, String, f64, f64);
+
+pub struct ScaleProcessor {
+    name: String,
+    config: ScaleConfig,
+    conversions: Vec<Conversion>,
+    timing: TimingMixin,
+}
+
+impl ScaleProcessor {
+    pub const METADATA: ProcessorMetadata = ProcessorMetadata {
+        name: "scale",
+        description: "Scales and offsets numeric fields",
+        parameters: &[
+            ParamSpec::new("scale_factor", ParamType::Number, "Multiplier applied to each field"),
+            ParamSpec::new("offset", ParamType::Number, "Added to each field after scaling"),
+            ParamSpec::new("scale_factors", ParamType::Array, "Multipliers of each of fields_in"),
+            ParamSpec::new("offsets", ParamType::Array, "Offsets of each of fields_in"),
+            ParamSpec::new("precision", ParamType::Integer, "Decimal places results are rounded to"),
+        ],
+        shared: &[FIELD_PARAMS],
+    };
+
+    pub fn new(name: &str, config: StageConfig) -> Result<Box<dyn Processor>> {
+        let processor_config = ScaleConfig::from_stage_config(&config)?;
+        let timing = TimingMixin::new(processor_config.timing.as_ref());
+
+        let conversions = match &processor_config.field {
+            FieldConfig::Single { input, output } => {
+                vec![(input.clone(), output.clone(), processor_config.scale_factor, processor_config.offset)]
+            }
+            FieldConfig::Multiple { inputs, outputs } => inputs
+                .iter()
+                .zip(outputs)
+                .enumerate()
+                .map(|(i, (input, output))| {
+                    let factor = processor_config.scale_factors.as_ref().map_or(processor_config.scale_factor, |f| f[i]);
+                    let offset = processor_config.offsets.as_ref().map_or(processor_config.offset, |o| o[i]);
+                    (input.clone(), output.clone(), factor, offset)
+                })
+                .collect(),
+            FieldConfig::Mapping(mapping) => {
+                let mut conversions: Vec<Conversion> = mapping
+                    .iter()
+                    .map(|(input, output)| {
+                        (input.clone(), output.clone(), processor_config.scale_factor, processor_config.offset)
+                    })
+                    .collect();
+                conversions.sort_by(|a, b| a.0.cmp(&b.0));
+                conversions
+            }
+            _ => Vec::new(),
+        };
+
+        Ok(Box::new(Self {
+            name: name.to_string(),
+            config: processor_config,
+            conversions,
+            timing,
+        }))
+    }
+
+    fn round(&self, value: f64) -> f64 {
+        match self.config.precision {
+            Some(precision) => {
+                let scale = 10f64.powi(precision as i32);
+                (value * scale).round() / scale
+            }
+            None => value,
+        }
+    }
+
+    fn process_message(&self, mut message: Message) -> Result<Message> {
+        if let Some(batch) = message.batch_mut() {
+            for (input, output, factor, offset) in &self.conversions {
+                let Some(values) = batch.column(input) else {
+                    continue;
+                };
+                let mut scaled = values.to_vec();
+                column::scale(&mut scaled, *factor, *offset);
+                if let Some(precision) = self.config.precision {
+                    column::round(&mut scaled, precision);
+                }
+                batch.set_column(output, scaled)?;
+            }
+            message.source = self.name.clone();
+            return Ok(message);
+        }
+
+        for (input, output, factor, offset) in &self.conversions {
+            let Some(value) = FieldUtils::extract_f64(&message.payload, input) else {
+                continue;
+            };
+            let scaled = Number::from_f64(self.round(value * factor + offset)).map(Value::Number).unwrap_or(Value::Null);
+            FieldUtils::set_field_value(&mut message.payload, output, scaled)?;
+        }
+
+        message.source = self.name.clone();
+        Ok(message)
+    }
+}
+
+#[async_trait::async_trait]
+impl Processor for ScaleProcessor {
+    async fn init(&mut self) -> Result<()> {
+        tracing::info!("Scale processor '{}' initialised ({})", self.name, self.config.field);
+        Ok(())
+    }
+
+    fn accepts_batches(&self) -> bool {
+        true
+    }
+
+    async fn process(&mut self, context: &mut ProcessingContext) -> Result<()> {
+        if let Some((_, message)) = context.recv(tokio::time::Duration::from_millis(10)).await {
+            match self.process_message(message) {
+                Ok(mut output_message) => {
+                    if let Some(output_info) = &context.output {
+                        output_message.topic = output_info.name.clone();
+                        let output_message = self.timing.update_message_watermark(output_message);
+
+                        if let Err(e) = output_info.channel.publish(output_message).await {
+                            tracing::warn!("Failed to publish scale output: {:?}", e);
+                        }
+                    }
+                }
+                Err(e) => {
+                    error!("{}: Failed to scale message: {}", self.name, e);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl WithTimingMixin for ScaleProcessor {
+    fn timing_mixin(&self) -> &TimingMixin {
+        &self.timing
+    }
+
+    fn timing_mixin_mut(&mut self) -> &mut TimingMixin {
+        &mut self.timing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::batch::RecordBatch;
+    use crate::testing::{MessageBuilder, TestContext};
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_scale_applies_per_field_factors_and_offsets() {
+        let stage = StageConfig {
+            r#type: "scale".to_string(),
+            inputs: Some(vec!["in".to_string()]),
+            output: Some("out".to_string()),
+            parameters: serde_json::from_value(json!({
+                "fields_in": ["temp_raw", "rh_raw", "status"],
+                "fields_out": ["temp_c", "rh", "status"],
+                "scale_factors": [0.0625, 0.1, 2.0],
+                "offsets": [-40.0, 0.0, 0.0],
+                "precision": 2,
+            }))
+            .ok(),
+            ..Default::default()
+        };
+        let mut processor = ScaleProcessor::new("scale", stage).unwrap();
+        let mut test = TestContext::new("scale").input("in").output("out");
+
+        let payload = json!({ "temp_raw": 1000, "rh_raw": 456.789, "status": "ok" });
+        test.send("in", MessageBuilder::new(payload).build()).await.unwrap();
+        test.process_pending(processor.as_mut()).await.unwrap();
+
+        let scaled = test.try_output().await.unwrap().payload;
+        assert_eq!(scaled["temp_c"], json!(22.5));
+        assert_eq!(scaled["rh"], json!(45.68));
+        assert_eq!(scaled["status"], "ok");
+        assert_eq!(scaled["temp_raw"], 1000);
+    }
+
+    #[tokio::test]
+    async fn test_scale_converts_batches_by_column() {
+        let stage = StageConfig {
+            r#type: "scale".to_string(),
+            parameters: serde_json::from_value(json!({ "field_in": "raw", "field_out": "celsius", "scale_factor": 0.5, "offset": -10.0 })).ok(),
+            ..Default::default()
+        };
+        let mut processor = ScaleProcessor::new("scale", stage).unwrap();
+        let mut test = TestContext::new("scale").input("in").output("out");
+
+        let mut batch = RecordBatch::new(&["raw"]);
+        for raw in [40.0, 50.0, 61.0] {
+            batch.push_row(std::time::SystemTime::now(), &[raw]).unwrap();
+        }
+        test.send("in", Message::from_batch("sensor", "in", batch)).await.unwrap();
+        test.process_pending(processor.as_mut()).await.unwrap();
+
+        let scaled = test.try_output().await.unwrap().batch.unwrap();
+        assert_eq!(scaled.column("celsius"), Some(&[10.0, 15.0, 20.5][..]));
+        assert_eq!(scaled.column("raw"), Some(&[40.0, 50.0, 61.0][..]));
+    }
+
+    #[test]
+    fn test_scale_rejects_mismatched_factors() {
+        let stage = StageConfig {
+            r#type: "scale".to_string(),
+            parameters: serde_json::from_value(json!({
+                "fields_in": ["a", "b"],
+                "fields_out": ["x", "y"],
+                "scale_factors": [2.0],
+            }))
+            .ok(),
+            ..Default::default()
+        };
+        assert!(ScaleConfig::from_stage_config(&stage).is_err());
+    }
+}
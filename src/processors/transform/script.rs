@@ -0,0 +1,248 @@
+//! Script Transform
+//!
+//! General-purpose escape hatch for logic the rule engine cannot express. The
+//! user supplies a [Rhai](https://rhai.rs) script, inline or from a file, which
+//! is compiled once at startup and evaluated for every message. The script sees
+//! these variables:
+//!
+//! - `payload`: the message payload (object maps, arrays, numbers, strings)
+//! - `topic` and `source`: the message routing information
+//! - `event_time_ms`: the message event time in epoch milliseconds
+//!
+//! The value of the script's last expression becomes the new payload; returning
+//! `()` drops the message. For example:
+//!
+//! ```rhai
+//! if payload.temperature > 100.0 { return (); }
+//! payload.fahrenheit = payload.temperature * 9.0 / 5.0 + 32.0;
+//! payload
+//! ```
+
+use crate::config::{ParamSpec, ParamType, ProcessorConfig, ProcessorMetadata, StageConfig, extract_param};
+use crate::core::timing_mixin::{TimingMixin, WithTimingMixin};
+use crate::core::{context::ProcessingContext, message::Message};
+use crate::processors::processor::Processor;
+use crate::error::LiminalError;
+
+use anyhow::{Context, Result, anyhow};
+use rhai::{AST, Dynamic, Engine, Scope};
+use serde_json::Value;
+use std::time::UNIX_EPOCH;
+use tracing::{debug, error};
+
+#[derive(Debug, Clone)]
+pub struct ScriptConfig {
+    /// Script source, either given inline or read from `script_file`
+    pub source: String,
+    /// Upper bound on operations per evaluation, guarding against runaway loops
+    pub max_operations: u64,
+    pub timing: Option<crate::config::TimingConfig>,
+}
+
+impl ProcessorConfig for ScriptConfig {
+    fn from_stage_config(config: &StageConfig) -> Result<Self> {
+        let inline = extract_param(&config.parameters, "script", None::<String>);
+        let file = extract_param(&config.parameters, "script_file", None::<String>);
+
+        let source = match (inline, file) {
+            (Some(_), Some(_)) => {
+                return Err(anyhow!("script processor accepts either 'script' or 'script_file', not both"));
+            }
+            (Some(script), None) => script,
+            (None, Some(path)) => std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read script file '{}'", path))?,
+            (None, None) => {
+                return Err(anyhow!("script processor requires 'script' or 'script_file'"));
+            }
+        };
+
+        let config = Self {
+            source,
+            max_operations: extract_param(&config.parameters, "max_operations", 100_000_u64),
+            timing: config.timing.clone(),
+        };
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.source.trim().is_empty() {
+            return Err(LiminalError::invalid_parameter("script", "cannot be empty").into());
+        }
+        if self.max_operations == 0 {
+            return Err(LiminalError::invalid_parameter("max_operations", "must be greater than 0").into());
+        }
+        Ok(())
+    }
+}
+
+pub struct ScriptProcessor {
+    name: String,
+    config: ScriptConfig,
+    timing: TimingMixin,
+    engine: Engine,
+    ast: AST,
+}
+
+impl ScriptProcessor {
+    pub const METADATA: ProcessorMetadata = ProcessorMetadata {
+        name: "script",
+        description: "Runs a user-supplied Rhai script against each payload",
+        parameters: &[
+            ParamSpec::new("script", ParamType::String, "Inline Rhai script"),
+            ParamSpec::new("script_file", ParamType::String, "File containing the Rhai script"),
+            ParamSpec::new("max_operations", ParamType::Integer, "Operations a script may run per message"),
+        ],
+        shared: &[],
+    };
+
+    pub fn new(name: &str, config: StageConfig) -> Result<Box<dyn Processor>> {
+        let processor_config = ScriptConfig::from_stage_config(&config)?;
+        processor_config.validate()?;
+
+        let timing = TimingMixin::new(processor_config.timing.as_ref());
+
+        let mut engine = Engine::new();
+        engine.set_max_operations(processor_config.max_operations);
+
+        let ast = engine
+            .compile(&processor_config.source)
+            .map_err(|e| anyhow!("Failed to compile script for stage '{}': {}", name, e))?;
+
+        Ok(Box::new(Self {
+            name: name.to_string(),
+            config: processor_config,
+            timing,
+            engine,
+            ast,
+        }))
+    }
+
+    fn process_message(&mut self, mut message: Message) -> Result<Option<Message>> {
+        let payload = rhai::serde::to_dynamic(&message.payload)
+            .map_err(|e| anyhow!("Failed to convert payload for script: {}", e))?;
+        let event_time_ms = message
+            .timing
+            .event_time
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as i64)
+            .unwrap_or_default();
+
+        let mut scope = Scope::new();
+        scope.push("payload", payload);
+        scope.push_constant("topic", message.topic.clone());
+        scope.push_constant("source", message.source.clone());
+        scope.push_constant("event_time_ms", event_time_ms);
+
+        let result: Dynamic = self
+            .engine
+            .eval_ast_with_scope(&mut scope, &self.ast)
+            .map_err(|e| anyhow!("Script evaluation failed: {}", e))?;
+
+        if result.is_unit() {
+            return Ok(None);
+        }
+
+        message.payload = rhai::serde::from_dynamic::<Value>(&result)
+            .map_err(|e| anyhow!("Script returned a value that is not valid JSON: {}", e))?;
+        message.source = self.name.clone();
+        Ok(Some(message))
+    }
+}
+
+#[async_trait::async_trait]
+impl Processor for ScriptProcessor {
+    async fn init(&mut self) -> Result<()> {
+        tracing::info!(
+            "Script processor '{}' initialised ({} bytes, max {} operations)",
+            self.name,
+            self.config.source.len(),
+            self.config.max_operations
+        );
+        Ok(())
+    }
+
+    async fn process(&mut self, context: &mut ProcessingContext) -> Result<()> {
+        if let Some((channel_name, message)) = context.recv(tokio::time::Duration::from_millis(10)).await {
+            match self.process_message(message) {
+                Ok(Some(mut output_message)) => {
+                    if let Some(output_info) = &context.output {
+                        output_message.topic = output_info.name.clone();
+                        let output_message = self.timing.update_message_watermark(output_message);
+
+                        if let Err(e) = output_info.channel.publish(output_message).await {
+                            tracing::warn!("Failed to publish script output: {:?}", e);
+                        }
+                    }
+                }
+                Ok(None) => {
+                    debug!("Script dropped message from '{}'", channel_name);
+                }
+                Err(e) => {
+                    error!("Script error in '{}': {}", self.name, e);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl WithTimingMixin for ScriptProcessor {
+    fn timing_mixin(&self) -> &TimingMixin {
+        &self.timing
+    }
+
+    fn timing_mixin_mut(&mut self) -> &mut TimingMixin {
+        &mut self.timing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    fn stage_config(script: &str) -> StageConfig {
+        StageConfig {
+            r#type: "script".to_string(),
+            parameters: Some(HashMap::from([("script".to_string(), json!(script))])),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_script_transforms_and_drops() {
+        let script = r#"
+            if payload.value < 0 { return (); }
+            payload.doubled = payload.value * 2;
+            payload
+        "#;
+        let config = ScriptConfig::from_stage_config(&stage_config(script)).unwrap();
+        let engine = Engine::new();
+        let mut processor = ScriptProcessor {
+            name: "test".to_string(),
+            ast: engine.compile(script).unwrap(),
+            engine,
+            config,
+            timing: TimingMixin::new(None),
+        };
+
+        let output = processor
+            .process_message(Message::new("src", "topic", json!({"value": 21})))
+            .unwrap()
+            .unwrap();
+        assert_eq!(output.payload, json!({"value": 21, "doubled": 42}));
+
+        let dropped = processor
+            .process_message(Message::new("src", "topic", json!({"value": -1})))
+            .unwrap();
+        assert!(dropped.is_none());
+    }
+
+    #[test]
+    fn test_script_compile_error_is_reported() {
+        assert!(ScriptProcessor::new("test", stage_config("payload +")).is_err());
+    }
+}
@@ -0,0 +1,588 @@
+//! Sparkplug B Decode Transform
+//!
+//! `sparkplug_decode` turns Sparkplug B payloads into plain JSON messages.
+//! Available with the `sparkplug` Cargo feature.
+//!
+//! Payloads are read as base64 strings (`mqtt_sub` with `binary = true`), and
+//! the group, edge node, device and message type come from the MQTT topic
+//! (`spBv1.0/<group>/<type>/<edge node>[/<device>]`) in the `mqtt_topic`
+//! metadata entry. Each edge node's birth certificates (`NBIRTH`, `DBIRTH`)
+//! record the names and datatypes behind metric aliases, so that the data
+//! messages that follow, which usually carry only aliases, are emitted with
+//! metric names. Births and deaths are emitted with an `online` flag, and
+//! `NDEATH` certificates whose `bdSeq` does not match the node's last birth
+//! are ignored as stale. Gaps in a node's `seq` are logged.
+//!
+//! The alias tables are checkpointed with the stage, so a restart does not
+//! have to wait for edge nodes to be rebirthed.
+
+use crate::config::{ParamSpec, ParamType, ProcessorConfig, ProcessorMetadata, StageConfig, extract_param};
+use crate::core::checkpoint::Snapshot;
+use crate::core::message::{CONTENT_ENCODING, MQTT_TOPIC};
+use crate::core::timing_mixin::{TimingMixin, WithTimingMixin};
+use crate::core::{context::ProcessingContext, message::Message};
+use crate::processors::processor::Processor;
+
+use anyhow::{Result, anyhow};
+use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
+use prost::Message as _;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value, json};
+use std::collections::HashMap;
+use std::time::{Duration, UNIX_EPOCH};
+use tracing::{debug, error, warn};
+
+/// Topic namespace of Sparkplug B
+const NAMESPACE: &str = "spBv1.0";
+
+/// Metric carrying an edge node's birth/death sequence number
+const BD_SEQ: &str = "bdSeq";
+
+#[derive(Debug, Clone)]
+pub struct SparkplugConfig {
+    /// Emit one message per metric instead of one per payload
+    pub per_metric: bool,
+    pub timing: Option<crate::config::TimingConfig>,
+}
+
+impl ProcessorConfig for SparkplugConfig {
+    fn from_stage_config(config: &StageConfig) -> Result<Self> {
+        Ok(Self {
+            per_metric: extract_param(&config.parameters, "per_metric", false),
+            timing: config.timing.clone(),
+        })
+    }
+}
+
+/// Sparkplug B message types.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MessageType {
+    NBirth,
+    NDeath,
+    DBirth,
+    DDeath,
+    NData,
+    DData,
+    NCmd,
+    DCmd,
+}
+
+impl MessageType {
+    fn parse(value: &str) -> Option<Self> {
+        Some(match value {
+            "NBIRTH" => Self::NBirth,
+            "NDEATH" => Self::NDeath,
+            "DBIRTH" => Self::DBirth,
+            "DDEATH" => Self::DDeath,
+            "NDATA" => Self::NData,
+            "DDATA" => Self::DData,
+            "NCMD" => Self::NCmd,
+            "DCMD" => Self::DCmd,
+            _ => return None,
+        })
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::NBirth => "NBIRTH",
+            Self::NDeath => "NDEATH",
+            Self::DBirth => "DBIRTH",
+            Self::DDeath => "DDEATH",
+            Self::NData => "NDATA",
+            Self::DData => "DDATA",
+            Self::NCmd => "NCMD",
+            Self::DCmd => "DCMD",
+        }
+    }
+
+    /// `Some(true)` for births, `Some(false)` for deaths
+    fn online(self) -> Option<bool> {
+        match self {
+            Self::NBirth | Self::DBirth => Some(true),
+            Self::NDeath | Self::DDeath => Some(false),
+            _ => None,
+        }
+    }
+}
+
+/// The parts of a Sparkplug B topic.
+#[derive(Debug, Clone, PartialEq)]
+struct SparkplugTopic {
+    group: String,
+    message_type: MessageType,
+    edge_node: String,
+    device: Option<String>,
+}
+
+impl SparkplugTopic {
+    fn parse(topic: &str) -> Result<Self> {
+        let levels: Vec<&str> = topic.split('/').collect();
+        match levels.as_slice() {
+            [NAMESPACE, group, kind, edge_node, device @ ..] if device.len() <= 1 => {
+                let message_type = MessageType::parse(kind)
+                    .ok_or_else(|| anyhow!("'{}' is not a Sparkplug B message type", kind))?;
+                Ok(Self {
+                    group: group.to_string(),
+                    message_type,
+                    edge_node: edge_node.to_string(),
+                    device: device.first().map(|device| device.to_string()),
+                })
+            }
+            _ => Err(anyhow!("'{}' is not a Sparkplug B edge node or device topic", topic)),
+        }
+    }
+
+    /// Key of the edge node's state
+    fn node_key(&self) -> String {
+        format!("{}/{}", self.group, self.edge_node)
+    }
+}
+
+/// What the last birth certificates of an edge node declared.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct NodeState {
+    /// Metric names by alias
+    aliases: HashMap<u64, String>,
+    /// Metric datatypes by name
+    datatypes: HashMap<String, u32>,
+    /// `bdSeq` of the last `NBIRTH`
+    bd_seq: Option<u64>,
+    /// `seq` of the last payload
+    seq: Option<u64>,
+}
+
+impl NodeState {
+    /// Record the names and datatypes a birth certificate declares.
+    fn learn(&mut self, metrics: &[proto::Metric]) {
+        for metric in metrics {
+            let Some(name) = &metric.name else { continue };
+            if let Some(alias) = metric.alias {
+                self.aliases.insert(alias, name.clone());
+            }
+            if let Some(datatype) = metric.datatype {
+                self.datatypes.insert(name.clone(), datatype);
+            }
+        }
+    }
+
+    fn name(&self, metric: &proto::Metric) -> Option<String> {
+        metric
+            .name
+            .clone()
+            .or_else(|| metric.alias.and_then(|alias| self.aliases.get(&alias).cloned()))
+    }
+}
+
+pub struct SparkplugProcessor {
+    name: String,
+    config: SparkplugConfig,
+    nodes: HashMap<String, NodeState>,
+    timing: TimingMixin,
+}
+
+impl SparkplugProcessor {
+    pub const METADATA: ProcessorMetadata = ProcessorMetadata {
+        name: "sparkplug_decode",
+        description: "Decodes Sparkplug B payloads to JSON, resolving metric aliases from birth certificates",
+        parameters: &[
+            ParamSpec::new("per_metric", ParamType::Boolean, "Emit one message per metric instead of one per payload"),
+        ],
+        shared: &[],
+    };
+
+    pub fn new(name: &str, config: StageConfig) -> Result<Box<dyn Processor>> {
+        let processor_config = SparkplugConfig::from_stage_config(&config)?;
+        let timing = TimingMixin::new(processor_config.timing.as_ref());
+
+        Ok(Box::new(Self {
+            name: name.to_string(),
+            config: processor_config,
+            nodes: HashMap::new(),
+            timing,
+        }))
+    }
+
+    /// Decode a Sparkplug B message into the messages to emit (none for
+    /// stale deaths).
+    fn process_message(&mut self, message: Message) -> Result<Vec<Message>> {
+        let topic = message
+            .get_metadata(MQTT_TOPIC)
+            .ok_or_else(|| anyhow!("message has no '{}' metadata", MQTT_TOPIC))?;
+        let topic = SparkplugTopic::parse(topic)?;
+
+        let encoded = message
+            .payload
+            .as_str()
+            .ok_or_else(|| anyhow!("expected a base64 string, got {}", message.payload))?;
+        let bytes = BASE64.decode(encoded).map_err(|e| anyhow!("invalid base64: {}", e))?;
+        let payload = proto::Payload::decode(bytes.as_slice())
+            .map_err(|e| anyhow!("invalid Sparkplug B payload: {}", e))?;
+
+        let node_key = topic.node_key();
+        let node = self.nodes.entry(node_key.clone()).or_default();
+        match topic.message_type {
+            MessageType::NBirth => {
+                *node = NodeState { bd_seq: bd_seq(&payload), ..Default::default() };
+                node.learn(&payload.metrics);
+            }
+            MessageType::DBirth => node.learn(&payload.metrics),
+            MessageType::NDeath => {
+                if let (Some(death), Some(birth)) = (bd_seq(&payload), node.bd_seq)
+                    && death != birth
+                {
+                    debug!("Ignoring stale NDEATH of '{}' (bdSeq {} after birth {})", node_key, death, birth);
+                    return Ok(Vec::new());
+                }
+            }
+            _ => {}
+        }
+
+        // NDEATH is published by the broker as the node's will, outside the sequence
+        if let Some(seq) = payload.seq
+            && topic.message_type != MessageType::NDeath
+        {
+            if let Some(last) = node.seq
+                && topic.message_type != MessageType::NBirth
+                && seq != (last + 1) % 256
+            {
+                warn!("Sparkplug node '{}' skipped from seq {} to {}", node_key, last, seq);
+            }
+            node.seq = Some(seq);
+        }
+
+        let node = &self.nodes[&node_key];
+        let mut header = Map::new();
+        header.insert("group".into(), json!(topic.group));
+        header.insert("edge_node".into(), json!(topic.edge_node));
+        header.insert("device".into(), json!(topic.device));
+        header.insert("message_type".into(), json!(topic.message_type.as_str()));
+        if let Some(online) = topic.message_type.online() {
+            header.insert("online".into(), json!(online));
+        }
+
+        let metrics: Vec<(String, &proto::Metric)> = payload
+            .metrics
+            .iter()
+            .filter_map(|metric| match node.name(metric) {
+                Some(name) => Some((name, metric)),
+                None => {
+                    warn!(
+                        "Sparkplug node '{}' sent alias {:?} without a birth certificate naming it",
+                        node_key, metric.alias
+                    );
+                    None
+                }
+            })
+            .collect();
+
+        let mut outputs = Vec::new();
+        if !self.config.per_metric {
+            let mut payload_json = header;
+            payload_json.insert("seq".into(), json!(payload.seq));
+            payload_json.insert("timestamp".into(), json!(payload.timestamp));
+            let values = metrics
+                .iter()
+                .map(|(name, metric)| (name.clone(), value(metric, node.datatypes.get(name).copied())))
+                .collect::<Map<_, _>>();
+            payload_json.insert("metrics".into(), Value::Object(values));
+            outputs.push(self.output(&message, Value::Object(payload_json), payload.timestamp));
+        } else {
+            // Births and deaths also announce the change of state on their own
+            if topic.message_type.online().is_some() {
+                let mut state = header.clone();
+                state.insert("timestamp".into(), json!(payload.timestamp));
+                outputs.push(self.output(&message, Value::Object(state), payload.timestamp));
+            }
+            if topic.message_type != MessageType::NDeath {
+                for (name, metric) in &metrics {
+                    let timestamp = metric.timestamp.or(payload.timestamp);
+                    let mut metric_json = header.clone();
+                    metric_json.remove("online");
+                    metric_json.insert("metric".into(), json!(name));
+                    metric_json.insert("value".into(), value(metric, node.datatypes.get(name).copied()));
+                    metric_json.insert("timestamp".into(), json!(timestamp));
+                    if metric.is_historical == Some(true) {
+                        metric_json.insert("historical".into(), json!(true));
+                    }
+                    outputs.push(self.output(&message, Value::Object(metric_json), timestamp));
+                }
+            }
+        }
+
+        Ok(outputs)
+    }
+
+    /// Build an output message from the input, taking its event time from a
+    /// Sparkplug timestamp when there is one.
+    fn output(&self, input: &Message, payload: Value, timestamp: Option<u64>) -> Message {
+        let mut message = input.clone();
+        message.payload = payload;
+        message.metadata.remove(CONTENT_ENCODING);
+        message.source = self.name.clone();
+        if let Some(timestamp) = timestamp {
+            message.timing.event_time = UNIX_EPOCH + Duration::from_millis(timestamp);
+        }
+        message
+    }
+}
+
+/// The `bdSeq` metric of a birth or death certificate.
+fn bd_seq(payload: &proto::Payload) -> Option<u64> {
+    payload
+        .metrics
+        .iter()
+        .find(|metric| metric.name.as_deref() == Some(BD_SEQ))
+        .and_then(|metric| match metric.value {
+            Some(proto::metric::Value::Long(value)) => Some(value),
+            Some(proto::metric::Value::Int(value)) => Some(value as u64),
+            _ => None,
+        })
+}
+
+/// The JSON value of a metric. Signed integers travel as two's complement in
+/// the unsigned fields, so they are read back by their birth datatype.
+fn value(metric: &proto::Metric, birth_datatype: Option<u32>) -> Value {
+    use proto::metric::Value as V;
+
+    if metric.is_null == Some(true) {
+        return Value::Null;
+    }
+    let datatype = metric.datatype.or(birth_datatype).unwrap_or_default();
+    match &metric.value {
+        Some(V::Int(value)) => match datatype {
+            proto::INT8 => json!(*value as i8),
+            proto::INT16 => json!(*value as i16),
+            proto::INT32 => json!(*value as i32),
+            _ => json!(value),
+        },
+        Some(V::Long(value)) => match datatype {
+            proto::INT8 => json!(*value as i8),
+            proto::INT16 => json!(*value as i16),
+            proto::INT32 => json!(*value as i32),
+            proto::INT64 => json!(*value as i64),
+            _ => json!(value),
+        },
+        Some(V::Float(value)) => json!(value),
+        Some(V::Double(value)) => json!(value),
+        Some(V::Boolean(value)) => json!(value),
+        Some(V::String(value)) => json!(value),
+        Some(V::Bytes(value)) => json!(BASE64.encode(value)),
+        None => Value::Null,
+    }
+}
+
+#[async_trait::async_trait]
+impl Processor for SparkplugProcessor {
+    async fn init(&mut self) -> Result<()> {
+        tracing::info!("Sparkplug processor '{}' initialised (per_metric: {})", self.name, self.config.per_metric);
+        Ok(())
+    }
+
+    async fn process(&mut self, context: &mut ProcessingContext) -> Result<()> {
+        if let Some((channel_name, message)) = context.recv(tokio::time::Duration::from_millis(10)).await {
+            match self.process_message(message) {
+                Ok(output_messages) => {
+                    if let Some(output_info) = &context.output {
+                        for mut output_message in output_messages {
+                            output_message.topic = output_info.name.clone();
+                            let output_message = self.timing.update_message_watermark(output_message);
+
+                            if let Err(e) = output_info.channel.publish(output_message).await {
+                                tracing::warn!("Failed to publish sparkplug output: {:?}", e);
+                            }
+                        }
+                    }
+                }
+                Err(e) => {
+                    error!("Sparkplug stage '{}' dropped a message from '{}': {}", self.name, channel_name, e);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn as_snapshot(&mut self) -> Option<&mut dyn Snapshot> {
+        Some(self)
+    }
+}
+
+impl Snapshot for SparkplugProcessor {
+    fn snapshot(&self) -> Result<Value> {
+        Ok(serde_json::to_value(&self.nodes)?)
+    }
+
+    fn restore(&mut self, state: Value) -> Result<()> {
+        self.nodes = serde_json::from_value(state)?;
+        Ok(())
+    }
+}
+
+impl WithTimingMixin for SparkplugProcessor {
+    fn timing_mixin(&self) -> &TimingMixin {
+        &self.timing
+    }
+
+    fn timing_mixin_mut(&mut self) -> &mut TimingMixin {
+        &mut self.timing
+    }
+}
+
+/// The subset of the Sparkplug B `Payload` message this transform reads.
+/// Datasets, templates and metric properties are skipped.
+mod proto {
+    pub const INT8: u32 = 1;
+    pub const INT16: u32 = 2;
+    pub const INT32: u32 = 3;
+    pub const INT64: u32 = 4;
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Payload {
+        #[prost(uint64, optional, tag = "1")]
+        pub timestamp: Option<u64>,
+        #[prost(message, repeated, tag = "2")]
+        pub metrics: Vec<Metric>,
+        #[prost(uint64, optional, tag = "3")]
+        pub seq: Option<u64>,
+        #[prost(string, optional, tag = "4")]
+        pub uuid: Option<String>,
+        #[prost(bytes = "vec", optional, tag = "5")]
+        pub body: Option<Vec<u8>>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Metric {
+        #[prost(string, optional, tag = "1")]
+        pub name: Option<String>,
+        #[prost(uint64, optional, tag = "2")]
+        pub alias: Option<u64>,
+        #[prost(uint64, optional, tag = "3")]
+        pub timestamp: Option<u64>,
+        #[prost(uint32, optional, tag = "4")]
+        pub datatype: Option<u32>,
+        #[prost(bool, optional, tag = "5")]
+        pub is_historical: Option<bool>,
+        #[prost(bool, optional, tag = "6")]
+        pub is_transient: Option<bool>,
+        #[prost(bool, optional, tag = "7")]
+        pub is_null: Option<bool>,
+        #[prost(oneof = "metric::Value", tags = "10, 11, 12, 13, 14, 15, 16")]
+        pub value: Option<metric::Value>,
+    }
+
+    pub mod metric {
+        #[derive(Clone, PartialEq, prost::Oneof)]
+        pub enum Value {
+            #[prost(uint32, tag = "10")]
+            Int(u32),
+            #[prost(uint64, tag = "11")]
+            Long(u64),
+            #[prost(float, tag = "12")]
+            Float(f32),
+            #[prost(double, tag = "13")]
+            Double(f64),
+            #[prost(bool, tag = "14")]
+            Boolean(bool),
+            #[prost(string, tag = "15")]
+            String(String),
+            #[prost(bytes = "vec", tag = "16")]
+            Bytes(Vec<u8>),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proto::metric::Value as V;
+
+    fn metric(name: Option<&str>, alias: Option<u64>, datatype: Option<u32>, value: V) -> proto::Metric {
+        proto::Metric {
+            name: name.map(str::to_string),
+            alias,
+            datatype,
+            value: Some(value),
+            ..Default::default()
+        }
+    }
+
+    fn sparkplug(topic: &str, seq: Option<u64>, metrics: Vec<proto::Metric>) -> Message {
+        let payload = proto::Payload { timestamp: Some(1_700_000_000_000), metrics, seq, ..Default::default() };
+        Message::new("mqtt", "raw", Value::String(BASE64.encode(payload.encode_to_vec())))
+            .with_metadata(MQTT_TOPIC, topic)
+            .with_metadata(CONTENT_ENCODING, "base64")
+    }
+
+    fn processor(per_metric: bool) -> SparkplugProcessor {
+        SparkplugProcessor {
+            name: "decode".to_string(),
+            config: SparkplugConfig { per_metric, timing: None },
+            nodes: HashMap::new(),
+            timing: TimingMixin::new(None),
+        }
+    }
+
+    #[test]
+    fn test_sparkplug_resolves_aliases_from_births() {
+        let mut decoder = processor(false);
+
+        let birth = decoder
+            .process_message(sparkplug(
+                "spBv1.0/plant/NBIRTH/gw1",
+                Some(0),
+                vec![
+                    metric(Some(BD_SEQ), None, Some(proto::INT64), V::Long(7)),
+                    metric(Some("Temperature"), Some(1), Some(10), V::Double(20.5)),
+                    metric(Some("Offset"), Some(2), Some(proto::INT16), V::Int(0)),
+                ],
+            ))
+            .unwrap();
+        assert_eq!(birth[0].payload["online"], json!(true));
+        assert_eq!(birth[0].payload["metrics"]["Temperature"], json!(20.5));
+        assert!(birth[0].get_metadata(CONTENT_ENCODING).is_none());
+
+        // Data carries only aliases; the signed value comes back negative
+        let data = decoder
+            .process_message(sparkplug(
+                "spBv1.0/plant/NDATA/gw1",
+                Some(1),
+                vec![
+                    metric(None, Some(1), None, V::Double(21.0)),
+                    metric(None, Some(2), None, V::Int(-3i16 as u16 as u32)),
+                    metric(None, Some(9), None, V::Double(1.0)),
+                ],
+            ))
+            .unwrap();
+        assert_eq!(
+            data[0].payload,
+            json!({
+                "group": "plant", "edge_node": "gw1", "device": null, "message_type": "NDATA",
+                "seq": 1, "timestamp": 1_700_000_000_000u64,
+                "metrics": { "Temperature": 21.0, "Offset": -3 },
+            })
+        );
+        assert_eq!(data[0].timing.event_time, UNIX_EPOCH + Duration::from_millis(1_700_000_000_000));
+
+        // Alias tables survive a restart
+        let state = decoder.snapshot().unwrap();
+        let mut restored = processor(true);
+        restored.restore(state).unwrap();
+        let per_metric = restored
+            .process_message(sparkplug("spBv1.0/plant/NDATA/gw1", Some(2), vec![metric(None, Some(1), None, V::Double(22.0))]))
+            .unwrap();
+        assert_eq!(per_metric.len(), 1);
+        assert_eq!(per_metric[0].payload["metric"], json!("Temperature"));
+        assert_eq!(per_metric[0].payload["value"], json!(22.0));
+
+        // A death from an earlier session is stale; the current one is not
+        let stale = decoder
+            .process_message(sparkplug("spBv1.0/plant/NDEATH/gw1", None, vec![metric(Some(BD_SEQ), None, None, V::Long(6))]))
+            .unwrap();
+        assert!(stale.is_empty());
+        let death = decoder
+            .process_message(sparkplug("spBv1.0/plant/NDEATH/gw1", None, vec![metric(Some(BD_SEQ), None, None, V::Long(7))]))
+            .unwrap();
+        assert_eq!(death[0].payload["online"], json!(false));
+
+        assert!(decoder.process_message(sparkplug("plant/gw1/data", Some(3), Vec::new())).is_err());
+    }
+}
@@ -0,0 +1,284 @@
+//! Split Fields Transform
+//!
+//! Splits wide payloads, such as those of gateways combining several sensors,
+//! into one message per group of fields, each published to its own side
+//! output. Fields listed in `common_fields` (identifiers and timestamps) are
+//! copied into every group:
+//!
+//! ```toml
+//! side_outputs = ["environment", "diagnostics"]
+//! parameters = { common_fields = ["device_id"], groups = [
+//!     { output = "environment", fields = ["temperature", "humidity"] },
+//!     { output = "diagnostics", fields = ["battery", "rssi", "uptime"] },
+//! ] }
+//! ```
+//!
+//! Fields in no group go, with the common fields, to the stage's main
+//! `output` when it has one. Groups (and the remainder) with none of their
+//! fields present are not emitted. Split messages keep the original's
+//! timing and metadata.
+
+use crate::config::{ParamSpec, ParamType, ProcessorConfig, ProcessorMetadata, StageConfig, extract_param};
+use crate::core::timing_mixin::{TimingMixin, WithTimingMixin};
+use crate::core::{
+    context::{OutputInfo, ProcessingContext},
+    message::Message,
+};
+use crate::processors::common::field_utils::FieldUtils;
+use crate::processors::processor::Processor;
+
+use anyhow::{Result, anyhow};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use tracing::{debug, error};
+
+/// Fields published together to one side output.
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+pub struct FieldGroup {
+    pub output: String,
+    pub fields: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SplitFieldsConfig {
+    pub groups: Vec<FieldGroup>,
+    /// Fields copied into every group
+    pub common_fields: Vec<String>,
+    pub side_outputs: Vec<String>,
+    pub timing: Option<crate::config::TimingConfig>,
+}
+
+impl ProcessorConfig for SplitFieldsConfig {
+    fn from_stage_config(config: &StageConfig) -> Result<Self> {
+        let groups = config
+            .parameters
+            .as_ref()
+            .and_then(|parameters| parameters.get("groups"))
+            .map(|groups| serde_json::from_value::<Vec<FieldGroup>>(groups.clone()))
+            .transpose()
+            .map_err(|e| anyhow!("Invalid split_fields groups: {}", e))?
+            .unwrap_or_default();
+
+        let config = Self {
+            groups,
+            common_fields: extract_param(&config.parameters, "common_fields", Vec::<String>::new()),
+            side_outputs: config.side_outputs.clone().unwrap_or_default(),
+            timing: config.timing.clone(),
+        };
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.groups.is_empty() {
+            return Err(anyhow!("split_fields processor requires at least one group"));
+        }
+        for group in &self.groups {
+            if group.fields.is_empty() {
+                return Err(anyhow!("split_fields group '{}' has no fields", group.output));
+            }
+            if !self.side_outputs.contains(&group.output) {
+                return Err(anyhow!(
+                    "split_fields group output '{}' is not a side output of this stage (declared: {:?})",
+                    group.output,
+                    self.side_outputs
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct SplitFieldsProcessor {
+    name: String,
+    config: SplitFieldsConfig,
+    timing: TimingMixin,
+}
+
+impl SplitFieldsProcessor {
+    pub const METADATA: ProcessorMetadata = ProcessorMetadata {
+        name: "split_fields",
+        description: "Splits payloads into groups of fields published to separate outputs",
+        parameters: &[
+            ParamSpec::new("groups", ParamType::Array, "Groups of output (a side output) and fields"),
+            ParamSpec::new("common_fields", ParamType::Array, "Fields copied into every group"),
+        ],
+        shared: &[],
+    };
+
+    pub fn new(name: &str, config: StageConfig) -> Result<Box<dyn Processor>> {
+        let processor_config = SplitFieldsConfig::from_stage_config(&config)?;
+        let timing = TimingMixin::new(processor_config.timing.as_ref());
+
+        Ok(Box::new(Self {
+            name: name.to_string(),
+            config: processor_config,
+            timing,
+        }))
+    }
+
+    /// A copy of the message holding the common fields and `fields`, or `None`
+    /// if none of `fields` is present.
+    fn select(&self, message: &Message, fields: &[String]) -> Result<Option<Message>> {
+        let mut payload = Value::Object(Map::new());
+        let mut found = false;
+        for field in fields {
+            if let Some(value) = FieldUtils::extract_field_value(&message.payload, field) {
+                FieldUtils::set_field_value(&mut payload, field, value.clone())?;
+                found = true;
+            }
+        }
+        if !found {
+            return Ok(None);
+        }
+        for field in &self.config.common_fields {
+            if let Some(value) = FieldUtils::extract_field_value(&message.payload, field) {
+                FieldUtils::set_field_value(&mut payload, field, value.clone())?;
+            }
+        }
+
+        let mut split = message.clone();
+        split.payload = payload;
+        split.source = self.name.clone();
+        Ok(Some(split))
+    }
+
+    /// A copy of the message without the grouped fields, or `None` if only
+    /// common fields (and objects emptied of grouped ones) remain.
+    fn remainder(&self, message: &Message) -> Result<Option<Message>> {
+        let mut remainder = message.clone();
+        for field in self.config.groups.iter().flat_map(|group| &group.fields) {
+            if !self.config.common_fields.contains(field) {
+                FieldUtils::remove_field_value(&mut remainder.payload, field)?;
+            }
+        }
+
+        let has_own_fields = match &remainder.payload {
+            Value::Object(map) => map
+                .iter()
+                .any(|(key, value)| !self.config.common_fields.contains(key) && *value != Value::Object(Map::new())),
+            _ => false,
+        };
+        if !has_own_fields {
+            return Ok(None);
+        }
+        remainder.source = self.name.clone();
+        Ok(Some(remainder))
+    }
+
+    async fn publish(&mut self, output_info: &OutputInfo, mut message: Message) {
+        message.topic = output_info.name.clone();
+        let message = self.timing.update_message_watermark(message);
+
+        if let Err(e) = output_info.channel.publish(message).await {
+            tracing::warn!("Failed to publish split message to '{}': {:?}", output_info.name, e);
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Processor for SplitFieldsProcessor {
+    async fn init(&mut self) -> Result<()> {
+        tracing::info!(
+            "Split fields processor '{}' initialised ({} groups)",
+            self.name,
+            self.config.groups.len()
+        );
+        Ok(())
+    }
+
+    async fn process(&mut self, context: &mut ProcessingContext) -> Result<()> {
+        if let Some((_, message)) = context.recv(tokio::time::Duration::from_millis(10)).await {
+            let mut splits = Vec::new();
+            for group in &self.config.groups {
+                match self.select(&message, &group.fields) {
+                    Ok(Some(split)) => splits.push((group.output.clone(), split)),
+                    Ok(None) => debug!("{}: No fields of group '{}'", self.name, group.output),
+                    Err(e) => error!("{}: Failed to split group '{}': {}", self.name, group.output, e),
+                }
+            }
+            for (output, split) in splits {
+                match context.side_outputs.get(&output) {
+                    Some(output_info) => self.publish(output_info, split).await,
+                    None => error!("Split output '{}' is not connected", output),
+                }
+            }
+
+            if let Some(output_info) = &context.output {
+                match self.remainder(&message) {
+                    Ok(Some(remainder)) => self.publish(output_info, remainder).await,
+                    Ok(None) => {}
+                    Err(e) => error!("{}: Failed to split remainder: {}", self.name, e),
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl WithTimingMixin for SplitFieldsProcessor {
+    fn timing_mixin(&self) -> &TimingMixin {
+        &self.timing
+    }
+
+    fn timing_mixin_mut(&mut self) -> &mut TimingMixin {
+        &mut self.timing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{MessageBuilder, TestContext};
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_split_fields_publishes_groups_and_remainder() {
+        let stage = StageConfig {
+            r#type: "split_fields".to_string(),
+            inputs: Some(vec!["in".to_string()]),
+            output: Some("out".to_string()),
+            side_outputs: Some(vec!["environment".to_string(), "diagnostics".to_string()]),
+            parameters: serde_json::from_value(json!({
+                "common_fields": ["device_id"],
+                "groups": [
+                    { "output": "environment", "fields": ["temperature", "humidity"] },
+                    { "output": "diagnostics", "fields": ["battery", "radio.rssi"] },
+                ],
+            }))
+            .ok(),
+            ..Default::default()
+        };
+        let mut processor = SplitFieldsProcessor::new("split", stage).unwrap();
+        let mut test = TestContext::new("split")
+            .input("in")
+            .output("out")
+            .side_output("environment")
+            .side_output("diagnostics");
+
+        let wide = json!({
+            "device_id": "gw-1",
+            "temperature": 21.5,
+            "humidity": 40,
+            "battery": 3.1,
+            "radio": { "rssi": -70, "snr": 9 },
+        });
+        test.send("in", MessageBuilder::new(wide).build()).await.unwrap();
+        let environment_only = json!({ "device_id": "gw-2", "temperature": 19.0 });
+        test.send("in", MessageBuilder::new(environment_only).build()).await.unwrap();
+        test.process_pending(processor.as_mut()).await.unwrap();
+
+        let environment = test.try_side_output("environment").await.unwrap().payload;
+        assert_eq!(environment, json!({ "device_id": "gw-1", "temperature": 21.5, "humidity": 40 }));
+        let diagnostics = test.try_side_output("diagnostics").await.unwrap().payload;
+        assert_eq!(diagnostics, json!({ "device_id": "gw-1", "battery": 3.1, "radio": { "rssi": -70 } }));
+        let remainder = test.try_output().await.unwrap().payload;
+        assert_eq!(remainder, json!({ "device_id": "gw-1", "radio": { "snr": 9 } }));
+
+        let environment = test.try_side_output("environment").await.unwrap().payload;
+        assert_eq!(environment, json!({ "device_id": "gw-2", "temperature": 19.0 }));
+        assert!(test.try_side_output("diagnostics").await.is_none());
+        assert!(test.try_output().await.is_none());
+    }
+}
@@ -0,0 +1,238 @@
+//! Timestamp Parse/Format Transform
+//!
+//! Normalises timestamps from heterogeneous devices. In `parse` mode a string
+//! (ISO 8601, RFC 2822, custom strftime patterns, or a numeric epoch string) or
+//! a numeric epoch in s/ms/µs/ns is converted into a canonical epoch-milliseconds
+//! field. In `format` mode an epoch field is rendered as a string in a chosen
+//! timezone. Either way, the parsed time can also become the message event time.
+
+use crate::config::{ParamSpec, ParamType, ProcessorConfig, ProcessorMetadata, StageConfig, extract_param, require_param};
+use crate::core::timing_mixin::{TimingMixin, WithTimingMixin};
+use crate::core::{context::ProcessingContext, message::Message};
+use crate::processors::common::field_utils::FieldUtils;
+use crate::processors::common::time_utils::{EpochUnit, TimeUtils};
+use crate::processors::processor::Processor;
+use crate::error::LiminalError;
+
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use serde::Deserialize;
+use serde_json::Value;
+use std::time::SystemTime;
+use tracing::{debug, error};
+
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeParseMode {
+    /// Timestamp (string or number) -> epoch milliseconds
+    Parse,
+    /// Epoch number -> formatted string
+    Format,
+}
+
+/// What to do when the input field cannot be interpreted as a timestamp.
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeParseErrorPolicy {
+    /// Forward the message unchanged
+    Pass,
+    /// Drop the message
+    Drop,
+}
+
+#[derive(Debug, Clone)]
+pub struct TimeParseConfig {
+    pub field_in: String,
+    pub field_out: String,
+    pub mode: TimeParseMode,
+    /// Custom strftime patterns tried after the built-in formats
+    pub formats: Vec<String>,
+    /// Unit of numeric input values
+    pub input_unit: EpochUnit,
+    /// Output pattern for format mode ("rfc3339", "rfc2822", or strftime)
+    pub format: String,
+    /// Zone for offset-less input (parse) or rendered output (format)
+    pub timezone: Tz,
+    /// Also use the parsed time as the message event time
+    pub set_event_time: bool,
+    pub on_error: TimeParseErrorPolicy,
+    pub timing: Option<crate::config::TimingConfig>,
+}
+
+impl ProcessorConfig for TimeParseConfig {
+    fn from_stage_config(config: &StageConfig) -> Result<Self> {
+        let field_in = require_param::<String>(&config.parameters, "field_in")?;
+        let mode = extract_param(&config.parameters, "mode", TimeParseMode::Parse);
+        let default_out = match mode {
+            TimeParseMode::Parse => format!("{}_ms", field_in),
+            TimeParseMode::Format => format!("{}_formatted", field_in),
+        };
+        let timezone_name = extract_param(&config.parameters, "timezone", "UTC".to_string());
+        let timezone = timezone_name
+            .parse::<Tz>()
+            .map_err(|_| anyhow!("Unknown timezone '{}'", timezone_name))?;
+
+        let config = Self {
+            field_out: extract_param(&config.parameters, "field_out", default_out),
+            field_in,
+            mode,
+            formats: extract_param(&config.parameters, "formats", Vec::<String>::new()),
+            input_unit: extract_param(&config.parameters, "input_unit", EpochUnit::Auto),
+            format: extract_param(&config.parameters, "format", "rfc3339".to_string()),
+            timezone,
+            set_event_time: extract_param(&config.parameters, "set_event_time", false),
+            on_error: extract_param(&config.parameters, "on_error", TimeParseErrorPolicy::Pass),
+            timing: config.timing.clone(),
+        };
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.field_in.is_empty() || self.field_out.is_empty() {
+            return Err(anyhow!("field_in and field_out cannot be empty"));
+        }
+        if self.format.is_empty() {
+            return Err(LiminalError::invalid_parameter("format", "cannot be empty").into());
+        }
+        if self.formats.iter().any(|f| f.is_empty()) {
+            return Err(LiminalError::invalid_parameter("formats", "cannot contain empty patterns").into());
+        }
+        Ok(())
+    }
+}
+
+pub struct TimeParseProcessor {
+    name: String,
+    config: TimeParseConfig,
+    timing: TimingMixin,
+}
+
+impl TimeParseProcessor {
+    pub const METADATA: ProcessorMetadata = ProcessorMetadata {
+        name: "time_parse",
+        description: "Parses timestamps into epoch milliseconds or formats epochs as strings",
+        parameters: &[
+            ParamSpec::new("field_in", ParamType::String, "Timestamp field"),
+            ParamSpec::new("mode", ParamType::Choice(&["parse", "format"]), "Parse strings into epochs, or format epochs as strings"),
+            ParamSpec::new("timezone", ParamType::String, "Timezone of timestamps without an offset, and of formatted output"),
+            ParamSpec::new("field_out", ParamType::String, "Field receiving the result"),
+            ParamSpec::new("formats", ParamType::Array, "Custom strftime patterns tried after the built-in formats"),
+            ParamSpec::new("input_unit", ParamType::Choice(&["auto", "seconds", "millis", "micros", "nanos", "s", "ms", "us", "ns"]), "Unit of numeric epochs"),
+            ParamSpec::new("format", ParamType::String, "Output pattern in format mode: rfc3339, rfc2822 or a strftime pattern"),
+            ParamSpec::new("set_event_time", ParamType::Boolean, "Use the parsed time as the message's event time"),
+            ParamSpec::new("on_error", ParamType::Choice(&["pass", "drop"]), "What to do with unparseable timestamps"),
+        ],
+        shared: &[],
+    };
+
+    pub fn new(name: &str, config: StageConfig) -> Result<Box<dyn Processor>> {
+        let processor_config = TimeParseConfig::from_stage_config(&config)?;
+        processor_config.validate()?;
+
+        let timing = TimingMixin::new(processor_config.timing.as_ref());
+
+        Ok(Box::new(Self {
+            name: name.to_string(),
+            config: processor_config,
+            timing,
+        }))
+    }
+
+    fn read_timestamp(&self, value: &Value) -> Option<DateTime<Utc>> {
+        match value {
+            Value::Number(n) => TimeUtils::from_epoch(n.as_f64()?, self.config.input_unit),
+            Value::String(s) => match self.config.input_unit {
+                EpochUnit::Auto => TimeUtils::parse(s, &self.config.formats, self.config.timezone),
+                unit => s
+                    .trim()
+                    .parse::<f64>()
+                    .ok()
+                    .and_then(|n| TimeUtils::from_epoch(n, unit))
+                    .or_else(|| TimeUtils::parse(s, &self.config.formats, self.config.timezone)),
+            },
+            _ => None,
+        }
+    }
+
+    fn process_message(&mut self, mut message: Message) -> Result<Option<Message>> {
+        let timestamp = FieldUtils::extract_field_value(&message.payload, &self.config.field_in)
+            .and_then(|value| self.read_timestamp(value));
+
+        let Some(timestamp) = timestamp else {
+            debug!("Field '{}' missing or not a timestamp", self.config.field_in);
+            return Ok(match self.config.on_error {
+                TimeParseErrorPolicy::Pass => Some(message),
+                TimeParseErrorPolicy::Drop => None,
+            });
+        };
+
+        let output = match self.config.mode {
+            TimeParseMode::Parse => Value::from(timestamp.timestamp_millis()),
+            TimeParseMode::Format => Value::String(TimeUtils::format(
+                timestamp,
+                &self.config.format,
+                self.config.timezone,
+            )),
+        };
+        FieldUtils::set_field_value(&mut message.payload, &self.config.field_out, output)?;
+
+        if self.config.set_event_time {
+            message.timing.event_time = SystemTime::from(timestamp);
+        }
+
+        message.source = self.name.clone();
+        Ok(Some(message))
+    }
+}
+
+#[async_trait::async_trait]
+impl Processor for TimeParseProcessor {
+    async fn init(&mut self) -> Result<()> {
+        tracing::info!(
+            "Time parse processor '{}' initialised ({:?} '{}' -> '{}', tz: {})",
+            self.name,
+            self.config.mode,
+            self.config.field_in,
+            self.config.field_out,
+            self.config.timezone
+        );
+        Ok(())
+    }
+
+    async fn process(&mut self, context: &mut ProcessingContext) -> Result<()> {
+        if let Some((channel_name, message)) = context.recv(tokio::time::Duration::from_millis(10)).await {
+            match self.process_message(message) {
+                Ok(Some(mut output_message)) => {
+                    if let Some(output_info) = &context.output {
+                        output_message.topic = output_info.name.clone();
+                        let output_message = self.timing.update_message_watermark(output_message);
+
+                        if let Err(e) = output_info.channel.publish(output_message).await {
+                            tracing::warn!("Failed to publish time_parse output: {:?}", e);
+                        }
+                    }
+                }
+                Ok(None) => {
+                    debug!("Message from '{}' dropped: unparseable timestamp", channel_name);
+                }
+                Err(e) => {
+                    error!("Failed to process timestamp: {}", e);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl WithTimingMixin for TimeParseProcessor {
+    fn timing_mixin(&self) -> &TimingMixin {
+        &self.timing
+    }
+
+    fn timing_mixin_mut(&mut self) -> &mut TimingMixin {
+        &mut self.timing
+    }
+}
@@ -0,0 +1,233 @@
+//! WebAssembly Plugin Transform
+//!
+//! Runs a sandboxed WebAssembly module as a pipeline stage so third parties can
+//! ship processors without recompiling Liminal. Available with the `wasm`
+//! Cargo feature.
+//!
+//! # Guest ABI
+//!
+//! Payloads cross the boundary as UTF-8 JSON bytes. The module must export:
+//!
+//! - `memory`: the guest linear memory
+//! - `alloc(len: i32) -> i32`: reserve `len` bytes and return a pointer
+//! - `process(ptr: i32, len: i32) -> i64`: transform the JSON at `ptr..ptr+len`
+//!   and return `(out_ptr << 32) | out_len`; an `out_len` of 0 drops the message
+//!
+//! and may optionally export `dealloc(ptr: i32, len: i32)`, which the host calls
+//! for both the input and output buffers once they have been consumed. The
+//! module instance lives for the lifetime of the stage, so guests may keep state
+//! between calls. Execution is bounded by a per-message fuel budget and a memory
+//! limit.
+
+use crate::config::{ParamSpec, ParamType, ProcessorConfig, ProcessorMetadata, StageConfig, extract_param, require_param};
+use crate::core::timing_mixin::{TimingMixin, WithTimingMixin};
+use crate::core::{context::ProcessingContext, message::Message};
+use crate::processors::processor::Processor;
+use crate::error::LiminalError;
+
+use anyhow::{Result, anyhow};
+use tracing::{debug, error};
+use wasmtime::{Config, Engine, Instance, Memory, Module, Store, StoreLimits, StoreLimitsBuilder, TypedFunc};
+
+#[derive(Debug, Clone)]
+pub struct WasmConfig {
+    /// Path to the compiled `.wasm` module
+    pub module: String,
+    /// Fuel (roughly, instructions) available to each `process` call
+    pub fuel: u64,
+    /// Maximum guest linear memory in bytes
+    pub max_memory_bytes: usize,
+    pub timing: Option<crate::config::TimingConfig>,
+}
+
+impl ProcessorConfig for WasmConfig {
+    fn from_stage_config(config: &StageConfig) -> Result<Self> {
+        let module = require_param::<String>(&config.parameters, "module")?;
+
+        let config = Self {
+            module,
+            fuel: extract_param(&config.parameters, "fuel", 10_000_000_u64),
+            max_memory_bytes: extract_param(&config.parameters, "max_memory_bytes", 64 * 1024 * 1024_usize),
+            timing: config.timing.clone(),
+        };
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.module.is_empty() {
+            return Err(anyhow!("module path cannot be empty"));
+        }
+        if self.fuel == 0 {
+            return Err(LiminalError::invalid_parameter("fuel", "must be greater than 0").into());
+        }
+        if self.max_memory_bytes < 64 * 1024 {
+            return Err(LiminalError::invalid_parameter("max_memory_bytes", "must be at least one wasm page (65536)").into());
+        }
+        Ok(())
+    }
+}
+
+/// Exports resolved from the guest module.
+struct GuestExports {
+    memory: Memory,
+    alloc: TypedFunc<i32, i32>,
+    process: TypedFunc<(i32, i32), i64>,
+    dealloc: Option<TypedFunc<(i32, i32), ()>>,
+}
+
+pub struct WasmProcessor {
+    name: String,
+    config: WasmConfig,
+    timing: TimingMixin,
+    store: Store<StoreLimits>,
+    guest: GuestExports,
+}
+
+impl WasmProcessor {
+    pub const METADATA: ProcessorMetadata = ProcessorMetadata {
+        name: "wasm",
+        description: "Runs a sandboxed WebAssembly plugin",
+        parameters: &[
+            ParamSpec::new("module", ParamType::String, "Path to the WebAssembly module"),
+            ParamSpec::new("fuel", ParamType::Integer, "Fuel a plugin may use per message"),
+            ParamSpec::new("max_memory_bytes", ParamType::Integer, "Memory a plugin may allocate"),
+        ],
+        shared: &[],
+    };
+
+    pub fn new(name: &str, config: StageConfig) -> Result<Box<dyn Processor>> {
+        let processor_config = WasmConfig::from_stage_config(&config)?;
+        processor_config.validate()?;
+
+        let timing = TimingMixin::new(processor_config.timing.as_ref());
+
+        let mut engine_config = Config::new();
+        engine_config.consume_fuel(true);
+        let engine = Engine::new(&engine_config)?;
+
+        let module = Module::from_file(&engine, &processor_config.module)
+            .map_err(|e| anyhow!("Failed to load wasm module '{}': {}", processor_config.module, e))?;
+
+        let limits = StoreLimitsBuilder::new()
+            .memory_size(processor_config.max_memory_bytes)
+            .instances(1)
+            .build();
+        let mut store = Store::new(&engine, limits);
+        store.limiter(|limits| limits);
+        store.set_fuel(processor_config.fuel)?;
+
+        // No imports are provided: guests are fully sandboxed
+        let instance = Instance::new(&mut store, &module, &[])
+            .map_err(|e| anyhow!("Failed to instantiate wasm module for stage '{}': {}", name, e))?;
+
+        let guest = GuestExports {
+            memory: instance
+                .get_memory(&mut store, "memory")
+                .ok_or_else(|| anyhow!("wasm module must export 'memory'"))?,
+            alloc: instance.get_typed_func(&mut store, "alloc")?,
+            process: instance.get_typed_func(&mut store, "process")?,
+            dealloc: instance.get_typed_func(&mut store, "dealloc").ok(),
+        };
+
+        Ok(Box::new(Self {
+            name: name.to_string(),
+            config: processor_config,
+            timing,
+            store,
+            guest,
+        }))
+    }
+
+    /// Hand JSON bytes to the guest and read back its output, or `None` if the
+    /// guest dropped the message.
+    fn call_guest(&mut self, input: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.store.set_fuel(self.config.fuel)?;
+
+        let len = i32::try_from(input.len()).map_err(|_| anyhow!("Payload too large for wasm guest"))?;
+        let ptr = self.guest.alloc.call(&mut self.store, len)?;
+        self.guest.memory.write(&mut self.store, ptr as u32 as usize, input)?;
+
+        let packed = self.guest.process.call(&mut self.store, (ptr, len))?;
+        let out_ptr = (packed as u64 >> 32) as u32 as usize;
+        let out_len = (packed as u64 & 0xFFFF_FFFF) as usize;
+
+        let output = if out_len == 0 {
+            None
+        } else {
+            let mut buffer = vec![0u8; out_len];
+            self.guest.memory.read(&self.store, out_ptr, &mut buffer)?;
+            Some(buffer)
+        };
+
+        if let Some(dealloc) = &self.guest.dealloc {
+            dealloc.call(&mut self.store, (ptr, len))?;
+            if out_len > 0 {
+                dealloc.call(&mut self.store, (out_ptr as i32, out_len as i32))?;
+            }
+        }
+
+        Ok(output)
+    }
+
+    fn process_message(&mut self, mut message: Message) -> Result<Option<Message>> {
+        let input = serde_json::to_vec(&message.payload)?;
+
+        let Some(output) = self.call_guest(&input)? else {
+            return Ok(None);
+        };
+
+        message.payload = serde_json::from_slice(&output)
+            .map_err(|e| anyhow!("wasm guest returned invalid JSON: {}", e))?;
+        message.source = self.name.clone();
+        Ok(Some(message))
+    }
+}
+
+#[async_trait::async_trait]
+impl Processor for WasmProcessor {
+    async fn init(&mut self) -> Result<()> {
+        tracing::info!(
+            "Wasm processor '{}' initialised (module: {}, fuel: {})",
+            self.name,
+            self.config.module,
+            self.config.fuel
+        );
+        Ok(())
+    }
+
+    async fn process(&mut self, context: &mut ProcessingContext) -> Result<()> {
+        if let Some((channel_name, message)) = context.recv(tokio::time::Duration::from_millis(10)).await {
+            match self.process_message(message) {
+                Ok(Some(mut output_message)) => {
+                    if let Some(output_info) = &context.output {
+                        output_message.topic = output_info.name.clone();
+                        let output_message = self.timing.update_message_watermark(output_message);
+
+                        if let Err(e) = output_info.channel.publish(output_message).await {
+                            tracing::warn!("Failed to publish wasm output: {:?}", e);
+                        }
+                    }
+                }
+                Ok(None) => {
+                    debug!("Wasm guest dropped message from '{}'", channel_name);
+                }
+                Err(e) => {
+                    error!("Wasm guest error in '{}': {}", self.name, e);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl WithTimingMixin for WasmProcessor {
+    fn timing_mixin(&self) -> &TimingMixin {
+        &self.timing
+    }
+
+    fn timing_mixin_mut(&mut self) -> &mut TimingMixin {
+        &mut self.timing
+    }
+}
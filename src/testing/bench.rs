@@ -0,0 +1,195 @@
+//! Pipeline Benchmark
+//!
+//! Measures the engine end to end, for `liminal bench`: a `simulated` source
+//! publishes messages as fast as its output channel takes them, through a
+//! chain of `scale` transforms, to a sink that discards them. Every channel
+//! is of the type being measured.
+//!
+//! The report gives the throughput, the end-to-end latency of each message
+//! (from its ingestion by the source to its arrival at the sink) and, per
+//! hop, the processing latency each stage recorded in its metrics. Channels
+//! that drop messages under load, such as `broadcast`, deliver fewer messages
+//! than were published, which the report shows. With a batch size above 1
+//! the source publishes columnar batches, which the transforms scale a column
+//! at a time and the sink splits into rows; stage metrics count batches.
+//!
+//! The component benchmarks in `benches/` time channels, conditions and field
+//! access on their own.
+
+use crate::builder::PipelineBuilder;
+use crate::config::types::{ChannelConfig, ChannelType};
+use crate::core::context::ProcessingContext;
+use crate::core::metrics::metrics;
+use crate::core::summary::StageSummary;
+use crate::processors::Processor;
+use crate::processors::factory::register_processor;
+
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use serde_json::{Value, json};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Processor type of the discarding sink
+const SINK_TYPE: &str = "liminal_bench_sink";
+
+/// What to benchmark.
+#[derive(Debug, Clone)]
+pub struct BenchOptions {
+    /// Messages published by the source
+    pub messages: u64,
+    /// Transforms between the source and the sink
+    pub transforms: usize,
+    /// Type of every channel
+    pub channel: ChannelType,
+    /// Capacity of every channel
+    pub capacity: usize,
+    /// Readings the source publishes per batch (1 publishes JSON messages)
+    pub batch_size: usize,
+}
+
+impl Default for BenchOptions {
+    fn default() -> Self {
+        Self { messages: 100_000, transforms: 3, channel: ChannelType::Direct, capacity: 1024, batch_size: 1 }
+    }
+}
+
+/// Results of a benchmark run.
+#[derive(Debug, Clone)]
+pub struct BenchReport {
+    pub published: u64,
+    /// Messages that reached the sink
+    pub received: u64,
+    /// From starting the stages until every stage stopped
+    pub elapsed: Duration,
+    /// End-to-end latencies at the 50th, 95th and 99th percentiles
+    pub latency: Option<[Duration; 3]>,
+    /// Metrics of each stage, from the source to the sink
+    pub hops: Vec<StageSummary>,
+}
+
+impl BenchReport {
+    /// Messages received by the sink per second.
+    pub fn throughput(&self) -> f64 {
+        self.received as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+impl std::fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{} of {} messages in {:.2?}: {:.0} msg/s",
+            self.received,
+            self.published,
+            self.elapsed,
+            self.throughput()
+        )?;
+        if let Some([p50, p95, p99]) = self.latency {
+            writeln!(f, "end-to-end latency: p50 {:.2?}, p95 {:.2?}, p99 {:.2?}", p50, p95, p99)?;
+        }
+        for hop in &self.hops {
+            write!(f, "  {:<16} {:>9} in {:>9} out", hop.stage, hop.messages_in, hop.messages_out)?;
+            if let Some(latency) = &hop.latency {
+                write!(f, "   mean {:>8.3}ms   p99 {:>8.3}ms", latency.mean_ms, latency.p99_ms)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Discards what it receives, recording how long after ingestion it arrived.
+struct BenchSink {
+    latencies: Arc<Mutex<Vec<Duration>>>,
+}
+
+#[async_trait]
+impl Processor for BenchSink {
+    async fn init(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn process(&mut self, context: &mut ProcessingContext) -> Result<()> {
+        if let Some((_, message)) = context.recv(Duration::from_millis(10)).await {
+            let latency = SystemTime::now().duration_since(message.timing.ingestion_time).unwrap_or_default();
+            if let Ok(mut latencies) = self.latencies.lock() {
+                latencies.push(latency);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Run the benchmark pipeline once, until the source has published every
+/// message and the stages have drained.
+pub async fn run(options: &BenchOptions) -> Result<BenchReport> {
+    if options.messages == 0 {
+        return Err(anyhow!("Benchmark needs at least one message"));
+    }
+    let latencies = Arc::new(Mutex::new(Vec::with_capacity(options.messages as usize)));
+    let sink_latencies = latencies.clone();
+    register_processor(
+        SINK_TYPE,
+        Box::new(move |_, _| Ok(Box::new(BenchSink { latencies: sink_latencies.clone() }) as Box<dyn Processor>)),
+    );
+
+    let directory = std::env::temp_dir().join(format!("liminal-bench-{}", std::process::id()));
+    let channel = ChannelConfig {
+        r#type: options.channel.clone(),
+        capacity: options.capacity,
+        path: (options.channel == ChannelType::Persistent).then(|| directory.to_string_lossy().to_string()),
+        ..Default::default()
+    };
+    let on_channel = |stage: &mut crate::config::StageConfig| stage.channel = Some(channel.clone());
+
+    let mut names = vec!["bench_source".to_string()];
+    let mut builder = PipelineBuilder::new()
+        .pipeline("bench", "Benchmark")
+        .input("bench_source", "simulated", json!({ "interval_ms": 0, "max_messages": options.messages, "batch_size": options.batch_size }))
+        .configure(on_channel);
+    for index in 1..=options.transforms {
+        let name = format!("bench_scale_{}", index);
+        builder = builder
+            .transform(&name, "scale", json!({ "field_in": "value", "field_out": "value", "scale_factor": 1.0 }))
+            .configure(on_channel);
+        names.push(name);
+    }
+    names.push("bench_sink".to_string());
+    let builder = builder.output("bench_sink", SINK_TYPE, Value::Null);
+
+    let manager = builder.build()?.connect_stages().await?;
+    let started = Instant::now();
+    manager.start_all().await?.wait_until_stopped().await;
+    let elapsed = started.elapsed();
+    let _ = std::fs::remove_dir_all(&directory);
+
+    let mut latencies = std::mem::take(&mut *latencies.lock().map_err(|_| anyhow!("Benchmark sink panicked"))?);
+    latencies.sort();
+    let percentile = |q: f64| latencies[((latencies.len() - 1) as f64 * q).round() as usize];
+    Ok(BenchReport {
+        published: options.messages,
+        received: latencies.len() as u64,
+        elapsed,
+        latency: (!latencies.is_empty()).then(|| [percentile(0.5), percentile(0.95), percentile(0.99)]),
+        hops: names.iter().map(|name| metrics().summarise_stage(name)).collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_bench_delivers_every_message_over_direct_channels() {
+        let options = BenchOptions { messages: 200, transforms: 2, ..Default::default() };
+        let report = run(&options).await.unwrap();
+        assert_eq!(report.received, 200);
+        assert!(report.latency.is_some());
+        let hops: Vec<_> = report.hops.iter().map(|hop| (hop.stage.as_str(), hop.messages_in)).collect();
+        assert_eq!(
+            hops,
+            vec![("bench_source", 0), ("bench_scale_1", 200), ("bench_scale_2", 200), ("bench_sink", 200)]
+        );
+    }
+}
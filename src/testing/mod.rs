@@ -0,0 +1,309 @@
+//! Processor Test Harness
+//!
+//! Utilities for unit-testing a `Processor` without running a pipeline.
+//! `TestContext` wires a processing context to in-memory channels: tests send
+//! messages to the processor's inputs, drive `process`, and read what it
+//! publishes.
+//!
+//! ```rust,ignore
+//! use liminal::testing::{MessageBuilder, TestContext, assert_field_approx};
+//!
+//! #[tokio::test]
+//! async fn test_delta() {
+//!     let mut processor = create_processor("delta", stage_config)?;
+//!     processor.init().await?;
+//!
+//!     let mut test = TestContext::new("delta").input("raw").output("deltas");
+//!     test.send("raw", MessageBuilder::new(json!({ "value": 1.0 })).build()).await?;
+//!     test.send("raw", MessageBuilder::new(json!({ "value": 3.5 })).build()).await?;
+//!
+//!     let outputs = test.collect_n_outputs(processor.as_mut(), 2, Duration::from_secs(1)).await?;
+//!     assert_field_approx(&outputs[1], "value_delta", 2.5, 1e-9);
+//! }
+//! ```
+//!
+//! `process` is driven from the calling task, so tests of processors that wait
+//! on timers or I/O run under a tokio runtime, e.g. with `#[tokio::test]`.
+
+pub mod bench;
+pub mod runner;
+
+use crate::config::types::{ChannelConfig, ChannelType};
+use crate::core::channel::{Channel, PubSubChannel, Subscriber};
+use crate::core::context::ProcessingContext;
+use crate::core::message::{Message, TimingInfo};
+use crate::processors::Processor;
+use crate::processors::common::field_utils::FieldUtils;
+
+use anyhow::{Result, anyhow};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Capacity of the in-memory channels
+const CAPACITY: usize = 1024;
+
+fn channel() -> Arc<Channel<Message>> {
+    let config = ChannelConfig {
+        r#type: ChannelType::Direct,
+        capacity: CAPACITY,
+        ..Default::default()
+    };
+    Arc::new(Channel::open("test", &config).expect("in-memory channels always open"))
+}
+
+/// A processing context wired to in-memory channels.
+pub struct TestContext {
+    /// The context passed to `process`
+    pub context: ProcessingContext,
+    inputs: HashMap<String, Arc<Channel<Message>>>,
+    output: Option<Subscriber<Message>>,
+    side_outputs: HashMap<String, Subscriber<Message>>,
+}
+
+impl TestContext {
+    /// A context for the stage named `stage`, with no inputs or outputs.
+    pub fn new(stage: &str) -> Self {
+        Self {
+            context: ProcessingContext::new(stage.to_string()),
+            inputs: HashMap::new(),
+            output: None,
+            side_outputs: HashMap::new(),
+        }
+    }
+
+    /// Add an input channel, fed with `send`.
+    pub fn input(mut self, name: &str) -> Self {
+        let channel = channel();
+        self.context.add_input(name.to_string(), channel.subscribe());
+        self.inputs.insert(name.to_string(), channel);
+        self
+    }
+
+    /// Attach the output channel, read with `try_output` and the `expect_` and
+    /// `collect_` helpers.
+    pub fn output(mut self, name: &str) -> Self {
+        let channel = channel();
+        self.output = Some(channel.subscribe());
+        self.context.attach_output(name.to_string(), channel);
+        self
+    }
+
+    /// Attach a side output channel, read with `try_side_output`.
+    pub fn side_output(mut self, name: &str) -> Self {
+        let channel = channel();
+        self.side_outputs.insert(name.to_string(), channel.subscribe());
+        self.context.attach_side_output(name.to_string(), channel);
+        self
+    }
+
+    /// Send a message to the named input.
+    pub async fn send(&self, input: &str, message: Message) -> Result<()> {
+        let channel = self
+            .inputs
+            .get(input)
+            .ok_or_else(|| anyhow!("Test context has no input '{}'", input))?;
+        channel
+            .publish(message)
+            .await
+            .map_err(|_| anyhow!("Failed to send to input '{}'", input))
+    }
+
+    /// Run `process` once.
+    pub async fn process(&mut self, processor: &mut dyn Processor) -> Result<()> {
+        self.context.accept_batches(processor.accepts_batches());
+        processor.process(&mut self.context).await
+    }
+
+    /// Run `process` until every input is drained.
+    pub async fn process_pending(&mut self, processor: &mut dyn Processor) -> Result<()> {
+        self.context.accept_batches(processor.accepts_batches());
+        while self.context.has_pending_input() && !self.context.is_complete() {
+            processor.process(&mut self.context).await?;
+        }
+        Ok(())
+    }
+
+    /// Take the next message published to the output, if there is one.
+    pub async fn try_output(&mut self) -> Option<Message> {
+        self.output.as_mut()?.try_recv().await.into_message()
+    }
+
+    /// Take the next message published to the named side output, if there is one.
+    pub async fn try_side_output(&mut self, name: &str) -> Option<Message> {
+        self.side_outputs.get_mut(name)?.try_recv().await.into_message()
+    }
+
+    /// Run `process` until the processor publishes to the output, failing
+    /// after `timeout`.
+    pub async fn expect_output_within(&mut self, processor: &mut dyn Processor, timeout: Duration) -> Result<Message> {
+        let mut outputs = self.collect_n_outputs(processor, 1, timeout).await?;
+        Ok(outputs.remove(0))
+    }
+
+    /// Run `process` until the processor has published `n` messages to the
+    /// output, failing after `timeout`.
+    pub async fn collect_n_outputs(
+        &mut self,
+        processor: &mut dyn Processor,
+        n: usize,
+        timeout: Duration,
+    ) -> Result<Vec<Message>> {
+        if self.output.is_none() {
+            return Err(anyhow!("Test context has no output"));
+        }
+
+        self.context.accept_batches(processor.accepts_batches());
+        let deadline = Instant::now() + timeout;
+        let mut outputs = Vec::with_capacity(n);
+        while outputs.len() < n {
+            if let Some(message) = self.try_output().await {
+                outputs.push(message);
+                continue;
+            }
+            if Instant::now() >= deadline {
+                return Err(anyhow!(
+                    "Stage '{}' published {} of {} expected messages within {:?}",
+                    self.context.stage_name,
+                    outputs.len(),
+                    n,
+                    timeout
+                ));
+            }
+            processor.process(&mut self.context).await?;
+        }
+        Ok(outputs)
+    }
+}
+
+/// Builds a message for a test. The source and topic default to `test`, and
+/// all times to now.
+pub struct MessageBuilder {
+    message: Message,
+}
+
+impl MessageBuilder {
+    pub fn new(payload: Value) -> Self {
+        Self {
+            message: Message::new("test", "test", payload),
+        }
+    }
+
+    pub fn source(mut self, source: &str) -> Self {
+        self.message.source = source.to_string();
+        self
+    }
+
+    pub fn topic(mut self, topic: &str) -> Self {
+        self.message.topic = topic.to_string();
+        self
+    }
+
+    /// Event time, leaving ingestion time at now.
+    pub fn event_time(mut self, event_time: SystemTime) -> Self {
+        self.message.timing.event_time = event_time;
+        self
+    }
+
+    /// Event time as milliseconds since the Unix epoch.
+    pub fn event_time_ms(self, millis: u64) -> Self {
+        self.event_time(SystemTime::UNIX_EPOCH + Duration::from_millis(millis))
+    }
+
+    pub fn watermark(mut self, watermark: SystemTime) -> Self {
+        self.message.timing.watermark = Some(watermark);
+        self
+    }
+
+    pub fn sequence_id(mut self, sequence_id: u64) -> Self {
+        self.message.timing.sequence_id = Some(sequence_id);
+        self
+    }
+
+    /// Set a metadata entry.
+    pub fn metadata(mut self, key: &str, value: &str) -> Self {
+        self.message.metadata.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    /// Replace all timing information.
+    pub fn timing(mut self, timing: TimingInfo) -> Self {
+        self.message.timing = timing;
+        self
+    }
+
+    pub fn build(self) -> Message {
+        self.message
+    }
+}
+
+/// Assert that a payload field, given as a dotted path, equals `expected`.
+#[track_caller]
+pub fn assert_field(message: &Message, path: &str, expected: Value) {
+    let actual = FieldUtils::extract_field_value(&message.payload, path);
+    assert_eq!(actual, Some(&expected), "field '{}' of {}", path, message.payload);
+}
+
+/// Assert that a numeric payload field, given as a dotted path, is within
+/// `tolerance` of `expected`.
+#[track_caller]
+pub fn assert_field_approx(message: &Message, path: &str, expected: f64, tolerance: f64) {
+    match FieldUtils::extract_f64(&message.payload, path) {
+        Some(actual) => assert!(
+            (actual - expected).abs() <= tolerance,
+            "field '{}' is {}, expected {} ± {} in {}",
+            path,
+            actual,
+            expected,
+            tolerance,
+            message.payload
+        ),
+        None => panic!("field '{}' is not a number in {}", path, message.payload),
+    }
+}
+
+/// Assert that a payload field, given as a dotted path, is absent.
+#[track_caller]
+pub fn assert_no_field(message: &Message, path: &str) {
+    assert!(
+        !FieldUtils::field_exists(&message.payload, path),
+        "field '{}' is present in {}",
+        path,
+        message.payload
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::StageConfig;
+    use crate::processors::factory::create_processor;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_harness_drives_processors_through_channels() {
+        let config = StageConfig {
+            r#type: "delta".to_string(),
+            parameters: Some(HashMap::from([
+                ("field_in".to_string(), json!("value")),
+                ("field_out".to_string(), json!("value_delta")),
+            ])),
+            ..Default::default()
+        };
+        let mut processor = create_processor("delta", config).unwrap();
+        processor.init().await.unwrap();
+
+        let mut test = TestContext::new("delta").input("raw").output("deltas");
+        for value in [1.0, 3.5, 3.0] {
+            test.send("raw", MessageBuilder::new(json!({ "value": value })).build()).await.unwrap();
+        }
+        let outputs = test.collect_n_outputs(processor.as_mut(), 2, Duration::from_secs(1)).await.unwrap();
+        assert_field_approx(&outputs[0], "value_delta", 2.5, 1e-9);
+        assert_field_approx(&outputs[1], "value_delta", -0.5, 1e-9);
+        assert_field(&outputs[1], "value", json!(3.0));
+
+        let error = test.expect_output_within(processor.as_mut(), Duration::from_millis(50)).await.unwrap_err();
+        assert_eq!(error.to_string(), "Stage 'delta' published 0 of 1 expected messages within 50ms");
+    }
+}
@@ -0,0 +1,406 @@
+//! Pipeline Test Runner
+//!
+//! Runs a configuration against a test file, for `liminal test`. Each test
+//! injects messages into channels and lists the messages expected on others:
+//!
+//! ```toml
+//! [[tests]]
+//! name = "rising values give positive deltas"
+//! timeout_ms = 2000
+//! tolerance = 1e-6
+//!
+//! [tests.inject]
+//! raw_data = [{ value = 1.0 }, { value = 3.5 }]
+//!
+//! [tests.expect]
+//! processed_data = [{ value = 3.5, value_delta = 2.5 }]
+//! ```
+//!
+//! The pipeline runs headlessly: the configured inputs and outputs are
+//! replaced by sources publishing the injected messages and sinks collecting
+//! messages from the expected channels. Channels neither injected nor
+//! produced by a pipeline stage are left empty. Once the sources are done the
+//! stages drain and stop, and each expected channel must then have carried
+//! exactly the expected messages, in order.
+//!
+//! An expected message matches when every field it lists matches, so other
+//! fields are ignored. Numbers match within `tolerance` (by default 1e-9), or
+//! within a per-field tolerance from `tolerances`, keyed by dotted path. A
+//! test fails if the pipeline has not stopped within `timeout_ms` (by default
+//! 5000), with the messages received up to then checked as well.
+
+use crate::config::types::Config;
+use crate::config::{StageConfig, validate_config};
+use crate::core::context::ProcessingContext;
+use crate::core::message::Message;
+use crate::core::pipeline::PipelineManager;
+use crate::processors::Processor;
+use crate::processors::factory::register_processor;
+
+use anyhow::{Context, Result, anyhow};
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Processor types of the injecting sources and collecting sinks
+const SOURCE_TYPE: &str = "liminal_test_source";
+const SINK_TYPE: &str = "liminal_test_sink";
+
+/// A file of pipeline tests.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TestFile {
+    #[serde(default)]
+    pub tests: Vec<PipelineTest>,
+}
+
+/// One pipeline test.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PipelineTest {
+    pub name: String,
+
+    /// How long the pipeline may run before the test fails
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: u64,
+
+    /// Delay between injected messages on each channel
+    #[serde(default)]
+    pub interval_ms: u64,
+
+    /// Allowed difference between expected and received numbers
+    #[serde(default = "default_tolerance")]
+    pub tolerance: f64,
+
+    /// Tolerances of individual fields, by dotted path
+    #[serde(default)]
+    pub tolerances: HashMap<String, f64>,
+
+    /// Payloads published to each channel
+    #[serde(default)]
+    pub inject: BTreeMap<String, Vec<Value>>,
+
+    /// Payloads expected on each channel
+    #[serde(default)]
+    pub expect: BTreeMap<String, Vec<Value>>,
+}
+
+const fn default_timeout_ms() -> u64 {
+    5000
+}
+
+const fn default_tolerance() -> f64 {
+    1e-9
+}
+
+/// The outcome of one test.
+#[derive(Debug)]
+pub struct TestOutcome {
+    pub name: String,
+    /// Why the test failed; empty if it passed
+    pub failures: Vec<String>,
+}
+
+impl TestOutcome {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// Load a test file.
+pub fn load_tests(path: impl AsRef<Path>) -> Result<TestFile> {
+    let path = path.as_ref();
+    let content = std::fs::read_to_string(path).with_context(|| format!("Failed to read tests '{}'", path.display()))?;
+    toml::from_str(&content).with_context(|| format!("Failed to parse tests '{}'", path.display()))
+}
+
+/// Messages collected by the sinks, by channel.
+type Collected = Arc<Mutex<HashMap<String, Vec<Message>>>>;
+
+/// Publishes the injected payloads of one channel, then completes.
+struct TestSource {
+    channel: String,
+    payloads: Vec<Value>,
+    interval: Duration,
+    next: usize,
+}
+
+#[async_trait]
+impl Processor for TestSource {
+    async fn init(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn process(&mut self, context: &mut ProcessingContext) -> Result<()> {
+        let Some(payload) = self.payloads.get(self.next) else {
+            context.complete();
+            return Ok(());
+        };
+        if self.next > 0 && !self.interval.is_zero() {
+            tokio::time::sleep(self.interval).await;
+        }
+        self.next += 1;
+
+        if let Some(output) = &context.output
+            && output.channel.publish(Message::new(&context.stage_name, &self.channel, payload.clone())).await.is_err()
+        {
+            return Err(anyhow!("Failed to inject into '{}'", self.channel));
+        }
+        Ok(())
+    }
+}
+
+/// Collects every message it receives.
+struct TestSink {
+    collected: Collected,
+}
+
+#[async_trait]
+impl Processor for TestSink {
+    async fn init(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn process(&mut self, context: &mut ProcessingContext) -> Result<()> {
+        if let Some((channel, message)) = context.recv(Duration::from_millis(10)).await
+            && let Ok(mut collected) = self.collected.lock()
+        {
+            collected.entry(channel).or_default().push(message);
+        }
+        Ok(())
+    }
+}
+
+fn register_test_processors(collected: &Collected) {
+    register_processor(
+        SOURCE_TYPE,
+        Box::new(|_, config: StageConfig| {
+            let parameters = config.parameters.unwrap_or_default();
+            Ok(Box::new(TestSource {
+                channel: config.output.unwrap_or_default(),
+                payloads: parameters.get("payloads").and_then(Value::as_array).cloned().unwrap_or_default(),
+                interval: Duration::from_millis(parameters.get("interval_ms").and_then(Value::as_u64).unwrap_or(0)),
+                next: 0,
+            }) as Box<dyn Processor>)
+        }),
+    );
+    let collected = collected.clone();
+    register_processor(
+        SINK_TYPE,
+        Box::new(move |_, _| Ok(Box::new(TestSink { collected: collected.clone() }) as Box<dyn Processor>)),
+    );
+}
+
+fn test_stage(kind: &str, inputs: Option<&str>, output: Option<&str>, parameters: Value) -> StageConfig {
+    StageConfig {
+        r#type: kind.to_string(),
+        inputs: inputs.map(|input| vec![input.to_string()]),
+        output: output.map(str::to_string),
+        parameters: serde_json::from_value(parameters).ok(),
+        ..Default::default()
+    }
+}
+
+/// The configuration a test runs: the pipelines of `config`, fed by sources
+/// of the injected messages and read by sinks of the expected channels.
+fn test_config(config: &Config, test: &PipelineTest) -> Result<Config> {
+    let mut config = Config {
+        pipelines: config.pipelines.clone(),
+        shutdown: config.shutdown.clone(),
+        strict: config.strict,
+        ..Default::default()
+    };
+
+    let stages: Vec<&StageConfig> = config.pipelines.values().flat_map(|pipeline| pipeline.stages.values()).collect();
+    let produced: HashSet<&String> = stages
+        .iter()
+        .flat_map(|stage| stage.output.iter().chain(stage.side_outputs.iter().flatten()))
+        .collect();
+    let consumed: HashSet<&String> = stages.iter().flat_map(|stage| stage.inputs.iter().flatten()).collect();
+
+    for channel in test.inject.keys() {
+        if produced.contains(channel) {
+            return Err(anyhow!("Can't inject into '{}', which is produced by a pipeline stage", channel));
+        }
+    }
+
+    // Sources of every channel the pipelines read but don't produce, empty
+    // unless injected into
+    let mut sources: Vec<String> = consumed.into_iter().filter(|channel| !produced.contains(channel)).cloned().collect();
+    for channel in test.inject.keys() {
+        if !sources.contains(channel) {
+            sources.push(channel.clone());
+        }
+    }
+    let mut inputs = HashMap::new();
+    for channel in sources {
+        let payloads = test.inject.get(&channel).cloned().unwrap_or_default();
+        let parameters = json!({ "payloads": payloads, "interval_ms": test.interval_ms });
+        inputs.insert(format!("test_inject:{}", channel), test_stage(SOURCE_TYPE, None, Some(&channel), parameters));
+    }
+
+    let mut outputs = HashMap::new();
+    for channel in test.expect.keys() {
+        if !produced.contains(channel) && !test.inject.contains_key(channel) {
+            return Err(anyhow!("Expected channel '{}' is not produced by any pipeline stage", channel));
+        }
+        outputs.insert(format!("test_expect:{}", channel), test_stage(SINK_TYPE, Some(channel), None, Value::Null));
+    }
+
+    config.inputs = inputs;
+    config.outputs = outputs;
+    Ok(config)
+}
+
+/// Run one test against `config`.
+pub async fn run_test(config: &Config, test: &PipelineTest) -> TestOutcome {
+    let mut outcome = TestOutcome {
+        name: test.name.clone(),
+        failures: Vec::new(),
+    };
+    match run_pipeline(config, test).await {
+        Ok((collected, stopped)) => {
+            if !stopped {
+                outcome.failures.push(format!("pipeline did not stop within {}ms", test.timeout_ms));
+            }
+            for (channel, expected) in &test.expect {
+                let received = collected.get(channel).map(Vec::as_slice).unwrap_or_default();
+                check_channel(channel, expected, received, test, &mut outcome.failures);
+            }
+        }
+        Err(e) => outcome.failures.push(format!("{:#}", e)),
+    }
+    outcome
+}
+
+/// Run the pipeline of a test, returning the messages collected and whether
+/// it stopped before the timeout.
+async fn run_pipeline(config: &Config, test: &PipelineTest) -> Result<(HashMap<String, Vec<Message>>, bool)> {
+    let config = test_config(config, test)?;
+    validate_config(&config)?;
+
+    let collected = Collected::default();
+    register_test_processors(&collected);
+
+    let mut manager = PipelineManager::new(config).build_all()?.connect_stages().await?.start_all().await?;
+    let stopped = tokio::time::timeout(Duration::from_millis(test.timeout_ms), manager.wait_until_stopped())
+        .await
+        .is_ok();
+
+    // Messages arriving while shutting down a pipeline that timed out don't count
+    let received = collected.lock().map(|collected| collected.clone()).unwrap_or_default();
+    if !stopped {
+        manager.shutdown().await;
+    }
+    Ok((received, stopped))
+}
+
+fn check_channel(channel: &str, expected: &[Value], received: &[Message], test: &PipelineTest, failures: &mut Vec<String>) {
+    for (index, (expected, message)) in expected.iter().zip(received).enumerate() {
+        compare(expected, &message.payload, &format!("{}[{}]", channel, index), "", test, failures);
+    }
+    if expected.len() != received.len() {
+        failures.push(format!(
+            "{}: expected {} message(s), received {}",
+            channel,
+            expected.len(),
+            received.len()
+        ));
+    }
+}
+
+/// Compare an expected value with a received one, recording mismatches.
+/// `location` names the message, and `path` the field within it.
+fn compare(expected: &Value, actual: &Value, location: &str, path: &str, test: &PipelineTest, failures: &mut Vec<String>) {
+    let at = |path: &str| if path.is_empty() { location.to_string() } else { format!("{}.{}", location, path) };
+    match (expected, actual) {
+        (Value::Object(expected), Value::Object(actual)) => {
+            for (key, expected) in expected {
+                let path = if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) };
+                match actual.get(key) {
+                    Some(actual) => compare(expected, actual, location, &path, test, failures),
+                    None => failures.push(format!("{}: expected {}, but the field is missing", at(&path), expected)),
+                }
+            }
+        }
+        (Value::Array(expected_items), Value::Array(actual_items)) if expected_items.len() == actual_items.len() => {
+            for (index, (expected, actual)) in expected_items.iter().zip(actual_items).enumerate() {
+                compare(expected, actual, location, &format!("{}[{}]", path, index), test, failures);
+            }
+        }
+        (Value::Number(expected), Value::Number(actual)) => {
+            let tolerance = test.tolerances.get(path).copied().unwrap_or(test.tolerance);
+            if let (Some(expected), Some(actual)) = (expected.as_f64(), actual.as_f64())
+                && (expected - actual).abs() > tolerance
+            {
+                failures.push(format!("{}: expected {} ± {}, received {}", at(path), expected, tolerance, actual));
+            }
+        }
+        (expected, actual) if expected != actual => {
+            failures.push(format!("{}: expected {}, received {}", at(path), expected, actual));
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::loader::load_config_from_string;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_runner_checks_expected_outputs() {
+        let config = load_config_from_string(
+            r#"
+            [inputs.sensor]
+            type = "mqtt_sub"
+            output = "raw"
+            parameters = { broker_url = "mqtt://localhost:1883", topics = ["plant/#"] }
+
+            [pipelines.main]
+            description = "Deltas"
+
+            [pipelines.main.stages.delta]
+            type = "delta"
+            inputs = ["raw"]
+            output = "deltas"
+            parameters = { field_in = "value", field_out = "change" }
+            "#,
+        )
+        .unwrap();
+
+        let tests: TestFile = toml::from_str(
+            r#"
+            [[tests]]
+            name = "passes"
+            tolerance = 0.01
+            inject = { raw = [{ value = 1.0 }, { value = 3.5, id = "b" }] }
+            expect = { deltas = [{ change = 2.501, id = "b" }] }
+
+            [[tests]]
+            name = "fails"
+            tolerances = { change = 0.1 }
+            inject = { raw = [{ value = 1.0 }, { value = 3.5 }] }
+            expect = { deltas = [{ change = 2.0, id = "b" }, {}] }
+            "#,
+        )
+        .unwrap();
+
+        let outcome = run_test(&config, &tests.tests[0]).await;
+        assert!(outcome.passed(), "{:?}", outcome.failures);
+
+        let outcome = run_test(&config, &tests.tests[1]).await;
+        assert_eq!(
+            outcome.failures,
+            [
+                "deltas[0].change: expected 2 ± 0.1, received 2.5",
+                "deltas[0].id: expected \"b\", but the field is missing",
+                "deltas: expected 2 message(s), received 1",
+            ]
+        );
+    }
+}